@@ -4,7 +4,7 @@ use std::time::Instant;
 
 use clap::{Arg, Command};
 use futures::StreamExt;
-use mountpoint_s3_client::{ObjectClient, S3ClientConfig, S3CrtClient};
+use mountpoint_s3_client::{GetObjectParams, ObjectClient, S3ClientConfig, S3CrtClient};
 use mountpoint_s3_crt::common::rust_log_adapter::RustLogAdapter;
 use tracing_subscriber::fmt::Subscriber;
 use tracing_subscriber::util::SubscriberInitExt;
@@ -77,7 +77,7 @@ fn main() {
         let received_size_clone = Arc::clone(&received_size);
         futures::executor::block_on(async move {
             let mut request = client
-                .get_object(bucket, key, None, None)
+                .get_object(bucket, key, &GetObjectParams::new())
                 .await
                 .expect("couldn't create get request");
             loop {
@@ -1,5 +1,6 @@
 use std::ffi::OsStr;
 use std::os::unix::prelude::OsStrExt;
+use std::str::FromStr;
 
 use lazy_static::lazy_static;
 use mountpoint_s3_crt::common::allocator::Allocator;
@@ -7,11 +8,17 @@ use mountpoint_s3_crt::common::uri::Uri;
 use regex::Regex;
 use thiserror::Error;
 
+use crate::express::directory_bucket_zone;
+
 lazy_static! {
     /// Regions in the "aws" partition (from the SDK's `partitions.json`)
     static ref AWS_PARTITION_REGEX: Regex = Regex::new(r"^(us|eu|ap|sa|ca|me|af)\-\w+\-\d+$").unwrap();
     /// Bucket names that are acceptable as virtual host names for DNS
     static ref VALID_DNS_REGEX: Regex = Regex::new(r"[a-z0-9][a-z0-9\-]*[a-z0-9]").unwrap();
+    /// Partitions that access point ARNs can refer to
+    static ref ARN_PARTITION_REGEX: Regex = Regex::new(r"^aws(-cn|-us-gov)?$").unwrap();
+    /// AWS account IDs
+    static ref ACCOUNT_ID_REGEX: Regex = Regex::new(r"^\d{12}$").unwrap();
 }
 
 #[derive(Debug, Clone)]
@@ -19,6 +26,8 @@ lazy_static! {
 pub struct Endpoint {
     uri: Uri,
     addressing_style: AddressingStyle,
+    /// The region this endpoint was resolved for, if it wasn't specified manually
+    region: Option<String>,
 }
 
 impl Endpoint {
@@ -27,7 +36,9 @@ impl Endpoint {
     pub fn from_region(region: &str, addressing_style: AddressingStyle) -> Result<Self, EndpointError> {
         if AWS_PARTITION_REGEX.is_match(region) {
             // TODO: support partitions other than "aws"
-            Self::from_uri_inner(&format!("https://s3.{region}.amazonaws.com"), addressing_style)
+            let mut endpoint = Self::from_uri_inner(&format!("https://s3.{region}.amazonaws.com"), addressing_style)?;
+            endpoint.region = Some(region.to_owned());
+            Ok(endpoint)
         } else {
             Err(EndpointError::UnsupportedRegion(region.to_owned()))
         }
@@ -51,12 +62,38 @@ impl Endpoint {
         Ok(Self {
             uri: parsed_uri,
             addressing_style,
+            region: None,
         })
     }
 
     /// Given a bucket name, determine whether to do path-based or virtual-host-based addressing,
-    /// and return the host URI to access and the prefix to apply to paths
+    /// and return the host URI to access and the prefix to apply to paths. The bucket can also be
+    /// an access point ARN, in which case the host is derived from the ARN instead, or empty for
+    /// requests that aren't for any bucket, like ListBuckets. Directory buckets are addressed at
+    /// their zonal endpoint, unless the endpoint URI was specified manually.
     pub(crate) fn for_bucket(&self, bucket: &str) -> Result<(Uri, String), EndpointError> {
+        if bucket.is_empty() {
+            return Ok((self.uri.clone(), String::new()));
+        }
+
+        if bucket.starts_with(ARN_PREFIX) {
+            let arn: AccessPointArn = bucket.parse()?;
+            let scheme = self.uri.scheme().to_str().ok_or(InvalidUriError::InvalidUtf8)?;
+            let uri = format!("{scheme}://{}", arn.host());
+            let uri = Uri::new_from_str(&mut Allocator::default(), OsStr::from_bytes(uri.as_bytes()))
+                .map_err(InvalidUriError::CouldNotParse)?;
+            return Ok((uri, String::new()));
+        }
+
+        if let (Some(region), Some(zone)) = (&self.region, directory_bucket_zone(bucket)) {
+            // Directory buckets only support virtual-hosted-style requests to their zonal endpoint
+            let scheme = self.uri.scheme().to_str().ok_or(InvalidUriError::InvalidUtf8)?;
+            let uri = format!("{scheme}://{bucket}.s3express-{zone}.{region}.amazonaws.com");
+            let uri = Uri::new_from_str(&mut Allocator::default(), OsStr::from_bytes(uri.as_bytes()))
+                .map_err(InvalidUriError::CouldNotParse)?;
+            return Ok((uri, String::new()));
+        }
+
         match self.addressing_style {
             AddressingStyle::Automatic => {
                 if is_valid_dns_name(bucket) {
@@ -100,6 +137,118 @@ fn insert_virtual_host(bucket: &str, uri: &Uri) -> Result<Uri, InvalidUriError>
     )?)
 }
 
+const ARN_PREFIX: &str = "arn:";
+const MRAP_ALIAS_SUFFIX: &str = ".mrap";
+
+/// Whether a bucket name is the ARN of a multi-region access point
+pub(crate) fn is_multi_region_access_point(bucket: &str) -> bool {
+    bucket.starts_with(ARN_PREFIX) && bucket.ends_with(MRAP_ALIAS_SUFFIX)
+}
+
+/// If a bucket name is the ARN of an Object Lambda access point, the parsed ARN. Requests to these
+/// must be signed for the `s3-object-lambda` service in the access point's region.
+pub(crate) fn object_lambda_access_point(bucket: &str) -> Option<AccessPointArn> {
+    if !bucket.starts_with(ARN_PREFIX) {
+        return None;
+    }
+    let arn: AccessPointArn = bucket.parse().ok()?;
+    (arn.kind == AccessPointKind::ObjectLambda).then_some(arn)
+}
+
+/// The kind of resource an [AccessPointArn] refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessPointKind {
+    /// A standard S3 access point
+    AccessPoint,
+    /// An S3 Object Lambda access point
+    ObjectLambda,
+    /// A multi-region access point. Requests to these must be signed with SigV4A.
+    MultiRegion,
+}
+
+/// An S3 access point ARN, which can be used in place of a bucket name. For example,
+/// `arn:aws:s3:us-west-2:123456789012:accesspoint/my-access-point`,
+/// `arn:aws:s3-object-lambda:us-west-2:123456789012:accesspoint/my-lambda-access-point`, or
+/// `arn:aws:s3::123456789012:accesspoint/mfzwi23gnjvgw.mrap` for a multi-region access point
+/// (which has no region, and whose name is its alias).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessPointArn {
+    pub kind: AccessPointKind,
+    pub partition: String,
+    pub region: String,
+    pub account_id: String,
+    pub name: String,
+}
+
+impl AccessPointArn {
+    /// The host name to send requests for this access point to
+    pub fn host(&self) -> String {
+        let dns_suffix = match self.partition.as_str() {
+            "aws-cn" => "amazonaws.com.cn",
+            _ => "amazonaws.com",
+        };
+        let service = match self.kind {
+            AccessPointKind::AccessPoint => "s3-accesspoint",
+            AccessPointKind::ObjectLambda => "s3-object-lambda",
+            AccessPointKind::MultiRegion => return format!("{}.accesspoint.s3-global.{dns_suffix}", self.name),
+        };
+        format!(
+            "{}-{}.{service}.{}.{dns_suffix}",
+            self.name, self.account_id, self.region
+        )
+    }
+}
+
+impl FromStr for AccessPointArn {
+    type Err = EndpointError;
+
+    fn from_str(arn: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| EndpointError::InvalidAccessPointArn(arn.to_owned(), reason.to_owned());
+
+        // arn:partition:service:region:account-id:resource
+        let parts: Vec<_> = arn.splitn(6, ':').collect();
+        let [prefix, partition, service, region, account_id, resource] = parts[..] else {
+            return Err(invalid("expected 6 fields separated by ':'"));
+        };
+        if prefix != "arn" {
+            return Err(invalid("must start with 'arn:'"));
+        }
+        if !ARN_PARTITION_REGEX.is_match(partition) {
+            return Err(invalid("unknown partition"));
+        }
+        if !ACCOUNT_ID_REGEX.is_match(account_id) {
+            return Err(invalid("account ID must be 12 digits"));
+        }
+        let name = resource
+            .strip_prefix("accesspoint/")
+            .or_else(|| resource.strip_prefix("accesspoint:"))
+            .ok_or_else(|| invalid("resource must be an access point"))?;
+        let kind = match service {
+            // Multi-region access points are global, so have no region in their ARN
+            "s3" if region.is_empty() => AccessPointKind::MultiRegion,
+            "s3" => AccessPointKind::AccessPoint,
+            "s3-object-lambda" if !region.is_empty() => AccessPointKind::ObjectLambda,
+            "s3-object-lambda" => return Err(invalid("region must not be empty")),
+            _ => return Err(invalid("service must be s3 or s3-object-lambda")),
+        };
+        let valid_name = match kind {
+            AccessPointKind::MultiRegion => name.strip_suffix(MRAP_ALIAS_SUFFIX).map_or(false, is_valid_dns_name),
+            _ => is_valid_dns_name(name),
+        };
+        if !valid_name {
+            return Err(invalid("access point name is not valid"));
+        }
+
+        Ok(Self {
+            kind,
+            partition: partition.to_owned(),
+            region: region.to_owned(),
+            account_id: account_id.to_owned(),
+            name: name.to_owned(),
+        })
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum AddressingStyle {
     /// Use virtual addressing if possible, but fall back to path addressing if necessary
@@ -119,6 +268,8 @@ pub enum EndpointError {
     InvalidEndpoint,
     #[error("region {0} is not yet supported")]
     UnsupportedRegion(String),
+    #[error("invalid access point ARN {0}: {1}")]
+    InvalidAccessPointArn(String, String),
 }
 
 #[derive(Debug, Error)]
@@ -143,4 +294,135 @@ mod test {
         assert!(is_valid_dns_name("test-1bucket"));
         assert!(is_valid_dns_name("1test-bucket"));
     }
+
+    #[test]
+    fn access_point_arn() {
+        let arn: AccessPointArn = "arn:aws:s3:us-west-2:123456789012:accesspoint/my-access-point"
+            .parse()
+            .expect("valid ARN");
+        assert_eq!(arn.kind, AccessPointKind::AccessPoint);
+        assert_eq!(arn.region, "us-west-2");
+        assert_eq!(arn.account_id, "123456789012");
+        assert_eq!(arn.name, "my-access-point");
+        assert_eq!(
+            arn.host(),
+            "my-access-point-123456789012.s3-accesspoint.us-west-2.amazonaws.com"
+        );
+
+        // The resource can also be separated by ':'
+        let arn2: AccessPointArn = "arn:aws:s3:us-west-2:123456789012:accesspoint:my-access-point"
+            .parse()
+            .expect("valid ARN");
+        assert_eq!(arn, arn2);
+    }
+
+    #[test]
+    fn object_lambda_access_point_arn() {
+        let arn: AccessPointArn = "arn:aws:s3-object-lambda:eu-west-1:123456789012:accesspoint/my-lambda-ap"
+            .parse()
+            .expect("valid ARN");
+        assert_eq!(arn.kind, AccessPointKind::ObjectLambda);
+        assert_eq!(
+            arn.host(),
+            "my-lambda-ap-123456789012.s3-object-lambda.eu-west-1.amazonaws.com"
+        );
+
+        let arn =
+            object_lambda_access_point("arn:aws:s3-object-lambda:eu-west-1:123456789012:accesspoint/my-lambda-ap")
+                .expect("should be an Object Lambda access point");
+        assert_eq!(arn.region, "eu-west-1");
+        assert!(object_lambda_access_point("arn:aws:s3:us-west-2:123456789012:accesspoint/my-access-point").is_none());
+        assert!(object_lambda_access_point("my-bucket").is_none());
+    }
+
+    #[test]
+    fn multi_region_access_point_arn() {
+        let bucket = "arn:aws:s3::123456789012:accesspoint/mfzwi23gnjvgw.mrap";
+        assert!(is_multi_region_access_point(bucket));
+        let arn: AccessPointArn = bucket.parse().expect("valid ARN");
+        assert_eq!(arn.kind, AccessPointKind::MultiRegion);
+        assert_eq!(arn.region, "");
+        assert_eq!(arn.host(), "mfzwi23gnjvgw.mrap.accesspoint.s3-global.amazonaws.com");
+
+        let endpoint = Endpoint::from_region("us-east-1", AddressingStyle::Automatic).unwrap();
+        let (uri, prefix) = endpoint.for_bucket(bucket).expect("valid ARN");
+        assert_eq!(
+            uri.as_os_str(),
+            "https://mfzwi23gnjvgw.mrap.accesspoint.s3-global.amazonaws.com"
+        );
+        assert_eq!(prefix, "");
+
+        assert!(!is_multi_region_access_point(
+            "arn:aws:s3:us-west-2:123456789012:accesspoint/my-access-point"
+        ));
+    }
+
+    #[test]
+    fn access_point_arn_for_bucket() {
+        let endpoint = Endpoint::from_region("us-east-1", AddressingStyle::Path).unwrap();
+        let (uri, prefix) = endpoint
+            .for_bucket("arn:aws:s3:us-west-2:123456789012:accesspoint/my-access-point")
+            .expect("valid ARN");
+        assert_eq!(
+            uri.as_os_str(),
+            "https://my-access-point-123456789012.s3-accesspoint.us-west-2.amazonaws.com"
+        );
+        assert_eq!(prefix, "");
+    }
+
+    #[test]
+    fn directory_bucket_for_bucket() {
+        let bucket = "amzn-s3-demo-bucket--usw2-az1--x-s3";
+        for addressing_style in [AddressingStyle::Automatic, AddressingStyle::Path] {
+            let endpoint = Endpoint::from_region("us-west-2", addressing_style).unwrap();
+            let (uri, prefix) = endpoint.for_bucket(bucket).expect("valid bucket");
+            assert_eq!(
+                uri.as_os_str(),
+                "https://amzn-s3-demo-bucket--usw2-az1--x-s3.s3express-usw2-az1.us-west-2.amazonaws.com"
+            );
+            assert_eq!(prefix, "");
+        }
+
+        // A manually specified endpoint is used as is
+        let endpoint = Endpoint::from_uri("http://localhost:9000", AddressingStyle::Automatic).unwrap();
+        let (uri, prefix) = endpoint.for_bucket(bucket).expect("valid bucket");
+        assert_eq!(uri.as_os_str(), "http://localhost:9000");
+        assert_eq!(prefix, format!("/{bucket}"));
+    }
+
+    #[test]
+    fn empty_bucket_uses_endpoint() {
+        for addressing_style in [
+            AddressingStyle::Automatic,
+            AddressingStyle::Virtual,
+            AddressingStyle::Path,
+        ] {
+            let endpoint = Endpoint::from_region("us-east-1", addressing_style).unwrap();
+            let (uri, prefix) = endpoint.for_bucket("").expect("valid endpoint");
+            assert_eq!(uri.as_os_str(), "https://s3.us-east-1.amazonaws.com");
+            assert_eq!(prefix, "");
+        }
+    }
+
+    #[test]
+    fn invalid_access_point_arns() {
+        for arn in [
+            "arn:aws:s3:us-west-2:123456789012",
+            "arn:aws:s3:us-west-2:123456789012:bucket/my-bucket",
+            "arn:aws:ec2:us-west-2:123456789012:accesspoint/my-access-point",
+            "arn:aws:s3:us-west-2:1234:accesspoint/my-access-point",
+            "arn:aws:s3-object-lambda::123456789012:accesspoint/my-access-point",
+            "arn:aws:s3::123456789012:accesspoint/my-access-point",
+            "arn:not-aws:s3:us-west-2:123456789012:accesspoint/my-access-point",
+            "arn:aws:s3:us-west-2:123456789012:accesspoint/My_Access_Point",
+        ] {
+            assert!(
+                matches!(
+                    arn.parse::<AccessPointArn>(),
+                    Err(EndpointError::InvalidAccessPointArn(..))
+                ),
+                "{arn} should be invalid"
+            );
+        }
+    }
 }
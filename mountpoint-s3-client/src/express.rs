@@ -0,0 +1,128 @@
+//! Support for S3 Express One Zone directory buckets.
+//!
+//! Directory buckets are named `<base-name>--<zone-id>--x-s3` and authorize requests with
+//! short-lived session credentials obtained from CreateSession, rather than with the caller's
+//! credentials directly.
+
+use time::{Duration, OffsetDateTime};
+
+/// The suffix that all directory bucket names end with
+pub const DIRECTORY_BUCKET_SUFFIX: &str = "--x-s3";
+
+/// How early to refresh a session before it expires, so that requests in flight don't race with
+/// the expiry.
+const SESSION_EXPIRY_MARGIN: Duration = Duration::minutes(1);
+
+/// Whether a bucket name looks like an S3 Express One Zone directory bucket
+pub fn is_directory_bucket(bucket: &str) -> bool {
+    bucket.ends_with(DIRECTORY_BUCKET_SUFFIX)
+}
+
+/// The zone ID of a directory bucket, like `usw2-az1` for `amzn-s3-demo-bucket--usw2-az1--x-s3`,
+/// or None if the bucket name doesn't look like a directory bucket
+pub fn directory_bucket_zone(bucket: &str) -> Option<&str> {
+    let (_, zone) = bucket.strip_suffix(DIRECTORY_BUCKET_SUFFIX)?.rsplit_once("--")?;
+    (!zone.is_empty()).then_some(zone)
+}
+
+/// Whether a client should use S3 Express One Zone session authentication
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExpressMode {
+    /// Use session authentication for buckets whose names look like directory buckets
+    #[default]
+    Automatic,
+    /// Always use session authentication
+    On,
+    /// Never use session authentication
+    Off,
+}
+
+impl ExpressMode {
+    /// Whether requests to the given bucket should be made with session authentication
+    pub fn uses_sessions(&self, bucket: &str) -> bool {
+        match self {
+            ExpressMode::Automatic => is_directory_bucket(bucket),
+            ExpressMode::On => true,
+            ExpressMode::Off => false,
+        }
+    }
+}
+
+/// Session credentials for a directory bucket, as returned by CreateSession.
+/// See https://docs.aws.amazon.com/AmazonS3/latest/API/API_CreateSession.html for more details.
+#[derive(Clone)]
+pub struct ExpressSession {
+    /// The access key ID of the session credentials
+    pub access_key_id: String,
+
+    /// The secret access key of the session credentials
+    pub secret_access_key: String,
+
+    /// The session token, sent with each request in the `x-amz-s3session-token` header
+    pub session_token: String,
+
+    /// When the session credentials expire
+    pub expiration: OffsetDateTime,
+}
+
+impl ExpressSession {
+    /// Whether this session should be refreshed before making a request at time `now`
+    pub fn needs_refresh(&self, now: OffsetDateTime) -> bool {
+        now + SESSION_EXPIRY_MARGIN >= self.expiration
+    }
+}
+
+impl std::fmt::Debug for ExpressSession {
+    // Don't leak the credentials into logs
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExpressSession")
+            .field("access_key_id", &self.access_key_id)
+            .field("expiration", &self.expiration)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case("amzn-s3-demo-bucket--usw2-az1--x-s3", true; "directory bucket")]
+    #[test_case("amzn-s3-demo-bucket", false; "general purpose bucket")]
+    #[test_case("x-s3", false; "suffix without separator")]
+    fn test_is_directory_bucket(bucket: &str, expected: bool) {
+        assert_eq!(is_directory_bucket(bucket), expected);
+    }
+
+    #[test_case("amzn-s3-demo-bucket--usw2-az1--x-s3", Some("usw2-az1"); "directory bucket")]
+    #[test_case("amzn-s3-demo-bucket", None; "general purpose bucket")]
+    #[test_case("amzn-s3-demo-bucket--x-s3", None; "missing zone")]
+    #[test_case("amzn-s3-demo-bucket----x-s3", None; "empty zone")]
+    fn test_directory_bucket_zone(bucket: &str, expected: Option<&str>) {
+        assert_eq!(directory_bucket_zone(bucket), expected);
+    }
+
+    #[test]
+    fn test_express_mode() {
+        let directory_bucket = "amzn-s3-demo-bucket--usw2-az1--x-s3";
+        let bucket = "amzn-s3-demo-bucket";
+        assert!(ExpressMode::Automatic.uses_sessions(directory_bucket));
+        assert!(!ExpressMode::Automatic.uses_sessions(bucket));
+        assert!(ExpressMode::On.uses_sessions(bucket));
+        assert!(!ExpressMode::Off.uses_sessions(directory_bucket));
+    }
+
+    #[test]
+    fn test_session_needs_refresh() {
+        let now = OffsetDateTime::from_unix_timestamp(1_000_000).unwrap();
+        let mut session = ExpressSession {
+            access_key_id: "access_key_id".to_string(),
+            secret_access_key: "secret_access_key".to_string(),
+            session_token: "session_token".to_string(),
+            expiration: now + Duration::minutes(5),
+        };
+        assert!(!session.needs_refresh(now));
+        session.expiration = now + Duration::seconds(30);
+        assert!(session.needs_refresh(now));
+    }
+}
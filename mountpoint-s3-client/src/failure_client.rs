@@ -1,20 +1,24 @@
 use std::collections::HashMap;
 use std::fmt::Debug;
-use std::ops::Range;
 use std::pin::Pin;
 use std::sync::Mutex;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 use async_trait::async_trait;
 use futures::Stream;
 use pin_project::pin_project;
 
 use crate::object_client::{
-    DeleteObjectError, DeleteObjectResult, GetBodyPart, GetObjectAttributesError, GetObjectAttributesResult,
-    GetObjectError, HeadObjectError, HeadObjectResult, ListObjectsError, ObjectClientError, ObjectClientResult,
-    PutObjectError, PutObjectParams, PutObjectResult,
+    AbortMultipartUploadError, AbortMultipartUploadResult, DeleteObjectError, DeleteObjectResult, DeleteObjectsError,
+    DeleteObjectsResult, GetBodyPart, GetObjectAttributesError, GetObjectAttributesResult, GetObjectError,
+    GetObjectMetadata, GetObjectParams, GetObjectProgress, HeadObjectError, HeadObjectResult, ListBucketsError,
+    ListBucketsResult, ListMultipartUploadsError, ListMultipartUploadsResult, ListObjectVersionsError,
+    ListObjectVersionsResult, ListObjectsError, ObjectCannedAcl, ObjectClientError, ObjectClientResult,
+    ObjectMetadataError, PresignPostError, PutObjectAclError, PutObjectAclResult, PutObjectError, PutObjectParams,
+    PutObjectResult,
 };
-use crate::{ETag, ListObjectsResult, ObjectAttribute, ObjectClient};
+use crate::{ListObjectsResult, ObjectAttribute, ObjectClient, PostPolicyCondition, PresignedPost};
 
 // Wrapper for injecting failures into a get stream
 pub struct FailureGetWrapper<Client: ObjectClient, GetWrapperState> {
@@ -30,8 +34,7 @@ pub struct FailureClient<Client: ObjectClient, State, GetWrapperState> {
         &mut State,
         &str,
         &str,
-        Option<Range<u64>>,
-        Option<ETag>,
+        &GetObjectParams,
     ) -> Result<
         FailureGetWrapper<Client, GetWrapperState>,
         ObjectClientError<GetObjectError, Client::ClientError>,
@@ -46,6 +49,12 @@ pub struct FailureClient<Client: ObjectClient, State, GetWrapperState> {
         usize,
         &str,
     ) -> Result<(), ObjectClientError<ListObjectsError, Client::ClientError>>,
+    pub put_object_cb: fn(
+        &mut State,
+        &str,
+        &str,
+        &PutObjectParams,
+    ) -> Result<(), ObjectClientError<PutObjectError, Client::ClientError>>,
 }
 
 #[async_trait]
@@ -62,26 +71,29 @@ where
         &self,
         bucket: &str,
         key: &str,
+        version_id: Option<&str>,
     ) -> ObjectClientResult<DeleteObjectResult, DeleteObjectError, Self::ClientError> {
         // TODO failure hook for delete_object
-        self.client.delete_object(bucket, key).await
+        self.client.delete_object(bucket, key, version_id).await
+    }
+
+    async fn delete_objects(
+        &self,
+        bucket: &str,
+        keys: &[String],
+    ) -> ObjectClientResult<DeleteObjectsResult, DeleteObjectsError, Self::ClientError> {
+        // TODO failure hook for delete_objects
+        self.client.delete_objects(bucket, keys).await
     }
 
     async fn get_object(
         &self,
         bucket: &str,
         key: &str,
-        range: Option<Range<u64>>,
-        if_match: Option<ETag>,
+        params: &GetObjectParams,
     ) -> ObjectClientResult<Self::GetObjectResult, GetObjectError, Self::ClientError> {
-        let wrapper = (self.get_object_cb)(
-            &mut *self.state.lock().unwrap(),
-            bucket,
-            key,
-            range.clone(),
-            if_match.clone(),
-        )?;
-        let get_result = self.client.get_object(bucket, key, range, if_match).await?;
+        let wrapper = (self.get_object_cb)(&mut *self.state.lock().unwrap(), bucket, key, params)?;
+        let get_result = self.client.get_object(bucket, key, params).await?;
         Ok(FailureGetResult {
             state: wrapper.state,
             result_fn: wrapper.result_fn,
@@ -111,13 +123,28 @@ where
             .await
     }
 
+    async fn list_object_versions(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        key_marker: Option<&str>,
+        version_id_marker: Option<&str>,
+        max_keys: usize,
+    ) -> ObjectClientResult<ListObjectVersionsResult, ListObjectVersionsError, Self::ClientError> {
+        // TODO failure hook for list_object_versions
+        self.client
+            .list_object_versions(bucket, prefix, key_marker, version_id_marker, max_keys)
+            .await
+    }
+
     async fn head_object(
         &self,
         bucket: &str,
         key: &str,
+        version_id: Option<&str>,
     ) -> ObjectClientResult<HeadObjectResult, HeadObjectError, Self::ClientError> {
         (self.head_object_cb)(&mut *self.state.lock().unwrap(), bucket, key)?;
-        self.client.head_object(bucket, key).await
+        self.client.head_object(bucket, key, version_id).await
     }
 
     async fn put_object(
@@ -127,10 +154,59 @@ where
         params: &PutObjectParams,
         contents: impl Stream<Item = impl AsRef<[u8]> + Send> + Send,
     ) -> ObjectClientResult<PutObjectResult, PutObjectError, Self::ClientError> {
-        // TODO Add put fault injection hooks
+        (self.put_object_cb)(&mut *self.state.lock().unwrap(), bucket, key, params)?;
         self.client.put_object(bucket, key, params, contents).await
     }
 
+    async fn copy_object(
+        &self,
+        source_bucket: &str,
+        source_key: &str,
+        destination_bucket: &str,
+        destination_key: &str,
+    ) -> ObjectClientResult<CopyObjectResult, CopyObjectError, Self::ClientError> {
+        // TODO failure hook for copy_object
+        self.client
+            .copy_object(source_bucket, source_key, destination_bucket, destination_key)
+            .await
+    }
+
+    async fn put_object_acl(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        acl: ObjectCannedAcl,
+    ) -> ObjectClientResult<PutObjectAclResult, CopyObjectError, CopyObjectResult, PutObjectAclError, Self::ClientError>
+    {
+        // TODO failure hook for put_object_acl
+        self.client.put_object_acl(bucket, key, version_id, acl).await
+    }
+
+    async fn list_multipart_uploads(
+        &self,
+        bucket: &str,
+        prefix: &str,
+    ) -> ObjectClientResult<ListMultipartUploadsResult, ListMultipartUploadsError, Self::ClientError> {
+        // TODO failure hook for list_multipart_uploads
+        self.client.list_multipart_uploads(bucket, prefix).await
+    }
+
+    async fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> ObjectClientResult<AbortMultipartUploadResult, AbortMultipartUploadError, Self::ClientError> {
+        // TODO failure hook for abort_multipart_upload
+        self.client.abort_multipart_upload(bucket, key, upload_id).await
+    }
+
+    async fn list_buckets(&self) -> ObjectClientResult<ListBucketsResult, ListBucketsError, Self::ClientError> {
+        // TODO failure hook for list_buckets
+        self.client.list_buckets().await
+    }
+
     async fn get_object_attributes(
         &self,
         bucket: &str,
@@ -144,6 +220,18 @@ where
             .get_object_attributes(bucket, key, max_parts, part_number_marker, object_attributes)
             .await
     }
+
+    async fn presign_post_object(
+        &self,
+        bucket: &str,
+        key_prefix: &str,
+        conditions: &[PostPolicyCondition],
+        expires_in: Duration,
+    ) -> ObjectClientResult<PresignedPost, PresignPostError, Self::ClientError> {
+        self.client
+            .presign_post_object(bucket, key_prefix, conditions, expires_in)
+            .await
+    }
 }
 
 #[pin_project]
@@ -154,6 +242,16 @@ pub struct FailureGetResult<Client: ObjectClient, GetWrapperState> {
     get_result: Client::GetObjectResult,
 }
 
+impl<Client: ObjectClient, FailState> GetObjectProgress for FailureGetResult<Client, FailState> {
+    fn bytes_transferred(&self) -> u64 {
+        self.get_result.bytes_transferred()
+    }
+
+    fn object_metadata(&self) -> Option<Result<GetObjectMetadata, ObjectMetadataError>> {
+        self.get_result.object_metadata()
+    }
+}
+
 impl<Client: ObjectClient, FailState> Stream for FailureGetResult<Client, FailState> {
     type Item = ObjectClientResult<GetBodyPart, GetObjectError, Client::ClientError>;
 
@@ -185,6 +283,8 @@ pub struct CountdownFailureClientState<Client: ObjectClient> {
     head_failures: HashMap<usize, ObjectClientError<HeadObjectError, Client::ClientError>>,
     list_count: usize,
     list_failures: HashMap<usize, ObjectClientError<ListObjectsError, Client::ClientError>>,
+    put_count: usize,
+    put_failures: HashMap<usize, ObjectClientError<PutObjectError, Client::ClientError>>,
 }
 
 #[derive(Debug, Default)]
@@ -204,11 +304,11 @@ pub fn countdown_failure_client<Client: ObjectClient>(
     // (Note: we could also define a failure client that tracks offsets, and returns an error when the offset
     // reaches a specified threshold.)
     get_results: GetFailureMap<Client>,
-    // For HEAD and LIST, map entries are interpreted as follows:
+    // For HEAD, LIST, and PUT, map entries are interpreted as follows:
     //   (k -> E) means inject error E on the k'th call to that operation
     head_failures: HashMap<usize, ObjectClientError<HeadObjectError, Client::ClientError>>,
     list_failures: HashMap<usize, ObjectClientError<ListObjectsError, Client::ClientError>>,
-    // TODO add put failures
+    put_failures: HashMap<usize, ObjectClientError<PutObjectError, Client::ClientError>>,
 ) -> CountdownFailureClient<Client> {
     let state = Mutex::new(CountdownFailureClientState {
         get_count: 0usize,
@@ -217,11 +317,13 @@ pub fn countdown_failure_client<Client: ObjectClient>(
         head_failures,
         list_count: 0usize,
         list_failures,
+        put_count: 0usize,
+        put_failures,
     });
     FailureClient {
         client,
         state,
-        get_object_cb: |state, _bucket, _key, _range, _if_match| {
+        get_object_cb: |state, _bucket, _key, _params| {
             state.get_count += 1;
             let (fail_count, error) = if let Some(result) = state.get_results.remove(&state.get_count) {
                 let (fail_count, error) = result?;
@@ -261,6 +363,14 @@ pub fn countdown_failure_client<Client: ObjectClient>(
                 Ok(())
             }
         },
+        put_object_cb: |state, _bucket, _key, _params| {
+            state.put_count += 1;
+            if let Some(error) = state.put_failures.remove(&state.put_count) {
+                Err(error)
+            } else {
+                Ok(())
+            }
+        },
     }
 }
 
@@ -268,6 +378,7 @@ pub fn countdown_failure_client<Client: ObjectClient>(
 mod tests {
     use super::*;
     use crate::mock_client::{MockClient, MockClientConfig, MockClientError, MockObject};
+    use crate::ETag;
     use std::collections::HashSet;
 
     #[tokio::test]
@@ -299,11 +410,12 @@ mod tests {
             Err(ObjectClientError::ClientError(MockClientError("no such bucket".into()))),
         );
 
-        let fail_client = countdown_failure_client(client, get_failures, HashMap::new(), HashMap::new());
+        let fail_client =
+            countdown_failure_client(client, get_failures, HashMap::new(), HashMap::new(), HashMap::new());
 
         let fail_set = HashSet::from([2, 4, 5]);
         for i in 1..=6 {
-            let r = fail_client.get_object(bucket, key, None, None).await;
+            let r = fail_client.get_object(bucket, key, &GetObjectParams::new()).await;
             if fail_set.contains(&i) {
                 assert!(r.is_err());
             } else {
@@ -39,6 +39,7 @@ impl ImdsCrtClient {
         let bootstrap_options = ClientBootstrapOptions {
             event_loop_group: &mut event_loop_group,
             host_resolver: &mut host_resolver,
+            address_family: Default::default(),
         };
 
         let client_bootstrap = ClientBootstrap::new(&allocator, &bootstrap_options).unwrap();
@@ -0,0 +1,576 @@
+//! A pass-through [ObjectClient] that runs caller-provided hooks around the requests it forwards,
+//! for adding behavior like logging, fault injection, or caching to any client.
+//!
+//! "Before" hooks run before a request is forwarded, and can fail the request without forwarding it
+//! by returning an error. "After" hooks see the result of each forwarded request, but can't change
+//! it. Hooks get the same arguments as the [ObjectClient] method they intercept.
+
+use std::fmt::{self, Debug};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+
+use crate::object_client::{
+    AbortMultipartUploadError, AbortMultipartUploadResult, CopyObjectError, CopyObjectResult, DeleteObjectError,
+    DeleteObjectResult, DeleteObjectsError, DeleteObjectsResult, GetObjectAttributesError, GetObjectAttributesResult,
+    GetObjectError, GetObjectParams, HeadObjectError, HeadObjectResult, ListBucketsError, ListBucketsResult,
+    ListMultipartUploadsError, ListMultipartUploadsResult, ListObjectVersionsError, ListObjectVersionsResult,
+    ListObjectsError, ObjectCannedAcl, ObjectClientResult, PresignPostError, PutObjectAclError, PutObjectAclResult,
+    PutObjectError, PutObjectParams, PutObjectResult,
+};
+use crate::{ListObjectsResult, ObjectAttribute, ObjectClient, PostPolicyCondition, PresignedPost};
+
+/// An [ObjectClient] that forwards requests to another client, running hooks before and after
+/// each one. Construct with [InterceptingClient::new] and add hooks with the builder methods, e.g.
+/// `InterceptingClient::new(client).before_get(|bucket, key, params| Ok(()))`. Setting a hook
+/// replaces any previous hook for the same request.
+///
+/// Only GetObject, HeadObject, ListObjects, PutObject, and DeleteObject requests can be
+/// intercepted; other requests are forwarded unchanged.
+#[allow(clippy::type_complexity)]
+pub struct InterceptingClient<Client: ObjectClient> {
+    client: Client,
+    before_get: Option<
+        Box<
+            dyn Fn(&str, &str, &GetObjectParams) -> ObjectClientResult<(), GetObjectError, Client::ClientError>
+                + Send
+                + Sync,
+        >,
+    >,
+    after_get: Option<
+        Box<
+            dyn Fn(
+                    &str,
+                    &str,
+                    &GetObjectParams,
+                    &ObjectClientResult<Client::GetObjectResult, GetObjectError, Client::ClientError>,
+                ) + Send
+                + Sync,
+        >,
+    >,
+    before_head: Option<
+        Box<
+            dyn Fn(&str, &str, Option<&str>) -> ObjectClientResult<(), HeadObjectError, Client::ClientError>
+                + Send
+                + Sync,
+        >,
+    >,
+    after_head: Option<
+        Box<
+            dyn Fn(
+                    &str,
+                    &str,
+                    Option<&str>,
+                    &ObjectClientResult<HeadObjectResult, HeadObjectError, Client::ClientError>,
+                ) + Send
+                + Sync,
+        >,
+    >,
+    before_list: Option<
+        Box<
+            dyn Fn(
+                    &str,
+                    Option<&str>,
+                    &str,
+                    usize,
+                    &str,
+                ) -> ObjectClientResult<(), ListObjectsError, Client::ClientError>
+                + Send
+                + Sync,
+        >,
+    >,
+    after_list: Option<
+        Box<
+            dyn Fn(
+                    &str,
+                    Option<&str>,
+                    &str,
+                    usize,
+                    &str,
+                    &ObjectClientResult<ListObjectsResult, ListObjectsError, Client::ClientError>,
+                ) + Send
+                + Sync,
+        >,
+    >,
+    before_put: Option<
+        Box<
+            dyn Fn(&str, &str, &PutObjectParams) -> ObjectClientResult<(), PutObjectError, Client::ClientError>
+                + Send
+                + Sync,
+        >,
+    >,
+    after_put: Option<
+        Box<
+            dyn Fn(
+                    &str,
+                    &str,
+                    &PutObjectParams,
+                    &ObjectClientResult<PutObjectResult, PutObjectError, Client::ClientError>,
+                ) + Send
+                + Sync,
+        >,
+    >,
+    map_put_contents: Option<Box<dyn Fn(&str, &str, &[u8]) -> Vec<u8> + Send + Sync>>,
+    before_delete: Option<
+        Box<
+            dyn Fn(&str, &str, Option<&str>) -> ObjectClientResult<(), DeleteObjectError, Client::ClientError>
+                + Send
+                + Sync,
+        >,
+    >,
+    after_delete: Option<
+        Box<
+            dyn Fn(
+                    &str,
+                    &str,
+                    Option<&str>,
+                    &ObjectClientResult<DeleteObjectResult, DeleteObjectError, Client::ClientError>,
+                ) + Send
+                + Sync,
+        >,
+    >,
+}
+
+impl<Client: ObjectClient> InterceptingClient<Client> {
+    /// Wrap a client without any hooks
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            before_get: None,
+            after_get: None,
+            before_head: None,
+            after_head: None,
+            before_list: None,
+            after_list: None,
+            before_put: None,
+            after_put: None,
+            map_put_contents: None,
+            before_delete: None,
+            after_delete: None,
+        }
+    }
+
+    /// The client requests are forwarded to
+    pub fn inner(&self) -> &Client {
+        &self.client
+    }
+
+    /// Run a hook before each GetObject request
+    pub fn before_get(
+        mut self,
+        hook: impl Fn(&str, &str, &GetObjectParams) -> ObjectClientResult<(), GetObjectError, Client::ClientError>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.before_get = Some(Box::new(hook));
+        self
+    }
+
+    /// Run a hook after each GetObject request
+    pub fn after_get(
+        mut self,
+        hook: impl Fn(
+                &str,
+                &str,
+                &GetObjectParams,
+                &ObjectClientResult<Client::GetObjectResult, GetObjectError, Client::ClientError>,
+            ) + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.after_get = Some(Box::new(hook));
+        self
+    }
+
+    /// Run a hook before each HeadObject request
+    pub fn before_head(
+        mut self,
+        hook: impl Fn(&str, &str, Option<&str>) -> ObjectClientResult<(), HeadObjectError, Client::ClientError>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.before_head = Some(Box::new(hook));
+        self
+    }
+
+    /// Run a hook after each HeadObject request
+    pub fn after_head(
+        mut self,
+        hook: impl Fn(&str, &str, Option<&str>, &ObjectClientResult<HeadObjectResult, HeadObjectError, Client::ClientError>)
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.after_head = Some(Box::new(hook));
+        self
+    }
+
+    /// Run a hook before each ListObjects request
+    pub fn before_list(
+        mut self,
+        hook: impl Fn(&str, Option<&str>, &str, usize, &str) -> ObjectClientResult<(), ListObjectsError, Client::ClientError>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.before_list = Some(Box::new(hook));
+        self
+    }
+
+    /// Run a hook after each ListObjects request
+    pub fn after_list(
+        mut self,
+        hook: impl Fn(
+                &str,
+                Option<&str>,
+                &str,
+                usize,
+                &str,
+                &ObjectClientResult<ListObjectsResult, ListObjectsError, Client::ClientError>,
+            ) + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.after_list = Some(Box::new(hook));
+        self
+    }
+
+    /// Run a hook before each PutObject request
+    pub fn before_put(
+        mut self,
+        hook: impl Fn(&str, &str, &PutObjectParams) -> ObjectClientResult<(), PutObjectError, Client::ClientError>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.before_put = Some(Box::new(hook));
+        self
+    }
+
+    /// Run a hook after each PutObject request
+    pub fn after_put(
+        mut self,
+        hook: impl Fn(&str, &str, &PutObjectParams, &ObjectClientResult<PutObjectResult, PutObjectError, Client::ClientError>)
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.after_put = Some(Box::new(hook));
+        self
+    }
+
+    /// Replace each chunk of the contents of PutObject requests with the result of a hook, for
+    /// example to corrupt or drop some of the data. The hook gets the bucket, key, and chunk.
+    pub fn map_put_contents(mut self, hook: impl Fn(&str, &str, &[u8]) -> Vec<u8> + Send + Sync + 'static) -> Self {
+        self.map_put_contents = Some(Box::new(hook));
+        self
+    }
+
+    /// Run a hook before each DeleteObject request
+    pub fn before_delete(
+        mut self,
+        hook: impl Fn(&str, &str, Option<&str>) -> ObjectClientResult<(), DeleteObjectError, Client::ClientError>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.before_delete = Some(Box::new(hook));
+        self
+    }
+
+    /// Run a hook after each DeleteObject request
+    pub fn after_delete(
+        mut self,
+        hook: impl Fn(
+                &str,
+                &str,
+                Option<&str>,
+                &ObjectClientResult<DeleteObjectResult, DeleteObjectError, Client::ClientError>,
+            ) + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.after_delete = Some(Box::new(hook));
+        self
+    }
+}
+
+impl<Client: ObjectClient + Debug> Debug for InterceptingClient<Client> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InterceptingClient")
+            .field("client", &self.client)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl<Client> ObjectClient for InterceptingClient<Client>
+where
+    Client: ObjectClient + Send + Sync + 'static,
+{
+    type GetObjectResult = Client::GetObjectResult;
+    type ClientError = Client::ClientError;
+
+    async fn delete_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> ObjectClientResult<DeleteObjectResult, DeleteObjectError, Self::ClientError> {
+        if let Some(hook) = &self.before_delete {
+            hook(bucket, key, version_id)?;
+        }
+        let result = self.client.delete_object(bucket, key, version_id).await;
+        if let Some(hook) = &self.after_delete {
+            hook(bucket, key, version_id, &result);
+        }
+        result
+    }
+
+    async fn delete_objects(
+        &self,
+        bucket: &str,
+        keys: &[String],
+    ) -> ObjectClientResult<DeleteObjectsResult, DeleteObjectsError, Self::ClientError> {
+        self.client.delete_objects(bucket, keys).await
+    }
+
+    async fn get_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &GetObjectParams,
+    ) -> ObjectClientResult<Self::GetObjectResult, GetObjectError, Self::ClientError> {
+        if let Some(hook) = &self.before_get {
+            hook(bucket, key, params)?;
+        }
+        let result = self.client.get_object(bucket, key, params).await;
+        if let Some(hook) = &self.after_get {
+            hook(bucket, key, params, &result);
+        }
+        result
+    }
+
+    async fn list_objects(
+        &self,
+        bucket: &str,
+        continuation_token: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, Self::ClientError> {
+        if let Some(hook) = &self.before_list {
+            hook(bucket, continuation_token, delimiter, max_keys, prefix)?;
+        }
+        let result = self
+            .client
+            .list_objects(bucket, continuation_token, delimiter, max_keys, prefix)
+            .await;
+        if let Some(hook) = &self.after_list {
+            hook(bucket, continuation_token, delimiter, max_keys, prefix, &result);
+        }
+        result
+    }
+
+    async fn list_object_versions(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        key_marker: Option<&str>,
+        version_id_marker: Option<&str>,
+        max_keys: usize,
+    ) -> ObjectClientResult<ListObjectVersionsResult, ListObjectVersionsError, Self::ClientError> {
+        self.client
+            .list_object_versions(bucket, prefix, key_marker, version_id_marker, max_keys)
+            .await
+    }
+
+    async fn head_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> ObjectClientResult<HeadObjectResult, HeadObjectError, Self::ClientError> {
+        if let Some(hook) = &self.before_head {
+            hook(bucket, key, version_id)?;
+        }
+        let result = self.client.head_object(bucket, key, version_id).await;
+        if let Some(hook) = &self.after_head {
+            hook(bucket, key, version_id, &result);
+        }
+        result
+    }
+
+    async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectParams,
+        contents: impl Stream<Item = impl AsRef<[u8]> + Send> + Send,
+    ) -> ObjectClientResult<PutObjectResult, PutObjectError, Self::ClientError> {
+        if let Some(hook) = &self.before_put {
+            hook(bucket, key, params)?;
+        }
+        let result = match &self.map_put_contents {
+            Some(hook) => {
+                let contents = contents.map(|chunk| hook(bucket, key, chunk.as_ref()));
+                self.client.put_object(bucket, key, params, contents).await
+            }
+            None => self.client.put_object(bucket, key, params, contents).await,
+        };
+        if let Some(hook) = &self.after_put {
+            hook(bucket, key, params, &result);
+        }
+        result
+    }
+
+    async fn copy_object(
+        &self,
+        source_bucket: &str,
+        source_key: &str,
+        destination_bucket: &str,
+        destination_key: &str,
+    ) -> ObjectClientResult<CopyObjectResult, CopyObjectError, Self::ClientError> {
+        self.client
+            .copy_object(source_bucket, source_key, destination_bucket, destination_key)
+            .await
+    }
+
+    async fn put_object_acl(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        acl: ObjectCannedAcl,
+    ) -> ObjectClientResult<PutObjectAclResult, PutObjectAclError, Self::ClientError> {
+        self.client.put_object_acl(bucket, key, version_id, acl).await
+    }
+
+    async fn list_multipart_uploads(
+        &self,
+        bucket: &str,
+        prefix: &str,
+    ) -> ObjectClientResult<ListMultipartUploadsResult, ListMultipartUploadsError, Self::ClientError> {
+        self.client.list_multipart_uploads(bucket, prefix).await
+    }
+
+    async fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> ObjectClientResult<AbortMultipartUploadResult, AbortMultipartUploadError, Self::ClientError> {
+        self.client.abort_multipart_upload(bucket, key, upload_id).await
+    }
+
+    async fn list_buckets(&self) -> ObjectClientResult<ListBucketsResult, ListBucketsError, Self::ClientError> {
+        self.client.list_buckets().await
+    }
+
+    async fn get_object_attributes(
+        &self,
+        bucket: &str,
+        key: &str,
+        max_parts: Option<usize>,
+        part_number_marker: Option<usize>,
+        object_attributes: &[ObjectAttribute],
+    ) -> ObjectClientResult<GetObjectAttributesResult, GetObjectAttributesError, Self::ClientError> {
+        self.client
+            .get_object_attributes(bucket, key, max_parts, part_number_marker, object_attributes)
+            .await
+    }
+
+    async fn presign_post_object(
+        &self,
+        bucket: &str,
+        key_prefix: &str,
+        conditions: &[PostPolicyCondition],
+        expires_in: Duration,
+    ) -> ObjectClientResult<PresignedPost, PresignPostError, Self::ClientError> {
+        self.client
+            .presign_post_object(bucket, key_prefix, conditions, expires_in)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::mock_client::{MockClient, MockClientConfig, MockClientError, MockObject, Operation};
+    use crate::{ETag, ObjectClientError};
+
+    #[tokio::test]
+    async fn count_get_object_calls() {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 128,
+        });
+        client.add_object("key", MockObject::constant(0xa1, 50, ETag::for_tests()));
+
+        let attempted = Arc::new(AtomicUsize::new(0));
+        let succeeded = Arc::new(AtomicUsize::new(0));
+        let client = InterceptingClient::new(client)
+            .before_get({
+                let attempted = Arc::clone(&attempted);
+                move |_bucket, _key, _params| {
+                    attempted.fetch_add(1, Ordering::SeqCst);
+                    Ok(())
+                }
+            })
+            .after_get({
+                let succeeded = Arc::clone(&succeeded);
+                move |_bucket, _key, _params, result| {
+                    if result.is_ok() {
+                        succeeded.fetch_add(1, Ordering::SeqCst);
+                    }
+                }
+            });
+
+        for _ in 0..3 {
+            client
+                .get_object("test_bucket", "key", &GetObjectParams::new())
+                .await
+                .unwrap();
+        }
+        let missing = client
+            .get_object("test_bucket", "missing", &GetObjectParams::new())
+            .await;
+        assert!(matches!(
+            missing,
+            Err(ObjectClientError::ServiceError(GetObjectError::NoSuchKey))
+        ));
+        // Other requests aren't counted
+        client.head_object("test_bucket", "key", None).await.unwrap();
+
+        assert_eq!(attempted.load(Ordering::SeqCst), 4);
+        assert_eq!(succeeded.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn before_hook_fails_request() {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 128,
+        });
+        client.add_object("key", MockObject::constant(0xa1, 50, ETag::for_tests()));
+
+        let client = InterceptingClient::new(client).before_head(|_bucket, key, _version_id| {
+            if key == "key" {
+                Err(ObjectClientError::ClientError(MockClientError("injected".into())))
+            } else {
+                Ok(())
+            }
+        });
+
+        let result = client.head_object("test_bucket", "key", None).await;
+        assert!(matches!(result, Err(ObjectClientError::ClientError(_))));
+        // The request never reached the inner client
+        assert_eq!(client.inner().op_count(Operation::HeadObject), 0);
+    }
+}
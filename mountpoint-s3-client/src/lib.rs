@@ -1,16 +1,26 @@
 mod endpoint;
+pub mod express;
 pub mod failure_client;
 mod imds_crt_client;
+pub mod intercepting_client;
+pub mod local_client;
 pub mod mock_client;
 mod object_client;
+mod presign;
+pub mod retrying_client;
 mod s3_crt_client;
+pub mod timeout_client;
+pub mod timer;
 mod util;
 
-pub use endpoint::{AddressingStyle, Endpoint};
+pub use endpoint::{AccessPointArn, AccessPointKind, AddressingStyle, Endpoint};
+pub use express::ExpressMode;
 pub use imds_crt_client::ImdsCrtClient;
 pub use object_client::*;
+pub use presign::{PostPolicyCondition, PresignedPost};
+pub use retrying_client::{RetryBudgetConfig, RetryStrategyConfig};
 pub use s3_crt_client::head_bucket::HeadBucketError;
-pub use s3_crt_client::{S3ClientConfig, S3CrtClient, S3RequestError};
+pub use s3_crt_client::{S3ClientConfig, S3CrtClient, S3ErrorCode, S3ErrorResponse, S3RequestError};
 
 #[cfg(test)]
 mod tests {
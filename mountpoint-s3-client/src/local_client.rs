@@ -0,0 +1,856 @@
+//! An [ObjectClient] that stores objects as files in a local directory, for offline development and
+//! for tests that need real byte persistence without holding every object in memory.
+//!
+//! Each object is a file under the root directory. The key is split into components on `/`, each
+//! component but the last becomes a directory, and the last becomes a file with an `.obj` suffix.
+//! Components are percent-encoded so that any key can be stored: `%`, `.`, and NUL are escaped, and
+//! an empty component is stored as `%`. Since encoded components never contain a `.`, object files
+//! and directories can't collide, so a key like `a` can exist alongside keys under `a/`.
+//!
+//! The client doesn't keep any metadata besides the files themselves. ETags are derived from the
+//! file's modification time and size, and the bucket is unversioned: every object has the version
+//! id `null`, as in S3.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use thiserror::Error;
+use time::OffsetDateTime;
+use tracing::trace;
+
+use crate::object_client::{
+    AbortMultipartUploadError, AbortMultipartUploadResult, BucketInfo, CopyObjectError, CopyObjectResult,
+    DeleteObjectError, DeleteObjectResult, DeleteObjectsError, DeleteObjectsResult, GetBodyPart,
+    GetObjectAttributesError, GetObjectAttributesResult, GetObjectError, GetObjectMetadata, GetObjectParams,
+    GetObjectProgress, HeadObjectError, HeadObjectResult, ListBucketsError, ListBucketsResult,
+    ListMultipartUploadsError, ListMultipartUploadsResult, ListObjectVersionsError, ListObjectVersionsResult,
+    ListObjectsError, ListObjectsResult, ObjectCannedAcl, ObjectClient, ObjectClientError, ObjectClientResult,
+    ObjectInfo, ObjectMetadataError, ObjectVersion, PresignPostError, PutObjectAclError, PutObjectAclResult,
+    PutObjectError, PutObjectParams, PutObjectResult, MAX_DELETE_OBJECTS_KEYS,
+};
+use crate::retrying_client::RetryableError;
+use crate::{ETag, ObjectAttribute, PostPolicyCondition, PresignedPost};
+
+/// Suffix of the files that hold objects
+const OBJECT_SUFFIX: &str = ".obj";
+
+/// Version id S3 reports for objects in unversioned buckets
+const NULL_VERSION_ID: &str = "null";
+
+/// Size of the body parts returned by GetObject
+const PART_SIZE: usize = 1024 * 1024;
+
+/// An [ObjectClient] for a single bucket whose objects are stored under a local directory
+#[derive(Debug)]
+pub struct LocalObjectClient {
+    bucket: String,
+    root: PathBuf,
+    next_temp_file: AtomicU64,
+}
+
+impl LocalObjectClient {
+    /// Create a client for the given bucket that stores its objects under `root`. The directory is
+    /// created if it doesn't exist, and any objects already in it are visible to the client.
+    pub fn new(bucket: &str, root: impl Into<PathBuf>) -> io::Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        Ok(Self {
+            bucket: bucket.to_owned(),
+            root,
+            next_temp_file: AtomicU64::new(0),
+        })
+    }
+
+    /// The directory this client stores its objects in
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Path of the file that holds the object with the given key
+    fn object_path(&self, key: &str) -> PathBuf {
+        let mut components: Vec<_> = key.split('/').collect();
+        let name = components.pop().expect("split always returns at least one component");
+        let mut path = self.root.clone();
+        for component in components {
+            path.push(encode_component(component));
+        }
+        // An empty final component doesn't need the `%` placeholder, as the suffix is never empty
+        path.push(format!("{}{OBJECT_SUFFIX}", escape(name)));
+        path
+    }
+
+    /// Metadata of the object with the given key, or `None` if it doesn't exist
+    fn object_info(&self, key: &str) -> Result<Option<ObjectInfo>, LocalClientError> {
+        match fs::metadata(self.object_path(key)) {
+            Ok(metadata) if metadata.is_file() => Ok(Some(object_info(key, &metadata)?)),
+            Ok(_) => Ok(None),
+            Err(e) if is_not_found(&e) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Metadata of every object whose key starts with `prefix`, in key order
+    fn objects_with_prefix(&self, prefix: &str) -> Result<Vec<ObjectInfo>, LocalClientError> {
+        // Only walk the deepest directory that contains every key with the prefix
+        let mut path = self.root.clone();
+        let mut key_prefix = String::new();
+        if let Some((directory, _)) = prefix.rsplit_once('/') {
+            for component in directory.split('/') {
+                path.push(encode_component(component));
+                key_prefix.push_str(component);
+                key_prefix.push('/');
+            }
+        }
+
+        let mut objects = Vec::new();
+        walk_directory(&path, &key_prefix, &mut objects)?;
+        objects.retain(|object| object.key.starts_with(prefix));
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(objects)
+    }
+
+    /// Remove the object with the given key, and any directories it leaves empty
+    fn remove_object(&self, key: &str) -> Result<(), LocalClientError> {
+        let path = self.object_path(key);
+        match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(e) if is_not_found(&e) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+        let mut directory = path.parent();
+        while let Some(dir) = directory {
+            // Fails if the directory isn't empty, which is where we want to stop anyway
+            if dir == self.root || fs::remove_dir(dir).is_err() {
+                break;
+            }
+            directory = dir.parent();
+        }
+        Ok(())
+    }
+
+    /// Atomically replace the object with the given key with new contents
+    fn write_object(&self, key: &str, contents: &[u8]) -> Result<(), LocalClientError> {
+        let path = self.object_path(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Temporary files don't have the object suffix, so they're never listed
+        let temp_path = self.root.join(format!(
+            ".tmp-{}-{}",
+            std::process::id(),
+            self.next_temp_file.fetch_add(1, Ordering::SeqCst)
+        ));
+        let mut file = File::create(&temp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, &path)?;
+        Ok(())
+    }
+
+    /// Whether the given version id, if any, refers to the only version each object has
+    fn check_version_id(version_id: Option<&str>) -> bool {
+        version_id
+            .map(|version_id| version_id == NULL_VERSION_ID)
+            .unwrap_or(true)
+    }
+}
+
+/// Errors returned by a [LocalObjectClient]
+#[derive(Debug, Error)]
+pub enum LocalClientError {
+    #[error("IO error")]
+    IoError(#[from] io::Error),
+
+    #[error("invalid range {0:?} for object of size {1}")]
+    InvalidRange(std::ops::Range<u64>, u64),
+}
+
+impl RetryableError for LocalClientError {
+    fn is_retryable(&self) -> bool {
+        match self {
+            LocalClientError::IoError(e) => matches!(
+                e.kind(),
+                ErrorKind::Interrupted
+                    | ErrorKind::TimedOut
+                    | ErrorKind::WouldBlock
+                    | ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+            ),
+            LocalClientError::InvalidRange(..) => false,
+        }
+    }
+}
+
+fn is_not_found(error: &io::Error) -> bool {
+    // A path component being a file rather than a directory also means there's no such object
+    matches!(error.kind(), ErrorKind::NotFound) || error.raw_os_error() == Some(libc::ENOTDIR)
+}
+
+/// Percent-encode a directory component of a key
+fn encode_component(component: &str) -> String {
+    if component.is_empty() {
+        "%".to_owned()
+    } else {
+        escape(component)
+    }
+}
+
+/// Decode a directory component, or `None` if the name wasn't produced by [encode_component]
+fn decode_component(name: &str) -> Option<String> {
+    if name == "%" {
+        Some(String::new())
+    } else {
+        unescape(name)
+    }
+}
+
+fn escape(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c {
+            '%' | '.' | '\0' => escaped.push_str(&format!("%{:02X}", c as u8)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(name: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(name.len());
+    let mut iter = name.bytes();
+    while let Some(b) = iter.next() {
+        if b == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Collect the metadata of every object in the directory tree at `path`, whose keys start with
+/// `key_prefix`
+fn walk_directory(path: &Path, key_prefix: &str, objects: &mut Vec<ObjectInfo>) -> Result<(), LocalClientError> {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(e) if is_not_found(&e) => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let Some(name) = entry.file_name().to_str().map(str::to_owned) else {
+            continue;
+        };
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if let Some(component) = decode_component(&name) {
+                walk_directory(&entry.path(), &format!("{key_prefix}{component}/"), objects)?;
+            }
+        } else if let Some(name) = name.strip_suffix(OBJECT_SUFFIX).and_then(unescape) {
+            let key = format!("{key_prefix}{name}");
+            objects.push(object_info(&key, &entry.metadata()?)?);
+        }
+    }
+    Ok(())
+}
+
+fn object_info(key: &str, metadata: &fs::Metadata) -> Result<ObjectInfo, LocalClientError> {
+    let last_modified = OffsetDateTime::from(metadata.modified()?);
+    let etag = format!("{:x}-{:x}", last_modified.unix_timestamp_nanos(), metadata.len());
+    Ok(ObjectInfo {
+        key: key.to_owned(),
+        size: metadata.len(),
+        last_modified,
+        storage_class: None,
+        etag,
+        expiration: None,
+        version_id: None,
+    })
+}
+
+#[async_trait]
+impl ObjectClient for LocalObjectClient {
+    type GetObjectResult = LocalGetObjectResult;
+    type ClientError = LocalClientError;
+
+    async fn delete_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> ObjectClientResult<DeleteObjectResult, DeleteObjectError, Self::ClientError> {
+        trace!(bucket, key, ?version_id, "DeleteObject");
+        if bucket != self.bucket {
+            return Err(ObjectClientError::ServiceError(DeleteObjectError::NoSuchBucket));
+        }
+
+        // Deleting a version that doesn't exist is a no-op, as in S3
+        if Self::check_version_id(version_id) {
+            self.remove_object(key)?;
+        }
+        Ok(DeleteObjectResult {
+            version_id: version_id.map(str::to_owned),
+        })
+    }
+
+    async fn delete_objects(
+        &self,
+        bucket: &str,
+        keys: &[String],
+    ) -> ObjectClientResult<DeleteObjectsResult, DeleteObjectsError, Self::ClientError> {
+        trace!(bucket, num_keys = keys.len(), "DeleteObjects");
+        if bucket != self.bucket {
+            return Err(ObjectClientError::ServiceError(DeleteObjectsError::NoSuchBucket));
+        }
+
+        if keys.len() > MAX_DELETE_OBJECTS_KEYS {
+            return Err(ObjectClientError::ServiceError(DeleteObjectsError::TooManyKeys(
+                keys.len(),
+            )));
+        }
+
+        let mut result = DeleteObjectsResult::default();
+        for key in keys {
+            self.remove_object(key)?;
+            result.deleted.push(key.clone());
+        }
+        Ok(result)
+    }
+
+    async fn get_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &GetObjectParams,
+    ) -> ObjectClientResult<Self::GetObjectResult, GetObjectError, Self::ClientError> {
+        trace!(bucket, key, ?params, "GetObject");
+        if bucket != self.bucket {
+            return Err(ObjectClientError::ServiceError(GetObjectError::NoSuchBucket));
+        }
+        if !Self::check_version_id(params.version_id.as_deref()) {
+            return Err(ObjectClientError::ServiceError(GetObjectError::NoSuchKey));
+        }
+
+        // Hold the file open so the body stays consistent even if the object is replaced
+        let mut file = match File::open(self.object_path(key)) {
+            Ok(file) => file,
+            Err(e) if is_not_found(&e) => return Err(ObjectClientError::ServiceError(GetObjectError::NoSuchKey)),
+            Err(e) => return Err(LocalClientError::from(e).into()),
+        };
+        let object = object_info(key, &file.metadata().map_err(LocalClientError::from)?)?;
+
+        // As in S3, a matching If-Match takes precedence over If-Unmodified-Since
+        if let Some(etag_match) = &params.if_match {
+            if etag_match.as_str() != object.etag {
+                return Err(ObjectClientError::ServiceError(GetObjectError::PreconditionFailed));
+            }
+        } else if let Some(if_unmodified_since) = params.if_unmodified_since {
+            if object.last_modified > if_unmodified_since {
+                return Err(ObjectClientError::ServiceError(GetObjectError::PreconditionFailed));
+            }
+        }
+        if let Some(if_modified_since) = params.if_modified_since {
+            if object.last_modified <= if_modified_since {
+                return Err(ObjectClientError::ServiceError(GetObjectError::NotModified));
+            }
+        }
+
+        let range = match params.range.clone() {
+            Some(range) if range.start >= object.size || range.end > object.size || range.start > range.end => {
+                return Err(LocalClientError::InvalidRange(range, object.size).into());
+            }
+            Some(range) => range,
+            None => 0..object.size,
+        };
+        file.seek(SeekFrom::Start(range.start))
+            .map_err(LocalClientError::from)?;
+
+        Ok(LocalGetObjectResult {
+            file,
+            next_offset: range.start,
+            remaining: range.end - range.start,
+            bytes_transferred: 0,
+            metadata: GetObjectMetadata::new(
+                object.size,
+                ETag::from_str(&object.etag).expect("parsing an ETag is infallible"),
+                object.last_modified,
+            ),
+        })
+    }
+
+    async fn list_objects(
+        &self,
+        bucket: &str,
+        continuation_token: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, Self::ClientError> {
+        trace!(bucket, ?continuation_token, delimiter, max_keys, prefix, "ListObjects");
+        if bucket != self.bucket {
+            return Err(ObjectClientError::ServiceError(ListObjectsError::NoSuchBucket));
+        }
+
+        let delimiter = (!delimiter.is_empty()).then_some(delimiter);
+        let all_objects = self.objects_with_prefix(prefix)?;
+
+        let mut objects = Vec::new();
+        let mut common_prefixes: Vec<String> = Vec::new();
+        let mut next_continuation_token = None;
+        // The continuation token is the first key that wasn't returned
+        let start = continuation_token.unwrap_or("");
+        for object in all_objects.into_iter().filter(|object| object.key.as_str() >= start) {
+            let common_prefix = delimiter.and_then(|d| {
+                let (pre, _) = object.key[prefix.len()..].split_once(d)?;
+                Some(format!("{prefix}{pre}{d}"))
+            });
+            // Keys rolled up into the last common prefix don't count towards the limit
+            if let (Some(common_prefix), Some(last)) = (&common_prefix, common_prefixes.last()) {
+                if common_prefix == last {
+                    continue;
+                }
+            }
+            if objects.len() + common_prefixes.len() >= max_keys {
+                next_continuation_token = Some(object.key);
+                break;
+            }
+            match common_prefix {
+                Some(common_prefix) => common_prefixes.push(common_prefix),
+                None => objects.push(object),
+            }
+        }
+
+        Ok(ListObjectsResult {
+            bucket: bucket.to_owned(),
+            objects,
+            common_prefixes,
+            next_continuation_token,
+        })
+    }
+
+    async fn list_object_versions(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        key_marker: Option<&str>,
+        version_id_marker: Option<&str>,
+        max_keys: usize,
+    ) -> ObjectClientResult<ListObjectVersionsResult, ListObjectVersionsError, Self::ClientError> {
+        trace!(
+            bucket,
+            prefix,
+            ?key_marker,
+            ?version_id_marker,
+            max_keys,
+            "ListObjectVersions"
+        );
+        if bucket != self.bucket {
+            return Err(ObjectClientError::ServiceError(ListObjectVersionsError::NoSuchBucket));
+        }
+
+        // Every key has a single version, so the key marker alone says where to continue
+        let mut objects = self
+            .objects_with_prefix(prefix)?
+            .into_iter()
+            .filter(|object| key_marker.map(|marker| object.key.as_str() > marker).unwrap_or(true))
+            .peekable();
+        let mut versions = Vec::new();
+        while versions.len() < max_keys {
+            let Some(object) = objects.next() else {
+                break;
+            };
+            versions.push(ObjectVersion {
+                key: object.key,
+                version_id: NULL_VERSION_ID.to_owned(),
+                is_latest: true,
+                is_delete_marker: false,
+                size: object.size,
+                last_modified: object.last_modified,
+                etag: Some(object.etag),
+            });
+        }
+        let (next_key_marker, next_version_id_marker) = match (objects.peek(), versions.last()) {
+            (Some(_), Some(last)) => (Some(last.key.clone()), Some(NULL_VERSION_ID.to_owned())),
+            _ => (None, None),
+        };
+
+        Ok(ListObjectVersionsResult {
+            bucket: bucket.to_owned(),
+            versions,
+            next_key_marker,
+            next_version_id_marker,
+        })
+    }
+
+    async fn head_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> ObjectClientResult<HeadObjectResult, HeadObjectError, Self::ClientError> {
+        trace!(bucket, key, ?version_id, "HeadObject");
+        if bucket != self.bucket || !Self::check_version_id(version_id) {
+            return Err(ObjectClientError::ServiceError(HeadObjectError::NotFound));
+        }
+
+        match self.object_info(key)? {
+            Some(object) => Ok(HeadObjectResult {
+                bucket: bucket.to_owned(),
+                object: ObjectInfo {
+                    version_id: Some(NULL_VERSION_ID.to_owned()),
+                    ..object
+                },
+                bucket_key_enabled: false,
+                object_lock_mode: None,
+                object_lock_retain_until: None,
+                object_lock_legal_hold: false,
+                content_encoding: None,
+                user_metadata: HashMap::new(),
+            }),
+            None => Err(ObjectClientError::ServiceError(HeadObjectError::NotFound)),
+        }
+    }
+
+    async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectParams,
+        contents: impl Stream<Item = impl AsRef<[u8]> + Send> + Send,
+    ) -> ObjectClientResult<PutObjectResult, PutObjectError, Self::ClientError> {
+        trace!(bucket, key, ?params, "PutObject");
+        if bucket != self.bucket {
+            return Err(ObjectClientError::ServiceError(PutObjectError::NoSuchBucket));
+        }
+
+        let mut buffer = vec![];
+        contents
+            .for_each(|b| {
+                buffer.extend_from_slice(b.as_ref());
+                std::future::ready(())
+            })
+            .await;
+        self.write_object(key, &buffer)?;
+        let etag = self
+            .object_info(key)?
+            .map(|object| ETag::from_str(&object.etag).unwrap());
+
+        Ok(PutObjectResult {
+            version_id: None,
+            etag,
+            size: Some(buffer.len() as u64),
+        })
+    }
+
+    async fn copy_object(
+        &self,
+        source_bucket: &str,
+        source_key: &str,
+        destination_bucket: &str,
+        destination_key: &str,
+    ) -> ObjectClientResult<CopyObjectResult, CopyObjectError, Self::ClientError> {
+        trace!(
+            source_bucket,
+            source_key,
+            destination_bucket,
+            destination_key,
+            "CopyObject"
+        );
+        if source_bucket != self.bucket || destination_bucket != self.bucket {
+            return Err(ObjectClientError::ServiceError(CopyObjectError::NoSuchBucket));
+        }
+
+        let contents = match fs::read(self.object_path(source_key)) {
+            Ok(contents) => contents,
+            Err(e) if is_not_found(&e) => return Err(ObjectClientError::ServiceError(CopyObjectError::NoSuchKey)),
+            Err(e) => return Err(ObjectClientError::ClientError(e.into())),
+        };
+        self.write_object(destination_key, &contents)?;
+        Ok(CopyObjectResult {})
+    }
+
+    async fn put_object_acl(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        acl: ObjectCannedAcl,
+    ) -> ObjectClientResult<PutObjectAclResult, PutObjectAclError, Self::ClientError> {
+        trace!(bucket, key, ?version_id, ?acl, "PutObjectAcl");
+        if bucket != self.bucket {
+            return Err(ObjectClientError::ServiceError(PutObjectAclError::NoSuchBucket));
+        }
+
+        // The local file system has no ACLs, so there's nothing to do beyond checking the object
+        // exists
+        match self.object_info(key)? {
+            Some(_) if Self::check_version_id(version_id) => Ok(PutObjectAclResult {}),
+            _ => Err(ObjectClientError::ServiceError(PutObjectAclError::NoSuchKey)),
+        }
+    }
+
+    async fn list_multipart_uploads(
+        &self,
+        bucket: &str,
+        prefix: &str,
+    ) -> ObjectClientResult<ListMultipartUploadsResult, ListMultipartUploadsError, Self::ClientError> {
+        trace!(bucket, prefix, "ListMultipartUploads");
+        if bucket != self.bucket {
+            return Err(ObjectClientError::ServiceError(ListMultipartUploadsError::NoSuchBucket));
+        }
+
+        // Objects are written to their files in one go, so there are never any uploads in progress
+        Ok(ListMultipartUploadsResult {
+            bucket: bucket.to_owned(),
+            uploads: Vec::new(),
+        })
+    }
+
+    async fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> ObjectClientResult<AbortMultipartUploadResult, AbortMultipartUploadError, Self::ClientError> {
+        trace!(bucket, key, upload_id, "AbortMultipartUpload");
+        if bucket != self.bucket {
+            return Err(ObjectClientError::ServiceError(AbortMultipartUploadError::NoSuchBucket));
+        }
+
+        Err(ObjectClientError::ServiceError(AbortMultipartUploadError::NoSuchUpload))
+    }
+
+    async fn list_buckets(&self) -> ObjectClientResult<ListBucketsResult, ListBucketsError, Self::ClientError> {
+        trace!("ListBuckets");
+        let metadata = fs::metadata(&self.root).map_err(LocalClientError::from)?;
+        let creation_date = metadata
+            .created()
+            .or_else(|_| metadata.modified())
+            .map_err(LocalClientError::from)?;
+        let creation_date = OffsetDateTime::from(creation_date);
+        Ok(ListBucketsResult {
+            buckets: vec![BucketInfo {
+                name: self.bucket.clone(),
+                creation_date,
+            }],
+            owner: None,
+        })
+    }
+
+    async fn get_object_attributes(
+        &self,
+        bucket: &str,
+        key: &str,
+        _max_parts: Option<usize>,
+        _part_number_marker: Option<usize>,
+        object_attributes: &[ObjectAttribute],
+    ) -> ObjectClientResult<GetObjectAttributesResult, GetObjectAttributesError, Self::ClientError> {
+        trace!(bucket, key, "GetObjectAttributes");
+        if bucket != self.bucket {
+            return Err(ObjectClientError::ServiceError(GetObjectAttributesError::NoSuchBucket));
+        }
+
+        let Some(object) = self.object_info(key)? else {
+            return Err(ObjectClientError::ServiceError(GetObjectAttributesError::NoSuchKey));
+        };
+        let mut result = GetObjectAttributesResult::default();
+        for attribute in object_attributes {
+            match attribute {
+                ObjectAttribute::ETag => result.etag = Some(object.etag.clone()),
+                ObjectAttribute::StorageClass => result.storage_class = Some("STANDARD".to_owned()),
+                ObjectAttribute::ObjectSize => result.object_size = Some(object.size),
+                // Objects are never uploaded in parts and have no checksums
+                ObjectAttribute::Checksum | ObjectAttribute::ObjectParts => {}
+            }
+        }
+        Ok(result)
+    }
+
+    async fn presign_post_object(
+        &self,
+        bucket: &str,
+        key_prefix: &str,
+        conditions: &[PostPolicyCondition],
+        expires_in: Duration,
+    ) -> ObjectClientResult<PresignedPost, PresignPostError, Self::ClientError> {
+        trace!(bucket, key_prefix, ?conditions, ?expires_in, "PresignPostObject");
+        // Nothing could accept the upload anyway
+        Err(ObjectClientError::ServiceError(PresignPostError::NoCredentials))
+    }
+}
+
+/// The body of a GetObject request to a [LocalObjectClient], read from the file as it's polled
+#[derive(Debug)]
+pub struct LocalGetObjectResult {
+    file: File,
+    next_offset: u64,
+    remaining: u64,
+    bytes_transferred: u64,
+    metadata: GetObjectMetadata,
+}
+
+impl GetObjectProgress for LocalGetObjectResult {
+    fn bytes_transferred(&self) -> u64 {
+        self.bytes_transferred
+    }
+
+    fn object_metadata(&self) -> Option<Result<GetObjectMetadata, ObjectMetadataError>> {
+        Some(Ok(self.metadata.clone()))
+    }
+}
+
+impl Stream for LocalGetObjectResult {
+    type Item = ObjectClientResult<GetBodyPart, GetObjectError, LocalClientError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.remaining == 0 {
+            return Poll::Ready(None);
+        }
+
+        let part_size = (PART_SIZE as u64).min(self.remaining) as usize;
+        let mut part = vec![0u8; part_size];
+        if let Err(e) = self.file.read_exact(&mut part) {
+            self.remaining = 0;
+            return Poll::Ready(Some(Err(LocalClientError::from(e).into())));
+        }
+
+        let offset = self.next_offset;
+        self.next_offset += part_size as u64;
+        self.remaining -= part_size as u64;
+        self.bytes_transferred += part_size as u64;
+        Poll::Ready(Some(Ok((offset, part.into_boxed_slice()))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use test_case::test_case;
+
+    fn collect(mut body: LocalGetObjectResult) -> Vec<u8> {
+        block_on(async {
+            let mut collected = Vec::new();
+            while let Some(part) = body.next().await {
+                let (offset, part) = part.unwrap();
+                assert_eq!(offset as usize, collected.len());
+                collected.extend_from_slice(&part);
+            }
+            collected
+        })
+    }
+
+    #[test_case("a"; "simple")]
+    #[test_case("a.b/%c"; "escaped characters")]
+    #[test_case("a//b/"; "empty components")]
+    #[test_case("./../\0"; "dots and nul")]
+    fn encode_round_trip(key: &str) {
+        let root = tempfile::tempdir().unwrap();
+        let client = LocalObjectClient::new("test_bucket", root.path()).unwrap();
+        block_on(client.put_object(
+            "test_bucket",
+            key,
+            &Default::default(),
+            futures::stream::iter([b"hello"]),
+        ))
+        .unwrap();
+
+        let list = block_on(client.list_objects("test_bucket", None, "", 10, "")).unwrap();
+        let keys: Vec<_> = list.objects.iter().map(|object| object.key.as_str()).collect();
+        assert_eq!(keys, [key]);
+        assert!(client.object_path(key).starts_with(root.path()));
+    }
+
+    #[test]
+    fn file_and_prefix_with_same_name() {
+        let root = tempfile::tempdir().unwrap();
+        let client = LocalObjectClient::new("test_bucket", root.path()).unwrap();
+        for key in ["a", "a/b", "a/c/d", "ab"] {
+            block_on(client.put_object("test_bucket", key, &Default::default(), futures::stream::iter([key]))).unwrap();
+        }
+
+        let list = block_on(client.list_objects("test_bucket", None, "/", 10, "")).unwrap();
+        let keys: Vec<_> = list.objects.iter().map(|object| object.key.as_str()).collect();
+        assert_eq!(keys, ["a", "ab"]);
+        assert_eq!(list.common_prefixes, ["a/"]);
+
+        let list = block_on(client.list_objects("test_bucket", None, "/", 10, "a/")).unwrap();
+        let keys: Vec<_> = list.objects.iter().map(|object| object.key.as_str()).collect();
+        assert_eq!(keys, ["a/b"]);
+        assert_eq!(list.common_prefixes, ["a/c/"]);
+
+        let body = block_on(client.get_object("test_bucket", "a/c/d", &Default::default())).unwrap();
+        assert_eq!(collect(body), b"a/c/d");
+
+        block_on(client.delete_object("test_bucket", "a/c/d", None)).unwrap();
+        assert!(!root.path().join("a").join("c").exists());
+        let head = block_on(client.head_object("test_bucket", "a/c/d", None));
+        assert!(matches!(
+            head,
+            Err(ObjectClientError::ServiceError(HeadObjectError::NotFound))
+        ));
+    }
+
+    #[test]
+    fn list_objects_paginates() {
+        let root = tempfile::tempdir().unwrap();
+        let client = LocalObjectClient::new("test_bucket", root.path()).unwrap();
+        for key in ["a", "b/1", "b/2", "c", "d"] {
+            block_on(client.put_object("test_bucket", key, &Default::default(), futures::stream::iter([key]))).unwrap();
+        }
+
+        let mut continuation_token = None;
+        let mut pages = Vec::new();
+        loop {
+            let list = block_on(client.list_objects("test_bucket", continuation_token.as_deref(), "/", 2, "")).unwrap();
+            let mut page: Vec<_> = list.objects.into_iter().map(|object| object.key).collect();
+            page.extend(list.common_prefixes);
+            page.sort();
+            pages.push(page);
+            continuation_token = list.next_continuation_token;
+            if continuation_token.is_none() {
+                break;
+            }
+        }
+        assert_eq!(pages, [vec!["a", "b/"], vec!["c", "d"]]);
+    }
+
+    #[test]
+    fn get_object_range_and_conditions() {
+        let root = tempfile::tempdir().unwrap();
+        let client = LocalObjectClient::new("test_bucket", root.path()).unwrap();
+        block_on(client.put_object(
+            "test_bucket",
+            "key",
+            &Default::default(),
+            futures::stream::iter([b"0123456789"]),
+        ))
+        .unwrap();
+
+        let params = GetObjectParams::new().range(Some(2..5));
+        let body = block_on(client.get_object("test_bucket", "key", &params)).unwrap();
+        assert_eq!(collect(body), b"234");
+
+        let head = block_on(client.head_object("test_bucket", "key", None)).unwrap();
+        let params = GetObjectParams::new().if_match(Some(ETag::from_str(&head.object.etag).unwrap()));
+        assert!(block_on(client.get_object("test_bucket", "key", &params)).is_ok());
+
+        let params = GetObjectParams::new().if_match(Some(ETag::for_tests()));
+        let result = block_on(client.get_object("test_bucket", "key", &params));
+        assert!(matches!(
+            result,
+            Err(ObjectClientError::ServiceError(GetObjectError::PreconditionFailed))
+        ));
+    }
+}
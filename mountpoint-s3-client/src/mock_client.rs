@@ -1,23 +1,37 @@
 use std::borrow::Cow;
-use std::collections::{BTreeMap, BTreeSet};
-use std::ops::Range;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::future::Future;
 use std::pin::Pin;
-use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 use async_trait::async_trait;
 use futures::{Stream, StreamExt};
 use lazy_static::lazy_static;
+use mountpoint_s3_crt::auth::credentials::Credentials;
 use thiserror::Error;
 use time::OffsetDateTime;
 use tracing::trace;
 
+use crate::express::ExpressMode;
+use crate::object_client::{content_md5, upload_part_size};
 use crate::object_client::{
-    DeleteObjectError, DeleteObjectResult, GetBodyPart, GetObjectAttributesError, GetObjectAttributesResult,
-    GetObjectError, HeadObjectError, HeadObjectResult, ListObjectsError, ListObjectsResult, ObjectClient,
-    ObjectClientError, ObjectClientResult, ObjectInfo, PutObjectError, PutObjectParams, PutObjectResult,
+    AbortMultipartUploadError, AbortMultipartUploadResult, BucketInfo, BucketOwner, CompleteMultipartUploadError,
+    CopyObjectError, CopyObjectResult, DeleteObjectError, DeleteObjectResult, DeleteObjectsError, DeleteObjectsResult,
+    GetBodyPart, GetObjectAttributesError, GetObjectAttributesResult, GetObjectError, GetObjectMetadata,
+    GetObjectParams, GetObjectProgress, HeadObjectError, HeadObjectResult, ListBucketsError, ListBucketsResult,
+    ListMultipartUploadsError, ListMultipartUploadsResult, ListObjectVersionsError, ListObjectVersionsResult,
+    ListObjectsError, ListObjectsResult, MultipartUpload, ObjectCannedAcl, ObjectClient, ObjectClientError,
+    ObjectClientResult, ObjectExpiration, ObjectInfo, ObjectLockMode, ObjectMetadataError, ObjectVersion,
+    PresignPostError, PutObjectAclError, PutObjectAclResult, PutObjectError, PutObjectParams, PutObjectResult,
+    StorageClass, UploadMode, MAX_DELETE_OBJECTS_KEYS, MAX_UPLOAD_PARTS,
 };
-use crate::{Checksum, ETag, ObjectAttribute};
+use crate::presign::presign_post;
+use crate::retrying_client::{RetryStrategyConfig, RetryableError};
+use crate::timer::sleep;
+use crate::{Checksum, ETag, ObjectAttribute, PostPolicyCondition, PresignedPost};
 
 pub const RAMP_MODULUS: usize = 251; // Largest prime under 256
 static_assertions::const_assert!((RAMP_MODULUS > 0) && (RAMP_MODULUS <= 256));
@@ -40,20 +54,135 @@ lazy_static! {
     static ref RAMP_BYTES: Vec<u8> = ramp_bytes(0, RAMP_BUFFER_SIZE + RAMP_MODULUS);
 }
 
+/// The S3 operations a [MockClient] counts, see [MockClient::op_count]. Uploads are counted as the
+/// requests the CRT would make for them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    DeleteObject,
+    DeleteObjects,
+    GetObject,
+    GetObjectAttributes,
+    HeadObject,
+    ListObjects,
+    ListObjectVersions,
+    ListMultipartUploads,
+    AbortMultipartUpload,
+    ListBuckets,
+    PutObject,
+    PutObjectAcl,
+    CopyObject,
+    CreateMultipartUpload,
+    UploadPart,
+    CompleteMultipartUpload,
+}
+
 #[derive(Debug, Default)]
 pub struct MockClientConfig {
     /// The bucket name this client will connect to
     pub bucket: String,
-    /// The size of the parts that GetObject will respond with
+    /// The size of the parts that GetObject will respond with, and that multipart uploads are split
+    /// into
     pub part_size: usize,
 }
 
 /// A mock implementation of an object client that we can manually add objects to, and then query
 /// via the [ObjectClient] APIs.
+///
+/// The mock bucket behaves as if versioning were enabled: every object added to it is assigned a
+/// new version id, and previous versions of each key remain readable by version id.
 #[derive(Debug)]
 pub struct MockClient {
     config: MockClientConfig,
     objects: RwLock<BTreeMap<String, Arc<MockObject>>>,
+    /// All versions of each key, oldest first. The current object for a key (if any) is always the
+    /// last entry of its history, unless that entry is a delete marker.
+    versions: RwLock<BTreeMap<String, Vec<MockVersion>>>,
+    next_version_id: AtomicU64,
+    express_mode: ExpressMode,
+    /// Number of S3 Express One Zone sessions created. Mock sessions never expire, so this is at
+    /// most 1 unless [MockClient::expire_session] is called.
+    sessions_created: AtomicU64,
+    has_session: AtomicBool,
+    op_counts: Mutex<HashMap<Operation, u64>>,
+    latencies: Mutex<HashMap<Operation, Duration>>,
+    in_flight: AtomicU64,
+    max_in_flight: AtomicU64,
+    /// Errors to fail the next multipart uploads with when they're completed
+    complete_multipart_upload_errors: Mutex<VecDeque<CompleteMultipartUploadError>>,
+    /// Region to redirect ListObjectsV2 requests to, if any
+    redirect_region: Mutex<Option<String>>,
+    /// Part size of the last multipart upload of each key
+    multipart_part_sizes: Mutex<HashMap<String, usize>>,
+    /// Whether ListObjectsV2 pages can end inside a common prefix
+    split_common_prefixes: AtomicBool,
+    /// Whether ranged GetObject requests that end past the end of the object are served, like S3
+    clamp_get_ranges: AtomicBool,
+    /// Number of upcoming GetObject requests that fail with NoSuchKey whether the object exists or not
+    missing_gets: AtomicU64,
+    /// Whether HeadObject requests are denied
+    deny_head_object: AtomicBool,
+    /// Whether GetObject responses are missing the object's metadata
+    omit_get_metadata: AtomicBool,
+    /// Whether the body of the next PutObject should be corrupted in transit
+    corrupt_next_put_body: AtomicBool,
+    /// Multipart uploads that have been created but not completed or aborted, oldest first
+    multipart_uploads: Mutex<Vec<MultipartUpload>>,
+    next_upload_id: AtomicU64,
+    /// Number of parts returned by GetObject streams that should be transferred twice
+    get_parts_to_retry: Arc<AtomicU64>,
+    /// Buckets returned by ListBuckets other than this client's own bucket, which are always empty
+    other_buckets: Mutex<Vec<BucketInfo>>,
+    /// Owner returned by ListBuckets
+    bucket_owner: Mutex<Option<BucketOwner>>,
+    creation_date: OffsetDateTime,
+}
+
+/// A request counted as in flight by a [MockClient] until dropped
+#[derive(Debug)]
+struct InFlightRequest<'a> {
+    client: &'a MockClient,
+}
+
+impl Drop for InFlightRequest<'_> {
+    fn drop(&mut self) {
+        self.client.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A future that returns [Poll::Pending] once before completing
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+/// A single entry in the version history of a key in a [MockClient]
+#[derive(Debug, Clone)]
+enum MockVersion {
+    Object(Arc<MockObject>),
+    DeleteMarker {
+        version_id: String,
+        last_modified: OffsetDateTime,
+    },
+}
+
+impl MockVersion {
+    fn version_id(&self) -> &str {
+        match self {
+            MockVersion::Object(object) => object.version_id.as_deref().expect("stored objects have a version id"),
+            MockVersion::DeleteMarker { version_id, .. } => version_id,
+        }
+    }
 }
 
 impl MockClient {
@@ -62,17 +191,320 @@ impl MockClient {
         Self {
             config,
             objects: Default::default(),
+            versions: Default::default(),
+            next_version_id: AtomicU64::new(1),
+            express_mode: Default::default(),
+            sessions_created: AtomicU64::new(0),
+            has_session: AtomicBool::new(false),
+            op_counts: Default::default(),
+            latencies: Default::default(),
+            in_flight: AtomicU64::new(0),
+            max_in_flight: AtomicU64::new(0),
+            complete_multipart_upload_errors: Default::default(),
+            redirect_region: Default::default(),
+            multipart_part_sizes: Default::default(),
+            split_common_prefixes: AtomicBool::new(false),
+            clamp_get_ranges: AtomicBool::new(false),
+            missing_gets: AtomicU64::new(0),
+            deny_head_object: AtomicBool::new(false),
+            omit_get_metadata: AtomicBool::new(false),
+            corrupt_next_put_body: AtomicBool::new(false),
+            multipart_uploads: Default::default(),
+            next_upload_id: AtomicU64::new(1),
+            get_parts_to_retry: Default::default(),
+            other_buckets: Default::default(),
+            bucket_owner: Default::default(),
+            creation_date: OffsetDateTime::now_utc(),
+        }
+    }
+
+    /// Create a new [MockClient] whose bucket holds the objects listed in a manifest, one per line
+    /// as `key size [etag] [storage_class]`, separated by whitespace. Sizes are in bytes, or can
+    /// have a `KiB`, `MiB`, or `GiB` suffix. Objects without an etag get one derived from their
+    /// key, and objects without a storage class are `STANDARD`. Contents are generated with
+    /// [MockObject::ramp], seeded from the key, so even huge objects don't take up memory. Blank
+    /// lines and lines starting with `#` are ignored. Keys can't contain whitespace.
+    pub fn from_manifest(config: MockClientConfig, manifest: &str) -> Result<Self, MockManifestError> {
+        let client = Self::new(config);
+        for (index, line) in manifest.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |reason: String| MockManifestError {
+                line: index + 1,
+                reason,
+            };
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (key, size, etag, storage_class) = match fields[..] {
+                [key, size] => (key, size, None, None),
+                [key, size, etag] => (key, size, Some(etag), None),
+                [key, size, etag, storage_class] => (key, size, Some(etag), Some(storage_class)),
+                _ => return Err(error(format!("expected 2 to 4 fields, got {}", fields.len()))),
+            };
+            let size = parse_manifest_size(size).ok_or_else(|| error(format!("invalid size {size:?}")))?;
+            let etag = match etag {
+                Some(etag) => etag.parse().unwrap(),
+                None => ETag::from_object_bytes(key.as_bytes()),
+            };
+
+            let seed = key.bytes().fold(0u8, |seed, byte| seed.wrapping_add(byte));
+            let mut object = MockObject::ramp(seed, size, etag);
+            if let Some(storage_class) = storage_class {
+                object.set_storage_class(storage_class);
+            }
+            client.add_object(key, object);
+        }
+        Ok(client)
+    }
+
+    /// Number of times this client has made the given request
+    pub fn op_count(&self, operation: Operation) -> u64 {
+        self.op_counts.lock().unwrap().get(&operation).copied().unwrap_or(0)
+    }
+
+    /// Count requests of the given type, and wait out any latency injected for them with
+    /// [MockClient::set_latency]
+    async fn count_op(&self, operation: Operation, count: u64) {
+        *self.op_counts.lock().unwrap().entry(operation).or_default() += count;
+        let latency = self.latencies.lock().unwrap().get(&operation).copied();
+        if let Some(latency) = latency {
+            sleep(latency * count as u32).await;
+        }
+    }
+
+    /// Make every request of the given type take at least this long
+    pub fn set_latency(&self, operation: Operation, latency: Duration) {
+        self.latencies.lock().unwrap().insert(operation, latency);
+    }
+
+    /// The most requests this client has had in flight at the same time
+    pub fn max_in_flight(&self) -> u64 {
+        self.max_in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Track a request as in flight until the returned guard is dropped. Yields once before
+    /// returning, so that other concurrent requests get a chance to start while this one is still
+    /// outstanding.
+    async fn start_request(&self) -> InFlightRequest<'_> {
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+        let guard = InFlightRequest { client: self };
+        YieldNow(false).await;
+        guard
+    }
+
+    /// Fail the next completion of a multipart upload with this error, like S3 does when it returns
+    /// 200 OK for CompleteMultipartUpload with an error in the body. Like the CRT client, the mock
+    /// completes the upload again if the error is retryable, so each error only fails one attempt.
+    /// The object isn't created if the upload doesn't complete.
+    pub fn fail_next_complete_multipart_upload(&self, error: CompleteMultipartUploadError) {
+        self.complete_multipart_upload_errors.lock().unwrap().push_back(error);
+    }
+
+    /// Fail ListObjectsV2 requests with a redirect to the given region, like S3 does when a client
+    /// is configured for a different region to the bucket's
+    pub fn redirect_to_region(&self, region: &str) {
+        *self.redirect_region.lock().unwrap() = Some(region.to_owned());
+    }
+
+    /// The part size the last multipart upload of the given key was split into, if it was ever
+    /// uploaded in parts
+    pub fn multipart_part_size(&self, key: &str) -> Option<usize> {
+        self.multipart_part_sizes.lock().unwrap().get(key).copied()
+    }
+
+    /// Let ListObjectsV2 pages end inside a common prefix, so that the next page starts by returning
+    /// the same common prefix again. Callers that merge pages have to cope with the duplicate.
+    pub fn split_common_prefixes_across_pages(&self, split: bool) {
+        self.split_common_prefixes.store(split, Ordering::SeqCst);
+    }
+
+    /// Like S3, serve ranged GetObject requests that end past the end of the object with the bytes up
+    /// to its end, rather than failing them. Ranges that start past the end still fail. Useful for
+    /// testing what readers see when an object shrinks after they've looked up its size.
+    pub fn clamp_get_ranges(&self, clamp: bool) {
+        self.clamp_get_ranges.store(clamp, Ordering::SeqCst);
+    }
+
+    /// Fail the next `count` GetObject requests with NoSuchKey even if the object exists, like an
+    /// S3-compatible store that isn't read-after-write consistent can shortly after a PutObject
+    pub fn simulate_eventual_consistency(&self, count: u64) {
+        self.missing_gets.store(count, Ordering::SeqCst);
+    }
+
+    /// Fail HeadObject requests with AccessDenied, like S3 does for credentials whose policy
+    /// doesn't allow them, while still allowing GetObject requests
+    pub fn deny_head_object(&self, deny: bool) {
+        self.deny_head_object.store(deny, Ordering::SeqCst);
+    }
+
+    /// Leave the object's metadata out of GetObject responses, like an S3-compatible store that
+    /// doesn't send the headers it comes from. The body is still served.
+    pub fn omit_get_metadata(&self, omit: bool) {
+        self.omit_get_metadata.store(omit, Ordering::SeqCst);
+    }
+
+    /// Corrupt the body of the next PutObject in transit, after the client has computed any
+    /// `Content-MD5` of it. Uploads that send one fail with [PutObjectError::BadDigest], while those
+    /// that don't store the corrupted body.
+    pub fn corrupt_next_put_body(&self) {
+        self.corrupt_next_put_body.store(true, Ordering::SeqCst);
+    }
+
+    /// Add another bucket to be returned by ListBuckets. The bucket is empty: listing it returns no
+    /// objects, and all other requests to it fail as if it didn't exist.
+    pub fn add_bucket(&self, name: &str, creation_date: OffsetDateTime) {
+        self.other_buckets.lock().unwrap().push(BucketInfo {
+            name: name.to_owned(),
+            creation_date,
+        });
+    }
+
+    /// Set the owner returned by ListBuckets, which is `None` by default
+    pub fn set_bucket_owner(&self, owner: Option<BucketOwner>) {
+        *self.bucket_owner.lock().unwrap() = owner;
+    }
+
+    /// Create a multipart upload for the given key that's never completed, like one left behind by
+    /// a client that crashed, returning its upload id
+    pub fn create_multipart_upload(&self, key: &str, initiated: OffsetDateTime) -> String {
+        let upload_id = format!("upload{}", self.next_upload_id.fetch_add(1, Ordering::SeqCst));
+        self.multipart_uploads.lock().unwrap().push(MultipartUpload {
+            key: key.to_owned(),
+            upload_id: upload_id.clone(),
+            initiated,
+        });
+        upload_id
+    }
+
+    /// Whether a multipart upload with the given id is still in progress
+    pub fn has_multipart_upload(&self, upload_id: &str) -> bool {
+        self.multipart_uploads
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|upload| upload.upload_id == upload_id)
+    }
+
+    /// Transfer the next `count` parts returned by GetObject streams twice, like a real client does
+    /// when a part fails partway through and is fetched again. Each part is still only returned
+    /// once, but counts twice towards [GetObjectProgress::bytes_transferred].
+    pub fn retry_next_get_parts(&self, count: u64) {
+        self.get_parts_to_retry.fetch_add(count, Ordering::SeqCst);
+    }
+
+    /// Set whether this client should use S3 Express One Zone sessions for its bucket
+    pub fn set_express_mode(&mut self, express_mode: ExpressMode) {
+        self.express_mode = express_mode;
+    }
+
+    /// Number of S3 Express One Zone sessions this client has created
+    pub fn sessions_created(&self) -> u64 {
+        self.sessions_created.load(Ordering::SeqCst)
+    }
+
+    /// Expire the current S3 Express One Zone session, if any, so the next request creates a new one
+    pub fn expire_session(&self) {
+        self.has_session.store(false, Ordering::SeqCst);
+    }
+
+    /// Simulate CreateSession for requests that use S3 Express One Zone session authentication
+    fn refresh_express_session(&self, bucket: &str) {
+        if self.express_mode.uses_sessions(bucket) && !self.has_session.swap(true, Ordering::SeqCst) {
+            trace!(bucket, "CreateSession");
+            self.sessions_created.fetch_add(1, Ordering::SeqCst);
         }
     }
 
     /// Add an object to this mock client's bucket
     pub fn add_object(&self, key: &str, value: MockObject) {
-        self.objects.write().unwrap().insert(key.to_owned(), Arc::new(value));
+        self.insert_object(key, value);
+    }
+
+    /// Add a new version of an object to this mock client's bucket, returning its version id
+    fn insert_object(&self, key: &str, mut value: MockObject) -> String {
+        let version_id = self.next_version_id();
+        value.version_id = Some(version_id.clone());
+        let value = Arc::new(value);
+
+        let mut objects = self.objects.write().unwrap();
+        let mut versions = self.versions.write().unwrap();
+        objects.insert(key.to_owned(), Arc::clone(&value));
+        versions
+            .entry(key.to_owned())
+            .or_default()
+            .push(MockVersion::Object(value));
+        version_id
     }
 
     /// Remove object for the mock client's bucket
     pub fn remove_object(&self, key: &str) {
-        self.objects.write().unwrap().remove(key);
+        self.remove_current_object(key);
+    }
+
+    /// Remove the current version of an object, leaving a delete marker in its history. Returns the
+    /// version id of the delete marker, or `None` if the object did not exist.
+    fn remove_current_object(&self, key: &str) -> Option<String> {
+        let mut objects = self.objects.write().unwrap();
+        let mut versions = self.versions.write().unwrap();
+        objects.remove(key)?;
+
+        let version_id = self.next_version_id();
+        versions
+            .entry(key.to_owned())
+            .or_default()
+            .push(MockVersion::DeleteMarker {
+                version_id: version_id.clone(),
+                last_modified: OffsetDateTime::now_utc(),
+            });
+        Some(version_id)
+    }
+
+    /// Remove a single version (or delete marker) of an object from the mock client's bucket. If it
+    /// was the latest version, the most recent remaining version becomes current.
+    fn remove_object_version(&self, key: &str, version_id: &str) {
+        let mut objects = self.objects.write().unwrap();
+        let mut versions = self.versions.write().unwrap();
+        let Some(history) = versions.get_mut(key) else {
+            return;
+        };
+        history.retain(|version| version.version_id() != version_id);
+
+        match history.last() {
+            Some(MockVersion::Object(latest)) => objects.insert(key.to_owned(), Arc::clone(latest)),
+            _ => objects.remove(key),
+        };
+        if history.is_empty() {
+            versions.remove(key);
+        }
+    }
+
+    /// Get the given version of an object, or the current version if `version_id` is `None`
+    fn get_object_version(&self, key: &str, version_id: Option<&str>) -> Option<Arc<MockObject>> {
+        match version_id {
+            None => self.objects.read().unwrap().get(key).cloned(),
+            Some(version_id) => self
+                .versions
+                .read()
+                .unwrap()
+                .get(key)?
+                .iter()
+                .find_map(|version| match version {
+                    MockVersion::Object(object) if version.version_id() == version_id => Some(Arc::clone(object)),
+                    _ => None,
+                }),
+        }
+    }
+
+    fn next_version_id(&self) -> String {
+        self.next_version_id.fetch_add(1, Ordering::SeqCst).to_string()
+    }
+
+    /// The canned ACL of the current version of an object, if it exists
+    pub fn object_acl(&self, key: &str) -> Option<ObjectCannedAcl> {
+        self.objects.read().unwrap().get(key).map(|object| object.acl())
     }
 
     /// Returns `true` if this mock client's bucket contains the specified key
@@ -90,10 +522,19 @@ impl MockClient {
 pub struct MockObject {
     generator: Box<dyn Fn(u64, usize) -> Box<[u8]> + Send + Sync>,
     size: usize,
-    // TODO Set storage class from [MockClient::put_object]
     storage_class: String,
     last_modified: OffsetDateTime,
     etag: ETag,
+    expiration: Option<ObjectExpiration>,
+    version_id: Option<String>,
+    bucket_key_enabled: bool,
+    sse_customer_key_md5: Option<String>,
+    acl: Mutex<ObjectCannedAcl>,
+    object_lock_mode: Option<ObjectLockMode>,
+    object_lock_retain_until: Option<OffsetDateTime>,
+    object_lock_legal_hold: bool,
+    content_encoding: Option<String>,
+    user_metadata: HashMap<String, String>,
 }
 
 impl MockObject {
@@ -110,9 +551,21 @@ impl MockObject {
             storage_class: "STANDARD".to_owned(),
             last_modified: OffsetDateTime::now_utc(),
             etag,
+            expiration: None,
+            version_id: None,
+            bucket_key_enabled: false,
+            sse_customer_key_md5: None,
+            acl: Mutex::new(ObjectCannedAcl::default()),
+            object_lock_mode: None,
+            object_lock_retain_until: None,
+            object_lock_legal_hold: false,
+            content_encoding: None,
+            user_metadata: HashMap::new(),
         }
     }
 
+    /// An object of `size` bytes that are all `v`. The contents are generated on each read, so
+    /// large objects don't take up memory.
     pub fn constant(v: u8, size: usize, etag: ETag) -> Self {
         Self {
             generator: Box::new(move |_offset, size| vec![v; size].into_boxed_slice()),
@@ -120,9 +573,22 @@ impl MockObject {
             storage_class: "STANDARD".to_owned(),
             last_modified: OffsetDateTime::now_utc(),
             etag,
+            expiration: None,
+            version_id: None,
+            bucket_key_enabled: false,
+            sse_customer_key_md5: None,
+            acl: Mutex::new(ObjectCannedAcl::default()),
+            object_lock_mode: None,
+            object_lock_retain_until: None,
+            object_lock_legal_hold: false,
+            content_encoding: None,
+            user_metadata: HashMap::new(),
         }
     }
 
+    /// An object of `size` bytes where the byte at offset `k` is `(seed + k) % RAMP_MODULUS`. Like
+    /// [MockObject::constant], the contents are generated on each read, but reads from the wrong
+    /// offset return different bytes.
     pub fn ramp(seed: u8, size: usize, etag: ETag) -> Self {
         Self {
             generator: Box::new(move |offset, mut size| {
@@ -140,6 +606,16 @@ impl MockObject {
             storage_class: "STANDARD".to_owned(),
             last_modified: OffsetDateTime::now_utc(),
             etag,
+            expiration: None,
+            version_id: None,
+            bucket_key_enabled: false,
+            sse_customer_key_md5: None,
+            acl: Mutex::new(ObjectCannedAcl::default()),
+            object_lock_mode: None,
+            object_lock_retain_until: None,
+            object_lock_legal_hold: false,
+            content_encoding: None,
+            user_metadata: HashMap::new(),
         }
     }
 
@@ -147,6 +623,69 @@ impl MockObject {
         self.last_modified = last_modified;
     }
 
+    /// Set a synthetic lifecycle expiration for this object, to be reported by HeadObject
+    pub fn set_expiration(&mut self, expiration: Option<ObjectExpiration>) {
+        self.expiration = expiration;
+    }
+
+    /// Set the storage class reported for this object by GetObjectAttributes
+    pub fn set_storage_class(&mut self, storage_class: &str) {
+        self.storage_class = storage_class.to_owned();
+    }
+
+    /// Set whether this object is reported by HeadObject as encrypted with an S3 Bucket Key
+    pub fn set_bucket_key_enabled(&mut self, bucket_key_enabled: bool) {
+        self.bucket_key_enabled = bucket_key_enabled;
+    }
+
+    /// Set the MD5 digest of the customer-provided key this object is encrypted with. GetObject
+    /// fails with [GetObjectError::AccessDenied] unless it's given the same key.
+    pub fn set_sse_customer_key_md5(&mut self, sse_customer_key_md5: Option<String>) {
+        self.sse_customer_key_md5 = sse_customer_key_md5;
+    }
+
+    /// Set the canned ACL of this object. Objects are shared once added to a [MockClient], so
+    /// this doesn't need mutable access.
+    pub fn set_acl(&self, acl: ObjectCannedAcl) {
+        *self.acl.lock().unwrap() = acl;
+    }
+
+    /// The canned ACL of this object, set when it was uploaded or by PutObjectAcl
+    pub fn acl(&self) -> ObjectCannedAcl {
+        *self.acl.lock().unwrap()
+    }
+
+    /// Set the Object Lock retention of this object. While it's retained, this version of the
+    /// object can't be deleted.
+    pub fn set_object_lock_retention(&mut self, mode: Option<ObjectLockMode>, retain_until: Option<OffsetDateTime>) {
+        self.object_lock_mode = mode;
+        self.object_lock_retain_until = retain_until;
+    }
+
+    /// Set the `Content-Encoding` that HeadObject reports for this object
+    pub fn set_content_encoding(&mut self, content_encoding: Option<&str>) {
+        self.content_encoding = content_encoding.map(str::to_owned);
+    }
+
+    /// Set the user-defined metadata that HeadObject reports for this object, keyed by name
+    /// without the `x-amz-meta-` prefix
+    pub fn set_user_metadata(&mut self, user_metadata: HashMap<String, String>) {
+        self.user_metadata = user_metadata;
+    }
+
+    /// Set whether this object has an Object Lock legal hold, which prevents this version of the
+    /// object from being deleted regardless of its retention
+    pub fn set_object_lock_legal_hold(&mut self, legal_hold: bool) {
+        self.object_lock_legal_hold = legal_hold;
+    }
+
+    /// Whether this version of the object is currently protected from deletion by Object Lock
+    pub fn is_locked(&self) -> bool {
+        let retained = self.object_lock_mode.is_some()
+            && matches!(self.object_lock_retain_until, Some(retain_until) if retain_until > OffsetDateTime::now_utc());
+        retained || self.object_lock_legal_hold
+    }
+
     pub fn len(&self) -> usize {
         self.size
     }
@@ -158,6 +697,32 @@ impl MockObject {
     pub fn etag(&self) -> ETag {
         self.etag.clone()
     }
+
+    /// The version id assigned to this object when it was added to a [MockClient], if any
+    pub fn version_id(&self) -> Option<&str> {
+        self.version_id.as_deref()
+    }
+
+    /// A copy of this object, as CopyObject would create, that reads its contents from the source
+    fn copy_of(source: Arc<MockObject>) -> Self {
+        Self {
+            size: source.size,
+            storage_class: source.storage_class.clone(),
+            last_modified: OffsetDateTime::now_utc(),
+            etag: source.etag.clone(),
+            expiration: None,
+            version_id: None,
+            bucket_key_enabled: source.bucket_key_enabled,
+            sse_customer_key_md5: source.sse_customer_key_md5.clone(),
+            acl: Mutex::new(ObjectCannedAcl::default()),
+            object_lock_mode: None,
+            object_lock_retain_until: None,
+            object_lock_legal_hold: false,
+            content_encoding: source.content_encoding.clone(),
+            user_metadata: source.user_metadata.clone(),
+            generator: Box::new(move |offset, size| source.read(offset, size)),
+        }
+    }
 }
 
 impl<T: AsRef<[u8]>> From<T> for MockObject {
@@ -178,6 +743,28 @@ pub struct GetObjectResult {
     next_offset: u64,
     length: usize,
     part_size: usize,
+    bytes_transferred: u64,
+    /// Shared with the client, see [MockClient::retry_next_get_parts]
+    parts_to_retry: Arc<AtomicU64>,
+    /// See [MockClient::omit_get_metadata]
+    omit_metadata: bool,
+}
+
+impl GetObjectProgress for GetObjectResult {
+    fn bytes_transferred(&self) -> u64 {
+        self.bytes_transferred
+    }
+
+    fn object_metadata(&self) -> Option<Result<GetObjectMetadata, ObjectMetadataError>> {
+        if self.omit_metadata {
+            return Some(Err(ObjectMetadataError::Missing));
+        }
+        Some(Ok(GetObjectMetadata::new(
+            self.object.len() as u64,
+            self.object.etag(),
+            self.object.last_modified,
+        )))
+    }
 }
 
 impl GetObjectResult {
@@ -206,6 +793,14 @@ impl Stream for GetObjectResult {
         let next_part_size = self.part_size.min(self.length);
         let next_part = self.object.read(self.next_offset, next_part_size);
 
+        // A retried part is transferred twice, but only returned once
+        let retried = self
+            .parts_to_retry
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |parts| parts.checked_sub(1))
+            .is_ok();
+        let transfers = if retried { 2 } else { 1 };
+        self.bytes_transferred += transfers * next_part_size as u64;
+
         let result = (self.next_offset, next_part);
         self.next_offset += next_part_size as u64;
         self.length -= next_part_size;
@@ -216,12 +811,38 @@ impl Stream for GetObjectResult {
 #[derive(Debug, Error, PartialEq, Eq)]
 pub struct MockClientError(pub Cow<'static, str>);
 
+/// A line of a manifest for [MockClient::from_manifest] that couldn't be parsed
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("invalid manifest line {line}: {reason}")]
+pub struct MockManifestError {
+    /// The line number, starting from 1
+    pub line: usize,
+    pub reason: String,
+}
+
+/// Parse an object size from a manifest, in bytes or with a binary unit suffix
+fn parse_manifest_size(size: &str) -> Option<usize> {
+    let (digits, multiplier) = [("GiB", 1 << 30), ("MiB", 1 << 20), ("KiB", 1 << 10)]
+        .into_iter()
+        .find_map(|(suffix, multiplier)| Some((size.strip_suffix(suffix)?, multiplier)))
+        .unwrap_or((size, 1));
+    digits.parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
 impl std::fmt::Display for MockClientError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.0)
     }
 }
 
+/// Errors from the mock client are injected to stand in for transient failures of a real client,
+/// so they're always retryable
+impl RetryableError for MockClientError {
+    fn is_retryable(&self) -> bool {
+        true
+    }
+}
+
 fn mock_client_error<T, E>(s: impl Into<Cow<'static, str>>) -> ObjectClientResult<T, E, MockClientError> {
     Err(ObjectClientError::ClientError(MockClientError(s.into())))
 }
@@ -235,41 +856,113 @@ impl ObjectClient for MockClient {
         &self,
         bucket: &str,
         key: &str,
+        version_id: Option<&str>,
     ) -> ObjectClientResult<DeleteObjectResult, DeleteObjectError, Self::ClientError> {
-        trace!(bucket, key, "DeleteObject");
+        trace!(bucket, key, ?version_id, "DeleteObject");
+        self.refresh_express_session(bucket);
+        self.count_op(Operation::DeleteObject, 1).await;
+        let _in_flight = self.start_request().await;
 
         if bucket != self.config.bucket {
             return Err(ObjectClientError::ServiceError(DeleteObjectError::NoSuchBucket));
         }
 
-        self.remove_object(key);
+        let version_id = match version_id {
+            Some(version_id) => {
+                // Like S3, Object Lock only stops versions being permanently deleted. Deleting
+                // without a version id just adds a delete marker.
+                if matches!(self.get_object_version(key, Some(version_id)), Some(object) if object.is_locked()) {
+                    return Err(ObjectClientError::ServiceError(DeleteObjectError::ObjectLocked));
+                }
+                self.remove_object_version(key, version_id);
+                Some(version_id.to_owned())
+            }
+            None => self.remove_current_object(key),
+        };
+
+        Ok(DeleteObjectResult { version_id })
+    }
+
+    async fn delete_objects(
+        &self,
+        bucket: &str,
+        keys: &[String],
+    ) -> ObjectClientResult<DeleteObjectsResult, DeleteObjectsError, Self::ClientError> {
+        trace!(bucket, num_keys = keys.len(), "DeleteObjects");
+        self.refresh_express_session(bucket);
+        self.count_op(Operation::DeleteObjects, 1).await;
+        let _in_flight = self.start_request().await;
+
+        if bucket != self.config.bucket {
+            return Err(ObjectClientError::ServiceError(DeleteObjectsError::NoSuchBucket));
+        }
+
+        if keys.len() > MAX_DELETE_OBJECTS_KEYS {
+            return Err(ObjectClientError::ServiceError(DeleteObjectsError::TooManyKeys(
+                keys.len(),
+            )));
+        }
 
-        Ok(DeleteObjectResult {})
+        let mut result = DeleteObjectsResult::default();
+        for key in keys {
+            self.remove_current_object(key);
+            result.deleted.push(key.clone());
+        }
+        Ok(result)
     }
 
     async fn get_object(
         &self,
         bucket: &str,
         key: &str,
-        range: Option<Range<u64>>,
-        if_match: Option<ETag>,
+        params: &GetObjectParams,
     ) -> ObjectClientResult<Self::GetObjectResult, GetObjectError, Self::ClientError> {
-        trace!(bucket, key, ?range, ?if_match, "GetObject");
+        trace!(bucket, key, ?params, "GetObject");
+        self.refresh_express_session(bucket);
+        self.count_op(Operation::GetObject, 1).await;
+        let _in_flight = self.start_request().await;
 
         if bucket != self.config.bucket {
             return Err(ObjectClientError::ServiceError(GetObjectError::NoSuchBucket));
         }
 
-        let objects = self.objects.read().unwrap();
+        let missing = self
+            .missing_gets
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| count.checked_sub(1))
+            .is_ok();
+        if missing {
+            return Err(ObjectClientError::ServiceError(GetObjectError::NoSuchKey));
+        }
 
-        if let Some(object) = objects.get(key) {
-            if let Some(etag_match) = if_match {
-                if etag_match != object.etag {
+        if let Some(object) = self.get_object_version(key, params.version_id.as_deref()) {
+            // S3 doesn't keep customer-provided keys, only their digests to check later requests
+            // against. We also deny requests that are missing the key, where S3 would return a 400.
+            let key_md5 = params.sse_customer_key.as_ref().map(|key| key.key_md5());
+            if object.sse_customer_key_md5.is_some() && object.sse_customer_key_md5 != key_md5 {
+                return Err(ObjectClientError::ServiceError(GetObjectError::AccessDenied));
+            }
+
+            // As in S3, a matching If-Match takes precedence over If-Unmodified-Since
+            if let Some(etag_match) = &params.if_match {
+                if *etag_match != object.etag {
+                    return Err(ObjectClientError::ServiceError(GetObjectError::PreconditionFailed));
+                }
+            } else if let Some(if_unmodified_since) = params.if_unmodified_since {
+                if object.last_modified > if_unmodified_since {
                     return Err(ObjectClientError::ServiceError(GetObjectError::PreconditionFailed));
                 }
             }
 
-            let (next_offset, length) = if let Some(range) = range {
+            if let Some(if_modified_since) = params.if_modified_since {
+                if object.last_modified <= if_modified_since {
+                    return Err(ObjectClientError::ServiceError(GetObjectError::NotModified));
+                }
+            }
+
+            let (next_offset, length) = if let Some(mut range) = params.range.clone() {
+                if self.clamp_get_ranges.load(Ordering::SeqCst) {
+                    range.end = range.end.min(object.len() as u64);
+                }
                 if range.start >= object.len() as u64 || range.end > object.len() as u64 {
                     return mock_client_error(format!("invalid range, length={}", object.len()));
                 }
@@ -279,10 +972,13 @@ impl ObjectClient for MockClient {
             };
 
             Ok(GetObjectResult {
-                object: Arc::clone(object),
+                object,
                 next_offset,
                 length,
                 part_size: self.config.part_size,
+                bytes_transferred: 0,
+                parts_to_retry: Arc::clone(&self.get_parts_to_retry),
+                omit_metadata: self.omit_get_metadata.load(Ordering::SeqCst),
             })
         } else {
             Err(ObjectClientError::ServiceError(GetObjectError::NoSuchKey))
@@ -293,15 +989,22 @@ impl ObjectClient for MockClient {
         &self,
         bucket: &str,
         key: &str,
+        version_id: Option<&str>,
     ) -> ObjectClientResult<HeadObjectResult, HeadObjectError, Self::ClientError> {
-        trace!(bucket, key, "HeadObject");
+        trace!(bucket, key, ?version_id, "HeadObject");
+        self.refresh_express_session(bucket);
+        self.count_op(Operation::HeadObject, 1).await;
+        let _in_flight = self.start_request().await;
 
         if bucket != self.config.bucket {
             return Err(ObjectClientError::ServiceError(HeadObjectError::NotFound));
         }
 
-        let objects = self.objects.read().unwrap();
-        if let Some(object) = objects.get(key) {
+        if self.deny_head_object.load(Ordering::SeqCst) {
+            return Err(ObjectClientError::ServiceError(HeadObjectError::AccessDenied));
+        }
+
+        if let Some(object) = self.get_object_version(key, version_id) {
             Ok(HeadObjectResult {
                 bucket: bucket.to_string(),
                 object: ObjectInfo {
@@ -310,13 +1013,115 @@ impl ObjectClient for MockClient {
                     last_modified: object.last_modified,
                     etag: object.etag.as_str().to_string(),
                     storage_class: None,
+                    expiration: object.expiration.clone(),
+                    version_id: object.version_id.clone(),
                 },
+                bucket_key_enabled: object.bucket_key_enabled,
+                object_lock_mode: object.object_lock_mode,
+                object_lock_retain_until: object.object_lock_retain_until,
+                object_lock_legal_hold: object.object_lock_legal_hold,
+                content_encoding: object.content_encoding.clone(),
+                user_metadata: object.user_metadata.clone(),
             })
         } else {
             Err(ObjectClientError::ServiceError(HeadObjectError::NotFound))
         }
     }
 
+    async fn list_object_versions(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        key_marker: Option<&str>,
+        version_id_marker: Option<&str>,
+        max_keys: usize,
+    ) -> ObjectClientResult<ListObjectVersionsResult, ListObjectVersionsError, Self::ClientError> {
+        trace!(
+            bucket,
+            prefix,
+            ?key_marker,
+            ?version_id_marker,
+            max_keys,
+            "ListObjectVersions"
+        );
+        self.refresh_express_session(bucket);
+        self.count_op(Operation::ListObjectVersions, 1).await;
+        let _in_flight = self.start_request().await;
+
+        if bucket != self.config.bucket {
+            return Err(ObjectClientError::ServiceError(ListObjectVersionsError::NoSuchBucket));
+        }
+
+        let versions = self.versions.read().unwrap();
+
+        // Walk every version of every key under the prefix, in key order and newest first for each
+        // key, skipping everything up to and including the markers.
+        let mut entries = versions
+            .range(key_marker.unwrap_or("").to_string()..)
+            .filter(|(key, _)| key.starts_with(prefix))
+            .flat_map(|(key, history)| {
+                history
+                    .iter()
+                    .rev()
+                    .enumerate()
+                    .map(move |(i, version)| (key, i == 0, version))
+            })
+            .skip_while(|(key, _, version)| match key_marker {
+                Some(key_marker) if key.as_str() == key_marker => match version_id_marker {
+                    Some(version_id_marker) => version.version_id() != version_id_marker,
+                    None => true,
+                },
+                _ => false,
+            })
+            .skip_while(|(key, _, version)| {
+                key_marker == Some(key.as_str()) && version_id_marker == Some(version.version_id())
+            })
+            .peekable();
+
+        let mut result = vec![];
+        while result.len() < max_keys {
+            let Some((key, is_latest, version)) = entries.next() else {
+                break;
+            };
+            let version = match version {
+                MockVersion::Object(object) => ObjectVersion {
+                    key: key.to_string(),
+                    version_id: version.version_id().to_string(),
+                    is_latest,
+                    is_delete_marker: false,
+                    size: object.size as u64,
+                    last_modified: object.last_modified,
+                    etag: Some(object.etag.as_str().to_string()),
+                },
+                MockVersion::DeleteMarker {
+                    version_id,
+                    last_modified,
+                } => ObjectVersion {
+                    key: key.to_string(),
+                    version_id: version_id.clone(),
+                    is_latest,
+                    is_delete_marker: true,
+                    size: 0,
+                    last_modified: *last_modified,
+                    etag: None,
+                },
+            };
+            result.push(version);
+        }
+
+        let (next_key_marker, next_version_id_marker) = match (entries.peek(), result.last()) {
+            (Some(_), Some(last)) => (Some(last.key.clone()), Some(last.version_id.clone())),
+            _ => (None, None),
+        };
+
+        Ok(ListObjectVersionsResult {
+            bucket: bucket.to_string(),
+            versions: result,
+            next_key_marker,
+            next_version_id_marker,
+        })
+    }
+
     async fn list_objects(
         &self,
         bucket: &str,
@@ -326,8 +1131,31 @@ impl ObjectClient for MockClient {
         prefix: &str,
     ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, Self::ClientError> {
         trace!(bucket, ?continuation_token, delimiter, max_keys, prefix, "ListObjects");
+        self.refresh_express_session(bucket);
+        self.count_op(Operation::ListObjects, 1).await;
+        let _in_flight = self.start_request().await;
+
+        if let Some(region) = self.redirect_region.lock().unwrap().clone() {
+            return Err(ObjectClientError::ServiceError(ListObjectsError::IncorrectRegion(
+                region,
+            )));
+        }
 
         if bucket != self.config.bucket {
+            if self
+                .other_buckets
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|other| other.name == bucket)
+            {
+                return Ok(ListObjectsResult {
+                    bucket: bucket.to_owned(),
+                    objects: Vec::new(),
+                    common_prefixes: Vec::new(),
+                    next_continuation_token: None,
+                });
+            }
             return Err(ObjectClientError::ServiceError(ListObjectsError::NoSuchBucket));
         }
 
@@ -340,6 +1168,7 @@ impl ObjectClient for MockClient {
         let mut object_vec: Vec<ObjectInfo> = Vec::new();
         let mut next_continuation_token: Option<String> = None;
         let mut current_common_prefix: Option<String> = None;
+        let split_common_prefixes = self.split_common_prefixes.load(Ordering::SeqCst);
 
         // If there is a continuation token, set up an iterator starting at that token. Otherwise,
         // start at the beginning of the bucket.
@@ -373,7 +1202,7 @@ impl ObjectClient for MockClient {
             let key_count = common_prefixes.len() + object_vec.len();
             if key_count >= max_keys {
                 match current_common_prefix {
-                    Some(ref ccp) if key.starts_with(ccp) => continue,
+                    Some(ref ccp) if key.starts_with(ccp) && !split_common_prefixes => continue,
                     _ => {
                         next_continuation_token = Some(key.to_string());
                         break;
@@ -406,6 +1235,8 @@ impl ObjectClient for MockClient {
                     last_modified: object.last_modified,
                     etag: object.etag.as_str().to_string(),
                     storage_class: None,
+                    expiration: None,
+                    version_id: None,
                 });
             }
         }
@@ -424,10 +1255,11 @@ impl ObjectClient for MockClient {
         &self,
         bucket: &str,
         key: &str,
-        _params: &PutObjectParams,
+        params: &PutObjectParams,
         contents: impl Stream<Item = impl AsRef<[u8]> + Send> + Send,
     ) -> ObjectClientResult<PutObjectResult, PutObjectError, Self::ClientError> {
-        trace!(bucket, key, "PutObject");
+        trace!(bucket, key, ?params, "PutObject");
+        self.refresh_express_session(bucket);
 
         if bucket != self.config.bucket {
             return Err(ObjectClientError::ServiceError(PutObjectError::NoSuchBucket));
@@ -443,9 +1275,217 @@ impl ObjectClient for MockClient {
             })
             .await;
 
-        self.add_object(key, buffer.into());
+        // Like the CRT, automatically use a multipart upload for objects larger than a part
+        let multipart = match params.upload_mode {
+            UploadMode::Automatic => buffer.len() > self.config.part_size,
+            UploadMode::SinglePart => false,
+            UploadMode::Multipart => true,
+        };
+        // Each request's body has its own Content-MD5, so for a multipart upload that's each part
+        let mut request_size = buffer.len().max(1);
+        if multipart {
+            // Like the CRT, grow the part size if the object wouldn't fit in the maximum number of
+            // parts. The part size has to be chosen before the first part is uploaded, so it has to
+            // fit the expected size too.
+            let expected_size = params.object_size_hint.unwrap_or(0).max(buffer.len() as u64);
+            let part_size = upload_part_size(expected_size, self.config.part_size.max(1));
+            self.multipart_part_sizes
+                .lock()
+                .unwrap()
+                .insert(key.to_owned(), part_size);
+            let parts = buffer.len().div_ceil(part_size).max(1);
+            request_size = part_size;
+            assert!(
+                parts <= MAX_UPLOAD_PARTS,
+                "multipart uploads can have at most {MAX_UPLOAD_PARTS} parts"
+            );
+            self.count_op(Operation::CreateMultipartUpload, 1).await;
+            self.count_op(Operation::UploadPart, parts as u64).await;
+            self.count_op(Operation::CompleteMultipartUpload, 1).await;
+        } else {
+            self.count_op(Operation::PutObject, 1).await;
+        }
+        let _in_flight = self.start_request().await;
+
+        let digests: Option<Vec<_>> = params
+            .compute_content_md5
+            .then(|| buffer.chunks(request_size).map(content_md5).collect());
+        if self.corrupt_next_put_body.swap(false, Ordering::SeqCst) {
+            if let Some(byte) = buffer.last_mut() {
+                *byte ^= 0xff;
+            }
+        }
+        if let Some(digests) = digests {
+            if !buffer.chunks(request_size).map(content_md5).eq(digests) {
+                return Err(ObjectClientError::ServiceError(PutObjectError::BadDigest));
+            }
+        }
+
+        if multipart {
+            // Like the CRT client, complete the upload again after a transient failure, as many
+            // times as it does by default
+            let mut retries = 0;
+            while let Some(error) = self.complete_multipart_upload_errors.lock().unwrap().pop_front() {
+                if !error.is_retryable() || retries == RetryStrategyConfig::default().max_retries {
+                    // Like S3, an upload that fails to complete is left in progress
+                    self.create_multipart_upload(key, OffsetDateTime::now_utc());
+                    return Err(ObjectClientError::ServiceError(
+                        PutObjectError::CompleteMultipartUpload(error),
+                    ));
+                }
+                self.count_op(Operation::CompleteMultipartUpload, 1).await;
+                retries += 1;
+            }
+        }
+
+        let size = buffer.len() as u64;
+        let mut object: MockObject = buffer.into();
+        object.set_bucket_key_enabled(params.bucket_key_enabled.unwrap_or(false));
+        object.set_sse_customer_key_md5(params.sse_customer_key.as_ref().map(|key| key.key_md5()));
+        if let Some(storage_class) = &params.storage_class {
+            object.set_storage_class(storage_class);
+        } else if self.express_mode.uses_sessions(bucket) {
+            // Objects in directory buckets are always stored in S3 Express One Zone
+            object.set_storage_class(StorageClass::ExpressOnezone.as_str());
+        }
+        object.set_acl(params.acl.unwrap_or_default());
+        object.set_object_lock_retention(params.object_lock_mode, params.object_lock_retain_until);
+        object.set_object_lock_legal_hold(params.object_lock_legal_hold.unwrap_or(false));
+        let etag = object.etag();
+        let version_id = self.insert_object(key, object);
+
+        Ok(PutObjectResult {
+            version_id: Some(version_id),
+            etag: Some(etag),
+            size: Some(size),
+        })
+    }
+
+    async fn copy_object(
+        &self,
+        source_bucket: &str,
+        source_key: &str,
+        destination_bucket: &str,
+        destination_key: &str,
+    ) -> ObjectClientResult<CopyObjectResult, CopyObjectError, Self::ClientError> {
+        trace!(
+            source_bucket,
+            source_key,
+            destination_bucket,
+            destination_key,
+            "CopyObject"
+        );
+        self.refresh_express_session(destination_bucket);
+        self.count_op(Operation::CopyObject, 1).await;
+        let _in_flight = self.start_request().await;
+
+        if source_bucket != self.config.bucket || destination_bucket != self.config.bucket {
+            return Err(ObjectClientError::ServiceError(CopyObjectError::NoSuchBucket));
+        }
+
+        let Some(source) = self.get_object_version(source_key, None) else {
+            return Err(ObjectClientError::ServiceError(CopyObjectError::NoSuchKey));
+        };
+        self.insert_object(destination_key, MockObject::copy_of(source));
+        Ok(CopyObjectResult {})
+    }
+
+    async fn put_object_acl(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        acl: ObjectCannedAcl,
+    ) -> ObjectClientResult<PutObjectAclResult, PutObjectAclError, Self::ClientError> {
+        trace!(bucket, key, ?version_id, ?acl, "PutObjectAcl");
+        self.refresh_express_session(bucket);
+        self.count_op(Operation::PutObjectAcl, 1).await;
+        let _in_flight = self.start_request().await;
+
+        if bucket != self.config.bucket {
+            return Err(ObjectClientError::ServiceError(PutObjectAclError::NoSuchBucket));
+        }
+
+        match self.get_object_version(key, version_id) {
+            Some(object) => {
+                object.set_acl(acl);
+                Ok(PutObjectAclResult {})
+            }
+            None => Err(ObjectClientError::ServiceError(PutObjectAclError::NoSuchKey)),
+        }
+    }
+
+    async fn list_multipart_uploads(
+        &self,
+        bucket: &str,
+        prefix: &str,
+    ) -> ObjectClientResult<ListMultipartUploadsResult, ListMultipartUploadsError, Self::ClientError> {
+        trace!(bucket, prefix, "ListMultipartUploads");
+        self.refresh_express_session(bucket);
+        self.count_op(Operation::ListMultipartUploads, 1).await;
+        let _in_flight = self.start_request().await;
+
+        if bucket != self.config.bucket {
+            return Err(ObjectClientError::ServiceError(ListMultipartUploadsError::NoSuchBucket));
+        }
+
+        let mut uploads: Vec<_> = self
+            .multipart_uploads
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|upload| upload.key.starts_with(prefix))
+            .cloned()
+            .collect();
+        // Uploads are in creation order, so a stable sort keeps each key's uploads oldest first
+        uploads.sort_by(|a, b| a.key.cmp(&b.key));
+
+        Ok(ListMultipartUploadsResult {
+            bucket: bucket.to_owned(),
+            uploads,
+        })
+    }
+
+    async fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> ObjectClientResult<AbortMultipartUploadResult, AbortMultipartUploadError, Self::ClientError> {
+        trace!(bucket, key, upload_id, "AbortMultipartUpload");
+        self.refresh_express_session(bucket);
+        self.count_op(Operation::AbortMultipartUpload, 1).await;
+        let _in_flight = self.start_request().await;
+
+        if bucket != self.config.bucket {
+            return Err(ObjectClientError::ServiceError(AbortMultipartUploadError::NoSuchBucket));
+        }
+
+        let mut uploads = self.multipart_uploads.lock().unwrap();
+        let position = uploads
+            .iter()
+            .position(|upload| upload.key == key && upload.upload_id == upload_id)
+            .ok_or(ObjectClientError::ServiceError(AbortMultipartUploadError::NoSuchUpload))?;
+        uploads.remove(position);
+
+        Ok(AbortMultipartUploadResult {})
+    }
 
-        Ok(PutObjectResult {})
+    async fn list_buckets(&self) -> ObjectClientResult<ListBucketsResult, ListBucketsError, Self::ClientError> {
+        trace!("ListBuckets");
+        self.count_op(Operation::ListBuckets, 1).await;
+        let _in_flight = self.start_request().await;
+
+        let mut buckets = vec![BucketInfo {
+            name: self.config.bucket.clone(),
+            creation_date: self.creation_date,
+        }];
+        buckets.extend(self.other_buckets.lock().unwrap().iter().cloned());
+        buckets.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(ListBucketsResult {
+            buckets,
+            owner: self.bucket_owner.lock().unwrap().clone(),
+        })
     }
 
     async fn get_object_attributes(
@@ -457,6 +1497,9 @@ impl ObjectClient for MockClient {
         object_attributes: &[ObjectAttribute],
     ) -> ObjectClientResult<GetObjectAttributesResult, GetObjectAttributesError, Self::ClientError> {
         trace!(bucket, key, "GetObjectAttributes");
+        self.refresh_express_session(bucket);
+        self.count_op(Operation::GetObjectAttributes, 1).await;
+        let _in_flight = self.start_request().await;
 
         if bucket != self.config.bucket {
             return Err(ObjectClientError::ServiceError(GetObjectAttributesError::NoSuchBucket));
@@ -486,15 +1529,43 @@ impl ObjectClient for MockClient {
             Err(ObjectClientError::ServiceError(GetObjectAttributesError::NoSuchKey))
         }
     }
-}
 
-#[cfg(test)]
+    async fn presign_post_object(
+        &self,
+        bucket: &str,
+        key_prefix: &str,
+        conditions: &[PostPolicyCondition],
+        expires_in: Duration,
+    ) -> ObjectClientResult<PresignedPost, PresignPostError, Self::ClientError> {
+        trace!(bucket, key_prefix, ?conditions, ?expires_in, "PresignPostObject");
+
+        // The mock client has no real credentials, so sign with well-known fake ones
+        let credentials = Credentials::new("mock_access_key_id", "mock_secret_access_key", None);
+        Ok(presign_post(
+            format!("https://{bucket}.s3.us-east-1.amazonaws.com"),
+            &credentials,
+            "us-east-1",
+            bucket,
+            key_prefix,
+            conditions,
+            expires_in,
+            OffsetDateTime::now_utc(),
+        ))
+    }
+}
+
+#[cfg(test)]
 mod tests {
+    use std::ops::Range;
+    use std::str::FromStr;
+
     use futures::StreamExt;
     use rand::{Rng, RngCore, SeedableRng};
     use rand_chacha::ChaChaRng;
+    use test_case::test_case;
 
     use super::*;
+    use crate::{SseCustomerKey, MAX_OBJECT_SIZE, MAX_UPLOAD_PART_SIZE};
 
     async fn test_get_object(key: &str, size: usize, range: Option<Range<u64>>) {
         let mut rng = ChaChaRng::seed_from_u64(0x12345678);
@@ -509,7 +1580,7 @@ mod tests {
         client.add_object(key, MockObject::from_bytes(&body, ETag::for_tests()));
 
         let mut get_request = client
-            .get_object("test_bucket", key, range.clone(), None)
+            .get_object("test_bucket", key, &GetObjectParams::new().range(range.clone()))
             .await
             .expect("should not fail");
 
@@ -524,6 +1595,7 @@ mod tests {
         let expected_range = range.unwrap_or(0..size as u64);
         let expected_range = expected_range.start as usize..expected_range.end as usize;
         assert_eq!(&accum[..], &body[expected_range], "body does not match");
+        assert_eq!(get_request.bytes_transferred(), accum.len() as u64);
     }
 
     #[tokio::test]
@@ -533,6 +1605,32 @@ mod tests {
         test_get_object("key1", 10, Some(0..10)).await;
     }
 
+    #[tokio::test]
+    async fn get_object_bytes_transferred_counts_retries() {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+        });
+        client.add_object("key1", MockObject::constant(0xaa, 3000, ETag::for_tests()));
+
+        let mut get_request = client
+            .get_object("test_bucket", "key1", &GetObjectParams::new())
+            .await
+            .expect("should not fail");
+
+        // Retry the second part after the first has been received
+        let (_, part) = get_request.next().await.unwrap().unwrap();
+        assert_eq!(get_request.bytes_transferred(), part.len() as u64);
+        client.retry_next_get_parts(1);
+
+        let mut received = part.len();
+        while let Some(part) = get_request.next().await {
+            received += part.expect("get_object body part failed").1.len();
+        }
+        assert_eq!(received, 3000);
+        assert_eq!(get_request.bytes_transferred(), 3000 + 1024);
+    }
+
     #[allow(clippy::reversed_empty_ranges)]
     #[tokio::test]
     async fn get_object_errors() {
@@ -560,37 +1658,101 @@ mod tests {
         }
 
         assert!(matches!(
-            client.get_object("wrong_bucket", "key1", None, None).await,
+            client.get_object("wrong_bucket", "key1", &GetObjectParams::new()).await,
             Err(ObjectClientError::ServiceError(GetObjectError::NoSuchBucket))
         ));
 
         assert!(matches!(
-            client.get_object("test_bucket", "wrong_key", None, None).await,
+            client
+                .get_object("test_bucket", "wrong_key", &GetObjectParams::new())
+                .await,
             Err(ObjectClientError::ServiceError(GetObjectError::NoSuchKey))
         ));
 
         assert_client_error!(
-            client.get_object("test_bucket", "key1", Some(0..2001), None).await,
+            client
+                .get_object("test_bucket", "key1", &GetObjectParams::new().range(Some(0..2001)))
+                .await,
             "invalid range, length=2000"
         );
         assert_client_error!(
-            client.get_object("test_bucket", "key1", Some(2000..2000), None).await,
+            client
+                .get_object("test_bucket", "key1", &GetObjectParams::new().range(Some(2000..2000)))
+                .await,
             "invalid range, length=2000"
         );
         assert_client_error!(
-            client.get_object("test_bucket", "key1", Some(500..2001), None).await,
+            client
+                .get_object("test_bucket", "key1", &GetObjectParams::new().range(Some(500..2001)))
+                .await,
             "invalid range, length=2000"
         );
         assert_client_error!(
-            client.get_object("test_bucket", "key1", Some(5000..2001), None).await,
+            client
+                .get_object("test_bucket", "key1", &GetObjectParams::new().range(Some(5000..2001)))
+                .await,
             "invalid range, length=2000"
         );
         assert_client_error!(
-            client.get_object("test_bucket", "key1", Some(5000..1), None).await,
+            client
+                .get_object("test_bucket", "key1", &GetObjectParams::new().range(Some(5000..1)))
+                .await,
             "invalid range, length=2000"
         );
     }
 
+    #[tokio::test]
+    async fn get_object_conditional_time() {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+        });
+
+        let last_modified = OffsetDateTime::from_unix_timestamp(1_000_000).unwrap();
+        let mut object = MockObject::constant(0u8, 100, ETag::for_tests());
+        object.set_last_modified(last_modified);
+        client.add_object("key1", object);
+
+        let before = last_modified - time::Duration::seconds(1);
+        let after = last_modified + time::Duration::seconds(1);
+
+        let params = GetObjectParams::new()
+            .if_modified_since(Some(before))
+            .if_unmodified_since(Some(after));
+        client
+            .get_object("test_bucket", "key1", &params)
+            .await
+            .expect("preconditions should hold");
+
+        let params = GetObjectParams {
+            if_modified_since: Some(last_modified),
+            ..Default::default()
+        };
+        assert!(matches!(
+            client
+                .get_object("test_bucket", "key1", None, None, None, &params)
+                .await,
+            Err(ObjectClientError::ServiceError(GetObjectError::NotModified))
+        ));
+
+        let params = GetObjectParams {
+            if_unmodified_since: Some(before),
+            ..Default::default()
+        };
+        assert!(matches!(
+            client
+                .get_object("test_bucket", "key1", None, None, None, &params)
+                .await,
+            Err(ObjectClientError::ServiceError(GetObjectError::PreconditionFailed))
+        ));
+
+        // A matching If-Match takes precedence over If-Unmodified-Since
+        client
+            .get_object("test_bucket", "key1", &params.if_match(Some(ETag::for_tests())))
+            .await
+            .expect("If-Match should take precedence");
+    }
+
     #[tokio::test]
     async fn list_object_dirs() {
         let client = MockClient::new(MockClientConfig {
@@ -678,6 +1840,43 @@ mod tests {
         check_continuation!("/", 2, "dirs/dir2/", &keys[7..9], &[]);
     }
 
+    #[test_case(MockObject::constant(0xaa, 10_000, ETag::for_tests()), |_| 0xaa; "constant")]
+    #[test_case(MockObject::ramp(0xaa, 10_000, ETag::for_tests()), |k| ((0xaa + k) % RAMP_MODULUS as u64) as u8; "ramp")]
+    #[tokio::test]
+    async fn test_generated_contents_across_parts(object: MockObject, expected_byte: fn(u64) -> u8) {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+        });
+        client.add_object("key", object);
+
+        // Start part-way through a part so every part boundary falls mid-read
+        let params = GetObjectParams::new().range(Some(1000..9000));
+        let mut get_request = client
+            .get_object("test_bucket", "key", &params)
+            .await
+            .expect("get_object failed");
+
+        let mut next_offset = 1000;
+        let mut parts = 0;
+        while let Some(r) = get_request.next().await {
+            let (offset, body) = r.expect("get_object body part failed");
+            assert_eq!(offset, next_offset, "wrong body part offset");
+            for (i, byte) in body.iter().enumerate() {
+                assert_eq!(
+                    *byte,
+                    expected_byte(offset + i as u64),
+                    "wrong byte at offset {}",
+                    offset + i as u64
+                );
+            }
+            next_offset += body.len() as u64;
+            parts += 1;
+        }
+        assert_eq!(next_offset, 9000);
+        assert!(parts > 1, "read should span several parts");
+    }
+
     #[tokio::test]
     async fn test_put_object() {
         let mut rng = ChaChaRng::seed_from_u64(0x12345678);
@@ -708,7 +1907,7 @@ mod tests {
             .expect("put_object failed");
 
         let mut get_request = client
-            .get_object("test_bucket", "key1", None, None)
+            .get_object("test_bucket", "key1", &GetObjectParams::new())
             .await
             .expect("get_object failed");
 
@@ -722,6 +1921,784 @@ mod tests {
         }
     }
 
+    #[test_case(UploadMode::Automatic, 1024, 0, 0; "automatic small")]
+    #[test_case(UploadMode::Automatic, 2500, 1, 3; "automatic large")]
+    #[test_case(UploadMode::SinglePart, 2500, 0, 0; "single part")]
+    #[test_case(UploadMode::Multipart, 1000, 1, 1; "multipart small")]
+    #[test_case(UploadMode::Multipart, 2048, 1, 2; "multipart large")]
+    #[tokio::test]
+    async fn test_put_object_upload_mode(upload_mode: UploadMode, size: usize, multipart_uploads: u64, parts: u64) {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+        });
+
+        let params = PutObjectParams::new().upload_mode(upload_mode);
+        let contents = futures::stream::iter([vec![0u8; size]]);
+        client
+            .put_object("test_bucket", "key1", &params, contents)
+            .await
+            .expect("put_object failed");
+
+        assert_eq!(client.op_count(Operation::PutObject), 1 - multipart_uploads);
+        assert_eq!(client.op_count(Operation::CreateMultipartUpload), multipart_uploads);
+        assert_eq!(client.op_count(Operation::UploadPart), parts);
+        assert_eq!(client.op_count(Operation::CompleteMultipartUpload), multipart_uploads);
+    }
+
+    #[tokio::test]
+    async fn test_put_object_part_size_escalation() {
+        let part_size = 16;
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size,
+        });
+
+        // Big enough to need 16 times the maximum number of parts at the configured part size
+        let chunk = vec![0u8; part_size];
+        let chunks = 16 * MAX_UPLOAD_PARTS;
+        let contents = futures::stream::iter(std::iter::repeat(&chunk[..]).take(chunks));
+        let params = PutObjectParams::new().upload_mode(UploadMode::Multipart);
+        let result = client
+            .put_object("test_bucket", "large", &params, contents)
+            .await
+            .expect("put_object failed");
+        assert_eq!(result.size, Some((chunks * part_size) as u64));
+
+        let parts = client.op_count(Operation::UploadPart);
+        assert!(parts <= MAX_UPLOAD_PARTS as u64, "{parts} parts is too many");
+        assert_eq!(parts, MAX_UPLOAD_PARTS as u64);
+        assert_eq!(upload_part_size((chunks * part_size) as u64, part_size), 16 * part_size);
+
+        // Objects that already fit keep the configured part size
+        assert_eq!(upload_part_size(1000, part_size), part_size);
+        // The largest possible object fits within the limits
+        let max_part_size = upload_part_size(MAX_OBJECT_SIZE, part_size);
+        assert!(max_part_size <= MAX_UPLOAD_PART_SIZE);
+        assert!(max_part_size as u64 * MAX_UPLOAD_PARTS as u64 >= MAX_OBJECT_SIZE);
+    }
+
+    #[tokio::test]
+    async fn test_put_object_complete_multipart_upload_error() {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+        });
+
+        let error =
+            CompleteMultipartUploadError::new("InvalidPart", "One or more of the specified parts could not be found");
+        client.fail_next_complete_multipart_upload(error.clone());
+
+        // A single part upload doesn't complete a multipart upload, so isn't affected
+        let contents = futures::stream::iter([vec![0u8; 100]]);
+        client
+            .put_object("test_bucket", "small", &PutObjectParams::new(), contents)
+            .await
+            .expect("single part put_object should succeed");
+
+        // The error isn't transient, so isn't retried
+        let contents = futures::stream::iter([vec![0u8; 2500]]);
+        let result = client
+            .put_object("test_bucket", "large", &PutObjectParams::new(), contents)
+            .await;
+        assert!(matches!(
+            result,
+            Err(ObjectClientError::ServiceError(PutObjectError::CompleteMultipartUpload(e))) if e == error
+        ));
+        assert!(!client.contains_key("large"));
+
+        let contents = futures::stream::iter([vec![0u8; 2500]]);
+        client
+            .put_object("test_bucket", "large", &PutObjectParams::new(), contents)
+            .await
+            .expect("retried put_object should succeed");
+        assert!(client.contains_key("large"));
+    }
+
+    #[tokio::test]
+    async fn test_put_object_complete_multipart_upload_retries() {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+        });
+
+        // Transient failures are retried without uploading the parts again
+        let error = CompleteMultipartUploadError::new("InternalError", "We encountered an internal error");
+        client.fail_next_complete_multipart_upload(error.clone());
+        client.fail_next_complete_multipart_upload(error.clone());
+        let contents = futures::stream::iter([vec![0u8; 2500]]);
+        client
+            .put_object("test_bucket", "large", &PutObjectParams::new(), contents)
+            .await
+            .expect("put_object should succeed after retrying the completion");
+        assert!(client.contains_key("large"));
+        assert_eq!(client.op_count(Operation::UploadPart), 3);
+        assert_eq!(client.op_count(Operation::CompleteMultipartUpload), 3);
+
+        // Until the retries run out
+        for _ in 0..4 {
+            client.fail_next_complete_multipart_upload(error.clone());
+        }
+        let contents = futures::stream::iter([vec![0u8; 2500]]);
+        let result = client
+            .put_object("test_bucket", "large2", &PutObjectParams::new(), contents)
+            .await;
+        assert!(matches!(
+            result,
+            Err(ObjectClientError::ServiceError(PutObjectError::CompleteMultipartUpload(e))) if e == error
+        ));
+        assert!(!client.contains_key("large2"));
+    }
+
+    #[test_case(UploadMode::SinglePart; "single part")]
+    #[test_case(UploadMode::Multipart; "multipart")]
+    #[tokio::test]
+    async fn test_put_object_content_md5(upload_mode: UploadMode) {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+        });
+        let body = ramp_bytes(0, 2500);
+        async fn etag(client: &MockClient) -> String {
+            let result = client.head_object("test_bucket", "key1", None).await;
+            result.expect("head_object failed").object.etag
+        }
+
+        let params = PutObjectParams::new()
+            .upload_mode(upload_mode)
+            .compute_content_md5(true);
+        client
+            .put_object("test_bucket", "key1", &params, futures::stream::iter([body.clone()]))
+            .await
+            .expect("put_object with a correct Content-MD5 should succeed");
+        assert_eq!(etag(&client).await, ETag::from_object_bytes(&body).as_str());
+
+        // A body that doesn't match its digest is rejected, and the old object is left in place
+        client.corrupt_next_put_body();
+        let result = client
+            .put_object("test_bucket", "key1", &params, futures::stream::iter([vec![0u8; 2500]]))
+            .await;
+        assert!(matches!(
+            result,
+            Err(ObjectClientError::ServiceError(PutObjectError::BadDigest))
+        ));
+        assert_eq!(etag(&client).await, ETag::from_object_bytes(&body).as_str());
+
+        // Without a digest, the corruption goes unnoticed
+        client.corrupt_next_put_body();
+        let params = params.compute_content_md5(false);
+        client
+            .put_object("test_bucket", "key1", &params, futures::stream::iter([body.clone()]))
+            .await
+            .expect("put_object without Content-MD5 should succeed");
+        assert_ne!(etag(&client).await, ETag::from_object_bytes(&body).as_str());
+    }
+
+    #[test_case(None, false; "default")]
+    #[test_case(Some(false), false; "disabled")]
+    #[test_case(Some(true), true; "enabled")]
+    #[tokio::test]
+    async fn test_put_object_bucket_key(bucket_key_enabled: Option<bool>, expected: bool) {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+        });
+
+        let params = PutObjectParams::new().bucket_key_enabled(bucket_key_enabled);
+        let contents = futures::stream::iter([vec![0u8; 100]]);
+        client
+            .put_object("test_bucket", "key1", &params, contents)
+            .await
+            .expect("put_object failed");
+
+        let result = client
+            .head_object("test_bucket", "key1", None)
+            .await
+            .expect("head_object failed");
+        assert_eq!(result.bucket_key_enabled, expected);
+    }
+
+    #[tokio::test]
+    async fn test_put_object_acl() {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+        });
+
+        let params = PutObjectParams::new().acl(Some(ObjectCannedAcl::BucketOwnerFullControl));
+        let contents = futures::stream::iter([vec![0u8; 100]]);
+        client
+            .put_object("test_bucket", "key1", &params, contents)
+            .await
+            .expect("put_object failed");
+        assert_eq!(client.object_acl("key1"), Some(ObjectCannedAcl::BucketOwnerFullControl));
+
+        let contents = futures::stream::iter([vec![0u8; 100]]);
+        client
+            .put_object("test_bucket", "key2", &PutObjectParams::new(), contents)
+            .await
+            .expect("put_object failed");
+        assert_eq!(client.object_acl("key2"), Some(ObjectCannedAcl::Private));
+
+        client
+            .put_object_acl("test_bucket", "key2", None, ObjectCannedAcl::PublicRead)
+            .await
+            .expect("put_object_acl failed");
+        assert_eq!(client.object_acl("key2"), Some(ObjectCannedAcl::PublicRead));
+
+        let result = client
+            .put_object_acl("test_bucket", "missing", None, ObjectCannedAcl::PublicRead)
+            .await;
+        assert!(matches!(
+            result,
+            Err(ObjectClientError::ServiceError(PutObjectAclError::NoSuchKey))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_copy_object() {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+        });
+        let body: Vec<u8> = (0..2000).map(|i| i as u8).collect();
+        client.add_object("source", MockObject::from_bytes(&body, ETag::for_tests()));
+
+        client
+            .copy_object("test_bucket", "source", "test_bucket", "destination")
+            .await
+            .expect("copy_object failed");
+        let result = client
+            .get_object("test_bucket", "destination", &GetObjectParams::new())
+            .await
+            .expect("get_object failed");
+        let copied = result.collect().await.expect("get_object body failed");
+        assert_eq!(&copied[..], &body[..]);
+        assert_eq!(client.op_count(Operation::CopyObject), 1);
+
+        let result = client
+            .copy_object("test_bucket", "missing", "test_bucket", "destination")
+            .await;
+        assert!(matches!(
+            result,
+            Err(ObjectClientError::ServiceError(CopyObjectError::NoSuchKey))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_object_lock() {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+        });
+
+        let retain_until = OffsetDateTime::now_utc() + Duration::from_secs(24 * 60 * 60);
+        let params = PutObjectParams::new()
+            .object_lock_mode(Some(ObjectLockMode::Compliance))
+            .object_lock_retain_until(Some(retain_until));
+        let contents = futures::stream::iter([vec![0u8; 100]]);
+        let put_result = client
+            .put_object("test_bucket", "key1", &params, contents)
+            .await
+            .expect("put_object failed");
+        let version_id = put_result.version_id.expect("mock bucket is versioned");
+
+        let head_result = client
+            .head_object("test_bucket", "key1", None)
+            .await
+            .expect("head_object failed");
+        assert_eq!(head_result.object_lock_mode, Some(ObjectLockMode::Compliance));
+        assert_eq!(head_result.object_lock_retain_until, Some(retain_until));
+        assert!(!head_result.object_lock_legal_hold);
+
+        // The locked version can't be deleted
+        let result = client.delete_object("test_bucket", "key1", Some(&version_id)).await;
+        assert!(matches!(
+            result,
+            Err(ObjectClientError::ServiceError(DeleteObjectError::ObjectLocked))
+        ));
+        assert!(client.contains_key("key1"));
+
+        // Deleting without a version id only hides it behind a delete marker
+        client
+            .delete_object("test_bucket", "key1", None)
+            .await
+            .expect("delete_object failed");
+        assert!(!client.contains_key("key1"));
+        client
+            .head_object("test_bucket", "key1", Some(&version_id))
+            .await
+            .expect("locked version should still exist");
+
+        // Once retention has passed, the version can be deleted
+        let params = PutObjectParams::new()
+            .object_lock_mode(Some(ObjectLockMode::Governance))
+            .object_lock_retain_until(Some(OffsetDateTime::now_utc() - Duration::from_secs(1)));
+        let contents = futures::stream::iter([vec![0u8; 100]]);
+        let put_result = client
+            .put_object("test_bucket", "key2", &params, contents)
+            .await
+            .expect("put_object failed");
+        let version_id = put_result.version_id.expect("mock bucket is versioned");
+        client
+            .delete_object("test_bucket", "key2", Some(&version_id))
+            .await
+            .expect("delete_object failed");
+    }
+
+    #[test_case(Some([1u8; 32]), true; "matching key")]
+    #[test_case(Some([2u8; 32]), false; "mismatched key")]
+    #[test_case(None, false; "missing key")]
+    #[tokio::test]
+    async fn test_sse_customer_key(read_key: Option<[u8; 32]>, expect_success: bool) {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+        });
+
+        let body = vec![0xaau8; 100];
+        let params = PutObjectParams::new().sse_customer_key(Some(SseCustomerKey::aes256([1u8; 32])));
+        client
+            .put_object("test_bucket", "key1", &params, futures::stream::iter([&body[..]]))
+            .await
+            .expect("put_object failed");
+
+        let params = GetObjectParams::new().sse_customer_key(read_key.map(SseCustomerKey::aes256));
+        let result = client.get_object("test_bucket", "key1", &params).await;
+        if expect_success {
+            let mut get_request = result.expect("get_object should succeed");
+            let mut accum = vec![];
+            while let Some(r) = get_request.next().await {
+                let (_offset, part) = r.expect("get_object body part failed");
+                accum.extend_from_slice(&part[..]);
+            }
+            assert_eq!(accum, body);
+        } else {
+            assert!(matches!(
+                result,
+                Err(ObjectClientError::ServiceError(GetObjectError::AccessDenied))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn delete_objects() {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+        });
+
+        client.add_object("a", MockObject::constant(0u8, 5, ETag::for_tests()));
+        client.add_object("b", MockObject::constant(0u8, 5, ETag::for_tests()));
+        client.add_object("c", MockObject::constant(0u8, 5, ETag::for_tests()));
+
+        // Keys that don't exist are reported as deleted, as in S3
+        let keys = ["a".to_owned(), "c".to_owned(), "d".to_owned()];
+        let result = client
+            .delete_objects("test_bucket", &keys)
+            .await
+            .expect("delete_objects should succeed");
+        assert_eq!(result.deleted, keys);
+        assert!(result.errors.is_empty());
+        assert!(!client.contains_key("a"));
+        assert!(client.contains_key("b"));
+        assert!(!client.contains_key("c"));
+
+        let keys = vec!["b".to_owned(); MAX_DELETE_OBJECTS_KEYS + 1];
+        let result = client.delete_objects("test_bucket", &keys).await;
+        assert!(matches!(
+            result,
+            Err(ObjectClientError::ServiceError(DeleteObjectsError::TooManyKeys(_)))
+        ));
+        assert!(client.contains_key("b"));
+    }
+
+    #[tokio::test]
+    async fn head_object_expiration() {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+        });
+
+        client.add_object("key1", MockObject::constant(0u8, 5, ETag::for_tests()));
+        let expiration = ObjectExpiration {
+            expiry: OffsetDateTime::from_unix_timestamp(1356220800).unwrap(),
+            rule_id: "picture-deletion-rule".to_string(),
+        };
+        let mut object = MockObject::constant(0u8, 5, ETag::for_tests());
+        object.set_expiration(Some(expiration.clone()));
+        client.add_object("key2", object);
+
+        let result = client
+            .head_object("test_bucket", "key1", None)
+            .await
+            .expect("should not fail");
+        assert_eq!(result.object.expiration, None);
+
+        let result = client
+            .head_object("test_bucket", "key2", None)
+            .await
+            .expect("should not fail");
+        assert_eq!(result.object.expiration, Some(expiration));
+    }
+
+    #[tokio::test]
+    async fn head_object_cache_validators() {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+        });
+
+        let etag = ETag::from_str("etag1").unwrap();
+        client.add_object("key1", MockObject::constant(0u8, 5, etag.clone()));
+
+        let validators = client
+            .head_object("test_bucket", "key1", None)
+            .await
+            .expect("should not fail")
+            .cache_validators();
+        assert_eq!(validators.etag, etag);
+
+        let params = GetObjectParams::new()
+            .if_match(Some(validators.etag.clone()))
+            .if_modified_since(Some(validators.last_modified));
+        assert!(matches!(
+            client.get_object("test_bucket", "key1", &params).await,
+            Err(ObjectClientError::ServiceError(GetObjectError::NotModified))
+        ));
+
+        // Once the object changes, the cached validators no longer match
+        client.add_object("key1", MockObject::constant(1u8, 5, ETag::from_str("etag2").unwrap()));
+        assert!(matches!(
+            client.get_object("test_bucket", "key1", &params).await,
+            Err(ObjectClientError::ServiceError(GetObjectError::PreconditionFailed))
+        ));
+    }
+
+    #[test_case("test-bucket--usw2-az1--x-s3", ExpressMode::Automatic, 1; "directory bucket")]
+    #[test_case("test_bucket", ExpressMode::Automatic, 0; "general purpose bucket")]
+    #[test_case("test_bucket", ExpressMode::On, 1; "express mode on")]
+    #[test_case("test-bucket--usw2-az1--x-s3", ExpressMode::Off, 0; "express mode off")]
+    #[tokio::test]
+    async fn express_sessions(bucket: &str, express_mode: ExpressMode, expected_sessions: u64) {
+        let mut client = MockClient::new(MockClientConfig {
+            bucket: bucket.to_string(),
+            part_size: 1024,
+        });
+        client.set_express_mode(express_mode);
+        client.add_object("key1", MockObject::constant(0u8, 5, ETag::for_tests()));
+
+        // Sessions are reused across requests
+        client.head_object(bucket, "key1", None).await.expect("should not fail");
+        client
+            .list_objects(bucket, None, "/", 10, "")
+            .await
+            .expect("should not fail");
+        assert_eq!(client.sessions_created(), expected_sessions);
+
+        client.expire_session();
+        client.head_object(bucket, "key1", None).await.expect("should not fail");
+        assert_eq!(client.sessions_created(), expected_sessions * 2);
+    }
+
+    #[tokio::test]
+    async fn object_versions() {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+        });
+
+        let first = client
+            .put_object(
+                "test_bucket",
+                "key1",
+                &Default::default(),
+                futures::stream::once(async { b"first" }),
+            )
+            .await
+            .expect("put_object failed")
+            .version_id
+            .expect("mock client should return a version id");
+        let second = client
+            .put_object(
+                "test_bucket",
+                "key1",
+                &Default::default(),
+                futures::stream::once(async { b"second" }),
+            )
+            .await
+            .expect("put_object failed")
+            .version_id
+            .expect("mock client should return a version id");
+        assert_ne!(first, second);
+
+        for (version_id, expected) in [
+            (None, &b"second"[..]),
+            (Some(&first), &b"first"[..]),
+            (Some(&second), &b"second"[..]),
+        ] {
+            let body = client
+                .get_object(
+                    "test_bucket",
+                    "key1",
+                    &GetObjectParams::new().version_id(version_id.map(String::as_str)),
+                )
+                .await
+                .expect("get_object failed")
+                .collect()
+                .await
+                .expect("get_object body failed");
+            assert_eq!(&body[..], expected);
+        }
+
+        let head = client
+            .head_object("test_bucket", "key1", Some(&first))
+            .await
+            .expect("head_object failed");
+        assert_eq!(head.object.version_id.as_deref(), Some(first.as_str()));
+        assert_eq!(head.object.size, 5);
+
+        assert!(matches!(
+            client
+                .get_object(
+                    "test_bucket",
+                    "key1",
+                    &GetObjectParams::new().version_id(Some("not-a-version")),
+                )
+                .await,
+            Err(ObjectClientError::ServiceError(GetObjectError::NoSuchKey))
+        ));
+
+        // Deleting the current version makes the previous one current again
+        client
+            .delete_object("test_bucket", "key1", Some(&second))
+            .await
+            .expect("delete_object failed");
+        let head = client
+            .head_object("test_bucket", "key1", None)
+            .await
+            .expect("head_object failed");
+        assert_eq!(head.object.version_id.as_deref(), Some(first.as_str()));
+    }
+
+    #[tokio::test]
+    async fn list_and_abort_multipart_uploads() {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+        });
+
+        let initiated = OffsetDateTime::now_utc();
+        let first = client.create_multipart_upload("dir/b", initiated);
+        let second = client.create_multipart_upload("dir/a", initiated);
+        client.create_multipart_upload("other", initiated);
+
+        let result = client.list_multipart_uploads("test_bucket", "dir/").await.unwrap();
+        let uploads: Vec<_> = result.uploads.iter().map(|upload| upload.upload_id.as_str()).collect();
+        assert_eq!(uploads, [second.as_str(), first.as_str()]);
+
+        client
+            .abort_multipart_upload("test_bucket", "dir/b", &first)
+            .await
+            .unwrap();
+        assert!(!client.has_multipart_upload(&first));
+        let result = client.abort_multipart_upload("test_bucket", "dir/b", &first).await;
+        assert!(matches!(
+            result,
+            Err(ObjectClientError::ServiceError(AbortMultipartUploadError::NoSuchUpload))
+        ));
+    }
+
+    #[tokio::test]
+    async fn list_buckets() {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+        });
+        client.add_object("a", MockObject::constant(0u8, 5, ETag::for_tests()));
+        client.add_bucket("other_bucket", OffsetDateTime::UNIX_EPOCH);
+
+        let result = client.list_buckets().await.unwrap();
+        let names: Vec<_> = result.buckets.iter().map(|bucket| bucket.name.as_str()).collect();
+        assert_eq!(names, ["other_bucket", "test_bucket"]);
+        assert_eq!(result.buckets[0].creation_date, OffsetDateTime::UNIX_EPOCH);
+        assert_eq!(result.owner, None);
+
+        let owner = BucketOwner {
+            id: "bcaf1ffd86f41161ca5fb16fd081034f".to_string(),
+            display_name: Some("owner".to_string()),
+        };
+        client.set_bucket_owner(Some(owner.clone()));
+        let result = client.list_buckets().await.unwrap();
+        assert_eq!(result.owner, Some(owner));
+
+        // Other buckets are empty
+        let result = client.list_objects("other_bucket", None, "/", 10, "").await.unwrap();
+        assert!(result.objects.is_empty());
+        let result = client.list_objects("missing_bucket", None, "/", 10, "").await;
+        assert!(matches!(
+            result,
+            Err(ObjectClientError::ServiceError(ListObjectsError::NoSuchBucket))
+        ));
+    }
+
+    #[tokio::test]
+    async fn list_object_versions() {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+        });
+
+        client.add_object("a", MockObject::constant(0u8, 5, ETag::for_tests()));
+        client.add_object("key1", MockObject::constant(0u8, 5, ETag::for_tests()));
+        client.add_object("key1", MockObject::constant(1u8, 10, ETag::for_tests()));
+        let delete = client
+            .delete_object("test_bucket", "key1", None)
+            .await
+            .expect("delete_object failed");
+        let marker_version_id = delete.version_id.expect("delete should create a delete marker");
+        assert!(!client.contains_key("key1"));
+
+        let result = client
+            .list_object_versions("test_bucket", "key", None, None, 1000)
+            .await
+            .expect("list_object_versions failed");
+        assert!(result.next_key_marker.is_none());
+        assert!(result.next_version_id_marker.is_none());
+        assert_eq!(
+            result
+                .versions
+                .iter()
+                .map(|v| (v.key.as_str(), v.is_latest, v.is_delete_marker, v.size))
+                .collect::<Vec<_>>(),
+            vec![
+                ("key1", true, true, 0),
+                ("key1", false, false, 10),
+                ("key1", false, false, 5)
+            ]
+        );
+        assert_eq!(result.versions[0].version_id, marker_version_id);
+        assert!(result.versions[0].etag.is_none());
+
+        // Paginate one entry at a time and make sure we see the same versions
+        let mut paginated = vec![];
+        let mut key_marker = None;
+        let mut version_id_marker = None;
+        loop {
+            let result = client
+                .list_object_versions(
+                    "test_bucket",
+                    "key",
+                    key_marker.as_deref(),
+                    version_id_marker.as_deref(),
+                    1,
+                )
+                .await
+                .expect("list_object_versions failed");
+            paginated.extend(result.versions.into_iter().map(|v| v.version_id));
+            if result.next_key_marker.is_none() {
+                break;
+            }
+            key_marker = result.next_key_marker;
+            version_id_marker = result.next_version_id_marker;
+        }
+        assert_eq!(paginated.len(), 3);
+        assert_eq!(paginated[0], marker_version_id);
+
+        // Removing the delete marker restores the latest version
+        client
+            .delete_object("test_bucket", "key1", Some(&marker_version_id))
+            .await
+            .expect("delete_object failed");
+        let head = client
+            .head_object("test_bucket", "key1", None)
+            .await
+            .expect("head_object failed");
+        assert_eq!(head.object.size, 10);
+    }
+
+    #[tokio::test]
+    async fn test_from_manifest() {
+        let manifest = r#"
+            # A small bucket with a bit of everything
+            README.md 1024
+            data/2023/01/part-0000.parquet 64MiB
+            data/2023/01/part-0001.parquet 64MiB
+            data/2023/01/part-0002.parquet 12MiB
+            data/2023/01/_SUCCESS 0
+            data/2023/02/part-0000.parquet 64MiB
+            data/2023/02/part-0001.parquet 3MiB
+            data/2023/02/_SUCCESS 0
+            data/2024/01/part-0000.parquet 5GiB
+            data/2024/01/_SUCCESS 0
+            logs/app.log 10KiB "abc123"
+            logs/app.log.1 100KiB "def456" STANDARD_IA
+            logs/app.log.2 100KiB "0a1b2c" GLACIER
+
+            archive/2019.tar 2GiB "e7f8" DEEP_ARCHIVE
+            archive/2020.tar 2GiB "e7f9" DEEP_ARCHIVE
+            images/a.png 300KiB
+            images/b.png 250KiB
+            images/thumbs/a.png 8KiB
+            images/thumbs/b.png 7KiB
+            empty-dir/ 0
+        "#;
+        let client = MockClient::from_manifest(
+            MockClientConfig {
+                bucket: "test_bucket".to_string(),
+                part_size: 1024,
+            },
+            manifest,
+        )
+        .expect("manifest should parse");
+
+        let result = client
+            .list_objects("test_bucket", None, "/", 1000, "")
+            .await
+            .expect("list_objects failed");
+        let keys: Vec<_> = result.objects.iter().map(|object| object.key.as_str()).collect();
+        assert_eq!(keys, ["README.md"]);
+        assert_eq!(
+            result.common_prefixes,
+            ["archive/", "data/", "empty-dir/", "images/", "logs/"]
+        );
+
+        let result = client
+            .list_objects("test_bucket", None, "", 1000, "")
+            .await
+            .expect("list_objects failed");
+        assert_eq!(result.objects.len(), 20);
+        let logs: Vec<_> = result
+            .objects
+            .iter()
+            .filter(|object| object.key.starts_with("logs/"))
+            .map(|object| (object.key.as_str(), object.size, object.etag.as_str()))
+            .collect();
+        assert_eq!(
+            logs,
+            [
+                ("logs/app.log", 10 * 1024, "\"abc123\""),
+                ("logs/app.log.1", 100 * 1024, "\"def456\""),
+                ("logs/app.log.2", 100 * 1024, "\"0a1b2c\""),
+            ]
+        );
+        for (key, storage_class) in [("logs/app.log", "STANDARD"), ("logs/app.log.2", "GLACIER")] {
+            let attributes = client
+                .get_object_attributes("test_bucket", key, None, None, &[ObjectAttribute::StorageClass])
+                .await
+                .expect("get_object_attributes failed");
+            assert_eq!(attributes.storage_class.as_deref(), Some(storage_class));
+        }
+        let huge = client
+            .head_object("test_bucket", "data/2024/01/part-0000.parquet", None)
+            .await
+            .expect("head_object failed");
+        assert_eq!(huge.object.size, 5 * 1024 * 1024 * 1024);
+
+        let error = MockClient::from_manifest(Default::default(), "ok 10\nbad ten\n").unwrap_err();
+        assert_eq!(error.line, 2);
+    }
+
     proptest::proptest! {
         #[test]
         fn test_ramp(size in 1..2*RAMP_BUFFER_SIZE, read_size in 1..2*RAMP_BUFFER_SIZE, offset in 0..RAMP_BUFFER_SIZE) {
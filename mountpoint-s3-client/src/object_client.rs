@@ -1,18 +1,102 @@
 use async_trait::async_trait;
 use auto_impl::auto_impl;
-use futures::Stream;
+use bytes::Bytes;
+use futures::future::{BoxFuture, FutureExt};
+use futures::stream::{self, BoxStream};
+use futures::{Stream, StreamExt, TryStreamExt};
+use std::collections::HashMap;
 use std::str::FromStr;
+use std::time::Duration;
 use std::{fmt, ops::Range, string::ParseError};
 use thiserror::Error;
 use time::OffsetDateTime;
 
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
 use md5::{Digest, Md5};
 
+use crate::presign::{PostPolicyCondition, PresignedPost};
+use crate::s3_crt_client::S3ErrorCode;
+
 /// A single element of the [ObjectClient::get_object] response is a pair of offset within the
 /// object and the bytes starting at that offset.
 pub type GetBodyPart = (u64, Box<[u8]>);
 
-#[derive(Debug, Clone, PartialEq)]
+/// A [GetBodyPart] whose bytes are reference-counted, so they can be sliced and passed on (e.g. to
+/// FUSE) without copying. See [GetObjectResultExt::into_shared_parts].
+pub type GetBodyPartRef = (u64, Bytes);
+
+/// Extension methods for the body stream returned by [ObjectClient::get_object]
+pub trait GetObjectResultExt<E, C>: Stream<Item = ObjectClientResult<GetBodyPart, E, C>> + Sized {
+    /// Deliver the body as [GetBodyPartRef]s. Each part's buffer is handed over to a [Bytes]
+    /// rather than copied.
+    fn into_shared_parts(self) -> SharedBodyParts<Self, E, C> {
+        self.map(into_shared_part as fn(_) -> _)
+    }
+}
+
+impl<S, E, C> GetObjectResultExt<E, C> for S where S: Stream<Item = ObjectClientResult<GetBodyPart, E, C>> {}
+
+/// Accounting for the body stream returned by [ObjectClient::get_object]
+pub trait GetObjectProgress {
+    /// Number of body bytes received for this request so far. Bytes that were received more than
+    /// once, for example because a part failed partway through and was fetched again, are counted
+    /// each time, so this can be more than the length of the range requested. Once the stream has
+    /// ended, this is the total for the request.
+    fn bytes_transferred(&self) -> u64;
+
+    /// Metadata about the whole object from the response headers, or `None` if they haven't
+    /// arrived yet. They have once the stream returns its first part or ends. A response whose
+    /// headers don't describe the object can still have its body read, so that's an error here
+    /// rather than in the stream.
+    fn object_metadata(&self) -> Option<Result<GetObjectMetadata, ObjectMetadataError>>;
+}
+
+/// Metadata about an object from the headers of a GetObject response, see
+/// [GetObjectProgress::object_metadata]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct GetObjectMetadata {
+    /// Size of the whole object, even if only a range of it was requested
+    pub object_size: u64,
+
+    /// Entity tag of the object
+    pub etag: ETag,
+
+    /// The time the object was last modified
+    pub last_modified: OffsetDateTime,
+}
+
+impl GetObjectMetadata {
+    pub fn new(object_size: u64, etag: ETag, last_modified: OffsetDateTime) -> Self {
+        Self {
+            object_size,
+            etag,
+            last_modified,
+        }
+    }
+}
+
+/// Why a GetObject response has no [GetObjectMetadata], see [GetObjectProgress::object_metadata]
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ObjectMetadataError {
+    #[error("The response had no object metadata")]
+    Missing,
+
+    #[error("The object metadata in the response headers was invalid: {0}")]
+    Invalid(String),
+}
+
+/// Stream returned by [GetObjectResultExt::into_shared_parts]
+pub type SharedBodyParts<S, E, C> =
+    stream::Map<S, fn(ObjectClientResult<GetBodyPart, E, C>) -> ObjectClientResult<GetBodyPartRef, E, C>>;
+
+fn into_shared_part<E, C>(part: ObjectClientResult<GetBodyPart, E, C>) -> ObjectClientResult<GetBodyPartRef, E, C> {
+    part.map(|(offset, body)| (offset, Bytes::from(body)))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ETag {
     etag: String,
 }
@@ -53,26 +137,42 @@ impl FromStr for ETag {
 #[async_trait]
 #[auto_impl(Arc)]
 pub trait ObjectClient {
-    type GetObjectResult: Stream<Item = ObjectClientResult<GetBodyPart, GetObjectError, Self::ClientError>> + Send;
+    type GetObjectResult: Stream<Item = ObjectClientResult<GetBodyPart, GetObjectError, Self::ClientError>>
+        + GetObjectProgress
+        + Send;
     type ClientError: std::error::Error + Send + Sync + 'static;
 
     /// Delete a single object from the object store.
     ///
-    /// DeleteObject will succeed even if the object within the bucket does not exist.
+    /// DeleteObject will succeed even if the object within the bucket does not exist. If a
+    /// `version_id` is given, only that version of the object is deleted.
     async fn delete_object(
         &self,
         bucket: &str,
         key: &str,
+        version_id: Option<&str>,
     ) -> ObjectClientResult<DeleteObjectResult, DeleteObjectError, Self::ClientError>;
 
+    /// Delete a batch of objects from the object store with a single request. At most
+    /// [MAX_DELETE_OBJECTS_KEYS] keys can be deleted at once.
+    ///
+    /// As with [ObjectClient::delete_object], keys that don't exist are reported as deleted. The
+    /// request can succeed even if some of the keys couldn't be deleted; these are reported in
+    /// [DeleteObjectsResult::errors].
+    async fn delete_objects(
+        &self,
+        bucket: &str,
+        keys: &[String],
+    ) -> ObjectClientResult<DeleteObjectsResult, DeleteObjectsError, Self::ClientError>;
+
     /// Get an object from the object store. Returns a stream of body parts of the object. Parts are
-    /// guaranteed to be returned by the stream in order and contiguously.
+    /// guaranteed to be returned by the stream in order and contiguously. Optional parameters, like
+    /// the range to fetch or the version of the object, are given in the [GetObjectParams].
     async fn get_object(
         &self,
         bucket: &str,
         key: &str,
-        range: Option<Range<u64>>,
-        if_match: Option<ETag>,
+        params: &GetObjectParams,
     ) -> ObjectClientResult<Self::GetObjectResult, GetObjectError, Self::ClientError>;
 
     /// List the objects in a bucket under a given prefix
@@ -85,11 +185,25 @@ pub trait ObjectClient {
         prefix: &str,
     ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, Self::ClientError>;
 
-    /// Retrieve object metadata without retrieving the object contents
+    /// List the versions of objects (including delete markers) in a bucket under a given prefix.
+    /// Versions are returned in key order, and newest first for each key. To continue a truncated
+    /// listing, pass the `next_key_marker` and `next_version_id_marker` of the previous result.
+    async fn list_object_versions(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        key_marker: Option<&str>,
+        version_id_marker: Option<&str>,
+        max_keys: usize,
+    ) -> ObjectClientResult<ListObjectVersionsResult, ListObjectVersionsError, Self::ClientError>;
+
+    /// Retrieve object metadata without retrieving the object contents. If a `version_id` is
+    /// given, the metadata of that version of the object is returned.
     async fn head_object(
         &self,
         bucket: &str,
         key: &str,
+        version_id: Option<&str>,
     ) -> ObjectClientResult<HeadObjectResult, HeadObjectError, Self::ClientError>;
 
     /// Put an object into the object store.
@@ -102,6 +216,46 @@ pub trait ObjectClient {
         contents: impl Stream<Item = impl AsRef<[u8]> + Send> + Send,
     ) -> ObjectClientResult<PutObjectResult, PutObjectError, Self::ClientError>;
 
+    /// Copy an object to a new key without transferring its contents through the client. The
+    /// destination object is replaced if it already exists.
+    async fn copy_object(
+        &self,
+        source_bucket: &str,
+        source_key: &str,
+        destination_bucket: &str,
+        destination_key: &str,
+    ) -> ObjectClientResult<CopyObjectResult, CopyObjectError, Self::ClientError>;
+
+    /// Replace the access control list of an object with a canned ACL. If a `version_id` is given,
+    /// only that version of the object is changed.
+    async fn put_object_acl(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        acl: ObjectCannedAcl,
+    ) -> ObjectClientResult<PutObjectAclResult, PutObjectAclError, Self::ClientError>;
+
+    /// List the multipart uploads in a bucket under a given prefix that have been created but not
+    /// yet completed or aborted, following continuations until every upload has been listed.
+    async fn list_multipart_uploads(
+        &self,
+        bucket: &str,
+        prefix: &str,
+    ) -> ObjectClientResult<ListMultipartUploadsResult, ListMultipartUploadsError, Self::ClientError>;
+
+    /// Abort a multipart upload, freeing the storage used by any parts already uploaded to it
+    async fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> ObjectClientResult<AbortMultipartUploadResult, AbortMultipartUploadError, Self::ClientError>;
+
+    /// List the buckets owned by the account that the client's credentials belong to, and the
+    /// account itself
+    async fn list_buckets(&self) -> ObjectClientResult<ListBucketsResult, ListBucketsError, Self::ClientError>;
+
     /// Retrieves all the metadata from an object without returning the object contents.
     async fn get_object_attributes(
         &self,
@@ -111,8 +265,249 @@ pub trait ObjectClient {
         part_number_marker: Option<usize>,
         object_attributes: &[ObjectAttribute],
     ) -> ObjectClientResult<GetObjectAttributesResult, GetObjectAttributesError, Self::ClientError>;
+
+    /// Create a presigned POST policy that allows anyone holding it to upload objects with keys
+    /// starting with `key_prefix`, subject to the given additional conditions, until `expires_in`
+    /// has passed. No request is made to S3.
+    async fn presign_post_object(
+        &self,
+        bucket: &str,
+        key_prefix: &str,
+        conditions: &[PostPolicyCondition],
+        expires_in: Duration,
+    ) -> ObjectClientResult<PresignedPost, PresignPostError, Self::ClientError>;
 }
 
+/// Helpers built on top of the requests of an [ObjectClient], available for every client
+pub trait ObjectClientExt: ObjectClient {
+    /// Recursively list every object whose key starts with `prefix`, in key order, fetching one
+    /// page of results at a time as the stream is consumed. If a request fails, the error is the
+    /// last item of the stream.
+    fn walk<'a>(
+        &'a self,
+        bucket: &'a str,
+        prefix: &'a str,
+    ) -> BoxStream<'a, ObjectClientResult<ObjectInfo, ListObjectsError, Self::ClientError>>
+    where
+        Self: Sync,
+    {
+        struct WalkState {
+            page: std::vec::IntoIter<ObjectInfo>,
+            continuation_token: Option<String>,
+            finished: bool,
+        }
+
+        let state = WalkState {
+            page: Vec::new().into_iter(),
+            continuation_token: None,
+            finished: false,
+        };
+        stream::unfold(state, move |mut state| async move {
+            loop {
+                if let Some(object) = state.page.next() {
+                    return Some((Ok(object), state));
+                }
+                if state.finished {
+                    return None;
+                }
+                // With no delimiter, the listing includes objects under every nested prefix
+                let result = self
+                    .list_objects(bucket, state.continuation_token.as_deref(), "", WALK_PAGE_SIZE, prefix)
+                    .await;
+                match result {
+                    Ok(result) => {
+                        state.page = result.objects.into_iter();
+                        state.finished = result.next_continuation_token.is_none();
+                        state.continuation_token = result.next_continuation_token;
+                    }
+                    Err(e) => {
+                        state.finished = true;
+                        return Some((Err(e), state));
+                    }
+                }
+            }
+        })
+        .boxed()
+    }
+
+    /// List every object whose key starts with `prefix` like [ObjectClientExt::walk], but split
+    /// the listing into shards that are listed concurrently, with at most `shards` requests in
+    /// flight at once. The keyspace is split by the common prefixes (with delimiter `/`) directly
+    /// under `prefix`, so this is only faster than a walk if objects are spread across several of
+    /// them. Returns every object in key order.
+    fn list_parallel<'a>(
+        &'a self,
+        bucket: &'a str,
+        prefix: &'a str,
+        shards: usize,
+    ) -> BoxFuture<'a, ObjectClientResult<Vec<ObjectInfo>, ListObjectsError, Self::ClientError>>
+    where
+        Self: Sync,
+    {
+        assert!(shards > 0, "must list with at least one shard");
+        async move {
+            // First find the objects and common prefixes directly under the prefix
+            let mut objects = Vec::new();
+            let mut common_prefixes = Vec::new();
+            let mut continuation_token = None;
+            loop {
+                let result = self
+                    .list_objects(bucket, continuation_token.as_deref(), "/", WALK_PAGE_SIZE, prefix)
+                    .await?;
+                objects.extend(result.objects);
+                common_prefixes.extend(result.common_prefixes);
+                continuation_token = result.next_continuation_token;
+                if continuation_token.is_none() {
+                    break;
+                }
+            }
+            // A page can end inside a common prefix, which the next page then starts with again
+            common_prefixes.sort_unstable();
+            common_prefixes.dedup();
+
+            // Then walk each common prefix as its own shard
+            let mut shard_results = stream::iter(common_prefixes.iter())
+                .map(|common_prefix| self.walk(bucket, common_prefix).try_collect::<Vec<_>>())
+                .buffer_unordered(shards);
+            while let Some(shard) = shard_results.next().await {
+                objects.extend(shard?);
+            }
+
+            objects.sort_unstable_by(|a, b| a.key.cmp(&b.key));
+            Ok(objects)
+        }
+        .boxed()
+    }
+
+    /// List just the common prefixes directly under `prefix` when split by `delimiter`, like the
+    /// subdirectories of a directory, fetching every page. Objects are ignored. Returns each
+    /// prefix once, in key order.
+    fn list_common_prefixes<'a>(
+        &'a self,
+        bucket: &'a str,
+        prefix: &'a str,
+        delimiter: &'a str,
+    ) -> BoxFuture<'a, ObjectClientResult<Vec<String>, ListObjectsError, Self::ClientError>>
+    where
+        Self: Sync,
+    {
+        async move {
+            let mut common_prefixes = Vec::new();
+            let mut continuation_token = None;
+            loop {
+                let result = self
+                    .list_objects(bucket, continuation_token.as_deref(), delimiter, WALK_PAGE_SIZE, prefix)
+                    .await?;
+                common_prefixes.extend(result.common_prefixes);
+                continuation_token = result.next_continuation_token;
+                if continuation_token.is_none() {
+                    break;
+                }
+            }
+            // A page can end inside a common prefix, which the next page then starts with again
+            common_prefixes.sort_unstable();
+            common_prefixes.dedup();
+            Ok(common_prefixes)
+        }
+        .boxed()
+    }
+
+    /// Get an object's metadata with a GetObject request for its first byte, for when HeadObject
+    /// requests are denied but GetObject requests aren't. Only the object's size, ETag, and
+    /// last-modified time are filled in; everything else HeadObject would return is left empty.
+    fn head_object_via_get<'a>(
+        &'a self,
+        bucket: &'a str,
+        key: &'a str,
+        version_id: Option<&'a str>,
+    ) -> BoxFuture<'a, ObjectClientResult<HeadObjectResult, HeadObjectViaGetError, Self::ClientError>>
+    where
+        Self: Sync,
+    {
+        async move {
+            let get_error = |e: ObjectClientError<GetObjectError, Self::ClientError>| match e {
+                ObjectClientError::ServiceError(e) => {
+                    ObjectClientError::ServiceError(HeadObjectViaGetError::GetObject(e))
+                }
+                ObjectClientError::ClientError(e) => ObjectClientError::ClientError(e),
+            };
+            let params = GetObjectParams::new().version_id(version_id);
+            // An empty object has no first byte, so S3 fails the ranged request, but then getting
+            // the whole object is just as cheap
+            let request = match self.get_object(bucket, key, &params.clone().range(Some(0..1))).await {
+                Err(ObjectClientError::ServiceError(GetObjectError::InvalidRange)) => {
+                    self.get_object(bucket, key, &params).await
+                }
+                result => result,
+            };
+            let mut request = Box::pin(request.map_err(get_error)?);
+            // The metadata comes with the response headers, so the body can be dropped unread
+            while request.object_metadata().is_none() {
+                if request.try_next().await.map_err(get_error)?.is_none() {
+                    break;
+                }
+            }
+            let metadata = request
+                .object_metadata()
+                .unwrap_or(Err(ObjectMetadataError::Missing))
+                .map_err(|e| ObjectClientError::ServiceError(HeadObjectViaGetError::InvalidMetadata(e)))?;
+            Ok(HeadObjectResult {
+                bucket: bucket.to_owned(),
+                object: ObjectInfo {
+                    key: key.to_owned(),
+                    size: metadata.object_size,
+                    last_modified: metadata.last_modified,
+                    storage_class: None,
+                    etag: metadata.etag.as_str().to_owned(),
+                    expiration: None,
+                    version_id: version_id.map(str::to_owned),
+                },
+                bucket_key_enabled: false,
+                object_lock_mode: None,
+                object_lock_retain_until: None,
+                object_lock_legal_hold: false,
+                content_encoding: None,
+                user_metadata: HashMap::new(),
+            })
+        }
+        .boxed()
+    }
+
+    /// Fetch just the first `n` bytes of an object with a single ranged GetObject request, for
+    /// example to sniff its file type from its magic bytes. Objects shorter than `n` bytes are
+    /// returned whole.
+    fn get_object_prefix<'a>(
+        &'a self,
+        bucket: &'a str,
+        key: &'a str,
+        n: u64,
+    ) -> BoxFuture<'a, ObjectClientResult<Vec<u8>, GetObjectError, Self::ClientError>>
+    where
+        Self: Sync,
+    {
+        async move {
+            if n == 0 {
+                return Ok(Vec::new());
+            }
+            let params = GetObjectParams::new().range(Some(0..n));
+            let request = self.get_object(bucket, key, &params).await?;
+            let mut request = Box::pin(request);
+            let mut body = Vec::with_capacity(n as usize);
+            while let Some((offset, part)) = request.try_next().await? {
+                debug_assert_eq!(offset, body.len() as u64, "parts should arrive in order");
+                body.extend_from_slice(&part);
+            }
+            Ok(body)
+        }
+        .boxed()
+    }
+}
+
+impl<Client: ObjectClient + ?Sized> ObjectClientExt for Client {}
+
+/// Number of keys to request per ListObjectsV2 call when walking a prefix, the most S3 returns
+const WALK_PAGE_SIZE: usize = 1000;
+
 /// Errors returned by calls to an [ObjectClient]. Errors that are explicitly modeled on a
 /// per-request-type basis are [ServiceError]s. Other generic or unhandled errors are
 /// [ClientError]s.
@@ -148,6 +543,144 @@ pub enum GetObjectError {
 
     #[error("At least one of the preconditions specified did not hold")]
     PreconditionFailed,
+
+    #[error("The object has not been modified since the specified time")]
+    NotModified,
+
+    #[error("Access denied")]
+    AccessDenied,
+
+    #[error("The requested range is not satisfiable")]
+    InvalidRange,
+}
+
+/// Optional parameters to a [ObjectClient::get_object] request. Construct with
+/// [GetObjectParams::new] and the builder methods, e.g.
+/// `GetObjectParams::new().range(Some(0..1024)).if_match(Some(etag))`.
+#[derive(Debug, Default, Clone)]
+#[non_exhaustive]
+pub struct GetObjectParams {
+    /// The byte range of the object to fetch. The whole object is fetched if not given.
+    pub range: Option<Range<u64>>,
+
+    /// Return the object only if its ETag matches, otherwise fail with
+    /// [GetObjectError::PreconditionFailed].
+    pub if_match: Option<ETag>,
+
+    /// The version of the object to fetch. The current version is fetched if not given.
+    pub version_id: Option<String>,
+
+    /// Return the object only if it has been modified since this time, otherwise fail with
+    /// [GetObjectError::NotModified].
+    pub if_modified_since: Option<OffsetDateTime>,
+
+    /// Return the object only if it has not been modified since this time, otherwise fail with
+    /// [GetObjectError::PreconditionFailed]. Ignored if an `if_match` ETag was also provided and it
+    /// matches the object, as in S3.
+    pub if_unmodified_since: Option<OffsetDateTime>,
+
+    /// The key the object was encrypted with, if it was uploaded with SSE-C
+    pub sse_customer_key: Option<SseCustomerKey>,
+}
+
+impl GetObjectParams {
+    /// Create parameters for fetching the whole current version of an object, unconditionally
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the byte range of the object to fetch
+    pub fn range(mut self, range: Option<Range<u64>>) -> Self {
+        self.range = range;
+        self
+    }
+
+    /// Set the ETag the object must match
+    pub fn if_match(mut self, if_match: Option<ETag>) -> Self {
+        self.if_match = if_match;
+        self
+    }
+
+    /// Set the version of the object to fetch
+    pub fn version_id(mut self, version_id: Option<&str>) -> Self {
+        self.version_id = version_id.map(str::to_owned);
+        self
+    }
+
+    /// Set the time the object must have been modified since
+    pub fn if_modified_since(mut self, if_modified_since: Option<OffsetDateTime>) -> Self {
+        self.if_modified_since = if_modified_since;
+        self
+    }
+
+    /// Set the time the object must not have been modified since
+    pub fn if_unmodified_since(mut self, if_unmodified_since: Option<OffsetDateTime>) -> Self {
+        self.if_unmodified_since = if_unmodified_since;
+        self
+    }
+
+    /// Set the key the object was encrypted with
+    pub fn sse_customer_key(mut self, sse_customer_key: Option<SseCustomerKey>) -> Self {
+        self.sse_customer_key = sse_customer_key;
+        self
+    }
+}
+
+/// A customer-provided key for server-side encryption (SSE-C). S3 doesn't store the key, so the
+/// same key must be provided to read an object as was used to write it.
+///
+/// The [Debug] implementation only shows the key's MD5 digest, so that keys don't end up in logs.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SseCustomerKey {
+    algorithm: String,
+    key: [u8; 32],
+}
+
+impl SseCustomerKey {
+    /// Create a 256-bit AES key, the only algorithm S3 supports for SSE-C
+    pub fn aes256(key: [u8; 32]) -> Self {
+        Self::new("AES256", key)
+    }
+
+    /// Create a key for the given encryption algorithm
+    pub fn new(algorithm: impl Into<String>, key: [u8; 32]) -> Self {
+        Self {
+            algorithm: algorithm.into(),
+            key,
+        }
+    }
+
+    /// The encryption algorithm to use with this key
+    pub fn algorithm(&self) -> &str {
+        &self.algorithm
+    }
+
+    /// Base64-encoded MD5 digest of the key, which S3 uses to check the key wasn't corrupted in
+    /// transit and to identify the key an object was encrypted with
+    pub fn key_md5(&self) -> String {
+        BASE64.encode(Md5::digest(self.key))
+    }
+
+    /// The `x-amz-server-side-encryption-customer-*` request headers for this key
+    pub(crate) fn headers(&self) -> [(&'static str, String); 3] {
+        [
+            (
+                "x-amz-server-side-encryption-customer-algorithm",
+                self.algorithm.clone(),
+            ),
+            ("x-amz-server-side-encryption-customer-key", BASE64.encode(self.key)),
+            ("x-amz-server-side-encryption-customer-key-MD5", self.key_md5()),
+        ]
+    }
+}
+
+impl fmt::Debug for SseCustomerKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SseCustomerKey")
+            .field("algorithm", &self.algorithm)
+            .field("key_md5", &self.key_md5())
+            .finish()
+    }
 }
 
 /// Result of a [ObjectClient::list_objects] request
@@ -168,11 +701,167 @@ pub struct ListObjectsResult {
     pub next_continuation_token: Option<String>,
 }
 
+impl ListObjectsResult {
+    /// The objects and common prefixes together, in the order S3 would list them if they were all
+    /// keys, which is by the UTF-8 bytes of the key. S3 returns each list in that order already.
+    pub fn entries_sorted(&self) -> impl Iterator<Item = ListEntry<'_>> {
+        let mut objects = self.objects.iter().peekable();
+        let mut prefixes = self.common_prefixes.iter().peekable();
+        std::iter::from_fn(move || {
+            let take_object = match (objects.peek(), prefixes.peek()) {
+                (Some(object), Some(prefix)) => object.key.as_str() < prefix.as_str(),
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => return None,
+            };
+            if take_object {
+                objects.next().map(ListEntry::Object)
+            } else {
+                prefixes.next().map(|prefix| ListEntry::CommonPrefix(prefix))
+            }
+        })
+    }
+}
+
+/// An entry of a [ListObjectsResult], as returned by [ListObjectsResult::entries_sorted]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListEntry<'a> {
+    Object(&'a ObjectInfo),
+    CommonPrefix(&'a str),
+}
+
+impl<'a> ListEntry<'a> {
+    /// The key of the object, or the common prefix including its trailing delimiter
+    pub fn key(&self) -> &'a str {
+        match self {
+            ListEntry::Object(object) => &object.key,
+            ListEntry::CommonPrefix(prefix) => prefix,
+        }
+    }
+}
+
 #[derive(Debug, Error, PartialEq, Eq)]
 #[non_exhaustive]
 pub enum ListObjectsError {
     #[error("The bucket does not exist")]
     NoSuchBucket,
+
+    #[error("Access denied")]
+    AccessDenied,
+
+    #[error("Wrong region: should be {0}")]
+    IncorrectRegion(String),
+}
+
+/// Result of a [ObjectClient::list_object_versions] request
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ListObjectVersionsResult {
+    /// The name of the bucket.
+    pub bucket: String,
+
+    /// The list of object versions and delete markers.
+    pub versions: Vec<ObjectVersion>,
+
+    /// If the listing was truncated, the key marker to use to query more results.
+    pub next_key_marker: Option<String>,
+
+    /// If the listing was truncated, the version id marker to use to query more results.
+    pub next_version_id_marker: Option<String>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ListObjectVersionsError {
+    #[error("The bucket does not exist")]
+    NoSuchBucket,
+}
+
+/// Result of a [ObjectClient::list_multipart_uploads] request
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ListMultipartUploadsResult {
+    /// The name of the bucket.
+    pub bucket: String,
+
+    /// The uploads in progress, in key order and oldest first for each key.
+    pub uploads: Vec<MultipartUpload>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ListMultipartUploadsError {
+    #[error("The bucket does not exist")]
+    NoSuchBucket,
+}
+
+/// A multipart upload that has been created but not yet completed or aborted.
+/// See https://docs.aws.amazon.com/AmazonS3/latest/API/API_MultipartUpload.html for more details.
+#[derive(Debug, Clone)]
+pub struct MultipartUpload {
+    /// Key of the object being uploaded.
+    pub key: String,
+
+    /// Id of the upload.
+    pub upload_id: String,
+
+    /// The time the upload was created.
+    pub initiated: OffsetDateTime,
+}
+
+/// Result of a [ObjectClient::abort_multipart_upload] request
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct AbortMultipartUploadResult {}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AbortMultipartUploadError {
+    #[error("The bucket does not exist")]
+    NoSuchBucket,
+
+    #[error("The upload does not exist, or has already been completed or aborted")]
+    NoSuchUpload,
+}
+
+/// Result of a [ObjectClient::list_buckets] request
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ListBucketsResult {
+    /// The buckets owned by the account, in name order.
+    pub buckets: Vec<BucketInfo>,
+
+    /// The account that owns the buckets, if known.
+    pub owner: Option<BucketOwner>,
+}
+
+/// The owner of the buckets returned by a [ObjectClient::list_buckets] request.
+/// See https://docs.aws.amazon.com/AmazonS3/latest/API/API_Owner.html for more details.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BucketOwner {
+    /// Canonical user id of the owner.
+    pub id: String,
+
+    /// Display name of the owner. Only returned in some regions.
+    pub display_name: Option<String>,
+}
+
+/// A bucket returned by a [ObjectClient::list_buckets] request.
+/// See https://docs.aws.amazon.com/AmazonS3/latest/API/API_Bucket.html for more details.
+#[derive(Debug, Clone)]
+pub struct BucketInfo {
+    /// Name of the bucket.
+    pub name: String,
+
+    /// The time the bucket was created.
+    pub creation_date: OffsetDateTime,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ListBucketsError {
+    #[error("Access denied")]
+    AccessDenied,
 }
 
 /// Result of a [ObjectClient::head_object] request
@@ -184,6 +873,43 @@ pub struct HeadObjectResult {
 
     /// Object metadata
     pub object: ObjectInfo,
+
+    /// Whether the object is encrypted with SSE-KMS using an S3 Bucket Key
+    pub bucket_key_enabled: bool,
+
+    /// Object Lock retention mode of the object, if it has one
+    pub object_lock_mode: Option<ObjectLockMode>,
+
+    /// Time until which the object is protected by its retention mode
+    pub object_lock_retain_until: Option<OffsetDateTime>,
+
+    /// Whether the object has an Object Lock legal hold
+    pub object_lock_legal_hold: bool,
+
+    /// The `Content-Encoding` the object was stored with, if any, like `gzip`
+    pub content_encoding: Option<String>,
+
+    /// User-defined metadata stored with the object (`x-amz-meta-*` headers), keyed by lower-case
+    /// name without the `x-amz-meta-` prefix
+    pub user_metadata: HashMap<String, String>,
+}
+
+impl HeadObjectResult {
+    /// The validators to use for conditional requests against this object
+    pub fn cache_validators(&self) -> CacheValidators {
+        self.object.cache_validators()
+    }
+}
+
+/// Validators for a cached copy of an object, which can be used to make conditional requests that
+/// succeed only if the object has (or has not) changed since it was cached.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheValidators {
+    /// Entity tag of the object
+    pub etag: ETag,
+
+    /// The time the object was last modified
+    pub last_modified: OffsetDateTime,
 }
 
 #[derive(Debug, Error, PartialEq, Eq)]
@@ -192,22 +918,78 @@ pub enum HeadObjectError {
     /// Note that HeadObject cannot distinguish between NoSuchBucket and NoSuchKey errors
     #[error("The object was not found")]
     NotFound,
+
+    /// HeadObject responses have no body, so this can't say why access was denied
+    #[error("Access denied")]
+    AccessDenied,
+}
+
+/// Errors returned by [ObjectClient::head_object_via_get]
+#[derive(Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HeadObjectViaGetError {
+    #[error("GetObject failed")]
+    GetObject(#[source] GetObjectError),
+
+    #[error("GetObject response didn't describe the object")]
+    InvalidMetadata(#[source] ObjectMetadataError),
 }
 
 /// Result of a [ObjectClient::delete_object] request
 ///
 /// Note: DeleteObject calls on a non-existent object within a bucket are considered a success.
 ///
-/// TODO: Populate this struct with return fields from the S3 API, e.g., version id, delete marker.
+/// TODO: Populate this struct with return fields from the S3 API, e.g., delete marker.
 #[derive(Debug)]
 #[non_exhaustive]
-pub struct DeleteObjectResult {}
+pub struct DeleteObjectResult {
+    /// The version of the object that was deleted, if a version was requested or the bucket is
+    /// versioned.
+    pub version_id: Option<String>,
+}
 
 #[derive(Debug, Error, PartialEq, Eq)]
 #[non_exhaustive]
 pub enum DeleteObjectError {
     #[error("The bucket does not exist")]
     NoSuchBucket,
+
+    #[error("The object version is protected by Object Lock")]
+    ObjectLocked,
+}
+
+/// Maximum number of keys a single [ObjectClient::delete_objects] request can delete
+pub const MAX_DELETE_OBJECTS_KEYS: usize = 1000;
+
+/// Result of a [ObjectClient::delete_objects] request
+#[derive(Debug, Default)]
+#[non_exhaustive]
+pub struct DeleteObjectsResult {
+    /// Keys that were deleted
+    pub deleted: Vec<String>,
+    /// Keys that couldn't be deleted
+    pub errors: Vec<DeleteObjectsFailure>,
+}
+
+/// A key that a [ObjectClient::delete_objects] request failed to delete
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeleteObjectsFailure {
+    /// The key that wasn't deleted
+    pub key: String,
+    /// S3 error code, e.g. `AccessDenied`
+    pub code: String,
+    /// Description of the error
+    pub message: String,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DeleteObjectsError {
+    #[error("The bucket does not exist")]
+    NoSuchBucket,
+
+    #[error("Too many keys in one request: {0}")]
+    TooManyKeys(usize),
 }
 
 /// Result of a [ObjectClient::get_object_attributes] request
@@ -241,21 +1023,350 @@ pub enum GetObjectAttributesError {
 
 /// Parameters to a [ObjectClient::put_object] request
 /// TODO: Populate this struct with parameters from the S3 API, e.g., storage class, encryption.
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
+#[non_exhaustive]
+pub struct PutObjectParams {
+    /// How to upload the object
+    pub upload_mode: UploadMode,
+    /// Whether to use an S3 Bucket Key for SSE-KMS encryption of the object. If unset, the bucket's
+    /// default applies.
+    pub bucket_key_enabled: Option<bool>,
+    /// Encrypt the object with this customer-provided key (SSE-C). The same key must then be given
+    /// to read the object.
+    pub sse_customer_key: Option<SseCustomerKey>,
+    /// Storage class for the object, e.g. `STANDARD_IA`. If unset, S3 uses `STANDARD`.
+    pub storage_class: Option<String>,
+    /// Canned ACL to apply to the object. If unset, S3 uses [ObjectCannedAcl::Private].
+    pub acl: Option<ObjectCannedAcl>,
+    /// Object Lock retention mode for the object. Must be set together with
+    /// [object_lock_retain_until](Self::object_lock_retain_until).
+    pub object_lock_mode: Option<ObjectLockMode>,
+    /// Time until which the object is protected by its Object Lock retention mode
+    pub object_lock_retain_until: Option<OffsetDateTime>,
+    /// Whether to place an Object Lock legal hold on the object
+    pub object_lock_legal_hold: Option<bool>,
+    /// How big the object is expected to be, if that's known before its contents are. A client
+    /// that picks a part size before it has the whole object uses this to pick one that fits the
+    /// object in [MAX_UPLOAD_PARTS] parts from the start (see [upload_part_size]). The CRT client
+    /// only needs this when it streams uploads (see
+    /// [S3ClientConfig::upload_buffer_size](crate::S3ClientConfig::upload_buffer_size)).
+    pub object_size_hint: Option<u64>,
+    /// Send the MD5 digest of the body as `Content-MD5`, so that S3 rejects the upload with
+    /// [PutObjectError::BadDigest] if the body was corrupted in transit. Multipart uploads send
+    /// the digest of each part with that part instead.
+    pub compute_content_md5: bool,
+}
+
+impl PutObjectParams {
+    /// Create a default [PutObjectParams].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how to upload the object.
+    pub fn upload_mode(mut self, value: UploadMode) -> Self {
+        self.upload_mode = value;
+        self
+    }
+
+    /// Set whether to use an S3 Bucket Key for SSE-KMS encryption of the object.
+    pub fn bucket_key_enabled(mut self, value: Option<bool>) -> Self {
+        self.bucket_key_enabled = value;
+        self
+    }
+
+    /// Set the customer-provided key to encrypt the object with.
+    pub fn sse_customer_key(mut self, value: Option<SseCustomerKey>) -> Self {
+        self.sse_customer_key = value;
+        self
+    }
+
+    /// Set the storage class for the object.
+    pub fn storage_class(mut self, value: Option<String>) -> Self {
+        self.storage_class = value;
+        self
+    }
+
+    /// Set the canned ACL to apply to the object.
+    pub fn acl(mut self, value: Option<ObjectCannedAcl>) -> Self {
+        self.acl = value;
+        self
+    }
+
+    /// Set the Object Lock retention mode for the object.
+    pub fn object_lock_mode(mut self, value: Option<ObjectLockMode>) -> Self {
+        self.object_lock_mode = value;
+        self
+    }
+
+    /// Set the time until which the object is protected by its retention mode.
+    pub fn object_lock_retain_until(mut self, value: Option<OffsetDateTime>) -> Self {
+        self.object_lock_retain_until = value;
+        self
+    }
+
+    /// Set whether to place a legal hold on the object.
+    pub fn object_lock_legal_hold(mut self, value: Option<bool>) -> Self {
+        self.object_lock_legal_hold = value;
+        self
+    }
+
+    /// Set how big the object is expected to be.
+    pub fn object_size_hint(mut self, value: Option<u64>) -> Self {
+        self.object_size_hint = value;
+        self
+    }
+
+    /// Set whether to send the MD5 digest of the body as `Content-MD5`.
+    pub fn compute_content_md5(mut self, value: bool) -> Self {
+        self.compute_content_md5 = value;
+        self
+    }
+}
+
+/// An Object Lock retention mode, which prevents an object version from being deleted or
+/// overwritten until its retention period ends.
+/// See https://docs.aws.amazon.com/AmazonS3/latest/userguide/object-lock.html for more details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectLockMode {
+    /// Users with the `s3:BypassGovernanceRetention` permission can still delete the object
+    Governance,
+    /// No user, including the root user, can delete the object
+    Compliance,
+}
+
+impl ObjectLockMode {
+    /// The value of the `x-amz-object-lock-mode` header for this mode
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ObjectLockMode::Governance => "GOVERNANCE",
+            ObjectLockMode::Compliance => "COMPLIANCE",
+        }
+    }
+}
+
+/// A predefined access control list for an object.
+/// See https://docs.aws.amazon.com/AmazonS3/latest/userguide/acl-overview.html#canned-acl for more
+/// details.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectCannedAcl {
+    /// Only the owner has access
+    #[default]
+    Private,
+    /// Everyone can read the object
+    PublicRead,
+    /// Everyone can read and write the object
+    PublicReadWrite,
+    /// Any authenticated AWS user can read the object
+    AuthenticatedRead,
+    /// Amazon EC2 can read the object to bundle AMIs
+    AwsExecRead,
+    /// The bucket owner can read the object
+    BucketOwnerRead,
+    /// The bucket owner has full control of the object, as often required for cross-account writes
+    BucketOwnerFullControl,
+}
+
+impl ObjectCannedAcl {
+    /// The value of the `x-amz-acl` header for this ACL
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ObjectCannedAcl::Private => "private",
+            ObjectCannedAcl::PublicRead => "public-read",
+            ObjectCannedAcl::PublicReadWrite => "public-read-write",
+            ObjectCannedAcl::AuthenticatedRead => "authenticated-read",
+            ObjectCannedAcl::AwsExecRead => "aws-exec-read",
+            ObjectCannedAcl::BucketOwnerRead => "bucket-owner-read",
+            ObjectCannedAcl::BucketOwnerFullControl => "bucket-owner-full-control",
+        }
+    }
+}
+
+/// The storage class of an object.
+/// See https://docs.aws.amazon.com/AmazonS3/latest/userguide/storage-class-intro.html for more
+/// details.
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[non_exhaustive]
-pub struct PutObjectParams {}
+pub enum StorageClass {
+    Standard,
+    ReducedRedundancy,
+    StandardIa,
+    OnezoneIa,
+    IntelligentTiering,
+    Glacier,
+    GlacierIr,
+    DeepArchive,
+    /// S3 Express One Zone, the only storage class of objects in directory buckets
+    ExpressOnezone,
+    /// A storage class this client doesn't know about
+    Other(String),
+}
+
+impl StorageClass {
+    /// The name S3 uses for this storage class, like `STANDARD_IA`
+    pub fn as_str(&self) -> &str {
+        match self {
+            StorageClass::Standard => "STANDARD",
+            StorageClass::ReducedRedundancy => "REDUCED_REDUNDANCY",
+            StorageClass::StandardIa => "STANDARD_IA",
+            StorageClass::OnezoneIa => "ONEZONE_IA",
+            StorageClass::IntelligentTiering => "INTELLIGENT_TIERING",
+            StorageClass::Glacier => "GLACIER",
+            StorageClass::GlacierIr => "GLACIER_IR",
+            StorageClass::DeepArchive => "DEEP_ARCHIVE",
+            StorageClass::ExpressOnezone => "EXPRESS_ONEZONE",
+            StorageClass::Other(name) => name,
+        }
+    }
+}
+
+impl From<&str> for StorageClass {
+    fn from(name: &str) -> Self {
+        match name {
+            "STANDARD" => StorageClass::Standard,
+            "REDUCED_REDUNDANCY" => StorageClass::ReducedRedundancy,
+            "STANDARD_IA" => StorageClass::StandardIa,
+            "ONEZONE_IA" => StorageClass::OnezoneIa,
+            "INTELLIGENT_TIERING" => StorageClass::IntelligentTiering,
+            "GLACIER" => StorageClass::Glacier,
+            "GLACIER_IR" => StorageClass::GlacierIr,
+            "DEEP_ARCHIVE" => StorageClass::DeepArchive,
+            "EXPRESS_ONEZONE" => StorageClass::ExpressOnezone,
+            _ => StorageClass::Other(name.to_owned()),
+        }
+    }
+}
+
+/// How a [ObjectClient::put_object] request uploads an object
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UploadMode {
+    /// Let the client decide based on the size of the object
+    #[default]
+    Automatic,
+    /// Upload the object with a single PutObject request
+    SinglePart,
+    /// Upload the object with a multipart upload. Clients may still use a single request for
+    /// objects smaller than their part size.
+    Multipart,
+}
+
+/// Maximum number of parts S3 allows in a multipart upload
+pub const MAX_UPLOAD_PARTS: usize = 10_000;
+
+/// Maximum size of a single part of a multipart upload
+pub const MAX_UPLOAD_PART_SIZE: usize = 5 * 1024 * 1024 * 1024;
+
+/// Maximum size of an object. Uploads of up to this size never need more than [MAX_UPLOAD_PARTS]
+/// parts, since [upload_part_size] grows the part size to fit.
+pub const MAX_OBJECT_SIZE: u64 = 5 * 1024 * 1024 * 1024 * 1024;
+
+/// The part size to upload an object of `object_size` bytes with in a multipart upload. This is
+/// `part_size` unless that would need more than [MAX_UPLOAD_PARTS] parts, in which case it's the
+/// smallest size that fits the object in that many parts (up to [MAX_UPLOAD_PART_SIZE]). Objects
+/// of up to `part_size * MAX_UPLOAD_PARTS` bytes are uploaded with the configured part size.
+pub fn upload_part_size(object_size: u64, part_size: usize) -> usize {
+    let min_part_size = object_size.div_ceil(MAX_UPLOAD_PARTS as u64);
+    let min_part_size = usize::try_from(min_part_size).unwrap_or(usize::MAX);
+    part_size.max(min_part_size).min(MAX_UPLOAD_PART_SIZE)
+}
+
+/// The value of a `Content-MD5` header for a request body: its base64-encoded MD5 digest
+pub fn content_md5(body: &[u8]) -> String {
+    BASE64.encode(Md5::digest(body))
+}
 
 /// Result of a [ObjectClient::put_object] request
-/// TODO: Populate this struct with return fields from the S3 API, e.g., etag.
+/// TODO: Populate this struct with more return fields from the S3 API.
 #[derive(Debug)]
 #[non_exhaustive]
-pub struct PutObjectResult {}
+pub struct PutObjectResult {
+    /// The version of the newly created object, if the bucket is versioned.
+    pub version_id: Option<String>,
+
+    /// Entity tag of the newly created object, if the client knows
+    pub etag: Option<ETag>,
+
+    /// Number of bytes uploaded as the contents of the object, if the client knows
+    pub size: Option<u64>,
+}
 
 #[derive(Debug, Error, PartialEq, Eq)]
 #[non_exhaustive]
 pub enum PutObjectError {
     #[error("The bucket does not exist")]
     NoSuchBucket,
+
+    #[error("CompleteMultipartUpload failed")]
+    CompleteMultipartUpload(#[source] CompleteMultipartUploadError),
+
+    #[error("The Content-MD5 of the body did not match what was received")]
+    BadDigest,
+}
+
+/// An error S3 returned in the body of a CompleteMultipartUpload response. S3 can send a 200 OK
+/// status before it's finished assembling the object, so a failure to do that is only reported in
+/// the body, and the upload must be treated as failed even though the status was a success.
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+#[error("{code}: {message}")]
+#[non_exhaustive]
+pub struct CompleteMultipartUploadError {
+    /// The S3 error code, like `InternalError`
+    pub code: String,
+    /// The human-readable description of the error
+    pub message: String,
+}
+
+impl CompleteMultipartUploadError {
+    pub fn new(code: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            code: code.into(),
+            message: message.into(),
+        }
+    }
+
+    /// Whether completing the upload again is likely to succeed, like after an `InternalError` or
+    /// `SlowDown`. The upload stays in progress after a failed completion, so it can be completed
+    /// again without uploading its parts again.
+    pub fn is_retryable(&self) -> bool {
+        S3ErrorCode::from(self.code.as_str()).is_transient()
+    }
+}
+
+/// Result of a [ObjectClient::copy_object] request
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct CopyObjectResult {}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CopyObjectError {
+    #[error("The source or destination bucket does not exist")]
+    NoSuchBucket,
+
+    #[error("The source key does not exist")]
+    NoSuchKey,
+}
+
+/// Result of a [ObjectClient::put_object_acl] request
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct PutObjectAclResult {}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PutObjectAclError {
+    #[error("The bucket does not exist")]
+    NoSuchBucket,
+
+    #[error("The key does not exist")]
+    NoSuchKey,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PresignPostError {
+    #[error("The client has no credentials to sign with")]
+    NoCredentials,
 }
 
 /// Metadata about a single S3 object.
@@ -278,6 +1389,139 @@ pub struct ObjectInfo {
 
     /// Entity tag of this object.
     pub etag: String,
+
+    /// When this object is scheduled to be deleted by a lifecycle rule, if any. Only populated by
+    /// [ObjectClient::head_object], as list responses do not include the expiration.
+    pub expiration: Option<ObjectExpiration>,
+
+    /// Version of this object, if the bucket is versioned. Only populated by
+    /// [ObjectClient::head_object], as ListObjectsV2 responses do not include version ids.
+    pub version_id: Option<String>,
+}
+
+impl ObjectInfo {
+    /// Whether this object is a directory marker: an empty object whose key ends in `/`, like the
+    /// S3 Console creates for new folders. Markers only say that the directory exists, so aren't
+    /// shown as files.
+    pub fn is_directory_marker(&self) -> bool {
+        self.key.ends_with('/') && self.size == 0
+    }
+
+    /// The validators to use for conditional requests against this object
+    pub fn cache_validators(&self) -> CacheValidators {
+        CacheValidators {
+            etag: ETag::from_str(&self.etag).expect("parsing an ETag is infallible"),
+            last_modified: self.last_modified,
+        }
+    }
+}
+
+/// Objects are equal if they have the same key, ETag, and size, regardless of other metadata like
+/// when they were last modified.
+impl PartialEq for ObjectInfo {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key && self.etag == other.etag && self.size == other.size
+    }
+}
+
+impl Eq for ObjectInfo {}
+
+/// Objects are ordered by key. To stay consistent with [PartialEq], objects with the same key are
+/// then ordered by ETag and size.
+impl Ord for ObjectInfo {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (&self.key, &self.etag, self.size).cmp(&(&other.key, &other.etag, other.size))
+    }
+}
+
+impl PartialOrd for ObjectInfo {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The differences between two listings of objects, as returned by [diff_listings]. Each list is
+/// ordered by key.
+#[derive(Debug, Default)]
+pub struct ListingDiff<'a> {
+    /// Objects in the new listing whose key isn't in the old listing
+    pub added: Vec<&'a ObjectInfo>,
+
+    /// Objects in the old listing whose key isn't in the new listing
+    pub removed: Vec<&'a ObjectInfo>,
+
+    /// Objects whose key is in both listings but whose ETag or size changed, as pairs of the old
+    /// and new object
+    pub modified: Vec<(&'a ObjectInfo, &'a ObjectInfo)>,
+}
+
+/// Compare two listings of objects by key. The listings don't need to be sorted, but each key
+/// should appear at most once in each of them, as in the result of a ListObjectsV2 request.
+pub fn diff_listings<'a>(old: &'a [ObjectInfo], new: &'a [ObjectInfo]) -> ListingDiff<'a> {
+    let mut old: Vec<_> = old.iter().collect();
+    old.sort_unstable();
+    let mut new: Vec<_> = new.iter().collect();
+    new.sort_unstable();
+
+    let mut diff = ListingDiff::default();
+    let mut old = old.into_iter().peekable();
+    let mut new = new.into_iter().peekable();
+    loop {
+        match (old.peek(), new.peek()) {
+            (Some(o), Some(n)) => match o.key.cmp(&n.key) {
+                std::cmp::Ordering::Less => diff.removed.push(old.next().unwrap()),
+                std::cmp::Ordering::Greater => diff.added.push(new.next().unwrap()),
+                std::cmp::Ordering::Equal => {
+                    let (o, n) = (old.next().unwrap(), new.next().unwrap());
+                    if o != n {
+                        diff.modified.push((o, n));
+                    }
+                }
+            },
+            (Some(_), None) => diff.removed.push(old.next().unwrap()),
+            (None, Some(_)) => diff.added.push(new.next().unwrap()),
+            (None, None) => break,
+        }
+    }
+    diff
+}
+
+/// Metadata about a single version of an S3 object, or a delete marker.
+/// See https://docs.aws.amazon.com/AmazonS3/latest/API/API_ObjectVersion.html and
+/// https://docs.aws.amazon.com/AmazonS3/latest/API/API_DeleteMarkerEntry.html for more details.
+#[derive(Debug)]
+pub struct ObjectVersion {
+    /// Key for this object.
+    pub key: String,
+
+    /// Version id of this version.
+    pub version_id: String,
+
+    /// Whether this is the latest version of the object.
+    pub is_latest: bool,
+
+    /// Whether this version is a delete marker rather than an object.
+    pub is_delete_marker: bool,
+
+    /// Size of this version in bytes. Always 0 for delete markers.
+    pub size: u64,
+
+    /// The time this version was created.
+    pub last_modified: OffsetDateTime,
+
+    /// Entity tag of this version. Delete markers do not have an entity tag.
+    pub etag: Option<String>,
+}
+
+/// Lifecycle expiration of an object, as reported by the `x-amz-expiration` header.
+/// See https://docs.aws.amazon.com/AmazonS3/latest/API/API_HeadObject.html#API_HeadObject_ResponseSyntax
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectExpiration {
+    /// The time at which the object will expire.
+    pub expiry: OffsetDateTime,
+
+    /// The ID of the lifecycle rule that applies to this object.
+    pub rule_id: String,
 }
 
 /// All possible object attributes that can be retrived from [ObjectClient::get_object_attributes].
@@ -366,3 +1610,370 @@ pub struct ObjectPart {
     // Size of the part in bytes
     pub size: usize,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn storage_class_names() {
+        for name in ["STANDARD", "GLACIER_IR", "EXPRESS_ONEZONE", "SOMETHING_NEW"] {
+            assert_eq!(StorageClass::from(name).as_str(), name);
+        }
+        assert_eq!(StorageClass::from("EXPRESS_ONEZONE"), StorageClass::ExpressOnezone);
+        assert_eq!(
+            StorageClass::from("SOMETHING_NEW"),
+            StorageClass::Other("SOMETHING_NEW".to_owned())
+        );
+    }
+
+    #[test]
+    fn directory_marker() {
+        let object = |key: &str, size| ObjectInfo {
+            key: key.to_owned(),
+            size,
+            last_modified: OffsetDateTime::UNIX_EPOCH,
+            storage_class: None,
+            etag: "test_etag".to_owned(),
+            expiration: None,
+            version_id: None,
+        };
+        assert!(object("dir/", 0).is_directory_marker());
+        assert!(!object("dir/", 5).is_directory_marker());
+        assert!(!object("file", 0).is_directory_marker());
+    }
+
+    #[test]
+    fn get_object_params_default() {
+        let params = GetObjectParams::new();
+        assert_eq!(params.range, None);
+        assert_eq!(params.if_match, None);
+        assert_eq!(params.version_id, None);
+        assert_eq!(params.if_modified_since, None);
+        assert_eq!(params.if_unmodified_since, None);
+    }
+
+    #[test]
+    fn get_object_params_builder() {
+        let time = OffsetDateTime::from_unix_timestamp(1_000_000).unwrap();
+        let params = GetObjectParams::new()
+            .range(Some(10..20))
+            .if_match(Some(ETag::for_tests()))
+            .version_id(Some("version1"))
+            .if_modified_since(Some(time))
+            .if_unmodified_since(Some(time));
+        assert_eq!(params.range, Some(10..20));
+        assert_eq!(params.if_match, Some(ETag::for_tests()));
+        assert_eq!(params.version_id.as_deref(), Some("version1"));
+        assert_eq!(params.if_modified_since, Some(time));
+        assert_eq!(params.if_unmodified_since, Some(time));
+
+        // Later calls override earlier ones, including unsetting a parameter
+        let params = params.range(Some(0..5)).if_match(None).version_id(None);
+        assert_eq!(params.range, Some(0..5));
+        assert_eq!(params.if_match, None);
+        assert_eq!(params.version_id, None);
+        assert_eq!(params.if_modified_since, Some(time));
+    }
+
+    fn object_info(key: &str, etag: &str, size: u64) -> ObjectInfo {
+        ObjectInfo {
+            key: key.to_owned(),
+            size,
+            last_modified: OffsetDateTime::UNIX_EPOCH,
+            storage_class: None,
+            etag: etag.to_owned(),
+            expiration: None,
+            version_id: None,
+        }
+    }
+
+    #[test]
+    fn object_info_equality() {
+        let object = object_info("a", "\"etag1\"", 10);
+        let mut touched = object_info("a", "\"etag1\"", 10);
+        touched.last_modified = OffsetDateTime::from_unix_timestamp(1_000_000).unwrap();
+        assert_eq!(object, touched);
+        assert_ne!(object, object_info("a", "\"etag2\"", 10));
+        assert_ne!(object, object_info("a", "\"etag1\"", 11));
+        assert!(object < object_info("b", "\"etag0\"", 0));
+    }
+
+    #[test]
+    fn list_entries_sorted() {
+        let result = ListObjectsResult {
+            bucket: "bucket".to_owned(),
+            objects: vec![
+                object_info("a", "\"etag\"", 1),
+                object_info("a-b", "\"etag\"", 1),
+                object_info("a0", "\"etag\"", 1),
+                object_info("c", "\"etag\"", 1),
+            ],
+            // `-` sorts before `/`, and `0` after it
+            common_prefixes: vec!["a/".to_owned(), "b/".to_owned(), "d/".to_owned()],
+            next_continuation_token: None,
+        };
+        let entries: Vec<_> = result.entries_sorted().collect();
+        let keys: Vec<_> = entries.iter().map(|entry| entry.key()).collect();
+        assert_eq!(keys, ["a", "a-b", "a/", "a0", "b/", "c", "d/"]);
+        assert!(matches!(entries[2], ListEntry::CommonPrefix("a/")));
+        assert!(matches!(entries[3], ListEntry::Object(object) if object.key == "a0"));
+    }
+
+    #[test]
+    fn diff_two_listings() {
+        let old = vec![
+            object_info("unchanged", "\"etag1\"", 10),
+            object_info("modified", "\"etag1\"", 10),
+            object_info("removed", "\"etag1\"", 10),
+        ];
+        let new = vec![
+            object_info("added", "\"etag1\"", 10),
+            object_info("unchanged", "\"etag1\"", 10),
+            object_info("modified", "\"etag2\"", 10),
+        ];
+
+        let diff = diff_listings(&old, &new);
+        let keys = |objects: &[&ObjectInfo]| objects.iter().map(|o| o.key.clone()).collect::<Vec<_>>();
+        assert_eq!(keys(&diff.added), ["added"]);
+        assert_eq!(keys(&diff.removed), ["removed"]);
+        assert_eq!(diff.modified.len(), 1);
+        let (old_object, new_object) = diff.modified[0];
+        assert_eq!(old_object.key, "modified");
+        assert_eq!(old_object.etag, "\"etag1\"");
+        assert_eq!(new_object.etag, "\"etag2\"");
+
+        let diff = diff_listings(&old, &old);
+        assert!(diff.added.is_empty() && diff.removed.is_empty() && diff.modified.is_empty());
+    }
+
+    #[tokio::test]
+    async fn shared_parts_are_not_copied() {
+        let parts: Vec<Box<[u8]>> = vec![vec![1u8; 100].into(), vec![2u8; 50].into()];
+        let pointers: Vec<_> = parts.iter().map(|part| part.as_ptr()).collect();
+        let body = stream::iter(parts.into_iter().scan(0, |offset, part| {
+            let item: ObjectClientResult<GetBodyPart, GetObjectError, std::io::Error> = Ok((*offset, part));
+            *offset += 100;
+            Some(item)
+        }));
+
+        let shared: Vec<_> = body.into_shared_parts().collect().await;
+        assert_eq!(shared.len(), 2);
+        for (part, pointer) in shared.into_iter().zip(pointers) {
+            let (_offset, bytes) = part.expect("part should be delivered");
+            assert_eq!(bytes.as_ptr(), pointer);
+        }
+    }
+
+    #[tokio::test]
+    async fn walk_nested_objects() {
+        use crate::mock_client::{MockClient, MockClientConfig, MockObject};
+        use std::collections::HashSet;
+
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+        });
+        // Enough objects to need several pages of results
+        let mut expected = HashSet::new();
+        for i in 0..12 {
+            for j in 0..12 {
+                for k in 0..12 {
+                    let key = format!("dir{i}/dir{j}/file{k}");
+                    client.add_object(&key, MockObject::constant(0xa1, 5, ETag::for_tests()));
+                    expected.insert(key);
+                }
+            }
+        }
+        client.add_object("dir-file", MockObject::constant(0xa1, 5, ETag::for_tests()));
+        expected.insert("dir-file".to_owned());
+        client.add_object("other", MockObject::constant(0xa1, 5, ETag::for_tests()));
+
+        let keys: Vec<_> = client
+            .walk("test_bucket", "dir")
+            .map(|object| object.expect("walk should succeed").key)
+            .collect()
+            .await;
+        // Every key is yielded exactly once
+        assert_eq!(keys.len(), expected.len());
+        assert_eq!(keys.into_iter().collect::<HashSet<_>>(), expected);
+
+        let keys: Vec<_> = client
+            .walk("test_bucket", "dir0/")
+            .map(|object| object.expect("walk should succeed").key)
+            .collect()
+            .await;
+        let expected_under_dir0: HashSet<_> = expected.iter().filter(|k| k.starts_with("dir0/")).cloned().collect();
+        assert_eq!(keys.len(), expected_under_dir0.len());
+        assert_eq!(keys.into_iter().collect::<HashSet<_>>(), expected_under_dir0);
+
+        let mut walk = client.walk("wrong_bucket", "");
+        assert!(matches!(
+            walk.next().await,
+            Some(Err(ObjectClientError::ServiceError(ListObjectsError::NoSuchBucket)))
+        ));
+        assert!(walk.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn list_parallel_matches_walk() {
+        use crate::mock_client::{MockClient, MockClientConfig, MockObject, Operation};
+
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+        });
+        for i in 0..8 {
+            for j in 0..300 {
+                let key = format!("prefix/dir{i}/nested{}/file{j}", j % 3);
+                client.add_object(&key, MockObject::constant(0xa1, 5, ETag::for_tests()));
+            }
+            let key = format!("prefix/file{i}");
+            client.add_object(&key, MockObject::constant(0xa1, 5, ETag::for_tests()));
+        }
+        client.add_object("prefixed", MockObject::constant(0xa1, 5, ETag::for_tests()));
+
+        let serial: Vec<_> = client
+            .walk("test_bucket", "prefix/")
+            .map(|object| object.expect("walk should succeed").key)
+            .collect()
+            .await;
+        assert_eq!(serial.len(), 8 * 300 + 8);
+
+        let parallel = client
+            .list_parallel("test_bucket", "prefix/", 4)
+            .await
+            .expect("parallel listing should succeed");
+        let parallel: Vec<_> = parallel.into_iter().map(|object| object.key).collect();
+        assert_eq!(parallel, serial);
+        assert!(client.max_in_flight() > 1, "shards should be listed concurrently");
+        assert!(client.max_in_flight() <= 4);
+        assert!(client.op_count(Operation::ListObjects) > 8);
+
+        let result = client.list_parallel("wrong_bucket", "prefix/", 4).await;
+        assert!(matches!(
+            result,
+            Err(ObjectClientError::ServiceError(ListObjectsError::NoSuchBucket))
+        ));
+    }
+
+    #[tokio::test]
+    async fn head_object_via_get() {
+        use crate::mock_client::{MockClient, MockClientConfig, MockObject, Operation};
+
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 8,
+        });
+        client.add_object("key", MockObject::constant(0xa1, 4096, ETag::for_tests()));
+        client.deny_head_object(true);
+        assert!(matches!(
+            client.head_object("test_bucket", "key", None).await,
+            Err(ObjectClientError::ServiceError(HeadObjectError::AccessDenied))
+        ));
+
+        let result = client
+            .head_object_via_get("test_bucket", "key", None)
+            .await
+            .expect("get should succeed");
+        assert_eq!(result.object.size, 4096);
+        assert_eq!(result.object.etag, ETag::for_tests().as_str());
+        assert_eq!(client.op_count(Operation::GetObject), 1);
+
+        let missing = client.head_object_via_get("test_bucket", "missing", None).await;
+        assert!(matches!(
+            missing,
+            Err(ObjectClientError::ServiceError(HeadObjectViaGetError::GetObject(
+                GetObjectError::NoSuchKey
+            )))
+        ));
+
+        client.omit_get_metadata(true);
+        let no_metadata = client.head_object_via_get("test_bucket", "key", None).await;
+        assert!(matches!(
+            no_metadata,
+            Err(ObjectClientError::ServiceError(HeadObjectViaGetError::InvalidMetadata(
+                ObjectMetadataError::Missing
+            )))
+        ));
+        // Plain reads don't need the metadata
+        let get = client
+            .get_object("test_bucket", "key", &GetObjectParams::new())
+            .await
+            .unwrap();
+        assert_eq!(get.collect().await.unwrap().len(), 4096);
+    }
+
+    #[tokio::test]
+    async fn get_object_prefix() {
+        use crate::mock_client::{ramp_bytes, MockClient, MockClientConfig, MockObject, Operation};
+
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 8,
+        });
+        client.add_object("key", MockObject::ramp(0x11, 4096, ETag::for_tests()));
+
+        let prefix = client
+            .get_object_prefix("test_bucket", "key", 16)
+            .await
+            .expect("get should succeed");
+        assert_eq!(prefix, ramp_bytes(0x11, 16));
+        assert_eq!(client.op_count(Operation::GetObject), 1);
+
+        let empty = client
+            .get_object_prefix("test_bucket", "key", 0)
+            .await
+            .expect("get should succeed");
+        assert!(empty.is_empty());
+        assert_eq!(client.op_count(Operation::GetObject), 1);
+    }
+
+    #[tokio::test]
+    async fn list_common_prefixes_at_depth() {
+        use crate::mock_client::{MockClient, MockClientConfig, MockObject};
+
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+        });
+        // Pages can end inside a common prefix, so the next page repeats it
+        client.split_common_prefixes_across_pages(true);
+        // Enough subdirectories to need more than one page of results
+        let dirs = WALK_PAGE_SIZE + 200;
+        for i in 0..dirs {
+            for name in ["file", "nested/file"] {
+                let key = format!("data/dir{i:04}/{name}");
+                client.add_object(&key, MockObject::constant(0xa1, 5, ETag::for_tests()));
+            }
+        }
+        client.add_object("data/file", MockObject::constant(0xa1, 5, ETag::for_tests()));
+        client.add_object("data/dir0000-file", MockObject::constant(0xa1, 5, ETag::for_tests()));
+        client.add_object("other/dir/file", MockObject::constant(0xa1, 5, ETag::for_tests()));
+
+        let prefixes = client
+            .list_common_prefixes("test_bucket", "data/", "/")
+            .await
+            .expect("listing should succeed");
+        let expected: Vec<_> = (0..dirs).map(|i| format!("data/dir{i:04}/")).collect();
+        assert_eq!(prefixes, expected);
+
+        let prefixes = client
+            .list_common_prefixes("test_bucket", "data/dir0002/", "/")
+            .await
+            .expect("listing should succeed");
+        assert_eq!(prefixes, ["data/dir0002/nested/"]);
+
+        let prefixes = client
+            .list_common_prefixes("test_bucket", "data/", "-")
+            .await
+            .expect("listing should succeed");
+        assert_eq!(prefixes, ["data/dir0000-"]);
+
+        let prefixes = client
+            .list_common_prefixes("test_bucket", "data/file", "/")
+            .await
+            .expect("listing should succeed");
+        assert!(prefixes.is_empty());
+    }
+}
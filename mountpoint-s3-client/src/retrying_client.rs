@@ -0,0 +1,576 @@
+//! A decorator [ObjectClient] that retries requests that failed with a transient error, for clients
+//! that don't retry on their own like [crate::mock_client::MockClient] and
+//! [crate::local_client::LocalObjectClient]. The [S3CrtClient](crate::S3CrtClient) already retries
+//! requests in the CRT, using the same [RetryStrategyConfig].
+//!
+//! Only client errors can be retried, and only if the client says they're transient, like
+//! throttling or a dropped connection. Service errors like `NoSuchKey` or `AccessDenied` are
+//! returned straight away, since retrying won't change the outcome.
+//!
+//! A [RetryBudget] can additionally limit the retries made across all requests, so that a broad
+//! outage fails requests quickly instead of multiplying the load on S3.
+
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use tracing::warn;
+
+use crate::object_client::{
+    AbortMultipartUploadError, AbortMultipartUploadResult, CopyObjectError, CopyObjectResult, DeleteObjectError,
+    DeleteObjectResult, DeleteObjectsError, DeleteObjectsResult, GetObjectAttributesError, GetObjectAttributesResult,
+    GetObjectError, GetObjectParams, HeadObjectError, HeadObjectResult, ListBucketsError, ListBucketsResult,
+    ListMultipartUploadsError, ListMultipartUploadsResult, ListObjectVersionsError, ListObjectVersionsResult,
+    ListObjectsError, ObjectCannedAcl, ObjectClientResult, PresignPostError, PutObjectAclError, PutObjectAclResult,
+    PutObjectError, PutObjectParams, PutObjectResult,
+};
+use crate::timer::sleep;
+use crate::{ListObjectsResult, ObjectAttribute, ObjectClient, ObjectClientError, PostPolicyCondition, PresignedPost};
+
+/// How to retry failed requests
+#[derive(Debug, Clone, Copy)]
+pub struct RetryStrategyConfig {
+    /// Maximum number of times to retry a failed request, not counting the first attempt
+    pub max_retries: usize,
+    /// Maximum number of times to retry a failed read (GetObject, HeadObject, and the list and
+    /// attributes requests), if different from `max_retries`
+    pub read_max_retries: Option<usize>,
+    /// Maximum number of times to retry a failed write (PutObject, CopyObject, and the delete, ACL,
+    /// and abort requests), if different from `max_retries`. Writes buffer their whole body to be
+    /// resent, so retrying them costs more than retrying a read. The CRT client sends writes
+    /// through a second CRT client when this differs from the read limit.
+    pub write_max_retries: Option<usize>,
+    /// How long to wait before the first retry. Doubles after each failed retry.
+    pub backoff_scale_factor: Duration,
+    /// Maximum time to wait before a retry. The CRT has its own limit for the requests it retries,
+    /// so this is only used by [RetryingClient] and for the CompleteMultipartUpload failures the
+    /// CRT client retries itself.
+    pub max_backoff: Duration,
+    /// Limit on retries shared by all requests, so that a broad outage doesn't multiply the load
+    /// on S3 by the number of retries. No limit if `None`. Only used by [RetryingClient].
+    pub retry_budget: Option<RetryBudgetConfig>,
+}
+
+impl Default for RetryStrategyConfig {
+    fn default() -> Self {
+        // Match the SDK "legacy" retry strategies
+        Self {
+            max_retries: 3,
+            read_max_retries: None,
+            write_max_retries: None,
+            backoff_scale_factor: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(20),
+            retry_budget: None,
+        }
+    }
+}
+
+/// How many retries a [RetryBudget] allows
+#[derive(Debug, Clone, Copy)]
+pub struct RetryBudgetConfig {
+    /// Maximum number of retries that can be made in a burst
+    pub max_tokens: u32,
+    /// Number of retries the budget regains each second, up to `max_tokens`
+    pub tokens_per_second: f64,
+}
+
+/// A token bucket shared by all requests to a [RetryingClient]. Each retry takes a token, and
+/// requests fail without retrying once the bucket is empty.
+#[derive(Debug)]
+pub struct RetryBudget {
+    config: RetryBudgetConfig,
+    state: Mutex<RetryBudgetState>,
+}
+
+#[derive(Debug)]
+struct RetryBudgetState {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RetryBudget {
+    /// Create a budget that starts out full
+    pub fn new(config: RetryBudgetConfig) -> Self {
+        let state = RetryBudgetState {
+            tokens: config.max_tokens as f64,
+            last_refill: Instant::now(),
+        };
+        Self {
+            config,
+            state: Mutex::new(state),
+        }
+    }
+
+    /// Take a token for a retry, returning false if there are none left
+    pub fn try_acquire(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now.duration_since(state.last_refill).as_secs_f64();
+        state.tokens = (state.tokens + elapsed * self.config.tokens_per_second).min(self.config.max_tokens as f64);
+        state.last_refill = now;
+        if state.tokens >= 1.0 {
+            state.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// A client error that knows whether the request that caused it might succeed if it's retried
+pub trait RetryableError {
+    /// Whether the error is transient, like throttling or a connection failure
+    fn is_retryable(&self) -> bool;
+}
+
+/// An [ObjectClient] that retries failed requests to the client it wraps with exponential backoff.
+///
+/// A GetObject request is only retried if it fails before returning its body; errors while
+/// streaming the body are returned to the caller. PutObject requests buffer their contents in
+/// memory so that they can be sent again.
+#[derive(Debug)]
+pub struct RetryingClient<Client> {
+    client: Client,
+    config: RetryStrategyConfig,
+    budget: Option<RetryBudget>,
+}
+
+impl<Client> RetryingClient<Client>
+where
+    Client: ObjectClient,
+    Client::ClientError: RetryableError,
+{
+    /// Wrap the given client, retrying its requests according to `config`
+    pub fn new(client: Client, config: RetryStrategyConfig) -> Self {
+        let budget = config.retry_budget.map(RetryBudget::new);
+        Self { client, config, budget }
+    }
+
+    /// The client requests are forwarded to
+    pub fn inner(&self) -> &Client {
+        &self.client
+    }
+
+    /// Make a read request, retrying it up to [RetryStrategyConfig::read_max_retries] times
+    async fn retry_read<T, E, F, Fut>(
+        &self,
+        operation: &str,
+        request: F,
+    ) -> ObjectClientResult<T, E, Client::ClientError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = ObjectClientResult<T, E, Client::ClientError>>,
+    {
+        let max_retries = self.config.read_max_retries.unwrap_or(self.config.max_retries);
+        self.retry(operation, max_retries, request).await
+    }
+
+    /// Make a write request, retrying it up to [RetryStrategyConfig::write_max_retries] times
+    async fn retry_write<T, E, F, Fut>(
+        &self,
+        operation: &str,
+        request: F,
+    ) -> ObjectClientResult<T, E, Client::ClientError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = ObjectClientResult<T, E, Client::ClientError>>,
+    {
+        let max_retries = self.config.write_max_retries.unwrap_or(self.config.max_retries);
+        self.retry(operation, max_retries, request).await
+    }
+
+    /// Make a request, and make it again as long as it fails with a retryable error and there are
+    /// retries left
+    async fn retry<T, E, F, Fut>(
+        &self,
+        operation: &str,
+        max_retries: usize,
+        mut request: F,
+    ) -> ObjectClientResult<T, E, Client::ClientError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = ObjectClientResult<T, E, Client::ClientError>>,
+    {
+        let mut backoff = self.config.backoff_scale_factor;
+        let mut retries = 0;
+        loop {
+            match request().await {
+                Err(ObjectClientError::ClientError(e))
+                    if retries < max_retries && e.is_retryable() && self.take_retry_token(operation) =>
+                {
+                    warn!(operation, retries, ?backoff, "request failed, will retry: {e:?}");
+                    sleep(backoff).await;
+                    backoff = (backoff * 2).min(self.config.max_backoff);
+                    retries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Check the retry budget, if there is one, before retrying a request
+    fn take_retry_token(&self, operation: &str) -> bool {
+        let Some(budget) = &self.budget else {
+            return true;
+        };
+        let acquired = budget.try_acquire();
+        if !acquired {
+            warn!(operation, "retry budget exhausted, not retrying request");
+        }
+        acquired
+    }
+}
+
+#[async_trait]
+impl<Client> ObjectClient for RetryingClient<Client>
+where
+    Client: ObjectClient + Send + Sync + 'static,
+    Client::ClientError: RetryableError,
+{
+    type GetObjectResult = Client::GetObjectResult;
+    type ClientError = Client::ClientError;
+
+    async fn delete_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> ObjectClientResult<DeleteObjectResult, DeleteObjectError, Self::ClientError> {
+        self.retry_write("DeleteObject", || self.client.delete_object(bucket, key, version_id))
+            .await
+    }
+
+    async fn delete_objects(
+        &self,
+        bucket: &str,
+        keys: &[String],
+    ) -> ObjectClientResult<DeleteObjectsResult, DeleteObjectsError, Self::ClientError> {
+        self.retry_write("DeleteObjects", || self.client.delete_objects(bucket, keys))
+            .await
+    }
+
+    async fn get_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &GetObjectParams,
+    ) -> ObjectClientResult<Self::GetObjectResult, GetObjectError, Self::ClientError> {
+        self.retry_read("GetObject", || self.client.get_object(bucket, key, params))
+            .await
+    }
+
+    async fn list_objects(
+        &self,
+        bucket: &str,
+        continuation_token: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, Self::ClientError> {
+        self.retry_read("ListObjects", || {
+            self.client
+                .list_objects(bucket, continuation_token, delimiter, max_keys, prefix)
+        })
+        .await
+    }
+
+    async fn list_object_versions(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        key_marker: Option<&str>,
+        version_id_marker: Option<&str>,
+        max_keys: usize,
+    ) -> ObjectClientResult<ListObjectVersionsResult, ListObjectVersionsError, Self::ClientError> {
+        self.retry_read("ListObjectVersions", || {
+            self.client
+                .list_object_versions(bucket, prefix, key_marker, version_id_marker, max_keys)
+        })
+        .await
+    }
+
+    async fn head_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> ObjectClientResult<HeadObjectResult, HeadObjectError, Self::ClientError> {
+        self.retry_read("HeadObject", || self.client.head_object(bucket, key, version_id))
+            .await
+    }
+
+    async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectParams,
+        contents: impl Stream<Item = impl AsRef<[u8]> + Send> + Send,
+    ) -> ObjectClientResult<PutObjectResult, PutObjectError, Self::ClientError> {
+        let mut buffer = vec![];
+        contents
+            .for_each(|b| {
+                buffer.extend_from_slice(b.as_ref());
+                std::future::ready(())
+            })
+            .await;
+        self.retry_write("PutObject", || {
+            let stream = futures::stream::iter([buffer.as_slice()]);
+            self.client.put_object(bucket, key, params, stream)
+        })
+        .await
+    }
+
+    async fn copy_object(
+        &self,
+        source_bucket: &str,
+        source_key: &str,
+        destination_bucket: &str,
+        destination_key: &str,
+    ) -> ObjectClientResult<CopyObjectResult, CopyObjectError, Self::ClientError> {
+        self.retry_write("CopyObject", || {
+            self.client
+                .copy_object(source_bucket, source_key, destination_bucket, destination_key)
+        })
+        .await
+    }
+
+    async fn put_object_acl(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        acl: ObjectCannedAcl,
+    ) -> ObjectClientResult<PutObjectAclResult, PutObjectAclError, Self::ClientError> {
+        self.retry_write("PutObjectAcl", || {
+            self.client.put_object_acl(bucket, key, version_id, acl)
+        })
+        .await
+    }
+
+    async fn list_multipart_uploads(
+        &self,
+        bucket: &str,
+        prefix: &str,
+    ) -> ObjectClientResult<ListMultipartUploadsResult, ListMultipartUploadsError, Self::ClientError> {
+        self.retry_read("ListMultipartUploads", || {
+            self.client.list_multipart_uploads(bucket, prefix)
+        })
+        .await
+    }
+
+    async fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> ObjectClientResult<AbortMultipartUploadResult, AbortMultipartUploadError, Self::ClientError> {
+        self.retry_write("AbortMultipartUpload", || {
+            self.client.abort_multipart_upload(bucket, key, upload_id)
+        })
+        .await
+    }
+
+    async fn list_buckets(&self) -> ObjectClientResult<ListBucketsResult, ListBucketsError, Self::ClientError> {
+        self.retry_read("ListBuckets", || self.client.list_buckets()).await
+    }
+
+    async fn get_object_attributes(
+        &self,
+        bucket: &str,
+        key: &str,
+        max_parts: Option<usize>,
+        part_number_marker: Option<usize>,
+        object_attributes: &[ObjectAttribute],
+    ) -> ObjectClientResult<GetObjectAttributesResult, GetObjectAttributesError, Self::ClientError> {
+        self.retry_read("GetObjectAttributes", || {
+            self.client
+                .get_object_attributes(bucket, key, max_parts, part_number_marker, object_attributes)
+        })
+        .await
+    }
+
+    async fn presign_post_object(
+        &self,
+        bucket: &str,
+        key_prefix: &str,
+        conditions: &[PostPolicyCondition],
+        expires_in: Duration,
+    ) -> ObjectClientResult<PresignedPost, PresignPostError, Self::ClientError> {
+        // Presigning doesn't make a request, so there's nothing to retry
+        self.client
+            .presign_post_object(bucket, key_prefix, conditions, expires_in)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::failure_client::countdown_failure_client;
+    use crate::intercepting_client::InterceptingClient;
+    use crate::mock_client::{MockClient, MockClientConfig, MockClientError, MockObject, Operation};
+    use crate::ETag;
+
+    fn config() -> RetryStrategyConfig {
+        RetryStrategyConfig {
+            max_retries: 3,
+            read_max_retries: None,
+            write_max_retries: None,
+            backoff_scale_factor: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(10),
+            retry_budget: None,
+        }
+    }
+
+    fn mock_client() -> MockClient {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 128,
+        });
+        client.add_object("key", MockObject::constant(0xa1, 50, ETag::for_tests()));
+        client
+    }
+
+    #[tokio::test]
+    async fn retry_until_success() {
+        let throttled = || Err(ObjectClientError::ClientError(MockClientError("503 Slow Down".into())));
+        let get_failures = HashMap::from([(1, throttled()), (2, throttled())]);
+        let flaky_client = countdown_failure_client(
+            mock_client(),
+            get_failures,
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        );
+        let client = RetryingClient::new(flaky_client, config());
+
+        let mut body = client
+            .get_object("test_bucket", "key", &GetObjectParams::new())
+            .await
+            .expect("should succeed after retries");
+        let mut data = vec![];
+        while let Some(part) = body.next().await {
+            let (_offset, part) = part.expect("body should stream without errors");
+            data.extend_from_slice(&part);
+        }
+        assert_eq!(data, [0xa1; 50]);
+        assert_eq!(client.inner().client.op_count(Operation::GetObject), 1);
+    }
+
+    #[tokio::test]
+    async fn give_up_after_max_retries() {
+        let throttled = || Err(ObjectClientError::ClientError(MockClientError("503 Slow Down".into())));
+        let get_failures = (1..=4).map(|i| (i, throttled())).collect();
+        let flaky_client = countdown_failure_client(
+            mock_client(),
+            get_failures,
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        );
+        let client = RetryingClient::new(flaky_client, config());
+
+        let result = client.get_object("test_bucket", "key", &GetObjectParams::new()).await;
+        assert!(matches!(result, Err(ObjectClientError::ClientError(_))));
+    }
+
+    #[tokio::test]
+    async fn service_errors_not_retried() {
+        let client = RetryingClient::new(mock_client(), config());
+
+        let result = client
+            .get_object("test_bucket", "missing", &GetObjectParams::new())
+            .await;
+        assert!(matches!(
+            result,
+            Err(ObjectClientError::ServiceError(GetObjectError::NoSuchKey))
+        ));
+        assert_eq!(client.inner().op_count(Operation::GetObject), 1);
+    }
+
+    #[tokio::test]
+    async fn retries_bounded_by_budget() {
+        const REQUESTS: usize = 20;
+        const MAX_TOKENS: u32 = 5;
+
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let failing_client = InterceptingClient::new(mock_client()).before_get({
+            let attempts = attempts.clone();
+            move |_bucket, _key, _params| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(ObjectClientError::ClientError(MockClientError("503 Slow Down".into())))
+            }
+        });
+        let config = RetryStrategyConfig {
+            retry_budget: Some(RetryBudgetConfig {
+                max_tokens: MAX_TOKENS,
+                tokens_per_second: 0.0,
+            }),
+            ..config()
+        };
+        let client = RetryingClient::new(failing_client, config);
+
+        for _ in 0..REQUESTS {
+            let result = client.get_object("test_bucket", "key", &GetObjectParams::new()).await;
+            assert!(matches!(result, Err(ObjectClientError::ClientError(_))));
+        }
+
+        // Without a budget, every request would be retried `max_retries` times
+        assert_eq!(attempts.load(Ordering::SeqCst), REQUESTS + MAX_TOKENS as usize);
+    }
+
+    #[tokio::test]
+    async fn separate_read_and_write_retries() {
+        let get_attempts = Arc::new(AtomicUsize::new(0));
+        let put_attempts = Arc::new(AtomicUsize::new(0));
+        let failing_client = InterceptingClient::new(mock_client())
+            .before_get({
+                let get_attempts = get_attempts.clone();
+                move |_bucket, _key, _params| {
+                    get_attempts.fetch_add(1, Ordering::SeqCst);
+                    Err(ObjectClientError::ClientError(MockClientError("503 Slow Down".into())))
+                }
+            })
+            .before_put({
+                let put_attempts = put_attempts.clone();
+                move |_bucket, _key, _params| {
+                    put_attempts.fetch_add(1, Ordering::SeqCst);
+                    Err(ObjectClientError::ClientError(MockClientError("503 Slow Down".into())))
+                }
+            });
+        let config = RetryStrategyConfig {
+            read_max_retries: Some(1),
+            write_max_retries: Some(5),
+            ..config()
+        };
+        let client = RetryingClient::new(failing_client, config);
+
+        let result = client.get_object("test_bucket", "key", &GetObjectParams::new()).await;
+        assert!(matches!(result, Err(ObjectClientError::ClientError(_))));
+        assert_eq!(get_attempts.load(Ordering::SeqCst), 2);
+
+        let contents = futures::stream::iter([&[0xa1u8; 10][..]]);
+        let result = client
+            .put_object("test_bucket", "key", &PutObjectParams::new(), contents)
+            .await;
+        assert!(matches!(result, Err(ObjectClientError::ClientError(_))));
+        assert_eq!(put_attempts.load(Ordering::SeqCst), 6);
+    }
+
+    #[test]
+    fn budget_refills() {
+        let budget = RetryBudget::new(RetryBudgetConfig {
+            max_tokens: 1,
+            tokens_per_second: 1000.0,
+        });
+        assert!(budget.try_acquire());
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(budget.try_acquire());
+    }
+}
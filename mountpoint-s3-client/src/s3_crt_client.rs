@@ -1,6 +1,6 @@
+use std::collections::HashMap;
 use std::ffi::{OsStr, OsString};
 use std::future::Future;
-use std::ops::Range;
 use std::os::unix::prelude::OsStrExt;
 use std::pin::Pin;
 use std::sync::atomic::{AtomicU64, Ordering};
@@ -11,16 +11,19 @@ use std::time::{Duration, Instant};
 use mountpoint_s3_crt::auth::credentials::{
     CredentialsProvider, CredentialsProviderChainDefaultOptions, CredentialsProviderProfileOptions,
 };
+use mountpoint_s3_crt::auth::signing_config::{SigningAlgorithm, SigningConfig};
 use mountpoint_s3_crt::common::allocator::Allocator;
 use mountpoint_s3_crt::common::uri::Uri;
 use mountpoint_s3_crt::http::request_response::{Header, Headers, Message};
 use mountpoint_s3_crt::io::channel_bootstrap::{ClientBootstrap, ClientBootstrapOptions};
 use mountpoint_s3_crt::io::event_loop::EventLoopGroup;
-use mountpoint_s3_crt::io::host_resolver::{HostResolver, HostResolverDefaultOptions};
+use mountpoint_s3_crt::io::host_resolver::{AddressFamily, HostResolver, HostResolverDefaultOptions};
 use mountpoint_s3_crt::io::retry_strategy::{ExponentialBackoffJitterMode, RetryStrategy, StandardRetryOptions};
+use mountpoint_s3_crt::io::socket::SocketOptions;
 use mountpoint_s3_crt::io::stream::InputStream;
 use mountpoint_s3_crt::s3::client::{
-    init_default_signing_config, Client, ClientConfig, MetaRequestOptions, MetaRequestResult, MetaRequestType,
+    init_default_signing_config, init_signing_config, init_signing_config_for_service, Client, ClientConfig,
+    ClientMetrics, MetaRequestOptions, MetaRequestResult, MetaRequestType,
 };
 
 use async_trait::async_trait;
@@ -28,10 +31,18 @@ use futures::channel::oneshot;
 use percent_encoding::{percent_encode, AsciiSet, NON_ALPHANUMERIC};
 use pin_project::pin_project;
 use thiserror::Error;
+use time::format_description::well_known::Rfc3339;
+use time::{OffsetDateTime, PrimitiveDateTime};
 use tracing::{debug, error, trace, warn, Span};
 
-use crate::endpoint::{AddressingStyle, Endpoint, EndpointError};
+use crate::endpoint::{
+    is_multi_region_access_point, object_lambda_access_point, AddressingStyle, Endpoint, EndpointError,
+};
+use crate::express::ExpressMode;
 use crate::object_client::*;
+use crate::presign::{PostPolicyCondition, PresignedPost};
+use crate::retrying_client::{RetryStrategyConfig, RetryableError};
+use crate::s3_crt_client::create_session::CachedExpressSession;
 use crate::s3_crt_client::get_object::GetObjectRequest;
 
 macro_rules! request_span {
@@ -41,14 +52,26 @@ macro_rules! request_span {
     }};
 }
 
+pub(crate) mod abort_multipart_upload;
+pub(crate) mod copy_object;
+pub(crate) mod create_session;
 pub(crate) mod delete_object;
+pub(crate) mod delete_objects;
 pub(crate) mod get_object;
 pub(crate) mod get_object_attributes;
 pub(crate) mod head_bucket;
 
 pub(crate) mod head_object;
+pub(crate) mod list_buckets;
+pub(crate) mod list_multipart_uploads;
+pub(crate) mod list_object_versions;
 pub(crate) mod list_objects;
+pub(crate) mod presign_post;
 pub(crate) mod put_object;
+pub(crate) mod put_object_acl;
+
+/// The CRT's part size when none is configured
+const DEFAULT_PART_SIZE: usize = 8 * 1024 * 1024;
 
 #[derive(Debug, Clone, Default)]
 pub struct S3ClientConfig {
@@ -58,12 +81,40 @@ pub struct S3ClientConfig {
     pub part_size: Option<usize>,
     pub endpoint: Option<Endpoint>,
     pub user_agent_prefix: Option<String>,
+    /// Text to append to the `User-Agent` header of every request, for example to identify the
+    /// application making them
+    pub user_agent_suffix: Option<String>,
+    /// Application id to add to the `User-Agent` header as `app/<app id>`, like the `sdk_ua_app_id`
+    /// setting of the AWS SDKs
+    pub app_id: Option<String>,
     pub request_payer: Option<String>,
+    pub express_mode: ExpressMode,
+    /// Sign requests with SigV4A, as required by multi-region access points
+    pub enable_multi_region_access_points: bool,
+    /// How the CRT retries failed requests
+    pub retry_config: RetryStrategyConfig,
+    /// How long to wait for a new connection to S3 to be established, or `None` for the CRT's default
+    pub connect_timeout: Option<Duration>,
+    /// How long a connection can sit idle before TCP keepalive probes are sent on it, or `None` to
+    /// not send them. The CRT doesn't reap idle connections itself, so on long-lived clients with
+    /// bursty traffic this is what detects connections that were dropped while idle (for example
+    /// by a NAT gateway's timeout) before a request is sent on them.
+    pub keep_alive_interval: Option<Duration>,
+    /// Which IP address family to connect to S3 with. On IPv6-only networks, or to make sure a
+    /// dual-stack endpoint is reached over IPv6, set this to [AddressFamily::Ipv6].
+    pub address_family: AddressFamily,
+    /// Stream the contents of [put_object](S3CrtClient::put_object) requests to S3 as a multipart
+    /// upload, taking at most this many bytes (but at least one part) from the contents ahead of
+    /// the parts that have been uploaded. If `None`, the whole object is buffered in memory before
+    /// it's uploaded, which uploads more parts in parallel.
+    pub upload_buffer_size: Option<usize>,
 }
 
 #[derive(Debug)]
 pub struct S3CrtClient {
-    s3_client: Client,
+    s3_client: Arc<Client>,
+    /// The client to make write requests with, if they're retried differently to reads
+    write_client: Option<Arc<Client>>,
     event_loop_group: EventLoopGroup,
     endpoint: Endpoint,
     allocator: Allocator,
@@ -72,6 +123,22 @@ pub struct S3CrtClient {
     /// Here it will add the user agent prefix and s3 client information.
     user_agent_header: String,
     request_payer: Option<String>,
+    express_mode: ExpressMode,
+    /// Cached S3 Express One Zone sessions, keyed by bucket name
+    express_sessions: Mutex<HashMap<String, CachedExpressSession>>,
+    /// The algorithm requests are signed with, or None if requests are not signed
+    signing_algorithm: Option<SigningAlgorithm>,
+    /// The provider of the credentials requests are signed with, or None if requests are not signed
+    credentials_provider: Option<CredentialsProvider>,
+    /// The region requests are signed for
+    region: String,
+    /// The part size of multipart uploads
+    part_size: usize,
+    /// See [S3ClientConfig::upload_buffer_size]
+    upload_buffer_size: Option<usize>,
+    /// How to retry the failures the CRT doesn't retry itself, like a CompleteMultipartUpload that
+    /// fails after a 200 OK
+    retry_config: RetryStrategyConfig,
 }
 
 impl S3CrtClient {
@@ -90,19 +157,19 @@ impl S3CrtClient {
         let bootstrap_options = ClientBootstrapOptions {
             event_loop_group: &mut event_loop_group,
             host_resolver: &mut host_resolver,
+            address_family: config.address_family,
         };
 
         let mut client_bootstrap = ClientBootstrap::new(&allocator, &bootstrap_options).unwrap();
 
-        let mut client_config = ClientConfig::new();
-
-        let mut retry_strategy_options = StandardRetryOptions::default(&mut event_loop_group);
-        // Match the SDK "legacy" retry strategies
-        retry_strategy_options.backoff_retry_options.max_retries = 3;
-        retry_strategy_options.backoff_retry_options.backoff_scale_factor = Duration::from_millis(500);
-        retry_strategy_options.backoff_retry_options.jitter_mode = ExponentialBackoffJitterMode::Full;
-        let retry_strategy = RetryStrategy::standard(&allocator, &retry_strategy_options).unwrap();
+        let retry_config = config.retry_config;
+        let read_max_retries = retry_config.read_max_retries.unwrap_or(retry_config.max_retries);
+        let write_max_retries = retry_config.write_max_retries.unwrap_or(retry_config.max_retries);
+        let retry_strategy = new_retry_strategy(&allocator, &mut event_loop_group, &retry_config, read_max_retries);
 
+        let mut signing_algorithm = None;
+        let mut signing_credentials_provider = None;
+        let mut client_signing_config = None;
         if !config.no_sign_request {
             let credentials_provider = match config.profile_name_override {
                 Some(profile_name_override) => {
@@ -120,29 +187,83 @@ impl S3CrtClient {
                 }
             }
             .map_err(NewClientError::ProviderFailure)?;
-            let signing_config = init_default_signing_config(region, credentials_provider);
-            client_config.signing_config(signing_config);
+            signing_credentials_provider = Some(credentials_provider.clone());
+            // SigV4A signs for a set of regions rather than just one, and multi-region access points
+            // can route requests to any region.
+            let signing_config = if config.enable_multi_region_access_points {
+                init_signing_config("*", credentials_provider, SigningAlgorithm::SigV4A)
+            } else {
+                init_default_signing_config(region, credentials_provider)
+            };
+            signing_algorithm = Some(signing_config.algorithm());
+            client_signing_config = Some(signing_config);
         }
 
-        client_config
-            .client_bootstrap(client_bootstrap)
-            .retry_strategy(retry_strategy);
+        let new_client = |client_bootstrap: ClientBootstrap, retry_strategy: RetryStrategy| {
+            let mut client_config = ClientConfig::new();
 
-        if let Some(throughput_target_gbps) = config.throughput_target_gbps {
-            client_config.throughput_target_gbps(throughput_target_gbps);
-        }
+            if let Some(signing_config) = &client_signing_config {
+                client_config.signing_config(signing_config.clone());
+            }
 
-        if let Some(part_size) = config.part_size {
-            client_config.part_size(part_size);
-        }
+            client_config
+                .client_bootstrap(client_bootstrap)
+                .retry_strategy(retry_strategy);
+
+            if let Some(throughput_target_gbps) = config.throughput_target_gbps {
+                client_config.throughput_target_gbps(throughput_target_gbps);
+            }
+
+            if let Some(part_size) = config.part_size {
+                client_config.part_size(part_size);
+            }
+
+            // The CRT grows the part size of uploads that would otherwise need more than the
+            // maximum number of parts, up to this size
+            client_config.max_part_size(MAX_UPLOAD_PART_SIZE);
+
+            if config.connect_timeout.is_some() || config.keep_alive_interval.is_some() {
+                client_config.socket_options(SocketOptions {
+                    connect_timeout: config.connect_timeout.unwrap_or_default(),
+                    keep_alive_interval: config.keep_alive_interval,
+                    ..Default::default()
+                });
+            }
+
+            Client::new(&allocator, client_config).unwrap()
+        };
+
+        let s3_client = new_client(client_bootstrap, retry_strategy);
+
+        // The CRT retries all of a client's requests with the same strategy, so writes get a client
+        // of their own if they're retried a different number of times than reads
+        let write_client = if write_max_retries != read_max_retries {
+            let bootstrap_options = ClientBootstrapOptions {
+                event_loop_group: &mut event_loop_group,
+                host_resolver: &mut host_resolver,
+                address_family: config.address_family,
+            };
+            let client_bootstrap = ClientBootstrap::new(&allocator, &bootstrap_options).unwrap();
+            let retry_strategy =
+                new_retry_strategy(&allocator, &mut event_loop_group, &retry_config, write_max_retries);
+            Some(Arc::new(new_client(client_bootstrap, retry_strategy)))
+        } else {
+            None
+        };
 
         const CLIENT_NAME: &str = "mountpoint-s3-client";
-        let user_agent_header = match config.user_agent_prefix {
+        let mut user_agent_header = match config.user_agent_prefix {
             Some(prefix) => format!("{prefix} {CLIENT_NAME}"),
             None => CLIENT_NAME.to_owned(),
         };
-
-        let s3_client = Client::new(&allocator, client_config).unwrap();
+        if let Some(suffix) = config.user_agent_suffix {
+            user_agent_header.push(' ');
+            user_agent_header.push_str(&suffix);
+        }
+        if let Some(app_id) = config.app_id {
+            user_agent_header.push_str(" app/");
+            user_agent_header.push_str(&app_id);
+        }
 
         let endpoint = if let Some(endpoint) = config.endpoint {
             endpoint
@@ -152,12 +273,21 @@ impl S3CrtClient {
 
         Ok(Self {
             allocator,
-            s3_client,
+            s3_client: Arc::new(s3_client),
+            write_client,
             event_loop_group,
             endpoint,
             next_request_counter: AtomicU64::new(0),
             user_agent_header,
             request_payer: config.request_payer,
+            express_mode: config.express_mode,
+            express_sessions: Default::default(),
+            signing_algorithm,
+            credentials_provider: signing_credentials_provider,
+            region: region.to_owned(),
+            part_size: config.part_size.unwrap_or(DEFAULT_PART_SIZE),
+            upload_buffer_size: config.upload_buffer_size,
+            retry_config,
         })
     }
 
@@ -165,11 +295,22 @@ impl S3CrtClient {
         self.event_loop_group.clone()
     }
 
+    /// A function that polls the CRT client's current [ClientMetrics], for reporting them outside
+    /// of the `metrics` gauges this client already publishes. The function keeps the CRT client
+    /// alive until it's dropped.
+    pub fn client_metrics_source(&self) -> impl Fn() -> ClientMetrics + Send + Sync + 'static {
+        let s3_client = self.s3_client.clone();
+        move || s3_client.poll_client_metrics()
+    }
+
     /// Create a new HTTP request template for the given HTTP method and S3 bucket name.
     /// Pre-populates common headers used across all requests. Sets the "accept" header assuming the
     /// response should be XML; this header should be overwritten for requests like GET that return
     /// object data.
     fn new_request_template(&self, method: &str, bucket: &str) -> Result<S3Message, ConstructionError> {
+        if is_multi_region_access_point(bucket) && self.signing_algorithm == Some(SigningAlgorithm::SigV4) {
+            return Err(ConstructionError::SigV4ARequired);
+        }
         let (uri, path_prefix) = self.endpoint.for_bucket(bucket)?;
         let hostname = uri.host_name().to_str().unwrap();
         let port = uri.host_port();
@@ -189,10 +330,32 @@ impl S3CrtClient {
             message.add_header(&Header::new("x-amz-request-payer", payer))?;
         }
 
+        // Object Lambda access points expect requests signed for their own service and region
+        let mut signing_config = None;
+        if let (Some(arn), Some(credentials_provider)) =
+            (object_lambda_access_point(bucket), &self.credentials_provider)
+        {
+            signing_config = Some(init_signing_config_for_service(
+                &arn.region,
+                credentials_provider.clone(),
+                SigningAlgorithm::SigV4,
+                "s3-object-lambda",
+            ));
+        }
+
+        // Requests to directory buckets are signed with the session's credentials rather than the
+        // client's, and carry the session token in their own header
+        if let Some(cached) = self.express_sessions.lock().unwrap().get(bucket) {
+            message.add_header(&Header::new("x-amz-s3session-token", &cached.session.session_token))?;
+            signing_config = Some(cached.signing_config.clone());
+        }
+
         Ok(S3Message {
             inner: message,
             uri,
             path_prefix,
+            signing_config,
+            is_write: matches!(method, "PUT" | "POST" | "DELETE"),
         })
     }
 
@@ -219,7 +382,15 @@ impl S3CrtClient {
         let start_time = Instant::now();
         let mut first_body_part = true;
 
+        let s3_client = match &self.write_client {
+            Some(write_client) if message.is_write => write_client,
+            _ => &self.s3_client,
+        };
+
         let mut options = MetaRequestOptions::new();
+        if let Some(signing_config) = message.signing_config {
+            options.signing_config(signing_config);
+        }
         options
             .message(message.inner)
             .endpoint(message.uri)
@@ -275,7 +446,16 @@ impl S3CrtClient {
                     debug!(request_id, duration_us, "request finished");
                 }
 
-                let result = on_finish(request_result);
+                // Requests fail with a generic error when the clock is skewed, so recognize that
+                // here rather than in every request's error handling
+                let result = on_finish(request_result).map_err(|error| match error {
+                    ObjectClientError::ClientError(S3RequestError::ResponseError(result)) => {
+                        let error =
+                            S3RequestError::parse_clock_skew(&result).unwrap_or(S3RequestError::ResponseError(result));
+                        ObjectClientError::ClientError(error)
+                    }
+                    error => error,
+                });
 
                 let _ = tx.send(result);
             })
@@ -283,7 +463,7 @@ impl S3CrtClient {
 
         // Issue the HTTP request using the CRT's S3 meta request API. We don't need to hold on to
         // the resulting meta request, as it's a reference-counted object.
-        self.s3_client.make_meta_request(options)?;
+        s3_client.make_meta_request(options)?;
 
         Self::poll_client_metrics(&self.s3_client);
 
@@ -381,6 +561,24 @@ struct S3Message<'a> {
     inner: Message<'a>,
     uri: Uri,
     path_prefix: String,
+    /// The config to sign this message with, if not the client's own
+    signing_config: Option<SigningConfig>,
+    /// Whether the request writes to the bucket, so is retried as a write
+    is_write: bool,
+}
+
+/// A retry strategy for the CRT that retries a failed request up to `max_retries` times
+fn new_retry_strategy(
+    allocator: &Allocator,
+    event_loop_group: &mut EventLoopGroup,
+    config: &RetryStrategyConfig,
+    max_retries: usize,
+) -> RetryStrategy {
+    let mut retry_strategy_options = StandardRetryOptions::default(event_loop_group);
+    retry_strategy_options.backoff_retry_options.max_retries = max_retries;
+    retry_strategy_options.backoff_retry_options.backoff_scale_factor = config.backoff_scale_factor;
+    retry_strategy_options.backoff_retry_options.jitter_mode = ExponentialBackoffJitterMode::Full;
+    RetryStrategy::standard(allocator, &retry_strategy_options).unwrap()
 }
 
 impl<'a> S3Message<'a> {
@@ -506,12 +704,157 @@ pub enum S3RequestError {
     /// The request was sent but an unknown or unhandled failure occurred while processing it.
     #[error("Unknown response error: {0:?}")]
     ResponseError(MetaRequestResult),
+
+    /// S3 rejected the request because the time it was signed at is too far from S3's clock.
+    /// Retrying won't help until the local clock is corrected.
+    #[error("The system clock is off by {skew} (request signed at {local}, S3's time is {server}); synchronize it, for example with NTP")]
+    ClockSkew {
+        /// The time the request was signed at
+        local: OffsetDateTime,
+        /// S3's time when it received the request
+        server: OffsetDateTime,
+        /// How far ahead of S3's clock the local clock is. Negative if it's behind.
+        skew: time::Duration,
+    },
 }
 
 impl S3RequestError {
     fn construction_failure(inner: impl Into<ConstructionError>) -> Self {
         S3RequestError::ConstructionFailure(inner.into())
     }
+
+    /// The error S3 responded with, if the request got an error response with a body we could parse
+    pub fn error_response(&self) -> Option<S3ErrorResponse> {
+        match self {
+            S3RequestError::ResponseError(result) => S3ErrorResponse::parse(result),
+            _ => None,
+        }
+    }
+
+    /// A [S3RequestError::ClockSkew] error, if S3 rejected the request with `RequestTimeTooSkewed`
+    fn parse_clock_skew(result: &MetaRequestResult) -> Option<Self> {
+        let body = result.error_response_body.as_ref()?;
+        let root = xmltree::Element::parse(body.as_bytes()).ok()?;
+        if root.get_child("Code")?.get_text()? != "RequestTimeTooSkewed" {
+            return None;
+        }
+        let server = root.get_child("ServerTime")?.get_text()?;
+        let server = OffsetDateTime::parse(&server, &Rfc3339).ok()?;
+        // The request time is in the same format as the `x-amz-date` header we signed with
+        let amz_date = time::format_description::parse("[year][month][day]T[hour][minute][second]Z").ok()?;
+        let local = root
+            .get_child("RequestTime")
+            .and_then(|time| time.get_text())
+            .and_then(|time| PrimitiveDateTime::parse(&time, &amz_date).ok())
+            .map_or_else(OffsetDateTime::now_utc, PrimitiveDateTime::assume_utc);
+        Some(S3RequestError::ClockSkew {
+            local,
+            server,
+            skew: local - server,
+        })
+    }
+}
+
+impl RetryableError for S3RequestError {
+    fn is_retryable(&self) -> bool {
+        match self {
+            // A status of 0 means we never got a response, e.g. because the connection failed
+            S3RequestError::ResponseError(result) => {
+                matches!(result.response_status, 0 | 429 | 500 | 502 | 503 | 504)
+                    // S3 times out requests whose body it doesn't receive quickly enough with a 400
+                    || S3ErrorResponse::parse(result).map_or(false, |response| response.code.is_transient())
+            }
+            S3RequestError::CrtError(_) => true,
+            S3RequestError::InternalError(_)
+            | S3RequestError::ConstructionFailure(_)
+            | S3RequestError::ClockSkew { .. } => false,
+        }
+    }
+}
+
+/// The code of an error response from S3.
+/// See https://docs.aws.amazon.com/AmazonS3/latest/API/ErrorResponses.html#ErrorCodeList for the
+/// full list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum S3ErrorCode {
+    AccessDenied,
+    ExpiredToken,
+    InternalError,
+    InvalidAccessKeyId,
+    NoSuchBucket,
+    NoSuchKey,
+    NoSuchUpload,
+    PreconditionFailed,
+    RequestTimeTooSkewed,
+    RequestTimeout,
+    ServiceUnavailable,
+    SignatureDoesNotMatch,
+    SlowDown,
+    /// Any code without a variant of its own
+    Other(String),
+}
+
+impl S3ErrorCode {
+    /// Whether the error is likely to go away if the request is retried
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            S3ErrorCode::InternalError
+                | S3ErrorCode::RequestTimeout
+                | S3ErrorCode::ServiceUnavailable
+                | S3ErrorCode::SlowDown
+        )
+    }
+}
+
+impl From<&str> for S3ErrorCode {
+    fn from(code: &str) -> Self {
+        match code {
+            "AccessDenied" => S3ErrorCode::AccessDenied,
+            "ExpiredToken" => S3ErrorCode::ExpiredToken,
+            "InternalError" => S3ErrorCode::InternalError,
+            "InvalidAccessKeyId" => S3ErrorCode::InvalidAccessKeyId,
+            "NoSuchBucket" => S3ErrorCode::NoSuchBucket,
+            "NoSuchKey" => S3ErrorCode::NoSuchKey,
+            "NoSuchUpload" => S3ErrorCode::NoSuchUpload,
+            "PreconditionFailed" => S3ErrorCode::PreconditionFailed,
+            "RequestTimeTooSkewed" => S3ErrorCode::RequestTimeTooSkewed,
+            "RequestTimeout" => S3ErrorCode::RequestTimeout,
+            "ServiceUnavailable" => S3ErrorCode::ServiceUnavailable,
+            "SignatureDoesNotMatch" => S3ErrorCode::SignatureDoesNotMatch,
+            "SlowDown" => S3ErrorCode::SlowDown,
+            code => S3ErrorCode::Other(code.to_owned()),
+        }
+    }
+}
+
+/// An error response from S3, parsed from the XML body of the response
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct S3ErrorResponse {
+    /// The error's code
+    pub code: S3ErrorCode,
+    /// A human-readable description of the error, if S3 gave one
+    pub message: Option<String>,
+}
+
+impl S3ErrorResponse {
+    /// Parse the error response from the body of a failed request, if it has one. HEAD requests
+    /// never do, since their responses don't have a body.
+    pub(crate) fn parse(result: &MetaRequestResult) -> Option<Self> {
+        let body = result.error_response_body.as_ref()?;
+        let root = xmltree::Element::parse(body.as_bytes()).ok()?;
+        let code = root.get_child("Code")?.get_text()?;
+        let message = root
+            .get_child("Message")
+            .and_then(|message| message.get_text())
+            .map(|message| message.into_owned());
+        Some(Self {
+            code: code.as_ref().into(),
+            message,
+        })
+    }
 }
 
 #[derive(Error, Debug)]
@@ -523,6 +866,14 @@ pub enum ConstructionError {
     /// The S3 endpoint was invalid
     #[error("Invalid S3 endpoint: {0}")]
     InvalidEndpoint(#[from] EndpointError),
+
+    /// The request was to a multi-region access point, but the client doesn't sign with SigV4A
+    #[error("Multi-region access points require SigV4A signing")]
+    SigV4ARequired,
+
+    /// The request needed an S3 Express One Zone session, but the client doesn't sign requests
+    #[error("S3 Express One Zone sessions require signed requests")]
+    ExpressSessionRequiresCredentials,
 }
 
 #[async_trait]
@@ -534,20 +885,29 @@ impl ObjectClient for S3CrtClient {
         &self,
         bucket: &str,
         key: &str,
+        version_id: Option<&str>,
     ) -> ObjectClientResult<DeleteObjectResult, DeleteObjectError, Self::ClientError> {
-        self.delete_object(bucket, key).await
+        self.refresh_express_session(bucket).await?;
+        self.delete_object(bucket, key, version_id).await
+    }
+
+    async fn delete_objects(
+        &self,
+        bucket: &str,
+        keys: &[String],
+    ) -> ObjectClientResult<DeleteObjectsResult, DeleteObjectsError, Self::ClientError> {
+        self.refresh_express_session(bucket).await?;
+        self.delete_objects(bucket, keys).await
     }
 
     async fn get_object(
         &self,
         bucket: &str,
         key: &str,
-        range: Option<Range<u64>>,
-        if_match: Option<ETag>,
-        // TODO: If more arguments are added to get object, make a request struct having those arguments
-        // along with bucket and key.
+        params: &GetObjectParams,
     ) -> ObjectClientResult<Self::GetObjectResult, GetObjectError, Self::ClientError> {
-        self.get_object(bucket, key, range, if_match)
+        self.refresh_express_session(bucket).await?;
+        self.get_object(bucket, key, params)
     }
 
     async fn list_objects(
@@ -558,16 +918,32 @@ impl ObjectClient for S3CrtClient {
         max_keys: usize,
         prefix: &str,
     ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, Self::ClientError> {
+        self.refresh_express_session(bucket).await?;
         self.list_objects(bucket, continuation_token, delimiter, max_keys, prefix)
             .await
     }
 
+    async fn list_object_versions(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        key_marker: Option<&str>,
+        version_id_marker: Option<&str>,
+        max_keys: usize,
+    ) -> ObjectClientResult<ListObjectVersionsResult, ListObjectVersionsError, Self::ClientError> {
+        self.refresh_express_session(bucket).await?;
+        self.list_object_versions(bucket, prefix, key_marker, version_id_marker, max_keys)
+            .await
+    }
+
     async fn head_object(
         &self,
         bucket: &str,
         key: &str,
+        version_id: Option<&str>,
     ) -> ObjectClientResult<HeadObjectResult, HeadObjectError, Self::ClientError> {
-        self.head_object(bucket, key).await
+        self.refresh_express_session(bucket).await?;
+        self.head_object(bucket, key, version_id).await
     }
 
     async fn put_object(
@@ -577,9 +953,56 @@ impl ObjectClient for S3CrtClient {
         params: &PutObjectParams,
         contents: impl futures::Stream<Item = impl AsRef<[u8]> + Send> + Send,
     ) -> ObjectClientResult<PutObjectResult, PutObjectError, Self::ClientError> {
+        self.refresh_express_session(bucket).await?;
         self.put_object(bucket, key, params, contents).await
     }
 
+    async fn copy_object(
+        &self,
+        source_bucket: &str,
+        source_key: &str,
+        destination_bucket: &str,
+        destination_key: &str,
+    ) -> ObjectClientResult<CopyObjectResult, CopyObjectError, Self::ClientError> {
+        self.refresh_express_session(destination_bucket).await?;
+        self.copy_object(source_bucket, source_key, destination_bucket, destination_key)
+            .await
+    }
+
+    async fn put_object_acl(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        acl: ObjectCannedAcl,
+    ) -> ObjectClientResult<PutObjectAclResult, PutObjectAclError, Self::ClientError> {
+        self.refresh_express_session(bucket).await?;
+        self.put_object_acl(bucket, key, version_id, acl).await
+    }
+
+    async fn list_multipart_uploads(
+        &self,
+        bucket: &str,
+        prefix: &str,
+    ) -> ObjectClientResult<ListMultipartUploadsResult, ListMultipartUploadsError, Self::ClientError> {
+        self.refresh_express_session(bucket).await?;
+        self.list_multipart_uploads(bucket, prefix).await
+    }
+
+    async fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> ObjectClientResult<AbortMultipartUploadResult, AbortMultipartUploadError, Self::ClientError> {
+        self.refresh_express_session(bucket).await?;
+        self.abort_multipart_upload(bucket, key, upload_id).await
+    }
+
+    async fn list_buckets(&self) -> ObjectClientResult<ListBucketsResult, ListBucketsError, Self::ClientError> {
+        self.list_buckets().await
+    }
+
     async fn get_object_attributes(
         &self,
         bucket: &str,
@@ -588,15 +1011,26 @@ impl ObjectClient for S3CrtClient {
         part_number_marker: Option<usize>,
         object_attributes: &[ObjectAttribute],
     ) -> ObjectClientResult<GetObjectAttributesResult, GetObjectAttributesError, Self::ClientError> {
+        self.refresh_express_session(bucket).await?;
         self.get_object_attributes(bucket, key, max_parts, part_number_marker, object_attributes)
             .await
     }
+
+    async fn presign_post_object(
+        &self,
+        bucket: &str,
+        key_prefix: &str,
+        conditions: &[PostPolicyCondition],
+        expires_in: Duration,
+    ) -> ObjectClientResult<PresignedPost, PresignPostError, Self::ClientError> {
+        self.presign_post_object(bucket, key_prefix, conditions, expires_in)
+            .await
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::S3ClientConfig;
-    use crate::S3CrtClient;
+    use super::*;
     use std::assert_eq;
 
     //test if the prefix is added correctly to the User-Agent header
@@ -648,4 +1082,157 @@ mod tests {
 
         assert_eq!(expected_user_agent, user_agent_header_value);
     }
+
+    #[test]
+    fn test_user_agent_with_suffix_and_app_id() {
+        let expected_user_agent = "someprefix mountpoint-s3-client somesuffix/1.0 app/my-app";
+
+        let config = S3ClientConfig {
+            user_agent_prefix: Some(String::from("someprefix")),
+            user_agent_suffix: Some(String::from("somesuffix/1.0")),
+            app_id: Some(String::from("my-app")),
+            ..Default::default()
+        };
+
+        let client = S3CrtClient::new("eu-west-1", config).expect("Create test client");
+
+        let mut message = client
+            .new_request_template("GET", "plutotestankit")
+            .expect("new request template expected");
+
+        let headers = message.inner.get_headers().expect("Expected a block of HTTP headers");
+
+        let user_agent_header = headers
+            .get("User-Agent")
+            .expect("User Agent Header expected with given suffix");
+        let user_agent_header_value = user_agent_header.value();
+
+        assert_eq!(expected_user_agent, user_agent_header_value);
+    }
+
+    fn error_result(response_status: i32, body: &str) -> MetaRequestResult {
+        MetaRequestResult {
+            response_status,
+            crt_error: 1i32.into(),
+            error_response_headers: None,
+            error_response_body: Some(body.into()),
+        }
+    }
+
+    #[test]
+    fn parse_error_responses() {
+        let cases = [
+            (
+                503,
+                r#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>SlowDown</Code><Message>Please reduce your request rate.</Message><RequestId>2C8E3CA4E1A7ED6B</RequestId></Error>"#,
+                S3ErrorCode::SlowDown,
+                Some("Please reduce your request rate."),
+                true,
+            ),
+            (
+                403,
+                r#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>AccessDenied</Code><Message>Access Denied</Message><RequestId>CM0R497NB0WAQ977</RequestId></Error>"#,
+                S3ErrorCode::AccessDenied,
+                Some("Access Denied"),
+                false,
+            ),
+            (
+                404,
+                r#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>NoSuchKey</Code><Message>The specified key does not exist.</Message><Key>test-key</Key></Error>"#,
+                S3ErrorCode::NoSuchKey,
+                Some("The specified key does not exist."),
+                false,
+            ),
+            (
+                400,
+                r#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>RequestTimeout</Code><Message>Your socket connection to the server was not read from or written to within the timeout period.</Message></Error>"#,
+                S3ErrorCode::RequestTimeout,
+                Some("Your socket connection to the server was not read from or written to within the timeout period."),
+                true,
+            ),
+            (
+                500,
+                r#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>InternalError</Code><Message>We encountered an internal error. Please try again.</Message></Error>"#,
+                S3ErrorCode::InternalError,
+                Some("We encountered an internal error. Please try again."),
+                true,
+            ),
+            (
+                400,
+                r#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>InvalidArgument</Code></Error>"#,
+                S3ErrorCode::Other("InvalidArgument".to_string()),
+                None,
+                false,
+            ),
+        ];
+
+        for (status, body, code, message, retryable) in cases {
+            let error = S3RequestError::ResponseError(error_result(status, body));
+            let response = error.error_response().expect("should parse");
+            assert_eq!(response.code, code);
+            assert_eq!(response.message.as_deref(), message);
+            assert_eq!(error.is_retryable(), retryable, "unexpected retryability for {code:?}");
+        }
+
+        let error = S3RequestError::ResponseError(error_result(403, ""));
+        assert_eq!(error.error_response(), None);
+    }
+
+    #[test]
+    fn parse_clock_skew() {
+        let body = r#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>RequestTimeTooSkewed</Code><Message>The difference between the request time and the current time is too large.</Message><RequestTime>20231016T120000Z</RequestTime><ServerTime>2023-10-16T12:20:30Z</ServerTime><MaxAllowedSkewMilliseconds>900000</MaxAllowedSkewMilliseconds><RequestId>4442587FB7D0A2F9</RequestId></Error>"#;
+        let result = error_result(403, body);
+        assert_eq!(
+            S3ErrorResponse::parse(&result).unwrap().code,
+            S3ErrorCode::RequestTimeTooSkewed
+        );
+
+        let error = S3RequestError::parse_clock_skew(&result).expect("should parse");
+        let S3RequestError::ClockSkew { local, server, skew } = error else {
+            panic!("unexpected error {error:?}");
+        };
+        assert_eq!(local, OffsetDateTime::parse("2023-10-16T12:00:00Z", &Rfc3339).unwrap());
+        assert_eq!(server, OffsetDateTime::parse("2023-10-16T12:20:30Z", &Rfc3339).unwrap());
+        assert_eq!(skew, -time::Duration::seconds(20 * 60 + 30));
+        assert!(!error.is_retryable());
+        assert!(error.to_string().contains("NTP"));
+
+        // Other errors are left alone
+        let body = r#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>AccessDenied</Code><Message>Access Denied</Message></Error>"#;
+        assert!(S3RequestError::parse_clock_skew(&error_result(403, body)).is_none());
+    }
+
+    #[test]
+    fn test_multi_region_access_point() {
+        let bucket = "arn:aws:s3::123456789012:accesspoint/mfzwi23gnjvgw.mrap";
+
+        let config = S3ClientConfig {
+            enable_multi_region_access_points: true,
+            ..Default::default()
+        };
+        let client = S3CrtClient::new("eu-west-1", config).expect("Create test client");
+        assert_eq!(client.signing_algorithm, Some(SigningAlgorithm::SigV4A));
+
+        let message = client
+            .new_request_template("GET", bucket)
+            .expect("new request template expected");
+        assert_eq!(
+            message.uri.as_os_str(),
+            "https://mfzwi23gnjvgw.mrap.accesspoint.s3-global.amazonaws.com"
+        );
+        let headers = message.inner.get_headers().expect("Expected a block of HTTP headers");
+        let host_header = headers.get("Host").expect("Host header expected");
+        assert_eq!(
+            host_header.value(),
+            "mfzwi23gnjvgw.mrap.accesspoint.s3-global.amazonaws.com"
+        );
+
+        // Without SigV4A, requests to the multi-region access point can't be signed
+        let client = S3CrtClient::new("eu-west-1", Default::default()).expect("Create test client");
+        assert_eq!(client.signing_algorithm, Some(SigningAlgorithm::SigV4));
+        assert!(matches!(
+            client.new_request_template("GET", bucket),
+            Err(ConstructionError::SigV4ARequired)
+        ));
+    }
 }
@@ -0,0 +1,102 @@
+use std::ops::Deref;
+use std::os::unix::prelude::OsStrExt;
+
+use mountpoint_s3_crt::s3::client::{MetaRequestResult, MetaRequestType};
+use tracing::debug;
+
+use crate::object_client::{AbortMultipartUploadError, AbortMultipartUploadResult, ObjectClientError};
+use crate::s3_crt_client::S3HttpRequest;
+use crate::{ObjectClientResult, S3CrtClient, S3RequestError};
+
+impl S3CrtClient {
+    /// Create and begin a new AbortMultipartUpload request.
+    pub async fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> ObjectClientResult<AbortMultipartUploadResult, AbortMultipartUploadError, S3RequestError> {
+        self.start_abort_multipart_upload(bucket, key, upload_id)?.await?;
+
+        Ok(AbortMultipartUploadResult {})
+    }
+
+    /// Begin a new AbortMultipartUpload request. The request is sent even if the returned future
+    /// is never polled, so this can abort an upload from somewhere that can't wait for it.
+    pub(super) fn start_abort_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> Result<S3HttpRequest<(), AbortMultipartUploadError>, S3RequestError> {
+        let span = request_span!(self, "abort_multipart_upload");
+        span.in_scope(|| debug!(?bucket, ?key, ?upload_id, "new request"));
+
+        let mut message = self
+            .new_request_template("DELETE", bucket)
+            .map_err(S3RequestError::construction_failure)?;
+        message
+            .set_request_path_and_query(format!("/{key}"), [("uploadId", upload_id)])
+            .map_err(S3RequestError::construction_failure)?;
+
+        self.make_meta_request(
+            message,
+            MetaRequestType::Default,
+            span,
+            |_, _| (),
+            |_, _| (),
+            move |result| {
+                if result.is_err() {
+                    let parsed = parse_abort_multipart_upload_error(&result);
+                    Err(parsed
+                        .map(ObjectClientError::ServiceError)
+                        .unwrap_or(ObjectClientError::ClientError(S3RequestError::ResponseError(result))))
+                } else {
+                    Ok(())
+                }
+            },
+        )
+    }
+}
+
+fn parse_abort_multipart_upload_error(result: &MetaRequestResult) -> Option<AbortMultipartUploadError> {
+    match result.response_status {
+        404 => {
+            let body = result.error_response_body.as_ref()?;
+            let root = xmltree::Element::parse(body.as_bytes()).ok()?;
+            let error_code = root.get_child("Code")?;
+            let error_str = error_code.get_text()?;
+
+            match error_str.deref() {
+                "NoSuchBucket" => Some(AbortMultipartUploadError::NoSuchBucket),
+                "NoSuchUpload" => Some(AbortMultipartUploadError::NoSuchUpload),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{OsStr, OsString};
+
+    use super::*;
+
+    fn make_result(response_status: i32, body: impl Into<OsString>) -> MetaRequestResult {
+        MetaRequestResult {
+            response_status,
+            crt_error: 1i32.into(),
+            error_response_headers: None,
+            error_response_body: Some(body.into()),
+        }
+    }
+
+    #[test]
+    fn parse_404_no_such_upload() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>NoSuchUpload</Code><Message>The specified upload does not exist. The upload ID may be invalid, or the upload may have been aborted or completed.</Message><UploadId>VXBsb2FkIElEIGZvciBlbHZpbmcncyBteS1tb3ZpZS5tMnRzIHVwbG9hZA</UploadId><RequestId>4YAYHJ0E82DDDNF0</RequestId><HostId>Ajn9+i3d3VWQi339YrGqBbJqQlj5HaX2vplXp9IlDPAxsJ4vsIAsje0P2gJ0of/mTKKz/fv9pNy9RqhbLUBc/g==</HostId></Error>"#;
+        let result = make_result(404, OsStr::from_bytes(&body[..]));
+        let result = parse_abort_multipart_upload_error(&result);
+        assert_eq!(result, Some(AbortMultipartUploadError::NoSuchUpload));
+    }
+}
@@ -0,0 +1,128 @@
+use std::ops::Deref;
+use std::os::unix::prelude::OsStrExt;
+
+use mountpoint_s3_crt::http::request_response::Header;
+use mountpoint_s3_crt::s3::client::{MetaRequestResult, MetaRequestType};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use tracing::debug;
+
+use crate::object_client::{CopyObjectError, CopyObjectResult, ObjectClientError};
+use crate::{ObjectClientResult, S3CrtClient, S3RequestError};
+
+/// Characters to escape in the `x-amz-copy-source` header, which is a URL-encoded path
+const URLENCODE_COPY_SOURCE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~')
+    .remove(b'/');
+
+impl S3CrtClient {
+    /// Create and begin a new CopyObject request. The CRT splits copies of large objects into
+    /// multiple UploadPartCopy requests.
+    pub async fn copy_object(
+        &self,
+        source_bucket: &str,
+        source_key: &str,
+        destination_bucket: &str,
+        destination_key: &str,
+    ) -> ObjectClientResult<CopyObjectResult, CopyObjectError, S3RequestError> {
+        let span = request_span!(self, "copy_object");
+        span.in_scope(|| {
+            debug!(
+                ?source_bucket,
+                ?source_key,
+                ?destination_bucket,
+                ?destination_key,
+                "new request"
+            )
+        });
+
+        // Scope the endpoint, message, etc. since otherwise rustc thinks we use Message across the await.
+        let request = {
+            let mut message = self
+                .new_request_template("PUT", destination_bucket)
+                .map_err(S3RequestError::construction_failure)?;
+            message
+                .set_request_path(format!("/{destination_key}"))
+                .map_err(S3RequestError::construction_failure)?;
+            let copy_source =
+                utf8_percent_encode(&format!("/{source_bucket}/{source_key}"), URLENCODE_COPY_SOURCE).to_string();
+            message
+                .add_header(&Header::new("x-amz-copy-source", copy_source))
+                .map_err(S3RequestError::construction_failure)?;
+
+            self.make_meta_request(
+                message,
+                MetaRequestType::CopyObject,
+                span,
+                |_, _| (),
+                |_, _| (),
+                move |result| {
+                    if result.is_err() {
+                        let parsed = parse_copy_object_error(&result);
+                        Err(parsed
+                            .map(ObjectClientError::ServiceError)
+                            .unwrap_or(ObjectClientError::ClientError(S3RequestError::ResponseError(result))))
+                    } else {
+                        Ok(())
+                    }
+                },
+            )?
+        };
+
+        request.await?;
+
+        Ok(CopyObjectResult {})
+    }
+}
+
+fn parse_copy_object_error(result: &MetaRequestResult) -> Option<CopyObjectError> {
+    match result.response_status {
+        404 => {
+            let body = result.error_response_body.as_ref()?;
+            let root = xmltree::Element::parse(body.as_bytes()).ok()?;
+            let error_code = root.get_child("Code")?;
+            let error_str = error_code.get_text()?;
+
+            match error_str.deref() {
+                "NoSuchBucket" => Some(CopyObjectError::NoSuchBucket),
+                "NoSuchKey" => Some(CopyObjectError::NoSuchKey),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{OsStr, OsString};
+
+    use super::*;
+
+    fn make_result(response_status: i32, body: impl Into<OsString>) -> MetaRequestResult {
+        MetaRequestResult {
+            response_status,
+            crt_error: 1i32.into(),
+            error_response_headers: None,
+            error_response_body: Some(body.into()),
+        }
+    }
+
+    #[test]
+    fn parse_404_no_such_key() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>NoSuchKey</Code><Message>The specified key does not exist.</Message><Key>not-a-key</Key><RequestId>NN0K6KYG4ZJXHEPT</RequestId><HostId>rnSSzGjsKcmDCFfsPpv+ezMSqd5R8ezqCBCvhbZWDNNUVcxeeCHb5dOmzIuEeWkpp5RltmXZL3s=</HostId></Error>"#;
+        let result = make_result(404, OsStr::from_bytes(&body[..]));
+        let result = parse_copy_object_error(&result);
+        assert_eq!(result, Some(CopyObjectError::NoSuchKey));
+    }
+
+    #[test]
+    fn parse_403() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>AccessDenied</Code><Message>Access Denied</Message><RequestId>3N6HSCDYNRC0NEW0</RequestId><HostId>fUFmlaKqFCuGq7oCfnAyFSjBVt/P7+pvmKGcPbdnrHDY9MRB+P7qhHHiyQ2XpWI3OloKtJZWb0U=</HostId></Error>"#;
+        let result = make_result(403, OsStr::from_bytes(&body[..]));
+        let result = parse_copy_object_error(&result);
+        assert_eq!(result, None);
+    }
+}
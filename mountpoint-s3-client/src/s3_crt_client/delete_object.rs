@@ -1,5 +1,6 @@
 use std::ops::Deref;
 use std::os::unix::prelude::OsStrExt;
+use std::sync::{Arc, Mutex};
 
 use mountpoint_s3_crt::s3::client::{MetaRequestResult, MetaRequestType};
 use tracing::debug;
@@ -13,30 +14,51 @@ impl S3CrtClient {
         &self,
         bucket: &str,
         key: &str,
+        version_id: Option<&str>,
     ) -> ObjectClientResult<DeleteObjectResult, DeleteObjectError, S3RequestError> {
         let span = request_span!(self, "delete_object");
-        span.in_scope(|| debug!(?bucket, ?key, "new request"));
+        span.in_scope(|| debug!(?bucket, ?key, ?version_id, "new request"));
 
         // Scope the endpoint, message, etc. since otherwise rustc thinks we use Message across the await.
         let request = {
             let mut message = self
                 .new_request_template("DELETE", bucket)
                 .map_err(S3RequestError::construction_failure)?;
+            let query = version_id.map(|v| ("versionId", v)).into_iter().collect::<Vec<_>>();
             message
-                .set_request_path(format!("/{key}"))
+                .set_request_path_and_query(format!("/{key}"), query)
                 .map_err(S3RequestError::construction_failure)?;
 
-            self.make_simple_http_request(message, MetaRequestType::Default, span, |result| {
-                let parsed = parse_delete_object_error(&result);
-                parsed
-                    .map(ObjectClientError::ServiceError)
-                    .unwrap_or(ObjectClientError::ClientError(S3RequestError::ResponseError(result)))
-            })?
+            // Stash the version id from the response headers so we can return it when we finish.
+            let version_id: Arc<Mutex<Option<String>>> = Default::default();
+            let version_id_clone = Arc::clone(&version_id);
+
+            self.make_meta_request(
+                message,
+                MetaRequestType::Default,
+                span,
+                move |headers, _status| {
+                    if let Ok(header) = headers.get("x-amz-version-id") {
+                        *version_id_clone.lock().unwrap() = Some(header.value().to_string_lossy().to_string());
+                    }
+                },
+                |_, _| (),
+                move |result| {
+                    if result.is_err() {
+                        let parsed = parse_delete_object_error(&result);
+                        Err(parsed
+                            .map(ObjectClientError::ServiceError)
+                            .unwrap_or(ObjectClientError::ClientError(S3RequestError::ResponseError(result))))
+                    } else {
+                        Ok(version_id.lock().unwrap().take())
+                    }
+                },
+            )?
         };
 
-        let _body = request.await?;
+        let version_id = request.await?;
 
-        Ok(DeleteObjectResult {})
+        Ok(DeleteObjectResult { version_id })
     }
 }
 
@@ -54,6 +76,19 @@ fn parse_delete_object_error(result: &MetaRequestResult) -> Option<DeleteObjectE
                 _ => None,
             }
         }
+        403 => {
+            // S3 doesn't have a separate error code for Object Lock, so tell it apart from other
+            // permission errors by the message
+            let body = result.error_response_body.as_ref()?;
+            let root = xmltree::Element::parse(body.as_bytes()).ok()?;
+            let error_str = root.get_child("Code")?.get_text()?;
+            let message = root.get_child("Message")?.get_text()?;
+            if error_str == "AccessDenied" && message.contains("object lock") {
+                Some(DeleteObjectError::ObjectLocked)
+            } else {
+                None
+            }
+        }
         _ => None,
     }
 }
@@ -88,4 +123,12 @@ mod tests {
         let result = parse_delete_object_error(&result);
         assert_eq!(result, None);
     }
+
+    #[test]
+    fn parse_403_object_locked() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>AccessDenied</Code><Message>Access Denied because object protected by object lock.</Message><RequestId>3N6HSCDYNRC0NEW0</RequestId><HostId>fUFmlaKqFCuGq7oCfnAyFSjBVt/P7+pvmKGcPbdnrHDY9MRB+P7qhHHiyQ2XpWI3OloKtJZWb0U=</HostId></Error>"#;
+        let result = make_result(403, OsStr::from_bytes(&body[..]));
+        let result = parse_delete_object_error(&result);
+        assert_eq!(result, Some(DeleteObjectError::ObjectLocked));
+    }
 }
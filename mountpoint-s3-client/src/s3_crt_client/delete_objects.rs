@@ -0,0 +1,167 @@
+use std::ops::Deref;
+use std::os::unix::prelude::OsStrExt;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use md5::{Digest, Md5};
+use mountpoint_s3_crt::http::request_response::Header;
+use mountpoint_s3_crt::io::stream::InputStream;
+use mountpoint_s3_crt::s3::client::{MetaRequestResult, MetaRequestType};
+use tracing::debug;
+
+use crate::object_client::{
+    DeleteObjectsError, DeleteObjectsFailure, DeleteObjectsResult, ObjectClientError, MAX_DELETE_OBJECTS_KEYS,
+};
+use crate::s3_crt_client::list_objects::{get_field, ParseError};
+use crate::{ObjectClientResult, S3CrtClient, S3RequestError};
+
+impl DeleteObjectsResult {
+    fn parse_from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let mut element = xmltree::Element::parse(bytes)?;
+
+        let mut deleted = Vec::new();
+        while let Some(child) = element.take_child("Deleted") {
+            deleted.push(get_field(&child, "Key")?);
+        }
+
+        let mut errors = Vec::new();
+        while let Some(child) = element.take_child("Error") {
+            errors.push(DeleteObjectsFailure {
+                key: get_field(&child, "Key")?,
+                code: get_field(&child, "Code")?,
+                message: get_field(&child, "Message").unwrap_or_default(),
+            });
+        }
+
+        Ok(Self { deleted, errors })
+    }
+}
+
+impl S3CrtClient {
+    /// Create and begin a new DeleteObjects request.
+    pub async fn delete_objects(
+        &self,
+        bucket: &str,
+        keys: &[String],
+    ) -> ObjectClientResult<DeleteObjectsResult, DeleteObjectsError, S3RequestError> {
+        if keys.len() > MAX_DELETE_OBJECTS_KEYS {
+            return Err(ObjectClientError::ServiceError(DeleteObjectsError::TooManyKeys(
+                keys.len(),
+            )));
+        }
+
+        let span = request_span!(self, "delete_objects");
+        span.in_scope(|| debug!(?bucket, num_keys = keys.len(), "new request"));
+
+        let request_body = build_request_body(keys);
+
+        // Scope the endpoint, message, etc. since otherwise rustc thinks we use Message across the await.
+        let body = {
+            let mut message = self
+                .new_request_template("POST", bucket)
+                .map_err(S3RequestError::construction_failure)?;
+
+            message
+                .add_header(&Header::new("Content-Length", request_body.len().to_string()))
+                .map_err(S3RequestError::construction_failure)?;
+
+            // DeleteObjects requires an integrity check of the request body
+            let content_md5 = BASE64.encode(Md5::digest(request_body.as_bytes()));
+            message
+                .add_header(&Header::new("Content-MD5", content_md5))
+                .map_err(S3RequestError::construction_failure)?;
+
+            message
+                .set_request_path_and_query("/", [("delete", "")])
+                .map_err(S3RequestError::construction_failure)?;
+
+            let body_input_stream = InputStream::new_from_slice(&self.allocator, request_body.as_bytes())
+                .map_err(S3RequestError::CrtError)?;
+            message.set_body_stream(Some(body_input_stream));
+
+            self.make_simple_http_request(message, MetaRequestType::Default, span, |result| {
+                let parsed = parse_delete_objects_error(&result);
+                parsed
+                    .map(ObjectClientError::ServiceError)
+                    .unwrap_or(ObjectClientError::ClientError(S3RequestError::ResponseError(result)))
+            })?
+        };
+
+        let body = body.await?;
+
+        DeleteObjectsResult::parse_from_bytes(&body)
+            .map_err(|e| ObjectClientError::ClientError(S3RequestError::InternalError(e.into())))
+    }
+}
+
+/// Build the XML document listing the keys to delete
+fn build_request_body(keys: &[String]) -> String {
+    let mut body = String::from(r#"<Delete xmlns="http://s3.amazonaws.com/doc/2006-03-01/">"#);
+    for key in keys {
+        body.push_str("<Object><Key>");
+        body.push_str(&escape_xml(key));
+        body.push_str("</Key></Object>");
+    }
+    body.push_str("</Delete>");
+    body
+}
+
+fn escape_xml(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn parse_delete_objects_error(result: &MetaRequestResult) -> Option<DeleteObjectsError> {
+    match result.response_status {
+        404 => {
+            let body = result.error_response_body.as_ref()?;
+            let root = xmltree::Element::parse(body.as_bytes()).ok()?;
+            let error_code = root.get_child("Code")?;
+            let error_str = error_code.get_text()?;
+            match error_str.deref() {
+                "NoSuchBucket" => Some(DeleteObjectsError::NoSuchBucket),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_body_escapes_keys() {
+        let keys = ["a".to_owned(), "b&<c>".to_owned()];
+        assert_eq!(
+            build_request_body(&keys),
+            r#"<Delete xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Object><Key>a</Key></Object><Object><Key>b&amp;&lt;c&gt;</Key></Object></Delete>"#
+        );
+    }
+
+    #[test]
+    fn parse_partial_failure() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><DeleteResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Deleted><Key>a</Key></Deleted><Error><Key>b</Key><Code>AccessDenied</Code><Message>Access Denied</Message></Error><Deleted><Key>c</Key></Deleted></DeleteResult>"#;
+        let result = DeleteObjectsResult::parse_from_bytes(&body[..]).unwrap();
+        assert_eq!(result.deleted, ["a", "c"]);
+        assert_eq!(
+            result.errors,
+            [DeleteObjectsFailure {
+                key: "b".to_owned(),
+                code: "AccessDenied".to_owned(),
+                message: "Access Denied".to_owned(),
+            }]
+        );
+    }
+}
@@ -1,22 +1,32 @@
 use std::future::Future;
 use std::ops::Deref;
-use std::ops::Range;
 use std::os::unix::prelude::OsStrExt;
 use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
 
 use futures::channel::mpsc::UnboundedReceiver;
 use futures::Stream;
 use mountpoint_s3_crt::common::error::Error;
-use mountpoint_s3_crt::http::request_response::Header;
+use mountpoint_s3_crt::http::request_response::{Header, Headers};
 use mountpoint_s3_crt::s3::client::{MetaRequestResult, MetaRequestType};
 use pin_project::pin_project;
+use time::format_description::well_known::Rfc2822;
+use time::{OffsetDateTime, UtcOffset};
 use tracing::debug;
 
-use crate::object_client::{GetBodyPart, GetObjectError, ObjectClientError};
+use crate::object_client::{
+    GetBodyPart, GetObjectError, GetObjectMetadata, GetObjectParams, GetObjectProgress, ObjectClientError,
+    ObjectMetadataError,
+};
+use crate::s3_crt_client::head_object::{get_field, ParseError};
 use crate::s3_crt_client::S3HttpRequest;
-use crate::ETag;
-use crate::{ObjectClientResult, S3CrtClient, S3RequestError};
+use crate::{ETag, ObjectClientResult, S3CrtClient, S3RequestError};
+
+/// Metadata parsed from the headers of a successful response, shared with [GetObjectRequest]
+type SharedMetadata = Arc<Mutex<Option<Result<GetObjectMetadata, ObjectMetadataError>>>>;
 
 impl S3CrtClient {
     /// Create and begin a new GetObject request. The returned [GetObjectRequest] is a [Stream] (for
@@ -26,12 +36,11 @@ impl S3CrtClient {
         &self,
         bucket: &str,
         key: &str,
-        range: Option<Range<u64>>,
-        if_match: Option<ETag>,
+        params: &GetObjectParams,
     ) -> Result<GetObjectRequest, ObjectClientError<GetObjectError, S3RequestError>> {
         let span = request_span!(self, "get_object");
         span.in_scope(
-            || debug!(?bucket, ?key, ?range, ?if_match, size=?range.as_ref().map(|range| range.end - range.start), "new request"),
+            || debug!(?bucket, ?key, ?params, size=?params.range.as_ref().map(|range| range.end - range.start), "new request"),
         );
 
         let mut message = self
@@ -43,7 +52,7 @@ impl S3CrtClient {
             .add_header(&Header::new("accept", "*/*"))
             .map_err(S3RequestError::construction_failure)?;
 
-        if let Some(range) = range {
+        if let Some(range) = &params.range {
             // Range HTTP header is bounded below *inclusive*
             let range_value = format!("bytes={}-{}", range.start, range.end.saturating_sub(1));
             message
@@ -51,26 +60,68 @@ impl S3CrtClient {
                 .map_err(S3RequestError::construction_failure)?;
         }
 
-        if let Some(etag) = if_match {
+        if let Some(etag) = &params.if_match {
             // Return the object only if its entity tag (ETag) is matched
             message
                 .add_header(&Header::new("If-Match", etag.as_str()))
                 .map_err(S3RequestError::construction_failure)?;
         }
 
+        if let Some(time) = params.if_modified_since {
+            // Return the object only if it has been modified since the specified time
+            let value = format_http_date(time);
+            message
+                .add_header(&Header::new("If-Modified-Since", value))
+                .map_err(S3RequestError::construction_failure)?;
+        }
+
+        if let Some(time) = params.if_unmodified_since {
+            // Return the object only if it has not been modified since the specified time
+            let value = format_http_date(time);
+            message
+                .add_header(&Header::new("If-Unmodified-Since", value))
+                .map_err(S3RequestError::construction_failure)?;
+        }
+
+        if let Some(sse_customer_key) = &params.sse_customer_key {
+            for (name, value) in sse_customer_key.headers() {
+                message
+                    .add_header(&Header::new(name, value))
+                    .map_err(S3RequestError::construction_failure)?;
+            }
+        }
+
         let key = format!("/{key}");
+        let query = params
+            .version_id
+            .as_deref()
+            .map(|v| ("versionId", v))
+            .into_iter()
+            .collect::<Vec<_>>();
         message
-            .set_request_path(key)
+            .set_request_path_and_query(key, query)
             .map_err(S3RequestError::construction_failure)?;
 
         let (sender, receiver) = futures::channel::mpsc::unbounded();
+        let bytes_transferred = Arc::new(AtomicU64::new(0));
+        let bytes_transferred_clone = Arc::clone(&bytes_transferred);
+
+        let metadata: SharedMetadata = Default::default();
+        let metadata_headers = Arc::clone(&metadata);
 
         let request = self.make_meta_request(
             message,
             MetaRequestType::GetObject,
             span,
-            |_, _| (),
+            move |headers, status| {
+                if (200..300).contains(&status) {
+                    let parsed =
+                        parse_object_metadata(headers).map_err(|e| ObjectMetadataError::Invalid(e.to_string()));
+                    *metadata_headers.lock().unwrap() = Some(parsed);
+                }
+            },
             move |offset, data| {
+                bytes_transferred_clone.fetch_add(data.len() as u64, Ordering::SeqCst);
                 let _ = sender.unbounded_send(Ok((offset, data.into())));
             },
             move |result| {
@@ -89,6 +140,8 @@ impl S3CrtClient {
             request,
             finish_receiver: receiver,
             finished: false,
+            bytes_transferred,
+            metadata,
         })
     }
 }
@@ -101,6 +154,41 @@ pub struct GetObjectRequest {
     #[pin]
     finish_receiver: UnboundedReceiver<Result<GetBodyPart, Error>>,
     finished: bool,
+    bytes_transferred: Arc<AtomicU64>,
+    metadata: SharedMetadata,
+}
+
+/// The CRT retries failed parts internally and only delivers the body of the attempt that
+/// succeeded, so retried bytes aren't visible here.
+impl GetObjectProgress for GetObjectRequest {
+    fn bytes_transferred(&self) -> u64 {
+        self.bytes_transferred.load(Ordering::SeqCst)
+    }
+
+    fn object_metadata(&self) -> Option<Result<GetObjectMetadata, ObjectMetadataError>> {
+        self.metadata.lock().unwrap().clone()
+    }
+}
+
+/// Parse the metadata of the whole object from the headers of a GetObject response. The CRT
+/// rewrites the headers of the parts it fetched to look like a response to the request we made, so
+/// a ranged request has a `Content-Range` header with the object's size, like `bytes 0-0/1234`, and
+/// a request for the whole object just has a `Content-Length`.
+fn parse_object_metadata(headers: &Headers) -> Result<GetObjectMetadata, ParseError> {
+    let object_size = if headers.has_header("Content-Range") {
+        let value = get_field(headers, "Content-Range")?;
+        let size = value
+            .rsplit_once('/')
+            .map(|(_, size)| size)
+            .ok_or_else(|| ParseError::Invalid(value.clone().into()))?;
+        u64::from_str(size).map_err(|e| ParseError::Int(e, "ContentRange".into()))?
+    } else {
+        u64::from_str(&get_field(headers, "Content-Length")?).map_err(|e| ParseError::Int(e, "ContentLength".into()))?
+    };
+    let etag = ETag::from_str(&get_field(headers, "Etag")?).expect("parsing an ETag is infallible");
+    let last_modified = OffsetDateTime::parse(&get_field(headers, "Last-Modified")?, &Rfc2822)
+        .map_err(|e| ParseError::OffsetDateTime(e, "LastModified".into()))?;
+    Ok(GetObjectMetadata::new(object_size, etag, last_modified))
 }
 
 impl Stream for GetObjectRequest {
@@ -131,8 +219,24 @@ impl Stream for GetObjectRequest {
     }
 }
 
+/// Format a timestamp as an HTTP-date (RFC 7231 IMF-fixdate), e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+fn format_http_date(time: OffsetDateTime) -> String {
+    let time = time.to_offset(UtcOffset::UTC);
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+        &time.weekday().to_string()[..3],
+        time.day(),
+        &time.month().to_string()[..3],
+        time.year(),
+        time.hour(),
+        time.minute(),
+        time.second(),
+    )
+}
+
 fn parse_get_object_error(result: &MetaRequestResult) -> Option<GetObjectError> {
     match result.response_status {
+        304 => Some(GetObjectError::NotModified),
         404 => {
             let body = result.error_response_body.as_ref()?;
             let root = xmltree::Element::parse(body.as_bytes()).ok()?;
@@ -144,7 +248,18 @@ fn parse_get_object_error(result: &MetaRequestResult) -> Option<GetObjectError>
                 _ => None,
             }
         }
+        403 => {
+            let body = result.error_response_body.as_ref()?;
+            let root = xmltree::Element::parse(body.as_bytes()).ok()?;
+            let error_code = root.get_child("Code")?;
+            let error_str = error_code.get_text()?;
+            match error_str.deref() {
+                "AccessDenied" => Some(GetObjectError::AccessDenied),
+                _ => None,
+            }
+        }
         412 => Some(GetObjectError::PreconditionFailed),
+        416 => Some(GetObjectError::InvalidRange),
         _ => None,
     }
 }
@@ -180,6 +295,69 @@ mod tests {
         assert_eq!(result, Some(GetObjectError::NoSuchBucket));
     }
 
+    #[test]
+    fn parse_304_not_modified() {
+        let result = make_result(304, "");
+        let result = parse_get_object_error(&result);
+        assert_eq!(result, Some(GetObjectError::NotModified));
+    }
+
+    #[test]
+    fn parse_403_access_denied() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>AccessDenied</Code><Message>Access Denied</Message><RequestId>3N6HSCDYNRC0NEW0</RequestId><HostId>fUFmlaKqFCuGq7oCfnAyFSjBVt/P7+pvmKGcPbdnrHDY9MRB+P7qhHHiyQ2XpWI3OloKtJZWb0U=</HostId></Error>"#;
+        let result = make_result(403, OsStr::from_bytes(&body[..]));
+        let result = parse_get_object_error(&result);
+        assert_eq!(result, Some(GetObjectError::AccessDenied));
+    }
+
+    #[test]
+    fn parse_416_invalid_range() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>InvalidRange</Code><Message>The requested range is not satisfiable</Message><RangeRequested>bytes=0-0</RangeRequested><ActualObjectSize>0</ActualObjectSize><RequestId>1V2KAF4SBGTD4SQ0</RequestId></Error>"#;
+        let result = make_result(416, OsStr::from_bytes(&body[..]));
+        let result = parse_get_object_error(&result);
+        assert_eq!(result, Some(GetObjectError::InvalidRange));
+    }
+
+    #[test]
+    fn parse_metadata_headers() {
+        let make_headers = |headers: &[(&str, &str)]| {
+            let mut all_headers = Headers::new(&Default::default()).unwrap();
+            for (name, value) in [
+                ("Etag", "\"3858f62230ac3c915f300c664312c63f\""),
+                ("Last-Modified", "Sun, 06 Nov 1994 08:49:37 GMT"),
+            ]
+            .iter()
+            .chain(headers)
+            {
+                all_headers.add_header(&Header::new(name, value)).unwrap();
+            }
+            all_headers
+        };
+
+        let ranged = make_headers(&[("Content-Length", "1"), ("Content-Range", "bytes 0-0/1234")]);
+        let metadata = parse_object_metadata(&ranged).unwrap();
+        assert_eq!(metadata.object_size, 1234);
+        assert_eq!(metadata.etag.as_str(), "\"3858f62230ac3c915f300c664312c63f\"");
+        assert_eq!(
+            metadata.last_modified,
+            OffsetDateTime::from_unix_timestamp(784111777).unwrap()
+        );
+
+        let whole = make_headers(&[("Content-Length", "1234")]);
+        assert_eq!(parse_object_metadata(&whole).unwrap().object_size, 1234);
+
+        let unknown_size = make_headers(&[("Content-Range", "bytes 0-0/*")]);
+        assert!(parse_object_metadata(&unknown_size).is_err());
+    }
+
+    #[test]
+    fn format_http_date_header() {
+        let time = OffsetDateTime::from_unix_timestamp(784111777).unwrap();
+        assert_eq!(format_http_date(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+        let time = time.to_offset(UtcOffset::from_hms(2, 0, 0).unwrap());
+        assert_eq!(format_http_date(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+    }
+
     #[test]
     fn parse_403_glacier_storage_class() {
         let body = br#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>InvalidObjectState</Code><Message>The action is not valid for the object's storage class</Message><RequestId>9FEFFF118E15B86F</RequestId><HostId>WVQ5kzhiT+oiUfDCOiOYv8W4Tk9eNcxWi/MK+hTS/av34Xy4rBU3zsavf0aaaaa</HostId></Error>"#;
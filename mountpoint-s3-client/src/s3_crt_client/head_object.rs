@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::ffi::OsString;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
@@ -5,11 +6,14 @@ use std::sync::{Arc, Mutex};
 use mountpoint_s3_crt::http::request_response::{Headers, HeadersError};
 use mountpoint_s3_crt::s3::client::{MetaRequestResult, MetaRequestType};
 use thiserror::Error;
-use time::format_description::well_known::Rfc2822;
+use time::format_description::well_known::{Rfc2822, Rfc3339};
 use time::OffsetDateTime;
-use tracing::{debug, error};
+use tracing::{debug, error, warn};
 
-use crate::object_client::{HeadObjectError, HeadObjectResult, ObjectClientError, ObjectClientResult, ObjectInfo};
+use crate::object_client::{
+    HeadObjectError, HeadObjectResult, ObjectClientError, ObjectClientResult, ObjectExpiration, ObjectInfo,
+    ObjectLockMode,
+};
 use crate::s3_crt_client::S3RequestError;
 use crate::S3CrtClient;
 
@@ -27,9 +31,15 @@ pub enum ParseError {
 
     #[error("Failed to parse field {1} as an int: {0:?}")]
     Int(#[source] std::num::ParseIntError, String),
+
+    #[error("Failed to parse expiration header: {0:?}")]
+    Expiration(String),
+
+    #[error("Unknown Object Lock mode: {0:?}")]
+    ObjectLockMode(String),
 }
 
-fn get_field(headers: &Headers, name: &str) -> Result<String, ParseError> {
+pub(crate) fn get_field(headers: &Headers, name: &str) -> Result<String, ParseError> {
     let header = headers.get(name)?;
     let value = header.value();
     if let Some(s) = value.to_str() {
@@ -39,6 +49,68 @@ fn get_field(headers: &Headers, name: &str) -> Result<String, ParseError> {
     }
 }
 
+/// Parse the value of an `x-amz-expiration` header, which looks like
+/// `expiry-date="Fri, 23 Dec 2012 00:00:00 GMT", rule-id="picture-deletion-rule"`.
+fn parse_expiration(value: &str) -> Result<ObjectExpiration, ParseError> {
+    let mut expiry_date = None;
+    let mut rule_id = None;
+
+    // Values are quoted and may themselves contain commas (the date does), so we can't just split
+    // on commas. Instead, walk through the `name="value"` pairs one at a time.
+    let mut rest = value;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+        if rest.is_empty() {
+            break;
+        }
+        let (name, remaining) = rest
+            .split_once("=\"")
+            .ok_or_else(|| ParseError::Expiration(value.to_string()))?;
+        let (field, remaining) = remaining
+            .split_once('"')
+            .ok_or_else(|| ParseError::Expiration(value.to_string()))?;
+        match name.trim() {
+            "expiry-date" => expiry_date = Some(field),
+            "rule-id" => rule_id = Some(field),
+            _ => {}
+        }
+        rest = remaining;
+    }
+
+    let expiry_date = expiry_date.ok_or_else(|| ParseError::Expiration(value.to_string()))?;
+    let expiry =
+        OffsetDateTime::parse(expiry_date, &Rfc2822).map_err(|e| ParseError::OffsetDateTime(e, "Expiration".into()))?;
+    let rule_id = rule_id.ok_or_else(|| ParseError::Expiration(value.to_string()))?;
+
+    Ok(ObjectExpiration {
+        expiry,
+        rule_id: rule_id.to_string(),
+    })
+}
+
+/// Parse the `x-amz-expiration` header if present. The expiration is informational only, so a
+/// malformed or unrecognized value is logged and ignored rather than failing the whole request.
+fn parse_expiration_from_hdr(headers: &Headers) -> Option<ObjectExpiration> {
+    if !headers.has_header("x-amz-expiration") {
+        return None;
+    }
+    match get_field(headers, "x-amz-expiration").and_then(|value| parse_expiration(&value)) {
+        Ok(expiration) => Some(expiration),
+        Err(err) => {
+            warn!(?err, "ignoring unparseable x-amz-expiration header");
+            None
+        }
+    }
+}
+
+fn parse_object_lock_mode(value: &str) -> Result<ObjectLockMode, ParseError> {
+    match value {
+        "GOVERNANCE" => Ok(ObjectLockMode::Governance),
+        "COMPLIANCE" => Ok(ObjectLockMode::Compliance),
+        _ => Err(ParseError::ObjectLockMode(value.to_string())),
+    }
+}
+
 impl HeadObjectResult {
     fn parse_from_hdr(bucket: String, key: String, headers: &Headers) -> Result<Self, ParseError> {
         let last_modified = OffsetDateTime::parse(&get_field(headers, "Last-Modified")?, &Rfc2822)
@@ -46,22 +118,78 @@ impl HeadObjectResult {
         let size = u64::from_str(&get_field(headers, "Content-Length")?)
             .map_err(|e| ParseError::Int(e, "ContentLength".into()))?;
         let etag = get_field(headers, "Etag")?;
+        let expiration = parse_expiration_from_hdr(headers);
+        let version_id = if headers.has_header("x-amz-version-id") {
+            Some(get_field(headers, "x-amz-version-id")?)
+        } else {
+            None
+        };
+        let bucket_key_enabled = headers.has_header("x-amz-server-side-encryption-bucket-key-enabled")
+            && get_field(headers, "x-amz-server-side-encryption-bucket-key-enabled")?.eq_ignore_ascii_case("true");
+        let object_lock_mode = if headers.has_header("x-amz-object-lock-mode") {
+            Some(parse_object_lock_mode(&get_field(headers, "x-amz-object-lock-mode")?)?)
+        } else {
+            None
+        };
+        let object_lock_retain_until = if headers.has_header("x-amz-object-lock-retain-until-date") {
+            let value = get_field(headers, "x-amz-object-lock-retain-until-date")?;
+            Some(
+                OffsetDateTime::parse(&value, &Rfc3339)
+                    .map_err(|e| ParseError::OffsetDateTime(e, "ObjectLockRetainUntilDate".into()))?,
+            )
+        } else {
+            None
+        };
+        let object_lock_legal_hold = headers.has_header("x-amz-object-lock-legal-hold")
+            && get_field(headers, "x-amz-object-lock-legal-hold")? == "ON";
+        let content_encoding = if headers.has_header("Content-Encoding") {
+            Some(get_field(headers, "Content-Encoding")?)
+        } else {
+            None
+        };
+        let user_metadata = parse_user_metadata(headers);
         let object = ObjectInfo {
             key,
             size,
             last_modified,
             storage_class: None, // head_object responses do not contain storage class
             etag,
+            expiration,
+            version_id,
         };
-        Ok(HeadObjectResult { bucket, object })
+        Ok(HeadObjectResult {
+            bucket,
+            object,
+            bucket_key_enabled,
+            object_lock_mode,
+            object_lock_retain_until,
+            object_lock_legal_hold,
+            content_encoding,
+            user_metadata,
+        })
     }
 }
 
+/// Collect the `x-amz-meta-*` headers into a map from lower-case metadata names to values. Values
+/// that aren't valid UTF-8 are skipped.
+fn parse_user_metadata(headers: &Headers) -> HashMap<String, String> {
+    const PREFIX: &str = "x-amz-meta-";
+    headers
+        .iter()
+        .filter_map(|(name, value)| {
+            let name = name.into_string().ok()?.to_ascii_lowercase();
+            let name = name.strip_prefix(PREFIX)?.to_owned();
+            Some((name, value.into_string().ok()?))
+        })
+        .collect()
+}
+
 impl S3CrtClient {
     pub async fn head_object(
         &self,
         bucket: &str,
         key: &str,
+        version_id: Option<&str>,
     ) -> ObjectClientResult<HeadObjectResult, HeadObjectError, S3RequestError> {
         let request = {
             let mut message = self
@@ -69,8 +197,9 @@ impl S3CrtClient {
                 .map_err(S3RequestError::construction_failure)?;
 
             let key = key.to_string();
+            let query = version_id.map(|v| ("versionId", v)).into_iter().collect::<Vec<_>>();
             message
-                .set_request_path(format!("/{key}"))
+                .set_request_path_and_query(format!("/{key}"), query)
                 .map_err(S3RequestError::construction_failure)?;
 
             let bucket = bucket.to_owned();
@@ -81,7 +210,7 @@ impl S3CrtClient {
             let header1 = header.clone();
 
             let span = request_span!(self, "head_object");
-            span.in_scope(|| debug!(?bucket, ?key, "new request"));
+            span.in_scope(|| debug!(?bucket, ?key, ?version_id, "new request"));
 
             self.make_meta_request(
                 message,
@@ -121,6 +250,7 @@ impl S3CrtClient {
 fn parse_head_object_error(result: &MetaRequestResult) -> Option<HeadObjectError> {
     match result.response_status {
         404 => Some(HeadObjectError::NotFound),
+        403 => Some(HeadObjectError::AccessDenied),
         _ => None,
     }
 }
@@ -129,6 +259,8 @@ fn parse_head_object_error(result: &MetaRequestResult) -> Option<HeadObjectError
 mod tests {
     use std::ffi::OsString;
 
+    use mountpoint_s3_crt::http::request_response::Header;
+
     use super::*;
 
     fn make_result(response_status: i32, body: impl Into<OsString>) -> MetaRequestResult {
@@ -151,6 +283,62 @@ mod tests {
     fn parse_403() {
         let result = make_result(403, "");
         let result = parse_head_object_error(&result);
-        assert_eq!(result, None);
+        assert_eq!(result, Some(HeadObjectError::AccessDenied));
+    }
+
+    #[test]
+    fn parse_user_metadata_headers() {
+        let mut headers = Headers::new(&Default::default()).unwrap();
+        for (name, value) in [
+            ("Content-Length", "20"),
+            ("x-amz-meta-logical-size", "1024"),
+            ("X-Amz-Meta-Owner", "alice"),
+        ] {
+            headers.add_header(&Header::new(name, value)).unwrap();
+        }
+        let metadata = parse_user_metadata(&headers);
+        assert_eq!(
+            metadata,
+            HashMap::from([
+                ("logical-size".to_string(), "1024".to_string()),
+                ("owner".to_string(), "alice".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_expiration_header() {
+        // Example from https://docs.aws.amazon.com/AmazonS3/latest/API/API_HeadObject.html
+        let header = r#"expiry-date="Fri, 23 Dec 2012 00:00:00 GMT", rule-id="picture-deletion-rule""#;
+        let expiration = parse_expiration(header).expect("should parse");
+        assert_eq!(
+            expiration.expiry,
+            OffsetDateTime::from_unix_timestamp(1356220800).unwrap()
+        );
+        assert_eq!(expiration.rule_id, "picture-deletion-rule");
+    }
+
+    #[test]
+    fn parse_invalid_expiration_header() {
+        assert!(parse_expiration(r#"rule-id="picture-deletion-rule""#).is_err());
+        assert!(parse_expiration(r#"expiry-date="Fri, 23 Dec 2012 00:00:00 GMT"#).is_err());
+        assert!(parse_expiration(r#"expiry-date="not a date", rule-id="rule""#).is_err());
+    }
+
+    #[test]
+    fn invalid_expiration_header_is_ignored() {
+        let mut headers = Headers::new(&Default::default()).unwrap();
+        for (name, value) in [
+            ("Content-Length", "20"),
+            ("Last-Modified", "Fri, 23 Dec 2012 00:00:00 GMT"),
+            ("Etag", "\"etag\""),
+            ("x-amz-expiration", "not an expiration"),
+        ] {
+            headers.add_header(&Header::new(name, value)).unwrap();
+        }
+        let result = HeadObjectResult::parse_from_hdr("bucket".into(), "key".into(), &headers)
+            .expect("malformed expiration should not fail the request");
+        assert_eq!(result.object.size, 20);
+        assert!(result.object.expiration.is_none());
     }
 }
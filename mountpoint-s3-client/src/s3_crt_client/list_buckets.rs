@@ -0,0 +1,159 @@
+use std::ops::Deref;
+use std::os::unix::prelude::OsStrExt;
+
+use mountpoint_s3_crt::s3::client::{MetaRequestResult, MetaRequestType};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tracing::debug;
+
+use crate::object_client::{
+    BucketInfo, BucketOwner, ListBucketsError, ListBucketsResult, ObjectClientError, ObjectClientResult,
+};
+use crate::s3_crt_client::list_objects::{get_field, get_text, ParseError};
+use crate::s3_crt_client::S3RequestError;
+use crate::S3CrtClient;
+
+impl ListBucketsResult {
+    fn parse_from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        Self::parse_from_xml(&xmltree::Element::parse(bytes)?)
+    }
+
+    fn parse_from_xml(element: &xmltree::Element) -> Result<Self, ParseError> {
+        let mut buckets = Vec::new();
+        if let Some(list) = element.get_child("Buckets") {
+            for child in list.children.iter().filter_map(|node| node.as_element()) {
+                if child.name == "Bucket" {
+                    buckets.push(BucketInfo::parse_from_xml(child)?);
+                }
+            }
+        }
+
+        let owner = element
+            .get_child("Owner")
+            .map(BucketOwner::parse_from_xml)
+            .transpose()?;
+
+        Ok(Self { buckets, owner })
+    }
+}
+
+impl BucketOwner {
+    fn parse_from_xml(element: &xmltree::Element) -> Result<Self, ParseError> {
+        let id = get_field(element, "ID")?;
+
+        let display_name = element.get_child("DisplayName").map(get_text).transpose()?;
+
+        Ok(Self { id, display_name })
+    }
+}
+
+impl BucketInfo {
+    fn parse_from_xml(element: &xmltree::Element) -> Result<Self, ParseError> {
+        let name = get_field(element, "Name")?;
+
+        let creation_date = get_field(element, "CreationDate")?;
+        let creation_date = OffsetDateTime::parse(&creation_date, &Rfc3339)
+            .map_err(|e| ParseError::OffsetDateTime(e, "CreationDate".to_string()))?;
+
+        Ok(Self { name, creation_date })
+    }
+}
+
+impl S3CrtClient {
+    pub async fn list_buckets(&self) -> ObjectClientResult<ListBucketsResult, ListBucketsError, S3RequestError> {
+        // Scope the endpoint, message, etc. since otherwise rustc thinks we use Message across the await.
+        let body = {
+            // ListBuckets isn't a request for any bucket, so goes to the endpoint itself
+            let mut message = self
+                .new_request_template("GET", "")
+                .map_err(S3RequestError::construction_failure)?;
+
+            message
+                .set_request_path("/")
+                .map_err(S3RequestError::construction_failure)?;
+
+            let span = request_span!(self, "list_buckets");
+            span.in_scope(|| debug!(endpoint = ?self.endpoint, "new request"));
+
+            self.make_simple_http_request(message, MetaRequestType::Default, span, |result| {
+                let parsed = parse_list_buckets_error(&result);
+                parsed
+                    .map(ObjectClientError::ServiceError)
+                    .unwrap_or(ObjectClientError::ClientError(S3RequestError::ResponseError(result)))
+            })?
+        };
+
+        let body = body.await?;
+
+        ListBucketsResult::parse_from_bytes(&body)
+            .map_err(|e| ObjectClientError::ClientError(S3RequestError::InternalError(e.into())))
+    }
+}
+
+fn parse_list_buckets_error(result: &MetaRequestResult) -> Option<ListBucketsError> {
+    match result.response_status {
+        // S3 returns 400 for invalid or expired STS tokens
+        400 | 403 => {
+            let body = result.error_response_body.as_ref()?;
+            let root = xmltree::Element::parse(body.as_bytes()).ok()?;
+            let error_code = root.get_child("Code")?;
+            let error_str = error_code.get_text()?;
+            match error_str.deref() {
+                "AccessDenied" | "InvalidAccessKeyId" | "SignatureDoesNotMatch" | "ExpiredToken" | "InvalidToken" => {
+                    Some(ListBucketsError::AccessDenied)
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{OsStr, OsString};
+
+    use super::*;
+
+    fn make_result(response_status: i32, body: impl Into<OsString>) -> MetaRequestResult {
+        MetaRequestResult {
+            response_status,
+            crt_error: 1i32.into(),
+            error_response_headers: None,
+            error_response_body: Some(body.into()),
+        }
+    }
+
+    #[test]
+    fn parse_403_access_denied() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>AccessDenied</Code><Message>Access Denied</Message><RequestId>CM0R497NB0WAQ977</RequestId><HostId>w1tqXYVT3sfKQJCT+JlD9x4hFYTvLd3HnkBO4HnB48xjbLKxXmXiMpCQ0V5x5ENnDpfbYVgc3rk=</HostId></Error>"#;
+        let result = make_result(403, OsStr::from_bytes(&body[..]));
+        let result = parse_list_buckets_error(&result);
+        assert_eq!(result, Some(ListBucketsError::AccessDenied));
+    }
+
+    #[test]
+    fn parse_buckets() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><ListAllMyBucketsResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Owner><ID>bcaf1ffd86f41161ca5fb16fd081034f</ID><DisplayName>webfile</DisplayName></Owner><Buckets><Bucket><Name>DOC-EXAMPLE-BUCKET</Name><CreationDate>2019-12-11T23:32:47+00:00</CreationDate></Bucket><Bucket><Name>DOC-EXAMPLE-BUCKET2</Name><CreationDate>2019-11-10T23:32:13+00:00</CreationDate></Bucket></Buckets></ListAllMyBucketsResult>"#;
+        let result = ListBucketsResult::parse_from_bytes(&body[..]).expect("should parse");
+        assert_eq!(result.buckets.len(), 2);
+        assert_eq!(result.buckets[0].name, "DOC-EXAMPLE-BUCKET");
+        assert_eq!(result.buckets[1].name, "DOC-EXAMPLE-BUCKET2");
+        assert_eq!(
+            result.buckets[1].creation_date,
+            OffsetDateTime::parse("2019-11-10T23:32:13+00:00", &Rfc3339).unwrap()
+        );
+        assert_eq!(
+            result.owner,
+            Some(BucketOwner {
+                id: "bcaf1ffd86f41161ca5fb16fd081034f".to_string(),
+                display_name: Some("webfile".to_string()),
+            })
+        );
+
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><ListAllMyBucketsResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Owner><ID>bcaf1ffd86f41161ca5fb16fd081034f</ID></Owner><Buckets></Buckets></ListAllMyBucketsResult>"#;
+        let result = ListBucketsResult::parse_from_bytes(&body[..]).expect("should parse");
+        assert!(result.buckets.is_empty());
+        assert_eq!(result.owner.unwrap().display_name, None);
+    }
+}
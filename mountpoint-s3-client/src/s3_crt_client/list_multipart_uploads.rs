@@ -0,0 +1,211 @@
+use std::ops::Deref;
+use std::os::unix::prelude::OsStrExt;
+use std::str::FromStr;
+
+use mountpoint_s3_crt::s3::client::{MetaRequestResult, MetaRequestType};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tracing::debug;
+
+use crate::object_client::{
+    ListMultipartUploadsError, ListMultipartUploadsResult, MultipartUpload, ObjectClientError, ObjectClientResult,
+};
+use crate::s3_crt_client::list_objects::{get_field, get_text, ParseError};
+use crate::s3_crt_client::S3RequestError;
+use crate::S3CrtClient;
+
+/// One page of a ListMultipartUploads response
+#[derive(Debug)]
+struct ListMultipartUploadsPage {
+    bucket: String,
+    uploads: Vec<MultipartUpload>,
+    /// If the listing was truncated, the key and upload id markers to continue from
+    next_markers: Option<(String, String)>,
+}
+
+impl ListMultipartUploadsPage {
+    fn parse_from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        Self::parse_from_xml(&xmltree::Element::parse(bytes)?)
+    }
+
+    fn parse_from_xml(element: &xmltree::Element) -> Result<Self, ParseError> {
+        let mut uploads = Vec::new();
+        for child in element.children.iter().filter_map(|node| node.as_element()) {
+            if child.name == "Upload" {
+                uploads.push(MultipartUpload::parse_from_xml(child)?);
+            }
+        }
+
+        let bucket = get_field(element, "Bucket")?;
+
+        let is_truncated = get_field(element, "IsTruncated")?;
+        let is_truncated = bool::from_str(&is_truncated).map_err(|e| ParseError::Bool(e, "IsTruncated".to_string()))?;
+
+        let next_markers = if is_truncated {
+            let next_key_marker = element.get_child("NextKeyMarker").map(get_text).transpose()?;
+            let next_upload_id_marker = element.get_child("NextUploadIdMarker").map(get_text).transpose()?;
+            match (next_key_marker, next_upload_id_marker) {
+                (Some(key_marker), Some(upload_id_marker)) => Some((key_marker, upload_id_marker)),
+                _ => {
+                    return Err(ParseError::InvalidResponse(
+                        element.clone(),
+                        "truncated listing without NextKeyMarker and NextUploadIdMarker".to_string(),
+                    ))
+                }
+            }
+        } else {
+            None
+        };
+
+        Ok(Self {
+            bucket,
+            uploads,
+            next_markers,
+        })
+    }
+}
+
+impl MultipartUpload {
+    fn parse_from_xml(element: &xmltree::Element) -> Result<Self, ParseError> {
+        let key = get_field(element, "Key")?;
+
+        let upload_id = get_field(element, "UploadId")?;
+
+        let initiated = get_field(element, "Initiated")?;
+        let initiated = OffsetDateTime::parse(&initiated, &Rfc3339)
+            .map_err(|e| ParseError::OffsetDateTime(e, "Initiated".to_string()))?;
+
+        Ok(Self {
+            key,
+            upload_id,
+            initiated,
+        })
+    }
+}
+
+impl S3CrtClient {
+    pub async fn list_multipart_uploads(
+        &self,
+        bucket: &str,
+        prefix: &str,
+    ) -> ObjectClientResult<ListMultipartUploadsResult, ListMultipartUploadsError, S3RequestError> {
+        let mut uploads = Vec::new();
+        let mut markers: Option<(String, String)> = None;
+        loop {
+            let page = self
+                .list_multipart_uploads_page(bucket, prefix, markers.as_ref())
+                .await?;
+            uploads.extend(page.uploads);
+            markers = page.next_markers;
+            if markers.is_none() {
+                return Ok(ListMultipartUploadsResult {
+                    bucket: page.bucket,
+                    uploads,
+                });
+            }
+        }
+    }
+
+    async fn list_multipart_uploads_page(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        markers: Option<&(String, String)>,
+    ) -> ObjectClientResult<ListMultipartUploadsPage, ListMultipartUploadsError, S3RequestError> {
+        // Scope the endpoint, message, etc. since otherwise rustc thinks we use Message across the await.
+        let body = {
+            let mut message = self
+                .new_request_template("GET", bucket)
+                .map_err(S3RequestError::construction_failure)?;
+
+            let mut query = vec![("uploads", ""), ("prefix", prefix)];
+            if let Some((key_marker, upload_id_marker)) = markers {
+                query.push(("key-marker", key_marker.as_str()));
+                query.push(("upload-id-marker", upload_id_marker.as_str()));
+            }
+
+            message
+                .set_request_path_and_query("/", query)
+                .map_err(S3RequestError::construction_failure)?;
+
+            let span = request_span!(self, "list_multipart_uploads");
+            span.in_scope(|| debug!(?bucket, ?prefix, ?markers, "new request"));
+
+            self.make_simple_http_request(message, MetaRequestType::Default, span, |result| {
+                let parsed = parse_list_multipart_uploads_error(&result);
+                parsed
+                    .map(ObjectClientError::ServiceError)
+                    .unwrap_or(ObjectClientError::ClientError(S3RequestError::ResponseError(result)))
+            })?
+        };
+
+        let body = body.await?;
+
+        ListMultipartUploadsPage::parse_from_bytes(&body)
+            .map_err(|e| ObjectClientError::ClientError(S3RequestError::InternalError(e.into())))
+    }
+}
+
+fn parse_list_multipart_uploads_error(result: &MetaRequestResult) -> Option<ListMultipartUploadsError> {
+    match result.response_status {
+        404 => {
+            let body = result.error_response_body.as_ref()?;
+            let root = xmltree::Element::parse(body.as_bytes()).ok()?;
+            let error_code = root.get_child("Code")?;
+            let error_str = error_code.get_text()?;
+            match error_str.deref() {
+                "NoSuchBucket" => Some(ListMultipartUploadsError::NoSuchBucket),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{OsStr, OsString};
+
+    use super::*;
+
+    fn make_result(response_status: i32, body: impl Into<OsString>) -> MetaRequestResult {
+        MetaRequestResult {
+            response_status,
+            crt_error: 1i32.into(),
+            error_response_headers: None,
+            error_response_body: Some(body.into()),
+        }
+    }
+
+    #[test]
+    fn parse_404_no_such_bucket() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>NoSuchBucket</Code><Message>The specified bucket does not exist</Message><BucketName>DOC-EXAMPLE-BUCKET</BucketName><RequestId>4YAYHJ0E82DDDNF0</RequestId><HostId>Ajn9+i3d3VWQi339YrGqBbJqQlj5HaX2vplXp9IlDPAxsJ4vsIAsje0P2gJ0of/mTKKz/fv9pNy9RqhbLUBc/g==</HostId></Error>"#;
+        let result = make_result(404, OsStr::from_bytes(&body[..]));
+        let result = parse_list_multipart_uploads_error(&result);
+        assert_eq!(result, Some(ListMultipartUploadsError::NoSuchBucket));
+    }
+
+    #[test]
+    fn parse_truncated_uploads() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><ListMultipartUploadsResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Bucket>DOC-EXAMPLE-BUCKET</Bucket><KeyMarker></KeyMarker><UploadIdMarker></UploadIdMarker><NextKeyMarker>my-movie.m2ts</NextKeyMarker><NextUploadIdMarker>YW55IGlkZWEgd2h5IGVsdmluZydzIHVwbG9hZCBmYWlsZWQ</NextUploadIdMarker><MaxUploads>2</MaxUploads><IsTruncated>true</IsTruncated><Upload><Key>my-divisor</Key><UploadId>XMgbGlrZSBlbHZpbmcncyBub3QgaGF2aW5nIG11Y2ggbHVjaw</UploadId><StorageClass>STANDARD</StorageClass><Initiated>2010-11-10T20:48:33.000Z</Initiated></Upload><Upload><Key>my-movie.m2ts</Key><UploadId>YW55IGlkZWEgd2h5IGVsdmluZydzIHVwbG9hZCBmYWlsZWQ</UploadId><StorageClass>STANDARD</StorageClass><Initiated>2010-11-10T20:49:33.000Z</Initiated></Upload></ListMultipartUploadsResult>"#;
+        let page = ListMultipartUploadsPage::parse_from_bytes(&body[..]).expect("should parse");
+        assert_eq!(page.bucket, "DOC-EXAMPLE-BUCKET");
+        assert_eq!(page.uploads.len(), 2);
+        assert_eq!(page.uploads[0].key, "my-divisor");
+        assert_eq!(
+            page.uploads[1].upload_id,
+            "YW55IGlkZWEgd2h5IGVsdmluZydzIHVwbG9hZCBmYWlsZWQ"
+        );
+        assert_eq!(
+            page.uploads[1].initiated,
+            OffsetDateTime::parse("2010-11-10T20:49:33.000Z", &Rfc3339).unwrap()
+        );
+        assert_eq!(
+            page.next_markers,
+            Some((
+                "my-movie.m2ts".to_string(),
+                "YW55IGlkZWEgd2h5IGVsdmluZydzIHVwbG9hZCBmYWlsZWQ".to_string()
+            ))
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use std::ops::Deref;
+use std::os::unix::prelude::OsStrExt;
+use std::str::FromStr;
+
+use mountpoint_s3_crt::s3::client::{MetaRequestResult, MetaRequestType};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tracing::debug;
+
+use crate::object_client::{
+    ListObjectVersionsError, ListObjectVersionsResult, ObjectClientError, ObjectClientResult, ObjectVersion,
+};
+use crate::s3_crt_client::list_objects::{get_field, get_text, ParseError};
+use crate::s3_crt_client::S3RequestError;
+use crate::S3CrtClient;
+
+impl ListObjectVersionsResult {
+    fn parse_from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        Self::parse_from_xml(&xmltree::Element::parse(bytes)?)
+    }
+
+    fn parse_from_xml(element: &xmltree::Element) -> Result<Self, ParseError> {
+        // Versions and delete markers are interleaved in key order, so we need to walk the children
+        // in order rather than taking each kind separately.
+        let mut versions = Vec::new();
+        for child in element.children.iter().filter_map(|node| node.as_element()) {
+            match child.name.as_str() {
+                "Version" => versions.push(ObjectVersion::parse_from_xml(child, false)?),
+                "DeleteMarker" => versions.push(ObjectVersion::parse_from_xml(child, true)?),
+                _ => {}
+            }
+        }
+
+        let bucket = get_field(element, "Name")?;
+
+        let next_key_marker = element.get_child("NextKeyMarker").map(get_text).transpose()?;
+        let next_version_id_marker = element.get_child("NextVersionIdMarker").map(get_text).transpose()?;
+
+        let is_truncated = get_field(element, "IsTruncated")?;
+        let is_truncated = bool::from_str(&is_truncated).map_err(|e| ParseError::Bool(e, "IsTruncated".to_string()))?;
+
+        if is_truncated != next_key_marker.is_some() {
+            return Err(ParseError::InvalidResponse(
+                element.clone(),
+                "IsTruncated doesn't match NextKeyMarker".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            bucket,
+            versions,
+            next_key_marker,
+            next_version_id_marker,
+        })
+    }
+}
+
+impl ObjectVersion {
+    fn parse_from_xml(element: &xmltree::Element, is_delete_marker: bool) -> Result<Self, ParseError> {
+        let key = get_field(element, "Key")?;
+
+        let version_id = get_field(element, "VersionId")?;
+
+        let is_latest = get_field(element, "IsLatest")?;
+        let is_latest = bool::from_str(&is_latest).map_err(|e| ParseError::Bool(e, "IsLatest".to_string()))?;
+
+        let last_modified = get_field(element, "LastModified")?;
+        let last_modified = OffsetDateTime::parse(&last_modified, &Rfc3339)
+            .map_err(|e| ParseError::OffsetDateTime(e, "LastModified".to_string()))?;
+
+        // Delete markers have no contents, so they don't carry a size or entity tag
+        let (size, etag) = if is_delete_marker {
+            (0, None)
+        } else {
+            let size = get_field(element, "Size")?;
+            let size = u64::from_str(&size).map_err(|e| ParseError::Int(e, "Size".to_string()))?;
+            (size, Some(get_field(element, "ETag")?))
+        };
+
+        Ok(Self {
+            key,
+            version_id,
+            is_latest,
+            is_delete_marker,
+            size,
+            last_modified,
+            etag,
+        })
+    }
+}
+
+impl S3CrtClient {
+    pub async fn list_object_versions(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        key_marker: Option<&str>,
+        version_id_marker: Option<&str>,
+        max_keys: usize,
+    ) -> ObjectClientResult<ListObjectVersionsResult, ListObjectVersionsError, S3RequestError> {
+        // Scope the endpoint, message, etc. since otherwise rustc thinks we use Message across the await.
+        let body = {
+            let mut message = self
+                .new_request_template("GET", bucket)
+                .map_err(S3RequestError::construction_failure)?;
+
+            let max_keys = format!("{max_keys}");
+            let mut query = vec![("versions", ""), ("max-keys", &max_keys), ("prefix", prefix)];
+            if let Some(key_marker) = key_marker {
+                query.push(("key-marker", key_marker));
+            }
+            if let Some(version_id_marker) = version_id_marker {
+                query.push(("version-id-marker", version_id_marker));
+            }
+
+            message
+                .set_request_path_and_query("/", query)
+                .map_err(S3RequestError::construction_failure)?;
+
+            let span = request_span!(self, "list_object_versions");
+            span.in_scope(|| {
+                debug!(
+                    ?bucket,
+                    ?prefix,
+                    ?key_marker,
+                    ?version_id_marker,
+                    ?max_keys,
+                    "new request"
+                )
+            });
+
+            self.make_simple_http_request(message, MetaRequestType::Default, span, |result| {
+                let parsed = parse_list_object_versions_error(&result);
+                parsed
+                    .map(ObjectClientError::ServiceError)
+                    .unwrap_or(ObjectClientError::ClientError(S3RequestError::ResponseError(result)))
+            })?
+        };
+
+        let body = body.await?;
+
+        ListObjectVersionsResult::parse_from_bytes(&body)
+            .map_err(|e| ObjectClientError::ClientError(S3RequestError::InternalError(e.into())))
+    }
+}
+
+fn parse_list_object_versions_error(result: &MetaRequestResult) -> Option<ListObjectVersionsError> {
+    match result.response_status {
+        404 => {
+            let body = result.error_response_body.as_ref()?;
+            let root = xmltree::Element::parse(body.as_bytes()).ok()?;
+            let error_code = root.get_child("Code")?;
+            let error_str = error_code.get_text()?;
+            match error_str.deref() {
+                "NoSuchBucket" => Some(ListObjectVersionsError::NoSuchBucket),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{OsStr, OsString};
+
+    use super::*;
+
+    fn make_result(response_status: i32, body: impl Into<OsString>) -> MetaRequestResult {
+        MetaRequestResult {
+            response_status,
+            crt_error: 1i32.into(),
+            error_response_headers: None,
+            error_response_body: Some(body.into()),
+        }
+    }
+
+    #[test]
+    fn parse_404_no_such_bucket() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>NoSuchBucket</Code><Message>The specified bucket does not exist</Message><BucketName>DOC-EXAMPLE-BUCKET</BucketName><RequestId>4YAYHJ0E82DDDNF0</RequestId><HostId>Ajn9+i3d3VWQi339YrGqBbJqQlj5HaX2vplXp9IlDPAxsJ4vsIAsje0P2gJ0of/mTKKz/fv9pNy9RqhbLUBc/g==</HostId></Error>"#;
+        let result = make_result(404, OsStr::from_bytes(&body[..]));
+        let result = parse_list_object_versions_error(&result);
+        assert_eq!(result, Some(ListObjectVersionsError::NoSuchBucket));
+    }
+
+    #[test]
+    fn parse_versions_and_delete_markers() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><ListVersionsResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Name>DOC-EXAMPLE-BUCKET</Name><Prefix></Prefix><KeyMarker></KeyMarker><VersionIdMarker></VersionIdMarker><NextKeyMarker>my-image.jpg</NextKeyMarker><NextVersionIdMarker>03jpff543dhffds434rfdsFDN943fdsFkdmqnh892</NextVersionIdMarker><MaxKeys>3</MaxKeys><IsTruncated>true</IsTruncated><DeleteMarker><Key>my-image.jpg</Key><VersionId>03jpff543dhffds434rfdsFDN943fdsFkdmqnh892</VersionId><IsLatest>true</IsLatest><LastModified>2009-10-15T17:50:30.000Z</LastModified></DeleteMarker><Version><Key>my-image.jpg</Key><VersionId>3/L4kqtJl40Nr8X8gdRQBpUMLUo</VersionId><IsLatest>false</IsLatest><LastModified>2009-10-12T17:50:30.000Z</LastModified><ETag>"fba9dede5f27731c9771645a39863328"</ETag><Size>434234</Size><StorageClass>STANDARD</StorageClass></Version></ListVersionsResult>"#;
+        let result = ListObjectVersionsResult::parse_from_bytes(&body[..]).expect("should parse");
+        assert_eq!(result.bucket, "DOC-EXAMPLE-BUCKET");
+        assert_eq!(result.next_key_marker.as_deref(), Some("my-image.jpg"));
+        assert_eq!(
+            result.next_version_id_marker.as_deref(),
+            Some("03jpff543dhffds434rfdsFDN943fdsFkdmqnh892")
+        );
+        assert_eq!(result.versions.len(), 2);
+
+        let marker = &result.versions[0];
+        assert!(marker.is_delete_marker);
+        assert!(marker.is_latest);
+        assert_eq!(marker.size, 0);
+        assert_eq!(marker.etag, None);
+
+        let version = &result.versions[1];
+        assert!(!version.is_delete_marker);
+        assert!(!version.is_latest);
+        assert_eq!(version.version_id, "3/L4kqtJl40Nr8X8gdRQBpUMLUo");
+        assert_eq!(version.size, 434234);
+        assert_eq!(version.etag.as_deref(), Some("\"fba9dede5f27731c9771645a39863328\""));
+    }
+}
@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+use time::OffsetDateTime;
+use tracing::debug;
+
+use crate::object_client::{ObjectClientError, PresignPostError};
+use crate::presign::{presign_post, PostPolicyCondition, PresignedPost};
+use crate::{ObjectClientResult, S3CrtClient, S3RequestError};
+
+impl S3CrtClient {
+    /// Create a presigned POST policy, signed with this client's credentials. This doesn't make any
+    /// requests to S3, but might need to fetch credentials.
+    pub async fn presign_post_object(
+        &self,
+        bucket: &str,
+        key_prefix: &str,
+        conditions: &[PostPolicyCondition],
+        expires_in: Duration,
+    ) -> ObjectClientResult<PresignedPost, PresignPostError, S3RequestError> {
+        debug!(?bucket, ?key_prefix, ?conditions, ?expires_in, "presigning post");
+
+        let Some(credentials_provider) = &self.credentials_provider else {
+            return Err(ObjectClientError::ServiceError(PresignPostError::NoCredentials));
+        };
+        let credentials = credentials_provider
+            .get_credentials()
+            .await
+            .map_err(|e| ObjectClientError::ClientError(S3RequestError::CrtError(e)))?;
+
+        let (uri, path_prefix) = self
+            .endpoint
+            .for_bucket(bucket)
+            .map_err(S3RequestError::construction_failure)?;
+        let url = format!("{}{path_prefix}", uri.as_os_str().to_string_lossy());
+
+        Ok(presign_post(
+            url,
+            &credentials,
+            &self.region,
+            bucket,
+            key_prefix,
+            conditions,
+            expires_in,
+            OffsetDateTime::now_utc(),
+        ))
+    }
+}
@@ -1,10 +1,23 @@
-use crate::object_client::{ObjectClientResult, PutObjectError, PutObjectParams, PutObjectResult};
-use crate::{ObjectClientError, S3CrtClient, S3RequestError};
-use futures::{Stream, StreamExt};
+use crate::object_client::{
+    content_md5, upload_part_size, CompleteMultipartUploadError, ObjectClientResult, PutObjectError, PutObjectParams,
+    PutObjectResult, UploadMode,
+};
+use crate::s3_crt_client::list_objects::{get_field, ParseError};
+use crate::s3_crt_client::S3Message;
+use crate::timer::sleep;
+use crate::{ETag, ObjectClientError, RetryStrategyConfig, S3CrtClient, S3RequestError};
+use futures::future::{select, Either};
+use futures::stream::FuturesUnordered;
+use futures::{pin_mut, Future, Stream, StreamExt};
 use mountpoint_s3_crt::http::request_response::Header;
+use mountpoint_s3_crt::io::futures::FutureSpawner;
 use mountpoint_s3_crt::io::stream::InputStream;
-use mountpoint_s3_crt::s3::client::MetaRequestType;
-use tracing::debug;
+use mountpoint_s3_crt::s3::client::{MetaRequestResult, MetaRequestType};
+use std::os::unix::prelude::OsStrExt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use time::format_description::well_known::Rfc3339;
+use tracing::{debug, warn};
 
 impl S3CrtClient {
     pub(super) async fn put_object(
@@ -14,17 +27,77 @@ impl S3CrtClient {
         params: &PutObjectParams,
         contents: impl Stream<Item = impl AsRef<[u8]> + Send> + Send,
     ) -> ObjectClientResult<PutObjectResult, PutObjectError, S3RequestError> {
+        pin_mut!(contents);
         let mut buffer = vec![];
 
-        // Accumulate the stream contents into a buffer.
-        // TODO: Support streaming the data to the CRT.
-        contents
-            .for_each(|b| {
-                buffer.extend_from_slice(b.as_ref());
-                std::future::ready(())
+        // Without an upload buffer size, accumulate the whole stream into a buffer and let the
+        // CRT's PutObject meta request upload it.
+        let Some(high_water_mark) = self
+            .upload_buffer_size
+            .filter(|_| params.upload_mode != UploadMode::SinglePart)
+        else {
+            while let Some(chunk) = contents.next().await {
+                buffer.extend_from_slice(chunk.as_ref());
+            }
+            return self.put_object_from_buffer(bucket, key, params, buffer).await;
+        };
+
+        // Otherwise, upload the object ourselves with a multipart upload, only pulling from
+        // `contents` as parts are sent. Objects that fit in a single part are still sent with a
+        // single request.
+        let part_size = upload_part_size(params.object_size_hint.unwrap_or(0), self.part_size);
+        while buffer.len() < part_size {
+            match contents.next().await {
+                Some(chunk) => buffer.extend_from_slice(chunk.as_ref()),
+                None => return self.put_object_from_buffer(bucket, key, params, buffer).await,
+            }
+        }
+
+        let upload_id = self.create_multipart_upload(bucket, key, params).await?;
+        let abort_on_drop = AbortOnDrop {
+            client: self,
+            bucket,
+            key,
+            upload_id: &upload_id,
+        };
+        let upload = async {
+            let (parts, size) = upload_parts(
+                contents,
+                buffer,
+                part_size,
+                high_water_mark.max(part_size),
+                |part_number, part| self.upload_part(bucket, key, params, &upload_id, part_number, part),
+            )
+            .await?;
+            let (version_id, etag) = self
+                .complete_multipart_upload(bucket, key, params, &upload_id, &parts)
+                .await?;
+            Ok(PutObjectResult {
+                version_id,
+                etag,
+                size: Some(size),
             })
-            .await;
+        };
+
+        let result = upload.await;
+        if result.is_err() {
+            // Don't leave the parts we've already uploaded behind
+            if let Err(error) = self.abort_multipart_upload(bucket, key, &upload_id).await {
+                warn!(?bucket, ?key, ?upload_id, ?error, "failed to abort multipart upload");
+            }
+        }
+        abort_on_drop.disarm();
+        result
+    }
 
+    /// Upload an object whose contents are all in `buffer` with a PutObject meta request
+    async fn put_object_from_buffer(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectParams,
+        buffer: Vec<u8>,
+    ) -> ObjectClientResult<PutObjectResult, PutObjectError, S3RequestError> {
         let body = {
             let mut message = self
                 .new_request_template("PUT", bucket)
@@ -34,6 +107,19 @@ impl S3CrtClient {
                 .add_header(&Header::new("Content-Length", buffer.len().to_string()))
                 .map_err(S3RequestError::construction_failure)?;
 
+            add_object_headers(&mut message, params)?;
+
+            // For a PutObject meta request, a Content-MD5 header on the initial request tells the CRT
+            // to compute and send the digest of each part of a multipart upload. It doesn't send
+            // this header (the digest of the whole body) with the parts.
+            if params.compute_content_md5 {
+                message
+                    .add_header(&Header::new("Content-MD5", content_md5(&buffer)))
+                    .map_err(S3RequestError::construction_failure)?;
+            }
+
+            add_sse_customer_key_headers(&mut message, params)?;
+
             let key = format!("/{key}");
             message
                 .set_request_path(&key)
@@ -46,13 +132,708 @@ impl S3CrtClient {
             let span = request_span!(self, "put_object");
             span.in_scope(|| debug!(?bucket, ?key, ?params, "new request"));
 
-            self.make_simple_http_request(message, MetaRequestType::PutObject, span, |result| {
-                ObjectClientError::ClientError(S3RequestError::ResponseError(result))
+            // The CRT's PutObject meta request decides whether to do a multipart upload based on the
+            // size of the object and its part size, while a default meta request always sends the
+            // PutObject as a single request.
+            let request_type = match params.upload_mode {
+                UploadMode::SinglePart => MetaRequestType::Default,
+                UploadMode::Automatic | UploadMode::Multipart => MetaRequestType::PutObject,
+            };
+
+            // Stash the version id and ETag from the response headers so we can return them when we
+            // finish. For multipart uploads, the CRT adds the ETag from the body of the
+            // CompleteMultipartUpload response to its headers.
+            let version_id: Arc<Mutex<Option<String>>> = Default::default();
+            let version_id_clone = Arc::clone(&version_id);
+            let etag: Arc<Mutex<Option<ETag>>> = Default::default();
+            let etag_clone = Arc::clone(&etag);
+
+            self.make_meta_request(
+                message,
+                request_type,
+                span,
+                move |headers, _status| {
+                    if let Ok(header) = headers.get("x-amz-version-id") {
+                        *version_id_clone.lock().unwrap() = Some(header.value().to_string_lossy().to_string());
+                    }
+                    if let Ok(header) = headers.get("ETag") {
+                        *etag_clone.lock().unwrap() = ETag::from_str(&header.value().to_string_lossy()).ok();
+                    }
+                },
+                |_, _| (),
+                move |result| {
+                    if result.is_err() {
+                        match parse_put_object_error(&result) {
+                            Some(error) => Err(ObjectClientError::ServiceError(error)),
+                            None => Err(ObjectClientError::ClientError(S3RequestError::ResponseError(result))),
+                        }
+                    } else {
+                        Ok((version_id.lock().unwrap().take(), etag.lock().unwrap().take()))
+                    }
+                },
+            )?
+        };
+
+        let (version_id, etag) = body.await?;
+
+        Ok(PutObjectResult {
+            version_id,
+            etag,
+            size: Some(buffer.len() as u64),
+        })
+    }
+
+    /// Start a multipart upload of an object, returning its upload id
+    async fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectParams,
+    ) -> ObjectClientResult<String, PutObjectError, S3RequestError> {
+        let span = request_span!(self, "create_multipart_upload");
+        span.in_scope(|| debug!(?bucket, ?key, ?params, "new request"));
+
+        let body = {
+            let mut message = self
+                .new_request_template("POST", bucket)
+                .map_err(S3RequestError::construction_failure)?;
+
+            add_object_headers(&mut message, params)?;
+            add_sse_customer_key_headers(&mut message, params)?;
+
+            message
+                .set_request_path_and_query(format!("/{key}"), [("uploads", "")])
+                .map_err(S3RequestError::construction_failure)?;
+
+            self.make_simple_http_request(message, MetaRequestType::Default, span, |result| {
+                let parsed = parse_put_object_error(&result);
+                parsed
+                    .map(ObjectClientError::ServiceError)
+                    .unwrap_or(ObjectClientError::ClientError(S3RequestError::ResponseError(result)))
             })?
         };
 
-        body.await?;
+        let body = body.await?;
+
+        xmltree::Element::parse(&body[..])
+            .map_err(ParseError::from)
+            .and_then(|root| get_field(&root, "UploadId"))
+            .map_err(|e| ObjectClientError::ClientError(S3RequestError::InternalError(e.into())))
+    }
+
+    /// Upload one part of a multipart upload, returning its ETag
+    async fn upload_part(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectParams,
+        upload_id: &str,
+        part_number: usize,
+        part: Vec<u8>,
+    ) -> ObjectClientResult<String, PutObjectError, S3RequestError> {
+        let span = request_span!(self, "upload_part");
+        span.in_scope(|| debug!(?bucket, ?key, ?upload_id, part_number, size = part.len(), "new request"));
+
+        let request = {
+            let mut message = self
+                .new_request_template("PUT", bucket)
+                .map_err(S3RequestError::construction_failure)?;
+
+            message
+                .add_header(&Header::new("Content-Length", part.len().to_string()))
+                .map_err(S3RequestError::construction_failure)?;
+
+            if params.compute_content_md5 {
+                message
+                    .add_header(&Header::new("Content-MD5", content_md5(&part)))
+                    .map_err(S3RequestError::construction_failure)?;
+            }
+
+            add_sse_customer_key_headers(&mut message, params)?;
+
+            let part_number = part_number.to_string();
+            message
+                .set_request_path_and_query(
+                    format!("/{key}"),
+                    [("partNumber", part_number.as_str()), ("uploadId", upload_id)],
+                )
+                .map_err(S3RequestError::construction_failure)?;
+
+            let body_input_stream =
+                InputStream::new_from_slice(&self.allocator, &part).map_err(S3RequestError::CrtError)?;
+            message.set_body_stream(Some(body_input_stream));
+
+            let etag: Arc<Mutex<Option<String>>> = Default::default();
+            let etag_clone = Arc::clone(&etag);
+
+            self.make_meta_request(
+                message,
+                MetaRequestType::Default,
+                span,
+                move |headers, _status| {
+                    if let Ok(header) = headers.get("ETag") {
+                        *etag_clone.lock().unwrap() = Some(header.value().to_string_lossy().to_string());
+                    }
+                },
+                |_, _| (),
+                move |result| {
+                    if result.is_err() {
+                        match parse_put_object_error(&result) {
+                            Some(error) => Err(ObjectClientError::ServiceError(error)),
+                            None => Err(ObjectClientError::ClientError(S3RequestError::ResponseError(result))),
+                        }
+                    } else {
+                        Ok(etag.lock().unwrap().take())
+                    }
+                },
+            )?
+        };
+
+        request.await?.ok_or_else(|| {
+            ObjectClientError::ClientError(S3RequestError::InternalError("UploadPart response had no ETag".into()))
+        })
+    }
+
+    /// Complete a multipart upload from its parts' numbers and ETags, returning the version id and
+    /// ETag of the new object
+    /// Complete a multipart upload, completing it again while S3 fails it with a transient error.
+    /// The CRT retries a CompleteMultipartUpload whose status is an error, but not one that fails
+    /// after a 200 OK with the error only in the body. The upload is still in progress after that,
+    /// so only the completion needs to be sent again, not the parts.
+    async fn complete_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectParams,
+        upload_id: &str,
+        parts: &[(usize, String)],
+    ) -> ObjectClientResult<(Option<String>, Option<ETag>), PutObjectError, S3RequestError> {
+        retry_complete(&self.retry_config, || {
+            self.send_complete_multipart_upload(bucket, key, params, upload_id, parts)
+        })
+        .await
+    }
+
+    async fn send_complete_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectParams,
+        upload_id: &str,
+        parts: &[(usize, String)],
+    ) -> ObjectClientResult<(Option<String>, Option<ETag>), PutObjectError, S3RequestError> {
+        let span = request_span!(self, "complete_multipart_upload");
+        span.in_scope(|| debug!(?bucket, ?key, ?upload_id, num_parts = parts.len(), "new request"));
+
+        let request_body = build_complete_request_body(parts);
+
+        let version_id: Arc<Mutex<Option<String>>> = Default::default();
+        let request = {
+            let mut message = self
+                .new_request_template("POST", bucket)
+                .map_err(S3RequestError::construction_failure)?;
+
+            message
+                .add_header(&Header::new("Content-Length", request_body.len().to_string()))
+                .map_err(S3RequestError::construction_failure)?;
+
+            add_sse_customer_key_headers(&mut message, params)?;
+
+            message
+                .set_request_path_and_query(format!("/{key}"), [("uploadId", upload_id)])
+                .map_err(S3RequestError::construction_failure)?;
+
+            let body_input_stream = InputStream::new_from_slice(&self.allocator, request_body.as_bytes())
+                .map_err(S3RequestError::CrtError)?;
+            message.set_body_stream(Some(body_input_stream));
+
+            let version_id_clone = Arc::clone(&version_id);
+            let body: Arc<Mutex<Vec<u8>>> = Default::default();
+            let body_clone = Arc::clone(&body);
+
+            self.make_meta_request(
+                message,
+                MetaRequestType::Default,
+                span,
+                move |headers, _status| {
+                    if let Ok(header) = headers.get("x-amz-version-id") {
+                        *version_id_clone.lock().unwrap() = Some(header.value().to_string_lossy().to_string());
+                    }
+                },
+                move |_offset, data| body_clone.lock().unwrap().extend_from_slice(data),
+                move |result| {
+                    if result.is_err() {
+                        match parse_put_object_error(&result) {
+                            Some(error) => Err(ObjectClientError::ServiceError(error)),
+                            None => Err(ObjectClientError::ClientError(S3RequestError::ResponseError(result))),
+                        }
+                    } else {
+                        Ok(std::mem::take(&mut *body.lock().unwrap()))
+                    }
+                },
+            )?
+        };
+
+        let body = request.await?;
+
+        // CompleteMultipartUpload can fail after S3 has already sent a 200 OK, in which case the
+        // error is only in the body
+        let root = xmltree::Element::parse(&body[..])
+            .map_err(|e| ObjectClientError::ClientError(S3RequestError::InternalError(ParseError::from(e).into())))?;
+        if root.name == "Error" {
+            let code = get_field(&root, "Code").unwrap_or_default();
+            let message = get_field(&root, "Message").unwrap_or_default();
+            return Err(ObjectClientError::ServiceError(
+                PutObjectError::CompleteMultipartUpload(CompleteMultipartUploadError::new(code, message)),
+            ));
+        }
+
+        let etag = get_field(&root, "ETag")
+            .ok()
+            .and_then(|etag| ETag::from_str(&etag).ok());
+        let version_id = version_id.lock().unwrap().take();
+        Ok((version_id, etag))
+    }
+}
+
+/// Aborts a multipart upload if it's dropped before being disarmed. The future uploading the parts
+/// can be dropped before it finishes, for example by a timeout or by a caller that stops writing,
+/// and the parts uploaded so far would be left behind. Dropping can't wait for the abort, so it's
+/// sent in the background on the client's event loop.
+struct AbortOnDrop<'a> {
+    client: &'a S3CrtClient,
+    bucket: &'a str,
+    key: &'a str,
+    upload_id: &'a str,
+}
+
+impl AbortOnDrop<'_> {
+    /// Leave the upload alone, once it's finished or is being aborted some other way
+    fn disarm(self) {
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for AbortOnDrop<'_> {
+    fn drop(&mut self) {
+        let (bucket, key, upload_id) = (self.bucket.to_owned(), self.key.to_owned(), self.upload_id.to_owned());
+        debug!(?bucket, ?key, ?upload_id, "aborting unfinished upload");
+        match self.client.start_abort_multipart_upload(&bucket, &key, &upload_id) {
+            Ok(request) => {
+                self.client.event_loop_group.spawn_future(async move {
+                    if let Err(error) = request.await {
+                        warn!(?bucket, ?key, ?upload_id, ?error, "failed to abort multipart upload");
+                    }
+                });
+            }
+            Err(error) => warn!(?bucket, ?key, ?upload_id, ?error, "failed to abort multipart upload"),
+        }
+    }
+}
+
+/// Call `complete` until it succeeds or fails with an error that isn't a transient
+/// [CompleteMultipartUploadError], retrying as many times as `config` allows for a write and backing
+/// off between attempts.
+async fn retry_complete<T, E, F, Fut>(
+    config: &RetryStrategyConfig,
+    mut complete: F,
+) -> ObjectClientResult<T, PutObjectError, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ObjectClientResult<T, PutObjectError, E>>,
+{
+    let max_retries = config.write_max_retries.unwrap_or(config.max_retries);
+    let mut backoff = config.backoff_scale_factor;
+    let mut retries = 0;
+    loop {
+        match complete().await {
+            Err(ObjectClientError::ServiceError(PutObjectError::CompleteMultipartUpload(e)))
+                if retries < max_retries && e.is_retryable() =>
+            {
+                warn!(retries, ?backoff, "CompleteMultipartUpload failed, will retry: {e}");
+                sleep(backoff).await;
+                backoff = (backoff * 2).min(config.max_backoff);
+                retries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Add the headers that describe the object being uploaded to a PutObject or
+/// CreateMultipartUpload request
+fn add_object_headers(message: &mut S3Message<'_>, params: &PutObjectParams) -> Result<(), S3RequestError> {
+    if let Some(bucket_key_enabled) = params.bucket_key_enabled {
+        message
+            .add_header(&Header::new(
+                "x-amz-server-side-encryption-bucket-key-enabled",
+                bucket_key_enabled.to_string(),
+            ))
+            .map_err(S3RequestError::construction_failure)?;
+    }
+
+    if let Some(storage_class) = &params.storage_class {
+        message
+            .add_header(&Header::new("x-amz-storage-class", storage_class))
+            .map_err(S3RequestError::construction_failure)?;
+    }
+
+    if let Some(acl) = params.acl {
+        message
+            .add_header(&Header::new("x-amz-acl", acl.as_str()))
+            .map_err(S3RequestError::construction_failure)?;
+    }
+
+    if let Some(mode) = params.object_lock_mode {
+        message
+            .add_header(&Header::new("x-amz-object-lock-mode", mode.as_str()))
+            .map_err(S3RequestError::construction_failure)?;
+    }
+
+    if let Some(retain_until) = params.object_lock_retain_until {
+        let retain_until = retain_until
+            .format(&Rfc3339)
+            .map_err(S3RequestError::construction_failure)?;
+        message
+            .add_header(&Header::new("x-amz-object-lock-retain-until-date", retain_until))
+            .map_err(S3RequestError::construction_failure)?;
+    }
+
+    if let Some(legal_hold) = params.object_lock_legal_hold {
+        let legal_hold = if legal_hold { "ON" } else { "OFF" };
+        message
+            .add_header(&Header::new("x-amz-object-lock-legal-hold", legal_hold))
+            .map_err(S3RequestError::construction_failure)?;
+    }
+
+    Ok(())
+}
+
+/// Add the SSE-C headers, which every request that reads or writes the object's data needs
+fn add_sse_customer_key_headers(message: &mut S3Message<'_>, params: &PutObjectParams) -> Result<(), S3RequestError> {
+    if let Some(sse_customer_key) = &params.sse_customer_key {
+        for (name, value) in sse_customer_key.headers() {
+            message
+                .add_header(&Header::new(name, value))
+                .map_err(S3RequestError::construction_failure)?;
+        }
+    }
+    Ok(())
+}
+
+/// Build the XML document listing the parts of a multipart upload to complete it with
+fn build_complete_request_body(parts: &[(usize, String)]) -> String {
+    let mut body = String::from(r#"<CompleteMultipartUpload xmlns="http://s3.amazonaws.com/doc/2006-03-01/">"#);
+    for (part_number, etag) in parts {
+        body.push_str(&format!(
+            "<Part><PartNumber>{part_number}</PartNumber><ETag>{etag}</ETag></Part>"
+        ));
+    }
+    body.push_str("</CompleteMultipartUpload>");
+    body
+}
+
+/// Upload `contents` in parts of `part_size` bytes with `upload_part`, starting with the bytes
+/// already in `buffer`. Up to `high_water_mark` bytes (which must be at least `part_size`) are
+/// taken from `contents` ahead of the parts that have finished uploading, and `contents` isn't
+/// polled again until some of them do, so that's about all that's held in memory at once.
+///
+/// Returns the number and ETag of each part in order, and the total size of the parts.
+async fn upload_parts<T, E, Fut>(
+    mut contents: impl Stream<Item = T> + Unpin,
+    mut buffer: Vec<u8>,
+    part_size: usize,
+    high_water_mark: usize,
+    mut upload_part: impl FnMut(usize, Vec<u8>) -> Fut,
+) -> Result<(Vec<(usize, String)>, u64), E>
+where
+    T: AsRef<[u8]>,
+    Fut: Future<Output = Result<String, E>>,
+{
+    assert!(part_size > 0 && high_water_mark >= part_size);
+
+    enum Event<T, U> {
+        Chunk(Option<T>),
+        Uploaded(Option<U>),
+    }
+
+    let mut ahead = buffer.len();
+    let mut size = buffer.len() as u64;
+    let mut next_part_number = 1;
+    let mut parts = Vec::new();
+    let mut in_flight = FuturesUnordered::new();
+    let mut ended = false;
+
+    loop {
+        // Send every full part, and what's left once the contents have ended
+        while buffer.len() >= part_size || (ended && !buffer.is_empty()) {
+            let rest = buffer.split_off(part_size.min(buffer.len()));
+            let part = std::mem::replace(&mut buffer, rest);
+            let part_number = next_part_number;
+            let part_len = part.len();
+            let upload = upload_part(part_number, part);
+            in_flight.push(async move { upload.await.map(|etag| (part_number, part_len, etag)) });
+            next_part_number += 1;
+        }
+
+        if ended && in_flight.is_empty() {
+            break;
+        }
+
+        // While we're at the high-water mark, the buffer holds less than a part, so there must be
+        // parts in flight to wait for
+        let event = if ended || ahead >= high_water_mark {
+            Event::Uploaded(in_flight.next().await)
+        } else if in_flight.is_empty() {
+            Event::Chunk(contents.next().await)
+        } else {
+            match select(contents.next(), in_flight.next()).await {
+                Either::Left((chunk, _)) => Event::Chunk(chunk),
+                Either::Right((uploaded, _)) => Event::Uploaded(uploaded),
+            }
+        };
+
+        match event {
+            Event::Chunk(Some(chunk)) => {
+                let chunk = chunk.as_ref();
+                ahead += chunk.len();
+                size += chunk.len() as u64;
+                buffer.extend_from_slice(chunk);
+            }
+            Event::Chunk(None) => ended = true,
+            Event::Uploaded(Some(result)) => {
+                let (part_number, part_len, etag) = result?;
+                ahead -= part_len;
+                parts.push((part_number, etag));
+            }
+            Event::Uploaded(None) => unreachable!("only waiting for uploads while some are in flight"),
+        }
+    }
+
+    parts.sort_unstable();
+    Ok((parts, size))
+}
+
+fn parse_put_object_error(result: &MetaRequestResult) -> Option<PutObjectError> {
+    match result.response_status {
+        // CompleteMultipartUpload can fail after S3 has already sent a 200 OK, in which case the
+        // error is only in the body. The CRT fails the meta request when it sees an error body, but
+        // leaves the status as it was.
+        200 => {
+            let body = result.error_response_body.as_ref()?;
+            let root = xmltree::Element::parse(body.as_bytes()).ok()?;
+            if root.name != "Error" {
+                return None;
+            }
+            let code = root.get_child("Code")?.get_text()?;
+            let message = root.get_child("Message").and_then(|m| m.get_text()).unwrap_or_default();
+            Some(PutObjectError::CompleteMultipartUpload(
+                CompleteMultipartUploadError::new(code, message),
+            ))
+        }
+        400 => {
+            let body = result.error_response_body.as_ref()?;
+            let root = xmltree::Element::parse(body.as_bytes()).ok()?;
+            let code = root.get_child("Code")?.get_text()?;
+            match code.as_ref() {
+                "BadDigest" | "InvalidDigest" => Some(PutObjectError::BadDigest),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::ffi::{OsStr, OsString};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::Poll;
+
+    use futures::executor::block_on;
+    use futures::future::poll_fn;
+    use futures::stream;
+
+    use super::*;
+
+    #[test]
+    fn upload_parts_stays_within_high_water_mark() {
+        const CHUNK_SIZE: usize = 1024;
+        const PART_SIZE: usize = 4 * CHUNK_SIZE;
+        const HIGH_WATER_MARK: usize = 3 * PART_SIZE;
+        const NUM_CHUNKS: usize = 101;
+
+        // The first chunk is already in the buffer when the upload starts
+        let pulled = Arc::new(AtomicUsize::new(CHUNK_SIZE));
+        let uploaded = Arc::new(AtomicUsize::new(0));
+        let max_ahead = Arc::new(AtomicUsize::new(0));
+        let received = Arc::new(Mutex::new(BTreeMap::new()));
+
+        // Record how far ahead of the finished parts each chunk is taken
+        let contents = {
+            let pulled = pulled.clone();
+            let uploaded = uploaded.clone();
+            let max_ahead = max_ahead.clone();
+            stream::iter(1..NUM_CHUNKS).map(move |i| {
+                let pulled = pulled.fetch_add(CHUNK_SIZE, Ordering::SeqCst) + CHUNK_SIZE;
+                max_ahead.fetch_max(pulled - uploaded.load(Ordering::SeqCst), Ordering::SeqCst);
+                vec![i as u8; CHUNK_SIZE]
+            })
+        };
+
+        // Parts take a few polls to upload, and don't finish in order
+        let upload_part = |part_number: usize, part: Vec<u8>| {
+            let uploaded = uploaded.clone();
+            let received = received.clone();
+            let mut polls = part_number % 3 + 1;
+            async move {
+                poll_fn(|cx| {
+                    if polls == 0 {
+                        return Poll::Ready(());
+                    }
+                    polls -= 1;
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                })
+                .await;
+                uploaded.fetch_add(part.len(), Ordering::SeqCst);
+                received.lock().unwrap().insert(part_number, part);
+                Ok::<_, ()>(format!("etag{part_number}"))
+            }
+        };
+
+        let first = vec![0u8; CHUNK_SIZE];
+        let (parts, size) = block_on(upload_parts(contents, first, PART_SIZE, HIGH_WATER_MARK, upload_part)).unwrap();
+
+        let max_ahead = max_ahead.load(Ordering::SeqCst);
+        assert!(max_ahead <= HIGH_WATER_MARK, "took {max_ahead} bytes ahead");
+        assert!(max_ahead > PART_SIZE, "only took {max_ahead} bytes ahead");
+
+        assert_eq!(size, (NUM_CHUNKS * CHUNK_SIZE) as u64);
+        let num_parts = (NUM_CHUNKS * CHUNK_SIZE).div_ceil(PART_SIZE);
+        let expected_parts: Vec<_> = (1..=num_parts).map(|n| (n, format!("etag{n}"))).collect();
+        assert_eq!(parts, expected_parts);
+
+        let body: Vec<u8> = received.lock().unwrap().values().flatten().copied().collect();
+        let expected_body: Vec<u8> = (0..NUM_CHUNKS).flat_map(|i| vec![i as u8; CHUNK_SIZE]).collect();
+        assert_eq!(body, expected_body);
+    }
+
+    #[test]
+    fn upload_parts_stops_at_first_failure() {
+        let contents = stream::iter(0..16).map(|i| vec![i as u8; 8]);
+        let result = block_on(upload_parts(contents, vec![], 16, 32, |part_number, _| async move {
+            if part_number == 2 {
+                Err(part_number)
+            } else {
+                Ok(String::new())
+            }
+        }));
+        assert_eq!(result, Err(2));
+    }
+
+    #[test]
+    fn complete_request_body() {
+        let parts = [(1, r#""abc""#.to_string()), (2, r#""def""#.to_string())];
+        assert_eq!(
+            build_complete_request_body(&parts),
+            r#"<CompleteMultipartUpload xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Part><PartNumber>1</PartNumber><ETag>"abc"</ETag></Part><Part><PartNumber>2</PartNumber><ETag>"def"</ETag></Part></CompleteMultipartUpload>"#
+        );
+    }
+
+    fn make_result(response_status: i32, body: impl Into<OsString>) -> MetaRequestResult {
+        MetaRequestResult {
+            response_status,
+            crt_error: 1i32.into(),
+            error_response_headers: None,
+            error_response_body: Some(body.into()),
+        }
+    }
+
+    #[test]
+    fn parse_200_with_error_body() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?>
+
+<Error><Code>InternalError</Code><Message>We encountered an internal error. Please try again.</Message><RequestId>656c76696e6727732072657175657374</RequestId><HostId>Uuag1LuByRx9e6j5Onimru9pO4ZVKnJ2Qz7/C1NPcfTWAtRPfTaOFg==</HostId></Error>"#;
+        let result = make_result(200, OsStr::from_bytes(&body[..]));
+        let result = parse_put_object_error(&result);
+        assert_eq!(
+            result,
+            Some(PutObjectError::CompleteMultipartUpload(
+                CompleteMultipartUploadError::new(
+                    "InternalError",
+                    "We encountered an internal error. Please try again."
+                )
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_400_bad_digest() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>BadDigest</Code><Message>The Content-MD5 you specified did not match what we received.</Message><ExpectedDigest>1B2M2Y8AsgTpgAmY7PhCfg==</ExpectedDigest><CalculatedDigest>rL0Y20zC+Fzt72VPzMSk2A==</CalculatedDigest><RequestId>4442587FB7D0A2F9</RequestId><HostId>S8E7B5jTKVwZBgJ4Z0ZMTiFvZPQvCVWFCXHUeS1F9w0=</HostId></Error>"#;
+        let result = make_result(400, OsStr::from_bytes(&body[..]));
+        let result = parse_put_object_error(&result);
+        assert_eq!(result, Some(PutObjectError::BadDigest));
+    }
+
+    #[test]
+    fn parse_200_without_error_body() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><CompleteMultipartUploadResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Location>http://DOC-EXAMPLE-BUCKET.s3.amazonaws.com/key</Location><Bucket>DOC-EXAMPLE-BUCKET</Bucket><Key>key</Key><ETag>"3858f62230ac3c915f300c664312c11f-9"</ETag></CompleteMultipartUploadResult>"#;
+        let result = make_result(200, OsStr::from_bytes(&body[..]));
+        let result = parse_put_object_error(&result);
+        assert_eq!(result, None);
+    }
+
+    /// Complete an upload that fails with each of `errors` in turn, then succeeds. Returns the
+    /// result and how many times the completion was sent.
+    fn complete_with_errors(
+        errors: &[&str],
+        max_retries: usize,
+    ) -> (ObjectClientResult<(), PutObjectError, ()>, usize) {
+        let config = RetryStrategyConfig {
+            write_max_retries: Some(max_retries),
+            backoff_scale_factor: std::time::Duration::from_millis(1),
+            ..Default::default()
+        };
+        let attempts = AtomicUsize::new(0);
+        let result = block_on(retry_complete(&config, || {
+            let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+            let result = match errors.get(attempt) {
+                Some(code) => Err(ObjectClientError::ServiceError(
+                    PutObjectError::CompleteMultipartUpload(CompleteMultipartUploadError::new(*code, "failed")),
+                )),
+                None => Ok(()),
+            };
+            async move { result }
+        }));
+        (result, attempts.load(Ordering::SeqCst))
+    }
+
+    #[test]
+    fn retry_transient_complete_failures() {
+        let (result, attempts) = complete_with_errors(&["InternalError", "SlowDown"], 3);
+        assert!(result.is_ok());
+        assert_eq!(attempts, 3);
+    }
+
+    #[test]
+    fn give_up_completing_after_max_retries() {
+        let (result, attempts) = complete_with_errors(&["InternalError"; 4], 3);
+        assert!(matches!(
+            result,
+            Err(ObjectClientError::ServiceError(PutObjectError::CompleteMultipartUpload(e))) if e.code == "InternalError"
+        ));
+        assert_eq!(attempts, 4);
+    }
 
-        Ok(PutObjectResult {})
+    #[test]
+    fn dont_retry_permanent_complete_failures() {
+        let (result, attempts) = complete_with_errors(&["InvalidPart"], 3);
+        assert!(matches!(
+            result,
+            Err(ObjectClientError::ServiceError(PutObjectError::CompleteMultipartUpload(e))) if e.code == "InvalidPart"
+        ));
+        assert_eq!(attempts, 1);
     }
 }
@@ -0,0 +1,112 @@
+use std::ops::Deref;
+use std::os::unix::prelude::OsStrExt;
+
+use mountpoint_s3_crt::http::request_response::Header;
+use mountpoint_s3_crt::s3::client::{MetaRequestResult, MetaRequestType};
+use tracing::debug;
+
+use crate::object_client::{ObjectCannedAcl, ObjectClientError, PutObjectAclError, PutObjectAclResult};
+use crate::{ObjectClientResult, S3CrtClient, S3RequestError};
+
+impl S3CrtClient {
+    /// Create and begin a new PutObjectAcl request.
+    pub async fn put_object_acl(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        acl: ObjectCannedAcl,
+    ) -> ObjectClientResult<PutObjectAclResult, PutObjectAclError, S3RequestError> {
+        let span = request_span!(self, "put_object_acl");
+        span.in_scope(|| debug!(?bucket, ?key, ?version_id, ?acl, "new request"));
+
+        // Scope the endpoint, message, etc. since otherwise rustc thinks we use Message across the await.
+        let request = {
+            let mut message = self
+                .new_request_template("PUT", bucket)
+                .map_err(S3RequestError::construction_failure)?;
+            let mut query = vec![("acl", "")];
+            if let Some(version_id) = version_id {
+                query.push(("versionId", version_id));
+            }
+            message
+                .set_request_path_and_query(format!("/{key}"), query)
+                .map_err(S3RequestError::construction_failure)?;
+            message
+                .add_header(&Header::new("x-amz-acl", acl.as_str()))
+                .map_err(S3RequestError::construction_failure)?;
+
+            self.make_meta_request(
+                message,
+                MetaRequestType::Default,
+                span,
+                |_, _| (),
+                |_, _| (),
+                move |result| {
+                    if result.is_err() {
+                        let parsed = parse_put_object_acl_error(&result);
+                        Err(parsed
+                            .map(ObjectClientError::ServiceError)
+                            .unwrap_or(ObjectClientError::ClientError(S3RequestError::ResponseError(result))))
+                    } else {
+                        Ok(())
+                    }
+                },
+            )?
+        };
+
+        request.await?;
+
+        Ok(PutObjectAclResult {})
+    }
+}
+
+fn parse_put_object_acl_error(result: &MetaRequestResult) -> Option<PutObjectAclError> {
+    match result.response_status {
+        404 => {
+            let body = result.error_response_body.as_ref()?;
+            let root = xmltree::Element::parse(body.as_bytes()).ok()?;
+            let error_code = root.get_child("Code")?;
+            let error_str = error_code.get_text()?;
+
+            match error_str.deref() {
+                "NoSuchBucket" => Some(PutObjectAclError::NoSuchBucket),
+                "NoSuchKey" | "NoSuchVersion" => Some(PutObjectAclError::NoSuchKey),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{OsStr, OsString};
+
+    use super::*;
+
+    fn make_result(response_status: i32, body: impl Into<OsString>) -> MetaRequestResult {
+        MetaRequestResult {
+            response_status,
+            crt_error: 1i32.into(),
+            error_response_headers: None,
+            error_response_body: Some(body.into()),
+        }
+    }
+
+    #[test]
+    fn parse_404_no_such_key() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>NoSuchKey</Code><Message>The specified key does not exist.</Message><Key>not-a-key</Key><RequestId>NN0K6KYG4ZJXHEPT</RequestId><HostId>rnSSzGjsKcmDCFfsPpv+ezMSqd5R8ezqCBCvhbZWDNNUVcxeeCHb5dOmzIuEeWkpp5RltmXZL3s=</HostId></Error>"#;
+        let result = make_result(404, OsStr::from_bytes(&body[..]));
+        let result = parse_put_object_acl_error(&result);
+        assert_eq!(result, Some(PutObjectAclError::NoSuchKey));
+    }
+
+    #[test]
+    fn parse_403() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>AccessDenied</Code><Message>Access Denied</Message><RequestId>3N6HSCDYNRC0NEW0</RequestId><HostId>fUFmlaKqFCuGq7oCfnAyFSjBVt/P7+pvmKGcPbdnrHDY9MRB+P7qhHHiyQ2XpWI3OloKtJZWb0U=</HostId></Error>"#;
+        let result = make_result(403, OsStr::from_bytes(&body[..]));
+        let result = parse_put_object_acl_error(&result);
+        assert_eq!(result, None);
+    }
+}
@@ -0,0 +1,348 @@
+//! A decorator [ObjectClient] that fails requests that take too long, for clients that can
+//! otherwise hang forever like [crate::mock_client::MockClient] and
+//! [crate::local_client::LocalObjectClient]. This lets code that handles timeouts be tested the
+//! same way against any client.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::future::{select, Either};
+use futures::{pin_mut, Stream};
+use pin_project::pin_project;
+use thiserror::Error;
+use tracing::warn;
+
+use crate::object_client::{
+    AbortMultipartUploadError, AbortMultipartUploadResult, CopyObjectError, CopyObjectResult, DeleteObjectError,
+    DeleteObjectResult, DeleteObjectsError, DeleteObjectsResult, GetBodyPart, GetObjectAttributesError,
+    GetObjectAttributesResult, GetObjectError, GetObjectMetadata, GetObjectParams, GetObjectProgress, HeadObjectError,
+    HeadObjectResult, ListBucketsError, ListBucketsResult, ListMultipartUploadsError, ListMultipartUploadsResult,
+    ListObjectVersionsError, ListObjectVersionsResult, ListObjectsError, ObjectCannedAcl, ObjectClientResult,
+    ObjectMetadataError, PresignPostError, PutObjectAclError, PutObjectAclResult, PutObjectError, PutObjectParams,
+    PutObjectResult,
+};
+use crate::retrying_client::RetryableError;
+use crate::timer::sleep;
+use crate::{ListObjectsResult, ObjectAttribute, ObjectClient, ObjectClientError, PostPolicyCondition, PresignedPost};
+
+/// Errors returned by a [TimeoutClient]
+#[derive(Debug, Error)]
+pub enum TimeoutClientError<E> {
+    #[error("request timed out after {0:?}")]
+    Timeout(Duration),
+
+    #[error(transparent)]
+    Client(E),
+}
+
+impl<E: RetryableError> RetryableError for TimeoutClientError<E> {
+    fn is_retryable(&self) -> bool {
+        match self {
+            TimeoutClientError::Timeout(_) => true,
+            TimeoutClientError::Client(e) => e.is_retryable(),
+        }
+    }
+}
+
+/// An [ObjectClient] that forwards requests to another client, failing any request that doesn't
+/// complete within a timeout with [TimeoutClientError::Timeout].
+///
+/// For GetObject requests, the timeout only covers the request itself and not streaming its body.
+#[derive(Debug)]
+pub struct TimeoutClient<Client> {
+    client: Client,
+    timeout: Duration,
+}
+
+impl<Client: ObjectClient> TimeoutClient<Client> {
+    /// Wrap the given client, failing its requests after `timeout`
+    pub fn new(client: Client, timeout: Duration) -> Self {
+        Self { client, timeout }
+    }
+
+    /// The client requests are forwarded to
+    pub fn inner(&self) -> &Client {
+        &self.client
+    }
+
+    /// Race a request against the timeout
+    async fn with_timeout<T, E>(
+        &self,
+        operation: &str,
+        request: impl Future<Output = ObjectClientResult<T, E, Client::ClientError>>,
+    ) -> ObjectClientResult<T, E, TimeoutClientError<Client::ClientError>> {
+        let timer = sleep(self.timeout);
+        pin_mut!(request, timer);
+        match select(request, timer).await {
+            Either::Left((result, _)) => result.map_err(wrap_error),
+            Either::Right(((), _)) => {
+                warn!(operation, timeout = ?self.timeout, "request timed out");
+                Err(ObjectClientError::ClientError(TimeoutClientError::Timeout(
+                    self.timeout,
+                )))
+            }
+        }
+    }
+}
+
+fn wrap_error<S, E>(error: ObjectClientError<S, E>) -> ObjectClientError<S, TimeoutClientError<E>> {
+    match error {
+        ObjectClientError::ServiceError(e) => ObjectClientError::ServiceError(e),
+        ObjectClientError::ClientError(e) => ObjectClientError::ClientError(TimeoutClientError::Client(e)),
+    }
+}
+
+#[async_trait]
+impl<Client> ObjectClient for TimeoutClient<Client>
+where
+    Client: ObjectClient + Send + Sync + 'static,
+{
+    type GetObjectResult = TimeoutGetObjectResult<Client>;
+    type ClientError = TimeoutClientError<Client::ClientError>;
+
+    async fn delete_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> ObjectClientResult<DeleteObjectResult, DeleteObjectError, Self::ClientError> {
+        self.with_timeout("DeleteObject", self.client.delete_object(bucket, key, version_id))
+            .await
+    }
+
+    async fn delete_objects(
+        &self,
+        bucket: &str,
+        keys: &[String],
+    ) -> ObjectClientResult<DeleteObjectsResult, DeleteObjectsError, Self::ClientError> {
+        self.with_timeout("DeleteObjects", self.client.delete_objects(bucket, keys))
+            .await
+    }
+
+    async fn get_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &GetObjectParams,
+    ) -> ObjectClientResult<Self::GetObjectResult, GetObjectError, Self::ClientError> {
+        let get_result = self
+            .with_timeout("GetObject", self.client.get_object(bucket, key, params))
+            .await?;
+        Ok(TimeoutGetObjectResult { get_result })
+    }
+
+    async fn list_objects(
+        &self,
+        bucket: &str,
+        continuation_token: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, Self::ClientError> {
+        self.with_timeout(
+            "ListObjects",
+            self.client
+                .list_objects(bucket, continuation_token, delimiter, max_keys, prefix),
+        )
+        .await
+    }
+
+    async fn list_object_versions(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        key_marker: Option<&str>,
+        version_id_marker: Option<&str>,
+        max_keys: usize,
+    ) -> ObjectClientResult<ListObjectVersionsResult, ListObjectVersionsError, Self::ClientError> {
+        self.with_timeout(
+            "ListObjectVersions",
+            self.client
+                .list_object_versions(bucket, prefix, key_marker, version_id_marker, max_keys),
+        )
+        .await
+    }
+
+    async fn head_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> ObjectClientResult<HeadObjectResult, HeadObjectError, Self::ClientError> {
+        self.with_timeout("HeadObject", self.client.head_object(bucket, key, version_id))
+            .await
+    }
+
+    async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectParams,
+        contents: impl Stream<Item = impl AsRef<[u8]> + Send> + Send,
+    ) -> ObjectClientResult<PutObjectResult, PutObjectError, Self::ClientError> {
+        self.with_timeout("PutObject", self.client.put_object(bucket, key, params, contents))
+            .await
+    }
+
+    async fn copy_object(
+        &self,
+        source_bucket: &str,
+        source_key: &str,
+        destination_bucket: &str,
+        destination_key: &str,
+    ) -> ObjectClientResult<CopyObjectResult, CopyObjectError, Self::ClientError> {
+        self.with_timeout(
+            "CopyObject",
+            self.client
+                .copy_object(source_bucket, source_key, destination_bucket, destination_key),
+        )
+        .await
+    }
+
+    async fn put_object_acl(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        acl: ObjectCannedAcl,
+    ) -> ObjectClientResult<PutObjectAclResult, PutObjectAclError, Self::ClientError> {
+        self.with_timeout("PutObjectAcl", self.client.put_object_acl(bucket, key, version_id, acl))
+            .await
+    }
+
+    async fn list_multipart_uploads(
+        &self,
+        bucket: &str,
+        prefix: &str,
+    ) -> ObjectClientResult<ListMultipartUploadsResult, ListMultipartUploadsError, Self::ClientError> {
+        self.with_timeout(
+            "ListMultipartUploads",
+            self.client.list_multipart_uploads(bucket, prefix),
+        )
+        .await
+    }
+
+    async fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> ObjectClientResult<AbortMultipartUploadResult, AbortMultipartUploadError, Self::ClientError> {
+        self.with_timeout(
+            "AbortMultipartUpload",
+            self.client.abort_multipart_upload(bucket, key, upload_id),
+        )
+        .await
+    }
+
+    async fn list_buckets(&self) -> ObjectClientResult<ListBucketsResult, ListBucketsError, Self::ClientError> {
+        self.with_timeout("ListBuckets", self.client.list_buckets()).await
+    }
+
+    async fn get_object_attributes(
+        &self,
+        bucket: &str,
+        key: &str,
+        max_parts: Option<usize>,
+        part_number_marker: Option<usize>,
+        object_attributes: &[ObjectAttribute],
+    ) -> ObjectClientResult<GetObjectAttributesResult, GetObjectAttributesError, Self::ClientError> {
+        self.with_timeout(
+            "GetObjectAttributes",
+            self.client
+                .get_object_attributes(bucket, key, max_parts, part_number_marker, object_attributes),
+        )
+        .await
+    }
+
+    async fn presign_post_object(
+        &self,
+        bucket: &str,
+        key_prefix: &str,
+        conditions: &[PostPolicyCondition],
+        expires_in: Duration,
+    ) -> ObjectClientResult<PresignedPost, PresignPostError, Self::ClientError> {
+        // Presigning doesn't make a request, so it can't hang
+        self.client
+            .presign_post_object(bucket, key_prefix, conditions, expires_in)
+            .await
+            .map_err(wrap_error)
+    }
+}
+
+/// The body of a GetObject request made through a [TimeoutClient]
+#[pin_project]
+pub struct TimeoutGetObjectResult<Client: ObjectClient> {
+    #[pin]
+    get_result: Client::GetObjectResult,
+}
+
+impl<Client: ObjectClient> GetObjectProgress for TimeoutGetObjectResult<Client> {
+    fn bytes_transferred(&self) -> u64 {
+        self.get_result.bytes_transferred()
+    }
+
+    fn object_metadata(&self) -> Option<Result<GetObjectMetadata, ObjectMetadataError>> {
+        self.get_result.object_metadata()
+    }
+}
+
+impl<Client: ObjectClient> Stream for TimeoutGetObjectResult<Client> {
+    type Item = ObjectClientResult<GetBodyPart, GetObjectError, TimeoutClientError<Client::ClientError>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project()
+            .get_result
+            .poll_next(cx)
+            .map(|part| part.map(|part| part.map_err(wrap_error)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_client::{MockClient, MockClientConfig, MockObject, Operation};
+    use crate::ETag;
+
+    fn mock_client() -> MockClient {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 128,
+        });
+        client.add_object("key", MockObject::constant(0xa1, 50, ETag::for_tests()));
+        client
+    }
+
+    #[tokio::test]
+    async fn slow_request_times_out() {
+        let slow_client = mock_client();
+        slow_client.set_latency(Operation::HeadObject, Duration::from_secs(5));
+        let client = TimeoutClient::new(slow_client, Duration::from_millis(50));
+
+        let result = client.head_object("test_bucket", "key", None).await;
+        assert!(matches!(
+            result,
+            Err(ObjectClientError::ClientError(TimeoutClientError::Timeout(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn fast_request_succeeds() {
+        let client = TimeoutClient::new(mock_client(), Duration::from_secs(5));
+
+        let result = client
+            .head_object("test_bucket", "key", None)
+            .await
+            .expect("head should succeed");
+        assert_eq!(result.object.size, 50);
+
+        let result = client.head_object("test_bucket", "missing", None).await;
+        assert!(matches!(
+            result,
+            Err(ObjectClientError::ServiceError(HeadObjectError::NotFound))
+        ));
+    }
+}
@@ -0,0 +1,167 @@
+//! A timer for waiting without blocking the caller's executor.
+//!
+//! We don't know which runtime we're running on, so timers are run by a thread of their own. It's
+//! shared by every [Sleep], so waiting doesn't cost a thread each, and a [Sleep] that's dropped
+//! before it completes is forgotten straight away.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+
+lazy_static! {
+    static ref TIMER: Timer = Timer::start();
+}
+
+/// Wait for the given duration
+pub fn sleep(duration: Duration) -> Sleep {
+    Sleep {
+        deadline: Instant::now() + duration,
+        key: None,
+    }
+}
+
+/// Future returned by [sleep]
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct Sleep {
+    deadline: Instant,
+    /// Where this is registered with the timer thread, once it's been polled
+    key: Option<(Instant, u64)>,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            if let Some(key) = self.key.take() {
+                TIMER.cancel(key);
+            }
+            return Poll::Ready(());
+        }
+        let deadline = self.deadline;
+        self.key = Some(TIMER.register(self.key, deadline, cx.waker()));
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            TIMER.cancel(key);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Timer {
+    state: Mutex<TimerState>,
+    /// Notified when there's a new earliest deadline
+    changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct TimerState {
+    next_id: u64,
+    /// The tasks to wake, ordered by when to wake them. Ids break ties between equal deadlines.
+    waiting: BTreeMap<(Instant, u64), Waker>,
+}
+
+impl Timer {
+    fn start() -> Self {
+        std::thread::Builder::new()
+            .name("timer".to_owned())
+            .spawn(|| TIMER.run())
+            .expect("failed to spawn timer thread");
+        Self {
+            state: Default::default(),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Wake `waker` at `deadline`, replacing the waker registered with `key` if there is one.
+    /// Returns the key to cancel it with.
+    fn register(&self, key: Option<(Instant, u64)>, deadline: Instant, waker: &Waker) -> (Instant, u64) {
+        let mut state = self.state.lock().unwrap();
+        let key = key.unwrap_or_else(|| {
+            state.next_id += 1;
+            (deadline, state.next_id)
+        });
+        state.waiting.insert(key, waker.clone());
+        if state.waiting.keys().next() == Some(&key) {
+            self.changed.notify_one();
+        }
+        key
+    }
+
+    fn cancel(&self, key: (Instant, u64)) {
+        self.state.lock().unwrap().waiting.remove(&key);
+    }
+
+    fn run(&self) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            let mut expired = Vec::new();
+            while let Some(entry) = state.waiting.first_entry() {
+                if entry.key().0 > now {
+                    break;
+                }
+                expired.push(entry.remove());
+            }
+
+            // Don't hold the lock while waking, in case the executor polls the task straight away
+            if !expired.is_empty() {
+                drop(state);
+                expired.into_iter().for_each(Waker::wake);
+                state = self.state.lock().unwrap();
+                continue;
+            }
+
+            state = match state.waiting.keys().next().map(|(deadline, _)| *deadline) {
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(now);
+                    self.changed.wait_timeout(state, timeout).unwrap().0
+                }
+                None => self.changed.wait(state).unwrap(),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::future::{select, Either};
+
+    #[test]
+    fn sleep_waits() {
+        let start = Instant::now();
+        block_on(sleep(Duration::from_millis(50)));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn earliest_sleep_finishes_first() {
+        let result = block_on(select(sleep(Duration::from_secs(10)), sleep(Duration::from_millis(10))));
+        assert!(matches!(result, Either::Right(_)));
+    }
+
+    #[test]
+    fn dropped_sleeps_are_forgotten() {
+        let result = block_on(select(sleep(Duration::from_secs(10)), sleep(Duration::from_millis(10))));
+        let Either::Right(((), slow)) = result else {
+            panic!("slow sleep finished first");
+        };
+        let key = slow.key.expect("slow sleep was polled");
+        assert!(TIMER.state.lock().unwrap().waiting.contains_key(&key));
+        drop(slow);
+        assert!(!TIMER.state.lock().unwrap().waiting.contains_key(&key));
+    }
+}
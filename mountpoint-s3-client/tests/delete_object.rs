@@ -25,7 +25,7 @@ async fn test_delete_object() {
 
     let client: S3CrtClient = get_test_client();
     let _result = client
-        .delete_object(&bucket, &key)
+        .delete_object(&bucket, &key, None)
         .await
         .expect("delete_object should succeed");
 
@@ -58,7 +58,7 @@ async fn test_delete_object_no_obj() {
 
     let client: S3CrtClient = get_test_client();
     let _result = client
-        .delete_object(&bucket, &key)
+        .delete_object(&bucket, &key, None)
         .await
         .expect("delete_object should not fail for non-existent object");
 }
@@ -71,7 +71,7 @@ async fn test_delete_object_404_bucket() {
 
     let client: S3CrtClient = get_test_client();
 
-    let result = client.delete_object("DOC-EXAMPLE-BUCKET", &key).await;
+    let result = client.delete_object("DOC-EXAMPLE-BUCKET", &key, None).await;
     assert!(matches!(
         result,
         Err(ObjectClientError::ServiceError(DeleteObjectError::NoSuchBucket))
@@ -87,7 +87,7 @@ async fn test_delete_object_no_perm() {
 
     let client: S3CrtClient = get_test_client();
 
-    let result = client.delete_object(&bucket, &key).await;
+    let result = client.delete_object(&bucket, &key, None).await;
 
     if let Err(ObjectClientError::ClientError(S3RequestError::ResponseError(err))) = &result {
         assert!(err.response_status == 403);
@@ -5,7 +5,7 @@ pub mod common;
 use aws_sdk_s3::types::ByteStream;
 use bytes::Bytes;
 use common::*;
-use mountpoint_s3_client::{AddressingStyle, Endpoint, ObjectClient, S3ClientConfig, S3CrtClient};
+use mountpoint_s3_client::{AddressingStyle, Endpoint, GetObjectParams, ObjectClient, S3ClientConfig, S3CrtClient};
 use test_case::test_case;
 
 async fn run_test<F: FnOnce(&str) -> Endpoint>(f: F) {
@@ -33,7 +33,7 @@ async fn run_test<F: FnOnce(&str) -> Endpoint>(f: F) {
     let client = S3CrtClient::new(&region, config).expect("could not create test client");
 
     let result = client
-        .get_object(&bucket, &key, None, None)
+        .get_object(&bucket, &key, &GetObjectParams::new())
         .await
         .expect("get_object should succeed");
     check_get_result(result, None, &body[..]).await;
@@ -5,7 +5,10 @@ pub mod common;
 use aws_sdk_s3::types::ByteStream;
 use bytes::Bytes;
 use common::*;
-use mountpoint_s3_client::{HeadObjectError, ObjectClientError, S3CrtClient};
+use futures::StreamExt;
+use mountpoint_s3_client::{
+    GetObjectError, GetObjectParams, HeadObjectError, ObjectClient, ObjectClientError, S3CrtClient,
+};
 
 #[tokio::test]
 async fn test_head_object() {
@@ -24,13 +27,53 @@ async fn test_head_object() {
         .unwrap();
 
     let client: S3CrtClient = get_test_client();
-    let result = client.head_object(&bucket, &key).await.expect("head_object failed");
+    let result = client
+        .head_object(&bucket, &key, None)
+        .await
+        .expect("head_object failed");
 
     assert_eq!(result.bucket, bucket);
     assert_eq!(result.object.key, key);
     assert_eq!(result.object.size as usize, body.len());
 }
 
+#[tokio::test]
+async fn test_head_object_cache_validators() {
+    let sdk_client = get_test_sdk_client().await;
+    let (bucket, prefix) = get_test_bucket_and_prefix("test_head_object_cache_validators");
+
+    let key = format!("{prefix}/hello");
+    let body = b"hello world!";
+    sdk_client
+        .put_object()
+        .bucket(&bucket)
+        .key(&key)
+        .body(ByteStream::from(Bytes::from_static(body)))
+        .send()
+        .await
+        .unwrap();
+
+    let client: S3CrtClient = get_test_client();
+    let validators = client
+        .head_object(&bucket, &key, None)
+        .await
+        .expect("head_object failed")
+        .cache_validators();
+
+    let params = GetObjectParams::new()
+        .if_match(Some(validators.etag))
+        .if_modified_since(Some(validators.last_modified));
+    let mut result = client
+        .get_object(&bucket, &key, &params)
+        .await
+        .expect("get_object should succeed");
+    let next = StreamExt::next(&mut result).await.expect("stream needs to return Err");
+    assert!(matches!(
+        next,
+        Err(ObjectClientError::ServiceError(GetObjectError::NotModified))
+    ));
+}
+
 #[tokio::test]
 async fn test_head_object_404_key() {
     let (bucket, prefix) = get_test_bucket_and_prefix("test_head_object_404_key");
@@ -39,7 +82,7 @@ async fn test_head_object_404_key() {
 
     let client: S3CrtClient = get_test_client();
 
-    let result = client.head_object(&bucket, &key).await;
+    let result = client.head_object(&bucket, &key, None).await;
     assert!(matches!(
         result,
         Err(ObjectClientError::ServiceError(HeadObjectError::NotFound))
@@ -54,7 +97,7 @@ async fn test_head_object_404_bucket() {
 
     let client: S3CrtClient = get_test_client();
 
-    let result = client.head_object("DOC-EXAMPLE-BUCKET", &key).await;
+    let result = client.head_object("DOC-EXAMPLE-BUCKET", &key, None).await;
     assert!(matches!(
         result,
         Err(ObjectClientError::ServiceError(HeadObjectError::NotFound))
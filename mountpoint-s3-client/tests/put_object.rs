@@ -3,10 +3,12 @@
 pub mod common;
 
 use common::*;
-use futures::future;
-use futures::stream;
-use mountpoint_s3_client::ObjectClient;
+use futures::future::{self, Either};
+use futures::stream::{self, StreamExt};
+use mountpoint_s3_client::timer::sleep;
+use mountpoint_s3_client::{GetObjectParams, ObjectClient, S3ClientConfig, S3CrtClient};
 use rand::Rng;
+use std::time::Duration;
 
 // Simple test for PUT object. Puts a single, small object as a single part and checks that the
 // contents are correct with a GET.
@@ -29,7 +31,7 @@ async fn test_put_object(client: &impl ObjectClient, bucket: &str, prefix: &str)
         .expect("put_object should succeed");
 
     let result = client
-        .get_object(bucket, &key, None, None)
+        .get_object(bucket, &key, &GetObjectParams::new())
         .await
         .expect("get_object should succeed");
     check_get_result(result, None, &contents[..]).await;
@@ -56,10 +58,57 @@ async fn test_put_object_multi_part(client: &impl ObjectClient, bucket: &str, pr
         .expect("put_object failed");
 
     let result = client
-        .get_object(bucket, &key, None, None)
+        .get_object(bucket, &key, &GetObjectParams::new())
         .await
         .expect("get_object failed");
     check_get_result(result, None, &contents[..]).await;
 }
 
 object_client_test!(test_put_object_multi_part);
+
+/// Whether `key` has a multipart upload in progress
+async fn has_multipart_upload(client: &S3CrtClient, bucket: &str, key: &str) -> bool {
+    let result = client
+        .list_multipart_uploads(bucket, key)
+        .await
+        .expect("list_multipart_uploads failed");
+    result.uploads.iter().any(|upload| upload.key == key)
+}
+
+// A streaming multipart upload that's dropped before it finishes (for example by a timeout)
+// shouldn't leave the parts it already uploaded behind.
+#[tokio::test]
+async fn test_put_object_dropped_aborts_upload() {
+    const PART_SIZE: usize = 5 * 1024 * 1024;
+
+    let (bucket, prefix) = get_test_bucket_and_prefix("test_put_object_dropped_aborts_upload");
+    let key = format!("{prefix}hello");
+    let config = S3ClientConfig {
+        part_size: Some(PART_SIZE),
+        upload_buffer_size: Some(PART_SIZE),
+        ..Default::default()
+    };
+    let client = S3CrtClient::new(&get_test_region(), config).expect("could not create test client");
+
+    // One part, then contents that never finish
+    let contents = stream::once(future::ready(vec![0u8; PART_SIZE])).chain(stream::pending());
+    let put = Box::pin(client.put_object(&bucket, &key, &Default::default(), contents));
+    let started = Box::pin(async {
+        while !has_multipart_upload(&client, &bucket, &key).await {
+            sleep(Duration::from_millis(100)).await;
+        }
+    });
+    match future::select(put, started).await {
+        Either::Left((result, _)) => panic!("put_object shouldn't finish: {result:?}"),
+        Either::Right((_, put)) => drop(put),
+    }
+
+    // The abort is sent in the background, so give it a little while
+    for _ in 0..100 {
+        if !has_multipart_upload(&client, &bucket, &key).await {
+            return;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    panic!("dropped upload wasn't aborted");
+}
@@ -42,6 +42,7 @@ const CRT_HEADERS: &[&str] = &[
     "io/channel_bootstrap.h",
     "io/event_loop.h",
     "io/host_resolver.h",
+    "io/socket.h",
     "io/stream.h",
     "io/uri.h",
     "s3/s3.h",
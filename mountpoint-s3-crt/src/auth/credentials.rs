@@ -1,16 +1,22 @@
 //! AWS credentials providers
 
 use mountpoint_s3_crt_sys::{
-    aws_credentials_provider, aws_credentials_provider_chain_default_options,
+    aws_credentials, aws_credentials_get_access_key_id, aws_credentials_get_secret_access_key,
+    aws_credentials_get_session_token, aws_credentials_provider, aws_credentials_provider_acquire,
+    aws_credentials_provider_chain_default_options, aws_credentials_provider_get_credentials,
     aws_credentials_provider_new_chain_default, aws_credentials_provider_new_profile,
-    aws_credentials_provider_profile_options, aws_credentials_provider_release,
+    aws_credentials_provider_new_static, aws_credentials_provider_profile_options, aws_credentials_provider_release,
+    aws_credentials_provider_static_options,
 };
 
 use crate::auth::auth_library_init;
 use crate::common::allocator::Allocator;
 use crate::common::error::Error;
 use crate::io::channel_bootstrap::ClientBootstrap;
-use crate::{CrtError as _, StringExt};
+use crate::{aws_byte_cursor_as_slice, CrtError as _, StringExt};
+use futures::channel::oneshot;
+use std::fmt::Debug;
+use std::future::Future;
 use std::ptr::NonNull;
 
 /// Options for creating a default credentials provider
@@ -29,6 +35,17 @@ pub struct CredentialsProviderProfileOptions<'a> {
     pub profile_name_override: &'a str,
 }
 
+/// Options for creating a static credentials provider
+#[derive(Debug)]
+pub struct CredentialsProviderStaticOptions<'a> {
+    /// The access key ID
+    pub access_key_id: &'a str,
+    /// The secret access key
+    pub secret_access_key: &'a str,
+    /// The session token, for temporary credentials
+    pub session_token: Option<&'a str>,
+}
+
 /// A credentials provider is an object that has an asynchronous query function for retrieving AWS
 /// credentials
 #[derive(Debug)]
@@ -36,6 +53,11 @@ pub struct CredentialsProvider {
     pub(crate) inner: NonNull<aws_credentials_provider>,
 }
 
+// SAFETY: `aws_credentials_provider` is reference counted and its query function is thread-safe.
+unsafe impl Send for CredentialsProvider {}
+// SAFETY: `aws_credentials_provider` is reference counted and its query function is thread-safe.
+unsafe impl Sync for CredentialsProvider {}
+
 impl CredentialsProvider {
     /// Creates the default credential provider chain as used by most AWS SDKs
     pub fn new_chain_default(
@@ -75,6 +97,66 @@ impl CredentialsProvider {
 
         Ok(Self { inner })
     }
+
+    /// Creates a credentials provider that always returns the same credentials.
+    pub fn new_static(allocator: &Allocator, options: CredentialsProviderStaticOptions) -> Result<Self, Error> {
+        auth_library_init(allocator);
+
+        // SAFETY: aws_credentials_provider_new_static makes a copy of the credentials.
+        let inner = unsafe {
+            let inner_options = aws_credentials_provider_static_options {
+                access_key_id: options.access_key_id.as_aws_byte_cursor(),
+                secret_access_key: options.secret_access_key.as_aws_byte_cursor(),
+                session_token: options.session_token.unwrap_or_default().as_aws_byte_cursor(),
+                ..Default::default()
+            };
+
+            aws_credentials_provider_new_static(allocator.inner.as_ptr(), &inner_options).ok_or_last_error()?
+        };
+
+        Ok(Self { inner })
+    }
+
+    /// Retrieve credentials from this provider. The returned future completes once the provider
+    /// has resolved credentials, which may involve network requests (for example, to IMDS).
+    pub fn get_credentials(&self) -> impl Future<Output = Result<Credentials, Error>> {
+        let (sender, receiver) = oneshot::channel::<Result<Credentials, Error>>();
+        let sender_raw_ptr = Box::into_raw(Box::new(sender)) as *mut libc::c_void;
+
+        // SAFETY: `self.inner` is a valid `aws_credentials_provider`. `sender_raw_ptr` is leaked by
+        // [Box::into_raw] and reclaimed by `get_credentials_callback`, which the CRT invokes exactly
+        // once if the query was started successfully.
+        let started = unsafe {
+            aws_credentials_provider_get_credentials(
+                self.inner.as_ptr(),
+                Some(get_credentials_callback),
+                sender_raw_ptr,
+            )
+            .ok_or_last_error()
+        };
+
+        if started.is_err() {
+            // SAFETY: the query failed to start, so the callback will never run and we still own
+            // the sender.
+            unsafe { drop(Box::from_raw(sender_raw_ptr as *mut CredentialsSender)) };
+        }
+
+        async move {
+            started?;
+            receiver.await.expect("callback should always send a result")
+        }
+    }
+}
+
+impl Clone for CredentialsProvider {
+    fn clone(&self) -> Self {
+        // SAFETY: `self.inner` is a valid `aws_credentials_provider`, and the new reference is
+        // released when the clone is dropped.
+        unsafe {
+            aws_credentials_provider_acquire(self.inner.as_ptr());
+        }
+        Self { inner: self.inner }
+    }
 }
 
 impl Drop for CredentialsProvider {
@@ -86,3 +168,76 @@ impl Drop for CredentialsProvider {
         }
     }
 }
+
+/// A set of AWS credentials retrieved from a [CredentialsProvider]
+///
+/// The [Debug] implementation doesn't show the secret access key or session token.
+#[derive(Clone)]
+pub struct Credentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+impl Credentials {
+    /// Create credentials from their parts
+    pub fn new(access_key_id: &str, secret_access_key: &str, session_token: Option<&str>) -> Self {
+        Self {
+            access_key_id: access_key_id.to_owned(),
+            secret_access_key: secret_access_key.to_owned(),
+            session_token: session_token.map(str::to_owned),
+        }
+    }
+
+    /// The access key ID
+    pub fn access_key_id(&self) -> &str {
+        &self.access_key_id
+    }
+
+    /// The secret access key
+    pub fn secret_access_key(&self) -> &str {
+        &self.secret_access_key
+    }
+
+    /// The session token, for temporary credentials
+    pub fn session_token(&self) -> Option<&str> {
+        self.session_token.as_deref()
+    }
+}
+
+impl Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("access_key_id", &self.access_key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+type CredentialsSender = oneshot::Sender<Result<Credentials, Error>>;
+
+/// Rust binding for CRT's callback function `aws_on_get_credentials_callback_fn`.
+unsafe extern "C" fn get_credentials_callback(
+    credentials: *mut aws_credentials,
+    error_code: i32,
+    user_data: *mut libc::c_void,
+) {
+    // SAFETY: `user_data` is a raw pointer to a `Box<CredentialsSender>` created and leaked at query
+    // time. This function will be executed at most once, so the Box is still valid right now.
+    let sender = Box::from_raw(user_data as *mut CredentialsSender);
+
+    let result = if error_code != 0 || credentials.is_null() {
+        Err(error_code.into())
+    } else {
+        // SAFETY: the CRT guarantees `credentials` is valid for the duration of this callback, and
+        // we copy the strings out of it before returning.
+        let to_string = |cursor| String::from_utf8_lossy(aws_byte_cursor_as_slice(&cursor)).into_owned();
+        let session_token = to_string(aws_credentials_get_session_token(credentials));
+        Ok(Credentials {
+            access_key_id: to_string(aws_credentials_get_access_key_id(credentials)),
+            secret_access_key: to_string(aws_credentials_get_secret_access_key(credentials)),
+            session_token: (!session_token.is_empty()).then_some(session_token),
+        })
+    };
+
+    let _ = sender.send(result);
+}
@@ -1,7 +1,7 @@
 //! Configuration for signing requests to AWS APIs
 
 use crate::auth::credentials::CredentialsProvider;
-use mountpoint_s3_crt_sys::aws_signing_config_aws;
+use mountpoint_s3_crt_sys::{aws_signing_algorithm, aws_signing_config_aws};
 use std::ffi::OsString;
 use std::fmt::Debug;
 use std::marker::PhantomPinned;
@@ -15,6 +15,9 @@ pub(crate) struct SigningConfigInner {
     /// An owned copy of the region string, since the `aws_signing_config_aws` holds a pointer to it.
     pub(crate) region: OsString,
 
+    /// An owned copy of the service name, since the `aws_signing_config_aws` holds a pointer to it.
+    pub(crate) service: OsString,
+
     /// An owned CredentialProvider, since the holds `aws_signing_config_aws` a pointer to it inner.
     pub(crate) credentials_provider: CredentialsProvider,
 
@@ -26,6 +29,7 @@ impl Debug for SigningConfigInner {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("SigningConfigInner")
             .field("region", &self.region)
+            .field("service", &self.service)
             .finish()
     }
 }
@@ -41,4 +45,38 @@ impl SigningConfig {
     pub(crate) fn to_inner_ptr(&self) -> *const aws_signing_config_aws {
         &Pin::as_ref(&self.0).get_ref().inner
     }
+
+    /// The algorithm this config signs requests with
+    pub fn algorithm(&self) -> SigningAlgorithm {
+        Pin::as_ref(&self.0).get_ref().inner.algorithm.into()
+    }
+}
+
+/// The algorithm used to sign requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SigningAlgorithm {
+    /// Signature Version 4, which signs requests for a single region
+    #[default]
+    SigV4,
+    /// Signature Version 4A, which signs requests for a set of regions (for example, to use
+    /// multi-region access points)
+    SigV4A,
+}
+
+impl From<SigningAlgorithm> for aws_signing_algorithm {
+    fn from(algorithm: SigningAlgorithm) -> Self {
+        match algorithm {
+            SigningAlgorithm::SigV4 => aws_signing_algorithm::AWS_SIGNING_ALGORITHM_V4,
+            SigningAlgorithm::SigV4A => aws_signing_algorithm::AWS_SIGNING_ALGORITHM_V4_ASYMMETRIC,
+        }
+    }
+}
+
+impl From<aws_signing_algorithm> for SigningAlgorithm {
+    fn from(algorithm: aws_signing_algorithm) -> Self {
+        match algorithm {
+            aws_signing_algorithm::AWS_SIGNING_ALGORITHM_V4_ASYMMETRIC => SigningAlgorithm::SigV4A,
+            _ => SigningAlgorithm::SigV4,
+        }
+    }
 }
@@ -81,6 +81,12 @@ impl Uri {
         }
     }
 
+    /// Return the path portion of the URI with any percent-encoding decoded. Unlike query strings,
+    /// paths don't use `+` for spaces, so it's left as is.
+    pub fn decoded_path(&self) -> Result<String, UriError> {
+        decode_component(&self.path().to_string_lossy())
+    }
+
     /// Return the query portion of the URI, without the leading "?". If no query string was
     /// present, returns an empty string.
     pub fn query_string(&self) -> &OsStr {
@@ -108,6 +114,45 @@ impl Uri {
     }
 }
 
+/// Errors returned when decoding a URI component
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum UriError {
+    #[error("invalid percent-encoding in {0:?}")]
+    InvalidPercentEncoding(String),
+
+    #[error("decoded component is not valid UTF-8")]
+    InvalidUtf8(#[from] std::string::FromUtf8Error),
+}
+
+/// Decode a percent-encoded URI component, such as a path segment. `+` is decoded as itself rather
+/// than as a space; use [decode_query_component] for form-encoded values.
+pub fn decode_component(component: &str) -> Result<String, UriError> {
+    fn hex_value(digit: u8) -> Option<u8> {
+        (digit as char).to_digit(16).map(|d| d as u8)
+    }
+
+    let invalid = || UriError::InvalidPercentEncoding(component.to_owned());
+    let mut bytes = component.bytes();
+    let mut decoded = Vec::with_capacity(component.len());
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let high = bytes.next().and_then(hex_value).ok_or_else(invalid)?;
+            let low = bytes.next().and_then(hex_value).ok_or_else(invalid)?;
+            decoded.push(high << 4 | low);
+        } else {
+            decoded.push(byte);
+        }
+    }
+    Ok(String::from_utf8(decoded)?)
+}
+
+/// Decode a form-encoded query string value, where `+` stands for a space and a literal `+` is
+/// percent-encoded. S3 encodes keys this way in responses to requests made with
+/// `encoding-type=url`.
+pub fn decode_query_component(component: &str) -> Result<String, UriError> {
+    decode_component(&component.replace('+', " "))
+}
+
 impl Clone for Uri {
     fn clone(&self) -> Self {
         // `aws_uri` has no convenient clone method, and it's self-referential, so the easiest way
@@ -148,6 +193,10 @@ unsafe impl Sync for Uri {}
 
 #[cfg(test)]
 mod tests {
+    use mountpoint_s3_crt_sys::{
+        aws_byte_buf, aws_byte_buf_append_encoding_uri_param, aws_byte_buf_clean_up, aws_byte_buf_init,
+    };
+
     use super::*;
 
     #[test]
@@ -175,4 +224,62 @@ mod tests {
         let clone = uri.clone();
         assert_eq!(clone.as_os_str(), uri_str);
     }
+
+    /// Percent-encode a URI query parameter value with the CRT's encoder
+    fn encode_component(component: &str) -> String {
+        let allocator = Allocator::default();
+        // SAFETY: the buffer is initialized before it's appended to and cleaned up after its
+        // contents have been copied out.
+        unsafe {
+            let mut buf: aws_byte_buf = Default::default();
+            aws_byte_buf_init(&mut buf, allocator.inner.as_ptr(), component.len())
+                .ok_or_last_error()
+                .unwrap();
+            let cursor = OsStr::new(component).as_aws_byte_cursor();
+            aws_byte_buf_append_encoding_uri_param(&mut buf, &cursor)
+                .ok_or_last_error()
+                .unwrap();
+            let encoded = aws_byte_cursor_as_slice(&aws_byte_cursor_from_buf(&buf)).to_vec();
+            aws_byte_buf_clean_up(&mut buf);
+            String::from_utf8(encoded).unwrap()
+        }
+    }
+
+    #[test]
+    fn decode_component_roundtrip() {
+        for key in [
+            "hello world",
+            "a+b",
+            "dir/file",
+            "literal%2Fslash",
+            "spaces and+plus/%2F/ünïcödé",
+            "",
+        ] {
+            let encoded = encode_component(key);
+            assert!(!encoded.contains(' '), "{encoded:?} should have no spaces");
+            assert_eq!(decode_component(&encoded).unwrap(), key, "encoded as {encoded:?}");
+        }
+
+        assert_eq!(decode_component("a+b%20c%2Fd").unwrap(), "a+b c/d");
+        assert_eq!(decode_component("%2f%2F").unwrap(), "//");
+        assert_eq!(decode_query_component("a+b%2Bc%20d").unwrap(), "a b+c d");
+    }
+
+    #[test]
+    fn decode_component_invalid() {
+        for invalid in ["%", "%2", "abc%zz", "%g0"] {
+            assert_eq!(
+                decode_component(invalid),
+                Err(UriError::InvalidPercentEncoding(invalid.to_owned()))
+            );
+        }
+        assert!(matches!(decode_component("%ff%fe"), Err(UriError::InvalidUtf8(_))));
+    }
+
+    #[test]
+    fn decoded_path() {
+        let uri_str = OsStr::new("https://examplebucket.s3.amazonaws.com/dir%20one/a+b%2Bc%252F?list-type=2");
+        let uri = Uri::new_from_str(&mut Allocator::default(), uri_str).unwrap();
+        assert_eq!(uri.decoded_path().unwrap(), "/dir one/a+b+c%2F");
+    }
 }
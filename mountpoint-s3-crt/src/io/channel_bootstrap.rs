@@ -3,7 +3,7 @@
 use crate::common::allocator::Allocator;
 use crate::common::error::Error;
 use crate::io::event_loop::EventLoopGroup;
-use crate::io::host_resolver::HostResolver;
+use crate::io::host_resolver::{AddressFamily, HostResolver};
 use crate::io::io_library_init;
 use crate::CrtError as _;
 use mountpoint_s3_crt_sys::*;
@@ -22,6 +22,8 @@ pub struct ClientBootstrapOptions<'a> {
     pub event_loop_group: &'a mut EventLoopGroup,
     /// The [HostResolver] to use to resolve endpoints
     pub host_resolver: &'a mut HostResolver,
+    /// Which IP address family to connect to endpoints with
+    pub address_family: AddressFamily,
 }
 
 impl ClientBootstrap {
@@ -29,14 +31,19 @@ impl ClientBootstrap {
     pub fn new(allocator: &Allocator, options: &ClientBootstrapOptions) -> Result<Self, Error> {
         io_library_init(allocator);
 
+        let host_resolution_config = options.address_family.host_resolution_config();
         let inner_options = aws_client_bootstrap_options {
             event_loop_group: options.event_loop_group.inner.as_ptr(),
             host_resolver: options.host_resolver.inner.as_ptr(),
+            host_resolution_config: host_resolution_config
+                .as_ref()
+                .map_or(std::ptr::null(), |config| config as *const _),
             ..Default::default()
         };
 
         // Safety: `event_loop_group` and `host_resolver` are reference counted pointers, so they
-        // will survive even if their Rust versions are dropped
+        // will survive even if their Rust versions are dropped. The bootstrap copies the host
+        // resolution config.
         let inner = unsafe { aws_client_bootstrap_new(allocator.inner.as_ptr(), &inner_options).ok_or_last_error()? };
 
         Ok(Self { inner })
@@ -17,6 +17,99 @@ pub struct HostResolverDefaultOptions<'a> {
     pub event_loop_group: &'a mut EventLoopGroup,
 }
 
+/// Which IP address family to connect to endpoints with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AddressFamily {
+    /// Use whichever addresses the endpoint resolves to
+    #[default]
+    Auto,
+    /// Only connect to IPv4 (A record) addresses
+    Ipv4,
+    /// Only connect to IPv6 (AAAA record) addresses
+    Ipv6,
+}
+
+impl AddressFamily {
+    fn accepts(&self, address: &aws_host_address) -> bool {
+        match self {
+            AddressFamily::Auto => true,
+            AddressFamily::Ipv4 => address.record_type == aws_address_record_type::AWS_ADDRESS_RECORD_TYPE_A,
+            AddressFamily::Ipv6 => address.record_type == aws_address_record_type::AWS_ADDRESS_RECORD_TYPE_AAAA,
+        }
+    }
+
+    /// The resolution config that makes a [ClientBootstrap](crate::io::channel_bootstrap::ClientBootstrap)
+    /// only connect with this address family, or `None` to use the default config. Connections take
+    /// their socket domain from the address they connect to, so filtering the resolved addresses
+    /// is enough to pick the family.
+    pub(crate) fn host_resolution_config(&self) -> Option<aws_host_resolution_config> {
+        // `impl_data` must outlive the bootstrap, so point it at a static rather than `self`
+        static IPV4: AddressFamily = AddressFamily::Ipv4;
+        static IPV6: AddressFamily = AddressFamily::Ipv6;
+
+        let family = match self {
+            AddressFamily::Auto => return None,
+            AddressFamily::Ipv4 => &IPV4,
+            AddressFamily::Ipv6 => &IPV6,
+        };
+
+        Some(aws_host_resolution_config {
+            impl_: Some(resolve_with_address_family),
+            max_ttl: DEFAULT_DNS_TTL_SECS,
+            impl_data: family as *const AddressFamily as *mut libc::c_void,
+            ..Default::default()
+        })
+    }
+}
+
+/// The CRT's default TTL for resolved addresses (`AWS_DEFAULT_DNS_TTL`)
+const DEFAULT_DNS_TTL_SECS: usize = 30;
+
+/// Resolve a host with the CRT's default resolver, and then drop the addresses that aren't of the
+/// [AddressFamily] `user_data` points to.
+/// Safety: don't use except as an `aws_resolve_host_implementation` with `impl_data` pointing to a
+/// static [AddressFamily].
+unsafe extern "C" fn resolve_with_address_family(
+    allocator: *mut aws_allocator,
+    host_name: *const aws_string,
+    output_addresses: *mut aws_array_list,
+    user_data: *mut libc::c_void,
+) -> libc::c_int {
+    let result = aws_default_dns_resolve(allocator, host_name, output_addresses, std::ptr::null_mut());
+    if result != AWS_OP_SUCCESS {
+        return result;
+    }
+
+    let family = &*(user_data as *const AddressFamily);
+    let output_addresses = output_addresses.as_mut().unwrap();
+    retain_addresses(output_addresses, |address| family.accepts(address));
+
+    if output_addresses.length == 0 {
+        Error::from(aws_io_errors::AWS_IO_DNS_NO_ADDRESS_FOR_HOST as i32).raise_error()
+    } else {
+        AWS_OP_SUCCESS
+    }
+}
+
+/// Remove the addresses `keep` rejects from a list of [aws_host_address]es, cleaning them up.
+/// Safety: `addresses` must be an initialized list of [aws_host_address]es.
+unsafe fn retain_addresses(addresses: &mut aws_array_list, mut keep: impl FnMut(&aws_host_address) -> bool) {
+    if addresses.length == 0 {
+        return;
+    }
+    let items = std::slice::from_raw_parts_mut(addresses.data as *mut aws_host_address, addresses.length);
+    let mut kept = 0;
+    for i in 0..items.len() {
+        if keep(&items[i]) {
+            items.swap(kept, i);
+            kept += 1;
+        } else {
+            aws_host_address_clean_up(&mut items[i]);
+        }
+    }
+    addresses.length = kept;
+}
+
 /// A [HostResolver] is a tool for doing async DNS resolution and caching the results, including
 /// pooling multiple resolutions for a single hostname to enable load balancing and fanout.
 #[derive(Debug)]
@@ -64,3 +157,63 @@ impl Drop for HostResolver {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a list like the one the default resolver outputs, backed by `addresses`
+    fn resolved_addresses(addresses: &mut Vec<aws_host_address>) -> aws_array_list {
+        aws_array_list {
+            alloc: std::ptr::null_mut(),
+            current_size: addresses.capacity() * std::mem::size_of::<aws_host_address>(),
+            length: addresses.len(),
+            item_size: std::mem::size_of::<aws_host_address>(),
+            data: addresses.as_mut_ptr() as *mut libc::c_void,
+        }
+    }
+
+    fn record_types(list: &aws_array_list) -> Vec<aws_address_record_type> {
+        // SAFETY: the list was built from a Vec<aws_host_address> by `resolved_addresses`
+        let items = unsafe { std::slice::from_raw_parts(list.data as *const aws_host_address, list.length) };
+        items.iter().map(|address| address.record_type).collect()
+    }
+
+    #[test]
+    fn filter_resolved_addresses_by_family() {
+        use aws_address_record_type::{AWS_ADDRESS_RECORD_TYPE_A as A, AWS_ADDRESS_RECORD_TYPE_AAAA as AAAA};
+
+        for (family, expected) in [
+            (AddressFamily::Auto, vec![A, AAAA, A, AAAA]),
+            (AddressFamily::Ipv4, vec![A, A]),
+            (AddressFamily::Ipv6, vec![AAAA, AAAA]),
+        ] {
+            let mut addresses: Vec<_> = [A, AAAA, A, AAAA]
+                .into_iter()
+                .map(|record_type| aws_host_address {
+                    record_type,
+                    ..Default::default()
+                })
+                .collect();
+            let mut list = resolved_addresses(&mut addresses);
+            // SAFETY: the list is a valid list of aws_host_addresses with no strings to clean up
+            unsafe { retain_addresses(&mut list, |address| family.accepts(address)) };
+            assert_eq!(record_types(&list), expected, "wrong addresses for {family:?}");
+        }
+    }
+
+    #[test]
+    fn resolution_config_for_family() {
+        assert!(AddressFamily::Auto.host_resolution_config().is_none());
+
+        for family in [AddressFamily::Ipv4, AddressFamily::Ipv6] {
+            let config = family
+                .host_resolution_config()
+                .expect("non-default family needs a config");
+            assert!(config.impl_.is_some());
+            // SAFETY: impl_data points to a static AddressFamily
+            let configured = unsafe { *(config.impl_data as *const AddressFamily) };
+            assert_eq!(configured, family);
+        }
+    }
+}
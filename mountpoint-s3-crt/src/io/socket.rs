@@ -0,0 +1,96 @@
+//! Options for the TCP sockets the CRT opens to endpoints
+
+use std::time::Duration;
+
+use mountpoint_s3_crt_sys::{aws_s3_tcp_keep_alive_options, aws_socket_domain, aws_socket_options, aws_socket_type};
+
+/// Options for sockets opened to an endpoint
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SocketOptions {
+    /// How long to wait for a connection to be established. Zero means the CRT's default.
+    pub connect_timeout: Duration,
+    /// How long a connection can be idle before TCP keepalive probes are sent on it, or `None` to
+    /// not send keepalive probes. Keepalives both stop middleboxes like NAT gateways from dropping
+    /// idle connections, and detect connections that have died while idle so they can be closed.
+    pub keep_alive_interval: Option<Duration>,
+    /// How long to wait for a response to a keepalive probe before sending the next one. Zero means
+    /// the operating system's default.
+    pub keep_alive_timeout: Duration,
+    /// Number of unanswered keepalive probes after which the connection is closed. Zero means the
+    /// operating system's default.
+    pub keep_alive_max_failed_probes: u16,
+}
+
+impl SocketOptions {
+    pub(crate) fn to_inner(&self) -> aws_socket_options {
+        aws_socket_options {
+            type_: aws_socket_type::AWS_SOCKET_STREAM,
+            domain: aws_socket_domain::AWS_SOCKET_IPV4,
+            connect_timeout_ms: duration_as_u32_millis(self.connect_timeout),
+            keep_alive_interval_sec: self.keep_alive_interval.map(duration_as_u16_secs).unwrap_or(0),
+            keep_alive_timeout_sec: duration_as_u16_secs(self.keep_alive_timeout),
+            keep_alive_max_failed_probes: self.keep_alive_max_failed_probes,
+            keepalive: self.keep_alive_interval.is_some(),
+            ..Default::default()
+        }
+    }
+
+    /// The keepalive options in the form the S3 client takes them, or `None` if keepalives are
+    /// disabled
+    pub(crate) fn to_s3_keep_alive_options(&self) -> Option<aws_s3_tcp_keep_alive_options> {
+        let inner = self.to_inner();
+        inner.keepalive.then_some(aws_s3_tcp_keep_alive_options {
+            keep_alive_interval_sec: inner.keep_alive_interval_sec,
+            keep_alive_timeout_sec: inner.keep_alive_timeout_sec,
+            keep_alive_max_failed_probes: inner.keep_alive_max_failed_probes,
+        })
+    }
+}
+
+fn duration_as_u32_millis(duration: Duration) -> u32 {
+    duration.as_millis().min(u32::MAX as u128) as u32
+}
+
+fn duration_as_u16_secs(duration: Duration) -> u16 {
+    duration.as_secs().min(u16::MAX as u64) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn socket_options_round_trip() {
+        let options = SocketOptions {
+            connect_timeout: Duration::from_millis(1500),
+            keep_alive_interval: Some(Duration::from_secs(30)),
+            keep_alive_timeout: Duration::from_secs(5),
+            keep_alive_max_failed_probes: 3,
+        };
+
+        let inner = options.to_inner();
+        assert_eq!(inner.connect_timeout_ms, 1500);
+        assert!(inner.keepalive);
+        assert_eq!(inner.keep_alive_interval_sec, 30);
+        assert_eq!(inner.keep_alive_timeout_sec, 5);
+        assert_eq!(inner.keep_alive_max_failed_probes, 3);
+
+        let keep_alive = options.to_s3_keep_alive_options().expect("keepalive is enabled");
+        assert_eq!(keep_alive.keep_alive_interval_sec, 30);
+        assert_eq!(keep_alive.keep_alive_timeout_sec, 5);
+        assert_eq!(keep_alive.keep_alive_max_failed_probes, 3);
+    }
+
+    #[test]
+    fn socket_options_without_keep_alive() {
+        let options = SocketOptions {
+            connect_timeout: Duration::from_secs(u64::MAX),
+            ..Default::default()
+        };
+
+        let inner = options.to_inner();
+        assert_eq!(inner.connect_timeout_ms, u32::MAX);
+        assert!(!inner.keepalive);
+        assert!(options.to_s3_keep_alive_options().is_none());
+    }
+}
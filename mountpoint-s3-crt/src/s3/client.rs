@@ -1,13 +1,14 @@
 //! A client for high-throughput access to Amazon S3
 
 use crate::auth::credentials::CredentialsProvider;
-use crate::auth::signing_config::{SigningConfig, SigningConfigInner};
+use crate::auth::signing_config::{SigningAlgorithm, SigningConfig, SigningConfigInner};
 use crate::common::allocator::Allocator;
 use crate::common::error::Error;
 use crate::common::uri::Uri;
 use crate::http::request_response::{Headers, Message};
 use crate::io::channel_bootstrap::ClientBootstrap;
 use crate::io::retry_strategy::RetryStrategy;
+use crate::io::socket::SocketOptions;
 use crate::s3::s3_library_init;
 use crate::{aws_byte_cursor_as_slice, CrtError, ResultExt, StringExt};
 use mountpoint_s3_crt_sys::*;
@@ -52,6 +53,9 @@ pub struct ClientConfig {
     /// so we only need to hold onto it until this [ClientConfig] is consumed, at which point the
     /// client will take ownership.
     retry_strategy: Option<RetryStrategy>,
+
+    /// Owned copy of the TCP keepalive options, if provided, since `inner` only points to them.
+    tcp_keep_alive_options: Option<Box<aws_s3_tcp_keep_alive_options>>,
 }
 
 impl ClientConfig {
@@ -106,6 +110,18 @@ impl ClientConfig {
         self.inner.max_active_connections_override = max_active_connections_override;
         self
     }
+
+    /// Connection timeout and TCP keepalive options for the client's connections to S3. The S3
+    /// client takes the socket type and domain from the endpoint, so only those options apply.
+    pub fn socket_options(&mut self, socket_options: SocketOptions) -> &mut Self {
+        self.inner.connect_timeout_ms = socket_options.to_inner().connect_timeout_ms;
+        self.tcp_keep_alive_options = socket_options.to_s3_keep_alive_options().map(Box::new);
+        self.inner.tcp_keep_alive_options = match self.tcp_keep_alive_options.as_mut() {
+            Some(options) => options.as_mut() as *mut aws_s3_tcp_keep_alive_options,
+            None => std::ptr::null_mut(),
+        };
+        self
+    }
 }
 
 /// Callback for when headers are received as part of a successful HTTP request. Given (headers, response_status).
@@ -234,9 +250,9 @@ impl<'a> MetaRequestOptions<'a> {
         self
     }
 
-    /// Set the signing config used for this message. Not public because we copy it from the client
-    /// when making a request.
-    fn signing_config(&mut self, signing_config: SigningConfig) -> &mut Self {
+    /// Set the signing config used for this message. If not set, the request is signed with the
+    /// client's signing config.
+    pub fn signing_config(&mut self, signing_config: SigningConfig) -> &mut Self {
         // SAFETY: we aren't moving out of the struct.
         let options = unsafe { Pin::get_unchecked_mut(Pin::as_mut(&mut self.0)) };
         options.signing_config = Some(signing_config);
@@ -469,9 +485,13 @@ impl Client {
         // request does, since we only drop it in the shutdown callback. That struct owns everything
         // related to the request, like the message, signing config, etc.
         unsafe {
-            // The client holds a copy of the signing config, copy it again for this request.
-            if let Some(signing_config) = self.config.signing_config.as_ref() {
-                options.signing_config(signing_config.clone());
+            // The client holds a copy of the signing config, copy it again for this request unless
+            // the request has its own.
+            let has_signing_config = Pin::as_ref(&options.0).get_ref().signing_config.is_some();
+            if !has_signing_config {
+                if let Some(signing_config) = self.config.signing_config.as_ref() {
+                    options.signing_config(signing_config.clone());
+                }
             }
 
             // Unpin the options (we won't move out of it, nor will the callbacks).
@@ -625,23 +645,49 @@ impl MetaRequestResult {
 /// Create a new [SigningConfig] with the default configuration for signing S3 requests to a region
 /// using the given [CredentialsProvider]
 pub fn init_default_signing_config(region: &str, credentials_provider: CredentialsProvider) -> SigningConfig {
+    init_signing_config(region, credentials_provider, SigningAlgorithm::SigV4)
+}
+
+/// Create a new [SigningConfig] for signing S3 requests with the given [SigningAlgorithm]. For
+/// [SigningAlgorithm::SigV4A], `region` is a region set, like `*` for all regions.
+pub fn init_signing_config(
+    region: &str,
+    credentials_provider: CredentialsProvider,
+    algorithm: SigningAlgorithm,
+) -> SigningConfig {
+    init_signing_config_for_service(region, credentials_provider, algorithm, "s3")
+}
+
+/// Create a new [SigningConfig] like [init_signing_config], but that signs requests for the given
+/// service name rather than `s3`. For example, S3 Object Lambda access points expect requests to
+/// be signed for `s3-object-lambda`.
+pub fn init_signing_config_for_service(
+    region: &str,
+    credentials_provider: CredentialsProvider,
+    algorithm: SigningAlgorithm,
+    service: &str,
+) -> SigningConfig {
     let mut signing_config = Box::new(SigningConfigInner {
         inner: Default::default(),
         region: region.to_owned().into(),
+        service: service.to_owned().into(),
         credentials_provider,
         _pinned: Default::default(),
     });
 
     let credentials_provider = signing_config.credentials_provider.inner.as_ptr();
-    // SAFETY: we copied the region into the signing_config (`region.to_owned()` above), so the byte
-    // cursor we create here will point to bytes that are valid as long as this SigningConfig is.
-    // singing_config owns `credential_provider` that is valid as long as this SingingConfig is.
+    // SAFETY: we copied the region and service into the signing_config (`to_owned()` above), so the
+    // byte cursors we create here will point to bytes that are valid as long as this SigningConfig
+    // is. singing_config owns `credential_provider` that is valid as long as this SingingConfig is.
     unsafe {
         let region_cursor = signing_config.region.as_aws_byte_cursor();
 
         aws_s3_init_default_signing_config(&mut signing_config.inner, region_cursor, credentials_provider);
+
+        signing_config.inner.service = signing_config.service.as_aws_byte_cursor();
     }
     signing_config.inner.flags.set_use_double_uri_encode(false as u32);
+    signing_config.inner.algorithm = algorithm.into();
 
     SigningConfig(Arc::new(Box::into_pin(signing_config)))
 }
@@ -81,7 +81,7 @@ fn main() {
 
         let start = Instant::now();
 
-        let mut request = manager.get(bucket, key, size, ETag::for_tests());
+        let mut request = manager.get(bucket, key, None, size, ETag::for_tests());
         block_on(async {
             loop {
                 let offset = received_size.load(Ordering::SeqCst);
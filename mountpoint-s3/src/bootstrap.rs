@@ -0,0 +1,259 @@
+//! A single entry point for services that embed the file system rather than mounting it with the
+//! `mount-s3` binary.
+//!
+//! [bootstrap] checks up front that the bucket can actually be mounted, so that a misconfiguration
+//! fails at startup with a descriptive [BootstrapError] rather than on the first file system
+//! operation. It makes a single ListObjectsV2 request for the prefix, which is cheap but still
+//! signed, so it catches bad credentials, a missing bucket, and a bucket in another region.
+//!
+//! S3 only accepts requests for a bucket that are signed for the bucket's region, so
+//! [client_for_bucket] can be used first to find the right region to create the client for.
+
+use futures::task::Spawn;
+use mountpoint_s3_client::{ListObjectsError, ListObjectsResult, ObjectClient, ObjectClientError};
+use thiserror::Error;
+use tracing::{debug, warn};
+
+use crate::fs::{S3Filesystem, S3FilesystemConfig};
+use crate::prefix::{Prefix, PrefixError};
+
+/// Everything needed to create an [S3Filesystem] with [bootstrap]
+#[derive(Debug)]
+pub struct BootstrapConfig<Client, Runtime> {
+    /// Client to make requests with
+    pub client: Client,
+    /// Runtime to spawn prefetching tasks on
+    pub runtime: Runtime,
+    /// Name of the bucket to mount
+    pub bucket: String,
+    /// Prefix within the bucket to mount, or the empty string for the whole bucket
+    pub prefix: String,
+    /// Configuration for the file system
+    pub filesystem_config: S3FilesystemConfig,
+    /// Fail with [BootstrapError::EmptyPrefix] if there are no objects under the prefix. This
+    /// catches a mistyped prefix, but prevents mounting a prefix that's intended to start empty.
+    pub require_non_empty_prefix: bool,
+}
+
+/// Errors returned by [bootstrap]
+#[derive(Debug, Error)]
+pub enum BootstrapError<E: std::error::Error + Send + Sync + 'static> {
+    #[error("invalid prefix")]
+    InvalidPrefix(#[from] PrefixError),
+
+    #[error("bucket {0} does not exist")]
+    NoSuchBucket(String),
+
+    #[error("bucket {bucket} is in region {region}, not the client's region")]
+    IncorrectRegion { bucket: String, region: String },
+
+    #[error("bucket {bucket} is in region {actual}, not {expected}")]
+    WrongRegion {
+        bucket: String,
+        expected: String,
+        actual: String,
+    },
+
+    #[error("failed to create a client for region {0}")]
+    NewClient(String, #[source] Box<dyn std::error::Error + Send + Sync>),
+
+    #[error("access to bucket {0} was denied; check the credentials and their permissions")]
+    AccessDenied(String),
+
+    #[error("no objects found under prefix {0:?}")]
+    EmptyPrefix(String),
+
+    #[error("request to check the bucket failed")]
+    RequestFailed(#[source] ObjectClientError<ListObjectsError, E>),
+}
+
+/// Check that the configured bucket and prefix can be mounted, and create a file system for them
+pub async fn bootstrap<Client, Runtime>(
+    config: BootstrapConfig<Client, Runtime>,
+) -> Result<S3Filesystem<Client, Runtime>, BootstrapError<Client::ClientError>>
+where
+    Client: ObjectClient + Send + Sync + 'static,
+    Runtime: Spawn + Send + Sync,
+{
+    let BootstrapConfig {
+        client,
+        runtime,
+        bucket,
+        prefix,
+        filesystem_config,
+        require_non_empty_prefix,
+    } = config;
+
+    let prefix = Prefix::new(&prefix)?;
+
+    let result = probe_bucket(&client, &bucket, prefix.as_str()).await?;
+    debug!(?bucket, ?prefix, "bucket is accessible");
+
+    if require_non_empty_prefix && result.objects.is_empty() && result.common_prefixes.is_empty() {
+        return Err(BootstrapError::EmptyPrefix(prefix.to_string()));
+    }
+
+    Ok(S3Filesystem::new(client, runtime, &bucket, &prefix, filesystem_config))
+}
+
+/// Create a client for a bucket with `new_client`, which creates a client for the given region.
+///
+/// The client is first created for `region`. If the bucket turns out to be in a different region,
+/// then if `auto_region` is set, a new client is created for the bucket's actual region; otherwise
+/// this fails with [BootstrapError::WrongRegion].
+pub async fn client_for_bucket<Client, NewClient, NewClientError>(
+    bucket: &str,
+    region: &str,
+    auto_region: bool,
+    new_client: NewClient,
+) -> Result<Client, BootstrapError<Client::ClientError>>
+where
+    Client: ObjectClient,
+    NewClient: Fn(&str) -> Result<Client, NewClientError>,
+    NewClientError: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let new_client =
+        |region: &str| new_client(region).map_err(|e| BootstrapError::NewClient(region.to_owned(), e.into()));
+
+    let client = new_client(region)?;
+    match probe_bucket(&client, bucket, "").await {
+        Ok(_) => Ok(client),
+        Err(BootstrapError::IncorrectRegion { bucket, region: actual }) if auto_region => {
+            warn!("bucket {bucket} is in region {actual}, not {region}. redirecting...");
+            let client = new_client(&actual)?;
+            probe_bucket(&client, &bucket, "").await?;
+            Ok(client)
+        }
+        Err(BootstrapError::IncorrectRegion { bucket, region: actual }) => Err(BootstrapError::WrongRegion {
+            bucket,
+            expected: region.to_owned(),
+            actual,
+        }),
+        Err(e) => Err(e),
+    }
+}
+
+/// Check the bucket is accessible by listing at most one entry under the prefix
+async fn probe_bucket<Client: ObjectClient>(
+    client: &Client,
+    bucket: &str,
+    prefix: &str,
+) -> Result<ListObjectsResult, BootstrapError<Client::ClientError>> {
+    client
+        .list_objects(bucket, None, "/", 1, prefix)
+        .await
+        .map_err(|e| match e {
+            ObjectClientError::ServiceError(ListObjectsError::NoSuchBucket) => {
+                BootstrapError::NoSuchBucket(bucket.to_owned())
+            }
+            ObjectClientError::ServiceError(ListObjectsError::AccessDenied) => {
+                BootstrapError::AccessDenied(bucket.to_owned())
+            }
+            ObjectClientError::ServiceError(ListObjectsError::IncorrectRegion(region)) => {
+                BootstrapError::IncorrectRegion {
+                    bucket: bucket.to_owned(),
+                    region,
+                }
+            }
+            e => BootstrapError::RequestFailed(e),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use futures::executor::ThreadPool;
+    use mountpoint_s3_client::failure_client::countdown_failure_client;
+    use mountpoint_s3_client::mock_client::{MockClient, MockClientConfig, MockObject};
+    use mountpoint_s3_client::ETag;
+
+    use super::*;
+
+    fn config<Client>(client: Client, bucket: &str, prefix: &str) -> BootstrapConfig<Client, ThreadPool> {
+        BootstrapConfig {
+            client,
+            runtime: ThreadPool::builder().pool_size(1).create().unwrap(),
+            bucket: bucket.to_owned(),
+            prefix: prefix.to_owned(),
+            filesystem_config: Default::default(),
+            require_non_empty_prefix: true,
+        }
+    }
+
+    fn mock_client() -> MockClient {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+        });
+        client.add_object("dir/file", MockObject::constant(0u8, 5, ETag::for_tests()));
+        client
+    }
+
+    #[tokio::test]
+    async fn bootstrap_success() {
+        let result = bootstrap(config(mock_client(), "test_bucket", "dir")).await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn bootstrap_bad_credentials() {
+        let list_failures = HashMap::from([(1, ObjectClientError::ServiceError(ListObjectsError::AccessDenied))]);
+        let client = countdown_failure_client(
+            mock_client(),
+            HashMap::new(),
+            HashMap::new(),
+            list_failures,
+            HashMap::new(),
+        );
+        let result = bootstrap(config(client, "test_bucket", "dir")).await;
+        assert!(matches!(result, Err(BootstrapError::AccessDenied(bucket)) if bucket == "test_bucket"));
+    }
+
+    #[tokio::test]
+    async fn bootstrap_missing_bucket() {
+        let result = bootstrap(config(mock_client(), "missing_bucket", "dir")).await;
+        assert!(matches!(result, Err(BootstrapError::NoSuchBucket(bucket)) if bucket == "missing_bucket"));
+    }
+
+    #[tokio::test]
+    async fn bootstrap_empty_prefix() {
+        let result = bootstrap(config(mock_client(), "test_bucket", "other")).await;
+        assert!(matches!(result, Err(BootstrapError::EmptyPrefix(prefix)) if prefix == "other/"));
+
+        let mut allow_empty = config(mock_client(), "test_bucket", "other");
+        allow_empty.require_non_empty_prefix = false;
+        let result = bootstrap(allow_empty).await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn client_for_bucket_follows_redirect() {
+        let new_client = |region: &str| -> Result<MockClient, std::convert::Infallible> {
+            let client = mock_client();
+            if region != "eu-west-1" {
+                client.redirect_to_region("eu-west-1");
+            }
+            Ok(client)
+        };
+
+        let client = client_for_bucket("test_bucket", "us-east-1", true, new_client)
+            .await
+            .expect("should follow the redirect");
+        let result = bootstrap(config(client, "test_bucket", "dir")).await;
+        assert!(result.is_ok());
+
+        let result = client_for_bucket("test_bucket", "us-east-1", false, new_client).await;
+        assert!(matches!(
+            result,
+            Err(BootstrapError::WrongRegion { bucket, expected, actual })
+                if bucket == "test_bucket" && expected == "us-east-1" && actual == "eu-west-1"
+        ));
+    }
+
+    #[tokio::test]
+    async fn bootstrap_invalid_prefix() {
+        let result = bootstrap(config(mock_client(), "test_bucket", "  ")).await;
+        assert!(matches!(result, Err(BootstrapError::InvalidPrefix(_))));
+    }
+}
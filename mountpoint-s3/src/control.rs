@@ -0,0 +1,200 @@
+//! A synthetic `.mountpoint` directory at the root of the file system for inspecting and controlling a
+//! running mount, see [S3FilesystemConfig::enable_control_dir](crate::S3FilesystemConfig::enable_control_dir).
+//!
+//! The directory and its files don't exist in S3, so they have fixed inode numbers far above any
+//! the superblock will allocate, and their contents are generated when they're opened.
+
+use std::ffi::OsStr;
+use std::fmt::{self, Debug, Write};
+use std::sync::Arc;
+
+use mountpoint_s3_crt::s3::client::ClientMetrics;
+
+use crate::fs::InodeNo;
+
+/// Name of the control directory in the root of the file system
+pub const CONTROL_DIR_NAME: &str = ".mountpoint";
+
+/// Inode number of the control directory. The inodes of its subdirectories and files follow it.
+pub const CONTROL_DIR_INODE: InodeNo = 1 << 62;
+
+/// A directory in the control directory tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlDir {
+    /// The `.mountpoint` directory itself
+    Root,
+    /// The `control` subdirectory, whose files run a command when they're written to
+    Commands,
+}
+
+impl ControlDir {
+    const ALL: [ControlDir; 2] = [ControlDir::Root, ControlDir::Commands];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ControlDir::Root => CONTROL_DIR_NAME,
+            ControlDir::Commands => "control",
+        }
+    }
+
+    pub fn ino(self) -> InodeNo {
+        let index = Self::ALL.iter().position(|dir| *dir == self).unwrap();
+        CONTROL_DIR_INODE + index as InodeNo
+    }
+
+    /// The directory's entries, in the order they're listed
+    pub fn entries(self) -> Vec<ControlNode> {
+        let subdirs = Self::ALL
+            .into_iter()
+            .filter(|dir| *dir != ControlDir::Root && dir.parent() == Some(self))
+            .map(ControlNode::Dir);
+        let files = ControlFile::ALL
+            .into_iter()
+            .filter(|file| file.dir() == self)
+            .map(ControlNode::File);
+        subdirs.chain(files).collect()
+    }
+
+    /// The directory containing this one, or `None` for the root of the control directory, whose
+    /// parent is the root of the file system
+    pub fn parent(self) -> Option<ControlDir> {
+        match self {
+            ControlDir::Root => None,
+            ControlDir::Commands => Some(ControlDir::Root),
+        }
+    }
+}
+
+/// A virtual file in the control directory tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlFile {
+    /// Counters for the file system and the S3 client
+    Stats,
+    /// The file system's effective configuration
+    Config,
+    /// The version of Mountpoint
+    Version,
+    /// Writing to this file expires all cached metadata and drops prefetched data
+    FlushCaches,
+    /// Writing to this file drops every inode that isn't needed by an in-progress write
+    ForgetInodes,
+}
+
+impl ControlFile {
+    /// All the files, in the order they're listed
+    const ALL: [ControlFile; 5] = [
+        ControlFile::Stats,
+        ControlFile::Config,
+        ControlFile::Version,
+        ControlFile::FlushCaches,
+        ControlFile::ForgetInodes,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ControlFile::Stats => "stats",
+            ControlFile::Config => "config",
+            ControlFile::Version => "version",
+            ControlFile::FlushCaches => "flush_caches",
+            ControlFile::ForgetInodes => "forget_inodes",
+        }
+    }
+
+    pub fn ino(self) -> InodeNo {
+        let index = Self::ALL.iter().position(|file| *file == self).unwrap();
+        CONTROL_DIR_INODE + ControlDir::ALL.len() as InodeNo + index as InodeNo
+    }
+
+    /// The directory the file is in
+    pub fn dir(self) -> ControlDir {
+        match self {
+            ControlFile::Stats | ControlFile::Config | ControlFile::Version => ControlDir::Root,
+            ControlFile::FlushCaches | ControlFile::ForgetInodes => ControlDir::Commands,
+        }
+    }
+
+    /// Whether the file runs a command when written to, rather than having contents to read
+    pub fn is_command(self) -> bool {
+        self.dir() == ControlDir::Commands
+    }
+}
+
+/// A directory or file in the control directory tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlNode {
+    Dir(ControlDir),
+    File(ControlFile),
+}
+
+impl ControlNode {
+    pub fn from_ino(ino: InodeNo) -> Option<Self> {
+        let dirs = ControlDir::ALL.into_iter().map(ControlNode::Dir);
+        let files = ControlFile::ALL.into_iter().map(ControlNode::File);
+        dirs.chain(files).find(|node| node.ino() == ino)
+    }
+
+    pub fn ino(self) -> InodeNo {
+        match self {
+            ControlNode::Dir(dir) => dir.ino(),
+            ControlNode::File(file) => file.ino(),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ControlNode::Dir(dir) => dir.name(),
+            ControlNode::File(file) => file.name(),
+        }
+    }
+
+    /// The entry named `name` in the given control directory
+    pub fn lookup(dir: ControlDir, name: &OsStr) -> Option<Self> {
+        dir.entries().into_iter().find(|node| name == node.name())
+    }
+}
+
+/// Polls the current [ClientMetrics] of the S3 client the file system uses, for the `stats` file.
+/// The file system is generic over its client, so it can't get them from the client itself.
+#[derive(Clone)]
+pub struct ClientMetricsSource(Arc<dyn Fn() -> ClientMetrics + Send + Sync>);
+
+impl ClientMetricsSource {
+    pub fn new(poll: impl Fn() -> ClientMetrics + Send + Sync + 'static) -> Self {
+        Self(Arc::new(poll))
+    }
+
+    pub fn poll(&self) -> ClientMetrics {
+        (self.0)()
+    }
+}
+
+impl Debug for ClientMetricsSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ClientMetricsSource").finish_non_exhaustive()
+    }
+}
+
+/// Append the client metrics to the `stats` file, one `name: value` line each
+pub fn format_client_metrics(out: &mut String, metrics: &ClientMetrics) {
+    let counters = [
+        ("num_requests_tracked_requests", metrics.num_requests_tracked_requests),
+        ("num_requests_being_prepared", metrics.num_requests_being_prepared),
+        ("request_queue_size", metrics.request_queue_size),
+        ("num_auto_default_network_io", metrics.num_auto_default_network_io),
+        ("num_auto_ranged_get_network_io", metrics.num_auto_ranged_get_network_io),
+        ("num_auto_ranged_put_network_io", metrics.num_auto_ranged_put_network_io),
+        (
+            "num_auto_ranged_copy_network_io",
+            metrics.num_auto_ranged_copy_network_io,
+        ),
+        (
+            "num_requests_stream_queued_waiting",
+            metrics.num_requests_stream_queued_waiting,
+        ),
+        ("num_requests_streaming", metrics.num_requests_streaming),
+        ("num_total_network_io", metrics.num_total_network_io()),
+    ];
+    for (name, value) in counters {
+        let _ = writeln!(out, "client.{name}: {value}");
+    }
+}
@@ -0,0 +1,80 @@
+//! Deadlines that bound how long a whole file system operation can take, however many requests to
+//! S3 it makes along the way, see
+//! [S3FilesystemConfig::op_deadline](crate::S3FilesystemConfig::op_deadline).
+//!
+//! An operation creates a [Deadline] when it starts and passes it down to the helpers that make
+//! requests for it. Each step runs under [Deadline::run], so once the deadline has passed, the step
+//! in progress is dropped (cancelling any requests it has in flight) and no further steps start.
+
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use futures::future::{select, Either};
+use futures::{pin_mut, poll};
+use mountpoint_s3_client::timer::sleep;
+use thiserror::Error;
+
+/// The time by which an operation must have finished, if there is one
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline(Option<Instant>);
+
+#[derive(Debug, Error)]
+#[error("operation deadline exceeded")]
+pub struct DeadlineExceeded;
+
+impl Deadline {
+    /// A deadline `timeout` from now, or no deadline if `timeout` isn't set
+    pub fn after(timeout: Option<Duration>) -> Self {
+        Self(timeout.map(|timeout| Instant::now() + timeout))
+    }
+
+    /// Run a step of the operation until it completes or the deadline passes, whichever comes
+    /// first. Steps that complete without waiting don't need a timer, so are cheap to run.
+    pub async fn run<F: Future>(&self, future: F) -> Result<F::Output, DeadlineExceeded> {
+        let Some(deadline) = self.0 else {
+            return Ok(future.await);
+        };
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(DeadlineExceeded);
+        }
+
+        pin_mut!(future);
+        if let std::task::Poll::Ready(output) = poll!(&mut future) {
+            return Ok(output);
+        }
+        let timer = sleep(remaining);
+        pin_mut!(timer);
+        match select(future, timer).await {
+            Either::Left((output, _)) => Ok(output),
+            Either::Right(((), _)) => Err(DeadlineExceeded),
+        }
+    }
+}
+
+impl From<DeadlineExceeded> for i32 {
+    fn from(_: DeadlineExceeded) -> Self {
+        libc::ETIMEDOUT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    #[test]
+    fn slow_steps_time_out() {
+        let deadline = Deadline::after(Some(Duration::from_millis(50)));
+        block_on(async {
+            assert!(deadline.run(async { 1 }).await.is_ok());
+            let slow = deadline.run(sleep(Duration::from_secs(10))).await;
+            assert!(slow.is_err());
+            // Once it's passed, even steps that would complete straight away fail
+            assert!(deadline.run(async { 1 }).await.is_err());
+        });
+
+        let none = Deadline::after(None);
+        assert!(block_on(none.run(sleep(Duration::from_millis(10)))).is_ok());
+    }
+}
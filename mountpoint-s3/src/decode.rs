@@ -0,0 +1,265 @@
+//! Transparent decoding of objects stored with a `Content-Encoding`, see
+//! [S3FilesystemConfig::decode_content_encoding](crate::S3FilesystemConfig::decode_content_encoding).
+//!
+//! An object's decoded size isn't known until it's been decoded in full, so decoded objects aren't
+//! read through the prefetcher. Instead, each file handle fetches the object in order, a window at a
+//! time, and decodes it as the application reads forwards. Reading backwards starts again from the
+//! beginning of the object.
+//!
+//! Each window is fetched in full by the read that needs it, so no request is left open between
+//! reads. Otherwise a handle that was read partway and then left idle would keep one of the
+//! [max_concurrent_requests](crate::S3FilesystemConfig::max_concurrent_requests) slots until it
+//! was released.
+
+use std::fmt::Debug;
+use std::io::Write;
+
+use bytes::{Bytes, BytesMut};
+use flate2::write::MultiGzDecoder;
+use futures::StreamExt;
+use mountpoint_s3_client::{ETag, GetObjectError, GetObjectParams, ObjectClient, ObjectClientError};
+use thiserror::Error;
+use tracing::trace;
+
+/// A `Content-Encoding` that can be decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentEncoding {
+    Gzip,
+}
+
+impl ContentEncoding {
+    /// The encoding named by a `Content-Encoding` header, if it's one we can decode
+    pub fn from_header(value: &str) -> Option<Self> {
+        value.trim().eq_ignore_ascii_case("gzip").then_some(Self::Gzip)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum DecodeError<E: std::error::Error + Send + Sync + 'static> {
+    #[error("get request failed")]
+    GetRequestFailed(#[source] ObjectClientError<GetObjectError, E>),
+
+    #[error("object could not be decoded")]
+    InvalidData(#[source] std::io::Error),
+}
+
+/// Size of the ranged GetObject requests the encoded object is fetched with
+const WINDOW_SIZE: u64 = 2 * 1024 * 1024;
+
+/// Most encoded bytes to feed to the decoder at once, so that a read doesn't decode much more than
+/// it needs
+const FEED_SIZE: usize = 64 * 1024;
+
+/// An object being read through a decoder by a single file handle
+pub struct DecodedObject<Client: ObjectClient> {
+    bucket: String,
+    key: String,
+    version_id: Option<String>,
+    etag: ETag,
+    /// Size of the encoded object
+    size: u64,
+    /// Offset in the encoded object of the next window to fetch
+    fetched: u64,
+    /// Encoded bytes that have been fetched but not yet fed to the decoder
+    pending: Bytes,
+    decoder: MultiGzDecoder<Vec<u8>>,
+    /// Offset in the decoded contents of the first byte in the decoder's output buffer
+    offset: u64,
+    /// Whether the whole object has been decoded
+    finished: bool,
+}
+
+impl<Client: ObjectClient> DecodedObject<Client> {
+    pub fn new(
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        etag: ETag,
+        size: u64,
+        encoding: ContentEncoding,
+    ) -> Self {
+        let decoder = match encoding {
+            ContentEncoding::Gzip => MultiGzDecoder::new(Vec::new()),
+        };
+        Self {
+            bucket: bucket.to_owned(),
+            key: key.to_owned(),
+            version_id: version_id.map(str::to_owned),
+            etag,
+            size,
+            fetched: 0,
+            pending: Bytes::new(),
+            decoder,
+            offset: 0,
+            finished: false,
+        }
+    }
+
+    /// Read up to `size` bytes of the decoded contents starting at `offset`. Fewer bytes are only
+    /// returned at the end of the decoded contents.
+    pub async fn read(
+        &mut self,
+        client: &Client,
+        offset: u64,
+        size: usize,
+    ) -> Result<Vec<u8>, DecodeError<Client::ClientError>> {
+        if offset < self.offset {
+            trace!(
+                key = self.key,
+                offset,
+                current_offset = self.offset,
+                "restarting decoded read"
+            );
+            self.restart();
+        }
+
+        let end = offset + size as u64;
+        loop {
+            self.discard_before(offset);
+            let buffered_end = self.offset + self.decoder.get_ref().len() as u64;
+            if self.finished || buffered_end >= end {
+                break;
+            }
+            self.decode_next_part(client).await?;
+        }
+
+        let buffer = self.decoder.get_mut();
+        if self.offset < offset {
+            // The read starts past the end of the decoded contents
+            return Ok(Vec::new());
+        }
+        let len = size.min(buffer.len());
+        let data = buffer.drain(..len).collect();
+        self.offset += len as u64;
+        Ok(data)
+    }
+
+    /// Go back to the start of the object
+    fn restart(&mut self) {
+        self.fetched = 0;
+        self.pending = Bytes::new();
+        self.decoder = MultiGzDecoder::new(Vec::new());
+        self.offset = 0;
+        self.finished = false;
+    }
+
+    /// Drop decoded bytes before `offset`, since reads only go forwards
+    fn discard_before(&mut self, offset: u64) {
+        let buffer = self.decoder.get_mut();
+        let skip = offset.saturating_sub(self.offset).min(buffer.len() as u64);
+        buffer.drain(..skip as usize);
+        self.offset += skip;
+    }
+
+    /// Feed the next part of the object to the decoder, fetching the next window if needed
+    async fn decode_next_part(&mut self, client: &Client) -> Result<(), DecodeError<Client::ClientError>> {
+        if self.pending.is_empty() {
+            if self.fetched >= self.size {
+                self.decoder.try_finish().map_err(DecodeError::InvalidData)?;
+                self.finished = true;
+                return Ok(());
+            }
+            self.pending = self.fetch_window(client).await?;
+        }
+
+        let part = self.pending.split_to(FEED_SIZE.min(self.pending.len()));
+        self.decoder.write_all(&part).map_err(DecodeError::InvalidData)
+    }
+
+    /// Fetch the next window of the encoded object in full. If the request fails, nothing has been
+    /// fed to the decoder from it, so the next read fetches the same window again.
+    async fn fetch_window(&mut self, client: &Client) -> Result<Bytes, DecodeError<Client::ClientError>> {
+        let range = self.fetched..(self.fetched + WINDOW_SIZE).min(self.size);
+        trace!(key = self.key, ?range, "fetching encoded window");
+        let params = GetObjectParams::new()
+            .range(Some(range.clone()))
+            .if_match(Some(self.etag.clone()))
+            .version_id(self.version_id.as_deref());
+        let body = client
+            .get_object(&self.bucket, &self.key, &params)
+            .await
+            .map_err(DecodeError::GetRequestFailed)?;
+        futures::pin_mut!(body);
+
+        let mut window = BytesMut::with_capacity((range.end - range.start) as usize);
+        while let Some(next) = body.next().await {
+            let (_offset, part) = next.map_err(DecodeError::GetRequestFailed)?;
+            window.extend_from_slice(&part);
+        }
+        self.fetched = range.end;
+        Ok(window.freeze())
+    }
+}
+
+impl<Client: ObjectClient> Debug for DecodedObject<Client> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DecodedObject")
+            .field("bucket", &self.bucket)
+            .field("key", &self.key)
+            .field("version_id", &self.version_id)
+            .field("etag", &self.etag)
+            .field("size", &self.size)
+            .field("fetched", &self.fetched)
+            .field("offset", &self.offset)
+            .field("finished", &self.finished)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use futures::executor::block_on;
+    use mountpoint_s3_client::failure_client::countdown_failure_client;
+    use mountpoint_s3_client::mock_client::{ramp_bytes, MockClient, MockClientConfig, MockClientError, MockObject};
+    use std::collections::HashMap;
+
+    /// Compress `plaintext` as a series of gzip members, like concatenated `.gz` files
+    fn gzip_members(plaintext: &[u8], member_size: usize) -> Vec<u8> {
+        plaintext
+            .chunks(member_size)
+            .flat_map(|chunk| {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(chunk).unwrap();
+                encoder.finish().unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn read_again_after_body_fails() {
+        let plaintext = ramp_bytes(0, 256 * 1024);
+        let compressed = gzip_members(&plaintext, 16 * 1024);
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 64,
+        });
+        client.add_object("key", MockObject::from_bytes(&compressed, ETag::for_tests()));
+        let mut get_failures = HashMap::new();
+        get_failures.insert(1, Ok((5, MockClientError("connection reset".into()))));
+        let client = countdown_failure_client(client, get_failures, HashMap::new(), HashMap::new(), HashMap::new());
+
+        let mut object = DecodedObject::new(
+            "test_bucket",
+            "key",
+            None,
+            ETag::for_tests(),
+            compressed.len() as u64,
+            ContentEncoding::Gzip,
+        );
+        let mut decoded = Vec::new();
+        let mut failures = 0;
+        loop {
+            match block_on(object.read(&client, decoded.len() as u64, 4096)) {
+                Ok(data) if data.is_empty() => break,
+                Ok(data) => decoded.extend_from_slice(&data),
+                Err(DecodeError::GetRequestFailed(_)) => failures += 1,
+                Err(e) => panic!("unexpected error: {e:?}"),
+            }
+        }
+        assert_eq!(failures, 1);
+        assert_eq!(decoded, plaintext);
+    }
+}
@@ -0,0 +1,118 @@
+//! A bounded cache of complete directory listings, so that reopening a directory that was just
+//! listed doesn't need to list it from S3 again, see
+//! [S3FilesystemConfig::dir_snapshot_cache_size](crate::S3FilesystemConfig::dir_snapshot_cache_size).
+//!
+//! Snapshots outlive the handles that listed them, so the cache is bounded: it keeps only the most
+//! recently listed directories, and evicts the least recently used one when it's full. Snapshots
+//! also expire after the directory's stat TTL, so a reopened directory is never staler than its
+//! entries' attributes would be.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::fs::InodeNo;
+use crate::inode::LookedUp;
+use crate::sync::{Arc, Mutex};
+
+/// The entries of a directory, in the order they're returned by `readdir`, as of when it was
+/// listed
+#[derive(Debug)]
+pub struct DirSnapshot {
+    pub entries: Vec<LookedUp>,
+    expiry: Instant,
+}
+
+/// A least-recently-used cache of [DirSnapshot]s, keyed by directory inode
+#[derive(Debug)]
+pub struct DirSnapshotCache {
+    capacity: usize,
+    /// Most recently used first
+    snapshots: Mutex<VecDeque<(InodeNo, Arc<DirSnapshot>)>>,
+}
+
+impl DirSnapshotCache {
+    /// A cache that holds at most `capacity` snapshots. A capacity of 0 disables the cache.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            snapshots: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// The snapshot of a directory, if there's one that hasn't expired yet. Marks it as the most
+    /// recently used.
+    pub fn get(&self, dir: InodeNo) -> Option<Arc<DirSnapshot>> {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        let index = snapshots.iter().position(|(ino, _)| *ino == dir)?;
+        let (ino, snapshot) = snapshots.remove(index).unwrap();
+        if snapshot.expiry <= Instant::now() {
+            return None;
+        }
+        snapshots.push_front((ino, Arc::clone(&snapshot)));
+        Some(snapshot)
+    }
+
+    /// Cache the listing of a directory for `ttl`, replacing any older snapshot of it and evicting
+    /// the least recently used snapshot if the cache is full
+    pub fn insert(&self, dir: InodeNo, entries: Vec<LookedUp>, ttl: Duration) {
+        if !self.is_enabled() || ttl.is_zero() {
+            return;
+        }
+        let snapshot = Arc::new(DirSnapshot {
+            entries,
+            expiry: Instant::now() + ttl,
+        });
+        let mut snapshots = self.snapshots.lock().unwrap();
+        snapshots.retain(|(ino, _)| *ino != dir);
+        snapshots.truncate(self.capacity - 1);
+        snapshots.push_front((dir, snapshot));
+    }
+
+    /// Drop the snapshot of a directory, because its contents changed
+    pub fn invalidate(&self, dir: InodeNo) {
+        self.snapshots.lock().unwrap().retain(|(ino, _)| *ino != dir);
+    }
+
+    /// Drop every snapshot
+    pub fn clear(&self) {
+        self.snapshots.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let cache = DirSnapshotCache::new(2);
+        let ttl = Duration::from_secs(60);
+        cache.insert(1, Vec::new(), ttl);
+        cache.insert(2, Vec::new(), ttl);
+        // Using 1 makes 2 the least recently used
+        assert!(cache.get(1).is_some());
+        cache.insert(3, Vec::new(), ttl);
+        assert!(cache.get(2).is_none());
+        assert!(cache.get(1).is_some());
+        assert!(cache.get(3).is_some());
+
+        cache.invalidate(3);
+        assert!(cache.get(3).is_none());
+    }
+
+    #[test]
+    fn expired_snapshots_are_dropped() {
+        let cache = DirSnapshotCache::new(2);
+        cache.insert(1, Vec::new(), Duration::from_millis(1));
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(cache.get(1).is_none());
+
+        let disabled = DirSnapshotCache::new(0);
+        disabled.insert(1, Vec::new(), Duration::from_secs(60));
+        assert!(disabled.get(1).is_none());
+    }
+}
@@ -1,30 +1,145 @@
 use futures::task::Spawn;
+use futures::{pin_mut, StreamExt};
 use nix::unistd::{getgid, getuid};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::ffi::OsStr;
-use std::str::FromStr;
-use std::time::{Duration, UNIX_EPOCH};
-use tracing::{debug, error, trace};
-
-use fuser::{FileAttr, KernelConfig};
-use mountpoint_s3_client::{ETag, ObjectClient, PutObjectParams};
-
-use crate::inode::{Inode, InodeError, InodeKind, LookedUp, ReaddirHandle, Superblock, WriteHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use time::OffsetDateTime;
+use tracing::{debug, error, info, trace, warn};
+
+use fuser::{FileAttr, FileType, KernelConfig};
+use mountpoint_s3_client::timer::sleep;
+use mountpoint_s3_client::{
+    upload_part_size, ETag, GetObjectError, GetObjectParams, HeadObjectError, HeadObjectResult, ObjectClient,
+    ObjectClientError, PutObjectParams, UploadMode, MAX_DELETE_OBJECTS_KEYS, MAX_OBJECT_SIZE,
+};
+
+use crate::build_info;
+use crate::control::{
+    format_client_metrics, ClientMetricsSource, ControlDir, ControlFile, ControlNode, CONTROL_DIR_NAME,
+};
+use crate::deadline::Deadline;
+use crate::decode::{ContentEncoding, DecodedObject};
+use crate::dir_cache::DirSnapshotCache;
+use crate::inode::{
+    parse_ranged_name, parse_versioned_name, Inode, InodeError, InodeKind, LookedUp, ReaddirHandle, Superblock,
+    SuperblockConfig, WriteHandle,
+};
+use crate::limiter::ConcurrencyLimitedClient;
 use crate::prefetch::{PrefetchGetObject, PrefetchReadError, Prefetcher, PrefetcherConfig};
 use crate::prefix::Prefix;
-use crate::sync::atomic::{AtomicI64, AtomicU64, Ordering};
-use crate::sync::{Arc, AsyncMutex, AsyncRwLock};
+use crate::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, AtomicUsize, Ordering};
+use crate::sync::{Arc, AsyncMutex, AsyncRwLock, Mutex};
+use crate::upload::{StreamingUpload, Uploader};
 
-pub use crate::inode::InodeNo;
+pub use crate::inode::{
+    CaseSensitivity, InodeNo, InvalidKeyHandling, ShadowPolicy, DEFAULT_MAX_NAME_LENGTH, DIRECTORY_OBJECT_NAME,
+    SHADOWED_FILE_SUFFIX,
+};
 
 pub const FUSE_ROOT_INODE: InodeNo = 1u64;
 
+/// User metadata key (`x-amz-meta-logical-size`) giving the logical size of a sparse object, see
+/// [S3FilesystemConfig::sparse_reads_return_zeros]
+pub const LOGICAL_SIZE_METADATA_KEY: &str = "logical-size";
+
+/// Most bytes a single `copy_file_range` copies when it can't use CopyObject and has to read and
+/// write them instead. Callers repeat the call until everything has been copied.
+const COPY_FILE_RANGE_CHUNK_SIZE: u32 = 1024 * 1024;
+
+/// How long to wait before the first retry of a read that found no object, see
+/// [S3FilesystemConfig::read_after_write_retries]. Doubles after each retry.
+const READ_AFTER_WRITE_INITIAL_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Size of the buffer returned by [S3_KEY_IOCTL]: the longest possible S3 key (1024 bytes) plus a
+/// nul terminator.
+pub const S3_KEY_IOCTL_SIZE: usize = 1025;
+
+/// Custom `ioctl` command that returns the full S3 key (including the mount's prefix) of the file or
+/// directory it's issued on, as a nul-terminated string. Encoded like `_IOR('S', 1, [u8; 1025])`.
+pub const S3_KEY_IOCTL: u32 = ioctl_read(b'S', 1, S3_KEY_IOCTL_SIZE);
+
+/// Size of the argument to [FADVISE_IOCTL]: the offset and length of the range the advice is
+/// about as native-endian `u64`s, followed by the advice as a native-endian `u32`.
+pub const FADVISE_IOCTL_SIZE: usize = 20;
+
+/// Custom `ioctl` command that gives [S3Filesystem::fadvise] advice about a file open for reading.
+/// Encoded like `_IOW('S', 2, [u8; 20])`.
+pub const FADVISE_IOCTL: u32 = ioctl_write(b'S', 2, FADVISE_IOCTL_SIZE);
+
+/// Mode flag for [S3Filesystem::fallocate] that keeps the file's size, like Linux's
+/// `FALLOC_FL_KEEP_SIZE`, which `libc` doesn't define on other platforms
+pub const FALLOC_FL_KEEP_SIZE: i32 = 0x01;
+
+/// Extended attribute that can be set on a file open for writing to give the ETag its object is
+/// expected to have, like the hex MD5 of its contents for a single part upload. If the ETag S3
+/// returns for the upload doesn't match, `flush` (and so `close`) fails with `EIO`. The object
+/// has already been uploaded by then, so it's up to the application to deal with it.
+pub const EXPECTED_ETAG_XATTR: &str = "user.expected-etag";
+
+/// Flag for [S3Filesystem::rename] that swaps the two names, like Linux's `RENAME_EXCHANGE`
+pub const RENAME_EXCHANGE: u32 = 1 << 1;
+
+/// Encode a read-only `ioctl` command, like the `_IOR` macro
+const fn ioctl_read(typ: u8, nr: u8, size: usize) -> u32 {
+    #[cfg(target_os = "macos")]
+    const IOC_OUT: u32 = 0x4000_0000;
+    #[cfg(not(target_os = "macos"))]
+    const IOC_OUT: u32 = 2 << 30;
+    IOC_OUT | ((size as u32 & 0x1fff) << 16) | ((typ as u32) << 8) | nr as u32
+}
+
+/// Encode a write-only `ioctl` command, like the `_IOW` macro
+const fn ioctl_write(typ: u8, nr: u8, size: usize) -> u32 {
+    #[cfg(target_os = "macos")]
+    const IOC_IN: u32 = 0x8000_0000;
+    #[cfg(not(target_os = "macos"))]
+    const IOC_IN: u32 = 1 << 30;
+    IOC_IN | ((size as u32 & 0x1fff) << 16) | ((typ as u32) << 8) | nr as u32
+}
+
+/// Advice about how a file open for reading will be read, see [S3Filesystem::fadvise]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadAdvice {
+    /// No particular pattern, so fetch ahead of sequential reads as usual
+    Normal,
+    /// Reads will be random, so only fetch the bytes each read needs
+    Random,
+    /// The range will be read sequentially, so fetch all of it ahead of the reads
+    Sequential,
+    /// The range will be read soon, so fetch all of it ahead of the reads
+    WillNeed,
+}
+
+impl ReadAdvice {
+    /// Convert the advice argument of [FADVISE_IOCTL], which uses the same values as Linux's
+    /// `POSIX_FADV_*` constants
+    pub fn from_raw(advice: u32) -> Option<Self> {
+        match advice {
+            0 => Some(Self::Normal),
+            1 => Some(Self::Random),
+            2 => Some(Self::Sequential),
+            3 => Some(Self::WillNeed),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug)]
 struct DirHandle {
-    #[allow(unused)]
     ino: InodeNo,
     handle: ReaddirHandle,
     offset: AtomicI64,
+    /// Entries collected up front and reordered according to [ReaddirOrder], or taken from a cached
+    /// snapshot of the directory. `None` until the first entry is requested, and always `None` for
+    /// [ReaddirOrder::S3Lexicographic] unless snapshots are being cached.
+    reordered: Mutex<Option<VecDeque<LookedUp>>>,
+    /// Entries collected so far by a listing of the whole directory that was interrupted, for the
+    /// next listing to carry on from
+    partial: Mutex<Vec<LookedUp>>,
+    /// How long a snapshot of the listing can be reused for by later handles
+    snapshot_ttl: Duration,
 }
 
 impl DirHandle {
@@ -35,26 +150,272 @@ impl DirHandle {
     fn next_offset(&self) {
         self.offset.fetch_add(1, Ordering::SeqCst);
     }
+
+    /// Whether entries have to be collected up front with [DirHandle::list_all] before
+    /// [DirHandle::next] can return them
+    fn needs_whole_listing(order: ReaddirOrder, snapshots: &DirSnapshotCache) -> bool {
+        order != ReaddirOrder::S3Lexicographic || snapshots.is_enabled()
+    }
+
+    /// List the whole directory and put it in the given order, if that's needed and hasn't been
+    /// done yet. Any order other than S3's needs the whole listing before it can return the first
+    /// entry, and so does caching a snapshot of it. Once it's been sorted, the sequence is fixed,
+    /// so offsets stay stable across `readdir` calls.
+    ///
+    /// Big directories can take many pages to list, so this checks `interrupted` between entries,
+    /// and fails with `EINTR` if it's set, or with `ETIMEDOUT` once the deadline has passed. The
+    /// entries listed so far are kept for the next call.
+    async fn list_all<OC: ObjectClient>(
+        &self,
+        client: &OC,
+        order: ReaddirOrder,
+        snapshots: &DirSnapshotCache,
+        interrupted: &AtomicBool,
+        deadline: &Deadline,
+    ) -> Result<(), libc::c_int> {
+        if !Self::needs_whole_listing(order, snapshots) || self.reordered.lock().unwrap().is_some() {
+            return Ok(());
+        }
+
+        let mut entries = std::mem::take(&mut *self.partial.lock().unwrap());
+        loop {
+            if interrupted.load(Ordering::SeqCst) {
+                debug!(dir = ?self.ino, listed = entries.len(), "listing interrupted");
+                *self.partial.lock().unwrap() = entries;
+                return Err(libc::EINTR);
+            }
+            let Ok(next) = deadline.run(self.handle.next(client)).await else {
+                debug!(dir = ?self.ino, listed = entries.len(), "listing timed out");
+                *self.partial.lock().unwrap() = entries;
+                return Err(libc::ETIMEDOUT);
+            };
+            match next? {
+                Some(entry) => entries.push(entry),
+                None => break,
+            }
+        }
+        order.sort(&mut entries);
+        snapshots.insert(self.ino, entries.clone(), self.snapshot_ttl);
+        *self.reordered.lock().unwrap() = Some(entries.into());
+        Ok(())
+    }
+
+    /// Get the next entry in the listing, in the given order. If the order needs the whole listing,
+    /// [DirHandle::list_all] must have been called first.
+    async fn next<OC: ObjectClient>(
+        &self,
+        client: &OC,
+        order: ReaddirOrder,
+        snapshots: &DirSnapshotCache,
+    ) -> Result<Option<LookedUp>, InodeError> {
+        if !Self::needs_whole_listing(order, snapshots) {
+            return self.handle.next(client).await;
+        }
+
+        Ok(self
+            .reordered
+            .lock()
+            .unwrap()
+            .as_mut()
+            .and_then(|entries| entries.pop_front()))
+    }
+
+    /// Re-add an entry to the front of the listing if the consumer wasn't able to use it
+    fn readd(&self, entry: LookedUp) {
+        match self.reordered.lock().unwrap().as_mut() {
+            Some(entries) => entries.push_front(entry),
+            None => self.handle.readd(entry),
+        }
+    }
+}
+
+/// How much of the limits on the size of a `readdir` reply an in-progress reply has used
+#[derive(Debug)]
+struct ReaddirBudget {
+    max_entries: Option<usize>,
+    max_bytes: Option<usize>,
+    entries: usize,
+    bytes: usize,
+}
+
+impl ReaddirBudget {
+    /// Size of a `fuse_entry_out` followed by a `fuse_dirent`, which come before the name of each
+    /// entry in a `readdirplus` reply
+    const ENTRY_HEADER_SIZE: usize = 128 + 24;
+
+    fn new(config: &S3FilesystemConfig) -> Self {
+        Self {
+            max_entries: config.readdir_max_entries,
+            max_bytes: config.readdir_reply_size,
+            entries: 0,
+            bytes: 0,
+        }
+    }
+
+    /// Account for an entry with the given name, or return false if it doesn't fit. The first
+    /// entry always fits, so that every reply makes progress however long the name is.
+    fn try_add(&mut self, name: &str) -> bool {
+        // Entries are padded to a multiple of 8 bytes
+        let size = (Self::ENTRY_HEADER_SIZE + name.len() + 7) & !7;
+        if self.entries > 0 {
+            let too_many = matches!(self.max_entries, Some(max) if self.entries >= max);
+            let too_big = matches!(self.max_bytes, Some(max) if self.bytes + size > max);
+            if too_many || too_big {
+                return false;
+            }
+        }
+        self.entries += 1;
+        self.bytes += size;
+        true
+    }
+}
+
+/// Logs a warning when dropped if the operation it was created for took longer than the threshold
+#[derive(Debug)]
+struct SlowOpGuard {
+    op: &'static str,
+    ino: InodeNo,
+    threshold: Option<Duration>,
+    start: Instant,
+}
+
+impl Drop for SlowOpGuard {
+    fn drop(&mut self) {
+        let Some(threshold) = self.threshold else {
+            return;
+        };
+        let elapsed = self.start.elapsed();
+        if elapsed > threshold {
+            warn!(op = self.op, ino = self.ino, ?elapsed, ?threshold, "slow operation");
+        }
+    }
+}
+
+/// The order in which `readdir` returns the entries of a directory (after `.` and `..`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReaddirOrder {
+    /// Lexicographic order of the entries' names, which is the order S3 lists keys in. Entries are
+    /// streamed from S3 as they're requested.
+    #[default]
+    S3Lexicographic,
+    /// Directories before files, each in lexicographic order
+    DirsFirst,
+    /// Case-insensitive order of the entries' names, breaking ties lexicographically
+    CaseInsensitive,
+}
+
+impl ReaddirOrder {
+    fn sort(&self, entries: &mut [LookedUp]) {
+        match self {
+            ReaddirOrder::S3Lexicographic => entries.sort_by(|a, b| a.inode.name().cmp(b.inode.name())),
+            ReaddirOrder::DirsFirst => entries.sort_by(|a, b| {
+                let is_file = |entry: &LookedUp| entry.inode.kind() != InodeKind::Directory;
+                is_file(a)
+                    .cmp(&is_file(b))
+                    .then_with(|| a.inode.name().cmp(b.inode.name()))
+            }),
+            ReaddirOrder::CaseInsensitive => entries.sort_by(|a, b| {
+                let (a, b) = (a.inode.name(), b.inode.name());
+                a.to_lowercase().cmp(&b.to_lowercase()).then_with(|| a.cmp(b))
+            }),
+        }
+    }
+}
+
+/// What to do when a file open for reading turns out to have been replaced in S3
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StaleReadPolicy {
+    /// Don't check whether the object has changed, and keep reading the version that was opened
+    /// for as long as S3 still has it
+    #[default]
+    Ignore,
+    /// Fail reads with `ESTALE` once the object has changed
+    Error,
+    /// Start reading the new version of the object instead
+    Restart,
 }
 
 #[derive(Debug)]
 struct FileHandle<Client: ObjectClient, Runtime> {
     inode: Inode,
     full_key: String,
-    object_size: u64,
     typ: FileHandleType<Client, Runtime>,
+    /// The process (thread group) that opened this handle. Only a `flush` from this process
+    /// completes an upload, so that a child closing an inherited descriptor doesn't finish the
+    /// object early.
+    open_tgid: u32,
+}
+
+/// Get the thread group id of the thread `pid`. FUSE requests carry the id of the calling thread,
+/// which differs from the process id for any thread but a process's first, so comparing processes
+/// needs the thread group. Falls back to `pid` if the thread has already exited.
+fn thread_group_id(pid: u32) -> u32 {
+    let Ok(status) = std::fs::read_to_string(format!("/proc/{pid}/status")) else {
+        return pid;
+    };
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Tgid:"))
+        .and_then(|tgid| tgid.trim().parse().ok())
+        .unwrap_or(pid)
+}
+
+/// A slot under [S3FilesystemConfig::max_open_files] taken by a handle that's being opened. Dropping
+/// it gives the slot back, so an open that fails or is cancelled doesn't keep it.
+struct OpenFileSlot<'a>(&'a AtomicUsize);
+
+impl Drop for OpenFileSlot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 #[derive(Debug)]
 enum FileHandleType<Client: ObjectClient, Runtime> {
     Read {
         request: AsyncMutex<Option<PrefetchGetObject<Client, Runtime>>>,
-        etag: ETag,
+        object: Mutex<ReadObject>,
     },
     Write {
-        parts: AsyncMutex<Vec<Box<[u8]>>>,
+        upload: AsyncMutex<UploadState<Client>>,
+        /// ETag the upload must end up with, see [EXPECTED_ETAG_XATTR]
+        expected_etag: Mutex<Option<String>>,
+    },
+    /// Opened for reading an object that's decoded as it's read, see
+    /// [S3FilesystemConfig::decode_content_encoding]
+    Decode { object: AsyncMutex<DecodedObject<Client>> },
+}
+
+/// The version of the object behind a file handle opened for reading
+#[derive(Debug)]
+struct ReadObject {
+    etag: ETag,
+    size: u64,
+    /// Size of the object including the hole of zeros past its data, if it's sparse (see
+    /// [S3FilesystemConfig::sparse_reads_return_zeros]). Otherwise the same as `size`.
+    logical_size: u64,
+    /// When we last checked that this is still the current version of the object
+    validated_at: Instant,
+}
+
+/// State of the upload behind a file handle opened for writing
+#[derive(Debug)]
+enum UploadState<Client: ObjectClient> {
+    /// Still accepting writes, which are buffered until the upload is completed
+    InProgress {
+        parts: Vec<Box<[u8]>>,
+        handle: WriteHandle,
+        /// How big the file is expected to grow, if the application said so with `fallocate`
+        size_hint: Option<u64>,
+    },
+    /// Still accepting writes, which are uploaded as they arrive, see
+    /// [S3FilesystemConfig::stream_writes_buffer_size]
+    Streaming {
+        upload: StreamingUpload<Client>,
         handle: WriteHandle,
     },
+    /// The upload was completed by an earlier `flush` or `release`, successfully or not
+    Completed,
 }
 
 #[derive(Debug)]
@@ -63,6 +424,12 @@ pub struct S3FilesystemConfig {
     pub stat_ttl: Duration,
     /// Readdir page size
     pub readdir_size: usize,
+    /// Maximum number of entries in a single `readdir` reply. The kernel's buffer is the only
+    /// limit if this isn't set.
+    pub readdir_max_entries: Option<usize>,
+    /// Maximum size in bytes of a single `readdir` reply, counting each entry as it would be
+    /// encoded in a `readdirplus` reply. The kernel's buffer is the only limit if this isn't set.
+    pub readdir_reply_size: Option<usize>,
     /// User id
     pub uid: u32,
     /// Group id
@@ -73,6 +440,165 @@ pub struct S3FilesystemConfig {
     pub file_mode: u16,
     /// Prefetcher configuration
     pub prefetcher_config: PrefetcherConfig,
+    /// How long a GetObject request has to return its first byte before it's sent again. If set,
+    /// overrides [PrefetcherConfig::first_byte_timeout].
+    pub get_first_byte_timeout: Option<Duration>,
+    /// Allow historical versions of objects to be read through synthetic `name@v=<version id>`
+    /// paths. These paths are read-only and are not listed by `readdir`. When enabled, keys that
+    /// themselves contain `@v=` can't be looked up directly.
+    pub expose_versions: bool,
+    /// Allow reading a range of bytes of an object through synthetic `name@range=<first>-<last>`
+    /// paths, where both offsets are included in the range. These paths are read-only and are not
+    /// listed by `readdir`. When enabled, keys that themselves end in a range suffix can't be looked
+    /// up directly.
+    pub expose_byte_ranges: bool,
+    /// Allow mounting a prefix that has no objects under it. See [S3Filesystem::verify_prefix].
+    pub allow_empty_prefix: bool,
+    /// At startup, abort multipart uploads under the mount's prefix that were created at least
+    /// this long ago, such as ones left behind by failed writes, so they stop incurring storage
+    /// charges. See [S3Filesystem::abort_orphaned_uploads].
+    pub abort_orphaned_uploads_older_than: Option<Duration>,
+    /// Order of entries returned by `readdir`. Any order other than the default needs to list the
+    /// entire directory before returning the first entry.
+    pub readdir_order: ReaddirOrder,
+    /// How to present names that are both an object and a directory prefix
+    pub shadow_policy: ShadowPolicy,
+    /// How to present objects whose keys end in `/`, and names longer than `max_name_length`
+    pub invalid_key_handling: InvalidKeyHandling,
+    /// Whether lookups of names that differ only by case find the same file
+    pub case_sensitivity: CaseSensitivity,
+    /// Longest name of a file or directory, in bytes. Keys with a longer component are never
+    /// listed, and looking them up fails with `ENOENT` or `ENAMETOOLONG` depending on
+    /// `invalid_key_handling`.
+    pub max_name_length: usize,
+    /// Log a warning for any file system operation that takes longer than this
+    pub slow_op_threshold: Option<Duration>,
+    /// Longest a `lookup` or `readdir` can take in total, across all the requests to S3 it makes,
+    /// like the pages of a big directory listing. Once it's passed, any request still in flight is
+    /// cancelled and the operation fails with `ETIMEDOUT`. Unlimited if not set.
+    pub op_deadline: Option<Duration>,
+    /// Times to give the root directory if the mount's prefix doesn't have a directory marker
+    /// object (see [S3Filesystem::load_root_attributes]). Defaults to the time of the mount.
+    pub root_fallback_mtime: Option<OffsetDateTime>,
+    /// Allow existing objects to be opened with `O_APPEND`. The object's current contents are read
+    /// into memory when it's opened, and the whole object is uploaded again when it's closed.
+    pub enable_append: bool,
+    /// Largest existing object that can be opened for appending, in bytes
+    pub max_append_size: usize,
+    /// Objects smaller than this many bytes are uploaded with a single PutObject request, and
+    /// larger ones with a multipart upload
+    pub multipart_threshold: usize,
+    /// Upload new files as they're written rather than when they're closed, buffering at most about
+    /// this many bytes of each, which should be around the client's part size. The buffer grows if
+    /// `fallocate` said the file will be too big for parts of this size. A failed upload can't be
+    /// retried, and appends to existing objects are still buffered until the file is closed. The
+    /// client's uploads must stream too for memory to stay bounded (for the CRT client, see
+    /// [S3ClientConfig::upload_buffer_size](mountpoint_s3_client::S3ClientConfig::upload_buffer_size)).
+    pub stream_writes_buffer_size: Option<usize>,
+    /// Keep complete listings of up to this many recently listed directories, so that reopening
+    /// one within its `stat_ttl` lists it from the snapshot rather than from S3. The least recently
+    /// used snapshot is evicted when the cache is full. 0 disables the cache.
+    pub dir_snapshot_cache_size: usize,
+    /// Support swapping two files with `renameat2(RENAME_EXCHANGE)` by copying them through a
+    /// temporary object. S3 can't swap objects atomically, so other clients can see a half-finished
+    /// exchange, and a failure can leave the temporary object behind (see [S3Filesystem::rename]).
+    pub allow_nonatomic_exchange: bool,
+    /// Writes that would make a file larger than this many bytes fail with `EFBIG`, and the file's
+    /// upload is aborted. Defaults to S3's maximum object size of 5 TiB.
+    pub max_object_size: u64,
+    /// Answer `lookup`s of names a `readdir` just listed from the listing, rather than asking S3
+    /// again, until the entries are older than `stat_ttl`. With readdirplus, the kernel usually
+    /// has these attributes already, but this also covers lookups it makes anyway.
+    pub prime_lookup_cache_from_readdir: bool,
+    /// Number of times to retry a read that finds no object, with backoff, before giving up. S3 is
+    /// strongly consistent, so this is only useful with S3-compatible stores where an object can
+    /// briefly be missing right after it's written.
+    pub read_after_write_retries: u32,
+    /// Mount a read-only snapshot of the bucket as it was at this time. Each file reads the version
+    /// of its object that was current then, found with ListObjectVersions, so the bucket must be
+    /// versioned. Directories and `readdir` still reflect the bucket's current contents.
+    pub pinned_as_of: Option<OffsetDateTime>,
+    /// Accept writes as usual, but log the objects that would have been uploaded instead of
+    /// uploading them. Useful to check that a workload can run without changing the bucket.
+    pub dry_run_writes: bool,
+    /// What to do when an object open for reading is replaced by another writer. Unless this is
+    /// [StaleReadPolicy::Ignore], reads check the object again once its stat TTL has expired.
+    pub stale_read_policy: StaleReadPolicy,
+    /// Maximum number of requests to S3 that can be in flight at once, across all file system
+    /// operations. Further requests wait for an earlier one to finish. Unlimited if not set.
+    pub max_concurrent_requests: Option<usize>,
+    /// Maximum number of files that can be open at once. Opening another fails with `EMFILE` until
+    /// one is released. Unlimited if not set.
+    pub max_open_files: Option<usize>,
+    /// Decode objects stored with `Content-Encoding: gzip` when they're read, so applications see
+    /// the original contents. Opening a file for reading needs an extra HeadObject request to find
+    /// its encoding. The decoded size isn't known in advance, so `stat` still reports the size of
+    /// the stored object, and decoded files are opened with direct I/O so that the kernel reads
+    /// until the end of the decoded contents rather than stopping at that size.
+    pub decode_content_encoding: bool,
+    /// Treat objects with a `logical-size` user metadata value (see [LOGICAL_SIZE_METADATA_KEY])
+    /// larger than their length as sparse: reads past the end of their data but within their
+    /// logical size return zeros rather than ending the file. Like
+    /// [S3FilesystemConfig::decode_content_encoding], opening a file for reading needs an extra
+    /// HeadObject request, `stat` still reports the size of the stored object, and sparse files are
+    /// opened with direct I/O.
+    pub sparse_reads_return_zeros: bool,
+    /// Present the buckets the account owns as directories under the root, rather than the
+    /// contents of a single bucket, like mounting the account itself. Each bucket's directory
+    /// contains that bucket's objects. The bucket and prefix the file system is created with are
+    /// ignored, and every bucket must be in the client's region.
+    pub browse_buckets: bool,
+    /// If S3 denies the HeadObject requests that look up files, look them up with a GetObject
+    /// request for their first byte instead, for credentials that can read objects but not make
+    /// HeadObject requests. See [SuperblockConfig::head_object_fallback].
+    pub head_object_fallback: bool,
+    /// Add a `.mountpoint` directory to the root with files for inspecting the running mount:
+    /// `stats` has counters for the file system and its S3 client, `config` has this
+    /// configuration, and `version` has Mountpoint's version. Writing anything to
+    /// `control/flush_caches` expires cached metadata and drops prefetched data, and writing to
+    /// `control/forget_inodes` drops the inodes that aren't being written. The directory isn't
+    /// listed by `readdir`, and hides any directory of the same name in the bucket.
+    pub enable_control_dir: bool,
+    /// Where the `stats` file in the control directory gets the S3 client's metrics from. The file
+    /// only has the file system's own counters if this isn't set.
+    pub client_metrics: Option<ClientMetricsSource>,
+    /// Configuration to use instead of the above for keys under particular prefixes. Prefixes are
+    /// matched against full S3 keys, and for each setting the longest matching prefix that sets it
+    /// wins.
+    pub prefix_overrides: Vec<(Prefix, PartialConfig)>,
+}
+
+/// Settings that can be overridden for keys under a particular prefix, see
+/// [S3FilesystemConfig::prefix_overrides]. Settings left as `None` are inherited.
+#[derive(Debug, Default, Clone)]
+pub struct PartialConfig {
+    /// Storage class for new objects. If not set anywhere, S3 uses `STANDARD`.
+    pub storage_class: Option<String>,
+    /// Stat time to live in kernel cache
+    pub stat_ttl: Option<Duration>,
+}
+
+impl S3FilesystemConfig {
+    /// Storage class for new objects with the given key
+    pub fn storage_class_for(&self, full_key: &str) -> Option<&str> {
+        self.override_for(full_key, |config| config.storage_class.as_deref())
+    }
+
+    /// Stat time to live for the file or directory with the given key
+    pub fn stat_ttl_for(&self, full_key: &str) -> Duration {
+        self.override_for(full_key, |config| config.stat_ttl)
+            .unwrap_or(self.stat_ttl)
+    }
+
+    /// Find the setting from the most specific prefix override matching the key that has one
+    fn override_for<'a, T>(&'a self, full_key: &str, setting: impl Fn(&'a PartialConfig) -> Option<T>) -> Option<T> {
+        self.prefix_overrides
+            .iter()
+            .filter(|(prefix, _)| prefix.strip(full_key).is_some())
+            .filter_map(|(prefix, config)| Some((prefix.as_str().len(), setting(config)?)))
+            .max_by_key(|(len, _)| *len)
+            .map(|(_, value)| value)
+    }
 }
 
 impl Default for S3FilesystemConfig {
@@ -86,11 +612,47 @@ impl Default for S3FilesystemConfig {
             // than S3 ListObjects latency.
             stat_ttl: Duration::from_millis(1),
             readdir_size: 100,
+            readdir_max_entries: None,
+            readdir_reply_size: None,
             uid,
             gid,
             dir_mode: 0o755,
             file_mode: 0o644,
             prefetcher_config: PrefetcherConfig::default(),
+            get_first_byte_timeout: None,
+            expose_versions: false,
+            expose_byte_ranges: false,
+            allow_empty_prefix: false,
+            abort_orphaned_uploads_older_than: None,
+            readdir_order: ReaddirOrder::default(),
+            shadow_policy: ShadowPolicy::default(),
+            invalid_key_handling: InvalidKeyHandling::default(),
+            case_sensitivity: CaseSensitivity::default(),
+            max_name_length: DEFAULT_MAX_NAME_LENGTH,
+            root_fallback_mtime: None,
+            slow_op_threshold: None,
+            op_deadline: None,
+            enable_append: false,
+            max_append_size: 64 * 1024 * 1024,
+            multipart_threshold: 8 * 1024 * 1024,
+            stream_writes_buffer_size: None,
+            dir_snapshot_cache_size: 0,
+            allow_nonatomic_exchange: false,
+            max_object_size: MAX_OBJECT_SIZE,
+            prime_lookup_cache_from_readdir: false,
+            read_after_write_retries: 0,
+            pinned_as_of: None,
+            dry_run_writes: false,
+            stale_read_policy: StaleReadPolicy::default(),
+            max_concurrent_requests: None,
+            max_open_files: None,
+            decode_content_encoding: false,
+            sparse_reads_return_zeros: false,
+            browse_buckets: false,
+            head_object_fallback: false,
+            enable_control_dir: true,
+            client_metrics: None,
+            prefix_overrides: Vec::new(),
         }
     }
 }
@@ -98,15 +660,23 @@ impl Default for S3FilesystemConfig {
 #[derive(Debug)]
 pub struct S3Filesystem<Client: ObjectClient, Runtime> {
     config: S3FilesystemConfig,
-    client: Arc<Client>,
+    client: Arc<ConcurrencyLimitedClient<Client>>,
     superblock: Superblock,
-    prefetcher: Prefetcher<Client, Runtime>,
+    prefetcher: Prefetcher<ConcurrencyLimitedClient<Client>, Runtime>,
+    uploader: Uploader<ConcurrencyLimitedClient<Client>>,
     bucket: String,
-    #[allow(unused)]
     prefix: Prefix,
     next_handle: AtomicU64,
     dir_handles: AsyncRwLock<HashMap<u64, Arc<DirHandle>>>,
-    file_handles: AsyncRwLock<HashMap<u64, FileHandle<Client, Runtime>>>,
+    /// Recent listings of directories, see [S3FilesystemConfig::dir_snapshot_cache_size]
+    dir_snapshots: DirSnapshotCache,
+    file_handles: AsyncRwLock<HashMap<u64, Arc<FileHandle<ConcurrencyLimitedClient<Client>, Runtime>>>>,
+    /// Number of file handles that are open or being opened, see [S3FilesystemConfig::max_open_files]
+    open_files: AtomicUsize,
+    /// Contents of the open files in the control directory, as of when they were opened
+    control_handles: AsyncRwLock<HashMap<u64, Box<[u8]>>>,
+    /// When the file system was created, which is the time of everything in the control directory
+    created_at: SystemTime,
 }
 
 impl<Client, Runtime> S3Filesystem<Client, Runtime>
@@ -115,28 +685,224 @@ where
     Runtime: Spawn + Send + Sync,
 {
     pub fn new(client: Client, runtime: Runtime, bucket: &str, prefix: &Prefix, config: S3FilesystemConfig) -> Self {
-        let superblock = Superblock::new(bucket, prefix);
+        let superblock_config = SuperblockConfig {
+            shadow_policy: config.shadow_policy,
+            invalid_key_handling: config.invalid_key_handling,
+            case_sensitivity: config.case_sensitivity,
+            max_name_length: config.max_name_length,
+            root_mtime: config.root_fallback_mtime,
+            readdir_lookup_ttl: config.prime_lookup_cache_from_readdir.then_some(config.stat_ttl),
+            pinned_as_of: config.pinned_as_of,
+            browse_buckets: config.browse_buckets,
+            head_object_fallback: config.head_object_fallback,
+        };
+        let prefix = if config.browse_buckets {
+            Prefix::default()
+        } else {
+            prefix.clone()
+        };
+        let superblock = Superblock::new(bucket, &prefix, superblock_config);
 
-        let client = Arc::new(client);
+        let client = Arc::new(ConcurrencyLimitedClient::new(client, config.max_concurrent_requests));
 
-        let prefetcher = Prefetcher::new(client.clone(), runtime, config.prefetcher_config);
+        let prefetcher_config = PrefetcherConfig {
+            first_byte_timeout: config
+                .get_first_byte_timeout
+                .or(config.prefetcher_config.first_byte_timeout),
+            ..config.prefetcher_config
+        };
+        let prefetcher = Prefetcher::new(client.clone(), runtime, prefetcher_config);
+        let uploader = Uploader::new(client.clone());
+        let dir_snapshots = DirSnapshotCache::new(config.dir_snapshot_cache_size);
 
         Self {
             config,
             client,
             superblock,
             prefetcher,
+            uploader,
             bucket: bucket.to_string(),
-            prefix: prefix.clone(),
+            prefix,
             next_handle: AtomicU64::new(1),
             dir_handles: AsyncRwLock::new(HashMap::new()),
+            dir_snapshots,
             file_handles: AsyncRwLock::new(HashMap::new()),
+            open_files: AtomicUsize::new(0),
+            control_handles: AsyncRwLock::new(HashMap::new()),
+            created_at: SystemTime::now(),
         }
     }
 
     fn next_handle(&self) -> u64 {
         self.next_handle.fetch_add(1, Ordering::SeqCst)
     }
+
+    /// Check that the prefix being mounted has at least one object under it, so that typos in the
+    /// prefix fail fast rather than mounting an empty directory. Mounting the whole bucket, or
+    /// setting [S3FilesystemConfig::allow_empty_prefix], skips the check.
+    pub async fn verify_prefix(&self) -> Result<(), VerifyPrefixError> {
+        if self.prefix.is_root() || self.config.allow_empty_prefix {
+            return Ok(());
+        }
+
+        let result = self
+            .client
+            .list_objects(&self.bucket, None, "/", 1, self.prefix.as_str())
+            .await
+            .map_err(|e| VerifyPrefixError::ClientError(e.into()))?;
+
+        if result.objects.is_empty() && result.common_prefixes.is_empty() {
+            Err(VerifyPrefixError::EmptyPrefix(self.prefix.to_string()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Abort the multipart uploads under the prefix being mounted that are older than
+    /// [S3FilesystemConfig::abort_orphaned_uploads_older_than], returning how many were aborted.
+    /// This should run before the file system is mounted, while none of the uploads can be ours.
+    /// Uploads that fail to abort are logged and skipped.
+    pub async fn abort_orphaned_uploads(&self) -> Result<usize, AbortOrphanedUploadsError> {
+        let Some(older_than) = self.config.abort_orphaned_uploads_older_than else {
+            return Ok(0);
+        };
+        if self.config.browse_buckets {
+            warn!("not aborting orphaned uploads, since there's no single bucket being mounted");
+            return Ok(0);
+        }
+        let cutoff = OffsetDateTime::now_utc() - older_than;
+
+        let result = self
+            .client
+            .list_multipart_uploads(&self.bucket, self.prefix.as_str())
+            .await
+            .map_err(|e| AbortOrphanedUploadsError::ClientError(e.into()))?;
+
+        let mut aborted = 0;
+        for upload in result.uploads.iter().filter(|upload| upload.initiated <= cutoff) {
+            match self
+                .client
+                .abort_multipart_upload(&self.bucket, &upload.key, &upload.upload_id)
+                .await
+            {
+                Ok(_) => {
+                    info!(key=?upload.key, upload_id=?upload.upload_id, initiated=?upload.initiated, "aborted orphaned upload");
+                    aborted += 1;
+                }
+                Err(e) => {
+                    warn!(key=?upload.key, upload_id=?upload.upload_id, error=?e, "failed to abort orphaned upload");
+                }
+            }
+        }
+        Ok(aborted)
+    }
+
+    /// Remove the directory `name` in `parent` and everything under it, like `rm -r`. Unlike
+    /// `rmdir`, the directory doesn't need to be empty: every object under its prefix is listed and
+    /// deleted in batches. If some objects can't be deleted, the rest are still deleted, and the
+    /// keys that failed are returned in [RemoveDirAllError::PartialFailure].
+    pub async fn remove_dir_all(&self, parent: InodeNo, name: &OsStr) -> Result<(), RemoveDirAllError> {
+        let lookup = self
+            .superblock
+            .lookup(&self.client, parent, name)
+            .await
+            .map_err(|e| RemoveDirAllError::LookupFailed(e.into()))?;
+        if lookup.inode.kind() != InodeKind::Directory {
+            return Err(RemoveDirAllError::NotADirectory);
+        }
+        let (bucket, prefix) = self.superblock.bucket_and_key(lookup.inode.full_key());
+        // Only possible when browsing buckets, where it would empty the whole bucket
+        if prefix.is_empty() {
+            return Err(RemoveDirAllError::IsBucket);
+        }
+        debug!(bucket, prefix, "removing directory recursively");
+        self.dir_snapshots.invalidate(parent);
+        self.dir_snapshots.invalidate(lookup.inode.ino());
+
+        let mut failed_keys = Vec::new();
+        let mut continuation_token = None;
+        loop {
+            let listing = self
+                .client
+                .list_objects(
+                    bucket,
+                    continuation_token.as_deref(),
+                    "",
+                    MAX_DELETE_OBJECTS_KEYS,
+                    prefix,
+                )
+                .await
+                .map_err(|e| RemoveDirAllError::ClientError(e.into()))?;
+
+            let keys = listing.objects.into_iter().map(|object| object.key).collect::<Vec<_>>();
+            if !keys.is_empty() {
+                let result = self
+                    .client
+                    .delete_objects(bucket, &keys)
+                    .await
+                    .map_err(|e| RemoveDirAllError::ClientError(e.into()))?;
+                for failure in result.errors {
+                    warn!(
+                        key = failure.key,
+                        code = failure.code,
+                        "failed to delete object: {}",
+                        failure.message
+                    );
+                    failed_keys.push(failure.key);
+                }
+            }
+
+            continuation_token = listing.next_continuation_token;
+            if continuation_token.is_none() {
+                break;
+            }
+        }
+
+        if failed_keys.is_empty() {
+            Ok(())
+        } else {
+            Err(RemoveDirAllError::PartialFailure(failed_keys))
+        }
+    }
+}
+
+/// A key for the temporary object that [S3Filesystem::rename] exchanges objects through, next to
+/// `key` and hidden from `ls` without `-a`
+fn exchange_temp_key(key: &str) -> String {
+    let (dir, name) = match key.rfind('/') {
+        Some(i) => key.split_at(i + 1),
+        None => ("", key),
+    };
+    let nanos = OffsetDateTime::now_utc().unix_timestamp_nanos();
+    format!("{dir}.{name}.mountpoint-exchange-{nanos}")
+}
+
+#[derive(Debug, Error)]
+pub enum RemoveDirAllError {
+    #[error("lookup failed with errno {0}")]
+    LookupFailed(libc::c_int),
+    #[error("not a directory")]
+    NotADirectory,
+    #[error("buckets can't be removed")]
+    IsBucket,
+    #[error("failed to list or delete objects")]
+    ClientError(#[source] anyhow::Error),
+    #[error("failed to delete {} objects", .0.len())]
+    PartialFailure(Vec<String>),
+}
+
+#[derive(Debug, Error)]
+pub enum AbortOrphanedUploadsError {
+    #[error("failed to list multipart uploads under prefix")]
+    ClientError(#[source] anyhow::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum VerifyPrefixError {
+    #[error("no objects found under prefix {0:?}")]
+    EmptyPrefix(String),
+    #[error("failed to list objects under prefix")]
+    ClientError(#[source] anyhow::Error),
 }
 
 /// Reply to a `lookup` call
@@ -193,12 +959,43 @@ where
     Client: ObjectClient + Send + Sync + 'static,
     Runtime: Spawn + Send + Sync,
 {
+    /// Start timing an operation, to log a warning once it completes if it was slow. See
+    /// [S3FilesystemConfig::slow_op_threshold].
+    fn slow_op(&self, op: &'static str, ino: InodeNo) -> SlowOpGuard {
+        SlowOpGuard {
+            op,
+            ino,
+            threshold: self.config.slow_op_threshold,
+            start: Instant::now(),
+        }
+    }
+
     pub async fn init(&self, config: &mut KernelConfig) -> Result<(), libc::c_int> {
         let _ = config.set_max_readahead(0);
         let _ = config.add_capabilities(fuser::consts::FUSE_DO_READDIRPLUS);
+        self.load_root_attributes().await;
         Ok(())
     }
 
+    /// Give the root directory the times of the directory marker object for the mount's prefix
+    /// (like `prefix/`), if there is one. Otherwise, the root keeps the times from
+    /// [S3FilesystemConfig::root_fallback_mtime].
+    pub async fn load_root_attributes(&self) {
+        // Unprefixed mounts can't have a marker
+        if self.prefix.is_root() {
+            return;
+        }
+
+        match self.client.head_object(&self.bucket, self.prefix.as_str(), None).await {
+            Ok(result) => {
+                debug!(prefix = ?self.prefix, last_modified = ?result.object.last_modified, "found directory marker for root");
+                self.superblock.set_root_mtime(result.object.last_modified);
+            }
+            Err(ObjectClientError::ServiceError(HeadObjectError::NotFound)) => {}
+            Err(e) => warn!(prefix = ?self.prefix, "failed to look up directory marker for root: {e:?}"),
+        }
+    }
+
     fn make_attr(&self, lookup: &LookedUp) -> FileAttr {
         /// From man stat(2): `st_blocks`: "This field indicates the number of blocks allocated to
         /// the file, in 512-byte units."
@@ -235,33 +1032,98 @@ where
     }
 
     pub async fn lookup(&self, parent: InodeNo, name: &OsStr) -> Result<Entry, libc::c_int> {
+        let _slow_op = self.slow_op("lookup", parent);
         trace!("fs:lookup with parent {:?} name {:?}", parent, name);
 
-        let lookup = self.superblock.lookup(&self.client, parent, name).await?;
+        if let Some(node) = self.lookup_control_node(parent, name)? {
+            return Ok(Entry {
+                ttl: self.config.stat_ttl,
+                attr: self.make_control_attr(node).await,
+                generation: 0,
+            });
+        }
+
+        let versioned = self
+            .config
+            .expose_versions
+            .then(|| name.to_str().and_then(parse_versioned_name))
+            .flatten();
+        let ranged = self
+            .config
+            .expose_byte_ranges
+            .then(|| name.to_str().and_then(parse_ranged_name))
+            .flatten();
+        let deadline = Deadline::after(self.config.op_deadline);
+        let lookup = match (versioned, ranged) {
+            (Some((name, version_id)), _) => {
+                deadline
+                    .run(self.superblock.lookup_version(&self.client, parent, name, version_id))
+                    .await??
+            }
+            (None, Some((name, range))) => {
+                deadline
+                    .run(self.superblock.lookup_range(&self.client, parent, name, range))
+                    .await??
+            }
+            (None, None) => {
+                deadline
+                    .run(self.superblock.lookup(&self.client, parent, name))
+                    .await??
+            }
+        };
         let attr = self.make_attr(&lookup);
 
         Ok(Entry {
-            ttl: self.config.stat_ttl,
+            ttl: self.config.stat_ttl_for(lookup.inode.full_key()),
             attr,
             generation: 0,
         })
     }
 
     pub async fn getattr(&self, ino: InodeNo) -> Result<Attr, libc::c_int> {
+        let _slow_op = self.slow_op("getattr", ino);
         trace!("fs:getattr with ino {:?}", ino);
 
+        if let Some(node) = self.control_node(ino) {
+            return Ok(Attr {
+                ttl: self.config.stat_ttl,
+                attr: self.make_control_attr(node).await,
+            });
+        }
+
         let lookup = self.superblock.getattr(&self.client, ino).await?;
         let attr = self.make_attr(&lookup);
 
         Ok(Attr {
-            ttl: self.config.stat_ttl,
+            ttl: self.config.stat_ttl_for(lookup.inode.full_key()),
             attr,
         })
     }
 
-    pub async fn open(&self, ino: InodeNo, flags: i32) -> Result<Opened, libc::c_int> {
-        trace!("fs:open with ino {:?} flags {:?}", ino, flags);
+    pub async fn open(&self, ino: InodeNo, flags: i32, pid: u32) -> Result<Opened, libc::c_int> {
+        let _slow_op = self.slow_op("open", ino);
+        trace!("fs:open with ino {:?} flags {:?} pid {:?}", ino, flags, pid);
 
+        if let Some(node) = self.control_node(ino) {
+            return self.open_control_file(node, flags).await;
+        }
+
+        // Each open handle can hold prefetched data or upload parts, so cap how many there can be.
+        // The slot is taken before opening, so concurrent opens can't all fit under the limit.
+        let max_open_files = self.config.max_open_files.unwrap_or(usize::MAX);
+        let reserved = self
+            .open_files
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |open_files| {
+                (open_files < max_open_files).then_some(open_files + 1)
+            });
+        if let Err(open_files) = reserved {
+            warn!(open_files, max_open_files, "too many open files");
+            return Err(libc::EMFILE);
+        }
+        let open_file_slot = OpenFileSlot(&self.open_files);
+
+        // The inode's size and ETag come from whichever lookup or readdir last saw the object, so
+        // opening a file that was just listed doesn't need a HeadObject request.
         let lookup = self.superblock.getattr(&self.client, ino).await?;
 
         match lookup.inode.kind() {
@@ -269,6 +1131,7 @@ where
             InodeKind::File => (),
         }
 
+        let mut open_flags = 0;
         let handle_type = if flags & libc::O_RDWR != 0 {
             error!("O_RDWR is unsupported");
             return Err(libc::EINVAL);
@@ -280,19 +1143,91 @@ where
                 return Err(libc::EINVAL);
             }
 
-            let inode_handle = self.superblock.write(&self.client, ino, lookup.inode.parent()).await?;
-            FileHandleType::Write {
-                parts: Default::default(),
-                handle: inode_handle,
+            if flags & libc::O_APPEND != 0 && self.config.enable_append {
+                if lookup.stat.size > self.config.max_append_size {
+                    error!(
+                        size = lookup.stat.size,
+                        max_append_size = self.config.max_append_size,
+                        "object is too large to append to"
+                    );
+                    return Err(libc::EFBIG);
+                }
+
+                let inode_handle = self.superblock.append(&self.client, ino, lookup.inode.parent()).await?;
+                let parts = if lookup.stat.size > 0 {
+                    match self.read_for_append(&lookup).await {
+                        Ok(parts) => parts,
+                        Err(e) => {
+                            // The object is unchanged, so put the inode back the way it was
+                            inode_handle.finish_writing(lookup.stat.size)?;
+                            return Err(e);
+                        }
+                    }
+                } else {
+                    Vec::new()
+                };
+                FileHandleType::Write {
+                    upload: AsyncMutex::new(UploadState::InProgress {
+                        parts,
+                        handle: inode_handle,
+                        size_hint: None,
+                    }),
+                    expected_etag: Mutex::new(None),
+                }
+            } else {
+                let inode_handle = self.superblock.write(&self.client, ino, lookup.inode.parent()).await?;
+                FileHandleType::Write {
+                    upload: AsyncMutex::new(UploadState::InProgress {
+                        parts: Vec::new(),
+                        handle: inode_handle,
+                        size_hint: None,
+                    }),
+                    expected_etag: Mutex::new(None),
+                }
             }
         } else {
+            // Ranges of an object are read as they're stored, without decoding or zero-filling them
+            let head = if lookup.inode.byte_range().is_none()
+                && (self.config.decode_content_encoding || self.config.sparse_reads_return_zeros)
+            {
+                Some(self.head_for_read(&lookup).await?)
+            } else {
+                None
+            };
+            let encoding = head.as_ref().and_then(|head| self.content_encoding(&lookup, head));
+            let size = lookup.stat.size as u64;
+            let logical_size = head.as_ref().map_or(size, |head| self.logical_size(size, head));
             lookup.inode.start_reading()?;
-            FileHandleType::Read {
-                request: Default::default(),
-                etag: match lookup.stat.etag {
-                    None => return Err(libc::EBADF),
-                    Some(etag) => ETag::from_str(&etag).expect("E-Tag should be set"),
-                },
+            let etag = match lookup.stat.validators {
+                None => return Err(libc::EBADF),
+                Some(validators) => validators.etag,
+            };
+            match encoding {
+                Some(encoding) => {
+                    // The kernel would otherwise stop reading at the size we report, which is the
+                    // encoded size
+                    open_flags |= fuser::consts::FOPEN_DIRECT_IO;
+                    let (bucket, key) = self.superblock.bucket_and_key(lookup.inode.full_key());
+                    let object = DecodedObject::new(bucket, key, lookup.inode.version_id(), etag, size, encoding);
+                    FileHandleType::Decode {
+                        object: AsyncMutex::new(object),
+                    }
+                }
+                None => {
+                    if logical_size > size {
+                        // As above, the kernel would stop reading at the end of the object's data
+                        open_flags |= fuser::consts::FOPEN_DIRECT_IO;
+                    }
+                    FileHandleType::Read {
+                        request: Default::default(),
+                        object: Mutex::new(ReadObject {
+                            etag,
+                            size,
+                            logical_size,
+                            validated_at: Instant::now(),
+                        }),
+                    }
+                }
             }
         };
 
@@ -302,12 +1237,107 @@ where
         let handle = FileHandle {
             inode: lookup.inode,
             full_key,
-            object_size: lookup.stat.size as u64,
             typ: handle_type,
+            open_tgid: thread_group_id(pid),
         };
-        self.file_handles.write().await.insert(fh, handle);
+        self.file_handles.write().await.insert(fh, Arc::new(handle));
+        // The handle keeps the slot until it's released
+        std::mem::forget(open_file_slot);
 
-        Ok(Opened { fh, flags: 0 })
+        Ok(Opened { fh, flags: open_flags })
+    }
+
+    /// Get the metadata of an object being opened for reading that isn't available from listings
+    async fn head_for_read(&self, lookup: &LookedUp) -> Result<HeadObjectResult, libc::c_int> {
+        let (bucket, key) = self.superblock.bucket_and_key(lookup.inode.full_key());
+        self.client
+            .head_object(bucket, key, lookup.inode.version_id())
+            .await
+            .map_err(|e| {
+                error!(key, "failed to get object's metadata: {e:?}");
+                libc::EIO
+            })
+    }
+
+    /// The encoding to decode an object with when reading it, if it has one we can decode
+    fn content_encoding(&self, lookup: &LookedUp, head: &HeadObjectResult) -> Option<ContentEncoding> {
+        if !self.config.decode_content_encoding {
+            return None;
+        }
+        let value = head.content_encoding.as_deref()?;
+        let encoding = ContentEncoding::from_header(value);
+        if encoding.is_none() {
+            debug!(
+                key = lookup.inode.full_key(),
+                content_encoding = value,
+                "not decoding unsupported content encoding"
+            );
+        }
+        encoding
+    }
+
+    /// The logical size of an object with `size` bytes of data, which is larger than `size` only if
+    /// the object is sparse
+    fn logical_size(&self, size: u64, head: &HeadObjectResult) -> u64 {
+        if !self.config.sparse_reads_return_zeros {
+            return size;
+        }
+        let Some(value) = head.user_metadata.get(LOGICAL_SIZE_METADATA_KEY) else {
+            return size;
+        };
+        match value.parse::<u64>() {
+            Ok(logical_size) => logical_size.max(size),
+            Err(_) => {
+                warn!(key = head.object.key, value, "ignoring invalid logical size");
+                size
+            }
+        }
+    }
+
+    /// Read the current contents of an object into write parts, so that subsequent writes are
+    /// appended to it
+    async fn read_for_append(&self, lookup: &LookedUp) -> Result<Vec<Box<[u8]>>, libc::c_int> {
+        let (bucket, key) = self.superblock.bucket_and_key(lookup.inode.full_key());
+        let etag = lookup
+            .stat
+            .validators
+            .as_ref()
+            .map(|validators| validators.etag.clone());
+        let params = GetObjectParams::new().if_match(etag);
+        let request = self.client.get_object(bucket, key, &params).await.map_err(|e| {
+            error!(key, "failed to read object for append: {e:?}");
+            libc::EIO
+        })?;
+        pin_mut!(request);
+
+        let mut parts = Vec::new();
+        let mut next_offset = 0;
+        while let Some(part) = request.next().await {
+            let (offset, body) = part.map_err(|e| {
+                error!(key, "failed to read object for append: {e:?}");
+                libc::EIO
+            })?;
+            if offset != next_offset {
+                error!(
+                    key,
+                    offset, next_offset, "unexpected part offset while reading object for append"
+                );
+                return Err(libc::EIO);
+            }
+            next_offset += body.len() as u64;
+            parts.push(body);
+        }
+
+        if next_offset != lookup.stat.size as u64 {
+            error!(
+                key,
+                size = next_offset,
+                expected_size = lookup.stat.size,
+                "object changed size while reading it for append"
+            );
+            return Err(libc::EIO);
+        }
+        Ok(parts)
     }
 
     #[allow(clippy::too_many_arguments)] // We don't get to choose this interface
@@ -321,6 +1351,7 @@ where
         _lock: Option<u64>,
         reply: R,
     ) -> R::Replied {
+        let _slow_op = self.slow_op("read", ino);
         trace!(
             "fs:read with ino {:?} fh {:?} offset {:?} size {:?}",
             ino,
@@ -329,34 +1360,182 @@ where
             size
         );
 
+        if self.control_node(ino).is_some() {
+            let control_handles = self.control_handles.read().await;
+            let Some(contents) = control_handles.get(&fh) else {
+                return reply.error(libc::EBADF);
+            };
+            let start = (offset as usize).min(contents.len());
+            let end = start.saturating_add(size as usize).min(contents.len());
+            return reply.data(&contents[start..end]);
+        }
+
         let file_handles = self.file_handles.read().await;
         let Some(handle) = file_handles.get(&fh) else {
             return reply.error(libc::EBADF);
         };
-        let file_etag: ETag;
-        let mut request = match &handle.typ {
+        let (mut request, object) = match &handle.typ {
             FileHandleType::Write { .. } => return reply.error(libc::EBADF),
-            FileHandleType::Read { request, etag } => {
-                file_etag = etag.clone();
-                request.lock().await
+            FileHandleType::Read { request, object } => (request.lock().await, object),
+            FileHandleType::Decode { object } => {
+                let mut object = object.lock().await;
+                return match object.read(&self.client, offset as u64, size as usize).await {
+                    Ok(body) => reply.data(&body),
+                    Err(e) => {
+                        error!(key = handle.full_key, "failed to read decoded object: {e:?}");
+                        reply.error(libc::EIO)
+                    }
+                };
             }
         };
 
-        if request.is_none() {
-            *request = Some(
-                self.prefetcher
-                    .get(&self.bucket, &handle.full_key, handle.object_size, file_etag),
-            );
+        if let Err(e) = self.revalidate_read(handle, object, &mut request).await {
+            return reply.error(e);
+        }
+
+        // Reads of a sparse object that go past the end of its data are filled with zeros up to its
+        // logical size
+        let (data_size, logical_size) = {
+            let object = object.lock().unwrap();
+            (object.size, object.logical_size)
+        };
+        let read_end = (offset as u64 + size as u64).min(logical_size);
+        if offset as u64 >= data_size {
+            let len = read_end.saturating_sub(offset as u64) as usize;
+            return reply.data(&vec![0; len]);
         }
 
-        match request.as_mut().unwrap().read(offset as u64, size as usize).await {
+        // Reads of a ranged inode are at offsets into its range of the object
+        let object_offset = handle.inode.byte_range().map_or(0, |range| range.start) + offset as u64;
+
+        let mut retries = 0;
+        let result = loop {
+            if request.is_none() {
+                *request = Some(self.start_read_request(handle, &object.lock().unwrap()));
+            }
+            match request.as_mut().unwrap().read(object_offset, size as usize).await {
+                Err(PrefetchReadError::GetRequestFailed(ObjectClientError::ServiceError(
+                    GetObjectError::NoSuchKey,
+                ))) if retries < self.config.read_after_write_retries => {
+                    let backoff = READ_AFTER_WRITE_INITIAL_BACKOFF * 2u32.saturating_pow(retries);
+                    debug!(
+                        key = handle.full_key,
+                        retries,
+                        ?backoff,
+                        "object not found, will retry read"
+                    );
+                    *request = None;
+                    sleep(backoff).await;
+                    retries += 1;
+                }
+                result => break result,
+            }
+        };
+
+        match result {
+            Ok(body) if read_end > data_size && offset as u64 + body.len() as u64 == data_size => {
+                let mut data = body.to_vec();
+                data.resize((read_end - offset as u64) as usize, 0);
+                reply.data(&data)
+            }
             Ok(body) => reply.data(&body),
+            Err(PrefetchReadError::GetRequestFailed(ObjectClientError::ServiceError(GetObjectError::NoSuchKey))) => {
+                warn!(key = handle.full_key, "object open for reading was deleted");
+                handle.inode.mark_stale();
+                reply.error(InodeError::StaleInode(ino).into())
+            }
+            Err(PrefetchReadError::ObjectSizeMismatch { .. }) => {
+                warn!(key = handle.full_key, "object open for reading changed size");
+                handle.inode.mark_stale();
+                reply.error(InodeError::StaleInode(ino).into())
+            }
             Err(PrefetchReadError::GetRequestFailed(_)) | Err(PrefetchReadError::GetRequestTerminatedUnexpectedly) => {
                 reply.error(libc::EIO)
             }
         }
     }
 
+    /// Start a new prefetching request for the object behind a read handle
+    fn start_read_request(
+        &self,
+        handle: &FileHandle<ConcurrencyLimitedClient<Client>, Runtime>,
+        object: &ReadObject,
+    ) -> PrefetchGetObject<ConcurrencyLimitedClient<Client>, Runtime> {
+        let (bucket, key) = self.superblock.bucket_and_key(&handle.full_key);
+        let version_id = handle.inode.version_id();
+        match handle.inode.byte_range() {
+            Some(range) => self
+                .prefetcher
+                .get_range(bucket, key, version_id, range, object.etag.clone()),
+            None => self
+                .prefetcher
+                .get(bucket, key, version_id, object.size, object.etag.clone()),
+        }
+    }
+
+    /// Check whether the object behind a read handle has been replaced, if it's been longer than
+    /// the stat TTL since we last checked, and act on it according to the [StaleReadPolicy]. To
+    /// restart the read, this resets `request` so that the next read starts a new one.
+    async fn revalidate_read(
+        &self,
+        handle: &FileHandle<ConcurrencyLimitedClient<Client>, Runtime>,
+        object: &Mutex<ReadObject>,
+        request: &mut Option<PrefetchGetObject<ConcurrencyLimitedClient<Client>, Runtime>>,
+    ) -> Result<(), libc::c_int> {
+        // Specific versions of an object never change, and the size of a range is fixed when it's
+        // looked up, so a replaced object fails the range's reads instead of restarting them
+        if self.config.stale_read_policy == StaleReadPolicy::Ignore
+            || handle.inode.version_id().is_some()
+            || handle.inode.byte_range().is_some()
+        {
+            return Ok(());
+        }
+        let ttl = self.config.stat_ttl_for(&handle.full_key);
+        if object.lock().unwrap().validated_at.elapsed() < ttl {
+            return Ok(());
+        }
+
+        let (bucket, key) = self.superblock.bucket_and_key(&handle.full_key);
+        let head = self.client.head_object(bucket, key, None).await.map_err(|e| match e {
+            ObjectClientError::ServiceError(HeadObjectError::NotFound) => {
+                warn!(key, "object open for reading was deleted");
+                handle.inode.mark_stale();
+                InodeError::StaleInode(handle.inode.ino()).into()
+            }
+            e => {
+                error!(key, "failed to revalidate object: {e:?}");
+                libc::EIO
+            }
+        })?;
+
+        let mut object = object.lock().unwrap();
+        let validators = head.cache_validators();
+        if validators.etag == object.etag {
+            object.validated_at = Instant::now();
+            return Ok(());
+        }
+
+        match self.config.stale_read_policy {
+            StaleReadPolicy::Ignore => unreachable!("checked above"),
+            StaleReadPolicy::Error => {
+                // Leave the handle unvalidated so that every later read fails too
+                warn!(key, old_etag = ?object.etag, new_etag = ?validators.etag, "object open for reading was replaced");
+                Err(libc::ESTALE)
+            }
+            StaleReadPolicy::Restart => {
+                debug!(key, old_etag = ?object.etag, new_etag = ?validators.etag, "object open for reading was replaced, restarting read");
+                *object = ReadObject {
+                    etag: validators.etag,
+                    size: head.object.size,
+                    logical_size: self.logical_size(head.object.size, &head),
+                    validated_at: Instant::now(),
+                };
+                *request = None;
+                Ok(())
+            }
+        }
+    }
+
     pub async fn mknod(
         &self,
         parent: InodeNo,
@@ -365,6 +1544,7 @@ where
         _umask: u32,
         _rdev: u32,
     ) -> Result<Entry, libc::c_int> {
+        let _slow_op = self.slow_op("mknod", parent);
         if mode & libc::S_IFMT != libc::S_IFREG {
             error!(
                 ?parent,
@@ -374,15 +1554,19 @@ where
             );
             return Err(libc::EINVAL);
         }
+        if self.lookup_control_node(parent, name)?.is_some() {
+            return Err(libc::EEXIST);
+        }
 
         let lookup = self
             .superblock
             .create(&self.client, parent, name, InodeKind::File)
             .await?;
+        self.dir_snapshots.invalidate(parent);
         let attr = self.make_attr(&lookup);
 
         Ok(Entry {
-            ttl: self.config.stat_ttl,
+            ttl: self.config.stat_ttl_for(lookup.inode.full_key()),
             attr,
             generation: 0,
         })
@@ -395,14 +1579,19 @@ where
         _mode: libc::mode_t,
         _umask: u32,
     ) -> Result<Entry, libc::c_int> {
+        let _slow_op = self.slow_op("mkdir", parent);
+        if self.lookup_control_node(parent, name)?.is_some() {
+            return Err(libc::EEXIST);
+        }
         let lookup = self
             .superblock
             .create(&self.client, parent, name, InodeKind::Directory)
             .await?;
+        self.dir_snapshots.invalidate(parent);
         let attr = self.make_attr(&lookup);
 
         Ok(Entry {
-            ttl: self.config.stat_ttl,
+            ttl: self.config.stat_ttl_for(lookup.inode.full_key()),
             attr,
             generation: 0,
         })
@@ -419,7 +1608,7 @@ where
         _flags: i32,
         _lock_owner: Option<u64>,
     ) -> Result<u32, libc::c_int> {
-        const MAX_OBJECT_SIZE: usize = 5 * 1024 * 1024 * 1024;
+        let _slow_op = self.slow_op("write", ino);
 
         trace!(
             "fs:write with ino {:?} fh {:?} offset {:?} size {:?}",
@@ -429,43 +1618,347 @@ where
             data.len()
         );
 
+        if let Some(node) = self.control_node(ino) {
+            if !self.control_handles.read().await.contains_key(&fh) {
+                return Err(libc::EBADF);
+            }
+            let ControlNode::File(file) = node else {
+                return Err(libc::EISDIR);
+            };
+            self.run_control_command(file).await?;
+            return Ok(data.len() as u32);
+        }
+
         let file_handles = self.file_handles.read().await;
         let Some(handle) = file_handles.get(&fh) else {
             return Err(libc::EBADF);
         };
-        let mut request = match &handle.typ {
-            FileHandleType::Write { parts, .. } => parts.lock().await,
-            FileHandleType::Read { .. } => return Err(libc::EBADF),
+        let mut upload = match &handle.typ {
+            FileHandleType::Write { upload, .. } => upload.lock().await,
+            FileHandleType::Read { .. } | FileHandleType::Decode { .. } => return Err(libc::EBADF),
+        };
+        let next_offset = match &*upload {
+            UploadState::InProgress { parts, .. } => parts.iter().map(|p| p.len()).sum::<usize>(),
+            UploadState::Streaming { upload, .. } => upload.bytes_written(),
+            UploadState::Completed => {
+                error!(key = handle.full_key, "file was already closed for writing");
+                return Err(libc::EBADF);
+            }
         };
-
-        let next_offset = request.iter().map(|p| p.len()).sum::<usize>();
         if offset != next_offset as i64 {
             error!("out of order write; expected offset {next_offset} but got {offset}");
             return Err(libc::EINVAL);
         }
 
-        // If we'd go over the size limit, fail the entire write rather than short-writing
-        if next_offset + data.len() > MAX_OBJECT_SIZE {
-            error!("object too large");
+        // If we'd go over the size limit, fail the entire write rather than short-writing. The
+        // object could never be uploaded, so give up on it now rather than when it's closed.
+        if (next_offset + data.len()) as u64 > self.config.max_object_size {
+            error!(
+                key = handle.full_key,
+                size = next_offset + data.len(),
+                max_object_size = self.config.max_object_size,
+                "object too large, aborting upload"
+            );
+            // Dropping a streaming upload abandons its request
+            let (UploadState::InProgress { handle, .. } | UploadState::Streaming { handle, .. }) =
+                std::mem::replace(&mut *upload, UploadState::Completed)
+            else {
+                unreachable!("upload is in progress");
+            };
+            // Nothing was uploaded, so the file is empty until it's next looked up
+            handle.finish_writing(0)?;
             return Err(libc::EFBIG);
         }
 
+        // A streaming upload starts with the first write rather than at `open`, so that it can
+        // pick a part size for the file's expected size if `fallocate` said what that is
+        if let (Some(buffer_size), UploadState::InProgress { parts, .. }) =
+            (self.config.stream_writes_buffer_size, &*upload)
+        {
+            if parts.is_empty() && !self.config.dry_run_writes {
+                let UploadState::InProgress {
+                    handle: inode_handle,
+                    size_hint,
+                    ..
+                } = std::mem::replace(&mut *upload, UploadState::Completed)
+                else {
+                    unreachable!("upload is in progress");
+                };
+                let params = self.put_params(&handle.full_key, UploadMode::Multipart, size_hint);
+                let buffer_size = size_hint.map_or(buffer_size, |size| upload_part_size(size, buffer_size));
+                let (bucket, key) = self.superblock.bucket_and_key(&handle.full_key);
+                *upload = UploadState::Streaming {
+                    upload: self.uploader.put_object_streaming(bucket, key, params, buffer_size),
+                    handle: inode_handle,
+                };
+            }
+        }
+
         let len = data.len();
-        // TODO wrap this in the `Part` machinery and validate it on PUT (and checksum)
-        request.push(data.into());
+        match &mut *upload {
+            // TODO wrap this in the `Part` machinery and validate it on PUT (and checksum)
+            UploadState::InProgress { parts, .. } => parts.push(data.into()),
+            UploadState::Streaming { upload: stream, .. } => {
+                if stream.write(data).await.is_err() {
+                    // Closing the file reports why, and finishes the inode
+                    error!(key = handle.full_key, "upload failed before the file was closed");
+                    return Err(libc::EIO);
+                }
+            }
+            UploadState::Completed => unreachable!("upload is in progress"),
+        }
         Ok(len as u32)
     }
 
+    /// Copy bytes from a file open for reading to a file open for writing. If the entire object is
+    /// being copied into a new file, the copy is done in S3 with a CopyObject request, and completes
+    /// the destination's upload. Otherwise, up to [COPY_FILE_RANGE_CHUNK_SIZE] bytes are read from
+    /// the source and written to the destination, which must be sequential like any other write.
+    #[allow(clippy::too_many_arguments)] // We don't get to choose this interface
+    pub async fn copy_file_range(
+        &self,
+        ino_in: InodeNo,
+        fh_in: u64,
+        offset_in: i64,
+        ino_out: InodeNo,
+        fh_out: u64,
+        offset_out: i64,
+        len: u64,
+        _flags: u32,
+    ) -> Result<u32, libc::c_int> {
+        let _slow_op = self.slow_op("copy_file_range", ino_out);
+        trace!(
+            "fs:copy_file_range from ino {:?} fh {:?} offset {:?} to ino {:?} fh {:?} offset {:?} len {:?}",
+            ino_in,
+            fh_in,
+            offset_in,
+            ino_out,
+            fh_out,
+            offset_out,
+            len
+        );
+
+        // Let the kernel copy control files itself
+        if self.control_node(ino_in).is_some() || self.control_node(ino_out).is_some() {
+            return Err(libc::EOPNOTSUPP);
+        }
+
+        if offset_in == 0 && offset_out == 0 {
+            if let Some(copied) = self.copy_object(fh_in, fh_out, len).await? {
+                return Ok(copied);
+            }
+        }
+
+        struct CopyReply<'a>(&'a mut Result<Box<[u8]>, libc::c_int>);
+
+        impl ReadReplier for CopyReply<'_> {
+            type Replied = ();
+
+            fn data(self, data: &[u8]) {
+                *self.0 = Ok(data.into());
+            }
+
+            fn error(self, error: libc::c_int) {
+                *self.0 = Err(error);
+            }
+        }
+
+        let size = len.min(COPY_FILE_RANGE_CHUNK_SIZE as u64) as u32;
+        let mut data = Err(libc::EIO);
+        self.read(ino_in, fh_in, offset_in, size, 0, None, CopyReply(&mut data))
+            .await;
+        let data = data?;
+        if data.is_empty() {
+            return Ok(0);
+        }
+        self.write(ino_out, fh_out, offset_out, &data, 0, 0, None).await
+    }
+
+    /// Copy the whole object behind a read handle to the new file behind a write handle with a
+    /// CopyObject request, returning the number of bytes copied. Returns `None` without copying
+    /// anything if the copy can't be done in S3 and needs to be done by reading and writing.
+    async fn copy_object(&self, fh_in: u64, fh_out: u64, len: u64) -> Result<Option<u32>, libc::c_int> {
+        let file_handles = self.file_handles.read().await;
+        let source = file_handles.get(&fh_in).ok_or(libc::EBADF)?;
+        let destination = file_handles.get(&fh_out).ok_or(libc::EBADF)?;
+
+        let FileHandleType::Read { object, .. } = &source.typ else {
+            return Ok(None);
+        };
+        let FileHandleType::Write { upload, .. } = &destination.typ else {
+            return Err(libc::EBADF);
+        };
+        // CopyObject always copies the current version of the whole object, as it's stored
+        if source.inode.version_id().is_some() || source.inode.byte_range().is_some() {
+            return Ok(None);
+        }
+        let size = {
+            let object = object.lock().unwrap();
+            if object.logical_size != object.size {
+                return Ok(None);
+            }
+            object.size
+        };
+        // The size copied has to fit in the reply, and the copy would lose any storage class
+        // configured for the destination
+        if len < size
+            || size > u32::MAX as u64
+            || self.config.dry_run_writes
+            || self.config.storage_class_for(&destination.full_key).is_some()
+        {
+            return Ok(None);
+        }
+
+        let mut upload = upload.lock().await;
+        match &*upload {
+            UploadState::InProgress { parts, .. } if parts.is_empty() => {}
+            // A streaming upload has already started its own request
+            UploadState::InProgress { .. } | UploadState::Streaming { .. } => return Ok(None),
+            UploadState::Completed => {
+                error!(key = destination.full_key, "file was already closed for writing");
+                return Err(libc::EBADF);
+            }
+        }
+
+        let (source_bucket, source_key) = self.superblock.bucket_and_key(&source.full_key);
+        let (destination_bucket, destination_key) = self.superblock.bucket_and_key(&destination.full_key);
+        if let Err(e) = self
+            .client
+            .copy_object(source_bucket, source_key, destination_bucket, destination_key)
+            .await
+        {
+            error!(
+                source_key,
+                destination_key, "copy failed, destination was not written: {e:?}"
+            );
+            return Err(libc::EIO);
+        }
+        debug!(source_key, destination_key, size, "copy succeeded");
+
+        let UploadState::InProgress { handle, .. } = std::mem::replace(&mut *upload, UploadState::Completed) else {
+            unreachable!("upload is in progress");
+        };
+        handle.finish_writing(size as usize)?;
+        Ok(Some(size as u32))
+    }
+
+    /// Rename a file. Only [RENAME_EXCHANGE] is supported, and only if
+    /// [S3FilesystemConfig::allow_nonatomic_exchange] is set; other renames fail with `ENOSYS`.
+    ///
+    /// S3 has no way to swap two objects, so the exchange is done with CopyObject requests: `name`
+    /// is copied to a temporary object next to it, `newname` is copied over `name`, the temporary
+    /// object is copied over `newname`, and then it's deleted. This isn't atomic. Until it's done,
+    /// other clients can see both keys with the same contents, and if a request fails partway, the
+    /// temporary object is left behind with the original contents of `name`. Copies also lose the
+    /// objects' storage classes and any other metadata that CopyObject doesn't copy.
+    pub async fn rename(
+        &self,
+        parent: InodeNo,
+        name: &OsStr,
+        newparent: InodeNo,
+        newname: &OsStr,
+        flags: u32,
+    ) -> Result<(), libc::c_int> {
+        let _slow_op = self.slow_op("rename", parent);
+        trace!(
+            "fs:rename with parent {:?} name {:?} newparent {:?} newname {:?} flags {:?}",
+            parent,
+            name,
+            newparent,
+            newname,
+            flags
+        );
+
+        if flags != RENAME_EXCHANGE {
+            return Err(if flags == 0 { libc::ENOSYS } else { libc::EINVAL });
+        }
+        if !self.config.allow_nonatomic_exchange {
+            return Err(libc::ENOTSUP);
+        }
+        if self.config.dry_run_writes {
+            return Err(libc::EROFS);
+        }
+        if self.control_node(parent).is_some() || self.control_node(newparent).is_some() {
+            return Err(libc::EPERM);
+        }
+
+        let source = self.superblock.lookup(&self.client, parent, name).await?;
+        let target = self.superblock.lookup(&self.client, newparent, newname).await?;
+        if source.inode.ino() == target.inode.ino() {
+            return Ok(());
+        }
+        for lookup in [&source, &target] {
+            if lookup.inode.kind() == InodeKind::Directory {
+                return Err(libc::EISDIR);
+            }
+            if lookup.inode.version_id().is_some() || lookup.inode.byte_range().is_some() {
+                return Err(libc::EPERM);
+            }
+            // Files still being written don't exist in S3 yet
+            lookup.inode.start_reading()?;
+        }
+
+        let (bucket, source_key) = self.superblock.bucket_and_key(source.inode.full_key());
+        let (target_bucket, target_key) = self.superblock.bucket_and_key(target.inode.full_key());
+        if bucket != target_bucket {
+            return Err(libc::EXDEV);
+        }
+        let temp_key = exchange_temp_key(source_key);
+        debug!(source_key, target_key, temp_key, "exchanging objects");
+
+        for (from, to) in [
+            (source_key, &*temp_key),
+            (target_key, source_key),
+            (&*temp_key, target_key),
+        ] {
+            if let Err(e) = self.client.copy_object(bucket, from, bucket, to).await {
+                error!(from, to, temp_key, "copy failed, exchange is incomplete: {e:?}");
+                return Err(libc::EIO);
+            }
+        }
+        if let Err(e) = self.client.delete_object(bucket, &temp_key, None).await {
+            // Both names have their new contents, so the exchange itself succeeded
+            warn!(temp_key, "failed to delete temporary object after exchange: {e:?}");
+        }
+
+        // Both objects changed, so their next lookups need to ask S3
+        source.inode.expire_stat();
+        target.inode.expire_stat();
+        self.dir_snapshots.invalidate(parent);
+        self.dir_snapshots.invalidate(newparent);
+        Ok(())
+    }
+
     pub async fn opendir(&self, parent: InodeNo, _flags: i32) -> Result<Opened, libc::c_int> {
+        let _slow_op = self.slow_op("opendir", parent);
         trace!("fs:opendir with parent {:?} flags {:?}", parent, _flags);
 
+        // The control directory's entries never change, so there's nothing to keep in a handle
+        match self.control_node(parent) {
+            Some(ControlNode::Dir(_)) => {
+                return Ok(Opened {
+                    fh: self.next_handle(),
+                    flags: 0,
+                })
+            }
+            Some(ControlNode::File(_)) => return Err(libc::ENOTDIR),
+            None => {}
+        }
+
         let inode_handle = self.superblock.readdir(&self.client, parent, 1000).await?;
+        let snapshot = self.dir_snapshots.get(parent);
+        if snapshot.is_some() {
+            debug!(dir = ?parent, "listing directory from cached snapshot");
+        }
 
         let fh = self.next_handle();
         let handle = DirHandle {
             ino: parent,
+            snapshot_ttl: self.config.stat_ttl_for(inode_handle.full_path()),
             handle: inode_handle,
             offset: AtomicI64::new(0),
+            reordered: Mutex::new(snapshot.map(|snapshot| snapshot.entries.iter().cloned().collect())),
+            partial: Mutex::new(Vec::new()),
         };
 
         let mut dir_handles = self.dir_handles.write().await;
@@ -475,14 +1968,36 @@ where
     }
 
     pub async fn readdir<R: DirectoryReplier>(
+        &self,
+        parent: InodeNo,
+        fh: u64,
+        offset: i64,
+        reply: R,
+    ) -> Result<R, libc::c_int> {
+        self.readdir_interruptible(parent, fh, offset, reply, &AtomicBool::new(false))
+            .await
+    }
+
+    /// Like [S3Filesystem::readdir], but gives up with `EINTR` if `interrupted` is set while the
+    /// whole directory is being listed up front (see [ReaddirOrder]). A later `readdir` at the same
+    /// offset carries on listing from where this one stopped.
+    pub async fn readdir_interruptible<R: DirectoryReplier>(
         &self,
         parent: InodeNo,
         fh: u64,
         offset: i64,
         mut reply: R,
+        interrupted: &AtomicBool,
     ) -> Result<R, libc::c_int> {
+        let _slow_op = self.slow_op("readdir", parent);
         trace!("fs:readdir with ino {:?} fh {:?} offset {:?}", parent, fh, offset);
 
+        match self.control_node(parent) {
+            Some(ControlNode::Dir(dir)) => return self.readdir_control(dir, offset, reply).await,
+            Some(ControlNode::File(_)) => return Err(libc::ENOTDIR),
+            None => {}
+        }
+
         let handle = {
             let dir_handles = self.dir_handles.read().await;
             dir_handles.get(&fh).cloned().ok_or(libc::EBADF)?
@@ -497,104 +2012,611 @@ where
             return Err(libc::EINVAL);
         }
 
+        let deadline = Deadline::after(self.config.op_deadline);
+
+        // List everything before replying with anything, so that an interrupted listing leaves the
+        // handle's offset where it was
+        handle
+            .list_all(
+                &self.client,
+                self.config.readdir_order,
+                &self.dir_snapshots,
+                interrupted,
+                &deadline,
+            )
+            .await?;
+
+        let mut budget = ReaddirBudget::new(&self.config);
+        // Entries in the reply have already moved the handle's offset on, so once there are any,
+        // running out of time ends the reply early instead of failing it
+        let start_offset = handle.offset();
+
         if handle.offset() < 1 {
             // TODO these can probably just be bare `get`, we don't care about directory stat
-            let lookup = self.superblock.getattr(&self.client, parent).await?;
+            let lookup = deadline.run(self.superblock.getattr(&self.client, parent)).await??;
             let attr = self.make_attr(&lookup);
-            if reply.add(parent, handle.offset() + 1, ".", attr, 0u64, self.config.stat_ttl) {
+            let ttl = self.config.stat_ttl_for(lookup.inode.full_key());
+            if !budget.try_add(".") || reply.add(parent, handle.offset() + 1, ".", attr, 0u64, ttl) {
                 return Ok(reply);
             }
             handle.next_offset();
         }
         if handle.offset() < 2 {
-            let lookup = self.superblock.getattr(&self.client, handle.handle.parent()).await?;
+            let lookup = match deadline
+                .run(self.superblock.getattr(&self.client, handle.handle.parent()))
+                .await
+            {
+                Err(_) if handle.offset() > start_offset => return Ok(reply),
+                result => result??,
+            };
             let attr = self.make_attr(&lookup);
-            if reply.add(
-                handle.handle.parent(),
-                handle.offset() + 1,
-                "..",
-                attr,
-                0u64,
-                self.config.stat_ttl,
-            ) {
+            if !budget.try_add("..")
+                || reply.add(
+                    handle.handle.parent(),
+                    handle.offset() + 1,
+                    "..",
+                    attr,
+                    0u64,
+                    self.config.stat_ttl_for(lookup.inode.full_key()),
+                )
+            {
                 return Ok(reply);
             }
             handle.next_offset();
         }
 
         loop {
-            let next = match handle.handle.next(&self.client).await? {
-                None => return Ok(reply),
-                Some(next) => next,
+            let next = match deadline
+                .run(handle.next(&self.client, self.config.readdir_order, &self.dir_snapshots))
+                .await
+            {
+                Err(_) if handle.offset() > start_offset => return Ok(reply),
+                result => match result?? {
+                    None => return Ok(reply),
+                    Some(next) => next,
+                },
             };
 
             let attr = self.make_attr(&next);
-            if reply.add(
-                attr.ino,
-                handle.offset() + 1,
-                next.inode.name(),
-                attr,
-                0u64,
-                self.config.stat_ttl,
-            ) {
-                handle.handle.readd(next);
+            if !budget.try_add(next.inode.name())
+                || reply.add(
+                    attr.ino,
+                    handle.offset() + 1,
+                    next.inode.name(),
+                    attr,
+                    0u64,
+                    self.config.stat_ttl_for(next.inode.full_key()),
+                )
+            {
+                handle.readd(next);
                 return Ok(reply);
             }
             handle.next_offset();
         }
     }
 
+    /// Act on advice about how a file open for reading will be read, like `posix_fadvise`. The
+    /// kernel doesn't pass `posix_fadvise` or `readahead` calls on to FUSE file systems, so
+    /// applications give this advice with [FADVISE_IOCTL]. `len` is relative to `offset`, and 0
+    /// means to the end of the file. Advice for files open for writing is ignored.
+    pub async fn fadvise(
+        &self,
+        ino: InodeNo,
+        fh: u64,
+        offset: u64,
+        len: u64,
+        advice: ReadAdvice,
+    ) -> Result<(), libc::c_int> {
+        trace!(
+            "fs:fadvise with ino {:?} fh {:?} offset {:?} len {:?} advice {:?}",
+            ino,
+            fh,
+            offset,
+            len,
+            advice
+        );
+
+        let file_handles = self.file_handles.read().await;
+        let handle = file_handles.get(&fh).ok_or(libc::EBADF)?;
+        let FileHandleType::Read { request, object } = &handle.typ else {
+            return Ok(());
+        };
+        let mut request = request.lock().await;
+        let object = object.lock().unwrap();
+        let request = request.get_or_insert_with(|| self.start_read_request(handle, &object));
+
+        match advice {
+            ReadAdvice::Normal => request.set_readahead(true),
+            ReadAdvice::Random => request.set_readahead(false),
+            ReadAdvice::Sequential | ReadAdvice::WillNeed => {
+                request.set_readahead(true);
+                let end = if len == 0 {
+                    object.size
+                } else {
+                    offset.saturating_add(len).min(object.size)
+                };
+                // Ranged inodes are read at offsets into their range of the object
+                let start_of_range = handle.inode.byte_range().map_or(0, |range| range.start);
+                request.prefetch(start_of_range + offset..start_of_range + end);
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle a custom `ioctl` command: [S3_KEY_IOCTL] or [FADVISE_IOCTL].
+    pub async fn ioctl(
+        &self,
+        ino: InodeNo,
+        fh: u64,
+        _flags: u32,
+        cmd: u32,
+        in_data: &[u8],
+        out_size: u32,
+    ) -> Result<Vec<u8>, libc::c_int> {
+        trace!("fs:ioctl with ino {:?} fh {:?} cmd {:#x}", ino, fh, cmd);
+
+        if cmd == FADVISE_IOCTL {
+            let Ok(in_data) = <[u8; FADVISE_IOCTL_SIZE]>::try_from(in_data) else {
+                return Err(libc::EINVAL);
+            };
+            let offset = u64::from_ne_bytes(in_data[0..8].try_into().unwrap());
+            let len = u64::from_ne_bytes(in_data[8..16].try_into().unwrap());
+            let advice = u32::from_ne_bytes(in_data[16..20].try_into().unwrap());
+            let advice = ReadAdvice::from_raw(advice).ok_or(libc::EINVAL)?;
+            self.fadvise(ino, fh, offset, len, advice).await?;
+            return Ok(Vec::new());
+        }
+        if cmd != S3_KEY_IOCTL {
+            return Err(libc::ENOTTY);
+        }
+
+        let lookup = self.superblock.getattr(&self.client, ino).await?;
+        let mut key = lookup.inode.full_key().as_bytes().to_vec();
+        key.push(0);
+        if key.len() > out_size as usize {
+            return Err(libc::ERANGE);
+        }
+        Ok(key)
+    }
+
+    /// Preallocate space in a file opened for writing. S3 has nothing to reserve, so this only
+    /// records how big the file is expected to grow, which lets its upload choose a part size that
+    /// fits the whole file from the start. The file's size is still only what's written to it, even
+    /// without `FALLOC_FL_KEEP_SIZE`. Other modes, like punching holes, aren't supported.
+    pub async fn fallocate(
+        &self,
+        ino: InodeNo,
+        fh: u64,
+        offset: i64,
+        length: i64,
+        mode: i32,
+    ) -> Result<(), libc::c_int> {
+        let _slow_op = self.slow_op("fallocate", ino);
+        trace!(
+            "fs:fallocate with ino {:?} fh {:?} offset {:?} length {:?} mode {:?}",
+            ino,
+            fh,
+            offset,
+            length,
+            mode
+        );
+
+        if mode & !FALLOC_FL_KEEP_SIZE != 0 {
+            return Err(libc::EOPNOTSUPP);
+        }
+        if offset < 0 || length <= 0 {
+            return Err(libc::EINVAL);
+        }
+        let size = offset as u64 + length as u64;
+        if size > self.config.max_object_size {
+            return Err(libc::EFBIG);
+        }
+
+        let file_handles = self.file_handles.read().await;
+        let Some(handle) = file_handles.get(&fh) else {
+            return Err(libc::EBADF);
+        };
+        let FileHandleType::Write { upload, .. } = &handle.typ else {
+            return Err(libc::EBADF);
+        };
+        match &mut *upload.lock().await {
+            UploadState::InProgress { size_hint, .. } => {
+                *size_hint = Some(size_hint.unwrap_or(0).max(size));
+                Ok(())
+            }
+            // It's too late to change the part size of an upload that's already started
+            UploadState::Streaming { .. } => Ok(()),
+            UploadState::Completed => Err(libc::EBADF),
+        }
+    }
+
+    /// Set an extended attribute. The only one supported is [EXPECTED_ETAG_XATTR], on a file that's
+    /// open for writing, which applies to every handle writing it.
+    pub async fn setxattr(
+        &self,
+        ino: InodeNo,
+        name: &OsStr,
+        value: &[u8],
+        _flags: i32,
+        position: u32,
+    ) -> Result<(), libc::c_int> {
+        let _slow_op = self.slow_op("setxattr", ino);
+        trace!("fs:setxattr with ino {:?} name {:?} position {:?}", ino, name, position);
+
+        if name != EXPECTED_ETAG_XATTR {
+            return Err(libc::ENOTSUP);
+        }
+        // Only macOS resource forks have a position
+        if position != 0 {
+            return Err(libc::EINVAL);
+        }
+        let etag = std::str::from_utf8(value).map_err(|_| libc::EINVAL)?.trim();
+        if etag.is_empty() {
+            return Err(libc::EINVAL);
+        }
+
+        let file_handles = self.file_handles.read().await;
+        let mut writing = false;
+        for handle in file_handles.values().filter(|handle| handle.inode.ino() == ino) {
+            if let FileHandleType::Write { expected_etag, .. } = &handle.typ {
+                *expected_etag.lock().unwrap() = Some(etag.to_owned());
+                writing = true;
+            }
+        }
+        // Only an upload has an ETag to check
+        if !writing {
+            return Err(libc::EPERM);
+        }
+        Ok(())
+    }
+
+    /// Complete the upload for a file opened for writing, so that errors can be returned to the
+    /// application from `close`. Both `flush` and `release` are safe to call after the upload has
+    /// completed, and will do nothing.
+    ///
+    /// The kernel sends a `flush` every time a descriptor for the handle is closed, including
+    /// descriptors that were duplicated or inherited by a child process. Only a `flush` from the
+    /// process that opened the file completes the upload; closes from other processes leave it open
+    /// for further writes, and `release` completes it if the opener never flushes.
+    pub async fn flush(&self, ino: InodeNo, fh: u64, _lock_owner: u64, pid: u32) -> Result<(), libc::c_int> {
+        let _slow_op = self.slow_op("flush", ino);
+        trace!("fs:flush with ino {:?} fh {:?} pid {:?}", ino, fh, pid);
+
+        if self.control_node(ino).is_some() {
+            return Ok(());
+        }
+
+        // Don't hold the handle map's lock during the upload, or every open and release would
+        // wait for it to finish
+        let file_handle = {
+            let file_handles = self.file_handles.read().await;
+            file_handles.get(&fh).cloned().ok_or(libc::EBADF)?
+        };
+        match &file_handle.typ {
+            FileHandleType::Write { .. } if thread_group_id(pid) != file_handle.open_tgid => {
+                debug!(
+                    fh,
+                    pid, "not completing upload on flush from a process that didn't open the file"
+                );
+                Ok(())
+            }
+            FileHandleType::Write { upload, expected_etag } => {
+                let mut upload = upload.lock().await;
+                let expected_etag = expected_etag.lock().unwrap().clone();
+                self.complete_upload(&file_handle.full_key, &mut upload, expected_etag.as_deref())
+                    .await
+            }
+            FileHandleType::Read { .. } | FileHandleType::Decode { .. } => Ok(()),
+        }
+    }
+
     pub async fn release(
         &self,
-        _ino: InodeNo,
+        ino: InodeNo,
         fh: u64,
         _flags: i32,
         _lock_owner: Option<u64>,
         _flush: bool,
     ) -> Result<(), libc::c_int> {
+        let _slow_op = self.slow_op("release", ino);
+        if self.control_node(ino).is_some() {
+            let mut control_handles = self.control_handles.write().await;
+            return control_handles.remove(&fh).map(|_| ()).ok_or(libc::EBADF);
+        }
+
         let file_handle = {
             let mut file_handles = self.file_handles.write().await;
             file_handles.remove(&fh).ok_or(libc::EBADF)?
         };
+        self.open_files.fetch_sub(1, Ordering::SeqCst);
+
+        match &file_handle.typ {
+            FileHandleType::Write { upload, expected_etag } => {
+                // Usually `flush` has already completed the upload, but not if the kernel never
+                // sent us one (for example, if the file was only written through a mapping). A
+                // `flush` might still be running, so wait for it rather than racing it.
+                let mut upload = upload.lock().await;
+                let expected_etag = expected_etag.lock().unwrap().take();
+                self.complete_upload(&file_handle.full_key, &mut upload, expected_etag.as_deref())
+                    .await
+            }
+            FileHandleType::Read { request: _, object: _ } | FileHandleType::Decode { object: _ } => {
+                // TODO make sure we cancel the inflight PrefetchingGetRequest. is just dropping enough?
+                file_handle.inode.finish_reading()?;
+                Ok(())
+            }
+        }
+    }
 
-        match file_handle.typ {
-            FileHandleType::Write { parts, handle } => {
-                // TODO how do we make sure we didn't already handle this via `flush`?
-                let parts = parts.into_inner();
-                let size = parts.iter().map(|part| part.len()).sum::<usize>();
-                let stream = futures::stream::iter(parts);
-                let key = file_handle.full_key;
+    /// The control directory or file with the given inode number, if the control directory is
+    /// enabled
+    fn control_node(&self, ino: InodeNo) -> Option<ControlNode> {
+        if !self.config.enable_control_dir {
+            return None;
+        }
+        ControlNode::from_ino(ino)
+    }
 
-                let put = self
-                    .client
-                    .put_object(&self.bucket, &key, &PutObjectParams::default(), stream)
-                    .await;
-                let result = match put {
-                    Ok(_result) => {
-                        debug!(key, size, "put succeeded");
-                        Ok(())
-                    }
-                    Err(e) => {
-                        error!(key, size, "put failed, object was not uploaded: {e:?}");
-                        // This won't actually be seen by the user because `release` is async, but
-                        // it's the right thing to do.
-                        Err(libc::EIO)
+    /// The control directory or file that `name` refers to in `parent`, if any. Fails with `ENOENT`
+    /// for any other name in a control directory.
+    fn lookup_control_node(&self, parent: InodeNo, name: &OsStr) -> Result<Option<ControlNode>, libc::c_int> {
+        if !self.config.enable_control_dir {
+            return Ok(None);
+        }
+        if parent == FUSE_ROOT_INODE && name == CONTROL_DIR_NAME {
+            return Ok(Some(ControlNode::Dir(ControlDir::Root)));
+        }
+        match ControlNode::from_ino(parent) {
+            Some(ControlNode::Dir(dir)) => ControlNode::lookup(dir, name).map(Some).ok_or(libc::ENOENT),
+            Some(ControlNode::File(_)) => Err(libc::ENOTDIR),
+            None => Ok(None),
+        }
+    }
+
+    async fn make_control_attr(&self, node: ControlNode) -> FileAttr {
+        let (kind, perm, nlink, size) = match node {
+            ControlNode::Dir(_) => (FileType::Directory, 0o555, 2, 0),
+            ControlNode::File(file) if file.is_command() => (FileType::RegularFile, 0o200, 1, 0),
+            ControlNode::File(file) => (
+                FileType::RegularFile,
+                0o444,
+                1,
+                self.control_file_contents(file).await.len(),
+            ),
+        };
+        FileAttr {
+            ino: node.ino(),
+            size: size as u64,
+            blocks: 0,
+            atime: self.created_at,
+            mtime: self.created_at,
+            ctime: self.created_at,
+            crtime: UNIX_EPOCH,
+            kind,
+            perm,
+            nlink,
+            uid: self.config.uid,
+            gid: self.config.gid,
+            rdev: 0,
+            flags: 0,
+            blksize: 4096,
+        }
+    }
+
+    /// Open a file in the control directory. Command files can only be opened for writing, and
+    /// other files only for reading, in which case we take a snapshot of their contents.
+    async fn open_control_file(&self, node: ControlNode, flags: i32) -> Result<Opened, libc::c_int> {
+        let ControlNode::File(file) = node else {
+            return Err(libc::EISDIR);
+        };
+        let writing = flags & (libc::O_WRONLY | libc::O_RDWR) != 0;
+        if writing != file.is_command() {
+            return Err(libc::EACCES);
+        }
+
+        let contents = if writing {
+            String::new()
+        } else {
+            self.control_file_contents(file).await
+        };
+        let fh = self.next_handle();
+        self.control_handles
+            .write()
+            .await
+            .insert(fh, contents.into_bytes().into());
+        // The contents can change size after `getattr`, so don't let the kernel stop reading at
+        // the size it last saw
+        Ok(Opened {
+            fh,
+            flags: fuser::consts::FOPEN_DIRECT_IO,
+        })
+    }
+
+    async fn control_file_contents(&self, file: ControlFile) -> String {
+        match file {
+            ControlFile::Stats => {
+                let mut stats = format!(
+                    "open_files: {}\nopen_control_files: {}\nopen_dirs: {}\n",
+                    self.file_handles.read().await.len(),
+                    self.control_handles.read().await.len(),
+                    self.dir_handles.read().await.len(),
+                );
+                if let Some(source) = &self.config.client_metrics {
+                    format_client_metrics(&mut stats, &source.poll());
+                }
+                stats
+            }
+            ControlFile::Config => format!("{:#?}\n", self.config),
+            ControlFile::Version => format!("{}\n", build_info::FULL_VERSION),
+            ControlFile::FlushCaches | ControlFile::ForgetInodes => String::new(),
+        }
+    }
+
+    /// Run the command for a write to a command file in the control directory. What was written
+    /// doesn't matter.
+    async fn run_control_command(&self, file: ControlFile) -> Result<(), libc::c_int> {
+        match file {
+            ControlFile::FlushCaches => {
+                self.superblock.expire_stats();
+                self.dir_snapshots.clear();
+                // Drop each read handle's in-progress request and the data it prefetched, so the
+                // next read starts a new one
+                for handle in self.file_handles.read().await.values() {
+                    if let FileHandleType::Read { request, .. } = &handle.typ {
+                        *request.lock().await = None;
                     }
+                }
+                info!("flushed caches");
+                Ok(())
+            }
+            ControlFile::ForgetInodes => {
+                let forgotten = self.superblock.forget_inodes();
+                // Snapshots would list the forgotten inodes
+                self.dir_snapshots.clear();
+                info!(forgotten, "forgot inodes");
+                Ok(())
+            }
+            ControlFile::Stats | ControlFile::Config | ControlFile::Version => Err(libc::EBADF),
+        }
+    }
+
+    /// List a control directory, starting from the entry at `offset`
+    async fn readdir_control<R: DirectoryReplier>(
+        &self,
+        dir: ControlDir,
+        offset: i64,
+        mut reply: R,
+    ) -> Result<R, libc::c_int> {
+        let parent_attr = match dir.parent() {
+            Some(parent) => self.make_control_attr(ControlNode::Dir(parent)).await,
+            None => {
+                let root = self.superblock.getattr(&self.client, FUSE_ROOT_INODE).await?;
+                self.make_attr(&root)
+            }
+        };
+        let mut entries = vec![
+            (".", self.make_control_attr(ControlNode::Dir(dir)).await),
+            ("..", parent_attr),
+        ];
+        for node in dir.entries() {
+            entries.push((node.name(), self.make_control_attr(node).await));
+        }
+
+        for (i, (name, attr)) in entries.into_iter().enumerate().skip(offset as usize) {
+            if reply.add(attr.ino, i as i64 + 1, name, attr, 0u64, self.config.stat_ttl) {
+                break;
+            }
+        }
+        Ok(reply)
+    }
+
+    /// Parameters for uploading the object with the given key
+    fn put_params(&self, key: &str, upload_mode: UploadMode, size_hint: Option<u64>) -> PutObjectParams {
+        let storage_class = self.config.storage_class_for(key).map(str::to_owned);
+        PutObjectParams::new()
+            .upload_mode(upload_mode)
+            .storage_class(storage_class)
+            .object_size_hint(size_hint)
+    }
+
+    /// Upload the writes for a file handle, or finish its streaming upload, if that hasn't been
+    /// done already
+    async fn complete_upload(
+        &self,
+        key: &str,
+        upload: &mut UploadState<ConcurrencyLimitedClient<Client>>,
+        expected_etag: Option<&str>,
+    ) -> Result<(), libc::c_int> {
+        let (put, size, handle) = match std::mem::replace(upload, UploadState::Completed) {
+            UploadState::InProgress {
+                parts,
+                handle,
+                size_hint,
+            } => {
+                let size = parts.iter().map(|part| part.len()).sum::<usize>();
+
+                let expected_size = size_hint.unwrap_or(0).max(size as u64);
+                let upload_mode = if expected_size < self.config.multipart_threshold as u64 {
+                    UploadMode::SinglePart
+                } else {
+                    UploadMode::Multipart
                 };
+                let params = self.put_params(key, upload_mode, size_hint);
 
-                handle.finish_writing(size)?;
+                if self.config.dry_run_writes {
+                    info!(key, size, ?params, "dry run, not uploading object");
+                    handle.finish_writing(size)?;
+                    return Ok(());
+                }
 
-                result
+                let (bucket, object_key) = self.superblock.bucket_and_key(key);
+                let put = self.uploader.put_object(bucket, object_key, &params, &parts).await;
+                (put, size, handle)
             }
-            FileHandleType::Read { request: _, etag: _ } => {
-                // TODO make sure we cancel the inflight PrefetchingGetRequest. is just dropping enough?
-                file_handle.inode.finish_reading()?;
+            UploadState::Streaming { upload, handle } => {
+                let size = upload.bytes_written();
+                debug!(
+                    key,
+                    size,
+                    peak_buffered = upload.peak_buffered(),
+                    "finishing streaming upload"
+                );
+                (upload.finish().await, size, handle)
+            }
+            UploadState::Completed => return Ok(()),
+        };
+        let result = match put {
+            // If the client reports how much it uploaded, make sure that's what was written, so a
+            // bug that drops data doesn't go unnoticed
+            Ok(result) if matches!(result.size, Some(uploaded) if uploaded != size as u64) => {
+                error!(
+                    key,
+                    size,
+                    uploaded_size = ?result.size,
+                    "uploaded object size doesn't match the bytes written"
+                );
+                Err(libc::EIO)
+            }
+            Ok(result) if !etag_matches(expected_etag, result.etag.as_ref()) => {
+                error!(
+                    key,
+                    size,
+                    expected_etag,
+                    etag = ?result.etag,
+                    "uploaded object's ETag doesn't match the expected ETag"
+                );
+                Err(libc::EIO)
+            }
+            Ok(_result) => {
+                debug!(key, size, "put succeeded");
                 Ok(())
             }
-        }
+            Err(e) => {
+                error!(key, size, "put failed, object was not uploaded: {e:?}");
+                Err(libc::EIO)
+            }
+        };
+
+        handle.finish_writing(size)?;
+
+        result
     }
 }
 
+/// Whether an uploaded object's ETag is the expected one, if there is one. ETags are compared
+/// without their quotes, and ignoring case, since MD5s are often written in upper case.
+fn etag_matches(expected: Option<&str>, etag: Option<&ETag>) -> bool {
+    let Some(expected) = expected else {
+        return true;
+    };
+    let Some(etag) = etag else {
+        return false;
+    };
+    expected
+        .trim_matches('"')
+        .eq_ignore_ascii_case(etag.as_str().trim_matches('"'))
+}
+
 impl From<InodeError> for i32 {
     fn from(err: InodeError) -> Self {
         match err {
@@ -602,13 +2624,16 @@ impl From<InodeError> for i32 {
             InodeError::FileDoesNotExist => libc::ENOENT,
             InodeError::InodeDoesNotExist(_) => libc::ENOENT,
             InodeError::InvalidFileName(_) => libc::EINVAL,
+            InodeError::NameTooLong(_) => libc::ENAMETOOLONG,
             InodeError::NotADirectory(_) => libc::ENOTDIR,
             InodeError::ShadowedByDirectory(_, _) => libc::ENOENT,
+            InodeError::ShadowedByFile(_, _) => libc::ENOENT,
             InodeError::FileAlreadyExists(_) => libc::EEXIST,
             // Not obvious what these two cases should be -- EINVAL would also be reasonable, or
             // EROFS for not-writable -- but we'll treat it like a sealed file
             InodeError::InodeNotWritable(_) => libc::EPERM,
             InodeError::InodeNotReadableWhileWriting(_) => libc::EPERM,
+            InodeError::StaleInode(_) => libc::ESTALE,
         }
     }
 }
@@ -1,13 +1,20 @@
 use futures::executor::block_on;
 use futures::task::Spawn;
+use std::collections::{HashMap, VecDeque};
 use std::ffi::OsStr;
 use std::time::Duration;
 use tracing::{instrument, Instrument};
 
-use crate::fs::{DirectoryReplier, InodeNo, ReadReplier, S3Filesystem, S3FilesystemConfig};
+use crate::fs::{
+    AbortOrphanedUploadsError, DirectoryReplier, InodeNo, ReadReplier, S3Filesystem, S3FilesystemConfig,
+    VerifyPrefixError,
+};
 use crate::prefix::Prefix;
+use crate::sync::atomic::{AtomicBool, Ordering};
+use crate::sync::{Arc, Mutex};
 use fuser::{
-    FileAttr, Filesystem, KernelConfig, ReplyAttr, ReplyData, ReplyEmpty, ReplyEntry, ReplyOpen, ReplyWrite, Request,
+    FileAttr, Filesystem, KernelConfig, ReplyAttr, ReplyData, ReplyEmpty, ReplyEntry, ReplyIoctl, ReplyOpen,
+    ReplyWrite, Request,
 };
 use mountpoint_s3_client::ObjectClient;
 
@@ -17,6 +24,54 @@ pub mod session;
 /// so that we can test our actual filesystem implementation without having actual FUSE in the loop.
 pub struct S3FuseFilesystem<Client: ObjectClient, Runtime> {
     fs: S3Filesystem<Client, Runtime>,
+    interrupts: Mutex<Interrupts>,
+}
+
+/// Most interrupts to remember for requests that weren't in progress when they arrived
+const MAX_UNMATCHED_INTERRUPTS: usize = 64;
+
+/// The requests in progress that the kernel can interrupt, by their unique ids
+#[derive(Debug, Default)]
+struct Interrupts {
+    /// Flags to set if the kernel interrupts a request in progress. Only requests that can give up
+    /// early are here.
+    in_progress: HashMap<u64, Arc<AtomicBool>>,
+    /// Interrupts for requests that weren't in progress, most recent last. The kernel can send an
+    /// interrupt before the request it's for has started, so it's kept for when it does. Requests
+    /// that can't give up early, or that already finished, never start, so only a few are kept.
+    unmatched: VecDeque<u64>,
+}
+
+impl Interrupts {
+    /// Start a request, returning the flag that's set if it's interrupted. It's already set if the
+    /// interrupt arrived first.
+    fn start(&mut self, unique: u64) -> Arc<AtomicBool> {
+        let interrupted = match self.unmatched.iter().position(|u| *u == unique) {
+            Some(index) => {
+                self.unmatched.remove(index);
+                true
+            }
+            None => false,
+        };
+        let interrupted = Arc::new(AtomicBool::new(interrupted));
+        self.in_progress.insert(unique, Arc::clone(&interrupted));
+        interrupted
+    }
+
+    fn finish(&mut self, unique: u64) {
+        self.in_progress.remove(&unique);
+    }
+
+    fn interrupt(&mut self, unique: u64) {
+        if let Some(interrupted) = self.in_progress.get(&unique) {
+            interrupted.store(true, Ordering::SeqCst);
+        } else {
+            if self.unmatched.len() == MAX_UNMATCHED_INTERRUPTS {
+                self.unmatched.pop_front();
+            }
+            self.unmatched.push_back(unique);
+        }
+    }
 }
 
 impl<Client, Runtime> S3FuseFilesystem<Client, Runtime>
@@ -27,7 +82,28 @@ where
     pub fn new(client: Client, runtime: Runtime, bucket: &str, prefix: &Prefix, config: S3FilesystemConfig) -> Self {
         let fs = S3Filesystem::new(client, runtime, bucket, prefix, config);
 
-        Self { fs }
+        Self {
+            fs,
+            interrupts: Default::default(),
+        }
+    }
+
+    /// Run a request that can be interrupted, with a flag that's set if the kernel interrupts it
+    fn interruptible<T>(&self, req: &Request<'_>, f: impl FnOnce(&AtomicBool) -> T) -> T {
+        let interrupted = self.interrupts.lock().unwrap().start(req.unique());
+        let result = f(&interrupted);
+        self.interrupts.lock().unwrap().finish(req.unique());
+        result
+    }
+
+    /// See [S3Filesystem::verify_prefix]
+    pub async fn verify_prefix(&self) -> Result<(), VerifyPrefixError> {
+        self.fs.verify_prefix().await
+    }
+
+    /// See [S3Filesystem::abort_orphaned_uploads]
+    pub async fn abort_orphaned_uploads(&self) -> Result<usize, AbortOrphanedUploadsError> {
+        self.fs.abort_orphaned_uploads().await
     }
 }
 
@@ -49,6 +125,11 @@ where
         }
     }
 
+    #[instrument(level="debug", skip_all, fields(req=_req.unique(), unique=unique))]
+    fn interrupt(&self, _req: &Request<'_>, unique: u64) {
+        self.interrupts.lock().unwrap().interrupt(unique);
+    }
+
     #[instrument(level="debug", skip_all, fields(req=_req.unique(), ino=ino))]
     fn getattr(&self, _req: &Request<'_>, ino: InodeNo, reply: ReplyAttr) {
         match block_on(self.fs.getattr(ino).in_current_span()) {
@@ -59,7 +140,7 @@ where
 
     #[instrument(level="debug", skip_all, fields(req=_req.unique(), ino=ino))]
     fn open(&self, _req: &Request<'_>, ino: InodeNo, flags: i32, reply: ReplyOpen) {
-        match block_on(self.fs.open(ino, flags).in_current_span()) {
+        match block_on(self.fs.open(ino, flags, _req.pid()).in_current_span()) {
             Ok(opened) => reply.opened(opened.fh, opened.flags),
             Err(e) => reply.error(e),
         }
@@ -123,8 +204,8 @@ where
         }
     }
 
-    #[instrument(level="debug", skip_all, fields(req=_req.unique(), ino=parent, fh=fh, offset=offset))]
-    fn readdir(&self, _req: &Request<'_>, parent: InodeNo, fh: u64, offset: i64, mut reply: fuser::ReplyDirectory) {
+    #[instrument(level="debug", skip_all, fields(req=req.unique(), ino=parent, fh=fh, offset=offset))]
+    fn readdir(&self, req: &Request<'_>, parent: InodeNo, fh: u64, offset: i64, mut reply: fuser::ReplyDirectory) {
         struct ReplyDirectory<'a> {
             inner: &'a mut fuser::ReplyDirectory,
         }
@@ -145,16 +226,23 @@ where
 
         let replier = ReplyDirectory { inner: &mut reply };
 
-        match block_on(self.fs.readdir(parent, fh, offset, replier).in_current_span()) {
+        let result = self.interruptible(req, |interrupted| {
+            block_on(
+                self.fs
+                    .readdir_interruptible(parent, fh, offset, replier, interrupted)
+                    .in_current_span(),
+            )
+        });
+        match result {
             Ok(_) => reply.ok(),
             Err(e) => reply.error(e),
         }
     }
 
-    #[instrument(level="debug", skip_all, fields(req=_req.unique(), ino=parent, fh=fh, offset=offset))]
+    #[instrument(level="debug", skip_all, fields(req=req.unique(), ino=parent, fh=fh, offset=offset))]
     fn readdirplus(
         &self,
-        _req: &Request<'_>,
+        req: &Request<'_>,
         parent: InodeNo,
         fh: u64,
         offset: i64,
@@ -180,12 +268,27 @@ where
 
         let replier = ReplyDirectoryPlus { inner: &mut reply };
 
-        match block_on(self.fs.readdir(parent, fh, offset, replier).in_current_span()) {
+        let result = self.interruptible(req, |interrupted| {
+            block_on(
+                self.fs
+                    .readdir_interruptible(parent, fh, offset, replier, interrupted)
+                    .in_current_span(),
+            )
+        });
+        match result {
             Ok(_) => reply.ok(),
             Err(e) => reply.error(e),
         }
     }
 
+    #[instrument(level="debug", skip_all, fields(req=_req.unique(), ino=ino, fh=fh))]
+    fn flush(&self, _req: &Request<'_>, ino: InodeNo, fh: u64, lock_owner: u64, reply: ReplyEmpty) {
+        match block_on(self.fs.flush(ino, fh, lock_owner, _req.pid()).in_current_span()) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(e),
+        }
+    }
+
     #[instrument(level="debug", skip_all, fields(req=_req.unique(), ino=ino, fh=fh))]
     fn release(
         &self,
@@ -203,6 +306,24 @@ where
         }
     }
 
+    #[instrument(level="debug", skip_all, fields(req=_req.unique(), ino=ino, fh=fh, cmd=cmd))]
+    fn ioctl(
+        &self,
+        _req: &Request<'_>,
+        ino: InodeNo,
+        fh: u64,
+        flags: u32,
+        cmd: u32,
+        in_data: &[u8],
+        out_size: u32,
+        reply: ReplyIoctl,
+    ) {
+        match block_on(self.fs.ioctl(ino, fh, flags, cmd, in_data, out_size).in_current_span()) {
+            Ok(data) => reply.ioctl(0, &data),
+            Err(e) => reply.error(e),
+        }
+    }
+
     #[instrument(level="debug", skip_all, fields(req=_req.unique(), parent=parent, name=?name))]
     fn mknod(
         &self,
@@ -256,4 +377,121 @@ where
             Err(e) => reply.error(e),
         }
     }
+
+    #[instrument(level="debug", skip_all, fields(req=_req.unique(), parent=parent, name=?name, newparent=newparent, newname=?newname))]
+    fn rename(
+        &self,
+        _req: &Request<'_>,
+        parent: InodeNo,
+        name: &OsStr,
+        newparent: InodeNo,
+        newname: &OsStr,
+        flags: u32,
+        reply: ReplyEmpty,
+    ) {
+        match block_on(
+            self.fs
+                .rename(parent, name, newparent, newname, flags)
+                .in_current_span(),
+        ) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(e),
+        }
+    }
+
+    #[instrument(level="debug", skip_all, fields(req=_req.unique(), ino=ino, fh=fh, offset=offset, length=length))]
+    fn fallocate(
+        &self,
+        _req: &Request<'_>,
+        ino: InodeNo,
+        fh: u64,
+        offset: i64,
+        length: i64,
+        mode: i32,
+        reply: ReplyEmpty,
+    ) {
+        match block_on(self.fs.fallocate(ino, fh, offset, length, mode).in_current_span()) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(e),
+        }
+    }
+
+    #[instrument(level="debug", skip_all, fields(req=_req.unique(), ino=ino, name=?name))]
+    fn setxattr(
+        &self,
+        _req: &Request<'_>,
+        ino: InodeNo,
+        name: &OsStr,
+        value: &[u8],
+        flags: i32,
+        position: u32,
+        reply: ReplyEmpty,
+    ) {
+        match block_on(self.fs.setxattr(ino, name, value, flags, position).in_current_span()) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(e),
+        }
+    }
+
+    #[instrument(level="debug", skip_all, fields(req=_req.unique(), ino_in=ino_in, fh_in=fh_in, ino_out=ino_out, fh_out=fh_out, len=len))]
+    fn copy_file_range(
+        &self,
+        _req: &Request<'_>,
+        ino_in: InodeNo,
+        fh_in: u64,
+        offset_in: i64,
+        ino_out: InodeNo,
+        fh_out: u64,
+        offset_out: i64,
+        len: u64,
+        flags: u32,
+        reply: ReplyWrite,
+    ) {
+        match block_on(
+            self.fs
+                .copy_file_range(ino_in, fh_in, offset_in, ino_out, fh_out, offset_out, len, flags)
+                .in_current_span(),
+        ) {
+            Ok(bytes_written) => reply.written(bytes_written),
+            Err(e) => reply.error(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interrupt_in_progress() {
+        let mut interrupts = Interrupts::default();
+        let interrupted = interrupts.start(1);
+        assert!(!interrupted.load(Ordering::SeqCst));
+        interrupts.interrupt(1);
+        assert!(interrupted.load(Ordering::SeqCst));
+        interrupts.finish(1);
+        assert!(interrupts.in_progress.is_empty());
+        assert!(interrupts.unmatched.is_empty());
+    }
+
+    #[test]
+    fn interrupt_before_start() {
+        let mut interrupts = Interrupts::default();
+        interrupts.interrupt(1);
+        assert!(interrupts.start(1).load(Ordering::SeqCst));
+        assert!(!interrupts.start(2).load(Ordering::SeqCst));
+        assert!(interrupts.unmatched.is_empty());
+    }
+
+    #[test]
+    fn unmatched_interrupts_are_bounded() {
+        let mut interrupts = Interrupts::default();
+        let count = 2 * MAX_UNMATCHED_INTERRUPTS as u64;
+        for unique in 0..count {
+            interrupts.interrupt(unique);
+        }
+        assert_eq!(interrupts.unmatched.len(), MAX_UNMATCHED_INTERRUPTS);
+        assert!(!interrupts.start(0).load(Ordering::SeqCst));
+        assert!(interrupts.start(count - 1).load(Ordering::SeqCst));
+    }
 }
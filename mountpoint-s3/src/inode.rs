@@ -22,12 +22,17 @@
 
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::ffi::{OsStr, OsString};
+use std::ops::Range;
 use std::os::unix::prelude::OsStrExt;
-use std::time::Instant;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
 use fuser::FileType;
-use futures::{select_biased, FutureExt};
-use mountpoint_s3_client::{HeadObjectError, HeadObjectResult, ObjectClient, ObjectClientError};
+use futures::{pin_mut, select_biased, FutureExt};
+use mountpoint_s3_client::{
+    CacheValidators, ETag, GetObjectError, HeadObjectError, HeadObjectResult, HeadObjectViaGetError, ListObjectsError,
+    ObjectClient, ObjectClientError, ObjectClientExt, ObjectVersion,
+};
 use thiserror::Error;
 use time::OffsetDateTime;
 use tracing::{error, trace, warn};
@@ -40,6 +45,144 @@ pub type InodeNo = u64;
 
 pub const ROOT_INODE_NO: InodeNo = 1;
 
+/// Separator between a file name and a version id in the synthetic names used to access historical
+/// versions of an object, like `file.txt@v=<version id>`.
+pub const VERSION_SUFFIX_SEPARATOR: &str = "@v=";
+
+/// Separator between a file name and a byte range in the synthetic names used to read part of an
+/// object, like `file.txt@range=1000-1999`.
+pub const RANGE_SUFFIX_SEPARATOR: &str = "@range=";
+
+/// Suffix appended to the name of a file that shares its name with a directory, when using
+/// [ShadowPolicy::Suffix]. For example, if the keys `a` and `a/b` both exist, the object `a` is
+/// visible as the file `a@file`.
+pub const SHADOWED_FILE_SUFFIX: &str = "@file";
+
+/// How to present a name that is both an object key and a directory prefix, like `a` when the keys
+/// `a` and `a/b` both exist. S3 allows this, but a POSIX file system can't have both a file and a
+/// directory with the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShadowPolicy {
+    /// Show the name as a directory, hiding the object
+    #[default]
+    PreferDirectory,
+    /// Show the name as a file, hiding everything under the directory prefix
+    PreferFile,
+    /// Show the name as a directory, and the object as a file with [SHADOWED_FILE_SUFFIX] appended
+    /// to its name. Keys and prefixes whose names already end with the suffix are hidden.
+    Suffix,
+}
+
+/// Whether names that differ only by case, like `Foo.txt` and `foo.txt`, are different files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CaseSensitivity {
+    /// Names that differ by case are different files, just like keys that differ by case are
+    /// different objects
+    #[default]
+    Sensitive,
+    /// Looking up a name finds the entry whose name is equal to it ignoring case. If there are
+    /// several, the first in key order wins, and a warning is logged. Every lookup has to list the
+    /// directory's entries that could match. New files keep the name they're created with, and
+    /// `readdir` still lists every entry under its own name.
+    Insensitive,
+}
+
+/// Name of the file that shows the contents of an object whose key ends in `/` inside the
+/// directory with that key, when using [InvalidKeyHandling::Sanitize]. For example, if the key `a/`
+/// isn't empty, its contents are shown as the file `a/@dir`.
+pub const DIRECTORY_OBJECT_NAME: &str = "@dir";
+
+/// How to present objects whose keys end in `/`, like `a/`. These can't be shown under their own
+/// name, which is also the name of the directory `a`. Empty objects like this are treated as
+/// directory markers (the S3 Console creates them for new folders) and are never shown as files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InvalidKeyHandling {
+    /// Hide objects whose keys end in `/`. The directory itself is still shown.
+    #[default]
+    Hide,
+    /// Show objects whose keys end in `/` and that aren't empty as a file named
+    /// [DIRECTORY_OBJECT_NAME] inside the directory. Keys and prefixes that are already named
+    /// [DIRECTORY_OBJECT_NAME] are hidden.
+    Sanitize,
+}
+
+/// Default for the longest name of a file or directory, in bytes, matching the limit of most Linux
+/// file systems (`NAME_MAX`)
+pub const DEFAULT_MAX_NAME_LENGTH: usize = 255;
+
+/// Configuration for a [Superblock]
+#[derive(Debug, Clone)]
+pub struct SuperblockConfig {
+    /// How to present names that are both an object and a directory
+    pub shadow_policy: ShadowPolicy,
+    /// How to present objects whose keys end in `/`, and names longer than `max_name_length`
+    pub invalid_key_handling: InvalidKeyHandling,
+    /// Whether lookups of names that differ only by case find the same entry
+    pub case_sensitivity: CaseSensitivity,
+    /// Longest name of a file or directory, in bytes. Keys with a longer component are never
+    /// listed. Looking them up fails with `ENOENT` when using [InvalidKeyHandling::Hide], or
+    /// `ENAMETOOLONG` when using [InvalidKeyHandling::Sanitize], and creating them always fails with
+    /// `ENAMETOOLONG`.
+    pub max_name_length: usize,
+    /// Time to give the root directory, if not the time of the mount. See
+    /// [Superblock::set_root_mtime].
+    pub root_mtime: Option<OffsetDateTime>,
+    /// How long the entries listed by a `readdir` can answer `lookup`s of the same names without
+    /// another request to S3, or `None` to always look names up in S3.
+    pub readdir_lookup_ttl: Option<Duration>,
+    /// Pin the file system to the bucket's contents at this time, or `None` to show its current
+    /// contents. Looking up a file resolves it to the version of its object that was current at
+    /// that time, which is read-only, and new files can't be created. Directories and `readdir`
+    /// still reflect the bucket's current contents.
+    pub pinned_as_of: Option<OffsetDateTime>,
+    /// Present the buckets the account owns as directories under the root, rather than the
+    /// contents of a single bucket. The first component of every full key is then the name of the
+    /// object's bucket, see [Superblock::bucket_and_key].
+    pub browse_buckets: bool,
+    /// If HeadObject requests are denied, get the metadata of objects being looked up with a
+    /// GetObject request for their first byte instead, for credentials that are allowed to read
+    /// objects but not to make HeadObject requests. Only the size, ETag, and last-modified time of
+    /// objects are available that way.
+    pub head_object_fallback: bool,
+}
+
+impl Default for SuperblockConfig {
+    fn default() -> Self {
+        Self {
+            shadow_policy: Default::default(),
+            invalid_key_handling: Default::default(),
+            case_sensitivity: Default::default(),
+            max_name_length: DEFAULT_MAX_NAME_LENGTH,
+            root_mtime: None,
+            readdir_lookup_ttl: None,
+            pinned_as_of: None,
+            browse_buckets: false,
+            head_object_fallback: false,
+        }
+    }
+}
+
+/// Split a synthetic versioned name like `file.txt@v=<version id>` into the file name and version
+/// id, or return `None` if the name doesn't refer to a specific version.
+pub fn parse_versioned_name(name: &str) -> Option<(&str, &str)> {
+    let (name, version_id) = name.rsplit_once(VERSION_SUFFIX_SEPARATOR)?;
+    (!name.is_empty() && !version_id.is_empty()).then_some((name, version_id))
+}
+
+/// Split a synthetic ranged name like `file.txt@range=<first>-<last>` into the file name and the
+/// range of bytes it refers to, or return `None` if the name doesn't refer to a range. As in an
+/// HTTP `Range` header, the last byte is included in the range.
+pub fn parse_ranged_name(name: &str) -> Option<(&str, Range<u64>)> {
+    let (name, range) = name.rsplit_once(RANGE_SUFFIX_SEPARATOR)?;
+    let (first, last) = range.split_once('-')?;
+    let first: u64 = first.parse().ok()?;
+    let last: u64 = last.parse().ok()?;
+    if name.is_empty() || first > last {
+        return None;
+    }
+    Some((name, first..last.checked_add(1)?))
+}
+
 pub fn valid_inode_name<T: AsRef<OsStr>>(name: T) -> bool {
     let name = name.as_ref();
     // Names cannot be empty
@@ -65,11 +208,12 @@ struct SuperblockInner {
     inodes: RwLock<HashMap<InodeNo, Inode>>,
     next_ino: AtomicU64,
     mount_time: OffsetDateTime,
+    config: SuperblockConfig,
 }
 
 impl Superblock {
     /// Create a new Superblock that targets the given bucket/prefix
-    pub fn new(bucket: &str, prefix: &Prefix) -> Self {
+    pub fn new(bucket: &str, prefix: &Prefix, config: SuperblockConfig) -> Self {
         let mount_time = OffsetDateTime::now_utc();
         let root = InodeInner {
             ino: ROOT_INODE_NO,
@@ -77,10 +221,13 @@ impl Superblock {
             name: String::new(),
             full_key: prefix.to_string(),
             kind: InodeKind::Directory,
+            version_id: None,
+            range: None,
             sync: RwLock::new(InodeState {
-                stat: InodeStat::for_directory(mount_time, Instant::now()), // TODO expiry
+                stat: InodeStat::for_directory(config.root_mtime.unwrap_or(mount_time), Instant::now()), // TODO expiry
                 write_status: WriteStatus::Remote,
                 kind_data: InodeKindData::default_for(InodeKind::Directory),
+                stale: false,
             }),
         };
         let root = Inode { inner: Arc::new(root) };
@@ -93,12 +240,73 @@ impl Superblock {
             inodes: RwLock::new(inodes),
             next_ino: AtomicU64::new(2),
             mount_time,
+            config,
         };
         Self { inner: Arc::new(inner) }
     }
 
+    /// Set the times of the root directory, for example to the last modified time of a directory
+    /// marker object for the mount's prefix
+    pub fn set_root_mtime(&self, mtime: OffsetDateTime) {
+        let root = self.inner.get(ROOT_INODE_NO).expect("root inode always exists");
+        let mut state = root.inner.sync.write().unwrap();
+        state.stat.atime = mtime;
+        state.stat.ctime = mtime;
+        state.stat.mtime = mtime;
+    }
+
+    /// Expire the cached stat of every inode, so that nothing is answered from a cached listing
+    pub fn expire_stats(&self) {
+        let now = Instant::now();
+        let inodes: Vec<Inode> = self.inner.inodes.read().unwrap().values().cloned().collect();
+        for inode in inodes {
+            let mut state = inode.inner.sync.write().unwrap();
+            state.stat.expiry = state.stat.expiry.min(now);
+        }
+    }
+
+    /// Drop every inode from the superblock except the root and inodes being written, along with
+    /// their ancestors, returning how many were dropped. Later lookups create new inodes for the
+    /// same names. Handles that are already open keep working, but the kernel gets `ENOENT` for
+    /// forgotten inode numbers until it looks their names up again.
+    pub fn forget_inodes(&self) -> usize {
+        let inodes: Vec<Inode> = self.inner.inodes.read().unwrap().values().cloned().collect();
+        let parents: HashMap<InodeNo, InodeNo> = inodes.iter().map(|inode| (inode.ino(), inode.parent())).collect();
+
+        let mut keep = HashSet::from([ROOT_INODE_NO]);
+        for inode in &inodes {
+            if inode.inner.sync.read().unwrap().write_status == WriteStatus::Remote {
+                continue;
+            }
+            let mut ino = inode.ino();
+            while keep.insert(ino) {
+                match parents.get(&ino) {
+                    Some(parent) => ino = *parent,
+                    None => break,
+                }
+            }
+        }
+
+        // Only hold one lock at a time, so we can't deadlock with a lookup that holds a directory's
+        // lock while it adds an inode. Inodes added since we took the snapshot are left alone.
+        let forget: HashSet<InodeNo> = parents.into_keys().filter(|ino| !keep.contains(ino)).collect();
+        for inode in inodes.iter().filter(|inode| keep.contains(&inode.ino())) {
+            if let InodeKindData::Directory { children, .. } = &mut inode.inner.sync.write().unwrap().kind_data {
+                children.retain(|_, child| !forget.contains(&child.ino()));
+            }
+        }
+        let mut inodes = self.inner.inodes.write().unwrap();
+        inodes.retain(|ino, _| !forget.contains(ino));
+        forget.len()
+    }
+
+    /// The bucket and key to make requests for the object with the given full key with
+    pub fn bucket_and_key<'a>(&'a self, full_key: &'a str) -> (&'a str, &'a str) {
+        self.inner.bucket_and_key(full_key)
+    }
+
     /// Lookup an inode in the parent directory with the given name
-    pub async fn lookup<OC: ObjectClient>(
+    pub async fn lookup<OC: ObjectClient + Sync>(
         &self,
         client: &OC,
         parent_ino: InodeNo,
@@ -110,21 +318,300 @@ impl Superblock {
             .to_str()
             .ok_or_else(|| InodeError::InvalidFileName(name.to_owned()))?;
 
+        // `.` and `..` aren't entries of their own, but resolve to the directory and its parent. The
+        // root is its own parent.
+        if name == "." || name == ".." {
+            let dir = self.inner.get(parent_ino)?;
+            if dir.kind() != InodeKind::Directory {
+                return Err(InodeError::NotADirectory(parent_ino));
+            }
+            let ino = if name == "." { dir.ino() } else { dir.parent() };
+            return self.getattr(client, ino).await;
+        }
+
         // This should be impossible, but just to be safe, explicitly reject lookups to files that
         // end with '/', since they could be shadowed by directories.
         if name.ends_with('/') {
             return Err(InodeError::InvalidFileName(name.into()));
         }
+        self.inner.check_name_length(name)?;
+
+        let resolved;
+        let name = match self.inner.config.case_sensitivity {
+            CaseSensitivity::Sensitive => name,
+            CaseSensitivity::Insensitive => {
+                resolved = self.resolve_case(client, parent_ino, name).await?;
+                resolved.as_str()
+            }
+        };
+
+        if let Some(as_of) = self.inner.config.pinned_as_of {
+            return self.lookup_as_of(client, parent_ino, name, as_of).await;
+        }
+
+        if let Some(lookup) = self.inner.cached_lookup(parent_ino, name)? {
+            trace!(parent=?parent_ino, ?name, ino=?lookup.inode.ino(), "lookup answered from readdir");
+            return Ok(lookup);
+        }
 
-        // TODO use caches. if we already know about this name, we just need to revalidate the stat
-        // cache and then read it.
         let remote = self.remote_lookup(client, parent_ino, name).await?;
         self.inner.update_from_remote(parent_ino, name, remote)
     }
 
+    /// The name of the entry in the parent directory that a case-insensitive lookup of `name` finds:
+    /// the first name in key order that's equal to `name` ignoring case, or `name` itself if there
+    /// isn't one. Files being written count as entries too.
+    async fn resolve_case<OC: ObjectClient>(
+        &self,
+        client: &OC,
+        parent_ino: InodeNo,
+        name: &str,
+    ) -> Result<String, InodeError> {
+        let parent = self.inner.get(parent_ino)?;
+        if parent.kind() != InodeKind::Directory {
+            return Err(InodeError::NotADirectory(parent_ino));
+        }
+        // Bucket names are always lowercase
+        if self.inner.config.browse_buckets && parent_ino == ROOT_INODE_NO {
+            return Ok(name.to_owned());
+        }
+
+        // A suffixed name refers to the object shadowed by the directory with the unsuffixed name,
+        // so it's the unsuffixed name that needs resolving
+        let (name, suffix) = match name.strip_suffix(SHADOWED_FILE_SUFFIX) {
+            Some(name) if self.inner.config.shadow_policy == ShadowPolicy::Suffix => (name, SHADOWED_FILE_SUFFIX),
+            _ => (name, ""),
+        };
+        let folded = name.to_lowercase();
+
+        let mut candidates: Vec<String> = {
+            let parent_state = parent.inner.sync.read().unwrap();
+            match &parent_state.kind_data {
+                InodeKindData::Directory {
+                    children,
+                    writing_children,
+                } => children
+                    .iter()
+                    .filter(|(child_name, child)| {
+                        writing_children.contains(&child.ino()) && child_name.to_lowercase() == folded
+                    })
+                    .map(|(child_name, _)| child_name.clone())
+                    .collect(),
+                InodeKindData::File {} => Vec::new(),
+            }
+        };
+
+        // Only list the keys that could match. Everything up to the first character of the name
+        // that has a case is the same in every match.
+        let invariant_len = name
+            .char_indices()
+            .find(|(_, c)| !c.to_lowercase().eq(c.to_uppercase()))
+            .map_or(name.len(), |(i, _)| i);
+        let mut full_prefix = parent.full_key().to_owned();
+        full_prefix.push_str(&name[..invariant_len]);
+        let (bucket, prefix) = self.inner.bucket_and_key(&full_prefix);
+        let dir_len = prefix.len() - invariant_len;
+
+        let mut continuation_token = None;
+        loop {
+            let result = client
+                .list_objects(bucket, continuation_token.as_deref(), "/", 1000, prefix)
+                .await
+                .map_err(|e| InodeError::ClientError(e.into()))?;
+            let objects = result.objects.iter().map(|object| &object.key[dir_len..]);
+            let prefixes = result
+                .common_prefixes
+                .iter()
+                .map(|prefix| prefix[dir_len..].trim_end_matches('/'));
+            candidates.extend(
+                objects
+                    .chain(prefixes)
+                    .filter(|candidate| candidate.to_lowercase() == folded)
+                    .map(str::to_owned),
+            );
+            continuation_token = result.next_continuation_token;
+            if continuation_token.is_none() {
+                break;
+            }
+        }
+
+        candidates.sort();
+        candidates.dedup();
+        let Some(winner) = candidates.first() else {
+            return Ok(format!("{name}{suffix}"));
+        };
+        if candidates.len() > 1 {
+            warn!(
+                parent=?parent_ino,
+                ?candidates,
+                ?winner,
+                "names differ only by case, lookups find the first of them"
+            );
+        }
+        Ok(format!("{winner}{suffix}"))
+    }
+
+    /// Lookup a specific version of the file with the given name in the parent directory. The
+    /// returned inode is named by its synthetic versioned name (see [parse_versioned_name]), is
+    /// read-only, and always reads the given version of the object. It won't be returned by
+    /// `readdir`.
+    pub async fn lookup_version<OC: ObjectClient + Sync>(
+        &self,
+        client: &OC,
+        parent_ino: InodeNo,
+        name: &str,
+        version_id: &str,
+    ) -> Result<LookedUp, InodeError> {
+        trace!(parent=?parent_ino, ?name, ?version_id, "lookup_version");
+
+        if !valid_inode_name(name) {
+            return Err(InodeError::InvalidFileName(name.into()));
+        }
+        self.inner.check_name_length(name)?;
+
+        let parent = self.inner.get(parent_ino)?;
+        if parent.kind() != InodeKind::Directory {
+            return Err(InodeError::NotADirectory(parent_ino));
+        }
+        let mut full_key = parent.full_key().to_owned();
+        assert!(full_key.is_empty() || full_key.ends_with('/'));
+        full_key.push_str(name);
+
+        let (bucket, key) = self.inner.bucket_and_key(&full_key);
+        let object = match self.inner.head_object(client, bucket, key, Some(version_id)).await? {
+            Some(HeadObjectResult { object, .. }) => object,
+            None => return Err(InodeError::FileDoesNotExist),
+        };
+        let stat = InodeStat::for_file(
+            object.size as usize,
+            object.last_modified,
+            Instant::now(),
+            Some(object.cache_validators()),
+        );
+
+        self.inner.insert_version(&parent, name, version_id, full_key, stat)
+    }
+
+    /// Lookup a range of bytes of the file with the given name in the parent directory. The returned
+    /// inode is named by its synthetic ranged name (see [parse_ranged_name]), is read-only, and its
+    /// contents are the given range of the object's contents, truncated to the end of the object.
+    /// It won't be returned by `readdir`.
+    pub async fn lookup_range<OC: ObjectClient + Sync>(
+        &self,
+        client: &OC,
+        parent_ino: InodeNo,
+        name: &str,
+        range: Range<u64>,
+    ) -> Result<LookedUp, InodeError> {
+        trace!(parent=?parent_ino, ?name, ?range, "lookup_range");
+
+        if !valid_inode_name(name) {
+            return Err(InodeError::InvalidFileName(name.into()));
+        }
+        self.inner.check_name_length(name)?;
+
+        let parent = self.inner.get(parent_ino)?;
+        if parent.kind() != InodeKind::Directory {
+            return Err(InodeError::NotADirectory(parent_ino));
+        }
+        let mut full_key = parent.full_key().to_owned();
+        assert!(full_key.is_empty() || full_key.ends_with('/'));
+        full_key.push_str(name);
+
+        let (bucket, key) = self.inner.bucket_and_key(&full_key);
+        let object = match self.inner.head_object(client, bucket, key, None).await? {
+            Some(HeadObjectResult { object, .. }) => object,
+            None => return Err(InodeError::FileDoesNotExist),
+        };
+        let end = range.end.min(object.size);
+        let start = range.start.min(end);
+        let stat = InodeStat::for_file(
+            (end - start) as usize,
+            object.last_modified,
+            Instant::now(),
+            Some(object.cache_validators()),
+        );
+
+        self.inner
+            .insert_range(&parent, name, range, start..end, full_key, stat)
+    }
+
+    /// Lookup the file with the given name as it was at `as_of`, using the version of its object
+    /// that was current at that time. Names that weren't files then can still be directories.
+    async fn lookup_as_of<OC: ObjectClient + Sync>(
+        &self,
+        client: &OC,
+        parent_ino: InodeNo,
+        name: &str,
+        as_of: OffsetDateTime,
+    ) -> Result<LookedUp, InodeError> {
+        let parent = self.inner.get(parent_ino)?;
+        if parent.kind() != InodeKind::Directory {
+            return Err(InodeError::NotADirectory(parent_ino));
+        }
+        let mut full_key = parent.full_key().to_owned();
+        assert!(full_key.is_empty() || full_key.ends_with('/'));
+        full_key.push_str(name);
+
+        if let Some(version) = self.version_as_of(client, &full_key, as_of).await? {
+            trace!(parent=?parent_ino, ?name, version_id=?version.version_id, "lookup pinned to version");
+            let validators = version.etag.as_deref().map(|etag| CacheValidators {
+                etag: ETag::from_str(etag).expect("parsing an ETag is infallible"),
+                last_modified: version.last_modified,
+            });
+            let stat = InodeStat::for_file(version.size as usize, version.last_modified, Instant::now(), validators);
+            return self
+                .inner
+                .insert_version(&parent, name, &version.version_id, full_key, stat);
+        }
+
+        match self.remote_lookup(client, parent_ino, name).await? {
+            Some(remote) if remote.kind == InodeKind::Directory => {
+                self.inner.update_from_remote(parent_ino, name, Some(remote))
+            }
+            _ => Err(InodeError::FileDoesNotExist),
+        }
+    }
+
+    /// Find the version of the object with the given key that was current at `as_of`, or `None`
+    /// if there was no such object at that time
+    async fn version_as_of<OC: ObjectClient>(
+        &self,
+        client: &OC,
+        key: &str,
+        as_of: OffsetDateTime,
+    ) -> Result<Option<ObjectVersion>, InodeError> {
+        let (bucket, key) = self.inner.bucket_and_key(key);
+        let mut key_marker = None;
+        let mut version_id_marker = None;
+        loop {
+            let result = client
+                .list_object_versions(bucket, key, key_marker.as_deref(), version_id_marker.as_deref(), 1000)
+                .await
+                .map_err(|e| InodeError::ClientError(e.into()))?;
+
+            // Versions are listed in key order and newest first for each key. The key itself sorts
+            // before every other key it's a prefix of, so its versions come first.
+            for version in result.versions {
+                if version.key != key {
+                    return Ok(None);
+                }
+                if version.last_modified <= as_of {
+                    return Ok((!version.is_delete_marker).then_some(version));
+                }
+            }
+
+            if result.next_key_marker.as_deref() != Some(key) {
+                return Ok(None);
+            }
+            key_marker = result.next_key_marker;
+            version_id_marker = result.next_version_id_marker;
+        }
+    }
+
     /// Lookup an inode in the parent directory with the given name
     /// on the remote client.
-    async fn remote_lookup<OC: ObjectClient>(
+    async fn remote_lookup<OC: ObjectClient + Sync>(
         &self,
         client: &OC,
         parent_ino: InodeNo,
@@ -134,6 +621,26 @@ impl Superblock {
         if parent.kind() != InodeKind::Directory {
             return Err(InodeError::NotADirectory(parent_ino));
         }
+
+        if self.inner.config.browse_buckets && parent_ino == ROOT_INODE_NO {
+            return self.remote_lookup_bucket(client, name).await;
+        }
+
+        if self.inner.config.invalid_key_handling == InvalidKeyHandling::Sanitize && name == DIRECTORY_OBJECT_NAME {
+            return self.remote_lookup_directory_object(client, &parent).await;
+        }
+
+        // With the suffix shadow policy, a suffixed name refers to the object that's shadowed by the
+        // directory with the unsuffixed name, so only exists if both of them do.
+        let shadow_policy = self.inner.config.shadow_policy;
+        let (name, is_shadowed_file) = match shadow_policy {
+            ShadowPolicy::Suffix => match name.strip_suffix(SHADOWED_FILE_SUFFIX) {
+                Some(name) => (name, true),
+                None => (name, false),
+            },
+            ShadowPolicy::PreferDirectory | ShadowPolicy::PreferFile => (name, false),
+        };
+
         let mut full_path = parent.full_key().to_owned();
         assert!(full_path.is_empty() || full_path.ends_with('/'));
         full_path.push_str(name);
@@ -141,6 +648,10 @@ impl Superblock {
         let mut full_path_suffixed = full_path.clone();
         full_path_suffixed.push('/');
 
+        // The keys to make requests for, which differ from the full paths when browsing buckets
+        let (bucket, key) = self.inner.bucket_and_key(&full_path);
+        let (_, key_suffixed) = self.inner.bucket_and_key(&full_path_suffixed);
+
         // We need to try two requests here, one to find an object with the given name, and one to
         // discover a possible shadowing (implicit) directory with the same name. There's a few
         // different cases we need to consider here:
@@ -165,25 +676,25 @@ impl Superblock {
         //       "/" to the prefix in the request, the first common prefix we'll get back will be
         //       "dir-1/", because that precedes "dir/" in lexicographic order. Doing the
         //       ListObjects with "/" appended makes sure we always observe the correct prefix.
-        let mut file_lookup = client.head_object(&self.inner.bucket, &full_path).fuse();
-        let mut dir_lookup = client
-            .list_objects(&self.inner.bucket, None, "/", 1, &full_path_suffixed)
-            .fuse();
+        // Which of the file or directory wins in cases (1) and (2) is decided by the [ShadowPolicy].
+        let file_lookup = self.inner.head_object(client, bucket, key, None).fuse();
+        pin_mut!(file_lookup);
+        let mut dir_lookup = client.list_objects(bucket, None, "/", 1, key_suffixed).fuse();
 
         let mut file_state = None;
+        let mut dir_state = None;
 
         for _ in 0..2 {
             select_biased! {
                 result = file_lookup => {
-                    match result {
-                        Ok(HeadObjectResult { object, .. }) => {
-                            let last_modified = object.last_modified;
-                            let stat = InodeStat::for_file(object.size as usize, last_modified, Instant::now(), Some(object.etag.clone()));
+                    match result? {
+                        Some(result) => {
+                            let object = &result.object;
+                            let stat = InodeStat::for_file(object.size as usize, object.last_modified, Instant::now(), Some(result.cache_validators()));
                             file_state = Some(stat);
                         }
                         // If the object is not found, might be a directory, so keep going
-                        Err(ObjectClientError::ServiceError(HeadObjectError::NotFound)) => {},
-                        Err(e) => return Err(InodeError::ClientError(e.into())),
+                        None => {},
                     }
                 }
 
@@ -193,17 +704,17 @@ impl Superblock {
                     let found_directory = if result
                         .common_prefixes
                         .get(0)
-                        .map(|prefix| prefix.starts_with(&full_path_suffixed))
+                        .map(|prefix| prefix.starts_with(key_suffixed))
                         .unwrap_or(false)
                     {
                         true
                     } else if result
                         .objects
                         .get(0)
-                        .map(|object| object.key.starts_with(&full_path_suffixed))
+                        .map(|object| object.key.starts_with(key_suffixed))
                         .unwrap_or(false)
                     {
-                        if result.objects[0].key == full_path_suffixed {
+                        if result.objects[0].key == key_suffixed {
                             trace!(
                                 parent = ?parent_ino,
                                 ?name,
@@ -211,8 +722,10 @@ impl Superblock {
                                 "found a directory that shadows this name"
                             );
                             // The S3 Console creates zero-sized keys for explicit directories, so
-                            // let's not warn about those cases.
-                            if result.objects[0].size > 0 {
+                            // let's not warn about those cases, or if the object will be shown
+                            // inside the directory instead.
+                            let sanitized = self.inner.config.invalid_key_handling == InvalidKeyHandling::Sanitize;
+                            if !result.objects[0].is_directory_marker() && !sanitized {
                                 warn!(
                                     "key {:?} is not a valid filename (ends in `/`); will be hidden and unavailable",
                                     full_path_suffixed
@@ -224,28 +737,93 @@ impl Superblock {
                         false
                     };
 
-                    // We don't have to wait for the HeadObject to complete because in our
-                    // semantics, directories always shadow files.
                     if found_directory {
                         trace!(parent = ?parent_ino, ?name, "lookup ListObjects found a directory");
                         let stat = InodeStat::for_directory(self.inner.mount_time, Instant::now());
-                        return Ok(Some(RemoteLookup { kind: InodeKind::Directory, stat }));
+
+                        // We don't have to wait for the HeadObject to complete if directories
+                        // shadow files of the same name.
+                        if shadow_policy != ShadowPolicy::PreferFile && !is_shadowed_file {
+                            return Ok(Some(RemoteLookup { kind: InodeKind::Directory, stat }));
+                        }
+                        dir_state = Some(stat);
                     }
                 }
             }
         }
 
-        // If we reach here, the ListObjects didn't find a shadowing directory, so we know we either
-        // have a valid file, or both requests failed to find the object so the file must not exist remotely
-        if let Some(stat) = file_state {
-            trace!(parent = ?parent_ino, ?name, "found a regular file");
-            Ok(Some(RemoteLookup {
-                kind: InodeKind::File,
+        // If we reach here, we either weren't looking for a directory, or the ListObjects didn't
+        // find one, or we were looking for a file that takes precedence over it.
+        match (file_state, dir_state) {
+            (Some(stat), dir_state) if !is_shadowed_file || dir_state.is_some() => {
+                trace!(parent = ?parent_ino, ?name, "found a regular file");
+                Ok(Some(RemoteLookup {
+                    kind: InodeKind::File,
+                    stat,
+                }))
+            }
+            (None, Some(stat)) if !is_shadowed_file => Ok(Some(RemoteLookup {
+                kind: InodeKind::Directory,
                 stat,
-            }))
-        } else {
-            trace!(parent = ?parent_ino, ?name, "not found");
-            Ok(None)
+            })),
+            _ => {
+                trace!(parent = ?parent_ino, ?name, "not found");
+                Ok(None)
+            }
+        }
+    }
+
+    /// Lookup the object with the same key as a directory, which is shown inside the directory as
+    /// [DIRECTORY_OBJECT_NAME] when using [InvalidKeyHandling::Sanitize]
+    async fn remote_lookup_directory_object<OC: ObjectClient + Sync>(
+        &self,
+        client: &OC,
+        parent: &Inode,
+    ) -> Result<Option<RemoteLookup>, InodeError> {
+        // The root of an unprefixed mount (or of a bucket, when browsing buckets) doesn't have a key
+        // of its own
+        let (bucket, key) = self.inner.bucket_and_key(parent.full_key());
+        if key.is_empty() {
+            return Ok(None);
+        }
+
+        match self.inner.head_object(client, bucket, key, None).await? {
+            // Directory markers aren't shown as files
+            Some(result) if !result.object.is_directory_marker() => {
+                let object = &result.object;
+                let stat = InodeStat::for_file(
+                    object.size as usize,
+                    object.last_modified,
+                    Instant::now(),
+                    Some(result.cache_validators()),
+                );
+                Ok(Some(RemoteLookup {
+                    kind: InodeKind::File,
+                    stat,
+                }))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Lookup the bucket with the given name, which is a directory under the root when browsing
+    /// buckets
+    async fn remote_lookup_bucket<OC: ObjectClient>(
+        &self,
+        client: &OC,
+        name: &str,
+    ) -> Result<Option<RemoteLookup>, InodeError> {
+        match client.list_objects(name, None, "/", 1, "").await {
+            Ok(_) => {
+                trace!(?name, "lookup found a bucket");
+                let stat = InodeStat::for_directory(self.inner.mount_time, Instant::now());
+                Ok(Some(RemoteLookup {
+                    kind: InodeKind::Directory,
+                    stat,
+                }))
+            }
+            Err(ObjectClientError::ServiceError(ListObjectsError::NoSuchBucket)) => Ok(None),
+            Err(e) => Err(InodeError::ClientError(e.into())),
         }
     }
 
@@ -254,7 +832,12 @@ impl Superblock {
         let inode = self.inner.get(ino)?;
 
         // TODO revalidate if expired
-        let stat = inode.inner.sync.read().unwrap().stat.clone();
+        let state = inode.inner.sync.read().unwrap();
+        if state.stale {
+            return Err(InodeError::StaleInode(ino));
+        }
+        let stat = state.stat.clone();
+        drop(state);
 
         Ok(LookedUp { inode, stat })
     }
@@ -277,6 +860,26 @@ impl Superblock {
         Ok(handle)
     }
 
+    /// Create a new write handle for appending to an inode. Unlike [Superblock::write], this also
+    /// allows writing to an inode that already exists remotely, in which case the caller is
+    /// responsible for carrying over the existing contents of the object.
+    pub async fn append<OC: ObjectClient>(
+        &self,
+        _client: &OC,
+        ino: InodeNo,
+        parent_ino: InodeNo,
+    ) -> Result<WriteHandle, InodeError> {
+        trace!(?ino, parent=?parent_ino, "append");
+
+        let handle = WriteHandle {
+            inner: self.inner.clone(),
+            ino,
+            parent_ino,
+        };
+        handle.start_appending()?;
+        Ok(handle)
+    }
+
     /// Start a readdir stream for the given directory inode
     ///
     /// Doesn't currently do any IO, so doesn't need to be async, but reserving it for future use.
@@ -306,11 +909,12 @@ impl Superblock {
             remote_results: Default::default(),
             local_results: Default::default(),
             next_continuation_token: Mutex::new(ReaddirStreamState::NotStarted),
+            previous_page_names: Default::default(),
         })
     }
 
     /// Create a new regular file or directory inode ready to be opened in write-only mode
-    pub async fn create<OC: ObjectClient>(
+    pub async fn create<OC: ObjectClient + Sync>(
         &self,
         client: &OC,
         dir: InodeNo,
@@ -319,6 +923,11 @@ impl Superblock {
     ) -> Result<LookedUp, InodeError> {
         trace!(parent=?dir, ?name, "create");
 
+        // A pinned file system is a read-only snapshot, and buckets can't be created as directories
+        if self.inner.config.pinned_as_of.is_some() || (self.inner.config.browse_buckets && dir == ROOT_INODE_NO) {
+            return Err(InodeError::InodeNotWritable(dir));
+        }
+
         let existing = self.lookup(client, dir, name).await;
         match existing {
             Ok(lookup) => return Err(InodeError::FileAlreadyExists(lookup.inode.ino())),
@@ -331,6 +940,21 @@ impl Superblock {
             .to_str()
             .ok_or_else(|| InodeError::InvalidFileName(name.to_owned()))?;
 
+        // A lookup of a name that's too long might have just said it doesn't exist, but we can't
+        // create it either way
+        if name.len() > self.inner.config.max_name_length {
+            return Err(InodeError::NameTooLong(name.into()));
+        }
+
+        // Suffixed names are reserved for shadowed files, which can't be created locally
+        if self.inner.config.shadow_policy == ShadowPolicy::Suffix && name.ends_with(SHADOWED_FILE_SUFFIX) {
+            return Err(InodeError::InvalidFileName(name.into()));
+        }
+        // Likewise for the name of a directory's own object
+        if self.inner.config.invalid_key_handling == InvalidKeyHandling::Sanitize && name == DIRECTORY_OBJECT_NAME {
+            return Err(InodeError::InvalidFileName(name.into()));
+        }
+
         let parent_inode = self.inner.get(dir)?;
         let mut parent_state = parent_inode.inner.sync.write().unwrap();
 
@@ -346,13 +970,14 @@ impl Superblock {
 
         let expiry = Instant::now(); // TODO local inode stats never expire?
         let stat = match kind {
-            InodeKind::File => InodeStat::for_file(0, OffsetDateTime::now_utc(), expiry, None), // Objects don't have an ETag until they are uploaded to S3
+            InodeKind::File => InodeStat::for_file(0, OffsetDateTime::now_utc(), expiry, None), // Objects don't have validators until they are uploaded to S3
             InodeKind::Directory => InodeStat::for_directory(self.inner.mount_time, expiry),
         };
         let state = InodeState {
             stat: stat.clone(),
             kind_data: InodeKindData::default_for(kind),
             write_status: WriteStatus::LocalUnopened,
+            stale: false,
         };
         let inode = self
             .inner
@@ -363,6 +988,167 @@ impl Superblock {
 }
 
 impl SuperblockInner {
+    /// Get an object's metadata with HeadObject, or `None` if it doesn't exist. If HeadObject is
+    /// denied, and [SuperblockConfig::head_object_fallback] is set, get it with a GetObject request
+    /// for the object's first byte instead.
+    async fn head_object<OC: ObjectClient + Sync>(
+        &self,
+        client: &OC,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Result<Option<HeadObjectResult>, InodeError> {
+        match client.head_object(bucket, key, version_id).await {
+            Ok(result) => Ok(Some(result)),
+            Err(ObjectClientError::ServiceError(HeadObjectError::NotFound)) => Ok(None),
+            Err(ObjectClientError::ServiceError(HeadObjectError::AccessDenied)) if self.config.head_object_fallback => {
+                trace!(?key, "HeadObject denied, falling back to GetObject");
+                match client.head_object_via_get(bucket, key, version_id).await {
+                    Ok(result) => Ok(Some(result)),
+                    Err(ObjectClientError::ServiceError(HeadObjectViaGetError::GetObject(
+                        GetObjectError::NoSuchBucket | GetObjectError::NoSuchKey,
+                    ))) => Ok(None),
+                    Err(e) => Err(InodeError::ClientError(e.into())),
+                }
+            }
+            Err(e) => Err(InodeError::ClientError(e.into())),
+        }
+    }
+
+    /// Split a full key into the bucket and key within it. When browsing buckets, the bucket is
+    /// the first component of the full key; otherwise, it's the mounted bucket.
+    fn bucket_and_key<'a>(&'a self, full_key: &'a str) -> (&'a str, &'a str) {
+        if self.config.browse_buckets {
+            full_key.split_once('/').unwrap_or((full_key, ""))
+        } else {
+            (&self.bucket, full_key)
+        }
+    }
+
+    /// Add an inode for a specific version of the file with the given name to its parent, or reuse
+    /// the inode if this version has been looked up before
+    fn insert_version(
+        &self,
+        parent: &Inode,
+        name: &str,
+        version_id: &str,
+        full_key: String,
+        stat: InodeStat,
+    ) -> Result<LookedUp, InodeError> {
+        let versioned_name = format!("{name}{VERSION_SUFFIX_SEPARATOR}{version_id}");
+        let mut parent_state = parent.inner.sync.write().unwrap();
+        let InodeKindData::Directory { children, .. } = &mut parent_state.kind_data else {
+            return Err(InodeError::NotADirectory(parent.ino()));
+        };
+
+        // Reuse the inode if we've looked up this version before
+        if let Some(inode) = children.get(&versioned_name) {
+            if inode.version_id() == Some(version_id) {
+                inode.inner.sync.write().unwrap().stat = stat.clone();
+                return Ok(LookedUp {
+                    inode: inode.clone(),
+                    stat,
+                });
+            }
+        }
+
+        let next_ino = self.next_ino.fetch_add(1, Ordering::SeqCst);
+        trace!(parent=?parent.ino(), name=?versioned_name, new_ino=?next_ino, ?full_key, "creating new version inode");
+        let inode = InodeInner {
+            ino: next_ino,
+            parent: parent.ino(),
+            name: versioned_name.clone(),
+            full_key,
+            kind: InodeKind::File,
+            version_id: Some(version_id.to_owned()),
+            range: None,
+            sync: RwLock::new(InodeState {
+                stat: stat.clone(),
+                write_status: WriteStatus::Remote,
+                kind_data: InodeKindData::default_for(InodeKind::File),
+                stale: false,
+            }),
+        };
+        let inode = Inode { inner: Arc::new(inode) };
+        children.insert(versioned_name, inode.clone());
+
+        let previous = self.inodes.write().unwrap().insert(next_ino, inode.clone());
+        assert!(previous.is_none(), "inode numbers are never reused");
+
+        Ok(LookedUp { inode, stat })
+    }
+
+    /// Add an inode for a range of the file with the given name to its parent, or reuse the inode if
+    /// this range has been looked up before. `requested` is the range in the inode's name, and
+    /// `range` is that range truncated to the current size of the object.
+    fn insert_range(
+        &self,
+        parent: &Inode,
+        name: &str,
+        requested: Range<u64>,
+        range: Range<u64>,
+        full_key: String,
+        stat: InodeStat,
+    ) -> Result<LookedUp, InodeError> {
+        let ranged_name = format!(
+            "{name}{RANGE_SUFFIX_SEPARATOR}{}-{}",
+            requested.start,
+            requested.end - 1
+        );
+        let mut parent_state = parent.inner.sync.write().unwrap();
+        let InodeKindData::Directory { children, .. } = &mut parent_state.kind_data else {
+            return Err(InodeError::NotADirectory(parent.ino()));
+        };
+
+        // Reuse the inode if we've looked up this range before and the object hasn't changed size
+        if let Some(inode) = children.get(&ranged_name) {
+            if inode.byte_range() == Some(range.clone()) {
+                inode.inner.sync.write().unwrap().stat = stat.clone();
+                return Ok(LookedUp {
+                    inode: inode.clone(),
+                    stat,
+                });
+            }
+        }
+
+        let next_ino = self.next_ino.fetch_add(1, Ordering::SeqCst);
+        trace!(parent=?parent.ino(), name=?ranged_name, new_ino=?next_ino, ?full_key, "creating new range inode");
+        let inode = InodeInner {
+            ino: next_ino,
+            parent: parent.ino(),
+            name: ranged_name.clone(),
+            full_key,
+            kind: InodeKind::File,
+            version_id: None,
+            range: Some(range),
+            sync: RwLock::new(InodeState {
+                stat: stat.clone(),
+                write_status: WriteStatus::Remote,
+                kind_data: InodeKindData::default_for(InodeKind::File),
+                stale: false,
+            }),
+        };
+        let inode = Inode { inner: Arc::new(inode) };
+        children.insert(ranged_name, inode.clone());
+
+        let previous = self.inodes.write().unwrap().insert(next_ino, inode.clone());
+        assert!(previous.is_none(), "inode numbers are never reused");
+
+        Ok(LookedUp { inode, stat })
+    }
+
+    /// Check a name being looked up isn't longer than the configured limit. How a name that's too
+    /// long is reported depends on the [InvalidKeyHandling].
+    fn check_name_length(&self, name: &str) -> Result<(), InodeError> {
+        if name.len() <= self.config.max_name_length {
+            return Ok(());
+        }
+        match self.config.invalid_key_handling {
+            InvalidKeyHandling::Hide => Err(InodeError::FileDoesNotExist),
+            InvalidKeyHandling::Sanitize => Err(InodeError::NameTooLong(name.into())),
+        }
+    }
+
     /// Retrieve the inode for the given number if it exists
     pub fn get(&self, ino: InodeNo) -> Result<Inode, InodeError> {
         self.inodes
@@ -373,6 +1159,32 @@ impl SuperblockInner {
             .ok_or(InodeError::InodeDoesNotExist(ino))
     }
 
+    /// Return the child with the given name in a parent directory if a recent `readdir` listed it
+    /// and its stat hasn't expired yet (see [SuperblockConfig::readdir_lookup_ttl]), so a lookup
+    /// doesn't need to ask S3 about it again
+    fn cached_lookup(&self, parent_ino: InodeNo, name: &str) -> Result<Option<LookedUp>, InodeError> {
+        if self.config.readdir_lookup_ttl.is_none() {
+            return Ok(None);
+        }
+
+        let parent = self.get(parent_ino)?;
+        let child = match &parent.inner.sync.read().unwrap().kind_data {
+            InodeKindData::File {} => return Err(InodeError::NotADirectory(parent_ino)),
+            InodeKindData::Directory { children, .. } => children.get(name).cloned(),
+        };
+        let Some(inode) = child else {
+            return Ok(None);
+        };
+
+        let state = inode.inner.sync.read().unwrap();
+        if state.stale || state.write_status != WriteStatus::Remote || state.stat.expiry <= Instant::now() {
+            return Ok(None);
+        }
+        let stat = state.stat.clone();
+        drop(state);
+        Ok(Some(LookedUp { inode, stat }))
+    }
+
     /// Update the inode with the given name in a parent directory with the remote data.
     /// It may update or delete an existing inode, or insert a new one.
     pub fn update_from_remote(
@@ -391,7 +1203,7 @@ impl SuperblockInner {
         // Fast path: try with only a read lock on the directory first.
         {
             let parent_state = parent.inner.sync.read().unwrap();
-            match Self::try_update_child(&parent_state, name, &remote)? {
+            match self.try_update_child(&parent_state, name, &remote)? {
                 UpdateStatus::Neither => return Err(InodeError::FileDoesNotExist),
                 UpdateStatus::Updated(lookedup) => return Ok(lookedup),
                 _ => {} // Fallback, we need a write lock to update the parent.
@@ -401,7 +1213,7 @@ impl SuperblockInner {
         // If the fast path failed, take the write lock. We first have to try the update again, as
         // a racing writer might have beat us to the lock after our fast path attempt.
         let mut parent_state = parent.inner.sync.write().unwrap();
-        match Self::try_update_child(&parent_state, name, &remote)? {
+        match self.try_update_child(&parent_state, name, &remote)? {
             UpdateStatus::Neither => Err(InodeError::FileDoesNotExist),
             UpdateStatus::Updated(lookedup) => Ok(lookedup),
             UpdateStatus::LocalOnly(inode) => {
@@ -429,6 +1241,7 @@ impl SuperblockInner {
                     stat: stat.clone(),
                     kind_data: InodeKindData::default_for(kind),
                     write_status: WriteStatus::Remote,
+                    stale: false,
                 };
                 self.create_inode_locked(&parent, &mut parent_state, name, kind, state, false)
                     .map(|inode| LookedUp { inode, stat })
@@ -440,6 +1253,7 @@ impl SuperblockInner {
     /// return an [UpdateStatus].
     /// Don't use this directly -- use [SuperblockInner::update_from_remote] instead.
     fn try_update_child(
+        &self,
         parent_state: &InodeState,
         name: &str,
         remote: &Option<RemoteLookup>,
@@ -455,25 +1269,31 @@ impl SuperblockInner {
             (Some(remote @ RemoteLookup { kind, stat }), Some(inode)) => {
                 let mut inode_state = inode.inner.sync.write().unwrap();
 
-                // In our semantics, directories shadow files of the same name. So if the inode
-                // already exists but the kind has changed, we need to decide what to do.
+                // Depending on the shadow policy, either directories shadow files of the same name
+                // or the other way around. So if the inode already exists but the kind has changed,
+                // we need to decide what to do.
+                let prefer_file = self.config.shadow_policy == ShadowPolicy::PreferFile;
                 match (inode.kind(), kind) {
                     // If the inode is currently a directory but we're asking to create a file,
-                    // fail the update, as the directory shadows the file.
+                    // fail the update if the directory shadows the file.
                     // TODO what if the directory is gone on the remote?
-                    (InodeKind::Directory, InodeKind::File) => Err(InodeError::ShadowedByDirectory(
+                    (InodeKind::Directory, InodeKind::File) if !prefer_file => Err(InodeError::ShadowedByDirectory(
                         inode.full_key().to_owned(),
                         inode.ino(),
                     )),
-                    // If the inode is currently a file but we're asking to update a directory,
-                    // overwrite it, since directories shadow files.
-                    (InodeKind::File, InodeKind::Directory) => {
-                        warn!(parent=?inode.parent(), name=?inode.name(), ino=?inode.ino(), "inode changed from file to directory, will recreate it");
+                    // Likewise if the inode is currently a file that shadows directories.
+                    (InodeKind::File, InodeKind::Directory) if prefer_file => {
+                        Err(InodeError::ShadowedByFile(inode.full_key().to_owned(), inode.ino()))
+                    }
+                    // Otherwise, the new kind takes precedence, so overwrite the inode.
+                    (InodeKind::File, InodeKind::Directory) | (InodeKind::Directory, InodeKind::File) => {
+                        warn!(parent=?inode.parent(), name=?inode.name(), ino=?inode.ino(), new_kind=?kind, "inode changed kind, will recreate it");
                         Ok(UpdateStatus::RemoteKey(remote.clone()))
                     }
                     // Otherwise, we'll just update this inode in place.
                     (InodeKind::File, InodeKind::File) | (InodeKind::Directory, InodeKind::Directory) => {
                         inode_state.stat = stat.clone();
+                        inode_state.stale = false;
                         Ok(UpdateStatus::Updated(LookedUp {
                             inode: inode.clone(),
                             stat: stat.clone(),
@@ -511,9 +1331,22 @@ impl SuperblockInner {
 
         let mut full_key = parent.full_key().to_owned();
         assert!(full_key.is_empty() || full_key.ends_with('/'));
-        full_key.push_str(name);
-        if kind == InodeKind::Directory {
-            full_key.push('/');
+        match kind {
+            InodeKind::File
+                if self.config.invalid_key_handling == InvalidKeyHandling::Sanitize
+                    && name == DIRECTORY_OBJECT_NAME =>
+            {
+                // The directory's own object has the same key as the directory, without the name
+            }
+            InodeKind::File if self.config.shadow_policy == ShadowPolicy::Suffix => {
+                // A suffixed file name refers to the object without the suffix
+                full_key.push_str(name.strip_suffix(SHADOWED_FILE_SUFFIX).unwrap_or(name));
+            }
+            InodeKind::File => full_key.push_str(name),
+            InodeKind::Directory => {
+                full_key.push_str(name);
+                full_key.push('/');
+            }
         }
 
         trace!(parent=?parent.ino(), ?name, ?kind, new_ino=?next_ino, ?full_key, "creating new inode");
@@ -524,6 +1357,8 @@ impl SuperblockInner {
             name: name.to_owned(),
             full_key,
             kind,
+            version_id: None,
+            range: None,
             sync: RwLock::new(state),
         };
         let inode = Inode { inner: Arc::new(inode) };
@@ -612,6 +1447,27 @@ impl WriteHandle {
         }
     }
 
+    /// Check the status on the inode and set it to writing state if it can be appended to, which
+    /// includes inodes that already exist remotely (but not specific versions of them)
+    pub fn start_appending(&self) -> Result<(), InodeError> {
+        let inode = self.inner.get(self.ino)?;
+        if inode.version_id().is_some() || inode.byte_range().is_some() {
+            error!(inode=?self.ino, "versioned and ranged inodes are read-only");
+            return Err(InodeError::InodeNotWritable(self.ino));
+        }
+        let mut state = inode.inner.sync.write().unwrap();
+        match state.write_status {
+            WriteStatus::LocalUnopened | WriteStatus::Remote => {
+                state.write_status = WriteStatus::LocalOpen;
+                Ok(())
+            }
+            WriteStatus::LocalOpen => {
+                error!(inode=?self.ino, "inode is already being written");
+                Err(InodeError::InodeNotWritable(self.ino))
+            }
+        }
+    }
+
     /// Update status of the inode and of containing "local" directories.
     pub fn finish_writing(self, object_size: usize) -> Result<(), InodeError> {
         let inode = self.inner.get(self.ino)?;
@@ -648,6 +1504,8 @@ impl WriteHandle {
             WriteStatus::LocalOpen => {
                 state.write_status = WriteStatus::Remote;
                 state.stat.size = object_size;
+                // If we replaced an existing object, its validators are no longer valid
+                state.stat.validators = None;
 
                 // Walk up the ancestors from parent to first remote ancestor to transition
                 // the inode and all "local" containing directories to "remote".
@@ -680,27 +1538,21 @@ pub struct ReaddirHandle {
     remote_results: RwLock<VecDeque<LookedUp>>,
     local_results: RwLock<VecDeque<LookedUp>>,
     next_continuation_token: Mutex<ReaddirStreamState>,
+    /// Names of the entries in the last page of the listing, which the next page can repeat if
+    /// the page ended inside a common prefix
+    previous_page_names: Mutex<HashSet<String>>,
 }
 
+/// Where the listing is up to. The state only moves on once a page has been fetched, so a call to
+/// [ReaddirHandle::next] that's dropped or fails partway through can be retried.
 #[derive(Debug, PartialEq, Eq)]
 enum ReaddirStreamState {
     NotStarted,
-    /// Continuation token for the next call
-    Continued(String),
+    /// Continuation token for the next call, or `None` if the first page hasn't been fetched yet
+    Continued(Option<String>),
     Finished,
 }
 
-impl ReaddirStreamState {
-    fn take(&mut self) -> Option<String> {
-        let old_state = std::mem::replace(self, ReaddirStreamState::Finished);
-        if let ReaddirStreamState::Continued(s) = old_state {
-            Some(s)
-        } else {
-            None
-        }
-    }
-}
-
 impl ReaddirHandle {
     pub async fn next<OC: ObjectClient>(&self, client: &OC) -> Result<Option<LookedUp>, InodeError> {
         // We will start fetching new results when number of items in the remote results queue is empty
@@ -728,6 +1580,7 @@ impl ReaddirHandle {
                         Ok(mut new_results) => {
                             new_results.sort_by(|left, right| left.inode.name().cmp(right.inode.name()));
                             self.local_results.write().unwrap().extend(new_results);
+                            *next_token = ReaddirStreamState::Continued(None);
                         }
                         Err(e) => {
                             error!(error=?e, "readdir failed");
@@ -736,72 +1589,111 @@ impl ReaddirHandle {
                     }
                 }
 
-                if *next_token == ReaddirStreamState::Finished {
-                    trace!(self=?self as *const _, "readdir finished");
-                    return Ok(self.compare_and_get_next());
+                match &*next_token {
+                    ReaddirStreamState::Continued(token) => token.clone(),
+                    _ => {
+                        trace!(self=?self as *const _, "readdir finished");
+                        return Ok(self.compare_and_get_next());
+                    }
                 }
-                next_token.take()
             };
 
             trace!(self=?self as *const _, ?continuation_token, "continuing readdir");
 
+            if self.inner.config.browse_buckets && self.dir_ino == ROOT_INODE_NO {
+                self.list_buckets(client).await?;
+                *self.next_continuation_token.lock().unwrap() = ReaddirStreamState::Finished;
+                continue;
+            }
+
+            let (bucket, dir_key) = self.inner.bucket_and_key(&self.full_path);
             let result = client
-                .list_objects(
-                    self.inner.bucket.as_str(),
-                    continuation_token.as_deref(),
-                    "/",
-                    self.page_size,
-                    self.full_path.as_str(),
-                )
+                .list_objects(bucket, continuation_token.as_deref(), "/", self.page_size, dir_key)
                 .await
                 .map_err(|e| InodeError::ClientError(anyhow::Error::new(e)))?;
 
             *self.next_continuation_token.lock().unwrap() = match result.next_continuation_token {
-                Some(token) => ReaddirStreamState::Continued(token),
+                Some(token) => ReaddirStreamState::Continued(Some(token)),
                 None => ReaddirStreamState::Finished,
             };
 
+            let readdir_expiry = Instant::now() + self.inner.config.readdir_lookup_ttl.unwrap_or_default();
+            let shadow_policy = self.inner.config.shadow_policy;
+            let sanitize_keys = self.inner.config.invalid_key_handling == InvalidKeyHandling::Sanitize;
+            // With the suffix shadow policy, names that end with the suffix are reserved for files
+            // shadowed by a directory, so we hide anything that already looks like one. Likewise for
+            // the name of the directory's own object when sanitizing keys.
+            let max_name_length = self.inner.config.max_name_length;
+            let not_reserved = |name: &&str| {
+                (shadow_policy != ShadowPolicy::Suffix || !name.ends_with(SHADOWED_FILE_SUFFIX))
+                    && (!sanitize_keys || *name != DIRECTORY_OBJECT_NAME)
+                    // Names that are too long can't be presented at all
+                    && name.len() <= max_name_length
+            };
             let prefixes = result
                 .common_prefixes
                 .iter()
-                .map(|prefix| &prefix[self.full_path.len()..prefix.len() - 1])
-                .filter(|name| valid_inode_name(name))
-                .map(|name| {
-                    let stat = InodeStat::for_directory(self.inner.mount_time, Instant::now());
-                    self.inner.update_from_remote(
+                .map(|prefix| (&prefix[dir_key.len()..prefix.len() - 1], prefix))
+                .filter(|(name, _prefix)| valid_inode_name(name) && not_reserved(name))
+                .flat_map(|(name, prefix)| {
+                    let stat = InodeStat::for_directory(self.inner.mount_time, readdir_expiry);
+                    let result = self.inner.update_from_remote(
                         self.dir_ino,
                         name,
                         Some(RemoteLookup {
                             kind: InodeKind::Directory,
                             stat,
                         }),
-                    )
+                    );
+                    // Skip over prefixes that are shadowed by a file. We can do this here because
+                    // with that policy, objects are iterated first.
+                    match result {
+                        Err(InodeError::ShadowedByFile(_, _)) => {
+                            warn!(
+                                "prefix {:?} is shadowed by a file with the same name and will be unavailable",
+                                prefix
+                            );
+                            None
+                        }
+                        _ => Some(result),
+                    }
                 });
             let objects = result
                 .objects
                 .iter()
-                .map(|object| (&object.key[self.full_path.len()..], object))
-                // Hide keys that end with '/', since they can be confused with directories
-                .filter(|(name, _object)| valid_inode_name(name))
+                .map(|object| (&object.key[dir_key.len()..], object))
+                .filter_map(|(name, object)| match name {
+                    // The directory's own object (if it isn't just a directory marker) can be
+                    // shown under a reserved name
+                    "" if sanitize_keys && !object.is_directory_marker() => Some((DIRECTORY_OBJECT_NAME, object)),
+                    // Hide keys that end with '/', since they can be confused with directories
+                    name if valid_inode_name(name) && not_reserved(&name) => Some((name, object)),
+                    _ => None,
+                })
                 .flat_map(|(name, object)| {
                     let last_modified = object.last_modified;
                     let stat = InodeStat::for_file(
                         object.size as usize,
                         last_modified,
-                        Instant::now(),
-                        Some(object.etag.clone()),
+                        readdir_expiry,
+                        Some(object.cache_validators()),
                     );
-                    let result = self.inner.update_from_remote(
-                        self.dir_ino,
-                        name,
-                        Some(RemoteLookup {
-                            kind: InodeKind::File,
-                            stat,
-                        }),
-                    );
-                    // Skip over keys that are shadowed by a directory. We can do this here because
+                    let remote = RemoteLookup {
+                        kind: InodeKind::File,
+                        stat,
+                    };
+                    let result = self.inner.update_from_remote(self.dir_ino, name, Some(remote.clone()));
+                    // Skip over keys that are shadowed by a directory, or make them available under
+                    // a suffixed name. We can do this here because unless files shadow directories,
                     // common prefixes are iterated first, and the `sort_by` below is stable.
                     match result {
+                        Err(InodeError::ShadowedByDirectory(_, _)) if shadow_policy == ShadowPolicy::Suffix => {
+                            let suffixed_name = format!("{name}{SHADOWED_FILE_SUFFIX}");
+                            Some(
+                                self.inner
+                                    .update_from_remote(self.dir_ino, &suffixed_name, Some(remote)),
+                            )
+                        }
                         Err(InodeError::ShadowedByDirectory(_, _)) => {
                             warn!(
                                 "key {:?} is shadowed by a directory with the same name and will be unavailable",
@@ -814,9 +1706,18 @@ impl ReaddirHandle {
                 });
 
             // TODO would be nice to do this as a merge sort but the Result makes it messy
-            match prefixes.chain(objects).collect::<Result<Vec<_>, _>>() {
+            let new_results = if shadow_policy == ShadowPolicy::PreferFile {
+                objects.chain(prefixes).collect::<Result<Vec<_>, _>>()
+            } else {
+                prefixes.chain(objects).collect::<Result<Vec<_>, _>>()
+            };
+            match new_results {
                 Ok(mut new_results) => {
                     new_results.sort_by(|left, right| left.inode.name().cmp(right.inode.name()));
+                    let mut previous_page_names = self.previous_page_names.lock().unwrap();
+                    let page_names = new_results.iter().map(|entry| entry.inode.name().to_owned()).collect();
+                    new_results.retain(|entry| !previous_page_names.contains(entry.inode.name()));
+                    *previous_page_names = page_names;
                     self.remote_results.write().unwrap().extend(new_results);
                 }
                 Err(e) => {
@@ -829,6 +1730,41 @@ impl ReaddirHandle {
         Ok(self.compare_and_get_next())
     }
 
+    /// Add the buckets the account owns to the results as the entries of the root directory, when
+    /// browsing buckets. The listing isn't paginated, so this is only called once.
+    async fn list_buckets<OC: ObjectClient>(&self, client: &OC) -> Result<(), InodeError> {
+        let result = client
+            .list_buckets()
+            .await
+            .map_err(|e| InodeError::ClientError(anyhow::Error::new(e)))?;
+
+        let readdir_expiry = Instant::now() + self.inner.config.readdir_lookup_ttl.unwrap_or_default();
+        let new_results = result
+            .buckets
+            .iter()
+            .filter(|bucket| valid_inode_name(&bucket.name))
+            .map(|bucket| {
+                let stat = InodeStat::for_directory(self.inner.mount_time, readdir_expiry);
+                let remote = RemoteLookup {
+                    kind: InodeKind::Directory,
+                    stat,
+                };
+                self.inner.update_from_remote(self.dir_ino, &bucket.name, Some(remote))
+            })
+            .collect::<Result<Vec<_>, _>>();
+        match new_results {
+            Ok(mut new_results) => {
+                new_results.sort_by(|left, right| left.inode.name().cmp(right.inode.name()));
+                self.remote_results.write().unwrap().extend(new_results);
+                Ok(())
+            }
+            Err(e) => {
+                error!(error=?e, "readdir failed");
+                Err(e)
+            }
+        }
+    }
+
     /// Re-add an entry to the front of the queue if the consumer wasn't able to use it
     pub fn readd(&self, entry: LookedUp) {
         self.remote_results.write().unwrap().push_front(entry);
@@ -838,6 +1774,11 @@ impl ReaddirHandle {
         self.parent_ino
     }
 
+    /// The full key of the directory being listed
+    pub fn full_path(&self) -> &str {
+        &self.full_path
+    }
+
     fn compare_and_get_next(&self) -> Option<LookedUp> {
         let mut local_locked = self.local_results.write().unwrap();
         let mut remote_locked = self.remote_results.write().unwrap();
@@ -886,6 +1827,10 @@ struct InodeInner {
     // TODO deduplicate keys by string interning or something -- many keys will have common prefixes
     full_key: String,
     kind: InodeKind,
+    /// If set, this inode is pinned to a specific version of the object at `full_key`
+    version_id: Option<String>,
+    /// If set, this inode's contents are this range of bytes of the object at `full_key`
+    range: Option<Range<u64>>,
 
     // Mutable inode state. This lock should also be used to serialize operations on an inode (like
     // creating a new child).
@@ -913,6 +1858,16 @@ impl Inode {
         &self.inner.full_key
     }
 
+    pub fn version_id(&self) -> Option<&str> {
+        self.inner.version_id.as_deref()
+    }
+
+    /// The range of bytes of the object this inode's contents come from, if it's a synthetic ranged
+    /// inode rather than the whole object
+    pub fn byte_range(&self) -> Option<Range<u64>> {
+        self.inner.range.clone()
+    }
+
     pub fn start_reading(&self) -> Result<(), InodeError> {
         let state = self.inner.sync.read().unwrap();
         match state.write_status {
@@ -925,6 +1880,19 @@ impl Inode {
         // Currently a no-op, but this is where you'd e.g. update atime
         Ok(())
     }
+
+    /// Expire the inode's cached stat, for example because its object was just replaced, so that
+    /// it isn't answered from the cache again
+    pub fn expire_stat(&self) {
+        let mut state = self.inner.sync.write().unwrap();
+        state.stat.expiry = state.stat.expiry.min(Instant::now());
+    }
+
+    /// Record that the object behind this inode has been deleted from S3, so that later operations
+    /// on the inode fail with [InodeError::StaleInode] until a lookup finds the object again
+    pub fn mark_stale(&self) {
+        self.inner.sync.write().unwrap().stale = true;
+    }
 }
 
 #[derive(Debug)]
@@ -932,6 +1900,8 @@ struct InodeState {
     stat: InodeStat,
     write_status: WriteStatus,
     kind_data: InodeKindData,
+    /// The object behind this inode was found to have been deleted remotely
+    stale: bool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -975,7 +1945,7 @@ impl InodeKindData {
 
 #[derive(Debug, Clone)]
 pub struct InodeStat {
-    #[allow(unused)] // TODO revalidate
+    /// Until when this stat can be used to answer a `lookup` without asking S3 again
     expiry: Instant,
 
     /// Size in bytes
@@ -987,8 +1957,8 @@ pub struct InodeStat {
     pub ctime: OffsetDateTime,
     /// Time of last access
     pub atime: OffsetDateTime,
-    /// Etag for the file (object)
-    pub etag: Option<String>,
+    /// Validators (ETag and last-modified time) of the file's object, if it has been uploaded
+    pub validators: Option<CacheValidators>,
 }
 
 /// Inode write status (local vs remote)
@@ -1004,14 +1974,19 @@ pub enum WriteStatus {
 
 impl InodeStat {
     /// Initialize an [InodeStat] for a file, given some metadata.
-    fn for_file(size: usize, datetime: OffsetDateTime, expiry: Instant, etag: Option<String>) -> InodeStat {
+    fn for_file(
+        size: usize,
+        datetime: OffsetDateTime,
+        expiry: Instant,
+        validators: Option<CacheValidators>,
+    ) -> InodeStat {
         InodeStat {
             expiry,
             size,
             atime: datetime,
             ctime: datetime,
             mtime: datetime,
-            etag,
+            validators,
         }
     }
 
@@ -1023,7 +1998,7 @@ impl InodeStat {
             atime: datetime,
             ctime: datetime,
             mtime: datetime,
-            etag: None,
+            validators: None,
         }
     }
 }
@@ -1038,8 +2013,12 @@ pub enum InodeError {
     InodeDoesNotExist(InodeNo),
     #[error("invalid file name {0:?}")]
     InvalidFileName(OsString),
+    #[error("file name {0:?} is too long")]
+    NameTooLong(String),
     #[error("file {0:?} is shadowed by a directory with inode {1}")]
     ShadowedByDirectory(String, InodeNo),
+    #[error("directory {0:?} is shadowed by a file with inode {1}")]
+    ShadowedByFile(String, InodeNo),
     #[error("inode {0} is not a directory")]
     NotADirectory(InodeNo),
     #[error("file already exists at inode {0}")]
@@ -1048,6 +2027,8 @@ pub enum InodeError {
     InodeNotWritable(InodeNo),
     #[error("inode {0} is not readable while being written")]
     InodeNotReadableWhileWriting(InodeNo),
+    #[error("inode {0} is stale, its object was deleted")]
+    StaleInode(InodeNo),
 }
 
 #[cfg(test)]
@@ -1112,7 +2093,7 @@ mod tests {
 
         let prefix = Prefix::new(prefix).expect("valid prefix");
         let ts = OffsetDateTime::now_utc();
-        let superblock = Superblock::new(bucket, &prefix);
+        let superblock = Superblock::new(bucket, &prefix, Default::default());
 
         // Try it twice to test the inode reuse path too
         for _ in 0..2 {
@@ -1171,7 +2152,7 @@ mod tests {
                         .expect("inode should exist");
                     // Grab last modified time according to mock S3
                     let modified_time = client
-                        .head_object(bucket, file.inode.full_key())
+                        .head_object(bucket, file.inode.full_key(), None)
                         .await
                         .expect("object should exist")
                         .object
@@ -1219,7 +2200,7 @@ mod tests {
 
         let prefix = Prefix::new(prefix).expect("valid prefix");
         let ts = OffsetDateTime::now_utc();
-        let superblock = Superblock::new("test_bucket", &prefix);
+        let superblock = Superblock::new("test_bucket", &prefix, Default::default());
 
         // Try it all twice to test inode reuse
         for _ in 0..2 {
@@ -1268,7 +2249,7 @@ mod tests {
         let client = Arc::new(MockClient::new(client_config));
 
         let prefix = Prefix::new(prefix).expect("valid prefix");
-        let superblock = Superblock::new("test_bucket", &prefix);
+        let superblock = Superblock::new("test_bucket", &prefix, Default::default());
 
         let mut expected_list = Vec::new();
 
@@ -1302,6 +2283,28 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_readdir_common_prefix_split_across_pages() {
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+        };
+        let client = Arc::new(MockClient::new(client_config));
+        for key in ["a", "dir/1", "dir/2", "dir/3", "z"] {
+            client.add_object(key, MockObject::constant(0xaa, 10, ETag::for_tests()));
+        }
+        // The first page is `a` and `dir/`, and the next one starts inside `dir/`
+        client.split_common_prefixes_across_pages(true);
+
+        let superblock = Superblock::new("test_bucket", &Default::default(), Default::default());
+        let dir_handle = superblock.readdir(&client, FUSE_ROOT_INODE, 2).await.unwrap();
+        let entries = dir_handle.collect(&client).await.unwrap();
+        assert_eq!(
+            entries.iter().map(|entry| entry.inode.name()).collect::<Vec<_>>(),
+            vec!["a", "dir", "z"]
+        );
+    }
+
     #[test_case(""; "unprefixed")]
     #[test_case("test_prefix/"; "prefixed")]
     #[tokio::test]
@@ -1313,7 +2316,7 @@ mod tests {
         let client = Arc::new(MockClient::new(client_config));
 
         let prefix = Prefix::new(prefix).expect("valid prefix");
-        let superblock = Superblock::new("test_bucket", &prefix);
+        let superblock = Superblock::new("test_bucket", &prefix, Default::default());
 
         let mut expected_list = Vec::new();
 
@@ -1368,7 +2371,7 @@ mod tests {
         };
         let client = Arc::new(MockClient::new(client_config));
         let prefix = Prefix::new(prefix).expect("valid prefix");
-        let superblock = Superblock::new("test_bucket", &prefix);
+        let superblock = Superblock::new("test_bucket", &prefix, Default::default());
 
         // Create local directory
         let dirname = "local_dir";
@@ -1405,7 +2408,7 @@ mod tests {
             part_size: 1024 * 1024,
         };
         let client = Arc::new(MockClient::new(client_config));
-        let superblock = Superblock::new("test_bucket", &Default::default());
+        let superblock = Superblock::new("test_bucket", &Default::default(), Default::default());
 
         let nested_dirs = (0..5).map(|i| format!("level{i}")).collect::<Vec<_>>();
         let leaf_dir_ino = {
@@ -1462,7 +2465,7 @@ mod tests {
         let client = Arc::new(MockClient::new(client_config));
         client.add_object("dir1/file1.txt", MockObject::constant(0xaa, 30, ETag::for_tests()));
 
-        let superblock = Superblock::new("test_bucket", &Default::default());
+        let superblock = Superblock::new("test_bucket", &Default::default(), Default::default());
 
         for _ in 0..2 {
             let dir1_1 = superblock
@@ -1510,7 +2513,7 @@ mod tests {
             MockObject::constant(0xaa, 30, ETag::for_tests()),
         );
 
-        let superblock = Superblock::new("test_bucket", &Default::default());
+        let superblock = Superblock::new("test_bucket", &Default::default(), Default::default());
 
         let dir_handle = superblock.readdir(&client, FUSE_ROOT_INODE, 2).await.unwrap();
         let entries = dir_handle.collect(&client).await.unwrap();
@@ -1557,7 +2560,7 @@ mod tests {
             MockObject::constant(0xaa, 30, ETag::from_str("test_etag_5").unwrap()),
         );
 
-        let superblock = Superblock::new("test_bucket", &Default::default());
+        let superblock = Superblock::new("test_bucket", &Default::default(), Default::default());
         let dir_handle = superblock.readdir(&client, FUSE_ROOT_INODE, 2).await.unwrap();
         let entries = dir_handle.collect(&client).await.unwrap();
         assert_eq!(
@@ -1582,6 +2585,27 @@ mod tests {
         }
     }
 
+    #[test_case("file.txt@v=abc", Some(("file.txt", "abc")); "simple")]
+    #[test_case("a@v=b@v=c", Some(("a@v=b", "c")); "last separator wins")]
+    #[test_case("file.txt", None; "no version")]
+    #[test_case("@v=abc", None; "empty name")]
+    #[test_case("file.txt@v=", None; "empty version")]
+    fn test_parse_versioned_name(name: &str, expected: Option<(&str, &str)>) {
+        assert_eq!(parse_versioned_name(name), expected);
+    }
+
+    #[test_case("file.bin@range=1000-1999", Some(("file.bin", 1000..2000)); "simple")]
+    #[test_case("file.bin@range=5-5", Some(("file.bin", 5..6)); "single byte")]
+    #[test_case("a@range=1-2@range=3-4", Some(("a@range=1-2", 3..5)); "last separator wins")]
+    #[test_case("file.bin", None; "no range")]
+    #[test_case("@range=0-10", None; "empty name")]
+    #[test_case("file.bin@range=10-5", None; "backwards")]
+    #[test_case("file.bin@range=10-", None; "open ended")]
+    #[test_case("file.bin@range=a-b", None; "not numbers")]
+    fn test_parse_ranged_name(name: &str, expected: Option<(&str, Range<u64>)>) {
+        assert_eq!(parse_ranged_name(name), expected);
+    }
+
     #[test]
     fn test_inodestat_constructors() {
         let ts = OffsetDateTime::UNIX_EPOCH + Duration::days(90);
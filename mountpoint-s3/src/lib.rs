@@ -1,10 +1,18 @@
+pub mod bootstrap;
+pub mod build_info;
+pub mod control;
+mod deadline;
+mod decode;
+mod dir_cache;
 pub mod fs;
 pub mod fuse;
 mod inode;
+mod limiter;
 pub mod metrics;
 pub mod prefetch;
 pub mod prefix;
 mod sync;
+pub mod upload;
 
 pub use fs::{S3Filesystem, S3FilesystemConfig};
 
@@ -0,0 +1,475 @@
+//! A wrapper around an [ObjectClient] that bounds how many requests can be in flight at once.
+//!
+//! Every request made through a [ConcurrencyLimitedClient] first acquires a permit from a shared
+//! semaphore, and requests beyond the limit wait for a permit rather than fail. A GetObject request
+//! holds its permit until its body stream is dropped, since the request is still in flight while
+//! the body is being streamed.
+//!
+//! Waiting requests are dispatched in order of their [RequestPriority], so that a read an
+//! application is blocked on doesn't queue up behind speculative prefetches. Requests are high
+//! priority unless they're made from within [with_priority]. The priority is only a scheduling hint
+//! for this client, and doesn't change the request sent to S3.
+
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::channel::oneshot;
+use futures::Stream;
+use mountpoint_s3_client::{
+    AbortMultipartUploadError, AbortMultipartUploadResult, CopyObjectError, CopyObjectResult, DeleteObjectError,
+    DeleteObjectResult, DeleteObjectsError, DeleteObjectsResult, GetBodyPart, GetObjectAttributesError,
+    GetObjectAttributesResult, GetObjectError, GetObjectMetadata, GetObjectParams, GetObjectProgress, HeadObjectError,
+    HeadObjectResult, ListBucketsError, ListBucketsResult, ListMultipartUploadsError, ListMultipartUploadsResult,
+    ListObjectVersionsError, ListObjectVersionsResult, ListObjectsError, ListObjectsResult, ObjectAttribute,
+    ObjectCannedAcl, ObjectClient, ObjectClientResult, ObjectMetadataError, PostPolicyCondition, PresignPostError,
+    PresignedPost, PutObjectAclError, PutObjectAclResult, PutObjectError, PutObjectParams, PutObjectResult,
+};
+
+use crate::sync::{Arc, Mutex};
+
+/// How urgently the result of a request is needed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RequestPriority {
+    /// Something is waiting on the request, like an application's read
+    #[default]
+    High,
+    /// The request is speculative and nothing is waiting on it yet, like a prefetch
+    Low,
+}
+
+thread_local! {
+    /// The priority of requests made by the future currently being polled on this thread
+    static CURRENT_PRIORITY: Cell<RequestPriority> = Cell::new(RequestPriority::High);
+}
+
+/// Make any requests `future` makes through a [ConcurrencyLimitedClient] with the given priority
+pub fn with_priority<F: Future>(priority: RequestPriority, future: F) -> WithPriority<F> {
+    WithPriority {
+        priority,
+        future: Box::pin(future),
+    }
+}
+
+/// A future that sets the priority of the requests it makes, see [with_priority]
+#[derive(Debug)]
+pub struct WithPriority<F> {
+    priority: RequestPriority,
+    future: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for WithPriority<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        /// Restores the previous priority when dropped, even if the future panics
+        struct Restore(RequestPriority);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CURRENT_PRIORITY.with(|priority| priority.set(self.0));
+            }
+        }
+
+        let _restore = Restore(CURRENT_PRIORITY.with(|priority| priority.replace(self.priority)));
+        self.future.as_mut().poll(cx)
+    }
+}
+
+/// An [ObjectClient] that allows at most a fixed number of concurrent requests to the client it
+/// wraps. Without a limit, requests are passed straight through.
+#[derive(Debug)]
+pub struct ConcurrencyLimitedClient<Client> {
+    client: Client,
+    semaphore: Option<Arc<PrioritySemaphore>>,
+}
+
+impl<Client> ConcurrencyLimitedClient<Client> {
+    /// Wrap the given client so that at most `max_concurrent_requests` requests are in flight at
+    /// once, or any number if it's `None`
+    pub fn new(client: Client, max_concurrent_requests: Option<usize>) -> Self {
+        let semaphore = max_concurrent_requests.map(|max| {
+            assert!(max > 0, "must allow at least one concurrent request");
+            Arc::new(PrioritySemaphore::new(max))
+        });
+        Self { client, semaphore }
+    }
+
+    /// Wait until there's room for another request with the given priority
+    async fn acquire(&self, priority: RequestPriority) -> Option<Permit> {
+        match &self.semaphore {
+            Some(semaphore) => Some(PrioritySemaphore::acquire(semaphore, priority).await),
+            None => None,
+        }
+    }
+}
+
+#[async_trait]
+impl<Client> ObjectClient for ConcurrencyLimitedClient<Client>
+where
+    Client: ObjectClient + Send + Sync + 'static,
+{
+    type GetObjectResult = ConcurrencyLimitedGetResult<Client>;
+    type ClientError = Client::ClientError;
+
+    async fn delete_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> ObjectClientResult<DeleteObjectResult, DeleteObjectError, Self::ClientError> {
+        let _permit = self.acquire(RequestPriority::High).await;
+        self.client.delete_object(bucket, key, version_id).await
+    }
+
+    async fn delete_objects(
+        &self,
+        bucket: &str,
+        keys: &[String],
+    ) -> ObjectClientResult<DeleteObjectsResult, DeleteObjectsError, Self::ClientError> {
+        let _permit = self.acquire(RequestPriority::High).await;
+        self.client.delete_objects(bucket, keys).await
+    }
+
+    async fn get_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &GetObjectParams,
+    ) -> ObjectClientResult<Self::GetObjectResult, GetObjectError, Self::ClientError> {
+        let priority = CURRENT_PRIORITY.with(Cell::get);
+        let permit = self.acquire(priority).await;
+        let get_result = self.client.get_object(bucket, key, params).await?;
+        Ok(ConcurrencyLimitedGetResult {
+            get_result: Box::pin(get_result),
+            _permit: permit,
+        })
+    }
+
+    async fn list_objects(
+        &self,
+        bucket: &str,
+        continuation_token: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, Self::ClientError> {
+        let _permit = self.acquire(RequestPriority::High).await;
+        self.client
+            .list_objects(bucket, continuation_token, delimiter, max_keys, prefix)
+            .await
+    }
+
+    async fn list_object_versions(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        key_marker: Option<&str>,
+        version_id_marker: Option<&str>,
+        max_keys: usize,
+    ) -> ObjectClientResult<ListObjectVersionsResult, ListObjectVersionsError, Self::ClientError> {
+        let _permit = self.acquire(RequestPriority::High).await;
+        self.client
+            .list_object_versions(bucket, prefix, key_marker, version_id_marker, max_keys)
+            .await
+    }
+
+    async fn head_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> ObjectClientResult<HeadObjectResult, HeadObjectError, Self::ClientError> {
+        let _permit = self.acquire(RequestPriority::High).await;
+        self.client.head_object(bucket, key, version_id).await
+    }
+
+    async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectParams,
+        contents: impl Stream<Item = impl AsRef<[u8]> + Send> + Send,
+    ) -> ObjectClientResult<PutObjectResult, PutObjectError, Self::ClientError> {
+        let _permit = self.acquire(RequestPriority::High).await;
+        self.client.put_object(bucket, key, params, contents).await
+    }
+
+    async fn copy_object(
+        &self,
+        source_bucket: &str,
+        source_key: &str,
+        destination_bucket: &str,
+        destination_key: &str,
+    ) -> ObjectClientResult<CopyObjectResult, CopyObjectError, Self::ClientError> {
+        let _permit = self.acquire(RequestPriority::High).await;
+        self.client
+            .copy_object(source_bucket, source_key, destination_bucket, destination_key)
+            .await
+    }
+
+    async fn put_object_acl(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        acl: ObjectCannedAcl,
+    ) -> ObjectClientResult<PutObjectAclResult, PutObjectAclError, Self::ClientError> {
+        let _permit = self.acquire(RequestPriority::High).await;
+        self.client.put_object_acl(bucket, key, version_id, acl).await
+    }
+
+    async fn list_multipart_uploads(
+        &self,
+        bucket: &str,
+        prefix: &str,
+    ) -> ObjectClientResult<ListMultipartUploadsResult, ListMultipartUploadsError, Self::ClientError> {
+        let _permit = self.acquire(RequestPriority::High).await;
+        self.client.list_multipart_uploads(bucket, prefix).await
+    }
+
+    async fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> ObjectClientResult<AbortMultipartUploadResult, AbortMultipartUploadError, Self::ClientError> {
+        let _permit = self.acquire(RequestPriority::High).await;
+        self.client.abort_multipart_upload(bucket, key, upload_id).await
+    }
+
+    async fn list_buckets(&self) -> ObjectClientResult<ListBucketsResult, ListBucketsError, Self::ClientError> {
+        let _permit = self.acquire(RequestPriority::High).await;
+        self.client.list_buckets().await
+    }
+
+    async fn get_object_attributes(
+        &self,
+        bucket: &str,
+        key: &str,
+        max_parts: Option<usize>,
+        part_number_marker: Option<usize>,
+        object_attributes: &[ObjectAttribute],
+    ) -> ObjectClientResult<GetObjectAttributesResult, GetObjectAttributesError, Self::ClientError> {
+        let _permit = self.acquire(RequestPriority::High).await;
+        self.client
+            .get_object_attributes(bucket, key, max_parts, part_number_marker, object_attributes)
+            .await
+    }
+
+    async fn presign_post_object(
+        &self,
+        bucket: &str,
+        key_prefix: &str,
+        conditions: &[PostPolicyCondition],
+        expires_in: Duration,
+    ) -> ObjectClientResult<PresignedPost, PresignPostError, Self::ClientError> {
+        // Presigning is done locally and doesn't make a request, so doesn't need a permit
+        self.client
+            .presign_post_object(bucket, key_prefix, conditions, expires_in)
+            .await
+    }
+}
+
+/// The body of a GetObject request made through a [ConcurrencyLimitedClient], which holds on to
+/// the request's permit until it's dropped
+pub struct ConcurrencyLimitedGetResult<Client: ObjectClient> {
+    get_result: Pin<Box<Client::GetObjectResult>>,
+    _permit: Option<Permit>,
+}
+
+impl<Client: ObjectClient> GetObjectProgress for ConcurrencyLimitedGetResult<Client> {
+    fn bytes_transferred(&self) -> u64 {
+        self.get_result.bytes_transferred()
+    }
+
+    fn object_metadata(&self) -> Option<Result<GetObjectMetadata, ObjectMetadataError>> {
+        self.get_result.object_metadata()
+    }
+}
+
+impl<Client: ObjectClient> Stream for ConcurrencyLimitedGetResult<Client> {
+    type Item = ObjectClientResult<GetBodyPart, GetObjectError, Client::ClientError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_result.as_mut().poll_next(cx)
+    }
+}
+
+/// A counting semaphore that hands out permits to waiting high priority requests before low
+/// priority ones, and in FIFO order within each priority
+#[derive(Debug)]
+struct PrioritySemaphore {
+    state: Mutex<PrioritySemaphoreState>,
+}
+
+#[derive(Debug)]
+struct PrioritySemaphoreState {
+    /// Permits not held by anyone. Permits are handed straight to waiters when released, so there
+    /// are only waiters when this is 0.
+    available: usize,
+    high_waiters: VecDeque<oneshot::Sender<()>>,
+    low_waiters: VecDeque<oneshot::Sender<()>>,
+}
+
+impl PrioritySemaphore {
+    fn new(permits: usize) -> Self {
+        let state = PrioritySemaphoreState {
+            available: permits,
+            high_waiters: VecDeque::new(),
+            low_waiters: VecDeque::new(),
+        };
+        Self {
+            state: Mutex::new(state),
+        }
+    }
+
+    async fn acquire(semaphore: &Arc<Self>, priority: RequestPriority) -> Permit {
+        let receiver = {
+            let mut state = semaphore.state.lock().unwrap();
+            if state.available > 0 {
+                state.available -= 1;
+                return Permit {
+                    semaphore: Arc::clone(semaphore),
+                };
+            }
+            let (sender, receiver) = oneshot::channel();
+            match priority {
+                RequestPriority::High => state.high_waiters.push_back(sender),
+                RequestPriority::Low => state.low_waiters.push_back(sender),
+            }
+            receiver
+        };
+
+        let mut waiter = Waiter {
+            semaphore: semaphore.as_ref(),
+            receiver: Some(receiver),
+        };
+        waiter
+            .receiver
+            .as_mut()
+            .unwrap()
+            .await
+            .expect("waiters are only removed by sending them a permit");
+        waiter.receiver = None;
+        Permit {
+            semaphore: Arc::clone(semaphore),
+        }
+    }
+
+    /// Hand a released permit to the next waiter, or make it available if there isn't one
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        while let Some(sender) = state.high_waiters.pop_front().or_else(|| state.low_waiters.pop_front()) {
+            // The send fails if the waiter gave up, so try the next one
+            if sender.send(()).is_ok() {
+                return;
+            }
+        }
+        state.available += 1;
+    }
+}
+
+/// A permit from a [PrioritySemaphore], released when dropped
+#[derive(Debug)]
+struct Permit {
+    semaphore: Arc<PrioritySemaphore>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.semaphore.release();
+    }
+}
+
+/// A request waiting for a permit. If the request is cancelled after it was handed a permit but
+/// before it noticed, the permit is released again when this is dropped.
+struct Waiter<'a> {
+    semaphore: &'a PrioritySemaphore,
+    receiver: Option<oneshot::Receiver<()>>,
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        if let Some(mut receiver) = self.receiver.take() {
+            receiver.close();
+            if let Ok(Some(())) = receiver.try_recv() {
+                self.semaphore.release();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::{pin_mut, poll};
+    use mountpoint_s3_client::mock_client::{MockClient, MockClientConfig, MockObject};
+    use mountpoint_s3_client::ETag;
+
+    #[test]
+    fn high_priority_requests_dispatched_first() {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+        });
+        client.add_object("key", MockObject::constant(0xa1, 16, ETag::for_tests()));
+        let client = ConcurrencyLimitedClient::new(client, Some(1));
+        let params = GetObjectParams::new();
+        let dispatched = Mutex::new(Vec::new());
+
+        block_on(async {
+            // Saturate the budget with a prefetch that's still streaming its body
+            let saturating = with_priority(RequestPriority::Low, client.get_object("test_bucket", "key", &params))
+                .await
+                .unwrap();
+
+            let request = |name: &'static str, priority: RequestPriority| {
+                let client = &client;
+                let params = &params;
+                let dispatched = &dispatched;
+                async move {
+                    let body = with_priority(priority, client.get_object("test_bucket", "key", params))
+                        .await
+                        .unwrap();
+                    dispatched.lock().unwrap().push(name);
+                    drop(body);
+                }
+            };
+            let prefetch1 = request("prefetch1", RequestPriority::Low);
+            let prefetch2 = request("prefetch2", RequestPriority::Low);
+            let read = request("read", RequestPriority::High);
+            pin_mut!(prefetch1, prefetch2, read);
+
+            // Queue up the prefetches first, then the on-demand read
+            assert!(poll!(prefetch1.as_mut()).is_pending());
+            assert!(poll!(prefetch2.as_mut()).is_pending());
+            assert!(poll!(read.as_mut()).is_pending());
+
+            drop(saturating);
+            futures::join!(prefetch1, prefetch2, read);
+        });
+
+        assert_eq!(*dispatched.lock().unwrap(), ["read", "prefetch1", "prefetch2"]);
+    }
+
+    #[test]
+    fn cancelled_waiter_releases_permit() {
+        let semaphore = Arc::new(PrioritySemaphore::new(1));
+        block_on(async {
+            let permit = PrioritySemaphore::acquire(&semaphore, RequestPriority::High).await;
+            {
+                let waiter = PrioritySemaphore::acquire(&semaphore, RequestPriority::High);
+                pin_mut!(waiter);
+                assert!(poll!(waiter.as_mut()).is_pending());
+                // Hand the permit to the waiter, but cancel it before it notices
+                drop(permit);
+            }
+            let _permit = PrioritySemaphore::acquire(&semaphore, RequestPriority::Low).await;
+        });
+    }
+}
@@ -7,6 +7,8 @@ use std::{fs, fs::File};
 use anyhow::{anyhow, Context as _};
 use clap::{value_parser, ArgGroup, Parser};
 use fuser::{MountOption, Session};
+use mountpoint_s3::build_info;
+use mountpoint_s3::control::ClientMetricsSource;
 use mountpoint_s3::fs::S3FilesystemConfig;
 use mountpoint_s3::fuse::session::FuseSession;
 use mountpoint_s3::fuse::S3FuseFilesystem;
@@ -14,7 +16,8 @@ use mountpoint_s3::metrics::{metrics_tracing_span_layer, MetricsSink};
 use mountpoint_s3::prefix::Prefix;
 use mountpoint_s3_client::ImdsCrtClient;
 use mountpoint_s3_client::{
-    AddressingStyle, Endpoint, HeadBucketError, ObjectClientError, S3ClientConfig, S3CrtClient,
+    AccessPointArn, AccessPointKind, AddressingStyle, Endpoint, ExpressMode, HeadBucketError, ObjectClientError,
+    RetryStrategyConfig, S3ClientConfig, S3CrtClient,
 };
 use mountpoint_s3_crt::common::rust_log_adapter::RustLogAdapter;
 use nix::sys::signal::Signal;
@@ -27,8 +30,6 @@ use tracing_subscriber::{
     filter::EnvFilter, filter::LevelFilter, layer::SubscriberExt, util::SubscriberInitExt, Layer,
 };
 
-mod build_info;
-
 fn init_tracing_subscriber(is_foreground: bool, log_directory: Option<&Path>) -> anyhow::Result<()> {
     const LOG_DIRECTORY: &str = ".mountpoint-s3";
     const LOG_FILE_NAME_FORMAT: &[FormatItem<'static>] =
@@ -88,7 +89,7 @@ const AWS_CREDENTIALS: &str = "AWS credentials options";
 #[clap(about = "Mountpoint for Amazon S3", version = build_info::FULL_VERSION)]
 #[clap(group(ArgGroup::new("addressing-style").args(&["virtual_addressing", "path_addressing"])))]
 struct CliArgs {
-    #[clap(help = "Name of bucket to mount", value_parser = parse_bucket_name)]
+    #[clap(help = "Name of bucket (or ARN of access point) to mount", value_parser = parse_bucket_name)]
     pub bucket_name: String,
 
     #[clap(help = "Mount point for file system")]
@@ -100,11 +101,18 @@ struct CliArgs {
     #[clap(
         long,
         default_value = "",
-        help = "Prefix inside the bucket to mount, ending in '/' [default: mount the entire bucket]",
+        help = "Prefix inside the bucket to mount, with '/' appended if missing [default: mount the entire bucket]",
         help_heading = BUCKET_OPTIONS_HEADER
     )]
     pub prefix: Prefix,
 
+    #[clap(
+        long,
+        help = "Allow mounting a prefix that has no objects under it",
+        help_heading = BUCKET_OPTIONS_HEADER
+    )]
+    pub allow_empty: bool,
+
     #[clap(
         long,
         help = "AWS region of the bucket [default: auto-detect region]",
@@ -128,6 +136,16 @@ struct CliArgs {
     #[clap(long, help = "Set the 'x-amz-request-payer' to 'requester' on S3 requests", help_heading = BUCKET_OPTIONS_HEADER)]
     pub requester_pays: bool,
 
+    #[clap(
+        long,
+        default_value = "auto",
+        help = "Use S3 Express One Zone session authentication (auto, on, off). auto enables it for directory buckets",
+        help_heading = BUCKET_OPTIONS_HEADER,
+        value_name = "MODE",
+        value_parser = parse_express_mode,
+    )]
+    pub express_mode: ExpressMode,
+
     #[clap(long, help = "Automatically unmount on exit", help_heading = MOUNT_OPTIONS_HEADER)]
     pub auto_unmount: bool,
 
@@ -169,6 +187,22 @@ struct CliArgs {
     )]
     pub part_size: Option<u64>,
 
+    #[clap(
+        long,
+        help = "Maximum number of times to retry a failed read request [default: 3]",
+        value_name = "N",
+        help_heading = CLIENT_OPTIONS_HEADER
+    )]
+    pub read_max_retries: Option<usize>,
+
+    #[clap(
+        long,
+        help = "Maximum number of times to retry a failed write request [default: 3]",
+        value_name = "N",
+        help_heading = CLIENT_OPTIONS_HEADER
+    )]
+    pub write_max_retries: Option<usize>,
+
     #[clap(
         long,
         help = "Owner UID [default: current user's UID]",
@@ -201,6 +235,50 @@ struct CliArgs {
     )]
     pub file_mode: Option<u16>,
 
+    #[clap(
+        long,
+        help = "Allow reading historical versions of objects through hidden `<name>@v=<version id>` paths",
+        help_heading = MOUNT_OPTIONS_HEADER
+    )]
+    pub expose_versions: bool,
+
+    #[clap(
+        long,
+        help = "Allow reading byte ranges of objects through hidden `<name>@range=<first>-<last>` paths",
+        help_heading = MOUNT_OPTIONS_HEADER
+    )]
+    pub expose_byte_ranges: bool,
+
+    #[clap(
+        long,
+        help = "Allow appending to existing objects by opening them with O_APPEND. \
+                The entire object is read into memory and uploaded again on close.",
+        help_heading = MOUNT_OPTIONS_HEADER
+    )]
+    pub allow_append: bool,
+
+    #[clap(
+        long,
+        help = "Accept writes but don't upload them to S3, only log the objects that would have been written",
+        help_heading = MOUNT_OPTIONS_HEADER
+    )]
+    pub dry_run_writes: bool,
+
+    #[clap(
+        long,
+        help = "Upload new files while they're written rather than when they're closed, with a bounded amount of memory. Failed uploads aren't retried",
+        help_heading = MOUNT_OPTIONS_HEADER
+    )]
+    pub stream_writes: bool,
+
+    #[clap(
+        long,
+        help = "Allow files to be swapped with renameat2(RENAME_EXCHANGE). S3 can't swap objects atomically, so the \
+                swap copies them through a temporary object, which a failure can leave behind",
+        help_heading = MOUNT_OPTIONS_HEADER
+    )]
+    pub allow_nonatomic_exchange: bool,
+
     #[clap(short, long, help = "Run as foreground process")]
     pub foreground: bool,
 
@@ -368,6 +446,8 @@ fn mount(args: CliArgs) -> anyhow::Result<FuseSession> {
             });
     tracing::info!("target network throughput {throughput_target_gbps} Gbps");
 
+    let access_point_arn = args.bucket_name.parse::<AccessPointArn>().ok();
+
     let client_config = S3ClientConfig {
         profile_name_override: args.profile,
         no_sign_request: args.no_sign_request,
@@ -375,12 +455,35 @@ fn mount(args: CliArgs) -> anyhow::Result<FuseSession> {
         part_size: args.part_size.map(|t| t as usize),
         endpoint,
         user_agent_prefix: Some(format!("mountpoint-s3/{}", build_info::FULL_VERSION)),
+        user_agent_suffix: None,
+        app_id: None,
         request_payer: args.requester_pays.then_some("requester".to_owned()),
+        express_mode: args.express_mode,
+        enable_multi_region_access_points: access_point_arn
+            .as_ref()
+            .map_or(false, |arn| arn.kind == AccessPointKind::MultiRegion),
+        retry_config: RetryStrategyConfig {
+            read_max_retries: args.read_max_retries,
+            write_max_retries: args.write_max_retries,
+            ..Default::default()
+        },
+        connect_timeout: None,
+        keep_alive_interval: None,
+        address_family: Default::default(),
+        // Let a few parts of each streamed file upload at once
+        upload_buffer_size: args
+            .stream_writes
+            .then(|| 4 * args.part_size.unwrap_or(8 * 1024 * 1024) as usize),
     };
 
+    // Access point ARNs carry their own region (except for multi-region access points), so use it
+    // unless one was given explicitly
+    let arn_region = access_point_arn
+        .map(|arn| arn.region)
+        .filter(|region| !region.is_empty());
     let client = create_client_for_bucket(
         &args.bucket_name,
-        args.region.as_deref(),
+        args.region.as_deref().or(arn_region.as_deref()),
         client_config,
         addressing_style,
     )
@@ -403,8 +506,26 @@ fn mount(args: CliArgs) -> anyhow::Result<FuseSession> {
     if let Some(part_size) = args.part_size {
         filesystem_config.prefetcher_config.part_alignment = part_size as usize;
     }
+    filesystem_config.expose_versions = args.expose_versions;
+    filesystem_config.expose_byte_ranges = args.expose_byte_ranges;
+    filesystem_config.allow_empty_prefix = args.allow_empty;
+    filesystem_config.enable_append = args.allow_append;
+    filesystem_config.dry_run_writes = args.dry_run_writes;
+    filesystem_config.allow_nonatomic_exchange = args.allow_nonatomic_exchange;
+    if args.stream_writes {
+        filesystem_config.stream_writes_buffer_size = Some(args.part_size.unwrap_or(8 * 1024 * 1024) as usize);
+    }
+    filesystem_config.client_metrics = Some(ClientMetricsSource::new(client.client_metrics_source()));
 
     let fs = S3FuseFilesystem::new(client, runtime, &args.bucket_name, &args.prefix, filesystem_config);
+    futures::executor::block_on(fs.verify_prefix()).with_context(|| {
+        format!(
+            "Failed to verify prefix {} (use --allow-empty to mount it anyway)",
+            args.prefix
+        )
+    })?;
+    futures::executor::block_on(fs.abort_orphaned_uploads())
+        .context("Failed to clean up orphaned multipart uploads")?;
 
     let fs_name = String::from("mountpoint-s3");
     let mut options = vec![
@@ -504,9 +625,23 @@ fn parse_perm_bits(perm_bit_str: &str) -> Result<u16, anyhow::Error> {
     }
 }
 
+fn parse_express_mode(mode: &str) -> anyhow::Result<ExpressMode> {
+    match mode {
+        "auto" => Ok(ExpressMode::Automatic),
+        "on" => Ok(ExpressMode::On),
+        "off" => Ok(ExpressMode::Off),
+        _ => Err(anyhow!("must be one of auto, on, or off")),
+    }
+}
+
 /// Validate a bucket name. This isn't intended to be an exhaustive validation, just a quick filter
 /// to catch common CLI mistakes like using an S3 URI (`s3://bucket/`) or a path (`~/mnt`).
 fn parse_bucket_name(bucket_name: &str) -> anyhow::Result<String> {
+    if bucket_name.starts_with("arn:") {
+        bucket_name.parse::<AccessPointArn>()?;
+        return Ok(bucket_name.to_owned());
+    }
+
     if bucket_name.len() < 3 || bucket_name.len() > 255 {
         return Err(anyhow!("bucket names must be 3-255 characters long"));
     }
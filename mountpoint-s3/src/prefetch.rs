@@ -9,22 +9,30 @@
 
 mod part;
 mod part_queue;
+mod reply_buffer;
 
 use std::collections::VecDeque;
 use std::fmt::Debug;
+use std::ops::Range;
 use std::time::Duration;
 
-use bytes::{Bytes, BytesMut};
+use bytes::Bytes;
+use futures::future::{select, Either};
 use futures::pin_mut;
 use futures::stream::StreamExt;
 use futures::task::{Spawn, SpawnExt};
 use metrics::counter;
-use mountpoint_s3_client::{ETag, GetObjectError, ObjectClient, ObjectClientError};
+use mountpoint_s3_client::timer::sleep;
+use mountpoint_s3_client::{
+    ETag, GetObjectError, GetObjectParams, GetObjectResultExt, ObjectClient, ObjectClientError,
+};
 use thiserror::Error;
-use tracing::{debug_span, error, trace, Instrument};
+use tracing::{debug_span, error, trace, warn, Instrument};
 
+use crate::limiter::{with_priority, RequestPriority};
 use crate::prefetch::part::Part;
 use crate::prefetch::part_queue::{unbounded_part_queue, PartQueue};
+pub use crate::prefetch::reply_buffer::{PooledBuffer, ReplyBuffer, ReplyBufferPool};
 use crate::sync::{Arc, RwLock};
 
 type TaskError<Client> = ObjectClientError<GetObjectError, <Client as ObjectClient>::ClientError>;
@@ -41,8 +49,25 @@ pub struct PrefetcherConfig {
     pub read_timeout: Duration,
     /// The size of the parts that the prefetcher is trying to align with
     pub part_alignment: usize,
+    /// If set, a GetObject request whose first body part doesn't arrive within this time is
+    /// cancelled and sent again, up to [FIRST_BYTE_ATTEMPTS] times, after which the last attempt
+    /// waits as long as it takes. A new request usually goes to a different S3 front end than a
+    /// slow one. Once the first part arrives, the rest of the body isn't timed.
+    pub first_byte_timeout: Option<Duration>,
+    /// Check that each GetObject request returns as many bytes as the object's metadata said it
+    /// would, and fail reads with [PrefetchReadError::ObjectSizeMismatch] if one ends early, which
+    /// means the object changed size since it was opened. Otherwise a request that ends early fails
+    /// with [PrefetchReadError::GetRequestTerminatedUnexpectedly]. On by default in debug builds.
+    pub check_object_size: bool,
+    /// Number of times a GetObject request whose body fails partway through is sent again for just
+    /// the bytes it hadn't returned yet, rather than failing the reads that need them
+    pub resume_attempts: usize,
 }
 
+/// Number of times a request is sent when each attempt has to get its first byte within
+/// [PrefetcherConfig::first_byte_timeout]
+pub const FIRST_BYTE_ATTEMPTS: usize = 3;
+
 impl Default for PrefetcherConfig {
     fn default() -> Self {
         Self {
@@ -51,6 +76,9 @@ impl Default for PrefetcherConfig {
             sequential_prefetch_multiplier: 8,
             read_timeout: Duration::from_secs(60),
             part_alignment: 8 * 1024 * 1024,
+            first_byte_timeout: None,
+            check_object_size: cfg!(debug_assertions),
+            resume_attempts: 3,
         }
     }
 }
@@ -66,8 +94,13 @@ struct PrefetcherInner<Client, Runtime> {
     client: Arc<Client>,
     config: PrefetcherConfig,
     runtime: Runtime,
+    reply_buffers: Arc<ReplyBufferPool>,
 }
 
+/// Maximum number of idle buffers kept for replies to reads that span more than one part. Each
+/// open file can only have one read in flight, so this only needs to cover concurrent readers.
+const MAX_POOLED_REPLY_BUFFERS: usize = 16;
+
 impl<Client, Runtime> Prefetcher<Client, Runtime>
 where
     Client: ObjectClient + Send + Sync + 'static,
@@ -79,14 +112,37 @@ where
             client,
             config,
             runtime,
+            reply_buffers: Arc::new(ReplyBufferPool::new(MAX_POOLED_REPLY_BUFFERS)),
         };
 
         Self { inner: Arc::new(inner) }
     }
 
-    /// Start a new get request to the specified object.
-    pub fn get(&self, bucket: &str, key: &str, size: u64, etag: ETag) -> PrefetchGetObject<Client, Runtime> {
-        PrefetchGetObject::new(Arc::clone(&self.inner), bucket, key, size, etag)
+    /// Start a new get request to the specified object. If `version_id` is given, that version of
+    /// the object is read rather than the current one.
+    pub fn get(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        size: u64,
+        etag: ETag,
+    ) -> PrefetchGetObject<Client, Runtime> {
+        PrefetchGetObject::new(Arc::clone(&self.inner), bucket, key, version_id, 0..size, etag)
+    }
+
+    /// Start a new get request to a range of bytes of the specified object. Reads are still at
+    /// offsets into the whole object, but prefetching starts at the beginning of the range and
+    /// never goes past its end.
+    pub fn get_range(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        range: Range<u64>,
+        etag: ETag,
+    ) -> PrefetchGetObject<Client, Runtime> {
+        PrefetchGetObject::new(Arc::clone(&self.inner), bucket, key, version_id, range, etag)
     }
 }
 
@@ -100,11 +156,15 @@ pub struct PrefetchGetObject<Client: ObjectClient, Runtime> {
     future_tasks: Arc<RwLock<VecDeque<RequestTask<TaskError<Client>>>>>,
     bucket: String,
     key: String,
+    version_id: Option<String>,
     next_sequential_read_offset: u64,
     next_request_size: usize,
     next_request_offset: u64,
     size: u64,
     etag: ETag,
+    /// Whether to fetch ahead of sequential reads. If not, each request only covers the read that
+    /// needed it.
+    readahead: bool,
 }
 
 impl<Client, Runtime> PrefetchGetObject<Client, Runtime>
@@ -113,25 +173,38 @@ where
     Runtime: Spawn,
 {
     /// Create and spawn a new prefetching request for an object
-    fn new(inner: Arc<PrefetcherInner<Client, Runtime>>, bucket: &str, key: &str, size: u64, etag: ETag) -> Self {
+    fn new(
+        inner: Arc<PrefetcherInner<Client, Runtime>>,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        range: Range<u64>,
+        etag: ETag,
+    ) -> Self {
         PrefetchGetObject {
             inner: inner.clone(),
             current_task: None,
             future_tasks: Default::default(),
             next_request_size: inner.config.first_request_size,
-            next_sequential_read_offset: 0,
-            next_request_offset: 0,
+            next_sequential_read_offset: range.start,
+            next_request_offset: range.start,
             bucket: bucket.to_owned(),
             key: key.to_owned(),
-            size,
+            version_id: version_id.map(str::to_owned),
+            size: range.end,
             etag,
+            readahead: true,
         }
     }
 
     /// Read some bytes from the object. This function will always return exactly `size` bytes,
     /// except at the end of the object where it will return however many bytes are left (including
     /// possibly 0 bytes).
-    pub async fn read(&mut self, offset: u64, length: usize) -> Result<Bytes, PrefetchReadError<TaskError<Client>>> {
+    pub async fn read(
+        &mut self,
+        offset: u64,
+        length: usize,
+    ) -> Result<ReplyBuffer, PrefetchReadError<TaskError<Client>>> {
         trace!(
             offset,
             length,
@@ -141,7 +214,7 @@ where
 
         let remaining = self.size.saturating_sub(offset);
         if remaining == 0 {
-            return Ok(Bytes::new());
+            return Ok(ReplyBuffer::Part(Bytes::new()));
         }
         let mut to_read = (length as u64).min(remaining);
 
@@ -153,26 +226,23 @@ where
                 "out-of-order read, resetting prefetch"
             );
             counter!("prefetch.out_of_order", 1);
-            // TODO cancel inflight requests
-            // TODO see if we can reuse any inflight requests rather than dropping them immediately
-            self.current_task = None;
-            self.future_tasks.write().unwrap().drain(..);
-            self.next_request_size = self.inner.config.first_request_size;
-            self.next_sequential_read_offset = offset;
-            self.next_request_offset = offset;
+            self.reset(offset);
         }
         debug_assert_eq!(self.next_sequential_read_offset, offset);
 
+        if !self.readahead {
+            self.next_request_size = (to_read as usize).max(1);
+        }
         self.prepare_requests();
 
         // If [prepare_requests] didn't spawn a request, then we must have reached the end of the
         // object.
         if self.current_task.is_none() {
             trace!(offset, length, "read beyond object size");
-            return Ok(Bytes::new());
+            return Ok(ReplyBuffer::Part(Bytes::new()));
         }
 
-        let mut response = BytesMut::new();
+        let mut response: Option<PooledBuffer> = None;
         while to_read > 0 {
             let current_task = self.current_task.as_mut().unwrap();
             debug_assert!(current_task.remaining > 0);
@@ -193,13 +263,21 @@ where
             // If we can complete the read with just a single buffer, early return to avoid copying
             // into a new buffer. This should be the common case as long as part size is larger than
             // read size, which it almost always is for real S3 clients and FUSE.
-            if response.is_empty() && part_bytes.len() == to_read as usize {
-                return Ok(part_bytes);
+            if response.is_none() && part_bytes.len() == to_read as usize {
+                return Ok(ReplyBuffer::Part(part_bytes));
             }
 
-            response.extend_from_slice(&part_bytes[..]);
+            response
+                .get_or_insert_with(|| self.inner.reply_buffers.get(to_read as usize))
+                .extend_from_slice(&part_bytes[..]);
             to_read -= part_bytes.len() as u64;
             if current_task.remaining == 0 {
+                if !self.readahead {
+                    if to_read == 0 {
+                        break;
+                    }
+                    self.next_request_size = to_read as usize;
+                }
                 self.prepare_requests();
                 if self.current_task.is_none() {
                     break;
@@ -207,7 +285,52 @@ where
             }
         }
 
-        Ok(response.freeze())
+        Ok(response.map_or_else(|| ReplyBuffer::Part(Bytes::new()), ReplyBuffer::Pooled))
+    }
+
+    /// Start fetching a range of the object before it's read, because the reader has said it's
+    /// going to read it. The range is fetched with as few requests as possible, and reads from the
+    /// start of the range onwards use the prefetched data.
+    pub fn prefetch(&mut self, range: Range<u64>) {
+        let end = range.end.min(self.size);
+        if range.start >= end {
+            return;
+        }
+        trace!(?range, "prefetch hint");
+        if self.next_sequential_read_offset != range.start {
+            self.reset(range.start);
+        }
+        if self.current_task.is_some() || !self.future_tasks.read().unwrap().is_empty() {
+            // We're already fetching from the start of the range
+            return;
+        }
+        self.next_request_size = ((end - range.start) as usize).min(self.inner.config.max_request_size);
+        self.current_task = self.spawn_next_request(RequestPriority::Low);
+    }
+
+    /// Enable or disable fetching ahead of sequential reads, such as when the reader has said that
+    /// its reads will be random
+    pub fn set_readahead(&mut self, readahead: bool) {
+        self.readahead = readahead;
+        if !readahead {
+            self.future_tasks.write().unwrap().drain(..);
+        }
+    }
+
+    /// Drop any in-flight requests and start the next read from `offset`
+    fn reset(&mut self, offset: u64) {
+        // TODO cancel inflight requests
+        // TODO see if we can reuse any inflight requests rather than dropping them immediately
+        self.current_task = None;
+        self.future_tasks.write().unwrap().drain(..);
+        self.next_request_size = self.inner.config.first_request_size;
+        self.next_sequential_read_offset = offset;
+        self.next_request_offset = offset;
+    }
+
+    /// The pool that buffers for reads spanning more than one part come from
+    pub fn reply_buffers(&self) -> &ReplyBufferPool {
+        &self.inner.reply_buffers
     }
 
     /// Runs on every read to prepare and spawn any requests our prefetching logic requires
@@ -219,22 +342,25 @@ where
                 self.current_task = Some(next_task);
                 return;
             }
-            self.current_task = self.spawn_next_request();
-        } else if current_task
-            .map(|task| task.remaining < task.total_size / 2)
-            .unwrap_or(false)
+            // The reader is waiting on this request, so it takes priority over prefetching
+            self.current_task = self.spawn_next_request(RequestPriority::High);
+        } else if self.readahead
+            && current_task
+                .map(|task| task.remaining < task.total_size / 2)
+                .unwrap_or(false)
             && self.future_tasks.read().unwrap().is_empty()
         {
             // The current task is nearing completion, so pre-spawn the next request in anticipation
             // of it completing.
-            if let Some(task) = self.spawn_next_request() {
+            if let Some(task) = self.spawn_next_request(RequestPriority::Low) {
                 self.future_tasks.write().unwrap().push_back(task);
             }
         }
     }
 
-    /// Spawn the next required request
-    fn spawn_next_request(&mut self) -> Option<RequestTask<TaskError<Client>>> {
+    /// Spawn the next required request. The priority tells the client whether a reader is already
+    /// waiting on the request, or it's only being prefetched.
+    fn spawn_next_request(&mut self, priority: RequestPriority) -> Option<RequestTask<TaskError<Client>>> {
         let start = self.next_request_offset;
         let end = (start + self.next_request_size as u64).min(self.size);
 
@@ -253,23 +379,81 @@ where
             let client = Arc::clone(&self.inner.client);
             let bucket = self.bucket.to_owned();
             let key = self.key.to_owned();
+            let version_id = self.version_id.clone();
             let etag = self.etag.clone();
+            let first_byte_timeout = self.inner.config.first_byte_timeout;
+            let resume_attempts = self.inner.config.resume_attempts;
             let span = debug_span!("prefetch", range=?range);
 
             async move {
-                match client.get_object(&bucket, &key, Some(range.clone()), Some(etag)).await {
+                let params = GetObjectParams::new()
+                    .range(Some(range.clone()))
+                    .if_match(Some(etag))
+                    .version_id(version_id.as_deref());
+
+                // Wait for the first part separately, so that a request that's slow to respond can be
+                // abandoned and sent again
+                let mut attempt = 1;
+                let first_part = loop {
+                    let first_part = async {
+                        let request = with_priority(priority, client.get_object(&bucket, &key, &params)).await?;
+                        // Take ownership of each part's buffer so reads can slice it without copying
+                        let mut request = Box::pin(request.into_shared_parts());
+                        let part = request.next().await;
+                        Ok::<_, TaskError<Client>>((request, part))
+                    };
+                    match first_byte_timeout {
+                        Some(timeout) if attempt < FIRST_BYTE_ATTEMPTS => {
+                            let timer = sleep(timeout);
+                            pin_mut!(first_part, timer);
+                            match select(first_part, timer).await {
+                                Either::Left((result, _)) => break result,
+                                Either::Right(((), _)) => {
+                                    warn!(
+                                        attempt,
+                                        ?timeout,
+                                        "no response to get request in time, sending it again"
+                                    );
+                                    counter!("prefetch.first_byte_timeouts", 1);
+                                    attempt += 1;
+                                }
+                            }
+                        }
+                        _ => break first_part.await,
+                    }
+                };
+
+                match first_part {
                     Err(e) => {
                         error!(error=?e, "RequestTask get object failed");
                         part_queue_producer.push(Err(e));
                     }
-                    Ok(request) => {
-                        pin_mut!(request);
+                    Ok((mut request, mut part)) => {
+                        // Parts already pushed stay readable while a failed request is resumed, so
+                        // track where the next one should start
+                        let mut next_offset = range.start;
+                        let mut resumes = 0;
                         loop {
-                            match request.next().await {
+                            match part {
                                 Some(Ok((offset, body))) => {
-                                    let part = Part::new(&key, offset, body.into());
+                                    next_offset = offset + body.len() as u64;
+                                    let part = Part::new(&key, offset, body);
                                     part_queue_producer.push(Ok(part));
                                 }
+                                Some(Err(e)) if resumes < resume_attempts && next_offset < range.end => {
+                                    resumes += 1;
+                                    warn!(error=?e, next_offset, resumes, "RequestTask body part failed, resuming");
+                                    counter!("prefetch.resumed_requests", 1);
+                                    let params = params.clone().range(Some(next_offset..range.end));
+                                    match with_priority(priority, client.get_object(&bucket, &key, &params)).await {
+                                        Ok(resumed) => request = Box::pin(resumed.into_shared_parts()),
+                                        Err(e) => {
+                                            error!(error=?e, "RequestTask resumed get object failed");
+                                            part_queue_producer.push(Err(e));
+                                            break;
+                                        }
+                                    }
+                                }
                                 Some(Err(e)) => {
                                     error!(error=?e, "RequestTask body part failed");
                                     part_queue_producer.push(Err(e));
@@ -277,6 +461,7 @@ where
                                 }
                                 None => break,
                             }
+                            part = request.next().await;
                         }
                         trace!("finished");
                     }
@@ -296,6 +481,7 @@ where
             total_size: size as usize,
             remaining: size as usize,
             part_queue,
+            check_size: self.inner.config.check_object_size,
         })
     }
 
@@ -332,11 +518,28 @@ struct RequestTask<E> {
     remaining: usize,
     total_size: usize,
     part_queue: PartQueue<E>,
+    /// See [PrefetcherConfig::check_object_size]
+    check_size: bool,
 }
 
 impl<E: std::error::Error + Send + Sync> RequestTask<E> {
     async fn read(&mut self, length: usize) -> Result<Part, PrefetchReadError<E>> {
-        let part = self.part_queue.read(length).await?;
+        // The request's task sends errors through the queue, so it only stops sending parts early
+        // if the body ended early
+        let part = match self.part_queue.read(length).await {
+            Err(PrefetchReadError::GetRequestTerminatedUnexpectedly) if self.check_size => {
+                let received = (self.total_size - self.remaining) as u64;
+                error!(
+                    expected = self.total_size,
+                    received, "get request returned fewer bytes than the object's size"
+                );
+                return Err(PrefetchReadError::ObjectSizeMismatch {
+                    expected: self.total_size as u64,
+                    received,
+                });
+            }
+            result => result?,
+        };
         debug_assert!(part.len() <= self.remaining);
         self.remaining -= part.len();
         Ok(part)
@@ -350,6 +553,9 @@ pub enum PrefetchReadError<E: std::error::Error> {
 
     #[error("get request terminated unexpectedly")]
     GetRequestTerminatedUnexpectedly,
+
+    #[error("get request returned {received} of the {expected} bytes the object's size said it would")]
+    ObjectSizeMismatch { expected: u64, received: u64 },
 }
 
 #[cfg(test)]
@@ -360,7 +566,10 @@ mod tests {
     use super::*;
     use futures::executor::{block_on, ThreadPool};
     use mountpoint_s3_client::failure_client::{countdown_failure_client, GetFailureMap};
-    use mountpoint_s3_client::mock_client::{ramp_bytes, MockClient, MockClientConfig, MockClientError, MockObject};
+    use mountpoint_s3_client::intercepting_client::InterceptingClient;
+    use mountpoint_s3_client::mock_client::{
+        ramp_bytes, MockClient, MockClientConfig, MockClientError, MockObject, Operation,
+    };
     use proptest::proptest;
     use proptest::strategy::{Just, Strategy};
     use proptest_derive::Arbitrary;
@@ -399,11 +608,13 @@ mod tests {
             sequential_prefetch_multiplier: test_config.sequential_prefetch_multiplier,
             read_timeout: Duration::from_secs(5),
             part_alignment: test_config.client_part_size,
+            first_byte_timeout: None,
+            check_object_size: true,
         };
         let runtime = ThreadPool::builder().pool_size(1).create().unwrap();
         let prefetcher = Prefetcher::new(Arc::new(client), runtime, test_config);
 
-        let mut request = prefetcher.get("test-bucket", "hello", size, etag);
+        let mut request = prefetcher.get("test-bucket", "hello", None, size, etag);
 
         let mut next_offset = 0;
         loop {
@@ -418,6 +629,91 @@ mod tests {
         assert_eq!(next_offset, size);
     }
 
+    #[test]
+    fn reads_within_a_part_are_not_copied() {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test-bucket".to_string(),
+            part_size: 1 * MB,
+        });
+        let object = MockObject::ramp(0xaa, 4 * MB, ETag::for_tests());
+        let etag = object.etag();
+        client.add_object("hello", object);
+
+        let runtime = ThreadPool::builder().pool_size(1).create().unwrap();
+        let prefetcher = Prefetcher::new(Arc::new(client), runtime, Default::default());
+        let mut request = prefetcher.get("test-bucket", "hello", None, 4 * MB as u64, etag);
+
+        // Consecutive reads from the same part are slices of the buffer the client delivered
+        let first = block_on(request.read(0, 100)).unwrap();
+        let second = block_on(request.read(100, 100)).unwrap();
+        assert!(matches!(first, ReplyBuffer::Part(_)));
+        assert!(matches!(second, ReplyBuffer::Part(_)));
+        assert_eq!(second.as_ptr(), first.as_ptr().wrapping_add(100));
+    }
+
+    #[test_case(Duration::from_millis(500), FIRST_BYTE_ATTEMPTS as u64; "slow first byte")]
+    #[test_case(Duration::ZERO, 1; "fast first byte")]
+    fn first_byte_timeout(latency: Duration, expected_requests: u64) {
+        let client = Arc::new(MockClient::new(MockClientConfig {
+            bucket: "test-bucket".to_string(),
+            part_size: 1 * MB,
+        }));
+        let object = MockObject::ramp(0xaa, 4 * MB, ETag::for_tests());
+        let etag = object.etag();
+        client.add_object("hello", object);
+        client.set_latency(Operation::GetObject, latency);
+
+        let config = PrefetcherConfig {
+            first_byte_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        };
+        let runtime = ThreadPool::builder().pool_size(1).create().unwrap();
+        let prefetcher = Prefetcher::new(client.clone(), runtime, config);
+        let mut request = prefetcher.get("test-bucket", "hello", None, 4 * MB as u64, etag);
+
+        // The last attempt waits for its first byte however long it takes, so the read succeeds
+        let buf = block_on(request.read(0, 100)).unwrap();
+        assert_eq!(&buf[..], &ramp_bytes(0xaa, 100)[..]);
+        assert_eq!(client.op_count(Operation::GetObject), expected_requests);
+    }
+
+    #[test]
+    fn reply_buffers_reused() {
+        let size = 4 * MB;
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test-bucket".to_string(),
+            part_size: 1000,
+        });
+        let object = MockObject::ramp(0xaa, size, ETag::for_tests());
+        let etag = object.etag();
+        client.add_object("hello", object);
+
+        let runtime = ThreadPool::builder().pool_size(1).create().unwrap();
+        let prefetcher = Prefetcher::new(Arc::new(client), runtime, Default::default());
+        let mut request = prefetcher.get("test-bucket", "hello", None, size as u64, etag);
+
+        // Reads are bigger than a part, so almost all of them have to be copied into a buffer
+        let mut next_offset = 0;
+        let mut pooled_reads = 0;
+        loop {
+            let buf = block_on(request.read(next_offset, 1500)).unwrap();
+            if buf.is_empty() {
+                break;
+            }
+            if matches!(buf, ReplyBuffer::Pooled(_)) {
+                pooled_reads += 1;
+            }
+            next_offset += buf.len() as u64;
+        }
+        assert_eq!(next_offset, size as u64);
+        assert!(pooled_reads > 1000);
+        assert!(
+            request.reply_buffers().allocations() <= 2,
+            "buffers should be reused, but {} were allocated",
+            request.reply_buffers().allocations()
+        );
+    }
+
     #[test]
     fn sequential_read_small() {
         let config = TestConfig {
@@ -467,7 +763,7 @@ mod tests {
 
         client.add_object("hello", object);
 
-        let client = countdown_failure_client(client, get_failures, HashMap::new(), HashMap::new());
+        let client = countdown_failure_client(client, get_failures, HashMap::new(), HashMap::new(), HashMap::new());
 
         let test_config = PrefetcherConfig {
             first_request_size: test_config.first_request_size,
@@ -478,7 +774,7 @@ mod tests {
         let runtime = ThreadPool::builder().pool_size(1).create().unwrap();
         let prefetcher = Prefetcher::new(Arc::new(client), runtime, test_config);
 
-        let mut request = prefetcher.get("test-bucket", "hello", size, etag);
+        let mut request = prefetcher.get("test-bucket", "hello", None, size, etag);
 
         let mut next_offset = 0;
         loop {
@@ -519,6 +815,66 @@ mod tests {
         fail_sequential_read_test(1024 * 1024 + 111, 1024 * 1024, config, get_failures);
     }
 
+    #[test_case(3; "resumed")]
+    #[test_case(0; "not resumed")]
+    fn resume_failed_request(resume_attempts: usize) {
+        let size = 10_000;
+        let config = MockClientConfig {
+            bucket: "test-bucket".to_string(),
+            part_size: 1000,
+        };
+        let client = MockClient::new(config);
+        let object = MockObject::ramp(0xaa, size, ETag::for_tests());
+        let etag = object.etag();
+        client.add_object("hello", object);
+
+        // The first request's stream fails on its third part, after returning 2000 bytes
+        let mut get_failures = HashMap::new();
+        get_failures.insert(1, Ok((3, MockClientError("connection reset".into()))));
+        let client = countdown_failure_client(client, get_failures, HashMap::new(), HashMap::new(), HashMap::new());
+        let ranges = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let client = {
+            let ranges = Arc::clone(&ranges);
+            InterceptingClient::new(client).before_get(move |_bucket, _key, params| {
+                ranges.lock().unwrap().push(params.range.clone());
+                Ok(())
+            })
+        };
+
+        let test_config = PrefetcherConfig {
+            first_request_size: 64 * KB,
+            resume_attempts,
+            ..Default::default()
+        };
+        let runtime = ThreadPool::builder().pool_size(1).create().unwrap();
+        let prefetcher = Prefetcher::new(Arc::new(client), runtime, test_config);
+        let mut request = prefetcher.get("test-bucket", "hello", None, size as u64, etag);
+
+        let mut next_offset = 0;
+        let result = loop {
+            match block_on(request.read(next_offset, 1500)) {
+                Ok(buf) if buf.is_empty() => break Ok(()),
+                Ok(buf) => {
+                    let expected = ramp_bytes((0xaa + next_offset) as usize, buf.len());
+                    assert_eq!(&buf[..], &expected[..]);
+                    next_offset += buf.len() as u64;
+                }
+                Err(e) => break Err(e),
+            }
+        };
+
+        let ranges = ranges.lock().unwrap().clone();
+        if resume_attempts > 0 {
+            result.expect("resumed request should return the whole object");
+            assert_eq!(next_offset, size as u64);
+            assert_eq!(ranges, vec![Some(0..size as u64), Some(2000..size as u64)]);
+        } else {
+            assert!(result.is_err());
+            assert_eq!(next_offset, 1500);
+            assert_eq!(ranges, vec![Some(0..size as u64)]);
+        }
+    }
+
     #[test_case(256 * KB, 256 * KB, 8, 100 * MB, 8 * MB, 2 * MB; "next request size is smaller than part size")]
     #[test_case(7 * MB, 256 * KB, 8, 100 * MB, 8 * MB, 1 * MB; "next request size is remaining bytes in the part")]
     #[test_case(9 * MB, (2 * MB) + 11, 11, 100 * MB, 9 * MB, 18 * MB; "next request size is trimmed to part boundaries")]
@@ -548,12 +904,15 @@ mod tests {
             max_request_size,
             read_timeout: Duration::from_secs(60),
             part_alignment: part_size,
+            first_byte_timeout: None,
+            check_object_size: true,
+            resume_attempts: 3,
         };
         let runtime = ThreadPool::builder().pool_size(1).create().unwrap();
         let prefetcher = Prefetcher::new(Arc::new(client), runtime, test_config);
         let etag = ETag::for_tests();
 
-        let mut request = prefetcher.get("test-bucket", "hello", object_size, etag);
+        let mut request = prefetcher.get("test-bucket", "hello", None, object_size, etag);
 
         request.next_request_offset = next_request_offset as u64;
         request.next_request_size = current_request_size;
@@ -598,7 +957,7 @@ mod tests {
         let runtime = ThreadPool::builder().pool_size(1).create().unwrap();
         let prefetcher = Prefetcher::new(Arc::new(client), runtime, test_config);
 
-        let mut request = prefetcher.get("test-bucket", "hello", object_size, etag);
+        let mut request = prefetcher.get("test-bucket", "hello", None, object_size, etag);
 
         for (offset, length) in reads {
             assert!(offset < object_size);
@@ -701,7 +1060,7 @@ mod tests {
 
             let prefetcher = Prefetcher::new(Arc::new(client), ShuttleRuntime, test_config);
 
-            let mut request = prefetcher.get("test-bucket", "hello", object_size, file_etag);
+            let mut request = prefetcher.get("test-bucket", "hello", None, object_size, file_etag);
 
             let mut next_offset = 0;
             loop {
@@ -753,7 +1112,7 @@ mod tests {
 
             let prefetcher = Prefetcher::new(Arc::new(client), ShuttleRuntime, test_config);
 
-            let mut request = prefetcher.get("test-bucket", "hello", object_size, file_etag);
+            let mut request = prefetcher.get("test-bucket", "hello", None, object_size, file_etag);
 
             let num_reads = rng.gen_range(10usize..50);
             for _ in 0..num_reads {
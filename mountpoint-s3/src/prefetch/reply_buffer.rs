@@ -0,0 +1,129 @@
+//! Buffers for the data returned by [PrefetchGetObject::read](super::PrefetchGetObject::read).
+//!
+//! Most reads are served straight from a single prefetched part without copying. Reads that span a
+//! part boundary have to be copied into a new buffer, and for a sequential read loop that's a fresh
+//! allocation every few reads. A [ReplyBufferPool] keeps those buffers around once a reply has been
+//! sent so the next read that needs one can reuse it.
+
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bytes::Bytes;
+
+use crate::sync::{Arc, Mutex};
+
+/// A pool of buffers for assembling replies to reads. At most `max_buffers` idle buffers are kept;
+/// any more are freed when they're returned.
+#[derive(Debug)]
+pub struct ReplyBufferPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+    max_buffers: usize,
+    allocations: AtomicUsize,
+}
+
+impl ReplyBufferPool {
+    pub fn new(max_buffers: usize) -> Self {
+        Self {
+            buffers: Mutex::new(Vec::new()),
+            max_buffers,
+            allocations: AtomicUsize::new(0),
+        }
+    }
+
+    /// Take an empty buffer with room for at least `capacity` bytes from the pool, allocating one
+    /// if there are none to reuse. The buffer goes back to the pool when it's dropped.
+    pub fn get(self: &Arc<Self>, capacity: usize) -> PooledBuffer {
+        let mut buffer = self.buffers.lock().unwrap().pop().unwrap_or_default();
+        if buffer.capacity() < capacity {
+            self.allocations.fetch_add(1, Ordering::Relaxed);
+            buffer.reserve(capacity);
+        }
+        PooledBuffer {
+            buffer,
+            pool: Arc::clone(self),
+        }
+    }
+
+    /// Number of times the pool has had to allocate (or grow) a buffer rather than reuse one
+    pub fn allocations(&self) -> usize {
+        self.allocations.load(Ordering::Relaxed)
+    }
+
+    fn put(&self, mut buffer: Vec<u8>) {
+        let mut buffers = self.buffers.lock().unwrap();
+        if buffers.len() < self.max_buffers {
+            buffer.clear();
+            buffers.push(buffer);
+        }
+    }
+}
+
+/// A buffer borrowed from a [ReplyBufferPool]
+#[derive(Debug)]
+pub struct PooledBuffer {
+    buffer: Vec<u8>,
+    pool: Arc<ReplyBufferPool>,
+}
+
+impl Deref for PooledBuffer {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buffer
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        self.pool.put(std::mem::take(&mut self.buffer));
+    }
+}
+
+/// The data returned by a read, either a slice of a single prefetched part or a pooled buffer
+/// holding data copied from several parts
+#[derive(Debug)]
+pub enum ReplyBuffer {
+    Part(Bytes),
+    Pooled(PooledBuffer),
+}
+
+impl Deref for ReplyBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            ReplyBuffer::Part(bytes) => bytes,
+            ReplyBuffer::Pooled(buffer) => buffer,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffers_are_reused() {
+        let pool = Arc::new(ReplyBufferPool::new(2));
+
+        for i in 0..1000 {
+            let mut buffer = pool.get(4096);
+            assert!(buffer.is_empty());
+            buffer.extend_from_slice(&[i as u8; 4096]);
+        }
+        assert_eq!(pool.allocations(), 1);
+
+        // Buffers beyond the limit are freed rather than pooled
+        let buffers: Vec<_> = (0..4).map(|_| pool.get(4096)).collect();
+        assert_eq!(pool.allocations(), 4);
+        drop(buffers);
+        let _buffers: Vec<_> = (0..4).map(|_| pool.get(4096)).collect();
+        assert_eq!(pool.allocations(), 6);
+    }
+}
@@ -5,26 +5,53 @@ use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum PrefixError {
-    #[error("prefix must end in '/'")]
-    MissingFinalDelimiter,
+    #[error("prefix must not be only whitespace")]
+    OnlyWhitespace,
 }
 
-/// A prefix string ending in `/`, or the empty string
+/// A prefix string ending in `/`, or the empty string for the whole bucket
 #[derive(Debug, Clone, Default)]
 pub struct Prefix {
     path: String,
 }
 
 impl Prefix {
+    /// Create a new prefix. The empty string means the whole bucket; any other prefix is treated
+    /// as a directory, so has a trailing `/` appended if it doesn't already end in one.
     pub fn new(prefix: &str) -> Result<Self, PrefixError> {
-        if !prefix.is_empty() && !prefix.ends_with('/') {
-            Err(PrefixError::MissingFinalDelimiter)
-        } else {
+        if prefix.is_empty() {
+            Ok(Self::default())
+        } else if prefix.trim().is_empty() {
+            Err(PrefixError::OnlyWhitespace)
+        } else if prefix.ends_with('/') {
             Ok(Self {
                 path: prefix.to_owned(),
             })
+        } else {
+            Ok(Self {
+                path: format!("{prefix}/"),
+            })
         }
     }
+
+    /// Whether this prefix is the whole bucket
+    pub fn is_root(&self) -> bool {
+        self.path.is_empty()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.path
+    }
+
+    /// The full S3 key of a key relative to this prefix
+    pub fn join(&self, key: &str) -> String {
+        format!("{}{key}", self.path)
+    }
+
+    /// The key relative to this prefix of a full S3 key, or None if the key isn't under this prefix
+    pub fn strip<'a>(&self, full_key: &'a str) -> Option<&'a str> {
+        full_key.strip_prefix(self.path.as_str())
+    }
 }
 
 impl Display for Prefix {
@@ -48,12 +75,36 @@ mod tests {
     use super::*;
 
     #[test_case(" "; "whitespace")]
-    #[test_case("hello"; "not ending in slash")]
-    #[test_case("hello/world"; "nested folder not ending in slash")]
+    #[test_case("\t\n"; "other whitespace")]
     fn test_invalid_prefix(prefix: &str) {
         assert!(Prefix::new(prefix).is_err(), "Prefix should be invalid: '{}'", prefix);
     }
 
+    #[test_case("", ""; "empty string")]
+    #[test_case("foo", "foo/"; "not ending in slash")]
+    #[test_case("foo/", "foo/"; "ending in slash")]
+    #[test_case("foo/bar", "foo/bar/"; "nested folder not ending in slash")]
+    fn test_normalized_prefix(prefix: &str, expected: &str) {
+        let prefix = Prefix::new(prefix).expect("valid prefix");
+        assert_eq!(prefix.as_str(), expected);
+        assert_eq!(prefix.to_string(), expected);
+        assert_eq!(prefix.is_root(), expected.is_empty());
+    }
+
+    #[test]
+    fn test_join_and_strip() {
+        let root = Prefix::new("").unwrap();
+        assert_eq!(root.join("dir/file.txt"), "dir/file.txt");
+        assert_eq!(root.strip("dir/file.txt"), Some("dir/file.txt"));
+
+        let prefix = Prefix::new("foo").unwrap();
+        assert_eq!(prefix.join("dir/file.txt"), "foo/dir/file.txt");
+        assert_eq!(prefix.strip("foo/dir/file.txt"), Some("dir/file.txt"));
+        assert_eq!(prefix.strip("foo/"), Some(""));
+        assert_eq!(prefix.strip("foobar/file.txt"), None);
+        assert_eq!(prefix.strip("bar/file.txt"), None);
+    }
+
     #[test_case(""; "empty string")]
     #[test_case("hello/"; "ending in slash")]
     #[test_case("hello/world/"; "nested folder ending in slash")]
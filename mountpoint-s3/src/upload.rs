@@ -0,0 +1,261 @@
+//! This module implements the upload half of the write path.
+//!
+//! Writes to a file are buffered in memory until the file is closed, and then uploaded as a single
+//! object with PutObject. For large objects, the client turns that PutObject into a multipart
+//! upload. The client retries each request of the upload itself, including a
+//! CompleteMultipartUpload that fails after all the parts were uploaded, so a transient failure
+//! doesn't mean sending the whole object again.
+//!
+//! S3 allows at most 10,000 parts in a multipart upload, so the client's part size on its own would
+//! cap the size of a file at 10,000 parts. Instead, clients grow the part size of an upload that
+//! wouldn't fit (see [upload_part_size](mountpoint_s3_client::upload_part_size)), so files can be
+//! written up to S3's maximum object size of 5 TiB whatever the part size is.
+//!
+//! Alternatively, a [StreamingUpload] starts the PutObject when the file is opened, and its body is
+//! fed by writes as they arrive through a [WriteBuffer] holding at most about one part. The file
+//! system never holds the whole object, but an upload that fails can't be retried, since the data
+//! already sent is gone.
+
+use std::collections::VecDeque;
+use std::fmt::{self, Debug};
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+
+use futures::stream;
+use mountpoint_s3_client::{ObjectClient, ObjectClientResult, PutObjectError, PutObjectParams, PutObjectResult};
+
+use crate::sync::{Arc, Mutex};
+
+/// An [Uploader] uploads the buffered contents of files to S3.
+#[derive(Debug)]
+pub struct Uploader<Client> {
+    client: Arc<Client>,
+}
+
+impl<Client> Uploader<Client>
+where
+    Client: ObjectClient + Send + Sync + 'static,
+{
+    /// Create a new [Uploader] that will make requests to the given client.
+    pub fn new(client: Arc<Client>) -> Self {
+        Self { client }
+    }
+
+    /// Upload the given parts as a single object
+    pub async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectParams,
+        parts: &[Box<[u8]>],
+    ) -> ObjectClientResult<PutObjectResult, PutObjectError, Client::ClientError> {
+        let stream = futures::stream::iter(parts);
+        self.client.put_object(bucket, key, params, stream).await
+    }
+
+    /// Start uploading an object whose contents will be written to the returned [StreamingUpload]
+    /// as they arrive. At most about `buffer_size` bytes are buffered before writes have to wait
+    /// for the upload to take them.
+    pub fn put_object_streaming(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: PutObjectParams,
+        buffer_size: usize,
+    ) -> StreamingUpload<Client> {
+        let buffer = Arc::new(Mutex::new(WriteBuffer::default()));
+        let contents = {
+            let buffer = buffer.clone();
+            stream::poll_fn(move |cx| buffer.lock().unwrap().poll_next_chunk(cx))
+        };
+        let client = self.client.clone();
+        let bucket = bucket.to_owned();
+        let key = key.to_owned();
+        let put = Box::pin(async move { client.put_object(&bucket, &key, &params, contents).await });
+        StreamingUpload {
+            buffer,
+            buffer_size,
+            written: 0,
+            put,
+            result: None,
+        }
+    }
+}
+
+type PutResult<Client> = ObjectClientResult<PutObjectResult, PutObjectError, <Client as ObjectClient>::ClientError>;
+
+/// An upload whose contents are sent as they're written, see [Uploader::put_object_streaming].
+///
+/// The PutObject request only makes progress while the upload is being written to or finished, so
+/// it doesn't need a task of its own.
+pub struct StreamingUpload<Client: ObjectClient> {
+    buffer: Arc<Mutex<WriteBuffer>>,
+    buffer_size: usize,
+    written: usize,
+    put: Pin<Box<dyn Future<Output = PutResult<Client>> + Send>>,
+    /// The result of the request, if it finished before the upload was finished
+    result: Option<PutResult<Client>>,
+}
+
+impl<Client: ObjectClient> StreamingUpload<Client> {
+    /// Number of bytes written to the upload so far
+    pub fn bytes_written(&self) -> usize {
+        self.written
+    }
+
+    /// Most bytes that were ever buffered waiting for the request to take them
+    pub fn peak_buffered(&self) -> usize {
+        self.buffer.lock().unwrap().peak_len
+    }
+
+    /// Queue `data` to be uploaded, first driving the request until it's taken enough of the
+    /// buffered data to make room. Fails if the request finished early, which only happens if it
+    /// failed; [finish](Self::finish) returns why.
+    pub async fn write(&mut self, data: &[u8]) -> Result<(), UploadFinishedEarly> {
+        if self.result.is_some() {
+            return Err(UploadFinishedEarly);
+        }
+
+        let has_room = {
+            let buffer = self.buffer.clone();
+            let buffer_size = self.buffer_size;
+            // A write larger than the whole buffer still has to fit once it's empty
+            move || {
+                let buffer = buffer.lock().unwrap();
+                buffer.len == 0 || buffer.len + data.len() <= buffer_size
+            }
+        };
+        poll_fn(|cx| {
+            if has_room() {
+                return Poll::Ready(Ok(()));
+            }
+            match self.put.as_mut().poll(cx) {
+                Poll::Ready(result) => {
+                    self.result = Some(result);
+                    Poll::Ready(Err(UploadFinishedEarly))
+                }
+                Poll::Pending if has_room() => Poll::Ready(Ok(())),
+                Poll::Pending => Poll::Pending,
+            }
+        })
+        .await?;
+
+        self.buffer.lock().unwrap().push(data.into());
+        self.written += data.len();
+        Ok(())
+    }
+
+    /// End the object's contents and wait for the request to complete
+    pub async fn finish(mut self) -> PutResult<Client> {
+        if let Some(result) = self.result.take() {
+            return result;
+        }
+        self.buffer.lock().unwrap().close();
+        self.put.await
+    }
+}
+
+impl<Client: ObjectClient> Debug for StreamingUpload<Client> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamingUpload")
+            .field("buffer_size", &self.buffer_size)
+            .field("written", &self.written)
+            .field("finished", &self.result.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A [StreamingUpload]'s request finished before the upload was finished
+#[derive(Debug)]
+pub struct UploadFinishedEarly;
+
+/// The data written to a [StreamingUpload] that its request hasn't taken yet, in the order it was
+/// written. It grows as needed, but writes wait for room, so it stays around the buffer size.
+#[derive(Debug, Default)]
+struct WriteBuffer {
+    chunks: VecDeque<Box<[u8]>>,
+    len: usize,
+    peak_len: usize,
+    closed: bool,
+    /// Woken when there's more data or the buffer is closed
+    reader: Option<Waker>,
+}
+
+impl WriteBuffer {
+    fn push(&mut self, chunk: Box<[u8]>) {
+        self.len += chunk.len();
+        self.peak_len = self.peak_len.max(self.len);
+        self.chunks.push_back(chunk);
+        if let Some(reader) = self.reader.take() {
+            reader.wake();
+        }
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+        if let Some(reader) = self.reader.take() {
+            reader.wake();
+        }
+    }
+
+    fn poll_next_chunk(&mut self, cx: &mut Context<'_>) -> Poll<Option<Box<[u8]>>> {
+        if let Some(chunk) = self.chunks.pop_front() {
+            self.len -= chunk.len();
+            Poll::Ready(Some(chunk))
+        } else if self.closed {
+            Poll::Ready(None)
+        } else {
+            self.reader = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::StreamExt;
+    use mountpoint_s3_client::mock_client::{MockClient, MockClientConfig};
+    use mountpoint_s3_client::{GetObjectParams, UploadMode};
+
+    const MB: usize = 1024 * 1024;
+
+    #[test]
+    fn streaming_upload_stays_within_buffer() {
+        let client = Arc::new(MockClient::new(MockClientConfig {
+            bucket: "bucket".to_string(),
+            part_size: 8 * MB,
+            ..Default::default()
+        }));
+        let uploader = Uploader::new(client.clone(), Default::default());
+        let params = PutObjectParams::new().upload_mode(UploadMode::Multipart);
+        let mut upload = uploader.put_object_streaming("bucket", "key", params, 8 * MB);
+
+        let write_size = 128 * 1024;
+        let size = 50 * MB;
+        block_on(async {
+            for i in 0..size / write_size {
+                let chunk = vec![i as u8; write_size];
+                upload.write(&chunk).await.unwrap();
+            }
+            assert_eq!(upload.bytes_written(), size);
+            let peak = upload.peak_buffered();
+            assert!(peak <= 8 * MB, "buffered {peak} bytes");
+            upload.finish().await.unwrap();
+
+            let mut request = client
+                .get_object("bucket", "key", &GetObjectParams::new())
+                .await
+                .unwrap();
+            let mut body = Vec::new();
+            while let Some(part) = request.next().await {
+                body.extend_from_slice(&part.unwrap().1);
+            }
+            assert_eq!(body.len(), size);
+            let mut chunks = body.chunks(write_size).enumerate();
+            assert!(chunks.all(|(i, chunk)| chunk.iter().all(|b| *b == i as u8)));
+        });
+    }
+}
@@ -34,16 +34,16 @@ fn mount_point_isnt_dir() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 #[test]
-fn prefix_doesnt_end_in_slash() -> Result<(), Box<dyn std::error::Error>> {
+fn prefix_only_whitespace() -> Result<(), Box<dyn std::error::Error>> {
     let dir = assert_fs::TempDir::new()?;
     let mut cmd = Command::cargo_bin("mount-s3")?;
 
-    let prefix = "foo";
+    let prefix = " ";
     cmd.arg("test-bucket")
         .arg(dir.path())
         .arg(format!("--prefix={}", prefix));
     let error_message = format!(
-        "error: invalid value '{}' for '--prefix <PREFIX>': prefix must end in '/'",
+        "error: invalid value '{}' for '--prefix <PREFIX>': prefix must not be only whitespace",
         prefix
     );
     cmd.assert().failure().stderr(predicate::str::contains(error_message));
@@ -159,6 +159,18 @@ fn s3_uri_as_bucket_name() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[test]
+fn invalid_access_point_arn_as_bucket_name() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("mount-s3")?;
+
+    cmd.arg("arn:aws:s3:us-west-2:123456789012:bucket/test-bucket")
+        .arg("test/dir");
+    let error_message = "resource must be an access point";
+    cmd.assert().failure().stderr(predicate::str::contains(error_message));
+
+    Ok(())
+}
+
 #[test]
 fn invalid_profile() -> Result<(), Box<dyn std::error::Error>> {
     let dir = assert_fs::TempDir::new()?;
@@ -5,11 +5,13 @@ use futures::executor::ThreadPool;
 use mountpoint_s3::fs::{DirectoryReplier, ReadReplier};
 use mountpoint_s3::prefix::Prefix;
 use mountpoint_s3::{S3Filesystem, S3FilesystemConfig};
-use mountpoint_s3_client::mock_client::{MockClient, MockClientConfig};
+use mountpoint_s3_client::failure_client::{countdown_failure_client, CountdownFailureClient};
+use mountpoint_s3_client::mock_client::{MockClient, MockClientConfig, MockClientError};
+use mountpoint_s3_client::{ObjectClientError, PutObjectError};
 use mountpoint_s3_crt::common::rust_log_adapter::RustLogAdapter;
 use rand::rngs::OsRng;
 use rand::RngCore;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::ffi::{OsStr, OsString};
 use std::sync::Arc;
 use std::time::Duration;
@@ -32,6 +34,41 @@ pub fn make_test_filesystem(
     (client, fs)
 }
 
+/// Like [make_test_filesystem], but the file system's client fails the given PutObject calls
+/// (numbered from 1)
+pub fn make_put_failure_filesystem(
+    bucket: &str,
+    prefix: &Prefix,
+    config: S3FilesystemConfig,
+    put_failures: impl IntoIterator<Item = usize>,
+) -> (
+    Arc<MockClient>,
+    S3Filesystem<CountdownFailureClient<Arc<MockClient>>, ThreadPool>,
+) {
+    let client_config = MockClientConfig {
+        bucket: bucket.to_string(),
+        part_size: 1024 * 1024,
+    };
+
+    let client = Arc::new(MockClient::new(client_config));
+    let put_failures: HashMap<usize, ObjectClientError<PutObjectError, MockClientError>> = put_failures
+        .into_iter()
+        .map(|n| (n, ObjectClientError::ClientError(MockClientError("put failed".into()))))
+        .collect();
+    let fail_client = countdown_failure_client(
+        Arc::clone(&client),
+        HashMap::new(),
+        HashMap::new(),
+        HashMap::new(),
+        put_failures,
+    );
+    let runtime = ThreadPool::builder().pool_size(1).create().unwrap();
+
+    let fs = S3Filesystem::new(fail_client, runtime, bucket, prefix, config);
+
+    (client, fs)
+}
+
 pub fn get_test_bucket_and_prefix(test_name: &str) -> (String, String) {
     let bucket = std::env::var("S3_BUCKET_NAME").expect("Set S3_BUCKET_NAME to run integration tests");
 
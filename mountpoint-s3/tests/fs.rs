@@ -1,19 +1,36 @@
 //! Manually implemented tests executing the FUSE protocol against [S3Filesystem]
 
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use fuser::FileType;
-use mountpoint_s3::fs::FUSE_ROOT_INODE;
+use futures::executor::ThreadPool;
+use mountpoint_s3::fs::{
+    ReaddirOrder, RemoveDirAllError, VerifyPrefixError, FALLOC_FL_KEEP_SIZE, FUSE_ROOT_INODE, S3_KEY_IOCTL,
+    S3_KEY_IOCTL_SIZE,
+};
 use mountpoint_s3::prefix::Prefix;
-use mountpoint_s3_client::ObjectClient;
-use mountpoint_s3_client::{mock_client::MockObject, ETag};
-use nix::unistd::{getgid, getuid};
+use mountpoint_s3::{S3Filesystem, S3FilesystemConfig};
+use mountpoint_s3_client::intercepting_client::InterceptingClient;
+use mountpoint_s3_client::mock_client::{MockClient, MockClientConfig, MockClientError, MockObject, Operation};
+use mountpoint_s3_client::retrying_client::RetryingClient;
+use mountpoint_s3_client::{
+    upload_part_size, CompleteMultipartUploadError, ETag, GetObjectParams, ObjectClient, ObjectClientError,
+    RetryStrategyConfig, MAX_UPLOAD_PARTS,
+};
+use nix::unistd::{getgid, gettid, getuid};
 use rand::{Rng, SeedableRng};
 use rand_chacha::ChaCha20Rng;
 use std::ffi::OsString;
+use std::io::Write;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 use test_case::test_case;
+use time::OffsetDateTime;
 
 mod common;
-use common::{assert_attr, make_test_filesystem, ReadReply};
+use common::{assert_attr, make_put_failure_filesystem, make_test_filesystem, ReadReply};
 
 #[test_case(""; "unprefixed")]
 #[test_case("test_prefix/"; "prefixed")]
@@ -66,7 +83,7 @@ async fn test_read_dir_root(prefix: &str) {
         assert_eq!(attr.attr.ino, reply.ino);
         assert_attr(attr.attr, FileType::RegularFile, 15, uid, gid, file_perm);
 
-        let fh = fs.open(reply.ino, 0x8000).await.unwrap().fh;
+        let fh = fs.open(reply.ino, 0x8000, 0).await.unwrap().fh;
         let mut read = Err(0);
         fs.read(reply.ino, fh, 0, 4096, 0, None, ReadReply(&mut read)).await;
         assert_eq!(&read.unwrap()[..], &[0xa0 + (i as u8 + 1); 15]);
@@ -141,7 +158,7 @@ async fn test_read_dir_nested(prefix: &str) {
         assert_eq!(attr.attr.ino, reply.ino);
         assert_attr(attr.attr, FileType::RegularFile, 15, uid, gid, file_perm);
 
-        let fh = fs.open(reply.ino, 0x8000).await.unwrap().fh;
+        let fh = fs.open(reply.ino, 0x8000, 0).await.unwrap().fh;
         let mut read = Err(0);
         fs.read(reply.ino, fh, 0, 4096, 0, None, ReadReply(&mut read)).await;
         assert_eq!(&read.unwrap()[..], &[0xa0 + (i as u8 + 1); 15]);
@@ -181,7 +198,7 @@ async fn test_random_read(object_size: usize) {
     assert_eq!(reply.entries[2].name, "file");
     let ino = reply.entries[2].ino;
 
-    let fh = fs.open(ino, 0x8000).await.unwrap().fh;
+    let fh = fs.open(ino, 0x8000, 0).await.unwrap().fh;
 
     let mut rng = ChaCha20Rng::seed_from_u64(0x12345678);
     for _ in 0..10 {
@@ -235,7 +252,7 @@ async fn test_implicit_directory_shadow(prefix: &str) {
     assert_eq!(reply.entries[2].name, "file2.txt");
     assert_eq!(reply.entries[2].attr.kind, FileType::RegularFile);
 
-    let fh = fs.open(reply.entries[2].ino, 0x8000).await.unwrap().fh;
+    let fh = fs.open(reply.entries[2].ino, 0x8000, 0).await.unwrap().fh;
     let mut read = Err(0);
     fs.read(reply.entries[2].ino, fh, 0, 4096, 0, None, ReadReply(&mut read))
         .await;
@@ -279,7 +296,7 @@ async fn test_sequential_write(write_size: usize) {
     let file_ino = dentry.attr.ino;
 
     let fh = fs
-        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY)
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY, 0)
         .await
         .unwrap()
         .fh;
@@ -295,7 +312,7 @@ async fn test_sequential_write(write_size: usize) {
 
     // Check that the object made it to S3 as we expected
     let get = client
-        .get_object(BUCKET_NAME, "dir1/file2.bin", None, None)
+        .get_object(BUCKET_NAME, "dir1/file2.bin", &GetObjectParams::new())
         .await
         .unwrap();
     let actual = get.collect().await.unwrap();
@@ -313,14 +330,14 @@ async fn test_sequential_write(write_size: usize) {
 
     // First let's check that we can't write it again
     let result = fs
-        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY)
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY, 0)
         .await
         .expect_err("file should not be overwritable");
     assert_eq!(result, libc::EPERM);
 
     // But read-only should work
     let fh = fs
-        .open(file_ino, libc::S_IFREG as i32 | libc::O_RDONLY)
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_RDONLY, 0)
         .await
         .unwrap()
         .fh;
@@ -363,7 +380,7 @@ async fn test_unordered_write_fails() {
     let file_ino = dentry.attr.ino;
 
     let fh = fs
-        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY)
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY, 0)
         .await
         .unwrap()
         .fh;
@@ -384,6 +401,275 @@ async fn test_unordered_write_fails() {
     assert_eq!(err, libc::EINVAL);
 }
 
+#[tokio::test]
+async fn test_write_too_large_aborts_upload() {
+    const BUCKET_NAME: &str = "test_write_too_large_aborts_upload";
+
+    let config = S3FilesystemConfig {
+        max_object_size: 100,
+        multipart_threshold: 10,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem(BUCKET_NAME, &Default::default(), config);
+
+    let mode = libc::S_IFREG | libc::S_IRWXU; // regular file + 0700 permissions
+    let dentry = fs
+        .mknod(FUSE_ROOT_INODE, "file.bin".as_ref(), mode, 0, 0)
+        .await
+        .unwrap();
+    let file_ino = dentry.attr.ino;
+
+    let fh = fs
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY, 0)
+        .await
+        .unwrap()
+        .fh;
+
+    // Writing right up to the limit is fine
+    let written = fs.write(file_ino, fh, 0, &[0xaa; 60], 0, 0, None).await.unwrap();
+    assert_eq!(written, 60);
+    let written = fs.write(file_ino, fh, 60, &[0xaa; 40], 0, 0, None).await.unwrap();
+    assert_eq!(written, 40);
+
+    let err = fs
+        .write(file_ino, fh, 100, &[0xaa; 1], 0, 0, None)
+        .await
+        .expect_err("writes past the maximum object size should fail");
+    assert_eq!(err, libc::EFBIG);
+
+    // The upload was aborted, so there's nothing left to write to or upload
+    let err = fs
+        .write(file_ino, fh, 100, &[0xaa; 1], 0, 0, None)
+        .await
+        .expect_err("writes after the upload was aborted should fail");
+    assert_eq!(err, libc::EBADF);
+    fs.flush(file_ino, fh, 0, 0).await.unwrap();
+    fs.release(file_ino, fh, 0, None, true).await.unwrap();
+
+    assert!(!client.contains_key("file.bin"));
+    assert_eq!(client.op_count(Operation::PutObject), 0);
+    assert_eq!(client.op_count(Operation::CreateMultipartUpload), 0);
+}
+
+#[tokio::test]
+async fn test_abort_orphaned_uploads() {
+    const BUCKET_NAME: &str = "test_abort_orphaned_uploads";
+
+    let prefix = Prefix::new("dir/").expect("valid prefix");
+    let config = S3FilesystemConfig {
+        abort_orphaned_uploads_older_than: Some(Duration::from_secs(24 * 60 * 60)),
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem(BUCKET_NAME, &prefix, config);
+
+    let now = OffsetDateTime::now_utc();
+    let abandoned = client.create_multipart_upload("dir/abandoned.bin", now - time::Duration::days(2));
+    let recent = client.create_multipart_upload("dir/recent.bin", now);
+    let outside_prefix = client.create_multipart_upload("other/abandoned.bin", now - time::Duration::days(2));
+
+    let aborted = fs.abort_orphaned_uploads().await.unwrap();
+    assert_eq!(aborted, 1);
+    assert!(!client.has_multipart_upload(&abandoned));
+    assert!(client.has_multipart_upload(&recent));
+    assert!(client.has_multipart_upload(&outside_prefix));
+    assert_eq!(client.op_count(Operation::AbortMultipartUpload), 1);
+}
+
+#[tokio::test]
+async fn test_max_open_files() {
+    const BUCKET_NAME: &str = "test_max_open_files";
+
+    let config = S3FilesystemConfig {
+        max_open_files: Some(3),
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem(BUCKET_NAME, &Default::default(), config);
+    for i in 0..4 {
+        client.add_object(
+            &format!("file{i}.txt"),
+            MockObject::constant(0xa1, 15, ETag::for_tests()),
+        );
+    }
+
+    let mut inodes = Vec::new();
+    for i in 0..4 {
+        let name = format!("file{i}.txt");
+        let entry = fs.lookup(FUSE_ROOT_INODE, name.as_ref()).await.unwrap();
+        inodes.push(entry.attr.ino);
+    }
+
+    let mut handles = Vec::new();
+    for ino in &inodes[..3] {
+        let fh = fs.open(*ino, libc::O_RDONLY, 0).await.unwrap().fh;
+        handles.push((*ino, fh));
+    }
+
+    let err = fs
+        .open(inodes[3], libc::O_RDONLY, 0)
+        .await
+        .expect_err("opening past the limit should fail");
+    assert_eq!(err, libc::EMFILE);
+
+    // Releasing a handle frees a slot for the next open
+    let (ino, fh) = handles.pop().unwrap();
+    fs.release(ino, fh, 0, None, false).await.unwrap();
+    fs.open(inodes[3], libc::O_RDONLY, 0).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_max_open_files_concurrent() {
+    const BUCKET_NAME: &str = "test_max_open_files_concurrent";
+
+    // Decoding makes each open send a HeadObject, so the opens are all in flight at once
+    let config = S3FilesystemConfig {
+        max_open_files: Some(3),
+        decode_content_encoding: true,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem(BUCKET_NAME, &Default::default(), config);
+    let mut inodes = Vec::new();
+    for i in 0..4 {
+        let name = format!("file{i}.txt");
+        client.add_object(&name, MockObject::constant(0xa1, 15, ETag::for_tests()));
+        let entry = fs.lookup(FUSE_ROOT_INODE, name.as_ref()).await.unwrap();
+        inodes.push(entry.attr.ino);
+    }
+    client.set_latency(Operation::HeadObject, Duration::from_millis(50));
+
+    let opens = inodes.iter().map(|ino| fs.open(*ino, libc::O_RDONLY, 0));
+    let results = futures::future::join_all(opens).await;
+    let errors: Vec<_> = results.iter().filter_map(|result| result.as_ref().err()).collect();
+    assert_eq!(errors, [&libc::EMFILE]);
+    for (ino, result) in inodes.iter().zip(&results) {
+        if let Ok(opened) = result {
+            fs.release(*ino, opened.fh, 0, None, false).await.unwrap();
+        }
+    }
+
+    // Opens that fail give their slot back
+    for _ in 0..4 {
+        let err = fs
+            .open(inodes[0], libc::O_RDWR, 0)
+            .await
+            .expect_err("O_RDWR is unsupported");
+        assert_eq!(err, libc::EINVAL);
+    }
+    for ino in &inodes[..3] {
+        fs.open(*ino, libc::O_RDONLY, 0).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_decode_gzip_object() {
+    const BUCKET_NAME: &str = "test_decode_gzip_object";
+
+    let config = S3FilesystemConfig {
+        decode_content_encoding: true,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem(BUCKET_NAME, &Default::default(), config);
+
+    // Large enough to span several parts once compressed
+    let mut rng = ChaCha20Rng::seed_from_u64(0x12345678);
+    let mut plaintext = vec![0u8; 3 * 1024 * 1024];
+    rng.fill(&mut plaintext[..]);
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&plaintext).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let mut object = MockObject::from_bytes(&compressed, ETag::for_tests());
+    object.set_content_encoding(Some("gzip"));
+    client.add_object("file.txt.gz", object);
+    client.add_object("plain.txt", MockObject::constant(0xa1, 15, ETag::for_tests()));
+
+    let entry = fs.lookup(FUSE_ROOT_INODE, "file.txt.gz".as_ref()).await.unwrap();
+    let ino = entry.attr.ino;
+    assert_eq!(entry.attr.size, compressed.len() as u64);
+
+    let opened = fs.open(ino, libc::O_RDONLY, 0).await.unwrap();
+    assert_ne!(opened.flags & fuser::consts::FOPEN_DIRECT_IO, 0);
+
+    // Read sequentially until the end of the decoded contents
+    let mut decoded = Vec::new();
+    loop {
+        let mut read = Err(0);
+        fs.read(
+            ino,
+            opened.fh,
+            decoded.len() as i64,
+            128 * 1024,
+            0,
+            None,
+            ReadReply(&mut read),
+        )
+        .await;
+        let read = read.unwrap();
+        if read.is_empty() {
+            break;
+        }
+        decoded.extend_from_slice(&read);
+    }
+    assert_eq!(decoded, plaintext);
+
+    // Reading backwards restarts from the beginning of the object
+    let mut read = Err(0);
+    fs.read(ino, opened.fh, 1000, 100, 0, None, ReadReply(&mut read)).await;
+    assert_eq!(&read.unwrap()[..], &plaintext[1000..1100]);
+    fs.release(ino, opened.fh, 0, None, true).await.unwrap();
+
+    // Objects without an encoding are read as usual
+    let entry = fs.lookup(FUSE_ROOT_INODE, "plain.txt".as_ref()).await.unwrap();
+    let opened = fs.open(entry.attr.ino, libc::O_RDONLY, 0).await.unwrap();
+    assert_eq!(opened.flags, 0);
+    let mut read = Err(0);
+    fs.read(entry.attr.ino, opened.fh, 0, 4096, 0, None, ReadReply(&mut read))
+        .await;
+    assert_eq!(&read.unwrap()[..], &[0xa1; 15]);
+}
+
+#[tokio::test]
+async fn test_decode_idle_handle() {
+    let config = S3FilesystemConfig {
+        decode_content_encoding: true,
+        max_concurrent_requests: Some(1),
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_decode_idle_handle", &Default::default(), config);
+
+    // Random bytes don't compress, so this takes several requests to fetch
+    let mut rng = ChaCha20Rng::seed_from_u64(0x12345678);
+    let mut plaintext = vec![0u8; 5 * 1024 * 1024];
+    rng.fill(&mut plaintext[..]);
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&plaintext).unwrap();
+    let mut object = MockObject::from_bytes(&encoder.finish().unwrap(), ETag::for_tests());
+    object.set_content_encoding(Some("gzip"));
+    client.add_object("file.gz", object);
+    client.add_object("plain.txt", MockObject::constant(0xa1, 15, ETag::for_tests()));
+
+    let ino = fs.lookup(FUSE_ROOT_INODE, "file.gz".as_ref()).await.unwrap().attr.ino;
+    let fh = fs.open(ino, libc::O_RDONLY, 0).await.unwrap().fh;
+    let mut read = Err(0);
+    fs.read(ino, fh, 0, 4096, 0, None, ReadReply(&mut read)).await;
+    assert_eq!(&read.unwrap()[..], &plaintext[..4096]);
+
+    // The handle is idle partway through the object, but doesn't hold on to the only request slot
+    let other = fs.lookup(FUSE_ROOT_INODE, "plain.txt".as_ref());
+    let other = tokio::time::timeout(Duration::from_secs(5), other)
+        .await
+        .expect("lookup shouldn't wait for the idle handle")
+        .unwrap();
+    let other_fh = fs.open(other.attr.ino, libc::O_RDONLY, 0).await.unwrap().fh;
+    let mut read = Err(0);
+    fs.read(other.attr.ino, other_fh, 0, 4096, 0, None, ReadReply(&mut read))
+        .await;
+    assert_eq!(&read.unwrap()[..], &[0xa1; 15]);
+
+    let mut read = Err(0);
+    fs.read(ino, fh, 4096, 4096, 0, None, ReadReply(&mut read)).await;
+    assert_eq!(&read.unwrap()[..], &plaintext[4096..8192]);
+}
+
 #[tokio::test]
 async fn test_duplicate_write_fails() {
     const BUCKET_NAME: &str = "test_duplicate_write_fails";
@@ -398,11 +684,14 @@ async fn test_duplicate_write_fails() {
     assert_eq!(dentry.attr.size, 0);
     let file_ino = dentry.attr.ino;
 
-    let _opened = fs.open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY).await.unwrap();
+    let _opened = fs
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY, 0)
+        .await
+        .unwrap();
 
     // Should not be allowed to open the file a second time
     let err = fs
-        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY)
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY, 0)
         .await
         .expect_err("should not be able to write twice");
     assert_eq!(err, libc::EPERM);
@@ -509,7 +798,7 @@ async fn test_local_dir(prefix: &str) {
     let file_entry = fs.mknod(dir_ino, filename.as_ref(), mode, 0, 0).await.unwrap();
     let file_ino = file_entry.attr.ino;
     let file_handle = fs
-        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY)
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY, 0)
         .await
         .unwrap()
         .fh;
@@ -523,3 +812,956 @@ async fn test_local_dir(prefix: &str) {
     let lookup = fs.lookup(FUSE_ROOT_INODE, dirname.as_ref()).await;
     assert!(matches!(lookup, Err(libc::ENOENT)));
 }
+
+#[test_case("test_prefix/", &["test_prefix/file.txt"], false, true; "object under prefix")]
+#[test_case("test_prefix/", &["test_prefix/dir/file.txt"], false, true; "directory under prefix")]
+#[test_case("test_prefix/", &["other_prefix/file.txt"], false, false; "no objects under prefix")]
+#[test_case("test_prefix/", &["test_prefix_file.txt"], false, false; "key sharing prefix without delimiter")]
+#[test_case("test_prefix/", &[], true, true; "empty prefix allowed")]
+#[test_case("", &[], false, true; "whole bucket")]
+#[tokio::test]
+async fn test_verify_prefix(prefix: &str, keys: &[&str], allow_empty_prefix: bool, expected_ok: bool) {
+    let config = S3FilesystemConfig {
+        allow_empty_prefix,
+        ..Default::default()
+    };
+    let prefix = Prefix::new(prefix).expect("valid prefix");
+    let (client, fs) = make_test_filesystem("test_verify_prefix", &prefix, config);
+
+    for key in keys {
+        client.add_object(key, MockObject::constant(0xaa, 10, ETag::for_tests()));
+    }
+
+    let result = fs.verify_prefix().await;
+    assert_eq!(result.is_ok(), expected_ok, "unexpected result {result:?}");
+    if !expected_ok {
+        assert!(matches!(result, Err(VerifyPrefixError::EmptyPrefix(_))));
+    }
+}
+
+#[tokio::test]
+async fn test_flush_surfaces_put_error() {
+    const BUCKET_NAME: &str = "test_flush_surfaces_put_error";
+
+    let (client, fs) = make_put_failure_filesystem(BUCKET_NAME, &Default::default(), Default::default(), [1]);
+
+    let mode = libc::S_IFREG | libc::S_IRWXU; // regular file + 0700 permissions
+    let dentry = fs
+        .mknod(FUSE_ROOT_INODE, "file.bin".as_ref(), mode, 0, 0)
+        .await
+        .unwrap();
+    let file_ino = dentry.attr.ino;
+
+    let fh = fs
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY, 0)
+        .await
+        .unwrap()
+        .fh;
+    let written = fs.write(file_ino, fh, 0, &[0xaa; 27], 0, 0, None).await.unwrap();
+    assert_eq!(written, 27);
+
+    // The error should be returned from `flush`, where `close` can see it
+    let err = fs.flush(file_ino, fh, 0, 0).await.expect_err("flush should fail");
+    assert_eq!(err, libc::EIO);
+    assert!(!client.contains_key("file.bin"));
+
+    // Once the upload has completed, the handle can't be written to anymore, but flushing and
+    // releasing it again are fine
+    let err = fs
+        .write(file_ino, fh, 27, &[0xaa; 27], 0, 0, None)
+        .await
+        .expect_err("write after flush should fail");
+    assert_eq!(err, libc::EBADF);
+    fs.flush(file_ino, fh, 0, 0).await.unwrap();
+    fs.release(file_ino, fh, 0, None, true).await.unwrap();
+    assert!(!client.contains_key("file.bin"));
+}
+
+#[tokio::test]
+async fn test_read_and_write_retry_limits() {
+    const BUCKET_NAME: &str = "test_read_and_write_retry_limits";
+
+    let client = Arc::new(MockClient::new(MockClientConfig {
+        bucket: BUCKET_NAME.to_string(),
+        part_size: 1024 * 1024,
+    }));
+    client.add_object("existing.bin", MockObject::constant(0xaa, 27, ETag::for_tests()));
+
+    let get_attempts = Arc::new(AtomicUsize::new(0));
+    let put_attempts = Arc::new(AtomicUsize::new(0));
+    let failing_client = InterceptingClient::new(Arc::clone(&client))
+        .before_get({
+            let get_attempts = Arc::clone(&get_attempts);
+            move |_bucket, _key, _params| {
+                get_attempts.fetch_add(1, Ordering::SeqCst);
+                Err(ObjectClientError::ClientError(MockClientError("get failed".into())))
+            }
+        })
+        .before_put({
+            let put_attempts = Arc::clone(&put_attempts);
+            move |_bucket, _key, _params| {
+                put_attempts.fetch_add(1, Ordering::SeqCst);
+                Err(ObjectClientError::ClientError(MockClientError("put failed".into())))
+            }
+        });
+    let retry_config = RetryStrategyConfig {
+        read_max_retries: Some(1),
+        write_max_retries: Some(4),
+        backoff_scale_factor: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(1),
+        ..Default::default()
+    };
+    let runtime = ThreadPool::builder().pool_size(1).create().unwrap();
+    let fs = S3Filesystem::new(
+        RetryingClient::new(failing_client, retry_config),
+        runtime,
+        BUCKET_NAME,
+        &Default::default(),
+        Default::default(),
+    );
+
+    let ino = fs
+        .lookup(FUSE_ROOT_INODE, "existing.bin".as_ref())
+        .await
+        .unwrap()
+        .attr
+        .ino;
+    let fh = fs.open(ino, libc::O_RDONLY, 0).await.unwrap().fh;
+    let mut read = Err(0);
+    fs.read(ino, fh, 0, 4096, 0, None, ReadReply(&mut read)).await;
+    assert_eq!(read.expect_err("read should fail"), libc::EIO);
+    assert_eq!(get_attempts.load(Ordering::SeqCst), 2);
+    fs.release(ino, fh, 0, None, true).await.unwrap();
+
+    let mode = libc::S_IFREG | libc::S_IRWXU; // regular file + 0700 permissions
+    let dentry = fs.mknod(FUSE_ROOT_INODE, "new.bin".as_ref(), mode, 0, 0).await.unwrap();
+    let file_ino = dentry.attr.ino;
+    let fh = fs
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY, 0)
+        .await
+        .unwrap()
+        .fh;
+    fs.write(file_ino, fh, 0, &[0xbb; 27], 0, 0, None).await.unwrap();
+    fs.release(file_ino, fh, 0, None, true)
+        .await
+        .expect_err("upload should fail");
+    assert_eq!(put_attempts.load(Ordering::SeqCst), 5);
+    assert!(!client.contains_key("new.bin"));
+}
+
+#[tokio::test]
+async fn test_put_size_mismatch() {
+    const BUCKET_NAME: &str = "test_put_size_mismatch";
+
+    let client = Arc::new(MockClient::new(MockClientConfig {
+        bucket: BUCKET_NAME.to_string(),
+        part_size: 1024 * 1024,
+    }));
+    // Simulate a bug that loses the end of each chunk of the upload
+    let lossy_client = InterceptingClient::new(Arc::clone(&client))
+        .map_put_contents(|_bucket, _key, chunk| chunk[..chunk.len().saturating_sub(1)].to_vec());
+    let runtime = ThreadPool::builder().pool_size(1).create().unwrap();
+    let fs = S3Filesystem::new(
+        lossy_client,
+        runtime,
+        BUCKET_NAME,
+        &Default::default(),
+        Default::default(),
+    );
+
+    let mode = libc::S_IFREG | libc::S_IRWXU; // regular file + 0700 permissions
+    let dentry = fs
+        .mknod(FUSE_ROOT_INODE, "file.bin".as_ref(), mode, 0, 0)
+        .await
+        .unwrap();
+    let file_ino = dentry.attr.ino;
+
+    let fh = fs
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY, 0)
+        .await
+        .unwrap()
+        .fh;
+    let written = fs.write(file_ino, fh, 0, &[0xaa; 27], 0, 0, None).await.unwrap();
+    assert_eq!(written, 27);
+
+    // The short upload should be caught rather than silently succeeding
+    let err = fs.flush(file_ino, fh, 0, 0).await.expect_err("flush should fail");
+    assert_eq!(err, libc::EIO);
+    let head = client.head_object(BUCKET_NAME, "file.bin", None).await.unwrap();
+    assert_eq!(head.object.size, 26);
+    fs.release(file_ino, fh, 0, None, true).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_flush_completes_upload() {
+    const BUCKET_NAME: &str = "test_flush_completes_upload";
+
+    let (client, fs) = make_test_filesystem(BUCKET_NAME, &Default::default(), Default::default());
+
+    let mode = libc::S_IFREG | libc::S_IRWXU; // regular file + 0700 permissions
+    let dentry = fs
+        .mknod(FUSE_ROOT_INODE, "file.bin".as_ref(), mode, 0, 0)
+        .await
+        .unwrap();
+    let file_ino = dentry.attr.ino;
+
+    let fh = fs
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY, 0)
+        .await
+        .unwrap()
+        .fh;
+    fs.write(file_ino, fh, 0, &[0xaa; 27], 0, 0, None).await.unwrap();
+
+    fs.flush(file_ino, fh, 0, 0).await.unwrap();
+    assert!(client.contains_key("file.bin"));
+
+    // Overwrite the object remotely to check that `release` doesn't upload it again
+    client.add_object("file.bin", MockObject::constant(0xbb, 5, ETag::for_tests()));
+    fs.release(file_ino, fh, 0, None, true).await.unwrap();
+    let head = client.head_object(BUCKET_NAME, "file.bin", None).await.unwrap();
+    assert_eq!(head.object.size, 5);
+}
+
+#[tokio::test]
+async fn test_flush_from_other_process() {
+    const BUCKET_NAME: &str = "test_flush_from_other_process";
+
+    // Flush checks which process each pid belongs to, so these have to be real processes
+    let open_pid = std::process::id();
+    let mut child = std::process::Command::new("sleep").arg("60").spawn().unwrap();
+    let child_pid = child.id();
+
+    let (client, fs) = make_test_filesystem(BUCKET_NAME, &Default::default(), Default::default());
+
+    let mode = libc::S_IFREG | libc::S_IRWXU; // regular file + 0700 permissions
+    let dentry = fs
+        .mknod(FUSE_ROOT_INODE, "file.bin".as_ref(), mode, 0, 0)
+        .await
+        .unwrap();
+    let file_ino = dentry.attr.ino;
+
+    let fh = fs
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY, open_pid)
+        .await
+        .unwrap()
+        .fh;
+    fs.write(file_ino, fh, 0, &[0xaa; 27], 0, 0, None).await.unwrap();
+
+    // A child closing its inherited descriptor shouldn't complete the upload, so the parent can
+    // keep writing
+    fs.flush(file_ino, fh, 0, child_pid).await.unwrap();
+    assert!(!client.contains_key("file.bin"));
+    fs.write(file_ino, fh, 27, &[0xaa; 27], 0, 0, None).await.unwrap();
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    fs.flush(file_ino, fh, 0, open_pid).await.unwrap();
+    let head = client.head_object(BUCKET_NAME, "file.bin", None).await.unwrap();
+    assert_eq!(head.object.size, 54);
+    fs.release(file_ino, fh, 0, None, true).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_flush_from_other_thread() {
+    const BUCKET_NAME: &str = "test_flush_from_other_thread";
+
+    let (client, fs) = make_test_filesystem(BUCKET_NAME, &Default::default(), Default::default());
+
+    let mode = libc::S_IFREG | libc::S_IRWXU; // regular file + 0700 permissions
+    let dentry = fs
+        .mknod(FUSE_ROOT_INODE, "file.bin".as_ref(), mode, 0, 0)
+        .await
+        .unwrap();
+    let file_ino = dentry.attr.ino;
+
+    let fh = fs
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY, std::process::id())
+        .await
+        .unwrap()
+        .fh;
+    fs.write(file_ino, fh, 0, &[0xaa; 27], 0, 0, None).await.unwrap();
+
+    // Keep another thread of this process alive while it "closes" the file, so its thread id
+    // still belongs to this process
+    let (tid_tx, tid_rx) = std::sync::mpsc::channel();
+    let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+    let thread = std::thread::spawn(move || {
+        tid_tx.send(gettid().as_raw() as u32).unwrap();
+        let _ = done_rx.recv();
+    });
+    let tid = tid_rx.recv().unwrap();
+    assert_ne!(tid, std::process::id());
+
+    fs.flush(file_ino, fh, 0, tid).await.unwrap();
+    drop(done_tx);
+    thread.join().unwrap();
+    let head = client.head_object(BUCKET_NAME, "file.bin", None).await.unwrap();
+    assert_eq!(head.object.size, 27);
+    fs.release(file_ino, fh, 0, None, true).await.unwrap();
+}
+
+#[test_case(0, true; "no failures")]
+#[test_case(2, true; "fails twice then succeeds")]
+#[test_case(4, false; "exhausts retries")]
+#[tokio::test]
+async fn test_upload_retries(complete_failures: usize, expect_success: bool) {
+    const BUCKET_NAME: &str = "test_upload_retries";
+
+    let config = S3FilesystemConfig {
+        multipart_threshold: 1024,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem(BUCKET_NAME, &Default::default(), config);
+    for _ in 0..complete_failures {
+        let error = CompleteMultipartUploadError::new("InternalError", "We encountered an internal error");
+        client.fail_next_complete_multipart_upload(error);
+    }
+
+    let mode = libc::S_IFREG | libc::S_IRWXU; // regular file + 0700 permissions
+    let dentry = fs
+        .mknod(FUSE_ROOT_INODE, "file.bin".as_ref(), mode, 0, 0)
+        .await
+        .unwrap();
+    let file_ino = dentry.attr.ino;
+
+    let fh = fs
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY, 0)
+        .await
+        .unwrap()
+        .fh;
+    let body = [0xaa; 2048];
+    fs.write(file_ino, fh, 0, &body, 0, 0, None).await.unwrap();
+
+    let result = fs.flush(file_ino, fh, 0, 0).await;
+    fs.release(file_ino, fh, 0, None, true).await.unwrap();
+
+    // Only the completion is sent again, not the whole upload
+    assert_eq!(client.op_count(Operation::CreateMultipartUpload), 1);
+    assert_eq!(client.op_count(Operation::UploadPart), 1);
+    assert_eq!(
+        client.op_count(Operation::CompleteMultipartUpload),
+        complete_failures.min(3) as u64 + 1
+    );
+
+    if expect_success {
+        result.expect("upload should eventually succeed");
+        let get = client
+            .get_object(BUCKET_NAME, "file.bin", &GetObjectParams::new())
+            .await
+            .unwrap();
+        let actual = get.collect().await.unwrap();
+        assert_eq!(&actual[..], &body[..]);
+    } else {
+        assert_eq!(result, Err(libc::EIO));
+        assert!(!client.contains_key("file.bin"));
+    }
+}
+
+#[test_case(1023, false; "below threshold")]
+#[test_case(1024, true; "at threshold")]
+#[test_case(1025, true; "above threshold")]
+#[tokio::test]
+async fn test_multipart_threshold(size: usize, expect_multipart: bool) {
+    const BUCKET_NAME: &str = "test_multipart_threshold";
+
+    let config = S3FilesystemConfig {
+        multipart_threshold: 1024,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem(BUCKET_NAME, &Default::default(), config);
+
+    let mode = libc::S_IFREG | libc::S_IRWXU; // regular file + 0700 permissions
+    let dentry = fs
+        .mknod(FUSE_ROOT_INODE, "file.bin".as_ref(), mode, 0, 0)
+        .await
+        .unwrap();
+    let file_ino = dentry.attr.ino;
+
+    let fh = fs
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY, 0)
+        .await
+        .unwrap()
+        .fh;
+    let body = vec![0xaa; size];
+    fs.write(file_ino, fh, 0, &body, 0, 0, None).await.unwrap();
+    fs.release(file_ino, fh, 0, None, true).await.unwrap();
+
+    if expect_multipart {
+        assert_eq!(client.op_count(Operation::PutObject), 0);
+        assert_eq!(client.op_count(Operation::CreateMultipartUpload), 1);
+        assert_eq!(client.op_count(Operation::CompleteMultipartUpload), 1);
+    } else {
+        assert_eq!(client.op_count(Operation::PutObject), 1);
+        assert_eq!(client.op_count(Operation::CreateMultipartUpload), 0);
+    }
+
+    let get = client
+        .get_object(BUCKET_NAME, "file.bin", &GetObjectParams::new())
+        .await
+        .unwrap();
+    let actual = get.collect().await.unwrap();
+    assert_eq!(&actual[..], &body[..]);
+}
+
+#[tokio::test]
+async fn test_dry_run_writes() {
+    const BUCKET_NAME: &str = "test_dry_run_writes";
+
+    let config = S3FilesystemConfig {
+        dry_run_writes: true,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem(BUCKET_NAME, &Default::default(), config);
+
+    let mode = libc::S_IFREG | libc::S_IRWXU; // regular file + 0700 permissions
+    let dentry = fs
+        .mknod(FUSE_ROOT_INODE, "file.bin".as_ref(), mode, 0, 0)
+        .await
+        .unwrap();
+    let file_ino = dentry.attr.ino;
+
+    let fh = fs
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY, 0)
+        .await
+        .unwrap()
+        .fh;
+    let written = fs.write(file_ino, fh, 0, &[0xaa; 27], 0, 0, None).await.unwrap();
+    assert_eq!(written, 27);
+    fs.flush(file_ino, fh, 0, 0).await.unwrap();
+    fs.release(file_ino, fh, 0, None, true).await.unwrap();
+
+    assert!(!client.contains_key("file.bin"));
+    assert_eq!(client.op_count(Operation::PutObject), 0);
+    assert_eq!(client.op_count(Operation::CreateMultipartUpload), 0);
+}
+
+#[tokio::test]
+async fn test_remove_dir_all() {
+    let (client, fs) = make_test_filesystem("test_remove_dir_all", &Default::default(), Default::default());
+
+    client.add_object("dir/file", MockObject::constant(0xa1, 15, ETag::for_tests()));
+    client.add_object("dir/sub/file", MockObject::constant(0xa2, 15, ETag::for_tests()));
+    // Enough objects at the deepest level to need more than one page of listing
+    for i in 0..1500 {
+        client.add_object(
+            &format!("dir/sub/subsub/file{i}"),
+            MockObject::constant(0xa3, 15, ETag::for_tests()),
+        );
+    }
+    // Shares a prefix with the directory's name but isn't in it
+    client.add_object("dir2/file", MockObject::constant(0xa4, 15, ETag::for_tests()));
+    client.add_object("file", MockObject::constant(0xa5, 15, ETag::for_tests()));
+
+    fs.remove_dir_all(FUSE_ROOT_INODE, "dir".as_ref()).await.unwrap();
+
+    assert!(!client.contains_prefix("dir/"));
+    assert!(client.contains_key("dir2/file"));
+    assert_eq!(client.op_count(Operation::DeleteObjects), 2);
+
+    let err = fs
+        .remove_dir_all(FUSE_ROOT_INODE, "file".as_ref())
+        .await
+        .expect_err("not a directory");
+    assert!(matches!(err, RemoveDirAllError::NotADirectory), "{err:?}");
+    assert!(client.contains_key("file"));
+}
+
+#[tokio::test]
+async fn test_s3_key_ioctl() {
+    let prefix = Prefix::new("test_prefix/").expect("valid prefix");
+    let (_client, fs) = make_test_filesystem("test_s3_key_ioctl", &prefix, Default::default());
+
+    let dir = fs
+        .mkdir(FUSE_ROOT_INODE, "dir".as_ref(), libc::S_IFDIR, 0)
+        .await
+        .unwrap();
+    let mode = libc::S_IFREG | libc::S_IRWXU; // regular file + 0700 permissions
+    let dentry = fs.mknod(dir.attr.ino, "file.txt".as_ref(), mode, 0, 0).await.unwrap();
+    let file_ino = dentry.attr.ino;
+
+    let fh = fs
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY, 0)
+        .await
+        .unwrap()
+        .fh;
+    let out_size = S3_KEY_IOCTL_SIZE as u32;
+    let data = fs.ioctl(file_ino, fh, 0, S3_KEY_IOCTL, &[], out_size).await.unwrap();
+    assert_eq!(data, b"test_prefix/dir/file.txt\0");
+
+    let err = fs.ioctl(file_ino, fh, 0, 0x1234, &[], out_size).await.unwrap_err();
+    assert_eq!(err, libc::ENOTTY);
+}
+
+#[tokio::test]
+async fn test_root_mtime_from_marker() {
+    let prefix = Prefix::new("test_prefix/").expect("valid prefix");
+    let fallback = OffsetDateTime::UNIX_EPOCH + time::Duration::days(1);
+    let config = S3FilesystemConfig {
+        root_fallback_mtime: Some(fallback),
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_root_mtime_from_marker", &prefix, config);
+
+    let last_modified = OffsetDateTime::UNIX_EPOCH + time::Duration::days(365);
+    let mut marker = MockObject::constant(0, 0, ETag::for_tests());
+    marker.set_last_modified(last_modified);
+    client.add_object("test_prefix/", marker);
+
+    fs.load_root_attributes().await;
+
+    let attr = fs.getattr(FUSE_ROOT_INODE).await.unwrap().attr;
+    assert_eq!(attr.kind, FileType::Directory);
+    assert_eq!(attr.mtime, SystemTime::from(last_modified));
+}
+
+#[tokio::test]
+async fn test_root_mtime_fallback() {
+    let prefix = Prefix::new("test_prefix/").expect("valid prefix");
+    let fallback = OffsetDateTime::UNIX_EPOCH + time::Duration::days(1);
+    let config = S3FilesystemConfig {
+        root_fallback_mtime: Some(fallback),
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_root_mtime_fallback", &prefix, config);
+
+    // Objects under the prefix aren't a marker for it
+    client.add_object(
+        "test_prefix/file.txt",
+        MockObject::constant(0xa1, 15, ETag::for_tests()),
+    );
+
+    fs.load_root_attributes().await;
+
+    let attr = fs.getattr(FUSE_ROOT_INODE).await.unwrap().attr;
+    assert_eq!(attr.kind, FileType::Directory);
+    assert_eq!(attr.mtime, SystemTime::from(fallback));
+}
+
+/// Collects everything written to it, to check the output of a tracing subscriber
+#[derive(Debug, Clone, Default)]
+struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_slow_op_warning() {
+    let logs = CapturedLogs::default();
+    let subscriber = tracing_subscriber::fmt()
+        .with_ansi(false)
+        .with_writer({
+            let logs = logs.clone();
+            move || logs.clone()
+        })
+        .finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let config = S3FilesystemConfig {
+        slow_op_threshold: Some(Duration::from_millis(20)),
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_slow_op_warning", &Default::default(), config);
+    client.add_object("file.txt", MockObject::constant(0xa1, 15, ETag::for_tests()));
+
+    let entry = fs.lookup(FUSE_ROOT_INODE, "file.txt".as_ref()).await.unwrap();
+    let fh = fs.open(entry.attr.ino, libc::O_RDONLY, 0).await.unwrap().fh;
+    let logs_contain = |needle: &str| String::from_utf8_lossy(&logs.0.lock().unwrap()).contains(needle);
+    assert!(!logs_contain("slow operation"));
+
+    client.set_latency(Operation::GetObject, Duration::from_millis(100));
+    let mut read = Err(0);
+    fs.read(entry.attr.ino, fh, 0, 15, 0, None, ReadReply(&mut read)).await;
+    assert_eq!(read.unwrap(), [0xa1; 15].into());
+
+    assert!(logs_contain("slow operation"), "read should have been logged as slow");
+    assert!(logs_contain("op=\"read\""));
+    assert!(logs_contain(&format!("ino={}", entry.attr.ino)));
+}
+
+#[tokio::test]
+async fn test_max_concurrent_requests() {
+    let config = S3FilesystemConfig {
+        max_concurrent_requests: Some(16),
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_max_concurrent_requests", &Default::default(), config);
+    for i in 0..1000 {
+        client.add_object(
+            &format!("file{i}.txt"),
+            MockObject::constant(0xa1, 15, ETag::for_tests()),
+        );
+    }
+
+    let lookups = (0..1000).map(|i| {
+        let name = format!("file{i}.txt");
+        let fs = &fs;
+        async move { fs.lookup(FUSE_ROOT_INODE, name.as_ref()).await }
+    });
+    let results = futures::future::join_all(lookups).await;
+    assert!(results.iter().all(|result| result.is_ok()));
+
+    assert!(
+        client.max_in_flight() <= 16,
+        "saw {} requests in flight",
+        client.max_in_flight()
+    );
+    assert!(client.max_in_flight() > 1, "lookups should have run concurrently");
+}
+
+#[test_case(None; "buffered")]
+#[test_case(Some(8 * 1024); "streaming")]
+#[tokio::test]
+async fn test_fallocate_size_hint(stream_writes_buffer_size: Option<usize>) {
+    const PART_SIZE: usize = 8 * 1024;
+    const FALLOC_FL_PUNCH_HOLE: i32 = 0x02;
+    const SIZE_HINT: u64 = 1024 * 1024 * 1024;
+
+    // At this part size, a 1 GiB object would need far more than the maximum number of parts
+    let client = Arc::new(MockClient::new(MockClientConfig {
+        bucket: "test_fallocate_size_hint".to_string(),
+        part_size: PART_SIZE,
+    }));
+    let runtime = ThreadPool::builder().pool_size(1).create().unwrap();
+    let config = S3FilesystemConfig {
+        stream_writes_buffer_size,
+        ..Default::default()
+    };
+    let fs = S3Filesystem::new(
+        Arc::clone(&client),
+        runtime,
+        "test_fallocate_size_hint",
+        &Default::default(),
+        config,
+    );
+
+    let mode = libc::S_IFREG | libc::S_IRWXU;
+    let dentry = fs
+        .mknod(FUSE_ROOT_INODE, "file.bin".as_ref(), mode, 0, 0)
+        .await
+        .unwrap();
+    let file_ino = dentry.attr.ino;
+    let fh = fs
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY, 0)
+        .await
+        .unwrap()
+        .fh;
+
+    fs.fallocate(file_ino, fh, 0, SIZE_HINT as i64, 0).await.unwrap();
+    // The hint doesn't change the file's size
+    assert_eq!(fs.getattr(file_ino).await.unwrap().attr.size, 0);
+    // Only plain preallocation is supported
+    let err = fs
+        .fallocate(file_ino, fh, 0, 4096, FALLOC_FL_PUNCH_HOLE | FALLOC_FL_KEEP_SIZE)
+        .await
+        .expect_err("punching holes isn't supported");
+    assert_eq!(err, libc::EOPNOTSUPP);
+
+    let body = vec![0xa1u8; 100 * 1024];
+    let mut offset = 0;
+    for data in body.chunks(PART_SIZE) {
+        let written = fs.write(file_ino, fh, offset, data, 0, 0, None).await.unwrap();
+        offset += written as i64;
+    }
+    fs.release(file_ino, fh, 0, None, false).await.unwrap();
+
+    let part_size = client
+        .multipart_part_size("file.bin")
+        .expect("should be a multipart upload");
+    assert_eq!(part_size, upload_part_size(SIZE_HINT, PART_SIZE));
+    assert!(SIZE_HINT.div_ceil(part_size as u64) <= MAX_UPLOAD_PARTS as u64);
+
+    let get = client
+        .get_object("test_fallocate_size_hint", "file.bin", &GetObjectParams::new())
+        .await
+        .unwrap();
+    assert_eq!(&get.collect().await.unwrap()[..], &body[..]);
+}
+
+#[tokio::test]
+async fn test_dir_snapshot_cache_eviction() {
+    let config = S3FilesystemConfig {
+        dir_snapshot_cache_size: 2,
+        stat_ttl: Duration::from_secs(60),
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_dir_snapshot_cache_eviction", &Default::default(), config);
+    let mut dirs = Vec::new();
+    for i in 0..3 {
+        client.add_object(
+            &format!("dir{i}/file.txt"),
+            MockObject::constant(0xa1, 15, ETag::for_tests()),
+        );
+        let entry = fs.lookup(FUSE_ROOT_INODE, format!("dir{i}").as_ref()).await.unwrap();
+        dirs.push(entry.attr.ino);
+    }
+
+    async fn list(fs: &S3Filesystem<Arc<MockClient>, ThreadPool>, dir: u64) -> Vec<String> {
+        let fh = fs.opendir(dir, 0).await.unwrap().fh;
+        let mut reply = Default::default();
+        let _reply = fs.readdir(dir, fh, 0, &mut reply).await.unwrap();
+        reply
+            .entries
+            .iter()
+            .map(|entry| entry.name.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    // More directories than the cache holds, so the first one is evicted
+    for dir in &dirs {
+        assert_eq!(list(&fs, *dir).await, [".", "..", "file.txt"]);
+    }
+    let lists = client.op_count(Operation::ListObjects);
+
+    // The two most recent are listed from their snapshots
+    assert_eq!(list(&fs, dirs[2]).await, [".", "..", "file.txt"]);
+    assert_eq!(list(&fs, dirs[1]).await, [".", "..", "file.txt"]);
+    assert_eq!(client.op_count(Operation::ListObjects), lists);
+
+    // The evicted one is listed from S3 again, and sees what changed since
+    client.add_object("dir0/new.txt", MockObject::constant(0xa1, 15, ETag::for_tests()));
+    assert_eq!(list(&fs, dirs[0]).await, [".", "..", "file.txt", "new.txt"]);
+    assert!(client.op_count(Operation::ListObjects) > lists);
+}
+
+#[test_case(0, 0, Ok(()); "consistent")]
+#[test_case(2, 2, Ok(()); "enough retries")]
+#[test_case(2, 3, Err(libc::ESTALE); "too few retries")]
+#[test_case(0, 1, Err(libc::ESTALE); "retries disabled")]
+#[tokio::test]
+async fn test_read_after_write_retries(retries: u32, missing_gets: u64, expected: Result<(), i32>) {
+    let config = S3FilesystemConfig {
+        read_after_write_retries: retries,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_read_after_write_retries", &Default::default(), config);
+    client.add_object("file.txt", MockObject::constant(0xa1, 15, ETag::for_tests()));
+
+    let entry = fs.lookup(FUSE_ROOT_INODE, "file.txt".as_ref()).await.unwrap();
+    let fh = fs.open(entry.attr.ino, libc::O_RDONLY, 0).await.unwrap().fh;
+
+    // The object was just written, so the store doesn't serve it yet
+    client.simulate_eventual_consistency(missing_gets);
+    let mut read = Err(0);
+    fs.read(entry.attr.ino, fh, 0, 4096, 0, None, ReadReply(&mut read))
+        .await;
+    match expected {
+        Ok(()) => assert_eq!(&read.unwrap()[..], &[0xa1; 15]),
+        Err(errno) => assert_eq!(read, Err(errno)),
+    }
+    assert_eq!(
+        client.op_count(Operation::GetObject),
+        missing_gets.min(retries as u64 + 1) + expected.is_ok() as u64
+    );
+}
+
+#[tokio::test]
+async fn test_readdir_interrupted() {
+    // Caching snapshots means every listing collects the whole directory before replying
+    let config = S3FilesystemConfig {
+        dir_snapshot_cache_size: 1,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_readdir_interrupted", &Default::default(), config);
+    // Opened directories are listed 1000 entries at a time, so this takes 10 pages
+    for i in 0..10_000 {
+        client.add_object(
+            &format!("dir/file{i:05}.txt"),
+            MockObject::constant(0xa1, 15, ETag::for_tests()),
+        );
+    }
+    let dir = fs.lookup(FUSE_ROOT_INODE, "dir".as_ref()).await.unwrap().attr.ino;
+    let fh = fs.opendir(dir, 0).await.unwrap().fh;
+    client.set_latency(Operation::ListObjects, Duration::from_millis(50));
+    let before = client.op_count(Operation::ListObjects);
+
+    let interrupted = AtomicBool::new(false);
+    let mut reply = Default::default();
+    let readdir = fs.readdir_interruptible(dir, fh, 0, &mut reply, &interrupted);
+    let interrupt = async {
+        tokio::time::sleep(Duration::from_millis(120)).await;
+        interrupted.store(true, Ordering::SeqCst);
+    };
+    let (result, ()) = futures::join!(readdir, interrupt);
+    assert!(matches!(result, Err(libc::EINTR)));
+    assert!(reply.entries.is_empty());
+
+    // The listing stopped partway through, and doesn't carry on in the background
+    let listed = client.op_count(Operation::ListObjects) - before;
+    assert!(listed > 0 && listed < 10, "listed {listed} pages");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(client.op_count(Operation::ListObjects) - before, listed);
+
+    // Listing again at the same offset carries on from where it stopped
+    client.set_latency(Operation::ListObjects, Duration::ZERO);
+    let mut reply = Default::default();
+    let _reply = fs.readdir(dir, fh, 0, &mut reply).await.unwrap();
+    assert_eq!(reply.entries.len(), 10_002);
+    let total = client.op_count(Operation::ListObjects) - before;
+    assert!(
+        total < 10 + listed,
+        "pages listed before the interruption were listed again"
+    );
+}
+
+#[tokio::test]
+async fn test_readdir_deadline() {
+    let config = S3FilesystemConfig {
+        readdir_order: ReaddirOrder::DirsFirst,
+        op_deadline: Some(Duration::from_millis(200)),
+        dir_snapshot_cache_size: 1,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_readdir_deadline", &Default::default(), config);
+    // Opened directories are listed 1000 entries at a time, so this takes 10 pages
+    for i in 0..10_000 {
+        client.add_object(
+            &format!("dir/file{i:05}.txt"),
+            MockObject::constant(0xa1, 15, ETag::for_tests()),
+        );
+    }
+    let dir = fs.lookup(FUSE_ROOT_INODE, "dir".as_ref()).await.unwrap().attr.ino;
+    let fh = fs.opendir(dir, 0).await.unwrap().fh;
+    // Each page is well within the deadline, but all of them together aren't
+    client.set_latency(Operation::ListObjects, Duration::from_millis(50));
+    let before = client.op_count(Operation::ListObjects);
+
+    let start = std::time::Instant::now();
+    let mut reply = Default::default();
+    let result = fs.readdir(dir, fh, 0, &mut reply).await;
+    assert!(matches!(result, Err(libc::ETIMEDOUT)));
+    assert!(
+        start.elapsed() < Duration::from_millis(400),
+        "took {:?}",
+        start.elapsed()
+    );
+
+    // The request in flight at the deadline was cancelled, and no more were sent
+    let listed = client.op_count(Operation::ListObjects) - before;
+    assert!(listed > 0 && listed < 10, "listed {listed} pages");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(client.op_count(Operation::ListObjects) - before, listed);
+
+    // Lookups that take too long time out too
+    client.set_latency(Operation::HeadObject, Duration::from_millis(300));
+    client.set_latency(Operation::ListObjects, Duration::from_millis(300));
+    let result = fs.lookup(dir, "file00000.txt".as_ref()).await;
+    assert!(matches!(result, Err(libc::ETIMEDOUT)));
+
+    // Listing again carries on from the page that timed out, and the whole listing is cached
+    client.set_latency(Operation::HeadObject, Duration::ZERO);
+    client.set_latency(Operation::ListObjects, Duration::ZERO);
+    let mut reply = Default::default();
+    let _reply = fs.readdir(dir, fh, 0, &mut reply).await.unwrap();
+    assert_eq!(reply.entries.len(), 10_002);
+    let listed = client.op_count(Operation::ListObjects);
+    let fh = fs.opendir(dir, 0).await.unwrap().fh;
+    let mut reply = Default::default();
+    let _reply = fs.readdir(dir, fh, 0, &mut reply).await.unwrap();
+    assert_eq!(reply.entries.len(), 10_002);
+    assert_eq!(client.op_count(Operation::ListObjects), listed);
+}
+
+#[tokio::test]
+async fn test_readdir_deadline_keeps_replied_entries() {
+    let config = S3FilesystemConfig {
+        op_deadline: Some(Duration::from_millis(200)),
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem(
+        "test_readdir_deadline_keeps_replied_entries",
+        &Default::default(),
+        config,
+    );
+    for i in 0..10_000 {
+        client.add_object(
+            &format!("dir/file{i:05}.txt"),
+            MockObject::constant(0xa1, 15, ETag::for_tests()),
+        );
+    }
+    let dir = fs.lookup(FUSE_ROOT_INODE, "dir".as_ref()).await.unwrap().attr.ino;
+    let fh = fs.opendir(dir, 0).await.unwrap().fh;
+    client.set_latency(Operation::ListObjects, Duration::from_millis(50));
+
+    // S3's order needs no whole listing, so entries are replied as they're listed. Running out of
+    // time after some of them ends the reply early, and the next call carries on after them.
+    let mut names = Vec::new();
+    let mut offset = 0;
+    loop {
+        let mut reply = Default::default();
+        let _reply = fs
+            .readdir(dir, fh, offset, &mut reply)
+            .await
+            .expect("readdir should succeed");
+        let Some(last) = reply.entries.back() else {
+            break;
+        };
+        assert!(
+            offset > 0 || reply.entries.len() < 10_002,
+            "first reply wasn't cut short"
+        );
+        offset = last.offset;
+        names.extend(reply.entries.into_iter().map(|entry| entry.name));
+    }
+    assert_eq!(names.len(), 10_002);
+    let expected = [".", ".."]
+        .into_iter()
+        .map(OsString::from)
+        .chain((0..10_000).map(|i| OsString::from(format!("file{i:05}.txt"))))
+        .collect::<Vec<_>>();
+    assert_eq!(names, expected);
+}
+
+#[test_case(true; "fallback")]
+#[test_case(false; "no fallback")]
+#[tokio::test]
+async fn test_head_object_fallback(head_object_fallback: bool) {
+    let config = S3FilesystemConfig {
+        head_object_fallback,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_head_object_fallback", &Default::default(), config);
+    client.add_object("file.txt", MockObject::constant(0xa1, 12345, ETag::for_tests()));
+    client.add_object("dir/file.txt", MockObject::constant(0xa1, 15, ETag::for_tests()));
+    client.deny_head_object(true);
+
+    let result = fs.lookup(FUSE_ROOT_INODE, "file.txt".as_ref()).await;
+    if head_object_fallback {
+        let entry = result.expect("lookup should fall back to GetObject");
+        assert_eq!(entry.attr.kind, FileType::RegularFile);
+        assert_eq!(entry.attr.size, 12345);
+        assert_eq!(client.op_count(Operation::GetObject), 1);
+
+        let fh = fs.open(entry.attr.ino, libc::O_RDONLY, 0).await.unwrap().fh;
+        let mut read = Err(0);
+        fs.read(entry.attr.ino, fh, 12340, 4096, 0, None, ReadReply(&mut read))
+            .await;
+        assert_eq!(&read.unwrap()[..], &[0xa1; 5]);
+
+        // Names that aren't objects are still found as directories, or not at all
+        let dir = fs.lookup(FUSE_ROOT_INODE, "dir".as_ref()).await.unwrap();
+        assert_eq!(dir.attr.kind, FileType::Directory);
+        let missing = fs.lookup(FUSE_ROOT_INODE, "missing".as_ref()).await;
+        assert!(matches!(missing, Err(libc::ENOENT)));
+
+        // Without the metadata GetObject responses usually carry, reads still work but the
+        // fallback can't tell what the object is
+        client.omit_get_metadata(true);
+        let mut read = Err(0);
+        fs.read(entry.attr.ino, fh, 0, 5, 0, None, ReadReply(&mut read)).await;
+        assert_eq!(&read.unwrap()[..], &[0xa1; 5]);
+        client.add_object("other.txt", MockObject::constant(0xa1, 15, ETag::for_tests()));
+        let other = fs.lookup(FUSE_ROOT_INODE, "other.txt".as_ref()).await;
+        assert!(matches!(other, Err(libc::EIO)));
+    } else {
+        assert!(matches!(result, Err(libc::EIO)));
+        assert_eq!(client.op_count(Operation::GetObject), 0);
+    }
+}
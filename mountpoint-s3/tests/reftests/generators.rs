@@ -25,6 +25,10 @@ pub struct Name(#[proptest(strategy = "name_strategy()")] pub String);
 pub enum FileSize {
     Small(u8),
     Large(#[proptest(strategy = "128*1024..2*1024*1024usize")] usize),
+    /// Hundreds of MiB, to exercise prefetching and multipart logic. Too slow to generate randomly,
+    /// so only used by hand-written tests.
+    #[proptest(skip)]
+    Huge(u64),
 }
 
 impl From<FileSize> for usize {
@@ -32,6 +36,7 @@ impl From<FileSize> for usize {
         match f {
             FileSize::Small(n) => n as usize,
             FileSize::Large(n) => n,
+            FileSize::Huge(n) => n as usize,
         }
     }
 }
@@ -40,8 +45,15 @@ impl From<FileSize> for usize {
 pub struct FileContent(pub u8, pub FileSize);
 
 impl FileContent {
+    /// A [MockObject] that generates this file's contents on demand, so that even huge files don't
+    /// need to be held in memory
     pub fn to_mock_object(&self) -> MockObject {
-        MockObject::constant(self.0, self.1.into(), ETag::for_tests())
+        match self.1 {
+            // Constant content can't catch reads from the wrong offset, which are more likely in
+            // big files that are read with many requests
+            FileSize::Huge(_) => MockObject::ramp(self.0, self.1.into(), ETag::for_tests()),
+            _ => MockObject::constant(self.0, self.1.into(), ETag::for_tests()),
+        }
     }
 
     pub fn to_boxed_slice(&self) -> Box<[u8]> {
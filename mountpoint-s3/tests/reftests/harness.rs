@@ -5,16 +5,18 @@ use fuser::FileType;
 use futures::executor::ThreadPool;
 use futures::future::{BoxFuture, FutureExt};
 use mountpoint_s3::{
-    fs::{InodeNo, FUSE_ROOT_INODE},
+    fs::{InodeNo, InvalidKeyHandling, ReaddirOrder, ShadowPolicy, DEFAULT_MAX_NAME_LENGTH, FUSE_ROOT_INODE},
+    prefetch::PrefetcherConfig,
     prefix::Prefix,
     {S3Filesystem, S3FilesystemConfig},
 };
 use mountpoint_s3_client::mock_client::{MockClient, MockObject};
+use mountpoint_s3_client::ObjectClient;
 use proptest::prelude::*;
 use proptest_derive::Arbitrary;
 use std::collections::{BTreeMap, HashSet};
 use std::fmt::Debug;
-use std::path::{Component, Path};
+use std::path::{Component, Path, PathBuf};
 use std::sync::Arc;
 use tracing::debug;
 
@@ -29,6 +31,9 @@ pub enum Op {
         DirectoryIndex,
         FileContent,
     ),
+    AppendFile(FileIndex, FileContent),
+    /// Create an empty file and close it without writing anything, like `touch`
+    Touch(#[proptest(strategy = "valid_name_strategy()")] String, DirectoryIndex),
 }
 
 /// An index into the reference model's list of directories. We use this to randomly select an
@@ -47,16 +52,36 @@ impl DirectoryIndex {
     }
 }
 
+/// An index into the reference model's list of files, like [DirectoryIndex]
+#[derive(Debug, Arbitrary)]
+pub struct FileIndex(usize);
+
+impl FileIndex {
+    /// Get the full path to the file at the given index in the reference (wrapping around if the
+    /// index is larger than the number of files), or None if there are no files
+    fn get(&self, reference: &Reference) -> Option<PathBuf> {
+        let files = reference.files();
+        if files.is_empty() {
+            None
+        } else {
+            Some(files[self.0 % files.len()].clone())
+        }
+    }
+}
+
 #[derive(Debug)]
-pub struct Harness {
+pub struct Harness<Client: ObjectClient = Arc<MockClient>> {
     readdir_limit: usize, // max number of entries that a readdir will return; 0 means no limit
     reference: Reference,
-    fs: S3Filesystem<Arc<MockClient>, ThreadPool>,
+    fs: S3Filesystem<Client, ThreadPool>,
 }
 
-impl Harness {
+impl<Client> Harness<Client>
+where
+    Client: ObjectClient + Send + Sync + 'static,
+{
     /// Create a new test harness
-    pub fn new(fs: S3Filesystem<Arc<MockClient>, ThreadPool>, reference: Reference, readdir_limit: usize) -> Self {
+    pub fn new(fs: S3Filesystem<Client, ThreadPool>, reference: Reference, readdir_limit: usize) -> Self {
         Self {
             readdir_limit,
             reference,
@@ -75,22 +100,7 @@ impl Harness {
                     let full_path = dir.as_ref().join(name);
 
                     // Find the inode for the directory by walking the file system tree
-                    let mut components = dir.as_ref().components();
-                    assert_eq!(components.next(), Some(Component::RootDir));
-                    let mut inode = FUSE_ROOT_INODE;
-                    for component in components {
-                        if let Component::Normal(folder) = component {
-                            inode = self
-                                .fs
-                                .lookup(inode, folder)
-                                .await
-                                .expect("directory must already exist")
-                                .attr
-                                .ino;
-                        } else {
-                            panic!("unexpected path component {component:?}");
-                        }
-                    }
+                    let inode = self.lookup_path(dir.as_ref()).await;
                     drop(dir);
 
                     // Random paths can shadow existing ones, so we check that we aren't allowed to
@@ -107,7 +117,7 @@ impl Harness {
                         );
                     } else {
                         let mknod = self.fs.mknod(inode, name.as_ref(), libc::S_IFREG, 0, 0).await.unwrap();
-                        let open = self.fs.open(mknod.attr.ino, libc::O_WRONLY).await.unwrap();
+                        let open = self.fs.open(mknod.attr.ino, libc::O_WRONLY, 0).await.unwrap();
 
                         // TODO try testing more than one `write` call
                         let bytes = contents.to_boxed_slice();
@@ -123,6 +133,48 @@ impl Harness {
                         self.reference.add_file(&full_path, contents);
                     }
                 }
+                Op::AppendFile(file_index, contents) => {
+                    let Some(path) = file_index.get(&self.reference) else {
+                        continue;
+                    };
+                    let inode = self.lookup_path(&path).await;
+
+                    let size = self.fs.getattr(inode).await.unwrap().attr.size;
+                    let open = self.fs.open(inode, libc::O_WRONLY | libc::O_APPEND, 0).await.unwrap();
+
+                    let bytes = contents.to_boxed_slice();
+                    let write = self
+                        .fs
+                        .write(inode, open.fh, size as i64, &bytes, 0, 0, None)
+                        .await
+                        .unwrap();
+                    assert_eq!(write as usize, bytes.len());
+
+                    self.fs.release(inode, open.fh, 0, None, false).await.unwrap();
+
+                    self.reference.append_file(&path, &bytes);
+                }
+                Op::Touch(name, directory_index) => {
+                    let dir = directory_index.get(&self.reference);
+                    let full_path = dir.as_ref().join(name);
+
+                    let inode = self.lookup_path(dir.as_ref()).await;
+                    drop(dir);
+
+                    if self.reference.lookup(&full_path).is_some() {
+                        let mknod = self.fs.mknod(inode, name.as_ref(), libc::S_IFREG, 0, 0).await;
+                        assert!(
+                            matches!(mknod, Err(libc::EEXIST)),
+                            "can't overwrite existing file/directory"
+                        );
+                    } else {
+                        let mknod = self.fs.mknod(inode, name.as_ref(), libc::S_IFREG, 0, 0).await.unwrap();
+                        let open = self.fs.open(mknod.attr.ino, libc::O_WRONLY, 0).await.unwrap();
+                        self.fs.release(mknod.attr.ino, open.fh, 0, None, false).await.unwrap();
+
+                        self.reference.add_file(&full_path, &FileContent(0, FileSize::Small(0)));
+                    }
+                }
             }
 
             debug!(?op, "checking contents");
@@ -130,6 +182,27 @@ impl Harness {
         }
     }
 
+    /// Find the inode for a path by walking the file system tree. The path must already exist.
+    async fn lookup_path(&self, path: &Path) -> InodeNo {
+        let mut components = path.components();
+        assert_eq!(components.next(), Some(Component::RootDir));
+        let mut inode = FUSE_ROOT_INODE;
+        for component in components {
+            if let Component::Normal(name) = component {
+                inode = self
+                    .fs
+                    .lookup(inode, name)
+                    .await
+                    .expect("path must already exist")
+                    .attr
+                    .ino;
+            } else {
+                panic!("unexpected path component {component:?}");
+            }
+        }
+        inode
+    }
+
     /// Walk the filesystem tree and check that at each level, contents match the reference
     pub async fn compare_contents(&self) {
         let root = self.reference.root();
@@ -172,6 +245,17 @@ impl Harness {
         }
     }
 
+    /// Check that `.` or `..` in a directory resolves to the expected directory, with matching
+    /// attributes from both `lookup` and `getattr`
+    async fn check_dot_entry(&self, fs_dir: InodeNo, name: &str, expected_ino: InodeNo) {
+        let lookup = self.fs.lookup(fs_dir, name.as_ref()).await.unwrap();
+        assert_eq!(lookup.attr.ino, expected_ino, "wrong inode for {name:?} in {fs_dir}");
+        assert_eq!(lookup.attr.kind, FileType::Directory);
+        let getattr = self.fs.getattr(expected_ino).await.unwrap();
+        assert_eq!(getattr.attr.ino, expected_ino);
+        assert_eq!(getattr.attr.kind, FileType::Directory);
+    }
+
     fn compare_contents_recursive<'a>(
         &'a self,
         fs_parent: InodeNo,
@@ -186,10 +270,11 @@ impl Harness {
             let mut reply = DirectoryReply::new(self.readdir_limit);
             let _reply = self.fs.readdir(fs_dir, dir_handle, 0, &mut reply).await.unwrap();
 
-            // TODO `stat` on these needs to work
             let e0 = reply.entries.pop_front().unwrap();
             assert_eq!(e0.name, ".");
             assert_eq!(e0.ino, fs_dir);
+            assert_eq!(e0.attr.kind, FileType::Directory);
+            self.check_dot_entry(fs_dir, ".", fs_dir).await;
             let mut offset = e0.offset;
 
             if reply.entries.is_empty() {
@@ -200,6 +285,8 @@ impl Harness {
             let e1 = reply.entries.pop_front().unwrap();
             assert_eq!(e1.name, "..");
             assert_eq!(e1.ino, fs_parent);
+            assert_eq!(e1.attr.kind, FileType::Directory);
+            self.check_dot_entry(fs_dir, "..", fs_parent).await;
             offset = offset.max(e1.offset);
 
             if reply.entries.is_empty() {
@@ -262,13 +349,22 @@ impl Harness {
     }
 
     async fn compare_file<'a>(&'a self, fs_file: InodeNo, ref_file: &'a MockObject) {
-        let fh = self.fs.open(fs_file, 0x8000).await.unwrap().fh;
+        let fh = self.fs.open(fs_file, 0x8000, 0).await.unwrap().fh;
         let mut offset = 0;
         const MAX_READ_SIZE: usize = 4_096;
+        // Read huge files in bigger chunks (the FUSE maximum) to keep the test fast. Each chunk is
+        // generated on demand, so we never hold the whole file in memory.
+        const HUGE_FILE_SIZE: usize = 16 * 1024 * 1024;
+        const MAX_HUGE_READ_SIZE: usize = 128 * 1024;
         let file_size = ref_file.len();
+        let max_read_size = if file_size >= HUGE_FILE_SIZE {
+            MAX_HUGE_READ_SIZE
+        } else {
+            MAX_READ_SIZE
+        };
         while offset < file_size {
             let mut read = Err(0);
-            let num_bytes = MAX_READ_SIZE.min(file_size - offset);
+            let num_bytes = max_read_size.min(file_size - offset);
             self.fs
                 .read(
                     fs_file,
@@ -293,6 +389,7 @@ impl Harness {
 /// paths is correct.
 mod read_only {
     use super::*;
+    use mountpoint_s3_client::mock_client::Operation;
 
     #[derive(Debug)]
     enum CheckType {
@@ -306,9 +403,30 @@ mod read_only {
     }
 
     fn run_test(tree: TreeNode, check: CheckType, readdir_limit: usize) {
+        run_test_with_shadow_policy(tree, check, readdir_limit, ShadowPolicy::default())
+    }
+
+    fn run_test_with_shadow_policy(
+        tree: TreeNode,
+        check: CheckType,
+        readdir_limit: usize,
+        shadow_policy: ShadowPolicy,
+    ) {
+        run_test_with_policies(tree, check, readdir_limit, shadow_policy, InvalidKeyHandling::default())
+    }
+
+    fn run_test_with_policies(
+        tree: TreeNode,
+        check: CheckType,
+        readdir_limit: usize,
+        shadow_policy: ShadowPolicy,
+        invalid_key_handling: InvalidKeyHandling,
+    ) {
         let test_prefix = Prefix::new("test_prefix/").expect("valid prefix");
         let config = S3FilesystemConfig {
             readdir_size: 5,
+            shadow_policy,
+            invalid_key_handling,
             ..Default::default()
         };
         let (client, fs) = make_test_filesystem("harness", &test_prefix, config);
@@ -318,7 +436,7 @@ mod read_only {
             client.add_object(&format!("{test_prefix}{key}"), object.to_mock_object());
         }
 
-        let reference = build_reference(namespace);
+        let reference = build_reference(namespace, shadow_policy, invalid_key_handling);
 
         let harness = Harness::new(fs, reference, readdir_limit);
 
@@ -345,6 +463,150 @@ mod read_only {
         fn reftest_random_tree_single(tree in gen_tree(5, 100, 5, 20), path_index: usize) {
             run_test(tree, CheckType::SinglePath { path_index }, 0);
         }
+
+        #[test]
+        fn reftest_random_tree_full_sanitized(readdir_limit in 0..10usize, tree in gen_tree(5, 100, 5, 20)) {
+            run_test_with_policies(tree, CheckType::FullTree, readdir_limit, ShadowPolicy::default(), InvalidKeyHandling::Sanitize);
+        }
+    }
+
+    #[test]
+    fn huge_file() {
+        let test_prefix = Prefix::new("test_prefix/").expect("valid prefix");
+        // Bound how far ahead the prefetcher reads, so only a few MiB of the file are ever buffered
+        let config = S3FilesystemConfig {
+            prefetcher_config: PrefetcherConfig {
+                max_request_size: 8 * 1024 * 1024,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (client, fs) = make_test_filesystem("harness", &test_prefix, config);
+
+        let tree = TreeNode::Directory(BTreeMap::from([(
+            Name("huge".to_string()),
+            TreeNode::File(FileContent(0x2a, FileSize::Huge(256 * 1024 * 1024))),
+        )]));
+        let namespace = flatten_tree(tree);
+        for (key, object) in namespace.iter() {
+            client.add_object(&format!("{test_prefix}{key}"), object.to_mock_object());
+        }
+        let reference = build_reference(namespace, ShadowPolicy::default(), InvalidKeyHandling::default());
+
+        let harness = Harness::new(fs, reference, 0);
+        futures::executor::block_on(harness.compare_contents());
+    }
+
+    #[test]
+    fn readdir_then_open_skips_head_object() {
+        let test_prefix = Prefix::new("test_prefix/").expect("valid prefix");
+        let (client, fs) = make_test_filesystem("harness", &test_prefix, Default::default());
+
+        let tree = TreeNode::Directory(BTreeMap::from([
+            (
+                Name("a".to_string()),
+                TreeNode::File(FileContent(0x0a, FileSize::Small(100))),
+            ),
+            (
+                Name("b".to_string()),
+                TreeNode::File(FileContent(0x0b, FileSize::Small(5000))),
+            ),
+        ]));
+        let namespace = flatten_tree(tree);
+        let mut objects = BTreeMap::new();
+        for (key, object) in namespace.iter() {
+            client.add_object(&format!("{test_prefix}{key}"), object.to_mock_object());
+            objects.insert(key.clone(), object.to_mock_object());
+        }
+        let reference = build_reference(namespace, ShadowPolicy::default(), InvalidKeyHandling::default());
+        let harness = Harness::new(fs, reference, 0);
+
+        futures::executor::block_on(async {
+            // Like `ls` then `cat`: with readdirplus, the kernel already has the attributes of
+            // each entry, so it opens them without looking them up first
+            let dir_handle = harness.fs.opendir(FUSE_ROOT_INODE, 0).await.unwrap().fh;
+            let mut reply = DirectoryReply::new(0);
+            let _reply = harness
+                .fs
+                .readdir(FUSE_ROOT_INODE, dir_handle, 0, &mut reply)
+                .await
+                .unwrap();
+
+            let mut opened = 0;
+            for entry in reply
+                .entries
+                .iter()
+                .filter(|entry| entry.attr.kind == FileType::RegularFile)
+            {
+                let object = &objects[entry.name.to_str().unwrap()];
+                harness.compare_file(entry.ino, object).await;
+                opened += 1;
+            }
+            assert_eq!(opened, 2);
+        });
+
+        assert_eq!(client.op_count(Operation::HeadObject), 0);
+        assert!(client.op_count(Operation::GetObject) > 0);
+    }
+
+    #[test]
+    fn readdir_primes_lookup_cache() {
+        for prime_lookup_cache_from_readdir in [false, true] {
+            let test_prefix = Prefix::new("test_prefix/").expect("valid prefix");
+            let config = S3FilesystemConfig {
+                prime_lookup_cache_from_readdir,
+                ..Default::default()
+            };
+            let (client, fs) = make_test_filesystem("harness", &test_prefix, config);
+
+            let tree = TreeNode::Directory(BTreeMap::from([
+                (
+                    Name("a".to_string()),
+                    TreeNode::File(FileContent(0x0a, FileSize::Small(100))),
+                ),
+                (
+                    Name("dir".to_string()),
+                    TreeNode::Directory(BTreeMap::from([(
+                        Name("b".to_string()),
+                        TreeNode::File(FileContent(0x0b, FileSize::Small(100))),
+                    )])),
+                ),
+            ]));
+            for (key, object) in flatten_tree(tree) {
+                client.add_object(&format!("{test_prefix}{key}"), object.to_mock_object());
+            }
+
+            futures::executor::block_on(async {
+                let dir_handle = fs.opendir(FUSE_ROOT_INODE, 0).await.unwrap().fh;
+                let mut reply = DirectoryReply::new(0);
+                let _reply = fs.readdir(FUSE_ROOT_INODE, dir_handle, 0, &mut reply).await.unwrap();
+
+                let requests_after_readdir = (
+                    client.op_count(Operation::HeadObject),
+                    client.op_count(Operation::ListObjects),
+                );
+                for entry in reply
+                    .entries
+                    .iter()
+                    .filter(|entry| entry.name != "." && entry.name != "..")
+                {
+                    let lookup = fs.lookup(FUSE_ROOT_INODE, &entry.name).await.unwrap();
+                    assert_eq!(lookup.attr.ino, entry.ino);
+                    assert_eq!(lookup.attr.kind, entry.attr.kind);
+                    assert_eq!(lookup.attr.size, entry.attr.size);
+                }
+                let requests_after_lookups = (
+                    client.op_count(Operation::HeadObject),
+                    client.op_count(Operation::ListObjects),
+                );
+
+                if prime_lookup_cache_from_readdir {
+                    assert_eq!(requests_after_lookups, requests_after_readdir);
+                } else {
+                    assert_ne!(requests_after_lookups, requests_after_readdir);
+                }
+            });
+        }
     }
 
     #[test]
@@ -383,6 +645,122 @@ mod read_only {
         );
     }
 
+    /// List the names in a directory, a few entries per `readdir` call so that offsets are
+    /// carried across calls
+    async fn readdir_names(fs: &S3Filesystem<Arc<MockClient>, ThreadPool>, ino: InodeNo) -> Vec<String> {
+        let dir_handle = fs.opendir(ino, 0).await.unwrap().fh;
+        let mut names = vec![];
+        let mut offset = 0;
+        loop {
+            let mut reply = DirectoryReply::new(2);
+            let _reply = fs.readdir(ino, dir_handle, offset, &mut reply).await.unwrap();
+            if reply.entries.is_empty() {
+                return names;
+            }
+            for entry in reply.entries {
+                assert_eq!(entry.offset, offset + 1, "offsets should be sequential");
+                offset = entry.offset;
+                names.push(entry.name.into_string().unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn readdir_order() {
+        let file = || TreeNode::File(FileContent(0, FileSize::Small(0)));
+        let dir = || TreeNode::Directory(BTreeMap::from([(Name("-".to_string()), file())]));
+        let tree = TreeNode::Directory(BTreeMap::from([
+            (Name("B".to_string()), file()),
+            (Name("a".to_string()), dir()),
+            (Name("C".to_string()), dir()),
+            (Name("b".to_string()), file()),
+            (Name("-a".to_string()), file()),
+            (Name("-a-".to_string()), dir()),
+        ]));
+
+        let cases = [
+            (ReaddirOrder::S3Lexicographic, ["-a", "-a-", "B", "C", "a", "b"]),
+            (ReaddirOrder::DirsFirst, ["-a-", "C", "a", "-a", "B", "b"]),
+            (ReaddirOrder::CaseInsensitive, ["-a", "-a-", "a", "B", "b", "C"]),
+        ];
+
+        let namespace = flatten_tree(tree);
+        for (readdir_order, expected) in cases {
+            let test_prefix = Prefix::new("test_prefix/").expect("valid prefix");
+            let config = S3FilesystemConfig {
+                readdir_size: 5,
+                readdir_order,
+                ..Default::default()
+            };
+            let (client, fs) = make_test_filesystem("harness", &test_prefix, config);
+            for (key, object) in namespace.iter() {
+                client.add_object(&format!("{test_prefix}{key}"), object.to_mock_object());
+            }
+
+            let names = futures::executor::block_on(readdir_names(&fs, FUSE_ROOT_INODE));
+            let mut expected_names = vec![".", ".."];
+            expected_names.extend(expected);
+            assert_eq!(names, expected_names, "unexpected order for {readdir_order:?}");
+        }
+    }
+
+    #[test]
+    fn readdir_reply_size() {
+        const REPLY_SIZE: usize = 1024;
+        let test_prefix = Prefix::new("test_prefix/").expect("valid prefix");
+        let config = S3FilesystemConfig {
+            readdir_size: 5,
+            readdir_reply_size: Some(REPLY_SIZE),
+            ..Default::default()
+        };
+        let (client, fs) = make_test_filesystem("harness", &test_prefix, config);
+
+        // Long enough that only a couple of entries fit in each reply
+        let mut expected_names = (0..20).map(|i| format!("{i:0>250}")).collect::<Vec<_>>();
+        for name in &expected_names {
+            client.add_object(
+                &format!("{test_prefix}{name}"),
+                FileContent(0, FileSize::Small(1)).to_mock_object(),
+            );
+        }
+        expected_names.sort();
+
+        futures::executor::block_on(async move {
+            let dir_handle = fs.opendir(FUSE_ROOT_INODE, 0).await.unwrap().fh;
+            let mut names = vec![];
+            let mut offset = 0;
+            loop {
+                let mut reply = DirectoryReply::new(0);
+                let _reply = fs
+                    .readdir(FUSE_ROOT_INODE, dir_handle, offset, &mut reply)
+                    .await
+                    .unwrap();
+                if reply.entries.is_empty() {
+                    break;
+                }
+                // Each entry is a `fuse_entry_out` and `fuse_dirent` followed by the padded name
+                let size: usize = reply
+                    .entries
+                    .iter()
+                    .map(|entry| (152 + entry.name.len() + 7) & !7)
+                    .sum();
+                assert!(
+                    size <= REPLY_SIZE || reply.entries.len() == 1,
+                    "reply of {size} bytes is too large"
+                );
+                for entry in reply.entries {
+                    assert_eq!(entry.offset, offset + 1, "offsets should be sequential");
+                    offset = entry.offset;
+                    names.push(entry.name.into_string().unwrap());
+                }
+            }
+
+            let mut expected = vec![".".to_owned(), "..".to_owned()];
+            expected.extend(expected_names);
+            assert_eq!(names, expected);
+        });
+    }
+
     #[test]
     fn random_tree_regression_invalid_name1() {
         run_test(
@@ -454,6 +832,203 @@ mod read_only {
             0,
         )
     }
+
+    /// All the paths in the reference, in order. `list_recursive` returns files twice, once as a
+    /// child of their directory and once as a node of their own.
+    fn reference_paths(reference: &Reference) -> Vec<String> {
+        let mut paths = reference
+            .list_recursive()
+            .into_iter()
+            .map(|(path, _node)| path.join("/"))
+            .collect::<Vec<_>>();
+        paths.dedup();
+        paths
+    }
+
+    /// Both `a/a` and `a/a/` exist, as in [random_tree_regression_directory_shadow], as well as a
+    /// file `b` that has a child `b/c`
+    fn shadow_policy_tree() -> TreeNode {
+        let file = || TreeNode::File(FileContent(0, FileSize::Small(0)));
+        TreeNode::Directory(BTreeMap::from([
+            (
+                Name("a".to_string()),
+                TreeNode::Directory(BTreeMap::from([
+                    (Name("a/".to_string()), file()),
+                    (Name("a".to_string()), file()),
+                ])),
+            ),
+            (Name("b".to_string()), file()),
+            (Name("b/c".to_string()), file()),
+        ]))
+    }
+
+    fn run_shadow_policy_test(shadow_policy: ShadowPolicy, expected_paths: &[&str]) {
+        let reference = build_reference(
+            flatten_tree(shadow_policy_tree()),
+            shadow_policy,
+            InvalidKeyHandling::default(),
+        );
+        assert_eq!(reference_paths(&reference), expected_paths);
+
+        run_test_with_shadow_policy(shadow_policy_tree(), CheckType::FullTree, 0, shadow_policy);
+        for path_index in 0..expected_paths.len() {
+            run_test_with_shadow_policy(
+                shadow_policy_tree(),
+                CheckType::SinglePath { path_index },
+                0,
+                shadow_policy,
+            );
+        }
+    }
+
+    #[test]
+    fn shadow_policy_prefer_directory() {
+        run_shadow_policy_test(ShadowPolicy::PreferDirectory, &["a", "a/a", "b", "b/c"]);
+    }
+
+    #[test]
+    fn shadow_policy_prefer_file() {
+        run_shadow_policy_test(ShadowPolicy::PreferFile, &["a", "a/a", "b"]);
+    }
+
+    #[test]
+    fn shadow_policy_suffix() {
+        run_shadow_policy_test(ShadowPolicy::Suffix, &["a", "a/a", "a/a@file", "b", "b/c", "b@file"]);
+    }
+
+    /// Keys ending in `/`: `a/` isn't empty, `b/` is an empty directory marker, and `c/` is both a
+    /// marker and has a child named like a directory's own object
+    fn trailing_slash_tree() -> TreeNode {
+        let file = |size| TreeNode::File(FileContent(0xaa, FileSize::Small(size)));
+        TreeNode::Directory(BTreeMap::from([(
+            Name("-".to_string()),
+            TreeNode::Directory(BTreeMap::from([
+                (Name("a/".to_string()), file(10)),
+                (Name("b/".to_string()), file(0)),
+                (Name("c/".to_string()), file(0)),
+                (Name("c/@dir".to_string()), file(5)),
+                (Name("c/d".to_string()), file(5)),
+            ])),
+        )]))
+    }
+
+    fn run_trailing_slash_test(invalid_key_handling: InvalidKeyHandling, expected_paths: &[&str]) {
+        let reference = build_reference(
+            flatten_tree(trailing_slash_tree()),
+            ShadowPolicy::default(),
+            invalid_key_handling,
+        );
+        assert_eq!(reference_paths(&reference), expected_paths);
+
+        for readdir_limit in [0, 1] {
+            run_test_with_policies(
+                trailing_slash_tree(),
+                CheckType::FullTree,
+                readdir_limit,
+                ShadowPolicy::default(),
+                invalid_key_handling,
+            );
+        }
+        for path_index in 0..expected_paths.len() {
+            run_test_with_policies(
+                trailing_slash_tree(),
+                CheckType::SinglePath { path_index },
+                0,
+                ShadowPolicy::default(),
+                invalid_key_handling,
+            );
+        }
+    }
+
+    #[test]
+    fn trailing_slash_hide() {
+        run_trailing_slash_test(
+            InvalidKeyHandling::Hide,
+            &["-", "-/a", "-/b", "-/c", "-/c/@dir", "-/c/d"],
+        );
+    }
+
+    #[test]
+    fn trailing_slash_sanitize() {
+        run_trailing_slash_test(
+            InvalidKeyHandling::Sanitize,
+            &["-", "-/a", "-/a/@dir", "-/b", "-/c", "-/c/d"],
+        );
+    }
+
+    /// A file and a directory whose names are 300 bytes long, next to a file with a short name
+    fn long_name_tree() -> TreeNode {
+        let file = || TreeNode::File(FileContent(0xaa, FileSize::Small(5)));
+        let long_name = "a".repeat(300);
+        TreeNode::Directory(BTreeMap::from([
+            (Name(long_name.clone()), file()),
+            (
+                Name(format!("{long_name}-dir")),
+                TreeNode::Directory(BTreeMap::from([(Name("b".to_string()), file())])),
+            ),
+            (Name("c".to_string()), file()),
+        ]))
+    }
+
+    fn run_long_name_test(invalid_key_handling: InvalidKeyHandling, expected_lookup_error: libc::c_int) {
+        let reference = build_reference(
+            flatten_tree(long_name_tree()),
+            ShadowPolicy::default(),
+            invalid_key_handling,
+        );
+        assert_eq!(reference_paths(&reference), &["c"]);
+
+        run_test_with_policies(
+            long_name_tree(),
+            CheckType::FullTree,
+            0,
+            ShadowPolicy::default(),
+            invalid_key_handling,
+        );
+
+        let config = S3FilesystemConfig {
+            invalid_key_handling,
+            ..Default::default()
+        };
+        let (client, fs) = make_test_filesystem("long_names", &Default::default(), config);
+        for (key, object) in flatten_tree(long_name_tree()) {
+            client.add_object(&key, object.to_mock_object());
+        }
+
+        futures::executor::block_on(async {
+            let long_name = "a".repeat(300);
+            for name in [long_name.clone(), format!("{long_name}-dir")] {
+                let lookup = fs.lookup(FUSE_ROOT_INODE, name.as_ref()).await;
+                assert!(
+                    matches!(lookup, Err(e) if e == expected_lookup_error),
+                    "unexpected lookup result {lookup:?}"
+                );
+            }
+
+            // Long names can't be created, whether or not they exist in the bucket
+            for name in [long_name.clone(), "b".repeat(300)] {
+                let mknod = fs
+                    .mknod(FUSE_ROOT_INODE, name.as_ref(), libc::S_IFREG | libc::S_IRWXU, 0, 0)
+                    .await;
+                assert!(matches!(mknod, Err(libc::ENAMETOOLONG)));
+            }
+
+            let name = "b".repeat(DEFAULT_MAX_NAME_LENGTH);
+            fs.mknod(FUSE_ROOT_INODE, name.as_ref(), libc::S_IFREG | libc::S_IRWXU, 0, 0)
+                .await
+                .expect("names of the maximum length can be created");
+        });
+    }
+
+    #[test]
+    fn long_name_hide() {
+        run_long_name_test(InvalidKeyHandling::Hide, libc::ENOENT);
+    }
+
+    #[test]
+    fn long_name_sanitize() {
+        run_long_name_test(InvalidKeyHandling::Sanitize, libc::ENAMETOOLONG);
+    }
 }
 
 /// Mutation tests that run a sequence of mutations against a file system and check equivalence to
@@ -463,9 +1038,15 @@ mod mutations {
     use proptest::collection::vec;
 
     fn run_test(initial_tree: TreeNode, ops: Vec<Op>, readdir_limit: usize) {
+        run_test_with_client(initial_tree, ops, readdir_limit);
+    }
+
+    /// Like [run_test], but return the client so that the test can check the bucket afterwards
+    fn run_test_with_client(initial_tree: TreeNode, ops: Vec<Op>, readdir_limit: usize) -> Arc<MockClient> {
         let test_prefix = Prefix::new("test_prefix/").expect("valid prefix");
         let config = S3FilesystemConfig {
             readdir_size: 5,
+            enable_append: true,
             ..Default::default()
         };
         let (client, fs) = make_test_filesystem("harness", &test_prefix, config);
@@ -475,11 +1056,13 @@ mod mutations {
             client.add_object(&format!("{test_prefix}{key}"), object.to_mock_object());
         }
 
-        let reference = build_reference(namespace);
+        let reference = build_reference(namespace, ShadowPolicy::default(), InvalidKeyHandling::default());
 
         let mut harness = Harness::new(fs, reference, readdir_limit);
 
         futures::executor::block_on(harness.run(ops));
+
+        client
     }
 
     proptest! {
@@ -531,4 +1114,1141 @@ mod mutations {
             0,
         )
     }
+
+    #[test]
+    fn touch_creates_empty_object() {
+        let client = run_test_with_client(
+            TreeNode::Directory(BTreeMap::from([(
+                Name("dir".to_string()),
+                TreeNode::Directory(BTreeMap::from([(
+                    Name("a".to_string()),
+                    TreeNode::File(FileContent(0xaa, FileSize::Small(10))),
+                )])),
+            )])),
+            vec![
+                Op::Touch("empty".to_string(), DirectoryIndex(0)),
+                Op::Touch("empty".to_string(), DirectoryIndex(1)),
+                // Already exists, so fails
+                Op::Touch("a".to_string(), DirectoryIndex(1)),
+            ],
+            0,
+        );
+
+        futures::executor::block_on(async {
+            for key in ["test_prefix/empty", "test_prefix/dir/empty"] {
+                let head = client
+                    .head_object("harness", key, None)
+                    .await
+                    .expect("empty object should have been uploaded");
+                assert_eq!(head.object.size, 0);
+            }
+            let head = client.head_object("harness", "test_prefix/dir/a", None).await.unwrap();
+            assert_eq!(head.object.size, 10);
+        });
+    }
+
+    #[test]
+    fn append_to_remote_file() {
+        run_test(
+            TreeNode::Directory(BTreeMap::from([(
+                Name("a".to_string()),
+                TreeNode::File(FileContent(0xaa, FileSize::Small(10))),
+            )])),
+            vec![
+                Op::AppendFile(FileIndex(0), FileContent(0xbb, FileSize::Small(5))),
+                Op::AppendFile(FileIndex(0), FileContent(0xcc, FileSize::Large(128 * 1024))),
+            ],
+            0,
+        )
+    }
+
+    #[test]
+    fn append_to_new_file() {
+        run_test(
+            TreeNode::Directory(BTreeMap::new()),
+            vec![
+                Op::WriteFile(
+                    "a".to_string(),
+                    DirectoryIndex(0),
+                    FileContent(0xaa, FileSize::Small(0)),
+                ),
+                Op::AppendFile(FileIndex(0), FileContent(0xbb, FileSize::Small(5))),
+            ],
+            0,
+        )
+    }
+}
+
+/// A subset of the read-only and mutation reftests, run against a [LocalObjectClient] that stores
+/// the bucket in a temporary directory rather than in memory.
+mod local_client {
+    use super::*;
+    use futures::executor::block_on;
+    use mountpoint_s3_client::local_client::LocalObjectClient;
+    use proptest::collection::vec;
+    use tempfile::TempDir;
+
+    /// Create a file system backed by a new temporary directory holding the given tree. The
+    /// directory is deleted when the returned [TempDir] is dropped.
+    fn make_local_filesystem(
+        tree: TreeNode,
+        config: S3FilesystemConfig,
+    ) -> (TempDir, S3Filesystem<LocalObjectClient, ThreadPool>, Reference) {
+        let test_prefix = Prefix::new("test_prefix/").expect("valid prefix");
+        let root = tempfile::tempdir().unwrap();
+        let client = LocalObjectClient::new("harness", root.path()).unwrap();
+
+        let namespace = flatten_tree(tree);
+        for (key, object) in namespace.iter() {
+            let contents = object.to_boxed_slice();
+            let key = format!("{test_prefix}{key}");
+            block_on(client.put_object("harness", &key, &Default::default(), futures::stream::iter([contents])))
+                .unwrap();
+        }
+
+        let runtime = ThreadPool::builder().pool_size(1).create().unwrap();
+        let fs = S3Filesystem::new(client, runtime, "harness", &test_prefix, config);
+        let reference = build_reference(namespace, ShadowPolicy::default(), InvalidKeyHandling::default());
+        (root, fs, reference)
+    }
+
+    fn run_read_only_test(tree: TreeNode, readdir_limit: usize) {
+        let config = S3FilesystemConfig {
+            readdir_size: 5,
+            ..Default::default()
+        };
+        let (_root, fs, reference) = make_local_filesystem(tree, config);
+        let harness = Harness::new(fs, reference, readdir_limit);
+        block_on(harness.compare_contents());
+    }
+
+    fn run_mutation_test(tree: TreeNode, ops: Vec<Op>, readdir_limit: usize) {
+        let config = S3FilesystemConfig {
+            readdir_size: 5,
+            enable_append: true,
+            ..Default::default()
+        };
+        let (_root, fs, reference) = make_local_filesystem(tree, config);
+        let mut harness = Harness::new(fs, reference, readdir_limit);
+        block_on(harness.run(ops));
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig {
+            // Every case writes its whole tree to disk, so run fewer of them than the mock tests
+            cases: 32,
+            failure_persistence: None,
+            .. ProptestConfig::default()
+        })]
+
+        #[test]
+        fn reftest_random_tree_full(readdir_limit in 0..10usize, tree in gen_tree(5, 100, 5, 20)) {
+            run_read_only_test(tree, readdir_limit);
+        }
+
+        #[test]
+        fn reftest_random_tree_mutations(tree in gen_tree(5, 100, 5, 20), readdir_limit in 0..10usize, ops in vec(any::<Op>(), 1..10)) {
+            run_mutation_test(tree, ops, readdir_limit);
+        }
+    }
+
+    #[test]
+    fn directory_shadow() {
+        // Keys `a/a` and `a/a/` need both a file and a directory for `a/a` on disk
+        run_read_only_test(
+            TreeNode::Directory(BTreeMap::from([(
+                Name("a".to_string()),
+                TreeNode::Directory(BTreeMap::from([
+                    (
+                        Name("a/".to_string()),
+                        TreeNode::File(FileContent(0, FileSize::Small(0))),
+                    ),
+                    (
+                        Name("a".to_string()),
+                        TreeNode::File(FileContent(0, FileSize::Small(0))),
+                    ),
+                ])),
+            )])),
+            0,
+        );
+    }
+
+    #[test]
+    fn append_to_remote_file() {
+        run_mutation_test(
+            TreeNode::Directory(BTreeMap::from([(
+                Name("a".to_string()),
+                TreeNode::File(FileContent(0xaa, FileSize::Small(10))),
+            )])),
+            vec![
+                Op::AppendFile(FileIndex(0), FileContent(0xbb, FileSize::Small(5))),
+                Op::AppendFile(FileIndex(0), FileContent(0xcc, FileSize::Large(128 * 1024))),
+            ],
+            0,
+        )
+    }
+}
+
+/// Tests for the synthetic `name@v=<version id>` paths that expose historical object versions.
+mod versions {
+    use super::*;
+    use mountpoint_s3_client::ObjectClient;
+    use time::OffsetDateTime;
+
+    async fn read_file(fs: &S3Filesystem<Arc<MockClient>, ThreadPool>, ino: InodeNo, size: usize) -> Box<[u8]> {
+        let fh = fs.open(ino, libc::O_RDONLY, 0).await.unwrap().fh;
+        let mut read = Err(0);
+        fs.read(ino, fh, 0, size as u32, 0, None, ReadReply(&mut read)).await;
+        fs.release(ino, fh, 0, None, false).await.unwrap();
+        read.unwrap()
+    }
+
+    #[test]
+    fn read_old_version_through_synthetic_path() {
+        let test_prefix = Prefix::new("test_prefix/").expect("valid prefix");
+        let config = S3FilesystemConfig {
+            expose_versions: true,
+            ..Default::default()
+        };
+        let (client, fs) = make_test_filesystem("harness", &test_prefix, config);
+
+        let old = FileContent(0xaa, FileSize::Small(20));
+        let new = FileContent(0xbb, FileSize::Small(30));
+        let key = format!("{test_prefix}file");
+
+        futures::executor::block_on(async move {
+            client.add_object(&key, old.to_mock_object());
+            let old_version = client
+                .head_object("harness", &key, None)
+                .await
+                .unwrap()
+                .object
+                .version_id
+                .expect("mock client should assign version ids");
+            client.add_object(&key, new.to_mock_object());
+
+            let versioned_name = format!("file@v={old_version}");
+            let lookup = fs.lookup(FUSE_ROOT_INODE, versioned_name.as_ref()).await.unwrap();
+            assert_eq!(lookup.attr.kind, FileType::RegularFile);
+            assert_eq!(lookup.attr.size, 20);
+            let bytes = read_file(&fs, lookup.attr.ino, 20).await;
+            assert_eq!(bytes, old.to_boxed_slice());
+
+            // Looking up the same version again should return the same inode
+            let again = fs.lookup(FUSE_ROOT_INODE, versioned_name.as_ref()).await.unwrap();
+            assert_eq!(again.attr.ino, lookup.attr.ino);
+
+            let latest = fs.lookup(FUSE_ROOT_INODE, "file".as_ref()).await.unwrap();
+            assert_ne!(latest.attr.ino, lookup.attr.ino);
+            assert_eq!(latest.attr.size, 30);
+            let bytes = read_file(&fs, latest.attr.ino, 30).await;
+            assert_eq!(bytes, new.to_boxed_slice());
+
+            // Versioned paths are read-only
+            assert!(matches!(
+                fs.open(lookup.attr.ino, libc::O_WRONLY, 0).await,
+                Err(libc::EPERM)
+            ));
+
+            // Unknown versions don't exist
+            let missing = fs.lookup(FUSE_ROOT_INODE, "file@v=not-a-version".as_ref()).await;
+            assert!(matches!(missing, Err(libc::ENOENT)));
+        });
+    }
+
+    #[test]
+    fn pinned_read_returns_older_version() {
+        let test_prefix = Prefix::new("test_prefix/").expect("valid prefix");
+        let created = OffsetDateTime::from_unix_timestamp(1_000_000).unwrap();
+        let config = S3FilesystemConfig {
+            pinned_as_of: Some(created + time::Duration::minutes(30)),
+            ..Default::default()
+        };
+        let (client, fs) = make_test_filesystem("harness", &test_prefix, config);
+
+        let old = FileContent(0xaa, FileSize::Small(20));
+        let new = FileContent(0xbb, FileSize::Small(30));
+
+        let mut object = old.to_mock_object();
+        object.set_last_modified(created);
+        client.add_object(&format!("{test_prefix}file"), object);
+        let mut object = new.to_mock_object();
+        object.set_last_modified(created + time::Duration::hours(1));
+        client.add_object(&format!("{test_prefix}file"), object);
+        // Created after the pinned time, so isn't visible
+        let mut object = new.to_mock_object();
+        object.set_last_modified(created + time::Duration::hours(1));
+        client.add_object(&format!("{test_prefix}later"), object);
+
+        futures::executor::block_on(async move {
+            let lookup = fs.lookup(FUSE_ROOT_INODE, "file".as_ref()).await.unwrap();
+            assert_eq!(lookup.attr.size, 20);
+            let bytes = read_file(&fs, lookup.attr.ino, 20).await;
+            assert_eq!(bytes, old.to_boxed_slice());
+
+            // The pinned version is read-only
+            assert!(matches!(
+                fs.open(lookup.attr.ino, libc::O_WRONLY, 0).await,
+                Err(libc::EPERM)
+            ));
+
+            let missing = fs.lookup(FUSE_ROOT_INODE, "later".as_ref()).await;
+            assert!(matches!(missing, Err(libc::ENOENT)));
+        });
+    }
+}
+
+/// Tests for settings that are overridden for keys under particular prefixes
+mod prefix_overrides {
+    use super::*;
+    use mountpoint_s3::fs::PartialConfig;
+    use mountpoint_s3_client::{ObjectAttribute, ObjectClient};
+    use std::time::Duration;
+
+    /// Create a file with the given contents in the given directory through the file system
+    async fn write_file(
+        fs: &S3Filesystem<Arc<MockClient>, ThreadPool>,
+        parent: InodeNo,
+        name: &str,
+        content: &FileContent,
+    ) {
+        let mode = libc::S_IFREG | libc::S_IRWXU;
+        let ino = fs.mknod(parent, name.as_ref(), mode, 0, 0).await.unwrap().attr.ino;
+        let fh = fs.open(ino, libc::O_WRONLY, 0).await.unwrap().fh;
+        fs.write(ino, fh, 0, &content.to_boxed_slice(), 0, 0, None)
+            .await
+            .unwrap();
+        fs.release(ino, fh, 0, None, true).await.unwrap();
+    }
+
+    #[test]
+    fn overlapping_prefixes() {
+        let test_prefix = Prefix::new("test_prefix/").expect("valid prefix");
+        let override_for = |prefix: &str, storage_class: &str, stat_ttl: Option<Duration>| {
+            let prefix = Prefix::new(&format!("{test_prefix}{prefix}")).expect("valid prefix");
+            let config = PartialConfig {
+                storage_class: Some(storage_class.to_owned()),
+                stat_ttl,
+            };
+            (prefix, config)
+        };
+        let config = S3FilesystemConfig {
+            stat_ttl: Duration::from_secs(1),
+            prefix_overrides: vec![
+                // The more specific rule comes first to check that order doesn't matter
+                override_for("archive/cold/", "GLACIER", None),
+                override_for("archive/", "STANDARD_IA", Some(Duration::from_secs(60))),
+            ],
+            ..Default::default()
+        };
+        let (client, fs) = make_test_filesystem("harness", &test_prefix, config);
+
+        // Make sure the directories exist
+        let placeholder = FileContent(0, FileSize::Small(0)).to_mock_object();
+        client.add_object(&format!("{test_prefix}archive/cold/placeholder"), placeholder);
+
+        let content = FileContent(0xaa, FileSize::Small(10));
+
+        futures::executor::block_on(async {
+            let archive = fs.lookup(FUSE_ROOT_INODE, "archive".as_ref()).await.unwrap();
+            assert_eq!(archive.ttl, Duration::from_secs(60));
+            let cold = fs.lookup(archive.attr.ino, "cold".as_ref()).await.unwrap();
+
+            let expected = [
+                ("file", FUSE_ROOT_INODE, "STANDARD", Duration::from_secs(1)),
+                ("archive/file", archive.attr.ino, "STANDARD_IA", Duration::from_secs(60)),
+                ("archive/cold/file", cold.attr.ino, "GLACIER", Duration::from_secs(60)),
+            ];
+            for (path, parent, storage_class, ttl) in expected {
+                write_file(&fs, parent, "file", &content).await;
+
+                let key = format!("{test_prefix}{path}");
+                let attributes = client
+                    .get_object_attributes("harness", &key, None, None, &[ObjectAttribute::StorageClass])
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    attributes.storage_class.as_deref(),
+                    Some(storage_class),
+                    "wrong storage class for {path}"
+                );
+
+                let entry = fs.lookup(parent, "file".as_ref()).await.unwrap();
+                assert_eq!(entry.ttl, ttl, "wrong stat TTL for {path}");
+            }
+        });
+    }
+}
+
+/// Tests for reading objects that are replaced or deleted by another writer while they're open
+mod stale_reads {
+    use super::*;
+    use mountpoint_s3::fs::StaleReadPolicy;
+    use mountpoint_s3_client::ETag;
+    use std::str::FromStr;
+    use std::time::Duration;
+    use test_case::test_case;
+
+    async fn read(
+        fs: &S3Filesystem<Arc<MockClient>, ThreadPool>,
+        ino: InodeNo,
+        fh: u64,
+        offset: i64,
+        size: u32,
+    ) -> Result<Box<[u8]>, libc::c_int> {
+        let mut read = Err(0);
+        fs.read(ino, fh, offset, size, 0, None, ReadReply(&mut read)).await;
+        read
+    }
+
+    #[test_case(StaleReadPolicy::Error; "error")]
+    #[test_case(StaleReadPolicy::Restart; "restart")]
+    fn replace_open_object(stale_read_policy: StaleReadPolicy) {
+        let test_prefix = Prefix::new("test_prefix/").expect("valid prefix");
+        let config = S3FilesystemConfig {
+            // Check the object again on every read
+            stat_ttl: Duration::ZERO,
+            stale_read_policy,
+            ..Default::default()
+        };
+        let (client, fs) = make_test_filesystem("harness", &test_prefix, config);
+
+        let key = format!("{test_prefix}file");
+        client.add_object(&key, MockObject::constant(0xaa, 20, ETag::from_str("old").unwrap()));
+
+        futures::executor::block_on(async move {
+            let ino = fs.lookup(FUSE_ROOT_INODE, "file".as_ref()).await.unwrap().attr.ino;
+            let fh = fs.open(ino, libc::O_RDONLY, 0).await.unwrap().fh;
+            assert_eq!(read(&fs, ino, fh, 0, 10).await.unwrap(), [0xaa; 10].into());
+
+            client.add_object(&key, MockObject::constant(0xbb, 30, ETag::from_str("new").unwrap()));
+
+            match stale_read_policy {
+                StaleReadPolicy::Error => {
+                    assert_eq!(read(&fs, ino, fh, 10, 10).await, Err(libc::ESTALE));
+                    // The handle stays stale
+                    assert_eq!(read(&fs, ino, fh, 0, 10).await, Err(libc::ESTALE));
+                }
+                StaleReadPolicy::Restart => {
+                    assert_eq!(read(&fs, ino, fh, 10, 20).await.unwrap(), [0xbb; 20].into());
+                    // The whole new object is readable through the same handle
+                    assert_eq!(read(&fs, ino, fh, 0, 30).await.unwrap(), [0xbb; 30].into());
+                }
+                StaleReadPolicy::Ignore => unreachable!(),
+            }
+
+            fs.release(ino, fh, 0, None, false).await.unwrap();
+        });
+    }
+
+    #[test]
+    fn read_deleted_object() {
+        let test_prefix = Prefix::new("test_prefix/").expect("valid prefix");
+        let (client, fs) = make_test_filesystem("harness", &test_prefix, Default::default());
+
+        let key = format!("{test_prefix}file");
+        client.add_object(&key, FileContent(0xaa, FileSize::Small(20)).to_mock_object());
+
+        futures::executor::block_on(async move {
+            let ino = fs.lookup(FUSE_ROOT_INODE, "file".as_ref()).await.unwrap().attr.ino;
+            let fh = fs.open(ino, libc::O_RDONLY, 0).await.unwrap().fh;
+
+            client.remove_object(&key);
+
+            assert_eq!(read(&fs, ino, fh, 0, 10).await, Err(libc::ESTALE));
+            assert!(matches!(fs.getattr(ino).await, Err(libc::ESTALE)));
+            assert!(matches!(fs.open(ino, libc::O_RDONLY, 0).await, Err(libc::ESTALE)));
+            fs.release(ino, fh, 0, None, false).await.unwrap();
+
+            // Looking the name up again finds that it's gone
+            assert!(matches!(
+                fs.lookup(FUSE_ROOT_INODE, "file".as_ref()).await,
+                Err(libc::ENOENT)
+            ));
+        });
+    }
+}
+
+/// Tests for presenting the account's buckets as directories under the root
+mod browse_buckets {
+    use super::*;
+    use time::OffsetDateTime;
+
+    #[test]
+    fn buckets_are_top_level_directories() {
+        let config = S3FilesystemConfig {
+            browse_buckets: true,
+            ..Default::default()
+        };
+        let (client, fs) = make_test_filesystem("harness", &Default::default(), config);
+        client.add_bucket("other", OffsetDateTime::now_utc());
+        let file = FileContent(0xaa, FileSize::Small(20));
+        client.add_object("dir/file", file.to_mock_object());
+
+        futures::executor::block_on(async move {
+            let dir_handle = fs.opendir(FUSE_ROOT_INODE, 0).await.unwrap().fh;
+            let mut reply = DirectoryReply::new(0);
+            let _reply = fs.readdir(FUSE_ROOT_INODE, dir_handle, 0, &mut reply).await.unwrap();
+            let entries: Vec<_> = reply
+                .entries
+                .iter()
+                .skip(2)
+                .map(|entry| (entry.name.to_str().unwrap(), entry.attr.kind))
+                .collect();
+            assert_eq!(
+                entries,
+                [("harness", FileType::Directory), ("other", FileType::Directory)]
+            );
+
+            // Descending into a bucket shows its objects
+            let bucket = fs.lookup(FUSE_ROOT_INODE, "harness".as_ref()).await.unwrap();
+            let dir = fs.lookup(bucket.attr.ino, "dir".as_ref()).await.unwrap();
+            assert_eq!(dir.attr.kind, FileType::Directory);
+            let lookup = fs.lookup(dir.attr.ino, "file".as_ref()).await.unwrap();
+            let fh = fs.open(lookup.attr.ino, libc::O_RDONLY, 0).await.unwrap().fh;
+            let mut read = Err(0);
+            fs.read(lookup.attr.ino, fh, 0, 20, 0, None, ReadReply(&mut read)).await;
+            assert_eq!(read.unwrap(), file.to_boxed_slice());
+
+            let other = fs.lookup(FUSE_ROOT_INODE, "other".as_ref()).await.unwrap();
+            let dir_handle = fs.opendir(other.attr.ino, 0).await.unwrap().fh;
+            let mut reply = DirectoryReply::new(0);
+            let _reply = fs.readdir(other.attr.ino, dir_handle, 0, &mut reply).await.unwrap();
+            assert_eq!(reply.entries.len(), 2);
+
+            let missing = fs.lookup(FUSE_ROOT_INODE, "missing".as_ref()).await;
+            assert!(matches!(missing, Err(libc::ENOENT)));
+        });
+    }
+}
+
+mod sparse_reads {
+    use super::*;
+    use mountpoint_s3::fs::LOGICAL_SIZE_METADATA_KEY;
+    use std::collections::HashMap;
+
+    #[test]
+    fn reads_past_data_return_zeros() {
+        let config = S3FilesystemConfig {
+            sparse_reads_return_zeros: true,
+            ..Default::default()
+        };
+        let (client, fs) = make_test_filesystem("harness", &Default::default(), config);
+        let file = FileContent(0xaa, FileSize::Small(20));
+        let mut object = file.to_mock_object();
+        object.set_user_metadata(HashMap::from([(
+            LOGICAL_SIZE_METADATA_KEY.to_string(),
+            "50".to_string(),
+        )]));
+        client.add_object("sparse", object);
+
+        futures::executor::block_on(async move {
+            let lookup = fs.lookup(FUSE_ROOT_INODE, "sparse".as_ref()).await.unwrap();
+            // The listing only knows the size of the data
+            assert_eq!(lookup.attr.size, 20);
+            let opened = fs.open(lookup.attr.ino, libc::O_RDONLY, 0).await.unwrap();
+            assert_ne!(opened.flags & fuser::consts::FOPEN_DIRECT_IO, 0);
+
+            let mut read = Err(0);
+            fs.read(lookup.attr.ino, opened.fh, 0, 30, 0, None, ReadReply(&mut read))
+                .await;
+            let mut expected = file.to_boxed_slice().to_vec();
+            expected.resize(30, 0);
+            assert_eq!(&read.unwrap()[..], &expected[..]);
+
+            let mut read = Err(0);
+            fs.read(lookup.attr.ino, opened.fh, 30, 30, 0, None, ReadReply(&mut read))
+                .await;
+            assert_eq!(&read.unwrap()[..], &[0; 20][..]);
+
+            let mut read = Err(0);
+            fs.read(lookup.attr.ino, opened.fh, 50, 30, 0, None, ReadReply(&mut read))
+                .await;
+            assert!(read.unwrap().is_empty());
+        });
+    }
+}
+
+mod byte_ranges {
+    use super::*;
+    use mountpoint_s3_client::ETag;
+
+    #[test]
+    fn range_suffix_matches_ranged_read() {
+        let config = S3FilesystemConfig {
+            expose_byte_ranges: true,
+            ..Default::default()
+        };
+        let (client, fs) = make_test_filesystem("harness", &Default::default(), config);
+        let body: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+        client.add_object("bigfile.bin", MockObject::from_bytes(&body, ETag::for_tests()));
+
+        futures::executor::block_on(async move {
+            // The same bytes read in-band from the whole object
+            let whole = fs.lookup(FUSE_ROOT_INODE, "bigfile.bin".as_ref()).await.unwrap();
+            let opened = fs.open(whole.attr.ino, libc::O_RDONLY, 0).await.unwrap();
+            let mut expected = Err(0);
+            fs.read(whole.attr.ino, opened.fh, 1000, 1001, 0, None, ReadReply(&mut expected))
+                .await;
+            fs.release(whole.attr.ino, opened.fh, 0, None, true).await.unwrap();
+            let expected = expected.unwrap();
+            assert_eq!(&expected[..], &body[1000..2001]);
+
+            let ranged = fs
+                .lookup(FUSE_ROOT_INODE, "bigfile.bin@range=1000-2000".as_ref())
+                .await
+                .unwrap();
+            assert_ne!(ranged.attr.ino, whole.attr.ino);
+            assert_eq!(ranged.attr.size, 1001);
+            assert!(fs.open(ranged.attr.ino, libc::O_WRONLY, 0).await.is_err());
+
+            let opened = fs.open(ranged.attr.ino, libc::O_RDONLY, 0).await.unwrap();
+            let mut read = Err(0);
+            fs.read(ranged.attr.ino, opened.fh, 0, 4096, 0, None, ReadReply(&mut read))
+                .await;
+            assert_eq!(read.unwrap(), expected);
+
+            // Reads within the range are relative to its start
+            let mut read = Err(0);
+            fs.read(ranged.attr.ino, opened.fh, 500, 100, 0, None, ReadReply(&mut read))
+                .await;
+            assert_eq!(&read.unwrap()[..], &body[1500..1600]);
+            fs.release(ranged.attr.ino, opened.fh, 0, None, true).await.unwrap();
+
+            // Ranges past the end of the object are truncated to it
+            let tail = fs
+                .lookup(FUSE_ROOT_INODE, "bigfile.bin@range=4000-9999".as_ref())
+                .await
+                .unwrap();
+            assert_eq!(tail.attr.size, 1000);
+        });
+    }
+
+    #[test]
+    fn range_suffix_disabled() {
+        let (client, fs) = make_test_filesystem("harness", &Default::default(), Default::default());
+        client.add_object("bigfile.bin", FileContent(0xaa, FileSize::Small(20)).to_mock_object());
+
+        futures::executor::block_on(async move {
+            let lookup = fs.lookup(FUSE_ROOT_INODE, "bigfile.bin@range=0-9".as_ref()).await;
+            assert!(matches!(lookup, Err(libc::ENOENT)));
+        });
+    }
+}
+
+mod copy_file_range {
+    use super::*;
+    use mountpoint_s3_client::mock_client::Operation;
+    use mountpoint_s3_client::{ETag, GetObjectParams};
+
+    #[test]
+    fn copy_whole_file_in_s3() {
+        let (client, fs) = make_test_filesystem("harness", &Default::default(), Default::default());
+        let body: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+        client.add_object("source", MockObject::from_bytes(&body, ETag::for_tests()));
+
+        futures::executor::block_on(async move {
+            let source = fs.lookup(FUSE_ROOT_INODE, "source".as_ref()).await.unwrap();
+            let source_fh = fs.open(source.attr.ino, libc::O_RDONLY, 0).await.unwrap().fh;
+            let destination = fs
+                .mknod(FUSE_ROOT_INODE, "destination".as_ref(), libc::S_IFREG, 0, 0)
+                .await
+                .unwrap();
+            let destination_fh = fs.open(destination.attr.ino, libc::O_WRONLY, 0).await.unwrap().fh;
+
+            let copied = fs
+                .copy_file_range(
+                    source.attr.ino,
+                    source_fh,
+                    0,
+                    destination.attr.ino,
+                    destination_fh,
+                    0,
+                    u64::MAX,
+                    0,
+                )
+                .await
+                .unwrap();
+            assert_eq!(copied as usize, body.len());
+            fs.release(destination.attr.ino, destination_fh, 0, None, true)
+                .await
+                .unwrap();
+            fs.release(source.attr.ino, source_fh, 0, None, true).await.unwrap();
+
+            // The contents never passed through the file system
+            assert_eq!(client.op_count(Operation::CopyObject), 1);
+            assert_eq!(client.op_count(Operation::GetObject), 0);
+            assert_eq!(client.op_count(Operation::PutObject), 0);
+
+            let attr = fs.getattr(destination.attr.ino).await.unwrap();
+            assert_eq!(attr.attr.size, body.len() as u64);
+            let fh = fs.open(destination.attr.ino, libc::O_RDONLY, 0).await.unwrap().fh;
+            let mut read = Err(0);
+            fs.read(destination.attr.ino, fh, 0, 8192, 0, None, ReadReply(&mut read))
+                .await;
+            assert_eq!(&read.unwrap()[..], &body[..]);
+        });
+    }
+
+    #[test]
+    fn copy_partial_range_by_reading() {
+        let (client, fs) = make_test_filesystem("harness", &Default::default(), Default::default());
+        let body: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+        client.add_object("source", MockObject::from_bytes(&body, ETag::for_tests()));
+
+        futures::executor::block_on(async move {
+            let source = fs.lookup(FUSE_ROOT_INODE, "source".as_ref()).await.unwrap();
+            let source_fh = fs.open(source.attr.ino, libc::O_RDONLY, 0).await.unwrap().fh;
+            let destination = fs
+                .mknod(FUSE_ROOT_INODE, "destination".as_ref(), libc::S_IFREG, 0, 0)
+                .await
+                .unwrap();
+            let destination_fh = fs.open(destination.attr.ino, libc::O_WRONLY, 0).await.unwrap().fh;
+
+            let copied = fs
+                .copy_file_range(
+                    source.attr.ino,
+                    source_fh,
+                    1000,
+                    destination.attr.ino,
+                    destination_fh,
+                    0,
+                    2000,
+                    0,
+                )
+                .await
+                .unwrap();
+            assert_eq!(copied, 2000);
+            fs.release(destination.attr.ino, destination_fh, 0, None, true)
+                .await
+                .unwrap();
+
+            assert_eq!(client.op_count(Operation::CopyObject), 0);
+            let object = client
+                .get_object("harness", "destination", &GetObjectParams::new())
+                .await
+                .unwrap();
+            assert_eq!(&object.collect().await.unwrap()[..], &body[1000..3000]);
+        });
+    }
+}
+
+mod read_advice {
+    use super::*;
+    use mountpoint_s3::fs::{ReadAdvice, FADVISE_IOCTL};
+    use mountpoint_s3_client::mock_client::Operation;
+    use std::time::{Duration, Instant};
+
+    /// Wait for requests spawned in the background to reach the client
+    fn wait_for_gets(client: &MockClient, count: usize) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while client.op_count(Operation::GetObject) < count {
+            assert!(Instant::now() < deadline, "timed out waiting for GetObject requests");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn sequential_advice_prefetches_before_reads() {
+        let (client, fs) = make_test_filesystem("harness", &Default::default(), Default::default());
+        let file = FileContent(0xaa, FileSize::Small(2 * 1024 * 1024));
+        client.add_object("file", file.to_mock_object());
+
+        futures::executor::block_on(async move {
+            let lookup = fs.lookup(FUSE_ROOT_INODE, "file".as_ref()).await.unwrap();
+            let fh = fs.open(lookup.attr.ino, libc::O_RDONLY, 0).await.unwrap().fh;
+
+            let mut advice = Vec::new();
+            advice.extend_from_slice(&0u64.to_ne_bytes());
+            advice.extend_from_slice(&0u64.to_ne_bytes());
+            advice.extend_from_slice(&2u32.to_ne_bytes());
+            fs.ioctl(lookup.attr.ino, fh, 0, FADVISE_IOCTL, &advice, 0)
+                .await
+                .unwrap();
+
+            // The whole object is requested before anything reads it
+            wait_for_gets(&client, 1);
+
+            let mut offset = 0;
+            while offset < 2 * 1024 * 1024 {
+                let mut read = Err(0);
+                fs.read(lookup.attr.ino, fh, offset, 128 * 1024, 0, None, ReadReply(&mut read))
+                    .await;
+                let read = read.unwrap();
+                assert_eq!(read.len(), 128 * 1024);
+                assert!(read.iter().all(|b| *b == 0xaa));
+                offset += read.len() as i64;
+            }
+            // Without the advice, the reads would have been split over several requests
+            assert_eq!(client.op_count(Operation::GetObject), 1);
+        });
+    }
+
+    #[test]
+    fn random_advice_disables_readahead() {
+        let (client, fs) = make_test_filesystem("harness", &Default::default(), Default::default());
+        let file = FileContent(0xaa, FileSize::Small(1024 * 1024));
+        client.add_object("file", file.to_mock_object());
+
+        futures::executor::block_on(async move {
+            let lookup = fs.lookup(FUSE_ROOT_INODE, "file".as_ref()).await.unwrap();
+            let fh = fs.open(lookup.attr.ino, libc::O_RDONLY, 0).await.unwrap().fh;
+            fs.fadvise(lookup.attr.ino, fh, 0, 0, ReadAdvice::Random).await.unwrap();
+
+            // Each read only fetches what it needs, so even sequential reads need a request each
+            for offset in [0, 64 * 1024] {
+                let mut read = Err(0);
+                fs.read(lookup.attr.ino, fh, offset, 64 * 1024, 0, None, ReadReply(&mut read))
+                    .await;
+                assert_eq!(read.unwrap().len(), 64 * 1024);
+            }
+            assert_eq!(client.op_count(Operation::GetObject), 2);
+        });
+    }
+}
+
+mod case_sensitivity {
+    use super::*;
+    use mountpoint_s3::fs::CaseSensitivity;
+
+    fn lookup_foo(case_sensitivity: CaseSensitivity) -> Vec<InodeNo> {
+        let config = S3FilesystemConfig {
+            case_sensitivity,
+            ..Default::default()
+        };
+        let (client, fs) = make_test_filesystem("harness", &Default::default(), config);
+        client.add_object("Foo", FileContent(0xaa, FileSize::Small(10)).to_mock_object());
+        client.add_object("foo", FileContent(0xbb, FileSize::Small(20)).to_mock_object());
+
+        futures::executor::block_on(async move {
+            let mut inos = Vec::new();
+            for name in ["Foo", "foo", "FOO"] {
+                match fs.lookup(FUSE_ROOT_INODE, name.as_ref()).await {
+                    Ok(entry) => inos.push(entry.attr.ino),
+                    Err(e) => assert_eq!(e, libc::ENOENT, "unexpected error looking up {name}"),
+                }
+            }
+            inos
+        })
+    }
+
+    #[test]
+    fn insensitive_lookups_find_the_same_object() {
+        let inos = lookup_foo(CaseSensitivity::Insensitive);
+        assert_eq!(inos.len(), 3);
+        assert!(inos.iter().all(|ino| *ino == inos[0]));
+    }
+
+    #[test]
+    fn sensitive_lookups_keep_keys_distinct() {
+        let inos = lookup_foo(CaseSensitivity::Sensitive);
+        // `FOO` doesn't exist
+        assert_eq!(inos.len(), 2);
+        assert_ne!(inos[0], inos[1]);
+    }
+}
+
+mod rename_exchange {
+    use super::*;
+    use mountpoint_s3::fs::RENAME_EXCHANGE;
+    use mountpoint_s3_client::GetObjectParams;
+
+    async fn read_file(fs: &S3Filesystem<Arc<MockClient>, ThreadPool>, parent: InodeNo, name: &str) -> Box<[u8]> {
+        let entry = fs.lookup(parent, name.as_ref()).await.unwrap();
+        let fh = fs.open(entry.attr.ino, libc::O_RDONLY, 0).await.unwrap().fh;
+        let mut read = Err(0);
+        fs.read(entry.attr.ino, fh, 0, 4096, 0, None, ReadReply(&mut read))
+            .await;
+        fs.release(entry.attr.ino, fh, 0, None, true).await.unwrap();
+        read.unwrap()
+    }
+
+    #[test]
+    fn exchange_swaps_contents() {
+        let config = S3FilesystemConfig {
+            allow_nonatomic_exchange: true,
+            ..Default::default()
+        };
+        let (client, fs) = make_test_filesystem("harness", &Default::default(), config);
+        client.add_object("dir/a", FileContent(0xaa, FileSize::Small(10)).to_mock_object());
+        client.add_object("dir/b", FileContent(0xbb, FileSize::Small(20)).to_mock_object());
+
+        futures::executor::block_on(async move {
+            let dir = fs.lookup(FUSE_ROOT_INODE, "dir".as_ref()).await.unwrap().attr.ino;
+            // Look `a` up first, so the exchange has to expire its cached stat
+            let a = fs.lookup(dir, "a".as_ref()).await.unwrap();
+            assert_eq!(a.attr.size, 10);
+
+            fs.rename(dir, "a".as_ref(), dir, "b".as_ref(), RENAME_EXCHANGE)
+                .await
+                .unwrap();
+
+            let a = fs.lookup(dir, "a".as_ref()).await.unwrap();
+            let b = fs.lookup(dir, "b".as_ref()).await.unwrap();
+            assert_eq!(a.attr.size, 20);
+            assert_eq!(b.attr.size, 10);
+            for (key, expected) in [("dir/a", [0xbb; 20].as_slice()), ("dir/b", [0xaa; 10].as_slice())] {
+                let object = client
+                    .get_object("harness", key, &GetObjectParams::new())
+                    .await
+                    .unwrap();
+                assert_eq!(&object.collect().await.unwrap()[..], expected);
+            }
+            assert_eq!(&read_file(&fs, dir, "a").await[..], &[0xbb; 20]);
+            assert_eq!(&read_file(&fs, dir, "b").await[..], &[0xaa; 10]);
+
+            // The temporary object is gone
+            let dir_handle = fs.opendir(dir, 0).await.unwrap().fh;
+            let mut reply = DirectoryReply::new(0);
+            let _reply = fs.readdir(dir, dir_handle, 0, &mut reply).await.unwrap();
+            let names: Vec<_> = reply.entries.iter().map(|entry| entry.name.to_str().unwrap()).collect();
+            assert_eq!(names, [".", "..", "a", "b"]);
+        });
+    }
+
+    #[test]
+    fn exchange_needs_config() {
+        let (client, fs) = make_test_filesystem("harness", &Default::default(), Default::default());
+        client.add_object("a", FileContent(0xaa, FileSize::Small(10)).to_mock_object());
+        client.add_object("b", FileContent(0xbb, FileSize::Small(20)).to_mock_object());
+
+        futures::executor::block_on(async move {
+            let result = fs
+                .rename(
+                    FUSE_ROOT_INODE,
+                    "a".as_ref(),
+                    FUSE_ROOT_INODE,
+                    "b".as_ref(),
+                    RENAME_EXCHANGE,
+                )
+                .await;
+            assert_eq!(result, Err(libc::ENOTSUP));
+            assert_eq!(&read_file(&fs, FUSE_ROOT_INODE, "a").await[..], &[0xaa; 10]);
+            assert_eq!(&read_file(&fs, FUSE_ROOT_INODE, "b").await[..], &[0xbb; 20]);
+        });
+    }
+}
+
+mod object_size_check {
+    use super::*;
+    use mountpoint_s3_client::ETag;
+
+    /// Read a file after its object shrank from 100 to 50 bytes, without the etag changing
+    fn read_shrunk_object(check_object_size: bool) -> Result<Box<[u8]>, libc::c_int> {
+        let config = S3FilesystemConfig {
+            prefetcher_config: PrefetcherConfig {
+                check_object_size,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (client, fs) = make_test_filesystem("harness", &Default::default(), config);
+        client.clamp_get_ranges(true);
+        client.add_object("file", MockObject::constant(0xaa, 100, ETag::for_tests()));
+
+        futures::executor::block_on(async move {
+            let entry = fs.lookup(FUSE_ROOT_INODE, "file".as_ref()).await.unwrap();
+            assert_eq!(entry.attr.size, 100);
+            let fh = fs.open(entry.attr.ino, libc::O_RDONLY, 0).await.unwrap().fh;
+
+            client.add_object("file", MockObject::constant(0xaa, 50, ETag::for_tests()));
+
+            let mut read = Err(0);
+            fs.read(entry.attr.ino, fh, 0, 100, 0, None, ReadReply(&mut read)).await;
+            read
+        })
+    }
+
+    #[test]
+    fn shrunk_object_is_stale() {
+        assert_eq!(read_shrunk_object(true), Err(libc::ESTALE));
+    }
+
+    #[test]
+    fn shrunk_object_fails_without_check() {
+        // Still not a short read, but nothing says why
+        assert_eq!(read_shrunk_object(false), Err(libc::EIO));
+    }
+}
+
+mod expected_etag {
+    use super::*;
+    use mountpoint_s3::fs::EXPECTED_ETAG_XATTR;
+    use mountpoint_s3_client::ETag;
+
+    /// Write a file with an expected ETag through the file system, returning the result of
+    /// flushing it
+    async fn write_with_expected_etag(
+        fs: &S3Filesystem<Arc<MockClient>, ThreadPool>,
+        name: &str,
+        contents: &[u8],
+        expected_etag: &str,
+    ) -> Result<(), i32> {
+        let mode = libc::S_IFREG | libc::S_IRWXU;
+        let ino = fs
+            .mknod(FUSE_ROOT_INODE, name.as_ref(), mode, 0, 0)
+            .await
+            .unwrap()
+            .attr
+            .ino;
+        let fh = fs.open(ino, libc::O_WRONLY, 0).await.unwrap().fh;
+        fs.write(ino, fh, 0, contents, 0, 0, None).await.unwrap();
+        fs.setxattr(ino, EXPECTED_ETAG_XATTR.as_ref(), expected_etag.as_bytes(), 0, 0)
+            .await
+            .unwrap();
+        let result = fs.flush(ino, fh, 0, 0).await;
+        // The upload is already complete, so releasing the file doesn't check again
+        fs.release(ino, fh, 0, None, true).await.unwrap();
+        result
+    }
+
+    #[test]
+    fn matching_etag() {
+        let (client, fs) = make_test_filesystem("harness", &Default::default(), Default::default());
+        let contents = FileContent(0xaa, FileSize::Small(100)).to_boxed_slice();
+        // Quotes and case don't matter
+        let etag = ETag::from_object_bytes(&contents);
+        let expected = format!("\"{}\"", etag.as_str().to_uppercase());
+
+        futures::executor::block_on(async move {
+            assert_eq!(write_with_expected_etag(&fs, "a", &contents, &expected).await, Ok(()));
+        });
+        assert!(client.contains_key("a"));
+    }
+
+    #[test]
+    fn mismatched_etag() {
+        let (client, fs) = make_test_filesystem("harness", &Default::default(), Default::default());
+        let contents = FileContent(0xaa, FileSize::Small(100)).to_boxed_slice();
+        let expected = ETag::from_object_bytes(b"something else");
+
+        futures::executor::block_on(async move {
+            let result = write_with_expected_etag(&fs, "a", &contents, expected.as_str()).await;
+            assert_eq!(result, Err(libc::EIO));
+
+            // Files that aren't being written have no upload to check
+            let ino = fs.lookup(FUSE_ROOT_INODE, "a".as_ref()).await.unwrap().attr.ino;
+            let result = fs.setxattr(ino, EXPECTED_ETAG_XATTR.as_ref(), b"abc", 0, 0).await;
+            assert_eq!(result, Err(libc::EPERM));
+            let result = fs.setxattr(ino, "user.other".as_ref(), b"abc", 0, 0).await;
+            assert_eq!(result, Err(libc::ENOTSUP));
+        });
+        // The object was still uploaded
+        assert!(client.contains_key("a"));
+    }
+}
+
+mod control_dir {
+    use super::*;
+    use mountpoint_s3::build_info;
+    use mountpoint_s3_client::mock_client::Operation;
+
+    /// Write to a command file in `.mountpoint/control`
+    async fn run_command(fs: &S3Filesystem<Arc<MockClient>, ThreadPool>, name: &str) {
+        let dir = fs.lookup(FUSE_ROOT_INODE, ".mountpoint".as_ref()).await.unwrap();
+        let control = fs.lookup(dir.attr.ino, "control".as_ref()).await.unwrap();
+        let command = fs.lookup(control.attr.ino, name.as_ref()).await.unwrap();
+        let fh = fs.open(command.attr.ino, libc::O_WRONLY, 0).await.unwrap().fh;
+        let written = fs.write(command.attr.ino, fh, 0, b"1\n", 0, 0, None).await.unwrap();
+        assert_eq!(written, 2);
+        fs.release(command.attr.ino, fh, 0, None, true).await.unwrap();
+    }
+
+    #[test]
+    fn read_version() {
+        let (client, fs) = make_test_filesystem("harness", &Default::default(), Default::default());
+        client.add_object("file", FileContent(0xaa, FileSize::Small(20)).to_mock_object());
+
+        futures::executor::block_on(async move {
+            let dir = fs.lookup(FUSE_ROOT_INODE, ".mountpoint".as_ref()).await.unwrap();
+            assert_eq!(dir.attr.kind, FileType::Directory);
+            let version = fs.lookup(dir.attr.ino, "version".as_ref()).await.unwrap();
+            assert_eq!(version.attr.kind, FileType::RegularFile);
+
+            let fh = fs.open(version.attr.ino, libc::O_RDONLY, 0).await.unwrap().fh;
+            let mut read = Err(0);
+            fs.read(version.attr.ino, fh, 0, 4096, 0, None, ReadReply(&mut read))
+                .await;
+            let contents = read.unwrap();
+            assert_eq!(&contents[..], format!("{}\n", build_info::FULL_VERSION).as_bytes());
+            fs.release(version.attr.ino, fh, 0, None, true).await.unwrap();
+
+            let missing = fs.lookup(dir.attr.ino, "missing".as_ref()).await;
+            assert!(matches!(missing, Err(libc::ENOENT)));
+
+            // The control directory lists its files, but isn't listed itself
+            let dir_handle = fs.opendir(dir.attr.ino, 0).await.unwrap().fh;
+            let mut reply = DirectoryReply::new(0);
+            let _reply = fs.readdir(dir.attr.ino, dir_handle, 0, &mut reply).await.unwrap();
+            let names: Vec<_> = reply.entries.iter().map(|entry| entry.name.to_str().unwrap()).collect();
+            assert_eq!(names, [".", "..", "control", "stats", "config", "version"]);
+
+            let dir_handle = fs.opendir(FUSE_ROOT_INODE, 0).await.unwrap().fh;
+            let mut reply = DirectoryReply::new(0);
+            let _reply = fs.readdir(FUSE_ROOT_INODE, dir_handle, 0, &mut reply).await.unwrap();
+            let names: Vec<_> = reply.entries.iter().map(|entry| entry.name.to_str().unwrap()).collect();
+            assert_eq!(names, [".", "..", "file"]);
+        });
+    }
+
+    #[test]
+    fn flush_caches() {
+        let config = S3FilesystemConfig {
+            prime_lookup_cache_from_readdir: true,
+            stat_ttl: std::time::Duration::from_secs(60),
+            ..Default::default()
+        };
+        let (client, fs) = make_test_filesystem("harness", &Default::default(), config);
+        let file = FileContent(0xaa, FileSize::Small(20));
+        client.add_object("file", file.to_mock_object());
+
+        futures::executor::block_on(async move {
+            let dir_handle = fs.opendir(FUSE_ROOT_INODE, 0).await.unwrap().fh;
+            let mut reply = DirectoryReply::new(0);
+            let _reply = fs.readdir(FUSE_ROOT_INODE, dir_handle, 0, &mut reply).await.unwrap();
+
+            // The first half of the file is read by a request that prefetches the rest
+            let lookup = fs.lookup(FUSE_ROOT_INODE, "file".as_ref()).await.unwrap();
+            assert_eq!(client.op_count(Operation::HeadObject), 0);
+            let fh = fs.open(lookup.attr.ino, libc::O_RDONLY, 0).await.unwrap().fh;
+            let mut read = Err(0);
+            fs.read(lookup.attr.ino, fh, 0, 10, 0, None, ReadReply(&mut read)).await;
+            assert_eq!(&read.unwrap()[..], &file.to_boxed_slice()[..10]);
+            assert_eq!(client.op_count(Operation::GetObject), 1);
+
+            run_command(&fs, "flush_caches").await;
+
+            // The prefetched data is gone, so the second half needs another request
+            let mut read = Err(0);
+            fs.read(lookup.attr.ino, fh, 10, 10, 0, None, ReadReply(&mut read))
+                .await;
+            assert_eq!(&read.unwrap()[..], &file.to_boxed_slice()[10..]);
+            assert_eq!(client.op_count(Operation::GetObject), 2);
+            fs.release(lookup.attr.ino, fh, 0, None, true).await.unwrap();
+
+            // And the listing no longer answers lookups
+            let relookup = fs.lookup(FUSE_ROOT_INODE, "file".as_ref()).await.unwrap();
+            assert_eq!(relookup.attr.ino, lookup.attr.ino);
+            assert!(client.op_count(Operation::HeadObject) > 0);
+        });
+    }
+
+    #[test]
+    fn forget_inodes() {
+        let (client, fs) = make_test_filesystem("harness", &Default::default(), Default::default());
+        client.add_object("dir/file", FileContent(0xaa, FileSize::Small(20)).to_mock_object());
+
+        futures::executor::block_on(async move {
+            let dir = fs.lookup(FUSE_ROOT_INODE, "dir".as_ref()).await.unwrap();
+            let file = fs.lookup(dir.attr.ino, "file".as_ref()).await.unwrap();
+
+            run_command(&fs, "forget_inodes").await;
+
+            assert!(matches!(fs.getattr(file.attr.ino).await, Err(libc::ENOENT)));
+            let new_dir = fs.lookup(FUSE_ROOT_INODE, "dir".as_ref()).await.unwrap();
+            assert_ne!(new_dir.attr.ino, dir.attr.ino);
+            let new_file = fs.lookup(new_dir.attr.ino, "file".as_ref()).await.unwrap();
+            assert_eq!(new_file.attr.size, 20);
+        });
+    }
 }
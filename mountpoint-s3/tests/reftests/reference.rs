@@ -1,5 +1,9 @@
 use fuser::FileType;
+use mountpoint_s3::fs::{
+    InvalidKeyHandling, ShadowPolicy, DEFAULT_MAX_NAME_LENGTH, DIRECTORY_OBJECT_NAME, SHADOWED_FILE_SUFFIX,
+};
 use mountpoint_s3_client::mock_client::MockObject;
+use mountpoint_s3_client::ETag;
 use std::cell::RefCell;
 use std::collections::BTreeMap;
 use std::path::{Component, Path, PathBuf};
@@ -124,6 +128,32 @@ impl Reference {
         }
     }
 
+    /// Append to the contents of an existing remote file
+    pub fn append_file(&mut self, path: impl AsRef<Path>, bytes: &[u8]) {
+        let mut components = path.as_ref().components();
+        assert_eq!(components.next(), Some(Component::RootDir));
+
+        let mut node = &mut self.root;
+        for component in components {
+            node = match node {
+                Node::Directory(children) => {
+                    let name = component.as_os_str().to_str().unwrap();
+                    children.get_mut(name).expect("file must already exist")
+                }
+                _ => panic!("unexpected internal file node"),
+            };
+        }
+
+        match node {
+            Node::File(File::Remote(object)) => {
+                let mut contents = object.read(0, object.len()).into_vec();
+                contents.extend_from_slice(bytes);
+                *object = MockObject::from_bytes(&contents, ETag::for_tests());
+            }
+            _ => panic!("can only append to remote files"),
+        }
+    }
+
     /// Get a node from a full path, if it exists. If any path component does not exist in the
     /// reference, returns None.
     pub fn lookup(&self, path: impl AsRef<Path>) -> Option<&Node> {
@@ -144,6 +174,15 @@ impl Reference {
         Some(node)
     }
 
+    /// A list of absolute paths for every file in the reference
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.list_recursive()
+            .into_iter()
+            .filter(|(_path, node)| matches!(node, Node::File(_)))
+            .map(|(path, _node)| Path::new("/").join(path.join("/")))
+            .collect()
+    }
+
     /// A list of absolute paths for every directory in the reference. This is never empty as "/" is
     /// always a valid directory, even in an empty file system.
     pub fn directories(&self) -> &[impl AsRef<Path>] {
@@ -151,14 +190,25 @@ impl Reference {
     }
 }
 
-fn valid_inode_name(name: &str) -> bool {
-    !name.is_empty() && name != "." && name != ".." && !name.contains('\0')
+fn valid_inode_name(name: &str, shadow_policy: ShadowPolicy, invalid_key_handling: InvalidKeyHandling) -> bool {
+    !name.is_empty()
+        && name != "."
+        && name != ".."
+        && !name.contains('\0')
+        && (shadow_policy != ShadowPolicy::Suffix || !name.ends_with(SHADOWED_FILE_SUFFIX))
+        && (invalid_key_handling != InvalidKeyHandling::Sanitize || name != DIRECTORY_OBJECT_NAME)
+        // Semantics decision: names that are too long can't be presented, so are hidden
+        && name.len() <= DEFAULT_MAX_NAME_LENGTH
 }
 
 /// Take an S3 namespace (list of keys) and create the expected reference file system tree. This is
 /// where all our semantics decisions about how to present a flat keyspace as a file system are
 /// made; we'll be testing the connector against the decisions made here.
-pub fn build_reference(flat: Vec<(String, FileContent)>) -> Reference {
+pub fn build_reference(
+    flat: Vec<(String, FileContent)>,
+    shadow_policy: ShadowPolicy,
+    invalid_key_handling: InvalidKeyHandling,
+) -> Reference {
     #[derive(Debug)]
     enum RefNode {
         Directory(Rc<RefCell<BTreeMap<String, RefNode>>>),
@@ -181,18 +231,25 @@ pub fn build_reference(flat: Vec<(String, FileContent)>) -> Reference {
         for dir in components.iter().take(components.len().saturating_sub(1)) {
             // Semantics decision: these characters are invalid in directory names, so nothing
             // below them should be visible.
-            if !valid_inode_name(dir) {
+            if !valid_inode_name(dir, shadow_policy, invalid_key_handling) {
                 continue 'next_key;
             }
 
             let mut leaf = leaf_dir.borrow_mut();
-            // Semantics decision: directories shadow files of the same name, so overwrite if it
-            // exists but is a file.
-            let should_create = leaf
-                .get(*dir)
-                .map(|node| matches!(node, RefNode::File(_)))
-                .unwrap_or(true);
-            if should_create {
+            // Semantics decision: the shadow policy decides whether directories shadow files of
+            // the same name, files shadow directories, or files are moved aside to a suffixed name.
+            if let Some(RefNode::File(_)) = leaf.get(*dir) {
+                match shadow_policy {
+                    ShadowPolicy::PreferDirectory => {
+                        leaf.insert(dir.to_string(), RefNode::Directory(Default::default()));
+                    }
+                    ShadowPolicy::PreferFile => continue 'next_key,
+                    ShadowPolicy::Suffix => {
+                        let file = leaf.insert(dir.to_string(), RefNode::Directory(Default::default()));
+                        leaf.insert(format!("{dir}{SHADOWED_FILE_SUFFIX}"), file.unwrap());
+                    }
+                }
+            } else if leaf.get(*dir).is_none() {
                 leaf.insert(dir.to_string(), RefNode::Directory(Default::default()));
             }
 
@@ -203,15 +260,26 @@ pub fn build_reference(flat: Vec<(String, FileContent)>) -> Reference {
 
         // Semantics decision: these characters are invalid in file names, so they should not be
         // visible, but the directories they're in will still be present.
-        let file_name = components.iter().last().unwrap();
-        let should_create = leaf_dir
-            .borrow()
-            .get(*file_name)
-            .map(|node| matches!(node, RefNode::File(_)))
-            .unwrap_or(true);
-        if valid_inode_name(file_name) && should_create {
-            leaf_dir.borrow_mut().insert(file_name.to_string(), RefNode::File(file));
+        let mut file_name = *components.iter().last().unwrap();
+        // Semantics decision: a key that ends in '/' is the directory's own object. If it's empty,
+        // it's just a marker for the directory (see `ObjectInfo::is_directory_marker`), but
+        // otherwise it can be shown inside the directory.
+        let file_size: usize = file.1.into();
+        if file_name.is_empty() && file_size > 0 && invalid_key_handling == InvalidKeyHandling::Sanitize {
+            file_name = DIRECTORY_OBJECT_NAME;
+        } else if !valid_inode_name(file_name, shadow_policy, invalid_key_handling) {
+            continue 'next_key;
         }
+        let mut leaf = leaf_dir.borrow_mut();
+        let file_name = match leaf.get(file_name) {
+            Some(RefNode::Directory(_)) => match shadow_policy {
+                ShadowPolicy::PreferDirectory => continue 'next_key,
+                ShadowPolicy::PreferFile => file_name.to_string(),
+                ShadowPolicy::Suffix => format!("{file_name}{SHADOWED_FILE_SUFFIX}"),
+            },
+            _ => file_name.to_string(),
+        };
+        leaf.insert(file_name, RefNode::File(file));
     }
 
     fn convert(